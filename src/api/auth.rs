@@ -0,0 +1,407 @@
+//! API authentication: the legacy wallet bearer token and scoped API keys
+//!
+//! Scoped keys look like `qtck_<id>_<secret>`. Only a digest of the full token is
+//! stored, so a key can't be recovered from the database, only revoked.
+
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::{header, Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::net::SocketAddr;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use crate::api::error::{ApiError, ErrorCode};
+use crate::config::ApiConfig;
+use crate::storage::database::AuditAction;
+use crate::storage::Database;
+use crate::{QtcError, Result};
+
+const API_KEY_PREFIX: &str = "qtck_";
+
+#[derive(Debug)]
+pub struct WalletAuth {
+    token_digest: [u8; 32],
+}
+
+impl WalletAuth {
+    pub fn new(token: &str) -> Self {
+        Self {
+            token_digest: Sha256::digest(token.as_bytes()).into(),
+        }
+    }
+
+    pub fn verify(&self, candidate: &str) -> bool {
+        digest_matches(&self.token_digest, candidate)
+    }
+}
+
+/// Compare digests in constant time so a token can't be guessed byte by byte
+fn digest_matches(expected: &[u8; 32], candidate: &str) -> bool {
+    let candidate: [u8; 32] = Sha256::digest(candidate.as_bytes()).into();
+    expected.iter()
+        .zip(candidate.iter())
+        .fold(0u8, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ApiScope {
+    /// Chain, mempool, network and mining queries
+    Read,
+    /// Submitting transactions
+    Broadcast,
+    /// Wallet endpoints
+    WalletSpend,
+    /// Everything, including key management and UTXO set scans
+    Admin,
+}
+
+impl fmt::Display for ApiScope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let scope = match self {
+            ApiScope::Read => "read",
+            ApiScope::Broadcast => "broadcast",
+            ApiScope::WalletSpend => "wallet-spend",
+            ApiScope::Admin => "admin",
+        };
+        write!(f, "{}", scope)
+    }
+}
+
+impl FromStr for ApiScope {
+    type Err = QtcError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "read" | "read-only" => Ok(ApiScope::Read),
+            "broadcast" => Ok(ApiScope::Broadcast),
+            "wallet-spend" | "wallet" => Ok(ApiScope::WalletSpend),
+            "admin" => Ok(ApiScope::Admin),
+            other => Err(QtcError::InvalidInput(format!(
+                "Unknown API scope '{}' (expected read, broadcast, wallet-spend or admin)", other
+            ))),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiKey {
+    pub id: String,
+    pub name: String,
+    pub scopes: Vec<ApiScope>,
+    digest: [u8; 32],
+    pub created_at: u64,
+    pub revoked_at: Option<u64>,
+}
+
+impl ApiKey {
+    /// Mint a key; the returned token is the only copy of the secret
+    pub fn generate(name: &str, scopes: Vec<ApiScope>) -> (Self, String) {
+        let id = hex::encode(rand::random::<[u8; 4]>());
+        let token = format!("{}{}_{}", API_KEY_PREFIX, id, hex::encode(rand::random::<[u8; 24]>()));
+
+        let key = Self {
+            id,
+            name: name.to_string(),
+            scopes,
+            digest: Sha256::digest(token.as_bytes()).into(),
+            created_at: chrono::Utc::now().timestamp() as u64,
+            revoked_at: None,
+        };
+        (key, token)
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.revoked_at.is_none()
+    }
+
+    /// Admin keys pass every scope check
+    pub fn allows(&self, scope: ApiScope) -> bool {
+        self.is_active() && self.scopes.iter().any(|s| *s == scope || *s == ApiScope::Admin)
+    }
+
+    pub fn verify(&self, token: &str) -> bool {
+        digest_matches(&self.digest, token)
+    }
+
+    /// Key ID embedded in a `qtck_<id>_<secret>` token
+    pub fn id_from_token(token: &str) -> Option<&str> {
+        token.strip_prefix(API_KEY_PREFIX)?
+            .split_once('_')
+            .map(|(id, _)| id)
+            .filter(|id| !id.is_empty())
+    }
+}
+
+/// What the key listing shows; never includes the digest
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiKeyInfo {
+    pub id: String,
+    pub name: String,
+    pub scopes: Vec<ApiScope>,
+    pub created_at: u64,
+    pub revoked_at: Option<u64>,
+}
+
+impl From<&ApiKey> for ApiKeyInfo {
+    fn from(key: &ApiKey) -> Self {
+        Self {
+            id: key.id.clone(),
+            name: key.name.clone(),
+            scopes: key.scopes.clone(),
+            created_at: key.created_at,
+            revoked_at: key.revoked_at,
+        }
+    }
+}
+
+/// Create and store a key, returning it with its token
+pub fn create_api_key(db: &Database, name: &str, scopes: Vec<ApiScope>, origin: &str) -> Result<(ApiKey, String)> {
+    if name.trim().is_empty() {
+        return Err(QtcError::InvalidInput("API key name cannot be empty".to_string()));
+    }
+    if scopes.is_empty() {
+        return Err(QtcError::InvalidInput("API key needs at least one scope".to_string()));
+    }
+
+    let mut scopes = scopes;
+    scopes.sort();
+    scopes.dedup();
+
+    let (key, token) = ApiKey::generate(name.trim(), scopes);
+    db.save_api_key(&key)?;
+
+    let scope_list: Vec<String> = key.scopes.iter().map(|s| s.to_string()).collect();
+    db.record_audit_event(
+        AuditAction::ApiKeyCreated,
+        origin,
+        format!("{} ({}) scopes {}", key.id, key.name, scope_list.join(",")),
+    )?;
+
+    Ok((key, token))
+}
+
+pub fn revoke_api_key(db: &Database, id: &str, origin: &str) -> Result<ApiKey> {
+    let mut key = db.get_api_key(id)?
+        .ok_or_else(|| QtcError::InvalidInput(format!("No API key with ID {}", id)))?;
+    if !key.is_active() {
+        return Err(QtcError::InvalidInput(format!("API key {} is already revoked", id)));
+    }
+
+    key.revoked_at = Some(chrono::Utc::now().timestamp() as u64);
+    db.save_api_key(&key)?;
+    db.record_audit_event(AuditAction::ApiKeyRevoked, origin, format!("{} ({})", key.id, key.name))?;
+
+    Ok(key)
+}
+
+/// Who made an authenticated call, available to handlers behind a `ScopeGuard`
+#[derive(Debug, Clone)]
+pub struct ApiCaller(pub String);
+
+/// Route-group middleware state: the scope a group needs and where keys live
+#[derive(Debug)]
+pub struct ScopeGuard {
+    scope: ApiScope,
+    db: Arc<Database>,
+    legacy: Option<WalletAuth>,
+    operator: Option<WalletAuth>,
+    public_paths: Vec<String>,
+}
+
+impl ScopeGuard {
+    pub fn new(scope: ApiScope, db: Arc<Database>) -> Self {
+        Self { scope, db, legacy: None, operator: None, public_paths: Vec::new() }
+    }
+
+    /// A guard honoring the `api_token` and, for reads, the `public_endpoints` of `config`
+    pub fn from_config(scope: ApiScope, db: Arc<Database>, config: &ApiConfig) -> Self {
+        let mut guard = Self::new(scope, db);
+        if let Some(token) = &config.api_token {
+            guard = guard.with_operator_token(token);
+        }
+        if scope == ApiScope::Read {
+            guard = guard.with_public_paths(config.public_endpoints.clone());
+        }
+        guard
+    }
+
+    /// Also accept the old single wallet token, for setups that predate scoped keys
+    pub fn with_legacy_token(mut self, token: &str) -> Self {
+        self.legacy = Some(WalletAuth::new(token));
+        self
+    }
+
+    /// Accept the `api_token` from the config file, which carries every scope
+    pub fn with_operator_token(mut self, token: &str) -> Self {
+        self.operator = Some(WalletAuth::new(token));
+        self
+    }
+
+    /// Let GET requests for these paths through without a key; `/api/v1/blocks*` matches a prefix
+    pub fn with_public_paths(mut self, paths: Vec<String>) -> Self {
+        self.public_paths = paths;
+        self
+    }
+
+    fn is_public(&self, method: &Method, path: &str) -> bool {
+        (method == Method::GET || method == Method::HEAD) && self.public_paths.iter().any(|public| {
+            match public.strip_suffix('*') {
+                Some(prefix) => path.starts_with(prefix),
+                None => path == public,
+            }
+        })
+    }
+
+    /// The caller's name on success, or the status to refuse with
+    fn authorize(&self, token: &str) -> std::result::Result<String, (StatusCode, String)> {
+        if let Some(id) = ApiKey::id_from_token(token) {
+            let key = match self.db.get_api_key(id) {
+                Ok(Some(key)) if key.verify(token) && key.is_active() => key,
+                Ok(_) => return Err((StatusCode::UNAUTHORIZED, "Invalid or revoked API key".to_string())),
+                Err(e) => return Err((StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to look up API key: {}", e))),
+            };
+            if !key.allows(self.scope) {
+                return Err((StatusCode::FORBIDDEN, format!("API key lacks the '{}' scope", self.scope)));
+            }
+            return Ok(format!("key {} ({})", key.id, key.name));
+        }
+
+        if self.operator.as_ref().is_some_and(|operator| operator.verify(token)) {
+            return Ok("api token".to_string());
+        }
+        match &self.legacy {
+            Some(legacy) if legacy.verify(token) => Ok("wallet token".to_string()),
+            _ => Err((StatusCode::UNAUTHORIZED, "Invalid API key".to_string())),
+        }
+    }
+}
+
+pub async fn require_scope(
+    State(guard): State<Arc<ScopeGuard>>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let token = bearer_token(&request);
+    if token.is_none() && guard.is_public(request.method(), request.uri().path()) {
+        return next.run(request).await;
+    }
+
+    let caller = match token.map(|token| guard.authorize(&token)) {
+        Some(Ok(caller)) => caller,
+        Some(Err(error)) => return ApiError::from(error).into_response(),
+        None => {
+            let message = format!("Endpoint requires an API key with the '{}' scope", guard.scope);
+            return ApiError::new(ErrorCode::Unauthorized, message).into_response();
+        }
+    };
+
+    let origin = request.extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| format!("api {} {}", addr.ip(), caller))
+        .unwrap_or_else(|| format!("api {}", caller));
+    let audited = (request.method() != Method::GET && request.method() != Method::HEAD)
+        .then(|| format!("{} {}", request.method(), request.uri().path()));
+
+    request.extensions_mut().insert(ApiCaller(origin.clone()));
+    let response = next.run(request).await;
+
+    if let Some(call) = audited {
+        let details = format!("{} -> {}", call, response.status().as_u16());
+        if let Err(e) = guard.db.record_audit_event(AuditAction::ApiWrite, &origin, details) {
+            log::error!("📝 Failed to record audit event: {}", e);
+        }
+    }
+
+    response
+}
+
+/// The `Authorization: Bearer` token, or for WebSocket upgrades an `access_token`
+/// query parameter, since browsers can't set headers on a WebSocket handshake
+fn bearer_token(request: &Request) -> Option<String> {
+    let headers = request.headers();
+    if let Some(token) = headers.get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+    {
+        return Some(token.to_string());
+    }
+
+    let upgrade = headers.get(header::UPGRADE).and_then(|value| value.to_str().ok());
+    if !upgrade.is_some_and(|value| value.eq_ignore_ascii_case("websocket")) {
+        return None;
+    }
+    request.uri().query()?
+        .split('&')
+        .find_map(|pair| pair.strip_prefix("access_token="))
+        .map(str::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_token_verification() {
+        let auth = WalletAuth::new("s3cret");
+        assert!(auth.verify("s3cret"));
+        assert!(!auth.verify("s3cre"));
+        assert!(!auth.verify(""));
+    }
+
+    #[test]
+    fn test_scoped_keys() -> Result<()> {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Arc::new(Database::new(temp_dir.path().join("test.db"))?);
+
+        let (reader, token) = create_api_key(&db, "explorer", vec![ApiScope::Read], "cli")?;
+        let (_, admin_token) = create_api_key(&db, "ops", vec![ApiScope::Admin], "cli")?;
+        assert_eq!(ApiKey::id_from_token(&token), Some(reader.id.as_str()));
+
+        let read_guard = ScopeGuard::new(ApiScope::Read, db.clone());
+        let broadcast_guard = ScopeGuard::new(ApiScope::Broadcast, db.clone());
+        assert!(read_guard.authorize(&token).is_ok());
+        assert_eq!(broadcast_guard.authorize(&token).unwrap_err().0, StatusCode::FORBIDDEN);
+        assert!(broadcast_guard.authorize(&admin_token).is_ok());
+
+        // A tampered secret with a valid ID is still refused
+        let forged = format!("{}x", token);
+        assert_eq!(read_guard.authorize(&forged).unwrap_err().0, StatusCode::UNAUTHORIZED);
+
+        revoke_api_key(&db, &reader.id, "cli")?;
+        assert_eq!(read_guard.authorize(&token).unwrap_err().0, StatusCode::UNAUTHORIZED);
+        assert!(revoke_api_key(&db, &reader.id, "cli").is_err());
+        assert_eq!(db.list_api_keys()?.len(), 2);
+
+        let wallet_guard = ScopeGuard::new(ApiScope::WalletSpend, db.clone()).with_legacy_token("s3cret");
+        assert!(wallet_guard.authorize("s3cret").is_ok());
+        assert!(wallet_guard.authorize("guess").is_err());
+
+        let admin_guard = ScopeGuard::new(ApiScope::Admin, db).with_operator_token("from-config");
+        assert_eq!(admin_guard.authorize("from-config").unwrap(), "api token");
+        assert!(admin_guard.authorize("s3cret").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_public_paths() -> Result<()> {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Arc::new(Database::new(temp_dir.path().join("test.db"))?);
+        let guard = ScopeGuard::new(ApiScope::Read, db)
+            .with_public_paths(vec!["/api/v1/info".to_string(), "/api/v1/blocks*".to_string()]);
+
+        assert!(guard.is_public(&Method::GET, "/api/v1/info"));
+        assert!(guard.is_public(&Method::GET, "/api/v1/blocks/latest"));
+        assert!(!guard.is_public(&Method::GET, "/api/v1/info/extra"));
+        assert!(!guard.is_public(&Method::GET, "/api/v1/mempool"));
+        assert!(!guard.is_public(&Method::POST, "/api/v1/blocks"));
+        Ok(())
+    }
+}
@@ -0,0 +1,114 @@
+//! Short-lived in-memory cache for GET API responses
+
+use axum::{
+    body::{to_bytes, Body, Bytes},
+    extract::{Request, State},
+    http::{header, HeaderValue, Method, StatusCode},
+    middleware::Next,
+    response::Response,
+};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+const MAX_CACHED_BODY: usize = 4 * 1024 * 1024;
+const MAX_ENTRIES: usize = 10_000;
+
+#[derive(Debug, Clone)]
+struct CachedResponse {
+    stored_at: Instant,
+    body: Bytes,
+}
+
+#[derive(Debug)]
+pub struct ResponseCache {
+    ttl: Duration,
+    entries: Mutex<HashMap<String, CachedResponse>>,
+}
+
+impl ResponseCache {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Option<Bytes> {
+        let entries = self.entries.lock().ok()?;
+        entries.get(key)
+            .filter(|entry| entry.stored_at.elapsed() < self.ttl)
+            .map(|entry| entry.body.clone())
+    }
+
+    pub fn insert(&self, key: String, body: Bytes) {
+        if let Ok(mut entries) = self.entries.lock() {
+            if entries.len() >= MAX_ENTRIES {
+                let ttl = self.ttl;
+                entries.retain(|_, entry| entry.stored_at.elapsed() < ttl);
+                if entries.len() >= MAX_ENTRIES {
+                    entries.clear();
+                }
+            }
+            entries.insert(key, CachedResponse { stored_at: Instant::now(), body });
+        }
+    }
+}
+
+pub async fn cache_middleware(
+    State(cache): State<Arc<ResponseCache>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if request.method() != Method::GET {
+        return next.run(request).await;
+    }
+
+    let key = request.uri().to_string();
+    if let Some(body) = cache.get(&key) {
+        return cached_response(body, "HIT");
+    }
+
+    let response = next.run(request).await;
+    if response.status() != StatusCode::OK {
+        return response;
+    }
+
+    let (parts, body) = response.into_parts();
+    match to_bytes(body, MAX_CACHED_BODY).await {
+        Ok(bytes) => {
+            cache.insert(key, bytes.clone());
+            let mut response = Response::from_parts(parts, Body::from(bytes));
+            response.headers_mut().insert("x-cache", HeaderValue::from_static("MISS"));
+            response
+        }
+        Err(_) => Response::builder()
+            .status(StatusCode::INTERNAL_SERVER_ERROR)
+            .body(Body::empty())
+            .unwrap_or_default(),
+    }
+}
+
+fn cached_response(body: Bytes, status: &'static str) -> Response {
+    let mut response = Response::new(Body::from(body));
+    response.headers_mut().insert(header::CONTENT_TYPE, HeaderValue::from_static("application/json"));
+    response.headers_mut().insert("x-cache", HeaderValue::from_static(status));
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cache_expiry() {
+        let cache = ResponseCache::new(Duration::from_millis(50));
+        cache.insert("/api/v1/info".to_string(), Bytes::from_static(b"{}"));
+
+        assert_eq!(cache.get("/api/v1/info"), Some(Bytes::from_static(b"{}")));
+        assert!(cache.get("/api/v1/stats").is_none());
+
+        std::thread::sleep(Duration::from_millis(60));
+        assert!(cache.get("/api/v1/info").is_none());
+    }
+}
@@ -0,0 +1,510 @@
+//! API workers: REST and WebSocket front ends running as separate processes
+//!
+//! A node listens on its control socket (`api.control_socket`, or
+//! `control.sock` in the data directory) for its workers. Every
+//! line a worker writes is a `ControlRequest` and every line back a
+//! `ControlResponse`: GETs are answered by the node's own read endpoints, and
+//! broadcasts go into the mempool and out to peers. `Subscribe` turns the
+//! connection into a stream of WebSocket events instead. Workers
+//! (`qtcd api worker`) hold no chain state, so explorer traffic can be spread
+//! over as many of them as needed, each with its own cache and rate limits.
+//! `qtcd mine start|stop|status` use the same socket to drive the node's miner,
+//! and `qtcd db backup` to archive the data directory the node has locked.
+//! The socket is only accessible to the node's user; anything that can open
+//! it can broadcast transactions, start or stop mining and write backups.
+
+use crate::api::cache::{cache_middleware, ResponseCache};
+use crate::api::ratelimit::{rate_limit_middleware, RateLimiter};
+use crate::api::error::ApiError;
+use crate::api::rest::{submit_transaction, ApiResponse, RestApi, SendTransactionRequest};
+use crate::api::websocket::{ChainEventTranslator, WebSocketEvent};
+use crate::config::ApiConfig;
+use crate::core::{Blockchain, Transaction};
+use crate::mining::MiningController;
+use crate::network::p2p::P2PCommand;
+use crate::storage::create_backup;
+use crate::{QtcError, Result};
+use axum::body::Body;
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::{Request, State};
+use axum::http::{header, Method, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{middleware, Json, Router};
+use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::{UnixListener, UnixStream};
+use tokio::sync::{broadcast, mpsc};
+use tower::Service;
+
+/// Largest response body relayed to a worker
+const MAX_RESPONSE_BYTES: usize = 16 * 1024 * 1024;
+
+/// How long a worker waits on the node before answering 504
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Pause before a worker resubscribes after losing the node's event stream
+const RESUBSCRIBE_DELAY: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ControlRequest {
+    Get { path: String }, // path and query, e.g. /api/v1/blocks?limit=5
+    Broadcast { raw_transaction: String },
+    Subscribe,
+    MiningStart {
+        address: Option<String>,
+        #[serde(default)]
+        payouts: Vec<String>, // address:percentage specs splitting each reward
+        threads: Option<usize>, // None: the node's defaults
+        #[serde(default)]
+        fast: bool, // RandomX fast mode, on top of mining.fast_mode
+    },
+    MiningStop,
+    MiningStatus,
+    Backup { path: PathBuf }, // absolute, as the node's working directory may differ
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ControlResponse {
+    pub status: u16,
+    pub body: String, // JSON, as the REST API would have sent it
+}
+
+impl ControlResponse {
+    fn json<T: Serialize>(status: StatusCode, body: &ApiResponse<T>) -> Self {
+        Self { status: status.as_u16(), body: serde_json::to_string(body).unwrap_or_default() }
+    }
+
+    fn error(error: ApiError) -> Self {
+        Self::json(error.status, &error.body())
+    }
+}
+
+/// The node's end of the control socket
+pub struct ControlServer {
+    blockchain: Arc<RwLock<Blockchain>>,
+    rest: RestApi,
+    path: PathBuf,
+    p2p_commands: Option<mpsc::Sender<P2PCommand>>,
+    mining: Option<Arc<MiningController>>,
+    data_dir: Option<PathBuf>,
+}
+
+impl ControlServer {
+    /// Serve `rest`'s read endpoints on the Unix socket at `path`
+    pub fn new(blockchain: Arc<RwLock<Blockchain>>, rest: RestApi, path: PathBuf) -> Self {
+        Self { blockchain, rest, path, p2p_commands: None, mining: None, data_dir: None }
+    }
+
+    /// Relay transactions workers broadcast to peers, not just the mempool
+    pub fn set_p2p_commands(&mut self, p2p_commands: mpsc::Sender<P2PCommand>) {
+        self.p2p_commands = Some(p2p_commands);
+    }
+
+    /// Answer the `Mining*` requests with this controller
+    pub fn set_mining(&mut self, mining: Arc<MiningController>) {
+        self.mining = Some(mining);
+    }
+
+    /// Answer `Backup` requests by archiving this data directory
+    pub fn set_data_dir(&mut self, data_dir: PathBuf) {
+        self.data_dir = Some(data_dir);
+    }
+
+    pub async fn start(self) -> Result<()> {
+        // Left behind by a node that didn't shut down cleanly
+        if std::fs::symlink_metadata(&self.path).is_ok_and(|meta| meta.file_type().is_socket()) {
+            std::fs::remove_file(&self.path)?;
+        }
+        let listener = UnixListener::bind(&self.path)
+            .map_err(|e| QtcError::Network(format!("Failed to bind control socket {}: {}", self.path.display(), e)))?;
+        std::fs::set_permissions(&self.path, std::fs::Permissions::from_mode(0o600))?;
+        log::info!("🧷 Control socket listening on {}", self.path.display());
+
+        let handler = Arc::new(ControlHandler {
+            router: self.rest.worker_routes(),
+            blockchain: self.blockchain,
+            p2p_commands: self.p2p_commands,
+            mining: self.mining,
+            data_dir: self.data_dir,
+        });
+        loop {
+            let (stream, _) = listener.accept().await
+                .map_err(|e| QtcError::Network(format!("Control socket accept failed: {}", e)))?;
+            let handler = handler.clone();
+            tokio::spawn(async move {
+                if let Err(e) = handler.serve(stream).await {
+                    log::debug!("🧷 Control connection closed: {}", e);
+                }
+            });
+        }
+    }
+}
+
+struct ControlHandler {
+    router: Router,
+    blockchain: Arc<RwLock<Blockchain>>,
+    p2p_commands: Option<mpsc::Sender<P2PCommand>>,
+    mining: Option<Arc<MiningController>>,
+    data_dir: Option<PathBuf>,
+}
+
+impl ControlHandler {
+    async fn serve(&self, stream: UnixStream) -> Result<()> {
+        let (reader, mut writer) = stream.into_split();
+        let mut lines = BufReader::new(reader).lines();
+
+        while let Some(line) = lines.next_line().await? {
+            let response = match serde_json::from_str(&line) {
+                Ok(ControlRequest::Get { path }) => self.get(&path).await,
+                Ok(ControlRequest::Broadcast { raw_transaction }) => self.broadcast(&raw_transaction).await,
+                Ok(ControlRequest::Subscribe) => return self.stream_events(&mut writer).await,
+                Ok(ControlRequest::MiningStart { address, payouts, threads, fast }) => self.mining(|mining| mining.start(address, &payouts, threads, fast)),
+                Ok(ControlRequest::MiningStop) => self.mining(|mining| mining.stop()),
+                Ok(ControlRequest::MiningStatus) => self.mining(|mining| Ok(mining.status())),
+                Ok(ControlRequest::Backup { path }) => self.backup(path).await,
+                Err(e) => ControlResponse::error(ApiError::bad_request(format!("Invalid control request: {}", e))),
+            };
+            write_line(&mut writer, &response).await?;
+        }
+        Ok(())
+    }
+
+    async fn get(&self, path: &str) -> ControlResponse {
+        let request = match Request::get(path).body(Body::empty()) {
+            Ok(request) if path.starts_with('/') => request,
+            _ => return ControlResponse::error(ApiError::bad_request(format!("Invalid path: {}", path))),
+        };
+
+        let response = match self.router.clone().call(request).await {
+            Ok(response) => response,
+            Err(never) => match never {},
+        };
+        let status = response.status().as_u16();
+        match axum::body::to_bytes(response.into_body(), MAX_RESPONSE_BYTES).await {
+            Ok(body) => ControlResponse { status, body: String::from_utf8_lossy(&body).into_owned() },
+            Err(_) => ControlResponse::error(ApiError::internal("Response too large to relay")),
+        }
+    }
+
+    async fn broadcast(&self, raw_transaction: &str) -> ControlResponse {
+        let tx = match hex::decode(raw_transaction).ok().and_then(|bytes| bincode::deserialize::<Transaction>(&bytes).ok()) {
+            Some(tx) => tx,
+            None => return ControlResponse::error(ApiError::bad_request("Invalid raw transaction")),
+        };
+
+        match submit_transaction(&self.blockchain, self.p2p_commands.as_ref(), tx).await {
+            Ok(txid) => ControlResponse::json(StatusCode::OK, &ApiResponse::success(txid.to_hex())),
+            Err(e) => ControlResponse::error(ApiError::bad_request(format!("Transaction rejected: {}", e))),
+        }
+    }
+
+    fn mining<T: Serialize>(&self, action: impl FnOnce(&MiningController) -> Result<T>) -> ControlResponse {
+        let Some(mining) = &self.mining else {
+            return ControlResponse::error(ApiError::not_found("This node does not accept mining commands"));
+        };
+        match action(mining) {
+            Ok(result) => ControlResponse::json(StatusCode::OK, &ApiResponse::success(result)),
+            Err(e) => ControlResponse::error(ApiError::bad_request(e.to_string())),
+        }
+    }
+
+    async fn backup(&self, path: PathBuf) -> ControlResponse {
+        let Some(data_dir) = self.data_dir.clone() else {
+            return ControlResponse::error(ApiError::not_found("This node does not write backups"));
+        };
+        if !path.is_absolute() {
+            return ControlResponse::error(ApiError::bad_request(format!("Backup path {} is not absolute", path.display())));
+        }
+
+        let blockchain = self.blockchain.clone();
+        let backup = tokio::task::spawn_blocking(move || create_backup(&blockchain, &data_dir, &path)).await;
+        match backup {
+            Ok(Ok(report)) => {
+                log::info!("💾 Backup of height {} written to {}", report.height, report.path.display());
+                ControlResponse::json(StatusCode::OK, &ApiResponse::success(report))
+            }
+            Ok(Err(e)) => ControlResponse::error(ApiError::from(e)),
+            Err(e) => ControlResponse::error(ApiError::internal(format!("Backup task failed: {}", e))),
+        }
+    }
+
+    async fn stream_events(&self, writer: &mut (impl AsyncWrite + Unpin)) -> Result<()> {
+        let (mut events, mut translator) = {
+            let blockchain = self.blockchain.read().unwrap();
+            (blockchain.subscribe_events(), ChainEventTranslator::new(&blockchain))
+        };
+        loop {
+            let event = match events.recv().await {
+                Ok(event) => event,
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    log::debug!("🧷 Worker subscription skipped {} chain events", skipped);
+                    continue;
+                }
+                Err(broadcast::error::RecvError::Closed) => return Ok(()),
+            };
+            let notifications = translator.translate(event, &self.blockchain.read().unwrap());
+            for notification in &notifications {
+                write_line(writer, notification).await?;
+            }
+        }
+    }
+}
+
+/// A worker's handle on the node's control socket
+#[derive(Debug, Clone)]
+pub struct ControlClient {
+    path: PathBuf,
+}
+
+impl ControlClient {
+    pub fn new(path: impl AsRef<Path>) -> Self {
+        Self { path: path.as_ref().to_path_buf() }
+    }
+
+    /// One request on its own connection; local sockets are cheap to open
+    pub async fn request(&self, request: &ControlRequest) -> Result<ControlResponse> {
+        let stream = self.connect().await?;
+        let (reader, mut writer) = stream.into_split();
+        write_line(&mut writer, request).await?;
+
+        let line = BufReader::new(reader).lines().next_line().await?
+            .ok_or_else(|| QtcError::Network("Control socket closed without answering".to_string()))?;
+        serde_json::from_str(&line)
+            .map_err(|e| QtcError::Network(format!("Invalid control response: {}", e)))
+    }
+
+    /// `request`, with the data unwrapped from the `ApiResponse` envelope
+    pub async fn call<T: serde::de::DeserializeOwned>(&self, request: &ControlRequest) -> Result<T> {
+        let response = self.request(request).await?;
+        let response: ApiResponse<T> = serde_json::from_str(&response.body)
+            .map_err(|e| QtcError::Network(format!("Invalid control response: {}", e)))?;
+        response.data
+            .ok_or_else(|| QtcError::Network(response.error.unwrap_or_else(|| "No data returned".to_string())))
+    }
+
+    /// Feed the node's WebSocket events into `events` until the connection drops
+    pub async fn subscribe(&self, events: &broadcast::Sender<WebSocketEvent>) -> Result<()> {
+        let stream = self.connect().await?;
+        let (reader, mut writer) = stream.into_split();
+        write_line(&mut writer, &ControlRequest::Subscribe).await?;
+
+        let mut lines = BufReader::new(reader).lines();
+        while let Some(line) = lines.next_line().await? {
+            let event = serde_json::from_str(&line)
+                .map_err(|e| QtcError::Network(format!("Invalid event from node: {}", e)))?;
+            // No WebSocket clients connected is fine
+            let _ = events.send(event);
+        }
+        Err(QtcError::Network("Node closed the event stream".to_string()))
+    }
+
+    async fn connect(&self) -> Result<UnixStream> {
+        UnixStream::connect(&self.path).await
+            .map_err(|e| QtcError::Network(format!("Failed to connect to control socket {}: {}", self.path.display(), e)))
+    }
+}
+
+async fn write_line<T: Serialize>(writer: &mut (impl AsyncWrite + Unpin), message: &T) -> Result<()> {
+    let mut line = serde_json::to_vec(message)?;
+    line.push(b'\n');
+    writer.write_all(&line).await?;
+    Ok(())
+}
+
+/// A REST and WebSocket front end serving from a node over its control socket
+pub struct ApiWorker {
+    client: ControlClient,
+    config: ApiConfig,
+    port: u16,
+}
+
+#[derive(Clone)]
+struct WorkerState {
+    client: ControlClient,
+    events: broadcast::Sender<WebSocketEvent>,
+}
+
+impl ApiWorker {
+    pub fn new(socket: impl AsRef<Path>, config: ApiConfig) -> Self {
+        let port = config.rest_port;
+        Self { client: ControlClient::new(socket), config, port }
+    }
+
+    pub fn set_port(&mut self, port: u16) {
+        self.port = port;
+    }
+
+    pub async fn start(self) -> Result<()> {
+        if self.config.require_api_keys {
+            return Err(QtcError::InvalidInput(
+                "API workers can't check API keys, which live in the node's database; serve keyed clients from the node".to_string()
+            ));
+        }
+        // Fail fast on a wrong socket path rather than answering 502 forever
+        self.client.request(&ControlRequest::Get { path: "/api/v1/info".to_string() }).await?;
+
+        let (events, _) = broadcast::channel(1000);
+        let subscriber = {
+            let (client, events) = (self.client.clone(), events.clone());
+            tokio::spawn(async move {
+                loop {
+                    if let Err(e) = client.subscribe(&events).await {
+                        log::warn!("🧷 Lost the node's event stream: {}", e);
+                    }
+                    tokio::time::sleep(RESUBSCRIBE_DELAY).await;
+                }
+            })
+        };
+
+        let app = self.create_router(WorkerState { client: self.client.clone(), events });
+        let addr = SocketAddr::new(self.config.bind_address, self.port);
+        let listener = tokio::net::TcpListener::bind(&addr).await
+            .map_err(|e| QtcError::Network(format!("Failed to bind to {}: {}", addr, e)))?;
+        log::info!("✅ API worker listening on http://{} (WebSocket at /ws)", addr);
+
+        let result = axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>()).await
+            .map_err(|e| QtcError::Network(format!("Server error: {}", e)));
+        subscriber.abort();
+        result
+    }
+
+    fn create_router(&self, state: WorkerState) -> Router {
+        let mut router = Router::new()
+            .route("/ws", get(worker_websocket))
+            .fallback(forward_read);
+        if !self.config.read_only {
+            router = router.route("/api/v1/transactions", post(forward_broadcast));
+        }
+        if self.config.cache_ttl_secs > 0 {
+            let cache = Arc::new(ResponseCache::new(Duration::from_secs(self.config.cache_ttl_secs)));
+            router = router.layer(middleware::from_fn_with_state(cache, cache_middleware));
+        }
+        if self.config.rate_limit_per_minute > 0 {
+            let limiter = Arc::new(RateLimiter::new(self.config.rate_limit_per_minute));
+            let pruner = limiter.clone();
+            tokio::spawn(async move {
+                let mut interval = tokio::time::interval(Duration::from_secs(300));
+                loop {
+                    interval.tick().await;
+                    pruner.prune();
+                }
+            });
+            router = router.layer(middleware::from_fn_with_state(limiter, rate_limit_middleware));
+        }
+        router.with_state(state)
+    }
+}
+
+async fn forward_read(State(state): State<WorkerState>, request: Request) -> Response {
+    if request.method() != Method::GET {
+        return ApiError::with_status(StatusCode::METHOD_NOT_ALLOWED, "API workers only serve reads and broadcasts").into_response();
+    }
+    let path = request.uri().path_and_query().map_or("/", |path| path.as_str()).to_string();
+    relay(&state.client, ControlRequest::Get { path }).await
+}
+
+async fn forward_broadcast(State(state): State<WorkerState>, Json(req): Json<SendTransactionRequest>) -> Response {
+    relay(&state.client, ControlRequest::Broadcast { raw_transaction: req.raw_transaction }).await
+}
+
+async fn relay(client: &ControlClient, request: ControlRequest) -> Response {
+    match tokio::time::timeout(REQUEST_TIMEOUT, client.request(&request)).await {
+        Ok(Ok(response)) => (
+            StatusCode::from_u16(response.status).unwrap_or(StatusCode::BAD_GATEWAY),
+            [(header::CONTENT_TYPE, "application/json")],
+            response.body,
+        ).into_response(),
+        Ok(Err(e)) => ApiError::with_status(StatusCode::BAD_GATEWAY, format!("Node unavailable: {}", e)).into_response(),
+        Err(_) => ApiError::with_status(StatusCode::GATEWAY_TIMEOUT, "Node took too long to answer").into_response(),
+    }
+}
+
+async fn worker_websocket(ws: WebSocketUpgrade, State(state): State<WorkerState>) -> Response {
+    let events = state.events.subscribe();
+    ws.on_upgrade(move |socket| relay_events(socket, events))
+}
+
+/// Every client gets every event; workers don't track subscriptions
+async fn relay_events(mut socket: WebSocket, mut events: broadcast::Receiver<WebSocketEvent>) {
+    loop {
+        tokio::select! {
+            event = events.recv() => match event {
+                Ok(event) => {
+                    let Ok(text) = serde_json::to_string(&event) else { continue };
+                    if socket.send(Message::Text(text)).await.is_err() {
+                        return;
+                    }
+                }
+                Err(broadcast::error::RecvError::Lagged(_)) => {}
+                Err(broadcast::error::RecvError::Closed) => return,
+            },
+            message = socket.recv() => match message {
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => return,
+                Some(Ok(_)) => {}
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::crypto::keys::KeyPair;
+    use crate::storage::Database;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_control_socket_answers_workers() -> Result<()> {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Arc::new(Database::new(temp_dir.path().join("test.db"))?);
+        let blockchain = Arc::new(RwLock::new(Blockchain::new(db.clone())?));
+        blockchain.write().unwrap().set_chain_params(Config::regtest().chain_params()?);
+        let socket = temp_dir.path().join("control.sock");
+
+        let rest = RestApi::with_db(blockchain.clone(), db, Config::regtest().api);
+        let server = tokio::spawn(ControlServer::new(blockchain.clone(), rest, socket.clone()).start());
+        let client = ControlClient::new(&socket);
+        for _ in 0..100 {
+            if socket.exists() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        let info = client.request(&ControlRequest::Get { path: "/api/v1/info".to_string() }).await?;
+        assert_eq!(info.status, 200);
+        let body: serde_json::Value = serde_json::from_str(&info.body)?;
+        assert_eq!(body["success"], true);
+        assert_eq!(client.request(&ControlRequest::Get { path: "/api/v1/nowhere".to_string() }).await?.status, 404);
+        // Admin endpoints aren't reachable through a worker
+        assert_eq!(client.request(&ControlRequest::Get { path: "/api/v1/mempool/dump".to_string() }).await?.status, 404);
+        let rejected = client.request(&ControlRequest::Broadcast { raw_transaction: "zz".to_string() }).await?;
+        assert_eq!(rejected.status, 400);
+
+        // New blocks reach subscribed workers
+        let (events, mut received) = broadcast::channel(16);
+        let subscription = {
+            let client = client.clone();
+            tokio::spawn(async move { client.subscribe(&events).await })
+        };
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let address = KeyPair::new()?.address();
+        let hashes = crate::mining::generate_blocks(&mut blockchain.write().unwrap(), 1, &address)?;
+        match tokio::time::timeout(Duration::from_secs(5), received.recv()).await {
+            Ok(Ok(WebSocketEvent::NewBlock { block })) => assert_eq!(block.hash.hash(), hashes[0]),
+            other => panic!("expected a new block event, got {:?}", other),
+        }
+
+        subscription.abort();
+        server.abort();
+        Ok(())
+    }
+}
@@ -0,0 +1,152 @@
+//! Raw blocks and transactions decoded field by field
+//!
+//! `POST /api/v1/decode/block` and `/api/v1/decode/transaction` take the same
+//! hex the node serves and accepts, and answer with every field it carries,
+//! scripts included, without looking anything up on the chain. Tooling can
+//! check what it built before broadcasting it. Decoding is strict: bytes left
+//! over after the block or transaction are an error, not silently dropped.
+
+use crate::api::error::ApiError;
+use crate::api::rest::{AmountInfo, TxOutputInfo};
+use crate::api::types::{BlockHashHex, TxIdHex};
+use crate::consensus::target::Target;
+use crate::core::{Block, Transaction};
+use crate::crypto::hash::Hashable;
+use bincode::Options;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+/// Decode `raw` hex as the node serializes a `what`, refusing trailing bytes
+pub fn decode_raw<T: DeserializeOwned>(raw: &str, what: &str) -> Result<T, ApiError> {
+    let bytes = hex::decode(raw.trim())
+        .map_err(|_| ApiError::bad_request("Invalid hex encoding"))?;
+    bincode::DefaultOptions::new()
+        .with_fixint_encoding()
+        .reject_trailing_bytes()
+        .deserialize(&bytes)
+        .map_err(|e| ApiError::bad_request(format!("Failed to deserialize {}: {}", what, e)))
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DecodedInput {
+    pub txid: TxIdHex, // all zeros for a coinbase
+    pub vout: u32,
+    pub signature_script: String,
+    pub sequence: u32,
+    pub witness: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DecodedOutput {
+    #[serde(flatten)]
+    pub info: TxOutputInfo,
+    pub script_pubkey: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DecodedTransaction {
+    pub txid: TxIdHex,
+    pub version: u32,
+    pub lock_time: u64,
+    pub size: usize,
+    pub is_coinbase: bool,
+    pub inputs: Vec<DecodedInput>,
+    pub outputs: Vec<DecodedOutput>,
+    pub total_output_value: AmountInfo, // input values need the chain, so there's no fee
+}
+
+impl DecodedTransaction {
+    pub fn from_transaction(tx: &Transaction) -> Self {
+        Self {
+            txid: tx.hash().into(),
+            version: tx.version,
+            lock_time: tx.lock_time,
+            size: tx.size(),
+            is_coinbase: tx.is_coinbase(),
+            inputs: tx.inputs.iter().map(|input| DecodedInput {
+                txid: input.previous_output.txid.into(),
+                vout: input.previous_output.vout,
+                signature_script: hex::encode(&input.signature_script),
+                sequence: input.sequence,
+                witness: input.witness.iter().map(hex::encode).collect(),
+            }).collect(),
+            outputs: tx.outputs.iter().enumerate().map(|(vout, output)| DecodedOutput {
+                info: TxOutputInfo::from_output(vout, output),
+                script_pubkey: hex::encode(&output.script_pubkey),
+            }).collect(),
+            total_output_value: AmountInfo::new(tx.total_output_value()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DecodedBlock {
+    pub hash: BlockHashHex,
+    pub size: usize,
+    pub height: u64,
+    pub previous_hash: BlockHashHex,
+    pub merkle_root: String,
+    pub merkle_root_valid: bool, // the header commits to these transactions
+    pub timestamp: u64,
+    pub bits: u32,
+    pub difficulty: f64,
+    pub nonce: u64,
+    pub transaction_count: usize,
+    pub transactions: Vec<DecodedTransaction>,
+}
+
+impl DecodedBlock {
+    pub fn from_block(block: &Block) -> Self {
+        Self {
+            hash: block.hash().into(),
+            size: block.size(),
+            height: block.header.height,
+            previous_hash: block.header.previous_hash.into(),
+            merkle_root: block.header.merkle_root.to_hex(),
+            merkle_root_valid: Block::calculate_merkle_root(&block.transactions) == block.header.merkle_root,
+            timestamp: block.header.timestamp,
+            bits: block.header.bits,
+            difficulty: Target::from_bits(block.header.bits).difficulty(),
+            nonce: block.header.nonce,
+            transaction_count: block.transactions.len(),
+            transactions: block.transactions.iter().map(DecodedTransaction::from_transaction).collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::UtxoSet;
+    use crate::crypto::hash::Hash256;
+    use crate::crypto::keys::KeyPair;
+
+    #[test]
+    fn test_decode_raw_block() -> crate::Result<()> {
+        let miner = KeyPair::new()?.address();
+        let coinbase = Transaction::new_coinbase(miner.clone(), 5_000, "decode me".to_string());
+        let block = Block::new(Hash256::zero(), vec![coinbase.clone()], 0x207fffff, 1);
+        let raw = hex::encode(bincode::serialize(&block).unwrap());
+
+        let decoded = DecodedBlock::from_block(&decode_raw::<Block>(&raw, "block").unwrap());
+        assert_eq!(decoded.hash, block.hash().into());
+        assert!(decoded.merkle_root_valid);
+        assert_eq!(decoded.transaction_count, 1);
+        let tx = &decoded.transactions[0];
+        assert!(tx.is_coinbase);
+        assert_eq!(tx.txid, coinbase.hash().into());
+        assert_eq!(tx.inputs[0].signature_script, hex::encode(&coinbase.inputs[0].signature_script));
+        assert_eq!(tx.outputs[0].info.address.as_deref(), Some(UtxoSet::output_address(&Transaction::address_to_script_pubkey(&miner)).as_str()));
+        assert_eq!(tx.total_output_value.amount_sats, 5_000);
+
+        // A block whose header doesn't commit to its transactions still decodes, flagged
+        let mut tampered = block.clone();
+        tampered.transactions.push(coinbase);
+        assert!(!DecodedBlock::from_block(&tampered).merkle_root_valid);
+
+        assert!(decode_raw::<Block>(&format!("{}00", raw), "block").is_err());
+        assert!(decode_raw::<Block>("not hex", "block").is_err());
+        assert!(decode_raw::<Transaction>(&raw[..raw.len() / 2], "transaction").is_err());
+        Ok(())
+    }
+}
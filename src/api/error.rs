@@ -0,0 +1,162 @@
+//! Error responses shared by the REST handlers and middleware
+//!
+//! A failed request gets a matching HTTP status and an `ApiResponse` body
+//! whose `code` names the kind of failure, so clients can branch on `code`
+//! instead of parsing `error` messages.
+
+use crate::api::rest::ApiResponse;
+use crate::QtcError;
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Json, Response};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    BadRequest,
+    Unauthorized,
+    Forbidden,
+    NotFound,
+    Conflict,
+    RateLimited,
+    Unavailable,
+    Internal,
+}
+
+impl ErrorCode {
+    pub fn status(self) -> StatusCode {
+        match self {
+            ErrorCode::BadRequest => StatusCode::BAD_REQUEST,
+            ErrorCode::Unauthorized => StatusCode::UNAUTHORIZED,
+            ErrorCode::Forbidden => StatusCode::FORBIDDEN,
+            ErrorCode::NotFound => StatusCode::NOT_FOUND,
+            ErrorCode::Conflict => StatusCode::CONFLICT,
+            ErrorCode::RateLimited => StatusCode::TOO_MANY_REQUESTS,
+            ErrorCode::Unavailable => StatusCode::SERVICE_UNAVAILABLE,
+            ErrorCode::Internal => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    /// The code for a status reported by code that predates `ErrorCode`
+    pub fn from_status(status: StatusCode) -> Self {
+        match status {
+            StatusCode::UNAUTHORIZED => ErrorCode::Unauthorized,
+            StatusCode::FORBIDDEN => ErrorCode::Forbidden,
+            StatusCode::NOT_FOUND => ErrorCode::NotFound,
+            StatusCode::CONFLICT => ErrorCode::Conflict,
+            StatusCode::TOO_MANY_REQUESTS => ErrorCode::RateLimited,
+            StatusCode::SERVICE_UNAVAILABLE | StatusCode::BAD_GATEWAY | StatusCode::GATEWAY_TIMEOUT => ErrorCode::Unavailable,
+            status if status.is_client_error() => ErrorCode::BadRequest,
+            _ => ErrorCode::Internal,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ApiError {
+    pub status: StatusCode,
+    pub code: ErrorCode,
+    pub message: String,
+}
+
+impl ApiError {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        Self { status: code.status(), code, message: message.into() }
+    }
+
+    /// Keep an exact status (e.g. 502 from a worker's relay) while still naming a code
+    pub fn with_status(status: StatusCode, message: impl Into<String>) -> Self {
+        Self { status, code: ErrorCode::from_status(status), message: message.into() }
+    }
+
+    pub fn bad_request(message: impl Into<String>) -> Self {
+        Self::new(ErrorCode::BadRequest, message)
+    }
+
+    pub fn not_found(message: impl Into<String>) -> Self {
+        Self::new(ErrorCode::NotFound, message)
+    }
+
+    pub fn unavailable(message: impl Into<String>) -> Self {
+        Self::new(ErrorCode::Unavailable, message)
+    }
+
+    pub fn internal(message: impl Into<String>) -> Self {
+        Self::new(ErrorCode::Internal, message)
+    }
+
+    /// Prefix the message, e.g. with the operation that failed
+    pub fn context(mut self, context: &str) -> Self {
+        self.message = format!("{}: {}", context, self.message);
+        self
+    }
+
+    pub fn body(&self) -> ApiResponse<()> {
+        ApiResponse::error(self.code, self.message.clone())
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", self.message, self.status)
+    }
+}
+
+/// Invalid input from the caller is their error; anything else is ours
+impl From<QtcError> for ApiError {
+    fn from(error: QtcError) -> Self {
+        let code = match &error {
+            QtcError::InvalidInput(_)
+            | QtcError::Transaction(_)
+            | QtcError::InsufficientFunds { .. }
+            | QtcError::InvalidSignature
+            | QtcError::InvalidBlockHash
+            | QtcError::InvalidDifficulty
+            | QtcError::Serialization(_) => ErrorCode::BadRequest,
+            QtcError::DoubleSpend(_) => ErrorCode::Conflict,
+            QtcError::StaleSnapshot | QtcError::LowWork { .. } => ErrorCode::Unavailable,
+            _ => ErrorCode::Internal,
+        };
+        Self::new(code, error.to_string())
+    }
+}
+
+impl From<(StatusCode, String)> for ApiError {
+    fn from((status, message): (StatusCode, String)) -> Self {
+        Self::with_status(status, message)
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.status, Json(self.body())).into_response()
+    }
+}
+
+/// What a JSON handler returns: the data, or an error with its status
+pub type ApiResult<T> = std::result::Result<Json<ApiResponse<T>>, ApiError>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_errors_map_to_status_and_code() {
+        let error = ApiError::from(QtcError::InvalidInput("bad hash".to_string()));
+        assert_eq!(error.status, StatusCode::BAD_REQUEST);
+        assert_eq!(error.code, ErrorCode::BadRequest);
+
+        let error = ApiError::from(QtcError::Storage("disk".to_string()));
+        assert_eq!(error.status, StatusCode::INTERNAL_SERVER_ERROR);
+
+        let error = ApiError::with_status(StatusCode::BAD_GATEWAY, "node down");
+        assert_eq!(error.status, StatusCode::BAD_GATEWAY);
+        assert_eq!(error.code, ErrorCode::Unavailable);
+
+        let body = serde_json::to_value(ApiError::not_found("Block not found").body()).unwrap();
+        assert_eq!(body["success"], false);
+        assert_eq!(body["code"], "not_found");
+        assert_eq!(body["error"], "Block not found");
+    }
+}
@@ -0,0 +1,281 @@
+//! Testnet faucet: small payouts from a local wallet to anyone who asks
+//!
+//! Each claim needs a one-time challenge from `/api/v1/faucet/challenge`, solved
+//! by finding a nonce whose `sha256("<token>:<nonce>")` starts with the configured
+//! number of zero bits. That costs a browser or the CLI a moment but makes
+//! scripted draining expensive. On top of that every IP and every address has
+//! to wait out a cooldown between payouts.
+
+use axum::http::StatusCode;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex, RwLock};
+use tokio::sync::mpsc;
+
+use crate::api::rest::AmountInfo;
+use crate::config::FaucetConfig;
+use crate::core::{Blockchain, Transaction};
+use crate::crypto::hash::Hashable;
+use crate::network::p2p::P2PCommand;
+use crate::storage::Database;
+
+/// How long an issued challenge can be redeemed
+const CHALLENGE_TTL_SECS: u64 = 300;
+
+/// Unredeemed challenges kept at once, so requesting them can't exhaust memory
+const MAX_OPEN_CHALLENGES: usize = 10_000;
+
+/// Public faucet settings, so a claimant knows what to expect
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FaucetInfo {
+    pub amount: AmountInfo,
+    pub balance: AmountInfo,
+    pub ip_cooldown_secs: u64,
+    pub address_cooldown_secs: u64,
+    pub challenge_bits: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FaucetChallenge {
+    pub token: String,
+    pub bits: u32,
+    pub expires_at: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FaucetClaim {
+    pub address: String,
+    pub token: String,
+    pub nonce: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FaucetPayout {
+    pub txid: String,
+    pub address: String,
+    pub amount: AmountInfo,
+}
+
+#[derive(Debug, Default)]
+struct FaucetState {
+    challenges: HashMap<String, u64>, // token -> expiry
+    last_ip_payout: HashMap<IpAddr, u64>,
+    last_address_payout: HashMap<String, u64>,
+}
+
+#[derive(Debug)]
+pub struct Faucet {
+    config: FaucetConfig,
+    db: Arc<Database>,
+    blockchain: Arc<RwLock<Blockchain>>,
+    p2p_commands: Option<mpsc::Sender<P2PCommand>>,
+    state: Mutex<FaucetState>,
+}
+
+impl Faucet {
+    pub fn new(config: FaucetConfig, db: Arc<Database>, blockchain: Arc<RwLock<Blockchain>>) -> Self {
+        Self {
+            config,
+            db,
+            blockchain,
+            p2p_commands: None,
+            state: Mutex::new(FaucetState::default()),
+        }
+    }
+
+    /// Relay payouts to peers instead of leaving them in the local mempool
+    pub fn set_p2p_commands(&mut self, p2p_commands: mpsc::Sender<P2PCommand>) {
+        self.p2p_commands = Some(p2p_commands);
+    }
+
+    pub fn info(&self) -> crate::Result<FaucetInfo> {
+        let wallet = self.db.load_wallet(&self.config.wallet, self.blockchain.clone())?;
+        Ok(FaucetInfo {
+            amount: AmountInfo::new(self.config.amount),
+            balance: AmountInfo::new(wallet.get_balance()?),
+            ip_cooldown_secs: self.config.ip_cooldown_secs,
+            address_cooldown_secs: self.config.address_cooldown_secs,
+            challenge_bits: self.config.challenge_bits,
+        })
+    }
+
+    pub fn issue_challenge(&self) -> std::result::Result<FaucetChallenge, (StatusCode, String)> {
+        let now = now();
+        let mut state = self.lock();
+        if state.challenges.len() >= MAX_OPEN_CHALLENGES {
+            state.challenges.retain(|_, expires_at| *expires_at > now);
+            if state.challenges.len() >= MAX_OPEN_CHALLENGES {
+                return Err((StatusCode::SERVICE_UNAVAILABLE, "Faucet is busy, try again shortly".to_string()));
+            }
+        }
+
+        let token = hex::encode(rand::random::<[u8; 16]>());
+        let expires_at = now + CHALLENGE_TTL_SECS;
+        state.challenges.insert(token.clone(), expires_at);
+
+        Ok(FaucetChallenge { token, bits: self.config.challenge_bits, expires_at })
+    }
+
+    /// `dispense`, then hand the payout to the P2P node
+    pub async fn claim(
+        &self,
+        ip: Option<IpAddr>,
+        claim: &FaucetClaim,
+    ) -> std::result::Result<FaucetPayout, (StatusCode, String)> {
+        let (payout, tx) = self.dispense(ip, claim)?;
+        if let Some(p2p_commands) = &self.p2p_commands {
+            if p2p_commands.send(P2PCommand::BroadcastTransaction(tx)).await.is_err() {
+                log::warn!("🚰 P2P node stopped, faucet payout {} was not relayed", payout.txid);
+            }
+        }
+        Ok(payout)
+    }
+
+    /// Pay `claim.address` if the challenge is solved and neither the caller's IP
+    /// nor the address is cooling down. The returned transaction is already in
+    /// the mempool; relaying it is up to the caller.
+    pub fn dispense(
+        &self,
+        ip: Option<IpAddr>,
+        claim: &FaucetClaim,
+    ) -> std::result::Result<(FaucetPayout, Transaction), (StatusCode, String)> {
+        if !crate::crypto::keys::is_valid_address(&claim.address) {
+            return Err((StatusCode::BAD_REQUEST, "Invalid address".to_string()));
+        }
+
+        // Held until the payout is recorded, so concurrent claims can't both pass the cooldowns
+        let now = now();
+        let mut state = self.lock();
+
+        if let Some(wait) = ip.and_then(|ip| cooldown_left(state.last_ip_payout.get(&ip), self.config.ip_cooldown_secs, now)) {
+            return Err((StatusCode::TOO_MANY_REQUESTS, format!("This IP can claim again in {}s", wait)));
+        }
+        if let Some(wait) = cooldown_left(state.last_address_payout.get(&claim.address), self.config.address_cooldown_secs, now) {
+            return Err((StatusCode::TOO_MANY_REQUESTS, format!("This address can claim again in {}s", wait)));
+        }
+
+        // A challenge is spent by any attempt, right or wrong
+        match state.challenges.remove(&claim.token) {
+            Some(expires_at) if expires_at > now => {}
+            _ => return Err((StatusCode::FORBIDDEN, "Unknown or expired challenge".to_string())),
+        }
+        if !solves_challenge(&claim.token, claim.nonce, self.config.challenge_bits) {
+            return Err((StatusCode::FORBIDDEN, "Challenge solution is wrong".to_string()));
+        }
+
+        let tx = self.pay(&claim.address)
+            .map_err(|e| (StatusCode::SERVICE_UNAVAILABLE, format!("Faucet payout failed: {}", e)))?;
+
+        if let Some(ip) = ip {
+            state.last_ip_payout.insert(ip, now);
+        }
+        state.last_address_payout.insert(claim.address.clone(), now);
+
+        let payout = FaucetPayout {
+            txid: tx.hash().to_hex(),
+            address: claim.address.clone(),
+            amount: AmountInfo::new(self.config.amount),
+        };
+        log::info!("🚰 Faucet sent {} sat to {} in {}", self.config.amount, payout.address, payout.txid);
+        Ok((payout, tx))
+    }
+
+    fn pay(&self, address: &str) -> crate::Result<Transaction> {
+        let wallet = self.db.load_wallet(&self.config.wallet, self.blockchain.clone())?;
+        let tx = wallet.create_transaction(address, self.config.amount, self.config.fee_rate)?;
+        if let Err(e) = self.blockchain.read().unwrap().accept_to_mempool(tx.clone()) {
+            wallet.release_transaction_locks(&tx)?;
+            return Err(e);
+        }
+        Ok(tx)
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, FaucetState> {
+        match self.state.lock() {
+            Ok(state) => state,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+}
+
+/// Seconds until a cooldown that started at `last` runs out, if it hasn't yet
+fn cooldown_left(last: Option<&u64>, cooldown_secs: u64, now: u64) -> Option<u64> {
+    let ready_at = last? + cooldown_secs;
+    (ready_at > now).then(|| ready_at - now)
+}
+
+pub fn solves_challenge(token: &str, nonce: u64, bits: u32) -> bool {
+    let digest = Sha256::digest(format!("{}:{}", token, nonce).as_bytes());
+    let mut zeros = 0;
+    for byte in digest.iter() {
+        zeros += byte.leading_zeros();
+        if *byte != 0 {
+            break;
+        }
+    }
+    zeros >= bits
+}
+
+/// Brute-force a nonce for `challenge`; what a claimant runs before claiming
+pub fn solve_challenge(challenge: &FaucetChallenge) -> u64 {
+    (0..).find(|nonce| solves_challenge(&challenge.token, *nonce, challenge.bits)).unwrap_or(0)
+}
+
+fn now() -> u64 {
+    chrono::Utc::now().timestamp() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wallet::Wallet;
+    use crate::Result;
+    use std::net::Ipv4Addr;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_faucet_challenges_and_cooldowns() -> Result<()> {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Arc::new(Database::new(temp_dir.path().join("test.db"))?);
+        let blockchain = Arc::new(RwLock::new(Blockchain::new(db.clone())?));
+        Wallet::new_simple("faucet".to_string(), db.clone(), blockchain.clone())?.save()?;
+
+        let config = FaucetConfig {
+            wallet: "faucet".to_string(),
+            amount: 1_000,
+            fee_rate: 1,
+            ip_cooldown_secs: 60,
+            address_cooldown_secs: 60,
+            challenge_bits: 8,
+        };
+        let faucet = Faucet::new(config, db, blockchain);
+        let ip = Some(IpAddr::V4(Ipv4Addr::LOCALHOST));
+        let address = crate::crypto::keys::KeyPair::new()?.address();
+
+        // Wrong answers burn the challenge
+        let challenge = faucet.issue_challenge().unwrap();
+        let wrong = (0..).find(|n| !solves_challenge(&challenge.token, *n, challenge.bits)).unwrap();
+        let claim = FaucetClaim { address: address.clone(), token: challenge.token.clone(), nonce: wrong };
+        assert_eq!(faucet.dispense(ip, &claim).unwrap_err().0, StatusCode::FORBIDDEN);
+        let claim = FaucetClaim { nonce: solve_challenge(&challenge), ..claim };
+        assert_eq!(faucet.dispense(ip, &claim).unwrap_err().0, StatusCode::FORBIDDEN);
+
+        // A solved challenge gets as far as paying, which an empty wallet can't
+        let challenge = faucet.issue_challenge().unwrap();
+        let claim = FaucetClaim { address: address.clone(), token: challenge.token.clone(), nonce: solve_challenge(&challenge) };
+        assert_eq!(faucet.dispense(ip, &claim).unwrap_err().0, StatusCode::SERVICE_UNAVAILABLE);
+
+        // Cooldowns are checked before the challenge is touched
+        faucet.lock().last_address_payout.insert(address.clone(), now());
+        let challenge = faucet.issue_challenge().unwrap();
+        let claim = FaucetClaim { address, token: challenge.token.clone(), nonce: solve_challenge(&challenge) };
+        assert_eq!(faucet.dispense(None, &claim).unwrap_err().0, StatusCode::TOO_MANY_REQUESTS);
+        assert!(faucet.lock().challenges.contains_key(&challenge.token));
+
+        assert_eq!(cooldown_left(Some(&100), 60, 130), Some(30));
+        assert_eq!(cooldown_left(Some(&100), 60, 160), None);
+        Ok(())
+    }
+}
@@ -0,0 +1,755 @@
+//! bitcoind-style JSON-RPC, so explorers and pool software written for Bitcoin
+//! Core can point at a QTC node
+//!
+//! Requests are JSON-RPC 1.0 or 2.0 POSTs (single or batched) to `/`, behind
+//! HTTP basic auth. Params may be positional or named, and error codes follow
+//! Bitcoin Core. Raw blocks and transactions are hex bincode, the same
+//! encoding the REST API uses.
+
+use crate::api::auth::WalletAuth;
+use crate::api::rest::submit_transaction;
+use crate::api::types::{BlockHashHex, TxIdHex};
+use crate::config::ApiConfig;
+use crate::consensus::target::Target;
+use crate::consensus::monetary::MonetaryUtils;
+use crate::core::{Block, Blockchain, Transaction};
+use crate::crypto::hash::{Hash256, Hashable};
+use crate::mining::pool::{ShareOutcome, ShareTracker};
+use crate::mining::template::{wait_for_template, BlockTemplate, BlockTemplateBuilder};
+use crate::network::p2p::P2PCommand;
+use crate::storage::Database;
+use crate::{QtcError, Result};
+use axum::{
+    body::Bytes,
+    extract::State,
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    routing::post,
+    Json, Router,
+};
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::net::SocketAddr;
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+use std::time::Instant;
+use tokio::sync::mpsc;
+
+// Error codes as returned by Bitcoin Core
+pub const RPC_MISC_ERROR: i64 = -1;
+pub const RPC_INVALID_ADDRESS_OR_KEY: i64 = -5;
+pub const RPC_INVALID_PARAMETER: i64 = -8;
+pub const RPC_DESERIALIZATION_ERROR: i64 = -22;
+pub const RPC_VERIFY_REJECTED: i64 = -26;
+pub const RPC_INVALID_REQUEST: i64 = -32600;
+pub const RPC_METHOD_NOT_FOUND: i64 = -32601;
+pub const RPC_PARSE_ERROR: i64 = -32700;
+
+const METHODS: &[&str] = &[
+    "decoderawtransaction", "getbestblockhash", "getblock", "getblockchaininfo", "getblockcount",
+    "getblockhash", "getblockheader", "getblocktemplate", "getdifficulty", "getmempoolinfo",
+    "getmininginfo", "getpoolinfo", "getrawmempool", "getrawtransaction", "help", "invalidateblock",
+    "reconsiderblock", "sendrawtransaction", "submitblock", "submitshare", "uptime", "validateaddress",
+];
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct RpcError {
+    pub code: i64,
+    pub message: String,
+}
+
+impl RpcError {
+    pub fn new(code: i64, message: impl Into<String>) -> Self {
+        Self { code, message: message.into() }
+    }
+}
+
+impl From<QtcError> for RpcError {
+    fn from(e: QtcError) -> Self {
+        Self::new(RPC_MISC_ERROR, e.to_string())
+    }
+}
+
+type RpcResult = std::result::Result<Value, RpcError>;
+
+#[derive(Clone)]
+struct RpcState {
+    blockchain: Arc<RwLock<Blockchain>>,
+    db: Arc<Database>,
+    auth: Arc<WalletAuth>,
+    network: String,
+    read_only: bool,
+    mining: bool,
+    p2p_commands: Option<mpsc::Sender<P2PCommand>>,
+    pool: Option<Arc<ShareTracker>>,
+    started: Instant,
+}
+
+pub struct JsonRpcServer {
+    blockchain: Arc<RwLock<Blockchain>>,
+    db: Arc<Database>,
+    config: ApiConfig,
+    network: String,
+    p2p_commands: Option<mpsc::Sender<P2PCommand>>,
+    pool: Option<Arc<ShareTracker>>,
+}
+
+impl JsonRpcServer {
+    pub fn with_db(blockchain: Arc<RwLock<Blockchain>>, db: Arc<Database>, config: ApiConfig) -> Self {
+        Self {
+            blockchain,
+            db,
+            config,
+            network: "main".to_string(),
+            p2p_commands: None,
+            pool: None,
+        }
+    }
+
+    /// Chain name reported by `getblockchaininfo`, "main" or "test"
+    pub fn set_network(&mut self, network: &str) {
+        self.network = network.to_string();
+    }
+
+    /// Relay transactions and blocks submitted over RPC to peers
+    pub fn set_p2p_commands(&mut self, p2p_commands: mpsc::Sender<P2PCommand>) {
+        self.p2p_commands = Some(p2p_commands);
+    }
+
+    /// Accept pool shares with `submitshare`
+    pub fn set_pool(&mut self, pool: Arc<ShareTracker>) {
+        self.pool = Some(pool);
+    }
+
+    pub async fn start(self) -> Result<()> {
+        let (Some(user), Some(password)) = (&self.config.rpc_user, &self.config.rpc_password) else {
+            return Err(QtcError::InvalidInput("JSON-RPC requires rpc_user and rpc_password to be set".to_string()));
+        };
+
+        let state = RpcState {
+            blockchain: self.blockchain.clone(),
+            db: self.db.clone(),
+            auth: Arc::new(WalletAuth::new(&format!("{}:{}", user, password))),
+            network: self.network.clone(),
+            read_only: self.config.read_only,
+            mining: self.config.enable_mining_endpoints,
+            p2p_commands: self.p2p_commands.clone(),
+            pool: self.pool.clone(),
+            started: Instant::now(),
+        };
+
+        let app = Router::new()
+            .route("/", post(handle_http))
+            .with_state(state);
+
+        let addr = SocketAddr::new(self.config.bind_address, self.config.rpc_port);
+        let listener = tokio::net::TcpListener::bind(&addr).await
+            .map_err(|e| QtcError::Network(format!("Failed to bind to {}: {}", addr, e)))?;
+
+        log::info!("✅ JSON-RPC listening on http://{}", addr);
+
+        axum::serve(listener, app).await
+            .map_err(|e| QtcError::Network(format!("Server error: {}", e)))?;
+
+        Ok(())
+    }
+}
+
+fn authorized(state: &RpcState, headers: &HeaderMap) -> bool {
+    headers.get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Basic "))
+        .and_then(|encoded| base64::engine::general_purpose::STANDARD.decode(encoded.trim()).ok())
+        .and_then(|decoded| String::from_utf8(decoded).ok())
+        .is_some_and(|credentials| state.auth.verify(&credentials))
+}
+
+async fn handle_http(State(state): State<RpcState>, headers: HeaderMap, body: Bytes) -> Response {
+    if !authorized(&state, &headers) {
+        return (StatusCode::UNAUTHORIZED, [(header::WWW_AUTHENTICATE, "Basic realm=\"jsonrpc\"")]).into_response();
+    }
+
+    let request: Value = match serde_json::from_slice(&body) {
+        Ok(request) => request,
+        Err(_) => {
+            let error = RpcError::new(RPC_PARSE_ERROR, "Parse error");
+            return (StatusCode::INTERNAL_SERVER_ERROR, Json(reply(false, Value::Null, Err(error)))).into_response();
+        }
+    };
+
+    match request {
+        Value::Array(batch) => {
+            let mut replies = Vec::with_capacity(batch.len());
+            for request in batch {
+                replies.push(handle_request(&state, request).await.0);
+            }
+            Json(Value::Array(replies)).into_response()
+        }
+        request => {
+            let (reply, status) = handle_request(&state, request).await;
+            (status, Json(reply)).into_response()
+        }
+    }
+}
+
+/// Answer one call; JSON-RPC 1.0 clients also get Bitcoin Core's HTTP status for errors
+async fn handle_request(state: &RpcState, request: Value) -> (Value, StatusCode) {
+    let version2 = request.get("jsonrpc").and_then(Value::as_str) == Some("2.0");
+    let id = request.get("id").cloned().unwrap_or(Value::Null);
+
+    let Some(method) = request.get("method").and_then(Value::as_str) else {
+        let error = RpcError::new(RPC_INVALID_REQUEST, "Method must be a string");
+        return (reply(version2, id, Err(error)), StatusCode::BAD_REQUEST);
+    };
+    let params = request.get("params").cloned().unwrap_or(Value::Null);
+
+    let result = call(state, method, &Params(&params)).await;
+    let status = match &result {
+        Err(_) if version2 => StatusCode::OK,
+        Err(e) if e.code == RPC_METHOD_NOT_FOUND => StatusCode::NOT_FOUND,
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        Ok(_) => StatusCode::OK,
+    };
+    (reply(version2, id, result), status)
+}
+
+fn reply(version2: bool, id: Value, result: RpcResult) -> Value {
+    match (version2, result) {
+        (true, Ok(result)) => json!({ "jsonrpc": "2.0", "result": result, "id": id }),
+        (true, Err(error)) => json!({ "jsonrpc": "2.0", "error": error, "id": id }),
+        (false, Ok(result)) => json!({ "result": result, "error": null, "id": id }),
+        (false, Err(error)) => json!({ "result": null, "error": error, "id": id }),
+    }
+}
+
+/// Positional (`[a, b]`) or named (`{"name": a}`) parameters
+struct Params<'a>(&'a Value);
+
+impl Params<'_> {
+    fn get(&self, index: usize, name: &str) -> Option<&Value> {
+        match self.0 {
+            Value::Array(values) => values.get(index),
+            Value::Object(values) => values.get(name),
+            _ => None,
+        }
+        .filter(|value| !value.is_null())
+    }
+
+    fn str(&self, index: usize, name: &str) -> std::result::Result<&str, RpcError> {
+        match self.get(index, name) {
+            Some(Value::String(value)) => Ok(value),
+            Some(_) => Err(RpcError::new(RPC_INVALID_PARAMETER, format!("{} must be a string", name))),
+            None => Err(RpcError::new(RPC_INVALID_PARAMETER, format!("Missing parameter {}", name))),
+        }
+    }
+
+    fn u64(&self, index: usize, name: &str) -> std::result::Result<u64, RpcError> {
+        self.get(index, name)
+            .and_then(Value::as_u64)
+            .ok_or_else(|| RpcError::new(RPC_INVALID_PARAMETER, format!("{} must be a non-negative integer", name)))
+    }
+
+    /// A block hash or txid parameter, as its typed API identifier
+    fn hash<T: FromStr>(&self, index: usize, name: &str) -> std::result::Result<T, RpcError> {
+        self.str(index, name)?.parse()
+            .map_err(|_| RpcError::new(RPC_INVALID_PARAMETER, format!("{} must be a 64 character hex string", name)))
+    }
+
+    /// Verbosity flags, given as a number or (older clients) a boolean
+    fn level(&self, index: usize, name: &str, default: u64) -> u64 {
+        match self.get(index, name) {
+            Some(Value::Bool(verbose)) => *verbose as u64,
+            Some(value) => value.as_u64().unwrap_or(default),
+            None => default,
+        }
+    }
+}
+
+async fn call(state: &RpcState, method: &str, params: &Params<'_>) -> RpcResult {
+    match method {
+        "getblockchaininfo" => get_blockchain_info(state),
+        "getblockcount" => Ok(json!(read_chain(state)?.height)),
+        "getbestblockhash" => Ok(json!(read_chain(state)?.tip.to_hex())),
+        "getdifficulty" => Ok(json!(read_chain(state)?.get_current_difficulty()?)),
+        "getblockhash" => get_block_hash(state, params.u64(0, "height")?),
+        "getblock" => get_block(state, params.hash(0, "blockhash")?, params.level(1, "verbosity", 1)),
+        "getblockheader" => get_block_header(state, params.hash(0, "blockhash")?, params.level(1, "verbose", 1) > 0),
+        "getrawtransaction" => get_raw_transaction(state, params.hash(0, "txid")?, params.level(1, "verbose", 0) > 0),
+        "decoderawtransaction" => Ok(transaction_json(&decode_hex::<Transaction>(params.str(0, "hexstring")?)?)),
+        "sendrawtransaction" => send_raw_transaction(state, params.str(0, "hexstring")?).await,
+        "getmempoolinfo" => get_mempool_info(state),
+        "getrawmempool" => get_raw_mempool(state, params.level(0, "verbose", 0) > 0),
+        "getmininginfo" => get_mining_info(state),
+        "getblocktemplate" => get_block_template(state, params).await,
+        "submitblock" => submit_block(state, params.str(0, "hexdata")?).await,
+        "submitshare" => submit_share(state, params).await,
+        "getpoolinfo" => get_pool_info(state),
+        "invalidateblock" => mark_block(state, params.hash(0, "blockhash")?, true),
+        "reconsiderblock" => mark_block(state, params.hash(0, "blockhash")?, false),
+        "validateaddress" => {
+            let address = params.str(0, "address")?;
+            Ok(json!({ "isvalid": crate::crypto::keys::is_valid_address(address), "address": address }))
+        }
+        "uptime" => Ok(json!(state.started.elapsed().as_secs())),
+        "help" => Ok(json!(METHODS.join("\n"))),
+        _ => Err(RpcError::new(RPC_METHOD_NOT_FOUND, "Method not found")),
+    }
+}
+
+fn read_chain(state: &RpcState) -> std::result::Result<std::sync::RwLockReadGuard<'_, Blockchain>, RpcError> {
+    state.blockchain.read()
+        .map_err(|_| RpcError::new(RPC_MISC_ERROR, "Failed to access blockchain"))
+}
+
+fn decode_hex<T: serde::de::DeserializeOwned>(data: &str) -> std::result::Result<T, RpcError> {
+    hex::decode(data.trim()).ok()
+        .and_then(|bytes| bincode::deserialize(&bytes).ok())
+        .ok_or_else(|| RpcError::new(RPC_DESERIALIZATION_ERROR, "Decode failed"))
+}
+
+fn encode_hex<T: Serialize>(value: &T) -> RpcResult {
+    bincode::serialize(value)
+        .map(|bytes| json!(hex::encode(bytes)))
+        .map_err(|e| RpcError::new(RPC_MISC_ERROR, format!("Failed to serialize: {}", e)))
+}
+
+/// Chain work as the 64 hex digits Bitcoin Core reports
+fn chainwork_hex(work: u128) -> String {
+    format!("{:064x}", work)
+}
+
+fn get_blockchain_info(state: &RpcState) -> RpcResult {
+    let blockchain = read_chain(state)?;
+    let tip_time = blockchain.get_block(&blockchain.tip)?
+        .map(|block| block.header.timestamp)
+        .unwrap_or_default();
+    let pruning = blockchain.prune_status()?;
+
+    Ok(json!({
+        "chain": state.network,
+        "blocks": blockchain.height,
+        "headers": blockchain.height,
+        "bestblockhash": blockchain.tip.to_hex(),
+        "difficulty": blockchain.get_current_difficulty()?,
+        "time": tip_time,
+        "verificationprogress": if blockchain.is_low_work() { 0.0 } else { 1.0 },
+        "initialblockdownload": blockchain.is_initial_block_download(),
+        "chainwork": chainwork_hex(blockchain.total_work),
+        "pruned": pruning.enabled,
+        "pruneheight": pruning.pruned_height.map_or(0, |height| height + 1),
+        "warnings": "",
+    }))
+}
+
+fn get_block_hash(state: &RpcState, height: u64) -> RpcResult {
+    let blockchain = read_chain(state)?;
+    if height > blockchain.height {
+        return Err(RpcError::new(RPC_INVALID_PARAMETER, "Block height out of range"));
+    }
+    match blockchain.get_block_by_height(height)? {
+        Some(block) => Ok(json!(block.hash().to_hex())),
+        None => Err(RpcError::new(RPC_INVALID_PARAMETER, "Block height out of range")),
+    }
+}
+
+/// A block and its position relative to the active chain
+struct LocatedBlock {
+    block: Block,
+    confirmations: i64, // -1 for blocks off the active chain, as in Bitcoin Core
+    next_hash: Option<Hash256>,
+}
+
+fn locate_block(state: &RpcState, hash: BlockHashHex) -> std::result::Result<LocatedBlock, RpcError> {
+    let hash = hash.hash();
+    let blockchain = read_chain(state)?;
+    let block = blockchain.get_block(&hash)?
+        .ok_or_else(|| RpcError::new(RPC_INVALID_ADDRESS_OR_KEY, "Block not found"))?;
+
+    let height = block.header.height;
+    let on_chain = height <= blockchain.height
+        && blockchain.get_block_by_height(height)?.is_some_and(|active| active.hash() == hash);
+    let (confirmations, next_hash) = if on_chain {
+        let next = blockchain.get_block_by_height(height + 1)?.map(|next| next.hash());
+        ((blockchain.height - height + 1) as i64, next)
+    } else {
+        (-1, None)
+    };
+
+    Ok(LocatedBlock { block, confirmations, next_hash })
+}
+
+fn header_json(located: &LocatedBlock) -> Map<String, Value> {
+    let header = &located.block.header;
+    let mut object = Map::new();
+    object.insert("hash".to_string(), json!(located.block.hash().to_hex()));
+    object.insert("confirmations".to_string(), json!(located.confirmations));
+    object.insert("height".to_string(), json!(header.height));
+    object.insert("merkleroot".to_string(), json!(header.merkle_root.to_hex()));
+    object.insert("time".to_string(), json!(header.timestamp));
+    object.insert("nonce".to_string(), json!(header.nonce));
+    object.insert("bits".to_string(), json!(format!("{:08x}", header.bits)));
+    object.insert("difficulty".to_string(), json!(Target::from_bits(header.bits).difficulty()));
+    object.insert("chainwork".to_string(), json!(chainwork_hex(Blockchain::block_work(header.bits))));
+    object.insert("nTx".to_string(), json!(located.block.transaction_count()));
+    if header.height > 0 {
+        object.insert("previousblockhash".to_string(), json!(header.previous_hash.to_hex()));
+    }
+    if let Some(next_hash) = &located.next_hash {
+        object.insert("nextblockhash".to_string(), json!(next_hash.to_hex()));
+    }
+    object
+}
+
+fn get_block(state: &RpcState, hash: BlockHashHex, verbosity: u64) -> RpcResult {
+    let located = locate_block(state, hash)?;
+    if verbosity == 0 {
+        return encode_hex(&located.block);
+    }
+
+    let mut object = header_json(&located);
+    object.insert("size".to_string(), json!(located.block.size()));
+    let transactions: Vec<Value> = located.block.transactions.iter()
+        .map(|tx| if verbosity >= 2 { transaction_json(tx) } else { json!(tx.hash().to_hex()) })
+        .collect();
+    object.insert("tx".to_string(), Value::Array(transactions));
+    Ok(Value::Object(object))
+}
+
+fn get_block_header(state: &RpcState, hash: BlockHashHex, verbose: bool) -> RpcResult {
+    let located = locate_block(state, hash)?;
+    if verbose {
+        Ok(Value::Object(header_json(&located)))
+    } else {
+        encode_hex(&located.block.header)
+    }
+}
+
+fn transaction_json(tx: &Transaction) -> Value {
+    let vin: Vec<Value> = tx.inputs.iter()
+        .map(|input| if tx.is_coinbase() {
+            json!({ "coinbase": hex::encode(&input.signature_script), "sequence": input.sequence })
+        } else {
+            json!({
+                "txid": input.previous_output.txid.to_hex(),
+                "vout": input.previous_output.vout,
+                "scriptSig": { "hex": hex::encode(&input.signature_script) },
+                "sequence": input.sequence,
+            })
+        })
+        .collect();
+    let vout: Vec<Value> = tx.outputs.iter()
+        .enumerate()
+        .map(|(n, output)| json!({
+            "value": MonetaryUtils::satoshis_to_qtc(output.value),
+            "n": n,
+            "scriptPubKey": { "hex": hex::encode(&output.script_pubkey) },
+        }))
+        .collect();
+
+    json!({
+        "txid": tx.hash().to_hex(),
+        "hash": tx.hash().to_hex(),
+        "version": tx.version,
+        "size": tx.size(),
+        "locktime": tx.lock_time,
+        "vin": vin,
+        "vout": vout,
+        "hex": bincode::serialize(tx).map(hex::encode).unwrap_or_default(),
+    })
+}
+
+fn get_raw_transaction(state: &RpcState, txid: TxIdHex, verbose: bool) -> RpcResult {
+    let txid = txid.hash();
+    let pooled = read_chain(state)?.mempool.read().unwrap().get(&txid).map(|entry| entry.tx.clone());
+    let tx = match pooled {
+        Some(tx) => tx,
+        None => state.db.get_transaction(&txid)?.ok_or_else(|| RpcError::new(
+            RPC_INVALID_ADDRESS_OR_KEY,
+            "No such mempool or blockchain transaction. Use txindex to enable blockchain transaction queries",
+        ))?,
+    };
+
+    if verbose {
+        Ok(transaction_json(&tx))
+    } else {
+        encode_hex(&tx)
+    }
+}
+
+async fn send_raw_transaction(state: &RpcState, hex_tx: &str) -> RpcResult {
+    if state.read_only {
+        return Err(RpcError::new(RPC_MISC_ERROR, "sendrawtransaction is disabled on read-only nodes"));
+    }
+    let tx: Transaction = decode_hex(hex_tx)?;
+
+    let txid = submit_transaction(&state.blockchain, state.p2p_commands.as_ref(), tx).await
+        .map_err(|e| RpcError::new(RPC_VERIFY_REJECTED, e.to_string()))?;
+    Ok(json!(txid.to_hex()))
+}
+
+fn get_mempool_info(state: &RpcState) -> RpcResult {
+    let blockchain = read_chain(state)?;
+    let mempool = blockchain.mempool.read().unwrap();
+    Ok(json!({
+        "loaded": true,
+        "size": mempool.len(),
+        "bytes": mempool.total_size(),
+        "usage": mempool.total_size(),
+        "maxmempool": mempool.max_size(),
+    }))
+}
+
+fn get_raw_mempool(state: &RpcState, verbose: bool) -> RpcResult {
+    let blockchain = read_chain(state)?;
+    let mempool = blockchain.mempool.read().unwrap();
+
+    if !verbose {
+        return Ok(json!(mempool.entries().map(|entry| entry.txid.to_hex()).collect::<Vec<_>>()));
+    }
+    let entries: Map<String, Value> = mempool.entries()
+        .map(|entry| (entry.txid.to_hex(), json!({
+            "vsize": entry.size,
+            "fee": MonetaryUtils::satoshis_to_qtc(entry.fee),
+            "time": entry.time,
+            "height": entry.height,
+            "ancestorcount": entry.ancestor_count,
+            "ancestorsize": entry.ancestor_size,
+            "ancestorfees": MonetaryUtils::satoshis_to_qtc(entry.ancestor_fee),
+            "descendantcount": entry.descendant_count,
+            "descendantsize": entry.descendant_size,
+        })))
+        .collect();
+    Ok(Value::Object(entries))
+}
+
+fn get_mining_info(state: &RpcState) -> RpcResult {
+    let blockchain = read_chain(state)?;
+    let pooled = blockchain.mempool.read().unwrap().len();
+    Ok(json!({
+        "blocks": blockchain.height,
+        "difficulty": blockchain.get_current_difficulty()?,
+        "pooledtx": pooled,
+        "chain": state.network,
+        "warnings": if blockchain.is_low_work() { "Chain work is below minimum_chain_work" } else { "" },
+    }))
+}
+
+/// `getblocktemplate` in Bitcoin Core's shape; `depends` are 1-based there
+fn template_json(template: &BlockTemplate) -> Value {
+    let transactions: Vec<Value> = template.transactions.iter()
+        .map(|tx| json!({
+            "data": tx.data,
+            "txid": tx.txid,
+            "hash": tx.txid,
+            "depends": tx.depends.iter().map(|index| index + 1).collect::<Vec<_>>(),
+            "fee": tx.fee,
+            "weight": tx.size,
+        }))
+        .collect();
+
+    json!({
+        "version": 1,
+        "previousblockhash": template.previous_block_hash,
+        "transactions": transactions,
+        "coinbasevalue": template.coinbase_value,
+        "longpollid": template.longpollid,
+        "target": template.target,
+        "bits": format!("{:08x}", template.bits),
+        "difficulty": template.difficulty,
+        "curtime": template.curtime,
+        "height": template.height,
+        "sizelimit": template.size_limit,
+        "mutable": ["time", "transactions", "prevblock"],
+    })
+}
+
+async fn get_block_template(state: &RpcState, params: &Params<'_>) -> RpcResult {
+    if !state.mining {
+        return Err(RpcError::new(RPC_METHOD_NOT_FOUND, "Mining methods are disabled on this node"));
+    }
+    let longpollid = params.get(0, "template_request")
+        .and_then(|request| request.get("longpollid"))
+        .and_then(Value::as_str);
+
+    let template = wait_for_template(&state.blockchain, &BlockTemplateBuilder::new(), longpollid).await?;
+    Ok(template_json(&template))
+}
+
+/// Like Bitcoin Core: null when accepted, otherwise the reason as a string
+async fn submit_block(state: &RpcState, hex_block: &str) -> RpcResult {
+    if !state.mining || state.read_only {
+        return Err(RpcError::new(RPC_METHOD_NOT_FOUND, "Mining methods are disabled on this node"));
+    }
+    let block: Block = decode_hex(hex_block)?;
+    let hash = block.hash();
+
+    let outcome = {
+        let mut blockchain = state.blockchain.write()
+            .map_err(|_| RpcError::new(RPC_MISC_ERROR, "Failed to access blockchain"))?;
+        if blockchain.get_block(&hash)?.is_some() {
+            return Ok(json!("duplicate"));
+        }
+        blockchain.add_block(block.clone())
+    };
+
+    match outcome {
+        Ok(()) => {
+            log::info!("📦 Block {} submitted over JSON-RPC", hash);
+            if let Some(p2p_commands) = &state.p2p_commands {
+                let _ = p2p_commands.send(P2PCommand::BroadcastBlock(block)).await;
+            }
+            Ok(Value::Null)
+        }
+        Err(e) => Ok(json!(format!("rejected: {}", e))),
+    }
+}
+
+/// `invalidateblock` / `reconsiderblock`: null once the chain has reorganized
+fn mark_block(state: &RpcState, hash: BlockHashHex, invalid: bool) -> RpcResult {
+    if state.read_only {
+        return Err(RpcError::new(RPC_MISC_ERROR, "Blocks cannot be invalidated or reconsidered on read-only nodes"));
+    }
+    let hash = hash.hash();
+    let mut blockchain = state.blockchain.write()
+        .map_err(|_| RpcError::new(RPC_MISC_ERROR, "Failed to access blockchain"))?;
+    if blockchain.get_block(&hash)?.is_none() {
+        return Err(RpcError::new(RPC_INVALID_ADDRESS_OR_KEY, "Block not found"));
+    }
+    if invalid {
+        blockchain.invalidate_block(&hash)?;
+    } else {
+        blockchain.reconsider_block(&hash)?;
+    }
+    Ok(Value::Null)
+}
+
+fn pool(state: &RpcState) -> std::result::Result<&ShareTracker, RpcError> {
+    match &state.pool {
+        Some(pool) if state.mining && !state.read_only => Ok(pool),
+        _ => Err(RpcError::new(RPC_METHOD_NOT_FOUND, "This node does not run a pool")),
+    }
+}
+
+/// `submitshare worker payoutaddress hexdata [difficulty]`; a share that is also a block is submitted as one
+async fn submit_share(state: &RpcState, params: &Params<'_>) -> RpcResult {
+    let pool = pool(state)?;
+    let worker = params.str(0, "worker")?;
+    let payout_address = params.str(1, "payoutaddress")?;
+    let block: Block = decode_hex(params.str(2, "hexdata")?)?;
+    let difficulty = match params.get(3, "difficulty") {
+        Some(_) => Some(u32::try_from(params.u64(3, "difficulty")?)
+            .map_err(|_| RpcError::new(RPC_INVALID_PARAMETER, "difficulty is out of range"))?),
+        None => None,
+    };
+
+    let outcome = {
+        let blockchain = read_chain(state)?;
+        pool.submit(&blockchain, worker, payout_address, &block, difficulty)
+            .map_err(|e| RpcError::new(RPC_INVALID_PARAMETER, e.to_string()))?
+    };
+    let mut reply = serde_json::to_value(&outcome).map_err(|e| RpcError::new(RPC_MISC_ERROR, e.to_string()))?;
+
+    if let ShareOutcome::Accepted { block: true, .. } = outcome {
+        let submitted = submit_block(state, params.str(2, "hexdata")?).await?;
+        if submitted.is_null() {
+            pool.block_found(&block)?;
+        }
+        reply["block_result"] = if submitted.is_null() { json!("accepted") } else { submitted };
+    }
+    Ok(reply)
+}
+
+fn get_pool_info(state: &RpcState) -> RpcResult {
+    let pool = pool(state)?;
+    let config = pool.config();
+    Ok(json!({
+        "wallet": config.wallet,
+        "share_difficulty": config.share_difficulty,
+        "scheme": config.scheme,
+        "pplns_window": config.pplns_window,
+        "fee_basis_points": config.fee_basis_points,
+        "workers": state.db.get_pool_workers()?,
+        "rounds": state.db.get_pool_rounds()?,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn test_state(temp_dir: &TempDir) -> Result<RpcState> {
+        let db = Arc::new(Database::new(temp_dir.path().join("test.db"))?);
+        let blockchain = Arc::new(RwLock::new(Blockchain::new(db.clone())?));
+        Ok(RpcState {
+            blockchain,
+            db,
+            auth: Arc::new(WalletAuth::new("user:pass")),
+            network: "test".to_string(),
+            read_only: false,
+            mining: true,
+            p2p_commands: None,
+            pool: None,
+            started: Instant::now(),
+        })
+    }
+
+    #[tokio::test]
+    async fn test_bitcoind_style_calls() -> Result<()> {
+        let temp_dir = TempDir::new().unwrap();
+        let state = test_state(&temp_dir)?;
+
+        let mut headers = HeaderMap::new();
+        assert!(!authorized(&state, &headers));
+        let credentials = base64::engine::general_purpose::STANDARD.encode("user:pass");
+        headers.insert(header::AUTHORIZATION, format!("Basic {}", credentials).parse().unwrap());
+        assert!(authorized(&state, &headers));
+
+        // 1.0 style: both fields present, positional params
+        let (reply, status) = handle_request(&state, json!({ "method": "getblockhash", "params": [0], "id": 1 })).await;
+        assert_eq!(status, StatusCode::OK);
+        let genesis = reply["result"].as_str().unwrap().to_string();
+        assert!(reply["error"].is_null());
+
+        // 2.0 style with named params
+        let (reply, _) = handle_request(&state, json!({
+            "jsonrpc": "2.0", "method": "getblock", "params": { "blockhash": genesis, "verbosity": 1 }, "id": "a"
+        })).await;
+        assert_eq!(reply["result"]["height"], 0);
+        assert_eq!(reply["result"]["confirmations"], 1);
+        assert_eq!(reply["id"], "a");
+        assert!(reply.get("error").is_none());
+
+        let (reply, _) = handle_request(&state, json!({ "method": "getblock", "params": [genesis, 0], "id": 2 })).await;
+        let raw: Block = decode_hex(reply["result"].as_str().unwrap()).unwrap();
+        assert_eq!(raw.hash().to_hex(), genesis);
+
+        let (reply, status) = handle_request(&state, json!({ "method": "getblockhash", "params": [5], "id": 3 })).await;
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(reply["error"]["code"], RPC_INVALID_PARAMETER);
+
+        let (reply, status) = handle_request(&state, json!({ "method": "nosuchmethod", "id": 4 })).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(reply["error"]["code"], RPC_METHOD_NOT_FOUND);
+
+        let (reply, _) = handle_request(&state, json!({ "method": "sendrawtransaction", "params": ["zz"], "id": 5 })).await;
+        assert_eq!(reply["error"]["code"], RPC_DESERIALIZATION_ERROR);
+        let mut unfunded = Transaction::new();
+        unfunded.add_input(crate::core::transaction::OutPoint::new(Hash256::hash(b"nowhere"), 0), Vec::new());
+        unfunded.add_output(1_000, "qtc1payee");
+        let raw = hex::encode(bincode::serialize(&unfunded).unwrap());
+        let (reply, _) = handle_request(&state, json!({ "method": "sendrawtransaction", "params": [raw], "id": 5 })).await;
+        assert_eq!(reply["error"]["code"], RPC_VERIFY_REJECTED);
+        assert_eq!(read_chain(&state).unwrap().mempool.read().unwrap().len(), 0);
+
+        let (reply, _) = handle_request(&state, json!({ "method": "getblocktemplate", "id": 6 })).await;
+        assert_eq!(reply["result"]["previousblockhash"], genesis);
+        assert_eq!(reply["result"]["height"], 1);
+
+        let (reply, _) = handle_request(&state, json!({ "method": "getblockchaininfo", "id": 7 })).await;
+        assert_eq!(reply["result"]["chain"], "test");
+        assert_eq!(reply["result"]["chainwork"].as_str().unwrap().len(), 64);
+        Ok(())
+    }
+}
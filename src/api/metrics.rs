@@ -0,0 +1,190 @@
+//! Node metrics in the Prometheus text exposition format, served at `/metrics`
+//!
+//! The blockchain, miner, mempool and P2P node record into one process-wide
+//! registry as they work; values that are cheap to read on demand (chain
+//! height, database size) are refreshed when scraped instead.
+
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::OnceLock;
+
+/// `Content-Type` of the exposition format
+pub const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// Upper bounds of the block validation time buckets, in seconds
+const VALIDATION_BUCKETS: &[f64] = &[0.001, 0.005, 0.01, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+static METRICS: OnceLock<Metrics> = OnceLock::new();
+
+/// The process-wide registry
+pub fn metrics() -> &'static Metrics {
+    METRICS.get_or_init(Metrics::new)
+}
+
+#[derive(Debug, Default)]
+pub struct Counter(AtomicU64);
+
+impl Counter {
+    pub fn inc(&self) {
+        self.0.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// A value that goes up and down, stored as `f64` bits
+#[derive(Debug, Default)]
+pub struct Gauge(AtomicU64);
+
+impl Gauge {
+    pub fn set(&self, value: f64) {
+        self.0.store(value.to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> f64 {
+        f64::from_bits(self.0.load(Ordering::Relaxed))
+    }
+}
+
+#[derive(Debug)]
+pub struct Histogram {
+    bounds: &'static [f64],
+    buckets: Vec<AtomicU64>, // not cumulative; summed when rendered
+    sum: AtomicU64,          // f64 bits
+    count: AtomicU64,
+}
+
+impl Histogram {
+    fn new(bounds: &'static [f64]) -> Self {
+        Self {
+            bounds,
+            buckets: bounds.iter().map(|_| AtomicU64::new(0)).collect(),
+            sum: AtomicU64::new(0f64.to_bits()),
+            count: AtomicU64::new(0),
+        }
+    }
+
+    pub fn observe(&self, value: f64) {
+        if let Some(index) = self.bounds.iter().position(|bound| value <= *bound) {
+            self.buckets[index].fetch_add(1, Ordering::Relaxed);
+        }
+        let _ = self.sum.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |bits| {
+            Some((f64::from_bits(bits) + value).to_bits())
+        });
+        self.count.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count.load(Ordering::Relaxed)
+    }
+}
+
+#[derive(Debug)]
+pub struct Metrics {
+    pub chain_height: Gauge,
+    pub peers: Gauge,
+    pub mempool_transactions: Gauge,
+    pub mempool_bytes: Gauge,
+    pub hashrate: Gauge,
+    pub database_bytes: Gauge,
+    pub blocks_connected: Counter,
+    pub blocks_mined: Counter,
+    pub reorgs: Counter,
+    pub block_validation_seconds: Histogram,
+}
+
+impl Metrics {
+    fn new() -> Self {
+        Self {
+            chain_height: Gauge::default(),
+            peers: Gauge::default(),
+            mempool_transactions: Gauge::default(),
+            mempool_bytes: Gauge::default(),
+            hashrate: Gauge::default(),
+            database_bytes: Gauge::default(),
+            blocks_connected: Counter::default(),
+            blocks_mined: Counter::default(),
+            reorgs: Counter::default(),
+            block_validation_seconds: Histogram::new(VALIDATION_BUCKETS),
+        }
+    }
+
+    /// Every metric in the text exposition format
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        gauge(&mut out, "qtc_chain_height", "Height of the active chain tip", &self.chain_height);
+        gauge(&mut out, "qtc_peers", "Connected P2P peers", &self.peers);
+        gauge(&mut out, "qtc_mempool_transactions", "Transactions in the mempool", &self.mempool_transactions);
+        gauge(&mut out, "qtc_mempool_bytes", "Weight of the transactions in the mempool", &self.mempool_bytes);
+        gauge(&mut out, "qtc_hashrate", "Local mining hashrate in hashes per second", &self.hashrate);
+        gauge(&mut out, "qtc_database_bytes", "Size of the database and block files on disk", &self.database_bytes);
+        counter(&mut out, "qtc_blocks_connected_total", "Blocks connected to the active chain", &self.blocks_connected);
+        counter(&mut out, "qtc_blocks_mined_total", "Blocks found by the local miner", &self.blocks_mined);
+        counter(&mut out, "qtc_reorgs_total", "Chain reorganizations", &self.reorgs);
+        histogram(&mut out, "qtc_block_validation_seconds", "Time to validate and connect a block", &self.block_validation_seconds);
+        out
+    }
+}
+
+fn header(out: &mut String, name: &str, help: &str, kind: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+fn gauge(out: &mut String, name: &str, help: &str, gauge: &Gauge) {
+    header(out, name, help, "gauge");
+    let _ = writeln!(out, "{} {}", name, gauge.get());
+}
+
+fn counter(out: &mut String, name: &str, help: &str, counter: &Counter) {
+    header(out, name, help, "counter");
+    let _ = writeln!(out, "{} {}", name, counter.get());
+}
+
+fn histogram(out: &mut String, name: &str, help: &str, histogram: &Histogram) {
+    header(out, name, help, "histogram");
+    let mut cumulative = 0;
+    for (bound, bucket) in histogram.bounds.iter().zip(&histogram.buckets) {
+        cumulative += bucket.load(Ordering::Relaxed);
+        let _ = writeln!(out, "{}_bucket{{le=\"{}\"}} {}", name, bound, cumulative);
+    }
+    let count = histogram.count();
+    let _ = writeln!(out, "{}_bucket{{le=\"+Inf\"}} {}", name, count);
+    let _ = writeln!(out, "{}_sum {}", name, f64::from_bits(histogram.sum.load(Ordering::Relaxed)));
+    let _ = writeln!(out, "{}_count {}", name, count);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_histogram_buckets_are_cumulative() {
+        let histogram = Histogram::new(&[0.1, 1.0]);
+        histogram.observe(0.0625);
+        histogram.observe(0.5);
+        histogram.observe(3.0);
+
+        let mut out = String::new();
+        super::histogram(&mut out, "t", "test", &histogram);
+        assert!(out.contains("t_bucket{le=\"0.1\"} 1\n"));
+        assert!(out.contains("t_bucket{le=\"1\"} 2\n"));
+        assert!(out.contains("t_bucket{le=\"+Inf\"} 3\n"));
+        assert!(out.contains("t_sum 3.5625\n"));
+        assert!(out.contains("t_count 3\n"));
+    }
+
+    #[test]
+    fn test_render_exposes_every_metric() {
+        let metrics = Metrics::new();
+        metrics.chain_height.set(42.0);
+        metrics.reorgs.inc();
+
+        let out = metrics.render();
+        assert!(out.contains("# TYPE qtc_chain_height gauge\nqtc_chain_height 42\n"));
+        assert!(out.contains("# TYPE qtc_reorgs_total counter\nqtc_reorgs_total 1\n"));
+        assert!(out.contains("# TYPE qtc_block_validation_seconds histogram\n"));
+    }
+}
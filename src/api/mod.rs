@@ -1,7 +1,25 @@
-//! API module for REST and WebSocket endpoints
+//! API module for REST, JSON-RPC and WebSocket endpoints, API workers, outgoing webhooks
+//! and pubsub notifications
 
+pub mod auth;
+pub mod cache;
+#[cfg(unix)]
+pub mod cluster;
+pub mod decode;
+pub mod error;
+pub mod faucet;
+pub mod jsonrpc;
+pub mod metrics;
+pub mod pubsub;
+pub mod ratelimit;
 pub mod rest;
+pub mod types;
+pub mod webhooks;
 pub mod websocket;
 
+pub use faucet::Faucet;
+pub use jsonrpc::JsonRpcServer;
+pub use pubsub::PubSubPublisher;
 pub use rest::RestApi;
+pub use webhooks::WebhookNotifier;
 pub use websocket::WebSocketServer;
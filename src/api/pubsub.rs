@@ -0,0 +1,253 @@
+//! Chain notifications pushed to TCP subscribers, in the style of bitcoind's ZeroMQ
+//!
+//! Each topic (`hashblock`, `hashtx`, `rawblock`, `rawtx`) is published on the
+//! endpoint configured for it under `api.pubsub`; topics may share an
+//! endpoint. Subscribers just connect and read: every notification is three
+//! frames, the topic, the body and a little-endian u32 sequence number counted
+//! per topic, each frame behind a big-endian u32 length. Hashes are the 32 raw
+//! bytes, blocks and transactions their bincode encoding.
+//!
+//! Blocks are published as they connect, along with each of their
+//! transactions; transactions also when the mempool accepts them. A subscriber
+//! that falls more than `HIGH_WATER_MARK` notifications behind misses the
+//! rest, as the gap in sequence numbers shows.
+
+use crate::config::PubSubConfig;
+use crate::core::{Block, ChainEvent, Transaction};
+use crate::crypto::hash::Hashable;
+use crate::node::ShutdownSignal;
+use crate::{QtcError, Result};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use tokio::io::AsyncWriteExt;
+use tokio::net::TcpListener;
+use tokio::sync::{broadcast, mpsc};
+use tokio::task::JoinHandle;
+
+/// Notifications queued for one subscriber before newer ones are dropped
+pub const HIGH_WATER_MARK: usize = 1_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Topic {
+    HashBlock,
+    HashTx,
+    RawBlock,
+    RawTx,
+}
+
+impl Topic {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::HashBlock => "hashblock",
+            Self::HashTx => "hashtx",
+            Self::RawBlock => "rawblock",
+            Self::RawTx => "rawtx",
+        }
+    }
+}
+
+/// One notification, framed for the wire
+pub fn encode_frames(topic: Topic, body: &[u8], sequence: u32) -> Vec<u8> {
+    let sequence = sequence.to_le_bytes();
+    let frames: [&[u8]; 3] = [topic.name().as_bytes(), body, &sequence];
+    let mut data = Vec::with_capacity(frames.iter().map(|frame| 4 + frame.len()).sum());
+    for frame in frames {
+        data.extend_from_slice(&(frame.len() as u32).to_be_bytes());
+        data.extend_from_slice(frame);
+    }
+    data
+}
+
+type Subscribers = Arc<Mutex<Vec<mpsc::Sender<Arc<Vec<u8>>>>>>;
+
+/// A bound listener and the topics published on it
+struct Endpoint {
+    address: SocketAddr,
+    topics: Vec<Topic>,
+    subscribers: Subscribers,
+    accept: JoinHandle<()>,
+}
+
+pub struct PubSubPublisher {
+    endpoints: Vec<Endpoint>,
+    sequences: HashMap<Topic, u32>,
+}
+
+impl PubSubPublisher {
+    /// Listen on every configured endpoint
+    pub async fn bind(config: &PubSubConfig) -> Result<Self> {
+        let mut endpoints: Vec<Endpoint> = Vec::new();
+        for (topic, address) in config.endpoints() {
+            if let Some(endpoint) = endpoints.iter_mut().find(|endpoint| endpoint.address == address) {
+                endpoint.topics.push(topic);
+                continue;
+            }
+            let listener = TcpListener::bind(address).await
+                .map_err(|e| QtcError::Network(format!("Failed to bind pubsub endpoint {}: {}", address, e)))?;
+            let address = listener.local_addr()?;
+            let subscribers = Subscribers::default();
+            let accept = tokio::spawn(accept_subscribers(listener, subscribers.clone()));
+            endpoints.push(Endpoint { address, topics: vec![topic], subscribers, accept });
+        }
+
+        for endpoint in &endpoints {
+            let topics: Vec<&str> = endpoint.topics.iter().map(Topic::name).collect();
+            log::info!("📣 Publishing {} on tcp://{}", topics.join(", "), endpoint.address);
+        }
+        Ok(Self { endpoints, sequences: HashMap::new() })
+    }
+
+    /// Where `topic` is published, once bound
+    pub fn address(&self, topic: Topic) -> Option<SocketAddr> {
+        self.endpoints.iter()
+            .find(|endpoint| endpoint.topics.contains(&topic))
+            .map(|endpoint| endpoint.address)
+    }
+
+    pub async fn run(mut self, mut events: broadcast::Receiver<ChainEvent>, mut shutdown: ShutdownSignal) -> Result<()> {
+        loop {
+            let event = tokio::select! {
+                event = events.recv() => event,
+                _ = shutdown.wait() => return Ok(()),
+            };
+            match event {
+                Ok(ChainEvent::BlockConnected { block, .. }) => self.publish_block(&block),
+                Ok(ChainEvent::TransactionAdded(tx)) => self.publish_transaction(&tx),
+                Ok(_) => {}
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    log::warn!("📣 Pubsub publisher missed {} chain events", skipped);
+                }
+                Err(broadcast::error::RecvError::Closed) => {
+                    return Err(QtcError::Blockchain("Chain event stream closed".to_string()));
+                }
+            }
+        }
+    }
+
+    fn publish_block(&mut self, block: &Block) {
+        if self.publishes(Topic::HashBlock) {
+            self.publish(Topic::HashBlock, block.hash().as_bytes());
+        }
+        if self.publishes(Topic::RawBlock) {
+            match bincode::serialize(block) {
+                Ok(raw) => self.publish(Topic::RawBlock, &raw),
+                Err(e) => log::warn!("📣 Failed to encode block {}: {}", block.hash(), e),
+            }
+        }
+        for tx in &block.transactions {
+            self.publish_transaction(tx);
+        }
+    }
+
+    fn publish_transaction(&mut self, tx: &Transaction) {
+        if self.publishes(Topic::HashTx) {
+            self.publish(Topic::HashTx, tx.hash().as_bytes());
+        }
+        if self.publishes(Topic::RawTx) {
+            match bincode::serialize(tx) {
+                Ok(raw) => self.publish(Topic::RawTx, &raw),
+                Err(e) => log::warn!("📣 Failed to encode transaction {}: {}", tx.hash(), e),
+            }
+        }
+    }
+
+    fn publishes(&self, topic: Topic) -> bool {
+        self.endpoints.iter().any(|endpoint| endpoint.topics.contains(&topic))
+    }
+
+    fn publish(&mut self, topic: Topic, body: &[u8]) {
+        let sequence = self.sequences.entry(topic).or_insert(0);
+        let frames = Arc::new(encode_frames(topic, body, *sequence));
+        *sequence = sequence.wrapping_add(1);
+
+        for endpoint in self.endpoints.iter().filter(|endpoint| endpoint.topics.contains(&topic)) {
+            endpoint.subscribers.lock().unwrap().retain(|subscriber| match subscriber.try_send(frames.clone()) {
+                Ok(()) => true,
+                Err(mpsc::error::TrySendError::Full(_)) => {
+                    log::debug!("📣 Dropped a {} notification for a slow subscriber", topic.name());
+                    true
+                }
+                Err(mpsc::error::TrySendError::Closed(_)) => false,
+            });
+        }
+    }
+}
+
+impl Drop for PubSubPublisher {
+    fn drop(&mut self) {
+        for endpoint in &self.endpoints {
+            endpoint.accept.abort();
+        }
+    }
+}
+
+async fn accept_subscribers(listener: TcpListener, subscribers: Subscribers) {
+    loop {
+        let (mut stream, peer) = match listener.accept().await {
+            Ok(connection) => connection,
+            Err(e) => {
+                log::warn!("📣 Failed to accept a pubsub subscriber: {}", e);
+                continue;
+            }
+        };
+        log::debug!("📣 Pubsub subscriber {} connected", peer);
+
+        let (sender, mut queue) = mpsc::channel::<Arc<Vec<u8>>>(HIGH_WATER_MARK);
+        subscribers.lock().unwrap().push(sender);
+        tokio::spawn(async move {
+            while let Some(frames) = queue.recv().await {
+                if stream.write_all(&frames).await.is_err() {
+                    break;
+                }
+            }
+            log::debug!("📣 Pubsub subscriber {} disconnected", peer);
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncReadExt;
+    use tokio::net::TcpStream;
+
+    async fn read_frame(stream: &mut TcpStream) -> Vec<u8> {
+        let mut len = [0u8; 4];
+        stream.read_exact(&mut len).await.unwrap();
+        let mut frame = vec![0u8; u32::from_be_bytes(len) as usize];
+        stream.read_exact(&mut frame).await.unwrap();
+        frame
+    }
+
+    #[tokio::test]
+    async fn test_subscribers_receive_topics_in_sequence() -> Result<()> {
+        let any: SocketAddr = "127.0.0.1:0".parse().unwrap();
+        let config = PubSubConfig { hashtx: Some(any), rawtx: Some(any), ..PubSubConfig::default() };
+        let mut publisher = PubSubPublisher::bind(&config).await?;
+        // Port 0 binds each topic apart
+        let mut hashes = TcpStream::connect(publisher.address(Topic::HashTx).unwrap()).await?;
+        let mut raws = TcpStream::connect(publisher.address(Topic::RawTx).unwrap()).await?;
+        assert!(publisher.address(Topic::HashBlock).is_none());
+        while publisher.endpoints.iter().any(|endpoint| endpoint.subscribers.lock().unwrap().is_empty()) {
+            tokio::task::yield_now().await;
+        }
+
+        let first = Transaction::new_coinbase("qtc1pubsub".to_string(), 50, "first".to_string());
+        let second = Transaction::new_coinbase("qtc1pubsub".to_string(), 50, "second".to_string());
+        publisher.publish_transaction(&first);
+        publisher.publish_transaction(&second);
+
+        for (sequence, tx) in [first, second].iter().enumerate() {
+            assert_eq!(read_frame(&mut hashes).await, b"hashtx");
+            assert_eq!(read_frame(&mut hashes).await, tx.hash().as_bytes());
+            assert_eq!(read_frame(&mut hashes).await, (sequence as u32).to_le_bytes());
+
+            assert_eq!(read_frame(&mut raws).await, b"rawtx");
+            let raw: Transaction = bincode::deserialize(&read_frame(&mut raws).await).unwrap();
+            assert_eq!(raw.hash(), tx.hash());
+            assert_eq!(read_frame(&mut raws).await, (sequence as u32).to_le_bytes());
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,122 @@
+//! Per-IP token bucket rate limiting for the HTTP APIs
+
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::api::error::{ApiError, ErrorCode};
+
+const PRUNE_INTERVAL: Duration = Duration::from_secs(300);
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+#[derive(Debug)]
+pub struct RateLimiter {
+    capacity: f64,
+    refill_per_sec: f64,
+    buckets: Mutex<HashMap<IpAddr, Bucket>>,
+}
+
+impl RateLimiter {
+    /// Allow `requests_per_minute` sustained, with bursts up to the same amount
+    pub fn new(requests_per_minute: u32) -> Self {
+        Self {
+            capacity: requests_per_minute as f64,
+            refill_per_sec: requests_per_minute as f64 / 60.0,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Take one token for `ip`, returning false when the client is over its limit
+    pub fn check(&self, ip: IpAddr) -> bool {
+        let mut buckets = match self.buckets.lock() {
+            Ok(buckets) => buckets,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+
+        let now = Instant::now();
+        let bucket = buckets.entry(ip).or_insert(Bucket {
+            tokens: self.capacity,
+            last_refill: now,
+        });
+
+        let elapsed = now.duration_since(bucket.last_refill).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.refill_per_sec).min(self.capacity);
+        bucket.last_refill = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Drop buckets that have refilled completely to keep memory bounded
+    pub fn prune(&self) {
+        if let Ok(mut buckets) = self.buckets.lock() {
+            let now = Instant::now();
+            let full_after = self.capacity / self.refill_per_sec.max(f64::EPSILON);
+            buckets.retain(|_, b| now.duration_since(b.last_refill).as_secs_f64() < full_after);
+        }
+    }
+
+    /// Prune every few minutes for as long as the returned task runs
+    pub fn spawn_pruner(limiter: Arc<Self>) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(PRUNE_INTERVAL);
+            loop {
+                interval.tick().await;
+                limiter.prune();
+            }
+        })
+    }
+}
+
+pub async fn rate_limit_middleware(
+    State(limiter): State<Arc<RateLimiter>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let ip = request.extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip())
+        .unwrap_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED));
+
+    if !limiter.check(ip) {
+        log::debug!("🚦 Rate limited API request from {}", ip);
+        return ApiError::new(ErrorCode::RateLimited, "Rate limit exceeded").into_response();
+    }
+
+    next.run(request).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bucket_exhaustion() {
+        let limiter = RateLimiter::new(3);
+        let ip: IpAddr = "10.0.0.1".parse().unwrap();
+        let other: IpAddr = "10.0.0.2".parse().unwrap();
+
+        assert!(limiter.check(ip));
+        assert!(limiter.check(ip));
+        assert!(limiter.check(ip));
+        assert!(!limiter.check(ip));
+
+        // Limits are tracked per client
+        assert!(limiter.check(other));
+    }
+}
@@ -1,27 +1,63 @@
-use crate::core::{Blockchain, Transaction};
-use crate::crypto::hash::Hashable;
-use crate::crypto::hash::Hash256;
+use crate::core::{Block, Blockchain, FeeEstimate, Transaction, TxOutput};
+use crate::core::blockchain::{PruneStatus, TxOutStatus};
+use crate::core::mempool::{FeeRateBucket, MempoolDump};
+use crate::core::scan::ScanResult;
+use crate::core::snapshot::with_snapshot;
+use crate::core::transaction::{data_as_text, OutPoint, TransactionPreview};
+use crate::core::utxo::UtxoSet;
+use crate::crypto::hash::{Hash256, Hashable};
 use crate::storage::Database;
+use crate::wallet::gap::AddressGap;
+use crate::wallet::wallet::WalletType;
+use crate::mining::template::{wait_for_template, BlockTemplate, BlockTemplateBuilder};
+use crate::network::bans::{Ban, BanList, DEFAULT_BAN_DURATION_SECS};
+use crate::network::diversity::{DiversityStats, PeerDiversity};
+use crate::network::p2p::P2PCommand;
+use crate::network::versions::{PeerVersions, VersionSummary};
+use crate::node::{HealthRegistry, SubsystemHealth};
 use crate::config::ApiConfig;
+use crate::consensus::target::Target;
+use crate::consensus::supply::{SupplyAudit, SupplyDiscrepancy};
+use crate::consensus::Units;
+use crate::api::auth::{self, require_scope, ApiCaller, ApiKeyInfo, ApiScope, ScopeGuard};
+use crate::api::error::{ApiError, ApiResult, ErrorCode};
+use crate::api::cache::{cache_middleware, ResponseCache};
+use crate::api::decode::{decode_raw, DecodedBlock, DecodedTransaction};
+use crate::api::metrics::{self, metrics};
+use crate::api::faucet::{Faucet, FaucetChallenge, FaucetClaim, FaucetInfo, FaucetPayout};
+use crate::api::ratelimit::{rate_limit_middleware, RateLimiter};
+use crate::api::types::{AddressStr, ApiJson, ApiPath, BlockHashHex, TxIdHex};
 use crate::{QtcError, Result};
 use axum::{
-    extract::{Path, Query, State},
-
-    response::Json,
-    routing::{get, post},
+    extract::{ConnectInfo, DefaultBodyLimit, Extension, Path, Query, State},
+    http::header,
+    middleware,
+    response::{IntoResponse, Json, Response},
+    routing::{delete, get, post},
     Router,
 };
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::sync::{Arc, RwLock};
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex, RwLock, RwLockReadGuard};
+use std::time::Duration;
+use tokio::sync::mpsc;
 use tower::ServiceBuilder;
 use tower_http::cors::{CorsLayer, Any};
 
+/// Largest mempool dump `/api/v1/mempool/load` accepts
+const MAX_MEMPOOL_DUMP_BYTES: usize = 1024 * 1024 * 1024;
+
+/// Largest body the raw block endpoints accept: a full block, hex encoded, with room to spare
+const MAX_RAW_BLOCK_BYTES: usize = 4 * 1024 * 1024;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ApiResponse<T> {
     pub success: bool,
     pub data: Option<T>,
     pub error: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code: Option<ErrorCode>, // set on failures
     pub timestamp: u64,
 }
 
@@ -31,77 +67,203 @@ impl<T> ApiResponse<T> {
             success: true,
             data: Some(data),
             error: None,
+            code: None,
             timestamp: chrono::Utc::now().timestamp() as u64,
         }
     }
     
-    pub fn error(message: String) -> Self {
+    pub fn error(code: ErrorCode, message: String) -> Self {
         Self {
             success: false,
             data: None,
             error: Some(message),
+            code: Some(code),
             timestamp: chrono::Utc::now().timestamp() as u64,
         }
     }
 }
 
+/// An amount as exact satoshis next to its QTC string; clients should compute with `amount_sats`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AmountInfo {
+    pub amount_sats: u64,
+    pub formatted: String, // e.g. "1.50000000 QTC"
+}
+
+impl AmountInfo {
+    pub fn new(amount_sats: u64) -> Self {
+        Self { amount_sats, formatted: Units::Qtc.format(amount_sats) }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ChainInfo {
     pub height: u64,
-    pub tip: String,
-    pub difficulty: u32,
-    pub total_supply: u64,
+    pub tip: BlockHashHex,
+    pub bits: u32, // the next block's compact target
+    pub difficulty: f64,
+    pub total_supply: AmountInfo,
     pub total_work: u128,
+    pub minimum_chain_work: u128,
+    pub status: String, // "synced" or "syncing (low work)"
     pub block_count: u64,
+    pub pruning: PruneStatus,
+}
+
+/// The coin supply recomputed from the blocks, next to what the policy allows
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SupplyInfo {
+    pub height: u64,
+    pub tip: BlockHashHex,
+    pub circulating: AmountInfo, // audited, less provably unspendable outputs
+    pub audited: AmountInfo,
+    pub unspendable: AmountInfo,
+    pub expected: AmountInfo, // premine plus every subsidy up to the tip
+    pub recorded: AmountInfo, // the chain state's total_supply
+    pub inflation: AmountInfo,
+    pub discrepancies: Vec<SupplyDiscrepancy>,
+    pub sound: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NodeStatus {
+    pub status: String, // "healthy" or "degraded"
+    pub chain: String,
+    pub height: u64,
+    pub tip: BlockHashHex,
+    pub subsystems: Vec<SubsystemHealth>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BlockInfo {
-    pub hash: String,
+    pub hash: BlockHashHex,
     pub height: u64,
-    pub previous_hash: String,
+    pub previous_hash: BlockHashHex,
     pub merkle_root: String,
     pub timestamp: u64,
-    pub difficulty: u32,
+    pub bits: u32,
+    pub difficulty: f64,
     pub nonce: u64,
     pub size: usize,
     pub transaction_count: usize,
-    pub transactions: Vec<String>, // Transaction hashes
+    pub transactions: Vec<TxIdHex>,
+    pub stale: bool, // true if the block is not on the main chain
+    pub fork_height: Option<u64>,
+}
+
+impl BlockInfo {
+    pub fn from_block(block: &crate::core::Block) -> Self {
+        Self {
+            hash: block.hash().into(),
+            height: block.header.height,
+            previous_hash: block.header.previous_hash.into(),
+            merkle_root: block.header.merkle_root.to_hex(),
+            timestamp: block.header.timestamp,
+            bits: block.header.bits,
+            difficulty: Target::from_bits(block.header.bits).difficulty(),
+            nonce: block.header.nonce,
+            size: block.size(),
+            transaction_count: block.transactions.len(),
+            transactions: block.transactions.iter().map(|tx| tx.hash().into()).collect(),
+            stale: false,
+            fork_height: None,
+        }
+    }
+}
+
+/// A block's compact filter, for light clients to test their scripts against
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BlockFilterInfo {
+    pub block_hash: BlockHashHex,
+    pub filter_type: String, // "basic", the BIP158 script filter
+    pub filter: String,      // hex
+    pub header: String,      // commits to this filter and every one before it
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StaleBlockSummary {
+    pub hash: BlockHashHex,
+    pub height: u64,
+    pub previous_hash: BlockHashHex,
+    pub fork_height: u64,
+    pub fork_point: BlockHashHex,
+    pub recorded_at: u64,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TransactionInfo {
-    pub hash: String,
+    pub hash: TxIdHex,
     pub version: u32,
     pub lock_time: u64,
     pub size: usize,
     pub input_count: usize,
     pub output_count: usize,
-    pub total_input_value: u64,
-    pub total_output_value: u64,
-    pub fee: u64,
+    pub total_input_value: AmountInfo,
+    pub total_output_value: AmountInfo,
+    pub fee: AmountInfo,
     pub is_coinbase: bool,
+    pub outputs: Vec<TxOutputInfo>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TxOutputInfo {
+    pub vout: u32,
+    pub value: AmountInfo,
+    pub address: Option<String>,
+    pub unspendable: bool,
+    pub data: Option<String>,      // hex payload of an OP_RETURN data output
+    pub data_text: Option<String>, // the payload, when it reads as text
+}
+
+impl TxOutputInfo {
+    pub(crate) fn from_output(vout: usize, output: &TxOutput) -> Self {
+        let address = UtxoSet::output_address(&output.script_pubkey);
+        let data = output.data_payload();
+        Self {
+            vout: vout as u32,
+            value: AmountInfo::new(output.value),
+            address: (address != "unknown").then_some(address),
+            unspendable: output.is_unspendable(),
+            data: data.map(hex::encode),
+            data_text: data.and_then(data_as_text).map(str::to_string),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AddressInfo {
-    pub address: String,
-    pub balance: u64,
+    pub address: AddressStr,
+    pub balance: AmountInfo,
     pub transaction_count: u64,
-    pub received: u64,
-    pub sent: u64,
+    pub received: AmountInfo,
+    pub sent: AmountInfo,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct UtxoInfo {
-    pub txid: String,
+    pub txid: TxIdHex,
     pub vout: u32,
-    pub value: u64,
+    pub value: AmountInfo,
     pub height: u64,
     pub confirmations: u64,
     pub is_coinbase: bool,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OutputInfo {
+    pub txid: TxIdHex,
+    pub vout: u32,
+    pub spent: bool,
+    pub value: Option<AmountInfo>,
+    pub address: Option<String>,
+    pub height: Option<u64>,
+    pub confirmations: Option<u64>,
+    pub is_coinbase: Option<bool>,
+    pub spent_by_txid: Option<TxIdHex>,
+    pub spent_by_input: Option<u32>,
+    pub spent_height: Option<u64>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MempoolInfo {
     pub size: usize,
@@ -111,6 +273,30 @@ pub struct MempoolInfo {
     pub fee_rate: u64,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProjectedBlockInfo {
+    pub index: usize, // 0 = next block
+    pub transaction_count: usize,
+    pub size: usize,
+    pub total_fees: AmountInfo,
+    pub min_fee_rate: u64,
+    pub max_fee_rate: u64,
+    pub txids: Vec<TxIdHex>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProjectedBlocksInfo {
+    pub mempool_size: usize,
+    pub blocks: Vec<ProjectedBlockInfo>,
+    pub fee_histogram: Vec<FeeRateBucket>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MempoolLoadResult {
+    pub loaded: usize,
+    pub skipped: usize,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct NetworkInfo {
     pub version: String,
@@ -124,7 +310,7 @@ pub struct NetworkInfo {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MiningInfo {
     pub blocks: u64,
-    pub difficulty: u32,
+    pub difficulty: f64,
     pub network_hashrate: f64,
     pub pooled_tx: usize,
     pub chain: String,
@@ -136,6 +322,16 @@ pub struct SendTransactionRequest {
     pub raw_transaction: String,
 }
 
+#[derive(Debug, Clone, Deserialize)]
+pub struct RawBlockRequest {
+    pub raw_block: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ScanTxoutSetRequest {
+    pub descriptors: Vec<String>,
+}
+
 #[derive(Debug, Clone, Deserialize)]
 pub struct BlockQuery {
     pub verbose: Option<bool>,
@@ -149,75 +345,352 @@ pub struct TransactionQuery {
 #[derive(Debug, Clone, Deserialize)]
 pub struct BlocksQuery {
     pub limit: Option<u64>,
-    pub offset: Option<u64>,
+    pub offset: Option<u64>, // ignored once a cursor is given
+    pub cursor: Option<u64>, // `next_cursor` of the previous page
+    pub from_height: Option<u64>,
+    pub to_height: Option<u64>,
+    pub from_time: Option<u64>, // unix seconds, inclusive
+    pub to_time: Option<u64>,
+}
+
+/// Blocks a single `/blocks` request may walk past, whatever the filters
+const MAX_BLOCKS_SCANNED: usize = 1_000;
+
+/// One page of a listing; `next_cursor` is absent on the last page
+#[derive(Debug, Clone, Serialize)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub next_cursor: Option<u64>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct BlockTemplateQuery {
+    pub longpollid: Option<String>, // wait until there is newer work than this
+    pub max_size: Option<usize>, // bytes of transactions, capped by the consensus limit
+    pub min_fee_rate: Option<u64>, // sat/byte
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct FeeEstimateQuery {
+    pub blocks: Option<u32>, // confirmation target, besides fast/medium/slow
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ProjectedBlocksQuery {
+    pub blocks: Option<usize>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct WalletStatus {
+    pub name: String,
+    pub wallet_type: WalletType,
+    pub balance: u64,
+    pub address_count: u32,
+    pub address_gap: AddressGap,
+    pub gap_limit_exceeded: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ReserveAddressesRequest {
+    pub count: u32,
+    pub label: Option<String>,
+    pub format: Option<String>, // "json" (default) or "csv"
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct CreateApiKeyRequest {
+    pub name: String,
+    pub scopes: Vec<String>, // "read", "broadcast", "wallet-spend", "admin"
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct BanPeerRequest {
+    pub target: String, // a peer ID or an IP address
+    #[serde(default)]
+    pub duration_secs: Option<u64>, // a day by default
+    #[serde(default)]
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct LoggingRequest {
+    pub level: String,
+    #[serde(default)]
+    pub modules: String, // e.g. "network=debug,mining=info"
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LoggingInfo {
+    pub filter: String, // the directives in effect
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreatedApiKey {
+    pub key: ApiKeyInfo,
+    pub token: String, // shown once, only a digest is kept
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct PreviewTransactionRequest {
+    pub to: AddressStr,
+    pub amount: u64, // satoshis
+    pub fee_rate: Option<u64>,
 }
 
 #[derive(Debug, Clone)]
 pub struct AppState {
     pub blockchain: Arc<RwLock<Blockchain>>,
     pub db: Arc<Database>,
+    pub address_index: bool,
+    pub peer_versions: Option<Arc<PeerVersions>>,
+    pub peer_diversity: Option<Arc<PeerDiversity>>,
+    pub bans: Option<Arc<BanList>>,
+    pub subsystems: Option<Arc<HealthRegistry>>,
+    pub supply_audit: Arc<Mutex<SupplyAudit>>, // carried forward from request to request
+    pub p2p_commands: Option<mpsc::Sender<P2PCommand>>,
 }
 
 pub struct RestApi {
     blockchain: Arc<RwLock<Blockchain>>,
     db: Arc<Database>,
     config: ApiConfig,
+    address_index: bool,
+    peer_versions: Option<Arc<PeerVersions>>,
+    peer_diversity: Option<Arc<PeerDiversity>>,
+    bans: Option<Arc<BanList>>,
+    subsystems: Option<Arc<HealthRegistry>>,
+    faucet: Option<Arc<Faucet>>,
+    supply_audit: Arc<Mutex<SupplyAudit>>,
+    p2p_commands: Option<mpsc::Sender<P2PCommand>>,
 }
 
 impl RestApi {
     pub fn new(blockchain: Arc<RwLock<Blockchain>>, config: ApiConfig) -> Self {
         let db = Arc::new(Database::new("qtc.db").expect("Failed to initialize database"));
-        
+        Self::with_db(blockchain, db, config)
+    }
+    
+    /// Serve from the node's own database instead of opening a separate one
+    pub fn with_db(blockchain: Arc<RwLock<Blockchain>>, db: Arc<Database>, config: ApiConfig) -> Self {
         Self {
             blockchain,
             db,
             config,
+            address_index: false,
+            peer_versions: None,
+            peer_diversity: None,
+            bans: None,
+            subsystems: None,
+            faucet: None,
+            supply_audit: Arc::new(Mutex::new(SupplyAudit::default())),
+            p2p_commands: None,
         }
     }
     
+    /// Serve address history from the chain (the `addrindex` storage option)
+    pub fn set_address_index(&mut self, enabled: bool) {
+        self.address_index = enabled;
+    }
+    
+    /// Serve client diversity stats gathered by the P2P node
+    pub fn set_peer_versions(&mut self, peer_versions: Arc<PeerVersions>) {
+        self.peer_versions = Some(peer_versions);
+    }
+    
+    pub fn set_peer_diversity(&mut self, peer_diversity: Arc<PeerDiversity>) {
+        self.peer_diversity = Some(peer_diversity);
+    }
+    
+    /// Let admins list, add and lift the P2P node's peer bans
+    pub fn set_ban_list(&mut self, bans: Arc<BanList>) {
+        self.bans = Some(bans);
+    }
+    
+    /// Report the node supervisor's view of its subsystems in `/api/v1/status`
+    pub fn set_subsystem_health(&mut self, subsystems: Arc<HealthRegistry>) {
+        self.subsystems = Some(subsystems);
+    }
+    
+    /// Serve a testnet faucet under `/api/v1/faucet`
+    pub fn set_faucet(&mut self, faucet: Arc<Faucet>) {
+        self.faucet = Some(faucet);
+    }
+    
+    /// Relay blocks submitted over `POST /api/v1/blocks` to peers
+    pub fn set_p2p_commands(&mut self, p2p_commands: mpsc::Sender<P2PCommand>) {
+        self.p2p_commands = Some(p2p_commands);
+    }
+    
     pub async fn start(self) -> Result<()> {
         log::info!("🚀 Starting QTC REST API on port {}", self.config.rest_port);
         
-        let state = AppState {
-            blockchain: self.blockchain.clone(),
-            db: self.db.clone(),
-        };
-        
-        let app = self.create_router(state);
-        let addr = format!("0.0.0.0:{}", self.config.rest_port);
+        let app = self.create_router(self.state());
+        let addr = SocketAddr::new(self.config.bind_address, self.config.rest_port);
         let listener = tokio::net::TcpListener::bind(&addr).await
             .map_err(|e| QtcError::Network(format!("Failed to bind to {}: {}", addr, e)))?;
         
         log::info!("✅ REST API listening on http://{}", addr);
         
-        axum::serve(listener, app).await
+        // Client addresses are needed for per-IP rate limiting
+        axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>()).await
             .map_err(|e| QtcError::Network(format!("Server error: {}", e)))?;
         
         Ok(())
     }
     
+    /// Read endpoints as served to API workers over the control socket; the
+    /// workers put their own caching and rate limiting in front
+    pub fn worker_routes(&self) -> Router {
+        let mut reads = Self::read_routes();
+        if self.config.enable_mining_endpoints {
+            reads = reads
+                .route("/api/v1/mining", get(get_mining_info))
+                .route("/api/v1/mining/difficulty", get(get_difficulty));
+        }
+        reads.with_state(self.state())
+    }
+    
+    fn state(&self) -> AppState {
+        AppState {
+            blockchain: self.blockchain.clone(),
+            db: self.db.clone(),
+            address_index: self.address_index,
+            peer_versions: self.peer_versions.clone(),
+            peer_diversity: self.peer_diversity.clone(),
+            bans: self.bans.clone(),
+            subsystems: self.subsystems.clone(),
+            supply_audit: self.supply_audit.clone(),
+            p2p_commands: self.p2p_commands.clone(),
+        }
+    }
+    
     fn create_router(&self, state: AppState) -> Router {
         let cors = CorsLayer::new()
             .allow_methods(Any)
             .allow_headers(Any)
             .allow_origin(Any);
         
+        let guard = |scope| Arc::new(ScopeGuard::from_config(scope, self.db.clone(), &self.config));
+        
+        let mut reads = Self::read_routes();
+        if self.config.enable_mining_endpoints {
+            reads = reads
+                .route("/api/v1/mining", get(get_mining_info))
+                .route("/api/v1/mining/difficulty", get(get_difficulty))
+                .route("/api/v1/mining/template", get(get_block_template));
+        }
+        // The cache sits inside the key check so cached bodies aren't served to unauthenticated callers
+        if self.config.cache_ttl_secs > 0 {
+            let cache = Arc::new(ResponseCache::new(Duration::from_secs(self.config.cache_ttl_secs)));
+            reads = reads.layer(middleware::from_fn_with_state(cache, cache_middleware));
+        }
+        if self.config.require_api_keys {
+            reads = reads.route_layer(middleware::from_fn_with_state(guard(ApiScope::Read), require_scope));
+        }
+        
+        // Health probes stay open so load balancers and supervisors don't need a key
+        let mut router = Router::new()
+            .route("/health", get(health_check))
+            .route("/api/v1/status", get(get_node_status))
+            .route("/", get(api_root))
+            .merge(reads);
+        
+        // Scraped outside the response cache so every scrape sees fresh values
+        if self.config.enable_metrics {
+            let mut metrics_routes = Router::new().route("/metrics", get(get_metrics));
+            if self.config.require_api_keys {
+                metrics_routes = metrics_routes.route_layer(middleware::from_fn_with_state(guard(ApiScope::Read), require_scope));
+            }
+            router = router.merge(metrics_routes);
+        }
+        
+        // Decoding never touches the chain, so read-only nodes serve it too
+        let mut decode = Router::new()
+            .route("/api/v1/decode/block", post(decode_block).layer(DefaultBodyLimit::max(MAX_RAW_BLOCK_BYTES)))
+            .route("/api/v1/decode/transaction", post(decode_transaction));
+        if self.config.require_api_keys {
+            decode = decode.route_layer(middleware::from_fn_with_state(guard(ApiScope::Read), require_scope));
+        }
+        router = router.merge(decode);
+        
+        if !self.config.read_only {
+            let mut broadcast = Router::new()
+                .route("/api/v1/transactions", post(send_transaction))
+                .route("/api/v1/blocks", post(submit_block).layer(DefaultBodyLimit::max(MAX_RAW_BLOCK_BYTES)));
+            if self.config.require_api_keys {
+                broadcast = broadcast.route_layer(middleware::from_fn_with_state(guard(ApiScope::Broadcast), require_scope));
+            }
+            
+            let admin = Router::new()
+                // Full UTXO set walk, so keep it off read-only public nodes
+                .route("/api/v1/utxos/scan", post(scan_txout_set))
+                // Debugging aids: dump a user's pool, replay it on a test node
+                .route("/api/v1/mempool/dump", get(dump_mempool))
+                .route("/api/v1/mempool/load", post(load_mempool).layer(DefaultBodyLimit::max(MAX_MEMPOOL_DUMP_BYTES)))
+                .route("/api/v1/admin/keys", get(list_api_keys).post(create_api_key))
+                .route("/api/v1/admin/keys/:id", delete(revoke_api_key))
+                .route("/api/v1/admin/logging", get(get_logging).put(set_logging))
+                .route("/api/v1/network/bans", get(list_peer_bans).post(ban_peer))
+                .route("/api/v1/network/bans/:target", delete(unban_peer))
+                .route_layer(middleware::from_fn_with_state(guard(ApiScope::Admin), require_scope));
+            
+            router = router.merge(broadcast).merge(admin);
+        }
+        
+        if self.config.enable_wallet_endpoints && !self.config.read_only {
+            let mut wallet_guard = ScopeGuard::from_config(ApiScope::WalletSpend, self.db.clone(), &self.config);
+            if let Some(token) = &self.config.wallet_api_token {
+                wallet_guard = wallet_guard.with_legacy_token(token);
+            }
+            let wallet_routes = Router::new()
+                .route("/api/v1/wallets/:name/status", get(get_wallet_status))
+                .route("/api/v1/wallets/:name/addresses", post(reserve_wallet_addresses))
+                .route("/api/v1/wallets/:name/transactions/preview", post(preview_wallet_transaction))
+                .route_layer(middleware::from_fn_with_state(Arc::new(wallet_guard), require_scope));
+            router = router.merge(wallet_routes);
+        }
+        
+        // Open to anyone; the faucet's own challenge and cooldowns guard it
+        if let (Some(faucet), false) = (&self.faucet, self.config.read_only) {
+            let faucet_routes = Router::new()
+                .route("/api/v1/faucet", get(get_faucet_info).post(claim_faucet))
+                .route("/api/v1/faucet/challenge", get(get_faucet_challenge))
+                .with_state(faucet.clone());
+            router = router.merge(faucet_routes);
+        }
+        
+        // Rate limiting sits outside the cache so cached hits still count
+        if self.config.rate_limit_per_minute > 0 {
+            let limiter = Arc::new(RateLimiter::new(self.config.rate_limit_per_minute));
+            RateLimiter::spawn_pruner(limiter.clone());
+            router = router.layer(middleware::from_fn_with_state(limiter, rate_limit_middleware));
+        }
+        
+        router
+            .layer(ServiceBuilder::new().layer(cors))
+            .with_state(state)
+    }
+    
+    fn read_routes() -> Router<AppState> {
         Router::new()
             // Blockchain info endpoints
             .route("/api/v1/info", get(get_chain_info))
             .route("/api/v1/chain/info", get(get_chain_info))  // Alternative endpoint
             .route("/api/v1/stats", get(get_chain_stats))
+            .route("/api/v1/supply", get(get_supply))
             
             // Block endpoints
             .route("/api/v1/blocks", get(get_blocks))
             .route("/api/v1/blocks/latest", get(get_latest_block))
+            .route("/api/v1/blocks/stale", get(get_stale_blocks))
             .route("/api/v1/blocks/height/:height", get(get_block_by_height))
             .route("/api/v1/blocks/:hash", get(get_block_by_hash))
+            .route("/api/v1/blocks/:hash/filter", get(get_block_filter))
             
             // Transaction endpoints
-            .route("/api/v1/transactions", post(send_transaction))
             .route("/api/v1/transactions/:hash", get(get_transaction))
             .route("/api/v1/transactions/raw/:hash", get(get_raw_transaction))
+            .route("/api/v1/outputs/:txid/:vout", get(get_output))
             
             // Address endpoints
             .route("/api/v1/addresses/:address", get(get_address_info))
@@ -228,25 +701,17 @@ impl RestApi {
             // Mempool endpoints
             .route("/api/v1/mempool", get(get_mempool_info))
             .route("/api/v1/mempool/transactions", get(get_mempool_transactions))
+            .route("/api/v1/mempool/projected-blocks", get(get_projected_blocks))
             
             // Network endpoints
             .route("/api/v1/network", get(get_network_info))
+            .route("/api/v1/network/versions", get(get_network_versions))
+            .route("/api/v1/network/diversity", get(get_network_diversity))
             .route("/api/v1/peers", get(get_peers))
             
-            // Mining endpoints
-            .route("/api/v1/mining", get(get_mining_info))
-            .route("/api/v1/mining/difficulty", get(get_difficulty))
-            
             // Utility endpoints
             .route("/api/v1/validate/address/:address", get(validate_address))
             .route("/api/v1/fee/estimate", get(estimate_fee))
-            
-            // Health check
-            .route("/health", get(health_check))
-            .route("/", get(api_root))
-            
-            .layer(ServiceBuilder::new().layer(cors))
-            .with_state(state)
     }
 }
 
@@ -261,48 +726,100 @@ async fn api_root() -> Json<ApiResponse<HashMap<String, String>>> {
     Json(ApiResponse::success(info))
 }
 
-async fn health_check() -> Json<ApiResponse<HashMap<String, String>>> {
+async fn health_check(State(state): State<AppState>) -> Json<ApiResponse<HashMap<String, String>>> {
     let mut status = HashMap::new();
     status.insert("status".to_string(), "healthy".to_string());
+    if let Ok(blockchain) = state.blockchain.read() {
+        status.insert("chain".to_string(), blockchain.sync_status().to_string());
+    }
     status.insert("timestamp".to_string(), chrono::Utc::now().to_rfc3339());
     
     Json(ApiResponse::success(status))
 }
 
-async fn get_chain_info(State(state): State<AppState>) -> Json<ApiResponse<ChainInfo>> {
+async fn get_metrics(State(state): State<AppState>) -> Response {
+    if let Ok(blockchain) = state.blockchain.read() {
+        metrics().chain_height.set(blockchain.height as f64);
+    }
+    match state.db.size_on_disk() {
+        Ok(size) => metrics().database_bytes.set(size as f64),
+        Err(e) => log::warn!("📈 Could not read the database size: {}", e),
+    }
+    ([(header::CONTENT_TYPE, metrics::CONTENT_TYPE)], metrics().render()).into_response()
+}
+
+async fn get_node_status(State(state): State<AppState>) -> ApiResult<NodeStatus> {
+    let (chain, height, tip) = {
+        let blockchain = read_chain(&state)?;
+        (blockchain.sync_status().to_string(), blockchain.height, blockchain.tip.into())
+    };
+    
+    // Without a supervisor (e.g. an embedded API) there is nothing to report on
+    let subsystems = state.subsystems.as_ref().map(|registry| registry.snapshot()).unwrap_or_default();
+    let healthy = subsystems.iter().all(SubsystemHealth::is_healthy);
+    
+    Ok(Json(ApiResponse::success(NodeStatus {
+        status: if healthy { "healthy" } else { "degraded" }.to_string(),
+        chain,
+        height,
+        tip,
+        subsystems,
+    })))
+}
+
+/// The chain behind a read lock; a poisoned lock means a writer panicked mid-update
+fn read_chain(state: &AppState) -> std::result::Result<RwLockReadGuard<'_, Blockchain>, ApiError> {
+    state.blockchain.read().map_err(|_| ApiError::internal("Failed to access blockchain"))
+}
+
+async fn get_chain_info(State(state): State<AppState>) -> ApiResult<ChainInfo> {
     log::info!("🔗 API: get_chain_info called");
     
-    match state.blockchain.read() {
-        Ok(blockchain) => {
-            log::info!("🔗 API: Got blockchain lock successfully");
-            match blockchain.get_chain_info() {
-                Ok(chain_state) => {
-                    log::info!("🔗 API: Retrieved chain state - height: {}, difficulty: {}", 
-                        chain_state.height, chain_state.difficulty);
-                    
-                    let info = ChainInfo {
-                        height: chain_state.height,
-                        tip: chain_state.tip.to_hex(),
-                        difficulty: chain_state.difficulty,
-                        total_supply: chain_state.total_supply,
-                        total_work: chain_state.total_work,
-                        block_count: chain_state.height + 1,
-                    };
-                    
-                    log::info!("🔗 API: Returning chain info response");
-                    Json(ApiResponse::success(info))
-                }
-                Err(e) => {
-                    log::error!("🔗 API: Failed to get chain info: {}", e);
-                    Json(ApiResponse::error(format!("Failed to get chain info: {}", e)))
-                }
-            }
-        }
-        Err(e) => {
-            log::error!("🔗 API: Failed to acquire blockchain lock: {:?}", e);
-            Json(ApiResponse::error("Failed to access blockchain".to_string()))
-        }
-    }
+    let blockchain = read_chain(&state)?;
+    let chain_state = blockchain.get_chain_info()
+        .map_err(|e| ApiError::from(e).context("Failed to get chain info"))?;
+    log::info!("🔗 API: Retrieved chain state - height: {}, difficulty: {:.3}", 
+        chain_state.height, chain_state.difficulty());
+    let pruning = blockchain.prune_status()
+        .map_err(|e| ApiError::from(e).context("Failed to get chain info"))?;
+    
+    Ok(Json(ApiResponse::success(ChainInfo {
+        height: chain_state.height,
+        tip: chain_state.tip.into(),
+        bits: chain_state.bits,
+        difficulty: chain_state.difficulty(),
+        total_supply: AmountInfo::new(chain_state.total_supply),
+        total_work: chain_state.total_work,
+        minimum_chain_work: blockchain.minimum_chain_work(),
+        status: blockchain.sync_status().to_string(),
+        block_count: chain_state.height + 1,
+        pruning,
+    })))
+}
+
+async fn get_supply(State(state): State<AppState>) -> ApiResult<SupplyInfo> {
+    let blockchain = read_chain(&state)?;
+    let recorded = blockchain.get_chain_info()
+        .map_err(|e| ApiError::from(e).context("Failed to get chain info"))?
+        .total_supply;
+    
+    // Only the blocks since the last request are audited
+    let mut audit = state.supply_audit.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    audit.advance(&blockchain)
+        .map_err(|e| ApiError::from(e).context("Failed to audit the supply"))?;
+    
+    Ok(Json(ApiResponse::success(SupplyInfo {
+        height: audit.height,
+        tip: audit.tip.into(),
+        circulating: AmountInfo::new(audit.circulating()),
+        audited: AmountInfo::new(audit.supply),
+        unspendable: AmountInfo::new(audit.unspendable),
+        expected: AmountInfo::new(audit.expected),
+        recorded: AmountInfo::new(recorded),
+        inflation: AmountInfo::new(audit.inflation()),
+        discrepancies: audit.discrepancies.clone(),
+        sound: audit.is_sound(),
+    })))
 }
 
 async fn get_chain_stats(State(state): State<AppState>) -> Json<ApiResponse<HashMap<String, serde_json::Value>>> {
@@ -311,8 +828,8 @@ async fn get_chain_stats(State(state): State<AppState>) -> Json<ApiResponse<Hash
     if let Ok(blockchain) = state.blockchain.read() {
         if let Ok(chain_info) = blockchain.get_chain_info() {
             stats.insert("height".to_string(), serde_json::Value::from(chain_info.height));
-            stats.insert("difficulty".to_string(), serde_json::Value::from(chain_info.difficulty));
-            stats.insert("total_supply".to_string(), serde_json::Value::from(chain_info.total_supply));
+            stats.insert("difficulty".to_string(), serde_json::Value::from(chain_info.difficulty()));
+            stats.insert("total_supply".to_string(), serde_json::json!(AmountInfo::new(chain_info.total_supply)));
         }
     }
     
@@ -324,318 +841,465 @@ async fn get_chain_stats(State(state): State<AppState>) -> Json<ApiResponse<Hash
     Json(ApiResponse::success(stats))
 }
 
+/// Main chain blocks, newest first. Pages are cut by height: `next_cursor` is
+/// the height the next page starts at, so blocks arriving between requests
+/// don't shift later pages. Height and time filters narrow the walk, which
+/// stops after `MAX_BLOCKS_SCANNED` blocks and hands back a cursor to go on.
 async fn get_blocks(
     State(state): State<AppState>,
     Query(query): Query<BlocksQuery>,
-) -> Json<ApiResponse<Vec<BlockInfo>>> {
-    let limit = query.limit.unwrap_or(10).min(100); // Max 100 blocks
-    let offset = query.offset.unwrap_or(0);
-    
-    match state.blockchain.read() {
-        Ok(blockchain) => {
-            let current_height = blockchain.height;
-            let start_height = current_height.saturating_sub(offset + limit - 1);
-            let end_height = current_height.saturating_sub(offset);
-            
-            let mut blocks = Vec::new();
-            
-            for height in start_height..=end_height {
-                if let Ok(Some(block)) = blockchain.get_block_by_height(height) {
-                    let block_info = BlockInfo {
-                        hash: block.hash().to_hex(),
-                        height: block.header.height,
-                        previous_hash: block.header.previous_hash.to_hex(),
-                        merkle_root: block.header.merkle_root.to_hex(),
-                        timestamp: block.header.timestamp,
-                        difficulty: block.header.difficulty,
-                        nonce: block.header.nonce,
-                        size: block.size(),
-                        transaction_count: block.transactions.len(),
-                        transactions: block.transactions.iter().map(|tx| tx.hash().to_hex()).collect(),
-                    };
-                    blocks.push(block_info);
-                }
-            }
-            
-            blocks.reverse(); // Newest first
-            Json(ApiResponse::success(blocks))
+) -> ApiResult<Page<BlockInfo>> {
+    let limit = query.limit.unwrap_or(10).clamp(1, 100) as usize;
+    if let (Some(from), Some(to)) = (query.from_height, query.to_height) {
+        if from > to {
+            return Err(ApiError::bad_request(format!("from_height {} is above to_height {}", from, to)));
+        }
+    }
+    if let (Some(from), Some(to)) = (query.from_time, query.to_time) {
+        if from > to {
+            return Err(ApiError::bad_request(format!("from_time {} is after to_time {}", from, to)));
         }
-        Err(_) => Json(ApiResponse::error("Failed to access blockchain".to_string())),
-    }
-}
-
-async fn get_latest_block(State(state): State<AppState>) -> Json<ApiResponse<BlockInfo>> {
-    match state.blockchain.read() {
-        Ok(blockchain) => {
-            let height = blockchain.height;
-            match blockchain.get_block_by_height(height) {
-                Ok(Some(block)) => {
-                    let block_info = BlockInfo {
-                        hash: block.hash().to_hex(),
-                        height: block.header.height,
-                        previous_hash: block.header.previous_hash.to_hex(),
-                        merkle_root: block.header.merkle_root.to_hex(),
-                        timestamp: block.header.timestamp,
-                        difficulty: block.header.difficulty,
-                        nonce: block.header.nonce,
-                        size: block.size(),
-                        transaction_count: block.transactions.len(),
-                        transactions: block.transactions.iter().map(|tx| tx.hash().to_hex()).collect(),
-                    };
-                    Json(ApiResponse::success(block_info))
+    }
+    
+    // One snapshot, so a block landing mid-request can't shift the page
+    let page = with_snapshot(&state.blockchain, |snapshot| {
+        let start = query.cursor
+            .unwrap_or_else(|| snapshot.height.saturating_sub(query.offset.unwrap_or(0)))
+            .min(snapshot.height)
+            .min(query.to_height.unwrap_or(u64::MAX));
+        let lowest = query.from_height.unwrap_or(0);
+        
+        let mut items = Vec::new();
+        let mut height = Some(start).filter(|height| *height >= lowest);
+        let mut scanned = 0;
+        while let Some(current) = height {
+            if items.len() == limit || scanned == MAX_BLOCKS_SCANNED {
+                break;
+            }
+            if let Some(block) = snapshot.get_block_by_height(current)? {
+                let timestamp = block.header.timestamp;
+                if query.from_time.is_none_or(|from| timestamp >= from) && query.to_time.is_none_or(|to| timestamp <= to) {
+                    items.push(BlockInfo::from_block(&block));
                 }
-                Ok(None) => Json(ApiResponse::error("Latest block not found".to_string())),
-                Err(e) => Json(ApiResponse::error(format!("Failed to get latest block: {}", e))),
             }
+            scanned += 1;
+            height = current.checked_sub(1).filter(|next| *next >= lowest);
         }
-        Err(_) => Json(ApiResponse::error("Failed to access blockchain".to_string())),
+        Ok(Page { items, next_cursor: height })
+    }).map_err(|e| ApiError::from(e).context("Failed to get blocks"))?;
+    
+    Ok(Json(ApiResponse::success(page)))
+}
+
+async fn get_latest_block(State(state): State<AppState>) -> ApiResult<BlockInfo> {
+    match with_snapshot(&state.blockchain, |snapshot| snapshot.get_block(&snapshot.tip)) {
+        Ok(Some(block)) => Ok(Json(ApiResponse::success(BlockInfo::from_block(&block)))),
+        Ok(None) => Err(ApiError::not_found("Latest block not found")),
+        Err(e) => Err(ApiError::from(e).context("Failed to get latest block")),
     }
 }
 
+async fn get_stale_blocks(
+    State(state): State<AppState>,
+    Query(query): Query<BlocksQuery>,
+) -> ApiResult<Vec<StaleBlockSummary>> {
+    let limit = query.limit.unwrap_or(10).min(100) as usize;
+    let offset = query.offset.unwrap_or(0) as usize;
+    
+    let stale = state.db.get_stale_blocks()
+        .map_err(|e| ApiError::from(e).context("Failed to get stale blocks"))?;
+    let blocks = stale.into_iter()
+        .skip(offset)
+        .take(limit)
+        .map(|info| StaleBlockSummary {
+            hash: info.hash.into(),
+            height: info.height,
+            previous_hash: info.previous_hash.into(),
+            fork_height: info.fork_height,
+            fork_point: info.fork_point.into(),
+            recorded_at: info.recorded_at,
+        })
+        .collect();
+    Ok(Json(ApiResponse::success(blocks)))
+}
+
 async fn get_block_by_height(
     State(state): State<AppState>,
-    Path(height): Path<u64>,
+    ApiPath(height): ApiPath<u64>,
     Query(_query): Query<BlockQuery>,
-) -> Json<ApiResponse<BlockInfo>> {
-    match state.blockchain.read() {
-        Ok(blockchain) => {
-            match blockchain.get_block_by_height(height) {
-                Ok(Some(block)) => {
-                    let block_info = BlockInfo {
-                        hash: block.hash().to_hex(),
-                        height: block.header.height,
-                        previous_hash: block.header.previous_hash.to_hex(),
-                        merkle_root: block.header.merkle_root.to_hex(),
-                        timestamp: block.header.timestamp,
-                        difficulty: block.header.difficulty,
-                        nonce: block.header.nonce,
-                        size: block.size(),
-                        transaction_count: block.transactions.len(),
-                        transactions: block.transactions.iter().map(|tx| tx.hash().to_hex()).collect(),
-                    };
-                    Json(ApiResponse::success(block_info))
-                }
-                Ok(None) => Json(ApiResponse::error("Block not found".to_string())),
-                Err(e) => Json(ApiResponse::error(format!("Failed to get block: {}", e))),
-            }
-        }
-        Err(_) => Json(ApiResponse::error("Failed to access blockchain".to_string())),
+) -> ApiResult<BlockInfo> {
+    match with_snapshot(&state.blockchain, |snapshot| snapshot.get_block_by_height(height)) {
+        Ok(Some(block)) => Ok(Json(ApiResponse::success(BlockInfo::from_block(&block)))),
+        Ok(None) => Err(ApiError::not_found(format!("No block at height {}", height))),
+        Err(e) => Err(ApiError::from(e).context("Failed to get block")),
     }
 }
 
 async fn get_block_by_hash(
     State(state): State<AppState>,
-    Path(hash_str): Path<String>,
+    ApiPath(hash): ApiPath<BlockHashHex>,
     Query(_query): Query<BlockQuery>,
-) -> Json<ApiResponse<BlockInfo>> {
-    let hash = match Hash256::from_hex(&hash_str) {
-        Ok(hash) => hash,
-        Err(_) => return Json(ApiResponse::error("Invalid block hash".to_string())),
-    };
+) -> ApiResult<BlockInfo> {
+    let block = read_chain(&state)?.get_block(&hash.hash())
+        .map_err(|e| ApiError::from(e).context("Failed to get block"))?
+        .ok_or_else(|| ApiError::not_found(format!("Block {} not found", hash)))?;
     
-    match state.blockchain.read() {
-        Ok(blockchain) => {
-            match blockchain.get_block(&hash) {
-                Ok(Some(block)) => {
-                    let block_info = BlockInfo {
-                        hash: block.hash().to_hex(),
-                        height: block.header.height,
-                        previous_hash: block.header.previous_hash.to_hex(),
-                        merkle_root: block.header.merkle_root.to_hex(),
-                        timestamp: block.header.timestamp,
-                        difficulty: block.header.difficulty,
-                        nonce: block.header.nonce,
-                        size: block.size(),
-                        transaction_count: block.transactions.len(),
-                        transactions: block.transactions.iter().map(|tx| tx.hash().to_hex()).collect(),
-                    };
-                    Json(ApiResponse::success(block_info))
-                }
-                Ok(None) => Json(ApiResponse::error("Block not found".to_string())),
-                Err(e) => Json(ApiResponse::error(format!("Failed to get block: {}", e))),
-            }
-        }
-        Err(_) => Json(ApiResponse::error("Failed to access blockchain".to_string())),
+    let mut block_info = BlockInfo::from_block(&block);
+    if let Ok(Some(stale)) = state.db.get_stale_block_info(&hash.hash()) {
+        block_info.stale = true;
+        block_info.fork_height = Some(stale.fork_height);
     }
+    Ok(Json(ApiResponse::success(block_info)))
+}
+
+async fn get_block_filter(
+    State(state): State<AppState>,
+    ApiPath(hash): ApiPath<BlockHashHex>,
+) -> ApiResult<BlockFilterInfo> {
+    let blockchain = read_chain(&state)?;
+    if !blockchain.has_block_filters() {
+        return Err(ApiError::not_found("Block filters are not built on this node (storage.blockfilterindex)"));
+    }
+    let filter = blockchain.get_block_filter(&hash.hash())
+        .map_err(|e| ApiError::from(e).context("Failed to get block filter"))?
+        .ok_or_else(|| ApiError::not_found(format!("No filter for block {}", hash)))?;
+    
+    Ok(Json(ApiResponse::success(BlockFilterInfo {
+        block_hash: hash,
+        filter_type: "basic".to_string(),
+        filter: hex::encode(&filter.filter),
+        header: filter.header.to_hex(),
+    })))
+}
+
+/// A transaction from the index, or a 404 naming it
+fn find_transaction(state: &AppState, txid: &TxIdHex) -> std::result::Result<Transaction, ApiError> {
+    state.db.get_transaction(&txid.hash())
+        .map_err(|e| ApiError::from(e).context("Failed to get transaction"))?
+        .ok_or_else(|| ApiError::not_found(format!("Transaction {} not found", txid)))
 }
 
 async fn get_transaction(
     State(state): State<AppState>,
-    Path(hash_str): Path<String>,
+    ApiPath(txid): ApiPath<TxIdHex>,
     Query(_query): Query<TransactionQuery>,
-) -> Json<ApiResponse<TransactionInfo>> {
-    let hash = match Hash256::from_hex(&hash_str) {
-        Ok(hash) => hash,
-        Err(_) => return Json(ApiResponse::error("Invalid transaction hash".to_string())),
+) -> ApiResult<TransactionInfo> {
+    let tx = find_transaction(&state, &txid)?;
+    Ok(Json(ApiResponse::success(TransactionInfo {
+        hash: tx.hash().into(),
+        version: tx.version,
+        lock_time: tx.lock_time,
+        size: tx.size(),
+        input_count: tx.inputs.len(),
+        output_count: tx.outputs.len(),
+        total_input_value: AmountInfo::new(tx.total_input_value()),
+        total_output_value: AmountInfo::new(tx.total_output_value()),
+        fee: AmountInfo::new(tx.fee()),
+        is_coinbase: tx.is_coinbase(),
+        outputs: tx.outputs.iter().enumerate().map(|(vout, output)| TxOutputInfo::from_output(vout, output)).collect(),
+    })))
+}
+
+async fn get_raw_transaction(
+    State(state): State<AppState>,
+    ApiPath(txid): ApiPath<TxIdHex>,
+) -> ApiResult<String> {
+    let tx = find_transaction(&state, &txid)?;
+    let raw_tx = bincode::serialize(&tx)
+        .map_err(|e| ApiError::internal(format!("Failed to serialize transaction: {}", e)))?;
+    Ok(Json(ApiResponse::success(hex::encode(raw_tx))))
+}
+
+async fn get_output(
+    State(state): State<AppState>,
+    ApiPath((txid, vout)): ApiPath<(TxIdHex, u32)>,
+) -> ApiResult<OutputInfo> {
+    let outpoint = OutPoint::new(txid.hash(), vout);
+    
+    let mut info = OutputInfo {
+        txid,
+        vout,
+        spent: false,
+        value: None,
+        address: None,
+        height: None,
+        confirmations: None,
+        is_coinbase: None,
+        spent_by_txid: None,
+        spent_by_input: None,
+        spent_height: None,
     };
     
-    match state.db.get_transaction(&hash) {
-        Ok(Some(tx)) => {
-            let tx_info = TransactionInfo {
-                hash: tx.hash().to_hex(),
-                version: tx.version,
-                lock_time: tx.lock_time,
-                size: tx.size(),
-                input_count: tx.inputs.len(),
-                output_count: tx.outputs.len(),
-                total_input_value: tx.total_input_value(),
-                total_output_value: tx.total_output_value(),
-                fee: tx.fee(),
-                is_coinbase: tx.is_coinbase(),
-            };
-            Json(ApiResponse::success(tx_info))
+    // Confirmations must be counted from the same tip the output was read at
+    let (status, tip_height) = with_snapshot(&state.blockchain, |snapshot| {
+        let status = snapshot.get_txout(&outpoint)?;
+        Ok((status, snapshot.height))
+    }).map_err(|e| ApiError::from(e).context("Failed to get output"))?;
+    
+    match status {
+        TxOutStatus::Unspent(utxo) => {
+            info.value = Some(AmountInfo::new(utxo.value));
+            info.address = Some(utxo.address);
+            info.height = Some(utxo.height);
+            info.confirmations = Some(tip_height.saturating_sub(utxo.height) + 1);
+            info.is_coinbase = Some(utxo.is_coinbase);
         }
-        Ok(None) => Json(ApiResponse::error("Transaction not found".to_string())),
-        Err(e) => Json(ApiResponse::error(format!("Failed to get transaction: {}", e))),
+        TxOutStatus::Spent(spent) => {
+            info.spent = true;
+            info.spent_by_txid = Some(spent.spending_txid.into());
+            info.spent_by_input = Some(spent.input_index);
+            info.spent_height = Some(spent.height);
+        }
+        TxOutStatus::Unknown => return Err(ApiError::not_found(format!("Output {}:{} not found", txid, vout))),
     }
+    Ok(Json(ApiResponse::success(info)))
 }
 
-async fn get_raw_transaction(
+async fn scan_txout_set(
     State(state): State<AppState>,
-    Path(hash_str): Path<String>,
-) -> Json<ApiResponse<String>> {
-    let hash = match Hash256::from_hex(&hash_str) {
-        Ok(hash) => hash,
-        Err(_) => return Json(ApiResponse::error("Invalid transaction hash".to_string())),
-    };
+    ApiJson(req): ApiJson<ScanTxoutSetRequest>,
+) -> ApiResult<ScanResult> {
+    let result = read_chain(&state)?.scan_txout_set(&req.descriptors)
+        .map_err(|e| ApiError::from(e).context("UTXO scan failed"))?;
+    Ok(Json(ApiResponse::success(result)))
+}
+
+/// Validate `tx` into the mempool and relay it: the one way a transaction enters the
+/// node from REST, JSON-RPC and the control socket
+pub async fn submit_transaction(
+    blockchain: &RwLock<Blockchain>,
+    p2p_commands: Option<&mpsc::Sender<P2PCommand>>,
+    tx: Transaction,
+) -> Result<Hash256> {
+    let txid = blockchain.read()
+        .map_err(|_| QtcError::Blockchain("Failed to access blockchain".to_string()))?
+        .accept_to_mempool(tx.clone())?;
+    if let Some(p2p_commands) = p2p_commands {
+        let _ = p2p_commands.send(P2PCommand::BroadcastTransaction(tx)).await;
+    }
+    Ok(txid)
+}
+
+async fn send_transaction(
+    State(state): State<AppState>,
+    ApiJson(req): ApiJson<SendTransactionRequest>,
+) -> ApiResult<TxIdHex> {
+    let tx: Transaction = decode_raw(&req.raw_transaction, "transaction")?;
+    let txid = submit_transaction(&state.blockchain, state.p2p_commands.as_ref(), tx).await
+        .map_err(|e| match e {
+            QtcError::DoubleSpend(_) => ApiError::from(e).context("Transaction rejected"),
+            e => ApiError::bad_request(format!("Transaction rejected: {}", e)),
+        })?;
+    log::info!("📨 Transaction {} submitted over REST", txid);
+    Ok(Json(ApiResponse::success(txid.into())))
+}
+
+async fn submit_block(
+    State(state): State<AppState>,
+    ApiJson(req): ApiJson<RawBlockRequest>,
+) -> ApiResult<BlockInfo> {
+    let block: Block = decode_raw(&req.raw_block, "block")?;
+    let hash = block.hash();
     
-    match state.db.get_transaction(&hash) {
-        Ok(Some(tx)) => {
-            match bincode::serialize(&tx) {
-                Ok(raw_tx) => {
-                    let hex_tx = hex::encode(raw_tx);
-                    Json(ApiResponse::success(hex_tx))
-                }
-                Err(e) => Json(ApiResponse::error(format!("Failed to serialize transaction: {}", e))),
-            }
+    {
+        let mut blockchain = state.blockchain.write()
+            .map_err(|_| ApiError::internal("Failed to access blockchain"))?;
+        let known = blockchain.get_block(&hash)
+            .map_err(|e| ApiError::from(e).context("Failed to look up block"))?;
+        if known.is_some() {
+            return Err(ApiError::new(ErrorCode::Conflict, format!("Block {} is already known", hash)));
         }
-        Ok(None) => Json(ApiResponse::error("Transaction not found".to_string())),
-        Err(e) => Json(ApiResponse::error(format!("Failed to get transaction: {}", e))),
+        blockchain.add_block(block.clone())
+            .map_err(|e| ApiError::bad_request(format!("Block rejected: {}", e)))?;
     }
+    
+    log::info!("📦 Block {} submitted over REST", hash);
+    let info = BlockInfo::from_block(&block);
+    if let Some(p2p_commands) = &state.p2p_commands {
+        let _ = p2p_commands.send(P2PCommand::BroadcastBlock(block)).await;
+    }
+    Ok(Json(ApiResponse::success(info)))
 }
 
-async fn send_transaction(
+async fn decode_block(ApiJson(req): ApiJson<RawBlockRequest>) -> ApiResult<DecodedBlock> {
+    let block: Block = decode_raw(&req.raw_block, "block")?;
+    Ok(Json(ApiResponse::success(DecodedBlock::from_block(&block))))
+}
+
+async fn decode_transaction(ApiJson(req): ApiJson<SendTransactionRequest>) -> ApiResult<DecodedTransaction> {
+    let tx: Transaction = decode_raw(&req.raw_transaction, "transaction")?;
+    Ok(Json(ApiResponse::success(DecodedTransaction::from_transaction(&tx))))
+}
+
+fn load_wallet(state: &AppState, name: &str) -> std::result::Result<crate::wallet::Wallet, ApiError> {
+    state.db.load_wallet(name, state.blockchain.clone())
+        .map_err(|e| ApiError::not_found(format!("Failed to load wallet: {}", e)))
+}
+
+async fn get_wallet_status(
     State(state): State<AppState>,
-    Json(req): Json<SendTransactionRequest>,
-) -> Json<ApiResponse<String>> {
-    // Decode the raw transaction
-    let raw_bytes = match hex::decode(&req.raw_transaction) {
-        Ok(bytes) => bytes,
-        Err(_) => return Json(ApiResponse::error("Invalid hex encoding".to_string())),
-    };
+    ApiPath(name): ApiPath<String>,
+) -> ApiResult<WalletStatus> {
+    let wallet = load_wallet(&state, &name)?;
+    let (balance, gap) = wallet.get_balance()
+        .and_then(|balance| Ok((balance, wallet.address_gap()?)))
+        .map_err(|e| ApiError::from(e).context("Failed to read wallet status"))?;
     
-    let tx: Transaction = match bincode::deserialize(&raw_bytes) {
-        Ok(tx) => tx,
-        Err(e) => return Json(ApiResponse::error(format!("Failed to deserialize transaction: {}", e))),
-    };
+    Ok(Json(ApiResponse::success(WalletStatus {
+        name: wallet.info.name,
+        wallet_type: wallet.info.wallet_type,
+        balance,
+        address_count: wallet.info.address_count,
+        address_gap: gap,
+        gap_limit_exceeded: gap.exceeded(),
+    })))
+}
+
+async fn reserve_wallet_addresses(
+    State(state): State<AppState>,
+    ApiPath(name): ApiPath<String>,
+    ApiJson(req): ApiJson<ReserveAddressesRequest>,
+) -> std::result::Result<Response, ApiError> {
+    let mut wallet = load_wallet(&state, &name)?;
+    let reservations = wallet.reserve_receive_addresses(req.count, req.label)
+        .map_err(|e| ApiError::from(e).context("Failed to generate addresses"))?;
     
-    // Validate transaction
-    match state.blockchain.read() {
-        Ok(blockchain) => {
-            match blockchain.is_valid_transaction(&tx) {
-                Ok(true) => {
-                    // Save transaction to database (in real implementation, would add to mempool)
-                    if let Err(e) = state.db.save_transaction(&tx) {
-                        return Json(ApiResponse::error(format!("Failed to save transaction: {}", e)));
-                    }
-                    
-                    let tx_hash = tx.hash().to_hex();
-                    Json(ApiResponse::success(tx_hash))
-                }
-                Ok(false) => Json(ApiResponse::error("Invalid transaction".to_string())),
-                Err(e) => Json(ApiResponse::error(format!("Transaction validation failed: {}", e))),
-            }
-        }
-        Err(_) => Json(ApiResponse::error("Failed to access blockchain".to_string())),
+    Ok(match req.format.as_deref() {
+        Some("csv") => (
+            [(header::CONTENT_TYPE, "text/csv")],
+            crate::wallet::wallet::reservations_to_csv(&reservations),
+        ).into_response(),
+        _ => Json(ApiResponse::success(reservations)).into_response(),
+    })
+}
+
+async fn preview_wallet_transaction(
+    State(state): State<AppState>,
+    ApiPath(name): ApiPath<String>,
+    ApiJson(req): ApiJson<PreviewTransactionRequest>,
+) -> ApiResult<TransactionPreview> {
+    let wallet = load_wallet(&state, &name)?;
+    let preview = wallet.preview_transaction(req.to.as_str(), req.amount, req.fee_rate.unwrap_or(1000))
+        .map_err(|e| ApiError::from(e).context("Failed to preview transaction"))?;
+    Ok(Json(ApiResponse::success(preview)))
+}
+
+async fn get_faucet_info(State(faucet): State<Arc<Faucet>>) -> ApiResult<FaucetInfo> {
+    let info = faucet.info().map_err(|e| ApiError::from(e).context("Failed to read faucet wallet"))?;
+    Ok(Json(ApiResponse::success(info)))
+}
+
+async fn get_faucet_challenge(State(faucet): State<Arc<Faucet>>) -> ApiResult<FaucetChallenge> {
+    Ok(Json(ApiResponse::success(faucet.issue_challenge()?)))
+}
+
+async fn claim_faucet(
+    State(faucet): State<Arc<Faucet>>,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
+    ApiJson(claim): ApiJson<FaucetClaim>,
+) -> ApiResult<FaucetPayout> {
+    let ip = connect_info.map(|ConnectInfo(addr)| addr.ip());
+    Ok(Json(ApiResponse::success(faucet.claim(ip, &claim).await?)))
+}
+
+async fn list_api_keys(State(state): State<AppState>) -> ApiResult<Vec<ApiKeyInfo>> {
+    let keys = state.db.list_api_keys().map_err(|e| ApiError::from(e).context("Failed to list API keys"))?;
+    Ok(Json(ApiResponse::success(keys.iter().map(ApiKeyInfo::from).collect())))
+}
+
+async fn create_api_key(
+    State(state): State<AppState>,
+    Extension(ApiCaller(caller)): Extension<ApiCaller>,
+    ApiJson(req): ApiJson<CreateApiKeyRequest>,
+) -> ApiResult<CreatedApiKey> {
+    let scopes = req.scopes.iter().map(|s| s.parse()).collect::<Result<Vec<ApiScope>>>()?;
+    let (key, token) = auth::create_api_key(&state.db, &req.name, scopes, &caller)
+        .map_err(|e| ApiError::from(e).context("Failed to create API key"))?;
+    Ok(Json(ApiResponse::success(CreatedApiKey { key: ApiKeyInfo::from(&key), token })))
+}
+
+async fn revoke_api_key(
+    State(state): State<AppState>,
+    Extension(ApiCaller(caller)): Extension<ApiCaller>,
+    ApiPath(id): ApiPath<String>,
+) -> ApiResult<ApiKeyInfo> {
+    let key = auth::revoke_api_key(&state.db, &id, &caller)
+        .map_err(|e| ApiError::from(e).context("Failed to revoke API key"))?;
+    Ok(Json(ApiResponse::success(ApiKeyInfo::from(&key))))
+}
+
+async fn get_logging() -> ApiResult<LoggingInfo> {
+    let filter = crate::node::logging::current_filter()
+        .ok_or_else(|| ApiError::unavailable("Logging is not managed by this node"))?;
+    Ok(Json(ApiResponse::success(LoggingInfo { filter })))
+}
+
+async fn set_logging(ApiJson(req): ApiJson<LoggingRequest>) -> ApiResult<LoggingInfo> {
+    if crate::node::logging::current_filter().is_none() {
+        return Err(ApiError::unavailable("Logging is not managed by this node"));
     }
+    let filter = crate::node::logging::set_filter(&req.level, &req.modules)?;
+    Ok(Json(ApiResponse::success(LoggingInfo { filter })))
 }
 
 async fn get_address_info(
     State(state): State<AppState>,
-    Path(address): Path<String>,
-) -> Json<ApiResponse<AddressInfo>> {
-    if !crate::crypto::keys::is_valid_address(&address) {
-        return Json(ApiResponse::error("Invalid address".to_string()));
-    }
-    
-    match state.blockchain.read() {
-        Ok(blockchain) => {
-            match blockchain.get_balance(&address) {
-                Ok(balance) => {
-                    let info = AddressInfo {
-                        address: address.clone(),
-                        balance,
-                        transaction_count: 0, // Would be calculated in full implementation
-                        received: balance,    // Simplified
-                        sent: 0,             // Would be calculated in full implementation
-                    };
-                    Json(ApiResponse::success(info))
-                }
-                Err(e) => Json(ApiResponse::error(format!("Failed to get address info: {}", e))),
-            }
-        }
-        Err(_) => Json(ApiResponse::error("Failed to access blockchain".to_string())),
-    }
+    ApiPath(address): ApiPath<AddressStr>,
+) -> ApiResult<AddressInfo> {
+    let balance = with_snapshot(&state.blockchain, |snapshot| snapshot.get_balance(address.as_str()))
+        .map_err(|e| ApiError::from(e).context("Failed to get address info"))?;
+    Ok(Json(ApiResponse::success(AddressInfo {
+        address,
+        balance: AmountInfo::new(balance),
+        transaction_count: 0, // Would be calculated in full implementation
+        received: AmountInfo::new(balance), // Simplified
+        sent: AmountInfo::new(0),           // Would be calculated in full implementation
+    })))
 }
 
 async fn get_address_balance(
     State(state): State<AppState>,
-    Path(address): Path<String>,
-) -> Json<ApiResponse<u64>> {
-    if !crate::crypto::keys::is_valid_address(&address) {
-        return Json(ApiResponse::error("Invalid address".to_string()));
-    }
-    
-    match state.blockchain.read() {
-        Ok(blockchain) => {
-            match blockchain.get_balance(&address) {
-                Ok(balance) => Json(ApiResponse::success(balance)),
-                Err(e) => Json(ApiResponse::error(format!("Failed to get balance: {}", e))),
-            }
-        }
-        Err(_) => Json(ApiResponse::error("Failed to access blockchain".to_string())),
-    }
+    ApiPath(address): ApiPath<AddressStr>,
+) -> ApiResult<AmountInfo> {
+    let balance = with_snapshot(&state.blockchain, |snapshot| snapshot.get_balance(address.as_str()))
+        .map_err(|e| ApiError::from(e).context("Failed to get balance"))?;
+    Ok(Json(ApiResponse::success(AmountInfo::new(balance))))
 }
 
 async fn get_address_utxos(
     State(state): State<AppState>,
-    Path(address): Path<String>,
-) -> Json<ApiResponse<Vec<UtxoInfo>>> {
-    if !crate::crypto::keys::is_valid_address(&address) {
-        return Json(ApiResponse::error("Invalid address".to_string()));
-    }
-    
-    match state.blockchain.read() {
-        Ok(blockchain) => {
-            match blockchain.get_utxos(&address) {
-                Ok(utxos) => {
-                    let current_height = blockchain.height;
-                    let utxo_infos: Vec<UtxoInfo> = utxos.into_iter().map(|(txid, vout, value)| {
-                        UtxoInfo {
-                            txid: txid.to_hex(),
-                            vout,
-                            value,
-                            height: 0, // Would be looked up in full implementation
-                            confirmations: current_height, // Simplified
-                            is_coinbase: false, // Would be determined in full implementation
-                        }
-                    }).collect();
-                    
-                    Json(ApiResponse::success(utxo_infos))
-                }
-                Err(e) => Json(ApiResponse::error(format!("Failed to get UTXOs: {}", e))),
-            }
+    ApiPath(address): ApiPath<AddressStr>,
+) -> ApiResult<Vec<UtxoInfo>> {
+    let (utxos, current_height) = with_snapshot(&state.blockchain, |snapshot| {
+        let utxos = snapshot.get_utxos(address.as_str())?;
+        Ok((utxos, snapshot.height))
+    }).map_err(|e| ApiError::from(e).context("Failed to get UTXOs"))?;
+    
+    let utxo_infos: Vec<UtxoInfo> = utxos.into_iter().map(|(txid, vout, value)| {
+        UtxoInfo {
+            txid: txid.into(),
+            vout,
+            value: AmountInfo::new(value),
+            height: 0, // Would be looked up in full implementation
+            confirmations: current_height, // Simplified
+            is_coinbase: false, // Would be determined in full implementation
         }
-        Err(_) => Json(ApiResponse::error("Failed to access blockchain".to_string())),
-    }
+    }).collect();
+    
+    Ok(Json(ApiResponse::success(utxo_infos)))
 }
 
 async fn get_address_transactions(
-    State(_state): State<AppState>,
-    Path(_address): Path<String>,
-) -> Json<ApiResponse<Vec<String>>> {
-    // Transaction history lookup would be implemented here
-    Json(ApiResponse::success(Vec::new()))
+    State(state): State<AppState>,
+    ApiPath(address): ApiPath<AddressStr>,
+) -> ApiResult<Vec<TxIdHex>> {
+    if !state.address_index {
+        return Err(ApiError::not_found("Address history requires addrindex to be enabled"));
+    }
+    
+    let transactions = state.db.get_address_transactions(address.as_str(), 100)
+        .map_err(|e| ApiError::from(e).context("Failed to get address transactions"))?;
+    Ok(Json(ApiResponse::success(transactions.into_iter().map(|(hash, _, _)| hash.into()).collect())))
 }
 
 async fn get_mempool_info(State(_state): State<AppState>) -> Json<ApiResponse<MempoolInfo>> {
@@ -656,6 +1320,52 @@ async fn get_mempool_transactions(State(_state): State<AppState>) -> Json<ApiRes
     Json(ApiResponse::success(Vec::new()))
 }
 
+async fn dump_mempool(State(state): State<AppState>) -> ApiResult<MempoolDump> {
+    Ok(Json(ApiResponse::success(read_chain(&state)?.dump_mempool())))
+}
+
+async fn load_mempool(
+    State(state): State<AppState>,
+    ApiJson(dump): ApiJson<MempoolDump>,
+) -> ApiResult<MempoolLoadResult> {
+    let total = dump.entries.len();
+    let loaded = read_chain(&state)?.load_mempool(dump)
+        .map_err(|e| ApiError::from(e).context("Failed to load mempool"))?;
+    Ok(Json(ApiResponse::success(MempoolLoadResult {
+        loaded: loaded.len(),
+        skipped: total - loaded.len(),
+    })))
+}
+
+async fn get_projected_blocks(
+    State(state): State<AppState>,
+    Query(query): Query<ProjectedBlocksQuery>,
+) -> ApiResult<ProjectedBlocksInfo> {
+    let count = query.blocks.unwrap_or(8).clamp(1, 32);
+    
+    let blockchain = read_chain(&state)?;
+    let blocks = blockchain.projected_blocks(count)
+        .into_iter()
+        .enumerate()
+        .map(|(index, block)| ProjectedBlockInfo {
+            index,
+            transaction_count: block.txids.len(),
+            size: block.size,
+            total_fees: AmountInfo::new(block.total_fees),
+            min_fee_rate: block.min_fee_rate,
+            max_fee_rate: block.max_fee_rate,
+            txids: block.txids.iter().map(|txid| (*txid).into()).collect(),
+        })
+        .collect();
+    
+    let mempool = blockchain.mempool.read().unwrap();
+    Ok(Json(ApiResponse::success(ProjectedBlocksInfo {
+        mempool_size: mempool.len(),
+        blocks,
+        fee_histogram: mempool.fee_histogram(),
+    })))
+}
+
 async fn get_network_info(State(_state): State<AppState>) -> Json<ApiResponse<NetworkInfo>> {
     let info = NetworkInfo {
         version: "1.0.0".to_string(),
@@ -669,41 +1379,93 @@ async fn get_network_info(State(_state): State<AppState>) -> Json<ApiResponse<Ne
     Json(ApiResponse::success(info))
 }
 
+async fn get_network_versions(State(state): State<AppState>) -> ApiResult<VersionSummary> {
+    let peer_versions = state.peer_versions.as_ref()
+        .ok_or_else(|| ApiError::unavailable("P2P networking is not running"))?;
+    Ok(Json(ApiResponse::success(peer_versions.summary())))
+}
+
+async fn get_network_diversity(State(state): State<AppState>) -> ApiResult<DiversityStats> {
+    let peer_diversity = state.peer_diversity.as_ref()
+        .ok_or_else(|| ApiError::unavailable("P2P networking is not running"))?;
+    Ok(Json(ApiResponse::success(peer_diversity.stats())))
+}
+
+fn ban_list(state: &AppState) -> std::result::Result<&Arc<BanList>, ApiError> {
+    state.bans.as_ref().ok_or_else(|| ApiError::unavailable("P2P networking is not running"))
+}
+
+async fn list_peer_bans(State(state): State<AppState>) -> ApiResult<Vec<Ban>> {
+    Ok(Json(ApiResponse::success(ban_list(&state)?.list())))
+}
+
+async fn ban_peer(
+    State(state): State<AppState>,
+    Extension(ApiCaller(caller)): Extension<ApiCaller>,
+    ApiJson(req): ApiJson<BanPeerRequest>,
+) -> ApiResult<Ban> {
+    let duration = Duration::from_secs(req.duration_secs.unwrap_or(DEFAULT_BAN_DURATION_SECS));
+    let reason = req.reason.as_deref().unwrap_or("banned by operator");
+    let ban = ban_list(&state)?.ban(&req.target, duration, reason, &caller)
+        .map_err(|e| ApiError::from(e).context("Failed to ban peer"))?;
+    log::info!("🚫 Banned {} until {}: {}", ban.target, ban.banned_until, ban.reason);
+    Ok(Json(ApiResponse::success(ban)))
+}
+
+async fn unban_peer(
+    State(state): State<AppState>,
+    Extension(ApiCaller(caller)): Extension<ApiCaller>,
+    ApiPath(target): ApiPath<String>,
+) -> ApiResult<String> {
+    if !ban_list(&state)?.unban(&target, &caller).map_err(|e| ApiError::from(e).context("Failed to unban peer"))? {
+        return Err(ApiError::not_found(format!("{} is not banned", target)));
+    }
+    log::info!("✅ Lifted the ban on {}", target);
+    Ok(Json(ApiResponse::success(target)))
+}
+
 async fn get_peers(State(_state): State<AppState>) -> Json<ApiResponse<Vec<HashMap<String, serde_json::Value>>>> {
     // Peer information would be fetched from P2P layer
     Json(ApiResponse::success(Vec::new()))
 }
 
-async fn get_mining_info(State(state): State<AppState>) -> Json<ApiResponse<MiningInfo>> {
-    match state.blockchain.read() {
-        Ok(blockchain) => {
-            let chain_info = blockchain.get_chain_info().unwrap_or_default();
-            
-            let info = MiningInfo {
-                blocks: chain_info.height,
-                difficulty: chain_info.difficulty,
-                network_hashrate: 0.0, // Would be calculated
-                pooled_tx: 0, // Mempool size
-                chain: "qtc".to_string(),
-                warnings: Vec::new(),
-            };
-            
-            Json(ApiResponse::success(info))
-        }
-        Err(_) => Json(ApiResponse::error("Failed to access blockchain".to_string())),
+async fn get_mining_info(State(state): State<AppState>) -> ApiResult<MiningInfo> {
+    let blockchain = read_chain(&state)?;
+    let chain_info = blockchain.get_chain_info().unwrap_or_default();
+    
+    let mut warnings = Vec::new();
+    if blockchain.is_low_work() {
+        warnings.push("Chain work is below minimum_chain_work; tip is not trusted until sync completes".to_string());
     }
+    
+    Ok(Json(ApiResponse::success(MiningInfo {
+        blocks: chain_info.height,
+        difficulty: chain_info.difficulty(),
+        network_hashrate: 0.0, // Would be calculated
+        pooled_tx: 0, // Mempool size
+        chain: "qtc".to_string(),
+        warnings,
+    })))
 }
 
-async fn get_difficulty(State(state): State<AppState>) -> Json<ApiResponse<u32>> {
-    match state.blockchain.read() {
-        Ok(blockchain) => {
-            match blockchain.get_current_difficulty() {
-                Ok(difficulty) => Json(ApiResponse::success(difficulty)),
-                Err(e) => Json(ApiResponse::error(format!("Failed to get difficulty: {}", e))),
-            }
-        }
-        Err(_) => Json(ApiResponse::error("Failed to access blockchain".to_string())),
+async fn get_difficulty(State(state): State<AppState>) -> ApiResult<f64> {
+    let difficulty = read_chain(&state)?.get_current_difficulty()
+        .map_err(|e| ApiError::from(e).context("Failed to get difficulty"))?;
+    Ok(Json(ApiResponse::success(difficulty)))
+}
+
+async fn get_block_template(
+    State(state): State<AppState>,
+    Query(query): Query<BlockTemplateQuery>,
+) -> ApiResult<BlockTemplate> {
+    let mut builder = BlockTemplateBuilder::new().with_min_fee_rate(query.min_fee_rate.unwrap_or(0));
+    if let Some(max_size) = query.max_size {
+        builder = builder.with_max_size(max_size);
     }
+    
+    let template = wait_for_template(&state.blockchain, &builder, query.longpollid.as_deref()).await
+        .map_err(|e| ApiError::from(e).context("Failed to build block template"))?;
+    Ok(Json(ApiResponse::success(template)))
 }
 
 async fn validate_address(
@@ -723,11 +1485,19 @@ async fn validate_address(
     Json(ApiResponse::success(result))
 }
 
-async fn estimate_fee(State(_state): State<AppState>) -> Json<ApiResponse<HashMap<String, u64>>> {
+async fn estimate_fee(
+    State(state): State<AppState>,
+    Query(query): Query<FeeEstimateQuery>,
+) -> ApiResult<HashMap<String, FeeEstimate>> {
+    let blockchain = read_chain(&state)?;
+    
     let mut fees = HashMap::new();
-    fees.insert("fast".to_string(), 5000);     // 5000 sat/byte
-    fees.insert("medium".to_string(), 2000);   // 2000 sat/byte
-    fees.insert("slow".to_string(), 1000);     // 1000 sat/byte
+    fees.insert("fast".to_string(), blockchain.estimate_fee(1));
+    fees.insert("medium".to_string(), blockchain.estimate_fee(6));
+    fees.insert("slow".to_string(), blockchain.estimate_fee(24));
+    if let Some(blocks) = query.blocks {
+        fees.insert("requested".to_string(), blockchain.estimate_fee(blocks));
+    }
     
-    Json(ApiResponse::success(fees))
+    Ok(Json(ApiResponse::success(fees)))
 }
@@ -0,0 +1,176 @@
+//! Typed identifiers for API request and response bodies
+//!
+//! Hashes and addresses cross the API as strings. Wrapping them keeps a block
+//! hash from being passed where a txid belongs, and checks them while they are
+//! deserialized so handlers never see a malformed one. `ApiPath` and `ApiJson`
+//! answer a rejected identifier with a 400 carrying the usual `ApiResponse`
+//! body instead of axum's plain-text rejection.
+
+use crate::api::error::ApiError;
+use crate::crypto::hash::Hash256;
+use crate::crypto::keys::is_valid_address;
+use crate::QtcError;
+use axum::async_trait;
+use axum::extract::{FromRequest, FromRequestParts, Path, Request};
+use axum::http::request::Parts;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+macro_rules! hash_hex {
+    ($name:ident, $what:literal) => {
+        /// A 32-byte hash written as 64 hex characters
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+        #[serde(try_from = "String", into = "String")]
+        pub struct $name(Hash256);
+
+        impl $name {
+            pub fn hash(&self) -> Hash256 {
+                self.0
+            }
+        }
+
+        impl From<Hash256> for $name {
+            fn from(hash: Hash256) -> Self {
+                Self(hash)
+            }
+        }
+
+        impl From<$name> for String {
+            fn from(value: $name) -> Self {
+                value.0.to_hex()
+            }
+        }
+
+        impl FromStr for $name {
+            type Err = QtcError;
+
+            fn from_str(s: &str) -> Result<Self, Self::Err> {
+                Hash256::from_hex(s)
+                    .map(Self)
+                    .map_err(|_| QtcError::InvalidInput(format!("{} must be 64 hex characters", $what)))
+            }
+        }
+
+        impl TryFrom<String> for $name {
+            type Error = QtcError;
+
+            fn try_from(s: String) -> Result<Self, Self::Error> {
+                s.parse()
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str(&self.0.to_hex())
+            }
+        }
+    };
+}
+
+hash_hex!(BlockHashHex, "block hash");
+hash_hex!(TxIdHex, "transaction id");
+
+/// A QTC address that passed `is_valid_address`
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct AddressStr(String);
+
+impl AddressStr {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<AddressStr> for String {
+    fn from(address: AddressStr) -> Self {
+        address.0
+    }
+}
+
+impl FromStr for AddressStr {
+    type Err = QtcError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if !is_valid_address(s) {
+            return Err(QtcError::InvalidInput(format!("{} is not a valid address", s)));
+        }
+        Ok(Self(s.to_string()))
+    }
+}
+
+impl TryFrom<String> for AddressStr {
+    type Error = QtcError;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl fmt::Display for AddressStr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+fn bad_request(message: String) -> Response {
+    ApiError::bad_request(message).into_response()
+}
+
+/// `Path`, rejecting malformed segments with a JSON 400
+#[derive(Debug)]
+pub struct ApiPath<T>(pub T);
+
+#[async_trait]
+impl<S, T> FromRequestParts<S> for ApiPath<T>
+where
+    S: Send + Sync,
+    T: DeserializeOwned + Send,
+{
+    type Rejection = Response;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        Path::<T>::from_request_parts(parts, state).await
+            .map(|Path(value)| ApiPath(value))
+            .map_err(|rejection| bad_request(rejection.body_text()))
+    }
+}
+
+/// `Json`, rejecting malformed bodies with a JSON 400
+#[derive(Debug)]
+pub struct ApiJson<T>(pub T);
+
+#[async_trait]
+impl<S, T> FromRequest<S> for ApiJson<T>
+where
+    S: Send + Sync,
+    T: DeserializeOwned,
+{
+    type Rejection = Response;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        Json::<T>::from_request(req, state).await
+            .map(|Json(value)| ApiJson(value))
+            .map_err(|rejection| bad_request(rejection.body_text()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_identifiers_validate_on_deserialize() {
+        let hash = Hash256::hash(b"block");
+        let json = serde_json::to_string(&BlockHashHex::from(hash)).unwrap();
+        assert_eq!(json, format!("\"{}\"", hash.to_hex()));
+        assert_eq!(serde_json::from_str::<TxIdHex>(&json).unwrap().hash(), hash);
+
+        assert!(serde_json::from_str::<TxIdHex>("\"abcd\"").is_err());
+        assert!(serde_json::from_str::<BlockHashHex>("\"not hex\"").is_err());
+        assert!(serde_json::from_str::<AddressStr>("\"nope\"").is_err());
+    }
+}
@@ -0,0 +1,178 @@
+//! Outgoing webhooks: node events are POSTed as JSON to configured URLs
+//!
+//! Bodies are canonical JSON. With a secret configured, each delivery carries
+//! `X-QTC-Signature: sha256=<hex>`, an HMAC-SHA256 of the exact body bytes.
+
+use crate::crypto::canonical::to_canonical_vec;
+use crate::mining::BlockMinedEvent;
+use crate::network::partition::PartitionAlert;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::broadcast;
+
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub const SIGNATURE_HEADER: &str = "X-QTC-Signature";
+
+/// Envelope shared by every webhook delivery
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookPayload<T> {
+    pub event: String,
+    pub timestamp: u64,
+    pub data: T,
+}
+
+#[derive(Debug, Clone)]
+pub struct WebhookNotifier {
+    client: reqwest::Client,
+    urls: Vec<String>,
+    secret: Option<String>,
+}
+
+impl WebhookNotifier {
+    pub fn new(urls: Vec<String>) -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(WEBHOOK_TIMEOUT)
+            .build()
+            .unwrap_or_default();
+
+        Self { client, urls, secret: None }
+    }
+
+    /// Sign every delivery with `secret`
+    pub fn set_secret(&mut self, secret: String) {
+        self.secret = Some(secret);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.urls.is_empty()
+    }
+
+    /// Deliver `data` to every endpoint; failures are logged and not retried
+    pub async fn notify<T: Serialize>(&self, event: &str, data: T) {
+        let payload = WebhookPayload {
+            event: event.to_string(),
+            timestamp: chrono::Utc::now().timestamp() as u64,
+            data,
+        };
+        let body = match to_canonical_vec(&payload) {
+            Ok(body) => body,
+            Err(e) => {
+                log::warn!("🪝 Failed to encode {} webhook: {}", event, e);
+                return;
+            }
+        };
+        let signature = self.secret.as_ref().map(|secret| sign_payload(secret, &body));
+
+        for url in &self.urls {
+            let mut request = self.client.post(url)
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .body(body.clone());
+            if let Some(signature) = &signature {
+                request = request.header(SIGNATURE_HEADER, signature);
+            }
+            match request.send().await {
+                Ok(response) if response.status().is_success() => {
+                    log::debug!("🪝 Delivered {} webhook to {}", event, url);
+                }
+                Ok(response) => {
+                    log::warn!("🪝 Webhook {} returned {} for {}", url, response.status(), event);
+                }
+                Err(e) => {
+                    log::warn!("🪝 Failed to deliver {} webhook to {}: {}", event, url, e);
+                }
+            }
+        }
+    }
+
+    /// Forward `block_mined` events from the local miner until it goes away
+    pub fn relay_block_mined(self: Arc<Self>, events: broadcast::Receiver<BlockMinedEvent>) -> tokio::task::JoinHandle<()> {
+        self.relay("block_mined", events)
+    }
+
+    /// Forward the P2P node's `possible_partition` alerts
+    pub fn relay_partition_alerts(self: Arc<Self>, alerts: broadcast::Receiver<PartitionAlert>) -> tokio::task::JoinHandle<()> {
+        self.relay("possible_partition", alerts)
+    }
+
+    fn relay<T>(self: Arc<Self>, event: &'static str, mut events: broadcast::Receiver<T>) -> tokio::task::JoinHandle<()>
+    where
+        T: Serialize + Clone + Send + 'static,
+    {
+        tokio::spawn(async move {
+            loop {
+                match events.recv().await {
+                    Ok(data) => self.notify(event, data).await,
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        log::warn!("🪝 Webhook relay skipped {} {} events", skipped, event);
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                }
+            }
+        })
+    }
+}
+
+/// The `X-QTC-Signature` value for `body`; receivers recompute it over the raw request body
+pub fn sign_payload(secret: &str, body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())
+        .expect("HMAC accepts keys of any length");
+    mac.update(body);
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Bytes, extract::State, http::HeaderMap, routing::post, Router};
+    use tokio::sync::mpsc;
+
+    #[tokio::test]
+    async fn test_block_mined_webhook_delivery() {
+        let (received_tx, mut received_rx) = mpsc::unbounded_channel();
+        let app = Router::new()
+            .route("/hook", post(|State(tx): State<mpsc::UnboundedSender<(Option<String>, Bytes)>>,
+                                  headers: HeaderMap, body: Bytes| async move {
+                let signature = headers.get(SIGNATURE_HEADER).and_then(|value| value.to_str().ok());
+                let _ = tx.send((signature.map(str::to_string), body));
+            }))
+            .with_state(received_tx);
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let mut notifier = WebhookNotifier::new(vec![format!("http://{}/hook", addr)]);
+        notifier.set_secret("hook secret".to_string());
+        let notifier = Arc::new(notifier);
+        let (events_tx, events_rx) = broadcast::channel(4);
+        let relay = notifier.relay_block_mined(events_rx);
+
+        events_tx.send(BlockMinedEvent {
+            hash: "00ab".to_string(),
+            height: 42,
+            reward: 2_710_000_000,
+            time_to_find_secs: 12.5,
+            timestamp: 1_700_000_000,
+        }).unwrap();
+
+        let (signature, body) = tokio::time::timeout(Duration::from_secs(5), received_rx.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(signature, Some(sign_payload("hook secret", &body)));
+        assert_ne!(signature, Some(sign_payload("other secret", &body)));
+        assert_eq!(body, to_canonical_vec(&serde_json::from_slice::<serde_json::Value>(&body).unwrap()).unwrap());
+
+        let payload: WebhookPayload<BlockMinedEvent> = serde_json::from_slice(&body).unwrap();
+        assert_eq!(payload.event, "block_mined");
+        assert_eq!(payload.data.height, 42);
+        assert_eq!(payload.data.reward, 2_710_000_000);
+
+        drop(events_tx);
+        relay.await.unwrap();
+    }
+}
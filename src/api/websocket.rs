@@ -1,5 +1,11 @@
-use crate::core::{Blockchain, Transaction};
-use crate::crypto::hash::Hashable;
+use crate::api::auth::{require_scope, ScopeGuard};
+use crate::api::ratelimit::{rate_limit_middleware, RateLimiter};
+use crate::api::types::{BlockHashHex, TxIdHex};
+use crate::consensus::target::Target;
+use crate::core::mempool::MempoolEntry;
+use crate::core::{Block, Blockchain, ChainEvent, Transaction};
+use crate::crypto::hash::{Hash256, Hashable};
+use crate::mining::BlockMinedEvent;
 
 use crate::{QtcError, Result};
 use axum::{
@@ -7,12 +13,14 @@ use axum::{
         ws::{WebSocket, WebSocketUpgrade},
         State,
     },
+    middleware,
     response::Response,
     routing::get,
     Router,
 };
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
 use std::sync::{Arc, RwLock};
 use tokio::sync::{broadcast, mpsc};
 use futures_util::{SinkExt, StreamExt};
@@ -25,11 +33,30 @@ pub enum WebSocketEvent {
         block: BlockNotification,
     },
     
-    #[serde(rename = "new_transaction")]
-    NewTransaction {
+    /// A transaction entered the mempool
+    #[serde(rename = "tx_accepted")]
+    TxAccepted {
         transaction: TransactionNotification,
     },
     
+    /// A transaction left the mempool unconfirmed: replaced, or in conflict with a block
+    #[serde(rename = "tx_removed")]
+    TxRemoved {
+        txid: TxIdHex,
+    },
+    
+    #[serde(rename = "tx_confirmed")]
+    TxConfirmed {
+        txid: TxIdHex,
+        block_hash: BlockHashHex,
+        height: u64,
+    },
+    
+    #[serde(rename = "block_mined")]
+    BlockMined {
+        block: BlockMinedEvent,
+    },
+    
     #[serde(rename = "mempool_update")]
     MempoolUpdate {
         size: usize,
@@ -39,7 +66,8 @@ pub enum WebSocketEvent {
     #[serde(rename = "difficulty_update")]
     DifficultyUpdate {
         height: u64,
-        difficulty: u32,
+        bits: u32,
+        difficulty: f64,
         network_hashrate: f64,
     },
     
@@ -67,10 +95,11 @@ pub enum WebSocketEvent {
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BlockNotification {
-    pub hash: String,
+    pub hash: BlockHashHex,
     pub height: u64,
     pub timestamp: u64,
-    pub difficulty: u32,
+    pub bits: u32,
+    pub difficulty: f64,
     pub size: usize,
     pub transaction_count: usize,
     pub miner: Option<String>,
@@ -78,15 +107,122 @@ pub struct BlockNotification {
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TransactionNotification {
-    pub hash: String,
+    pub hash: TxIdHex,
     pub size: usize,
-    pub fee: u64,
-    pub fee_rate: u64,
+    pub fee: Option<u64>, // known only while the transaction is in the mempool
+    pub fee_rate: Option<u64>,
     pub input_count: usize,
     pub output_count: usize,
     pub value: u64,
 }
 
+impl BlockNotification {
+    pub fn from_block(block: &Block) -> Self {
+        Self {
+            hash: block.hash().into(),
+            height: block.header.height,
+            timestamp: block.header.timestamp,
+            bits: block.header.bits,
+            difficulty: Target::from_bits(block.header.bits).difficulty(),
+            size: block.size(),
+            transaction_count: block.transactions.len(),
+            miner: block.get_coinbase_transaction()
+                .and_then(|tx| tx.outputs.first())
+                .map(|_| "Unknown".to_string()), // Would extract miner address
+        }
+    }
+}
+
+impl TransactionNotification {
+    /// With the fee the mempool worked out from the spent outputs
+    pub fn from_mempool_entry(entry: &MempoolEntry) -> Self {
+        Self {
+            hash: entry.txid.into(),
+            size: entry.size,
+            fee: Some(entry.fee),
+            fee_rate: Some(entry.fee_rate),
+            input_count: entry.tx.inputs.len(),
+            output_count: entry.tx.outputs.len(),
+            value: entry.tx.total_output_value(),
+        }
+    }
+    
+    /// Without a fee, which takes the outputs it spends to work out
+    pub fn from_transaction(tx: &Transaction) -> Self {
+        Self {
+            hash: tx.hash().into(),
+            size: tx.size(),
+            fee: None,
+            fee_rate: None,
+            input_count: tx.inputs.len(),
+            output_count: tx.outputs.len(),
+            value: tx.total_output_value(),
+        }
+    }
+}
+
+/// Turns the chain's events into the ones WebSocket clients are sent. A
+/// connected block reports its transactions as confirmed, so the mempool
+/// removals that follow for them aren't reported again as `tx_removed`.
+#[derive(Debug)]
+pub struct ChainEventTranslator {
+    confirmed: HashSet<Hash256>, // transactions of the last connected block
+    bits: u32,
+}
+
+impl ChainEventTranslator {
+    pub fn new(blockchain: &Blockchain) -> Self {
+        Self {
+            confirmed: HashSet::new(),
+            bits: blockchain.get_current_bits().unwrap_or(0),
+        }
+    }
+    
+    pub fn translate(&mut self, event: ChainEvent, blockchain: &Blockchain) -> Vec<WebSocketEvent> {
+        match event {
+            ChainEvent::BlockConnected { block, .. } => {
+                let block_hash = block.hash();
+                let txids: Vec<Hash256> = block.transactions.iter()
+                    .filter(|tx| !tx.is_coinbase())
+                    .map(|tx| tx.hash())
+                    .collect();
+                
+                let mut events = vec![WebSocketEvent::NewBlock { block: BlockNotification::from_block(&block) }];
+                events.extend(txids.iter().map(|txid| WebSocketEvent::TxConfirmed {
+                    txid: (*txid).into(),
+                    block_hash: block_hash.into(),
+                    height: block.header.height,
+                }));
+                self.confirmed = txids.into_iter().collect();
+                
+                if let Ok(bits) = blockchain.get_current_bits() {
+                    if bits != self.bits {
+                        events.push(WebSocketEvent::DifficultyUpdate {
+                            height: block.header.height,
+                            bits,
+                            difficulty: Target::from_bits(bits).difficulty(),
+                            network_hashrate: 0.0, // Would be calculated
+                        });
+                        self.bits = bits;
+                    }
+                }
+                events
+            }
+            ChainEvent::TransactionAdded(tx) => {
+                // Gone again already if it was replaced or confirmed before we got here
+                let transaction = blockchain.mempool.read().unwrap().get(&tx.hash())
+                    .map(TransactionNotification::from_mempool_entry)
+                    .unwrap_or_else(|| TransactionNotification::from_transaction(&tx));
+                vec![WebSocketEvent::TxAccepted { transaction }]
+            }
+            ChainEvent::TransactionRemoved(txid) if self.confirmed.contains(&txid) => Vec::new(),
+            ChainEvent::TransactionRemoved(txid) => vec![WebSocketEvent::TxRemoved { txid: txid.into() }],
+            // Its transactions come back as `tx_accepted` when they return to the mempool
+            ChainEvent::BlockDisconnected { .. } => Vec::new(),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type")]
 pub enum WebSocketRequest {
@@ -126,8 +262,11 @@ pub struct WebSocketState {
 pub struct WebSocketServer {
     blockchain: Arc<RwLock<Blockchain>>,
     port: u16,
+    bind_address: IpAddr,
     event_sender: broadcast::Sender<WebSocketEvent>,
     clients: Arc<RwLock<HashMap<String, WebSocketClient>>>,
+    auth: Option<Arc<ScopeGuard>>,
+    rate_limiter: Option<Arc<RateLimiter>>,
 }
 
 impl WebSocketServer {
@@ -138,11 +277,28 @@ impl WebSocketServer {
         Self {
             blockchain,
             port,
+            bind_address: IpAddr::V4(Ipv4Addr::UNSPECIFIED),
             event_sender,
             clients,
+            auth: None,
+            rate_limiter: None,
         }
     }
     
+    pub fn set_bind_address(&mut self, bind_address: IpAddr) {
+        self.bind_address = bind_address;
+    }
+    
+    /// Require a key before upgrading to a WebSocket; `/ws/health` stays open
+    pub fn set_auth(&mut self, guard: ScopeGuard) {
+        self.auth = Some(Arc::new(guard));
+    }
+    
+    /// Limit WebSocket handshakes per client IP, like the REST API's requests
+    pub fn set_rate_limit(&mut self, requests_per_minute: u32) {
+        self.rate_limiter = (requests_per_minute > 0).then(|| Arc::new(RateLimiter::new(requests_per_minute)));
+    }
+    
     pub async fn start(self) -> Result<()> {
         log::info!("🔌 Starting QTC WebSocket server on port {}", self.port);
         
@@ -152,17 +308,26 @@ impl WebSocketServer {
             clients: self.clients.clone(),
         };
         
+        // The rate limit goes outside the key check so guessing keys costs tokens too
+        let mut upgrade = Router::new().route("/ws", get(websocket_handler));
+        if let Some(guard) = &self.auth {
+            upgrade = upgrade.route_layer(middleware::from_fn_with_state(guard.clone(), require_scope));
+        }
+        if let Some(limiter) = &self.rate_limiter {
+            upgrade = upgrade.route_layer(middleware::from_fn_with_state(limiter.clone(), rate_limit_middleware));
+        }
         let app = Router::new()
-            .route("/ws", get(websocket_handler))
             .route("/ws/health", get(websocket_health))
+            .merge(upgrade)
             .with_state(state.clone());
         
         // Start background tasks
-        let heartbeat_task = self.start_heartbeat_task(state.clone());
-        let cleanup_task = self.start_cleanup_task(state.clone());
-        let blockchain_monitor_task = self.start_blockchain_monitor(state.clone());
+        let mut heartbeat_task = self.start_heartbeat_task(state.clone()).await;
+        let mut cleanup_task = self.start_cleanup_task(state.clone()).await;
+        let mut chain_event_task = self.start_chain_event_relay(state.clone()).await;
+        let pruner = self.rate_limiter.clone().map(RateLimiter::spawn_pruner);
         
-        let addr = format!("0.0.0.0:{}", self.port);
+        let addr = SocketAddr::new(self.bind_address, self.port);
         let listener = tokio::net::TcpListener::bind(&addr).await
             .map_err(|e| QtcError::Network(format!("Failed to bind to {}: {}", addr, e)))?;
         
@@ -170,22 +335,31 @@ impl WebSocketServer {
         
         // Run all tasks concurrently
         tokio::select! {
-            result = axum::serve(listener, app) => {
+            // Client addresses are needed for per-IP rate limiting
+            result = axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>()) => {
                 if let Err(e) = result {
                     log::error!("WebSocket server error: {}", e);
                 }
             }
-            _ = heartbeat_task => {
+            _ = &mut heartbeat_task => {
                 log::info!("Heartbeat task completed");
             }
-            _ = cleanup_task => {
+            _ = &mut cleanup_task => {
                 log::info!("Cleanup task completed");
             }
-            _ = blockchain_monitor_task => {
-                log::info!("Blockchain monitor task completed");
+            _ = &mut chain_event_task => {
+                log::info!("Chain event relay completed");
             }
         }
         
+        // Don't leave helpers behind for a restarted server to duplicate
+        heartbeat_task.abort();
+        cleanup_task.abort();
+        chain_event_task.abort();
+        if let Some(pruner) = pruner {
+            pruner.abort();
+        }
+        
         Ok(())
     }
     
@@ -234,80 +408,53 @@ impl WebSocketServer {
         })
     }
     
-    async fn start_blockchain_monitor(&self, state: WebSocketState) -> tokio::task::JoinHandle<()> {
+    /// Forward blocks and mempool changes to clients as the chain reports them
+    async fn start_chain_event_relay(&self, state: WebSocketState) -> tokio::task::JoinHandle<()> {
         let blockchain = self.blockchain.clone();
+        let (mut events, mut translator) = {
+            let blockchain = blockchain.read().unwrap();
+            (blockchain.subscribe_events(), ChainEventTranslator::new(&blockchain))
+        };
         
         tokio::spawn(async move {
-            let mut last_height = 0u64;
-            let mut last_difficulty = 0u32;
-            let mut interval = tokio::time::interval(tokio::time::Duration::from_secs(5));
-            
             loop {
-                interval.tick().await;
-                
-                if let Ok(blockchain) = blockchain.read() {
-                    let current_height = blockchain.height;
-                    
-                    // Check for new blocks
-                    if current_height > last_height {
-                        if let Ok(Some(block)) = blockchain.get_block_by_height(current_height) {
-                            let notification = WebSocketEvent::NewBlock {
-                                block: BlockNotification {
-                                    hash: block.hash().to_hex(),
-                                    height: block.header.height,
-                                    timestamp: block.header.timestamp,
-                                    difficulty: block.header.difficulty,
-                                    size: block.size(),
-                                    transaction_count: block.transactions.len(),
-                                    miner: block.get_coinbase_transaction()
-                                        .and_then(|tx| tx.outputs.first())
-                                        .map(|_| "Unknown".to_string()), // Would extract miner address
-                                },
-                            };
-                            
-                            if let Err(e) = state.event_sender.send(notification) {
-                                log::debug!("Failed to send new block notification: {}", e);
-                            }
-                        }
-                        last_height = current_height;
-                    }
-                    
-                    // Check for difficulty changes
-                    if let Ok(current_difficulty) = blockchain.get_current_difficulty() {
-                        if current_difficulty != last_difficulty && last_difficulty != 0 {
-                            let notification = WebSocketEvent::DifficultyUpdate {
-                                height: current_height,
-                                difficulty: current_difficulty,
-                                network_hashrate: 0.0, // Would be calculated
-                            };
-                            
-                            if let Err(e) = state.event_sender.send(notification) {
-                                log::debug!("Failed to send difficulty update: {}", e);
-                            }
-                        }
-                        last_difficulty = current_difficulty;
+                let event = match events.recv().await {
+                    Ok(event) => event,
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        log::warn!("WebSocket relay skipped {} chain events", skipped);
+                        continue;
                     }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                };
+                
+                let notifications = translator.translate(event, &blockchain.read().unwrap());
+                for notification in notifications {
+                    // No clients connected is fine
+                    let _ = state.event_sender.send(notification);
                 }
             }
         })
     }
     
-    pub fn broadcast_transaction(&self, tx: &Transaction) {
-        let notification = WebSocketEvent::NewTransaction {
-            transaction: TransactionNotification {
-                hash: tx.hash().to_hex(),
-                size: tx.size(),
-                fee: tx.fee(),
-                fee_rate: if tx.size() > 0 { tx.fee() / tx.size() as u64 } else { 0 },
-                input_count: tx.inputs.len(),
-                output_count: tx.outputs.len(),
-                value: tx.total_output_value(),
-            },
-        };
+    /// Relay blocks found by the local miner to connected clients
+    pub fn relay_block_mined(&self, mut events: broadcast::Receiver<BlockMinedEvent>) -> tokio::task::JoinHandle<()> {
+        let event_sender = self.event_sender.clone();
         
-        if let Err(e) = self.event_sender.send(notification) {
-            log::debug!("Failed to broadcast transaction: {}", e);
-        }
+        tokio::spawn(async move {
+            loop {
+                match events.recv().await {
+                    Ok(block) => {
+                        if let Err(e) = event_sender.send(WebSocketEvent::BlockMined { block }) {
+                            log::debug!("Failed to broadcast block_mined event: {}", e);
+                        }
+                    }
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        log::warn!("WebSocket relay skipped {} block_mined events", skipped);
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                }
+            }
+        })
     }
     
     pub fn broadcast_mempool_update(&self, size: usize, fee_rate: u64) {
@@ -393,7 +540,7 @@ async fn handle_websocket(socket: WebSocket, state: WebSocketState) {
                     // Check if client is subscribed to this event type
                     let should_send = match &event {
                         WebSocketEvent::NewBlock { .. } => true,
-                        WebSocketEvent::NewTransaction { .. } => true,
+                        WebSocketEvent::TxAccepted { .. } => true,
                         WebSocketEvent::Heartbeat { .. } => true,
                         _ => true, // Send all events for now
                     };
@@ -522,7 +669,8 @@ async fn handle_client_message(
                         // Create a status event (using difficulty update format)
                         let status = WebSocketEvent::DifficultyUpdate {
                             height: chain_info.height,
-                            difficulty: chain_info.difficulty,
+                            bits: chain_info.bits,
+                            difficulty: chain_info.difficulty(),
                             network_hashrate: 0.0,
                         };
                         
@@ -571,14 +719,88 @@ mod tests {
         assert_eq!(server.port, 0);
     }
     
+    #[tokio::test]
+    async fn test_block_mined_relay() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Arc::new(Database::new(temp_dir.path().join("test.db")).unwrap());
+        let blockchain = Arc::new(RwLock::new(Blockchain::new(db).unwrap()));
+        
+        let server = WebSocketServer::new(blockchain, 0);
+        let mut client = server.event_sender.subscribe();
+        let (miner_tx, miner_rx) = broadcast::channel(4);
+        let relay = server.relay_block_mined(miner_rx);
+        
+        miner_tx.send(BlockMinedEvent {
+            hash: "00ab".to_string(),
+            height: 7,
+            reward: 2_710_000_000,
+            time_to_find_secs: 3.0,
+            timestamp: 1_700_000_000,
+        }).unwrap();
+        
+        let event = client.recv().await.unwrap();
+        let serialized = serde_json::to_string(&event).unwrap();
+        assert!(serialized.contains("\"type\":\"block_mined\""));
+        assert!(serialized.contains("time_to_find_secs"));
+        
+        drop(miner_tx);
+        relay.await.unwrap();
+    }
+    
+    #[test]
+    fn test_chain_events_become_mempool_notifications() {
+        use crate::core::transaction::OutPoint;
+        
+        let temp_dir = TempDir::new().unwrap();
+        let db = Arc::new(Database::new(temp_dir.path().join("test.db")).unwrap());
+        let blockchain = Blockchain::new(db).unwrap();
+        let mut translator = ChainEventTranslator::new(&blockchain);
+        
+        let spend = |seed: &[u8]| {
+            let mut tx = Transaction::new();
+            tx.add_input(OutPoint::new(Hash256::hash(seed), 0), vec![1]);
+            tx.add_output(10_000, "qtc1recipient");
+            tx
+        };
+        let (confirmed, conflicting) = (spend(b"confirmed"), spend(b"conflicting"));
+        let types = |events: Vec<WebSocketEvent>| -> Vec<String> {
+            events.iter()
+                .map(|event| serde_json::to_value(event).unwrap()["type"].as_str().unwrap().to_string())
+                .collect()
+        };
+        
+        let accepted = translator.translate(ChainEvent::TransactionAdded(Arc::new(confirmed.clone())), &blockchain);
+        // Already gone from the mempool, so its fee is unknown rather than zero
+        let transaction = &serde_json::to_value(&accepted).unwrap()[0]["transaction"];
+        assert!(transaction["fee"].is_null() && transaction["fee_rate"].is_null());
+        assert_eq!(types(accepted), vec!["tx_accepted"]);
+        
+        let coinbase = Transaction::new_coinbase("qtc1miner".to_string(), 50, "block".to_string());
+        let block = Block::new(blockchain.tip, vec![coinbase, confirmed.clone()], 1, 1);
+        let connected = translator.translate(
+            ChainEvent::BlockConnected { block: Arc::new(block.clone()), spent: Arc::new(Vec::new()) },
+            &blockchain,
+        );
+        let json = serde_json::to_string(&connected).unwrap();
+        assert_eq!(types(connected), vec!["new_block", "tx_confirmed"]);
+        assert!(json.contains(&confirmed.hash().to_hex()) && json.contains(&block.hash().to_hex()));
+        
+        // The block's own transactions leaving the mempool are not reported twice
+        assert!(translator.translate(ChainEvent::TransactionRemoved(confirmed.hash()), &blockchain).is_empty());
+        let removed = translator.translate(ChainEvent::TransactionRemoved(conflicting.hash()), &blockchain);
+        assert_eq!(types(removed), vec!["tx_removed"]);
+    }
+    
     #[test]
     fn test_websocket_event_serialization() {
+        let hash = crate::crypto::hash::Hash256::hash(b"test block");
         let event = WebSocketEvent::NewBlock {
             block: BlockNotification {
-                hash: "test_hash".to_string(),
+                hash: hash.into(),
                 height: 100,
                 timestamp: 1234567890,
-                difficulty: 8,
+                bits: 0x1c01_0000,
+                difficulty: 1_073_741_824.0,
                 size: 1024,
                 transaction_count: 5,
                 miner: Some("test_miner".to_string()),
@@ -587,12 +809,12 @@ mod tests {
         
         let serialized = serde_json::to_string(&event).unwrap();
         assert!(serialized.contains("new_block"));
-        assert!(serialized.contains("test_hash"));
+        assert!(serialized.contains(&hash.to_hex()));
         
         let deserialized: WebSocketEvent = serde_json::from_str(&serialized).unwrap();
         match deserialized {
             WebSocketEvent::NewBlock { block } => {
-                assert_eq!(block.hash, "test_hash");
+                assert_eq!(block.hash.hash(), hash);
                 assert_eq!(block.height, 100);
             }
             _ => panic!("Wrong event type"),
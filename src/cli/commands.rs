@@ -1,20 +1,39 @@
-use crate::config::Config;
+use crate::config::{Config, NetworkType, Profile};
 use crate::cli::wallet_cli::WalletCli;
 use crate::cli::mining_cli::MiningCli;
-use crate::core::Blockchain;
-use crate::storage::Database;
-use crate::network::p2p::P2PNode;
-use crate::api::rest::RestApi;
-use crate::api::websocket::WebSocketServer;
+use crate::cli::shell::run_shell;
+use crate::core::{Blockchain, MempoolDump, PremineOutput, SigHashType};
+use crate::core::blockchain::TxOutStatus;
+use crate::core::transaction::{data_as_text, OutPoint};
+use crate::core::utxo::UtxoSet;
+use crate::core::fee_estimator::FeeBasis;
+use crate::consensus::{revalidate_chain, ChainParams, SupplyAudit};
+use crate::storage::{create_backup, BackupReport, Database, SnapshotInfo, StorageEncryption, StorageSecret, UtxoSnapshot};
+use crate::storage::database::AuditAction;
+use crate::storage::lock::DataDirLock;
+use crate::storage::upgrade::{format_estimate, UpgradeKind, UpgradePlan, SCHEMA_VERSION};
+use crate::network::bans::Ban;
+use crate::network::diversity::DiversityStats;
+use crate::network::messaging::Mailbox;
+use crate::network::p2p::{P2PCommand, P2PNode};
+use crate::api::auth::{create_api_key, revoke_api_key, ApiScope};
+use crate::api::faucet::{self, FaucetChallenge, FaucetClaim, FaucetInfo, FaucetPayout};
+use crate::api::rest::{ApiResponse, MempoolLoadResult};
+use crate::mining::simulation::{simulation_to_csv, EmissionSimulator, HashrateCurve};
+#[cfg(unix)]
+use crate::api::cluster::{ApiWorker, ControlClient, ControlRequest};
+#[cfg(unix)]
+use crate::mining::{MiningStats, MiningStatus};
+use crate::mining::split::format_percent;
 use crate::crypto::hash::Hashable;
+use crate::node::embedded::{federation, node_identity, open_blockchain, peer_diversity, proxy};
+use crate::node::{selftest, Node};
+use crate::wallet::{CoinSelection, ADDRESS_GAP_LIMIT};
 use crate::{QtcError, Result};
-use clap::{Parser, Subcommand};
+use clap::{Args, Parser, Subcommand};
 use std::sync::{Arc, RwLock};
 use tokio::signal;
-use std::fs::File;
 
-use tar::Builder;
-use flate2::{Compression, GzBuilder};
 use daemonize::Daemonize;
 
 #[derive(Parser)]
@@ -50,14 +69,43 @@ pub struct Cli {
     
     #[arg(long, help = "Configuration file path")]
     pub config: Option<String>,
+    
+    #[arg(long, help = "Configuration profile preset (public-explorer)")]
+    pub profile: Option<String>,
+    
+    #[arg(long, help = "Network to run on: mainnet, testnet or regtest")]
+    pub network: Option<String>,
+    
+    #[arg(long, global = true, help = "Show amounts in qtc or sats")]
+    pub units: Option<String>,
+    
+    #[arg(long, help = "Remove a data directory lock left by a qtcd process that is no longer running")]
+    pub force_unlock: bool,
+}
+
+/// The database a command runs against
+pub(crate) enum DataDir {
+    /// The shell's, opened once for the session
+    Shared(Arc<Database>),
+    /// Opened and locked by the command itself
+    Open { force_unlock: bool },
 }
 
 #[derive(Subcommand)]
 pub enum Commands {
     /// Initialize a new QTC node
     Init {
-        #[arg(long, help = "Genesis message")]
+        #[arg(long, help = "Message embedded in the genesis coinbase")]
         genesis_message: Option<String>,
+        
+        #[arg(long, help = "Genesis block timestamp (unix seconds)")]
+        genesis_timestamp: Option<u64>,
+        
+        #[arg(long, alias = "genesis-difficulty", value_name = "BITS", help = "Compact target bits of the genesis block, e.g. 0x207fffff")]
+        genesis_bits: Option<String>,
+        
+        #[arg(long = "premine", value_name = "ADDRESS:SATS", help = "Pay an output in the genesis block (repeatable)")]
+        premine: Vec<String>,
     },
     
     /// Start the QTC node daemon
@@ -70,6 +118,21 @@ pub enum Commands {
         
         #[arg(long, help = "Mining address")]
         mining_address: Option<String>,
+        
+        #[arg(long, help = "Show desktop notifications for payments, mined blocks and sync completion")]
+        notify_desktop: bool,
+        
+        #[arg(long, help = "Only report the upgrade work the data directory needs, then exit: 0 up to date, 3 migrations, 4 reindex, 5 action required")]
+        check_upgrade: bool,
+        
+        #[arg(long, help = "Start without the startup self-test")]
+        skip_self_test: bool,
+    },
+    
+    /// Check that this host can run a node: RandomX, signatures, storage, disk space and clock
+    Doctor {
+        #[arg(long, help = "Skip the NTP clock comparison")]
+        offline: bool,
     },
     
     /// Wallet management commands
@@ -95,6 +158,42 @@ pub enum Commands {
     /// Database maintenance commands
     #[command(subcommand)]
     Db(DbCommands),
+    
+    /// Audit trail of node-level actions
+    #[command(subcommand)]
+    Audit(AuditCommands),
+    
+    /// Offline tools that don't touch the node's data
+    #[command(subcommand)]
+    Util(UtilCommands),
+    
+    /// Request testnet coins from a node's faucet
+    #[command(subcommand)]
+    Faucet(FaucetCommands),
+    
+    /// Interactive prompt that opens the database once and runs commands against it
+    Shell,
+}
+
+impl Commands {
+    /// The subcommand's name, as recorded in the data directory lock
+    fn name(&self) -> &'static str {
+        match self {
+            Commands::Init { .. } => "init",
+            Commands::Start { .. } => "start",
+            Commands::Doctor { .. } => "doctor",
+            Commands::Wallet(_) => "wallet",
+            Commands::Mine(_) => "mine",
+            Commands::Network(_) => "network",
+            Commands::Chain(_) => "chain",
+            Commands::Api(_) => "api",
+            Commands::Db(_) => "db",
+            Commands::Audit(_) => "audit",
+            Commands::Util(_) => "util",
+            Commands::Faucet(_) => "faucet",
+            Commands::Shell => "shell",
+        }
+    }
 }
 
 #[derive(Subcommand)]
@@ -128,6 +227,15 @@ pub enum WalletCommands {
         wif: String,
     },
     
+    /// Import the keys of a Bitcoin Core or Electrum wallet and scan for their coins
+    ImportForeign {
+        name: String,
+        #[arg(long, help = "Source wallet format: electrum, core")]
+        format: String,
+        #[arg(long, help = "Electrum wallet file, Core wallet.dat or `bitcoin-cli dumpwallet` output")]
+        file: String,
+    },
+    
     /// List all wallets
     List,
     
@@ -136,11 +244,21 @@ pub enum WalletCommands {
         name: String,
     },
     
+    /// Show or change wallet settings
+    Settings {
+        name: String,
+        #[arg(long, value_name = "on|off", value_parser = clap::builder::BoolishValueParser::new(),
+            help = "Leave outputs on reused addresses out of automatic coin selection")]
+        avoid_reuse: Option<bool>,
+    },
+    
     /// Get wallet balance
     Balance {
         name: String,
         #[arg(long, help = "Show detailed UTXO breakdown")]
         detailed: bool,
+        #[arg(long, help = "Recompute from the UTXO set instead of the balance cache")]
+        force_refresh: bool,
     },
     
     /// Generate new receiving address
@@ -150,6 +268,30 @@ pub enum WalletCommands {
         change: bool,
     },
     
+    /// Generate and reserve a batch of receiving addresses
+    NewAddresses {
+        name: String,
+        #[arg(long, default_value_t = 1, help = "Number of addresses to generate")]
+        count: u32,
+        #[arg(long, help = "Label stored with the reserved addresses")]
+        label: Option<String>,
+        #[arg(long, help = "Export format: csv, json")]
+        export: Option<String>,
+        #[arg(long, value_name = "FILE", help = "Write the export to a file instead of stdout")]
+        output: Option<String>,
+    },
+    
+    /// Show a fresh receive address as a QR code and reserve it
+    Receive {
+        name: String,
+        #[arg(long, help = "Amount to request in QTC, added to a qtc: payment URI")]
+        amount: Option<String>,
+        #[arg(long, help = "Label for the payment URI and the reservation")]
+        label: Option<String>,
+        #[arg(long, help = "Print the address without a QR code")]
+        no_qr: bool,
+    },
+    
     /// List wallet addresses
     Addresses {
         name: String,
@@ -157,6 +299,19 @@ pub enum WalletCommands {
         unused: bool,
     },
     
+    /// Label an address, the wallet's own or one it pays; an empty label removes it
+    Label {
+        name: String,
+        address: String,
+        label: String,
+    },
+    
+    /// Names for addresses to pay, shared by every wallet
+    Contacts {
+        #[command(subcommand)]
+        command: ContactCommands,
+    },
+    
     /// Send QTC to an address
     Send {
         wallet: String,
@@ -166,6 +321,58 @@ pub enum WalletCommands {
         fee_rate: Option<u64>,
         #[arg(long, help = "Confirm transaction without prompting")]
         yes: bool,
+        #[arg(long, help = "Show inputs, size, fee and change without signing")]
+        preview: bool,
+        #[arg(long, default_value_t = CoinSelection::LargestFirst, help = "Coin selection strategy: largest-first, bnb or random")]
+        coin_selection: CoinSelection,
+        #[arg(long, help = "Signal replace-by-fee so the fee can be bumped while unconfirmed")]
+        replaceable: bool,
+        #[arg(long, help = "Earliest block height (or Unix time, from 500000000 on) the transaction can confirm at")]
+        locktime: Option<u64>,
+        #[arg(long, value_name = "HEX", help = "Data to put on chain in an unspendable OP_RETURN output (at most 80 bytes)")]
+        data: Option<String>,
+        #[arg(long, help = "Spend outputs on reused addresses even if the wallet avoids reuse")]
+        allow_reuse: bool,
+        #[arg(long, help = "Treat `to` as the name of a contact from `wallet contacts`")]
+        to_contact: bool,
+    },
+    
+    /// Replace an unconfirmed replaceable send with one paying a higher fee
+    BumpFee {
+        wallet: String,
+        txid: String,
+        #[arg(long, help = "New fee rate (satoshis per byte)")]
+        fee_rate: u64,
+        #[arg(long, help = "Confirm the replacement without prompting")]
+        yes: bool,
+    },
+    
+    /// Build an unsigned payment as hex for an offline signer, e.g. from a watch-only wallet
+    CreateUnsigned {
+        wallet: String,
+        to: String,
+        amount: String,
+        #[arg(long, help = "Transaction fee rate (satoshis per byte)")]
+        fee_rate: Option<u64>,
+        #[arg(long, value_name = "FILE", help = "Write the hex to a file instead of printing it")]
+        output: Option<String>,
+    },
+    
+    /// Sign a transaction from `wallet create-unsigned` on the offline machine, without chain data
+    SignOffline {
+        wallet: String,
+        #[arg(long, value_name = "FILE", help = "Read the hex from a file instead of stdin")]
+        input: Option<String>,
+        #[arg(long, value_name = "FILE", help = "Write the signed hex to a file instead of printing it")]
+        output: Option<String>,
+        #[arg(long, help = "Sign without prompting")]
+        yes: bool,
+    },
+    
+    /// Broadcast a transaction signed with `wallet sign-offline`
+    Broadcast {
+        #[arg(long, value_name = "FILE", help = "Read the hex from a file instead of stdin")]
+        input: Option<String>,
     },
     
     /// Show transaction history
@@ -175,11 +382,61 @@ pub enum WalletCommands {
         limit: Option<usize>,
     },
     
+    /// Look through past blocks for payments to imported keys or a restored seed
+    Rescan {
+        name: String,
+        #[arg(long, help = "Start at this block instead of where the last rescan stopped")]
+        from_height: Option<u64>,
+    },
+    
     /// Export wallet
     Export {
         name: String,
         #[arg(long, help = "Export format: mnemonic, wif, descriptor")]
         format: Option<String>,
+        #[arg(long, value_name = "FILE", help = "Write a password-protected watch-only bundle (no spend keys)")]
+        view_only: Option<String>,
+    },
+    
+    /// Lock outputs so they are never picked for new transactions
+    LockUnspent {
+        name: String,
+        #[arg(required = true, help = "Outputs to lock as <txid>:<vout>")]
+        outpoints: Vec<String>,
+        #[arg(long, value_name = "SECS", help = "Release the lock automatically after this many seconds")]
+        expires: Option<u64>,
+    },
+    
+    /// Release locked outputs
+    UnlockUnspent {
+        name: String,
+        #[arg(help = "Outputs to unlock as <txid>:<vout>")]
+        outpoints: Vec<String>,
+        #[arg(long, help = "Unlock every output locked by this wallet")]
+        all: bool,
+    },
+    
+    /// List outputs currently locked by a wallet
+    ListLockUnspent {
+        name: String,
+    },
+    
+    /// Import a view-only export as a watch-only wallet
+    ImportViewOnly {
+        name: String,
+        #[arg(help = "Path to the view-only export file")]
+        file: String,
+    },
+    
+    /// Rebuild a wallet from output descriptors, watch-only unless keys are given
+    ImportDescriptor {
+        name: String,
+        #[arg(required = true, help = "Descriptors as printed by `wallet export --format descriptor`")]
+        descriptors: Vec<String>,
+        #[arg(long, help = "Private key (WIF) for one of the described addresses; repeat for more")]
+        wif: Vec<String>,
+        #[arg(long, default_value_t = ADDRESS_GAP_LIMIT, help = "Addresses to derive from each ranged descriptor")]
+        range: u32,
     },
     
     /// Create multisig wallet
@@ -188,6 +445,18 @@ pub enum WalletCommands {
         command: MultisigCommands,
     },
     
+    /// Air-gapped signing with PSBTs passed as animated QR codes
+    Psbt {
+        #[command(subcommand)]
+        command: PsbtCommands,
+    },
+    
+    /// Exchange PSBTs with cosigners over end-to-end encrypted peer messages
+    Message {
+        #[command(subcommand)]
+        command: MessageCommands,
+    },
+    
     /// Backup wallet
     Backup {
         name: String,
@@ -212,47 +481,156 @@ pub enum MultisigCommands {
     /// Import multisig wallet from descriptor
     Import {
         name: String,
-        #[arg(long, help = "Miniscript descriptor")]
+        #[arg(long, help = "Output descriptor, e.g. sh(multi(2,<key>,<key>,<key>))#<checksum>")]
         descriptor: String,
         #[arg(long, help = "Our key indices")]
         our_keys: Vec<usize>,
     },
     
-    /// Create partial signature for transaction
+    /// Add our signatures to a payment from `wallet create-unsigned` or another cosigner
     Sign {
         wallet: String,
         #[arg(long, help = "Transaction hex")]
         tx_hex: String,
-        #[arg(long, help = "Input index to sign")]
-        input_index: usize,
+        #[arg(long, help = "Sign only this input (default: every input of the wallet)")]
+        input_index: Option<usize>,
+        #[arg(long, value_name = "FILE", help = "Write the signed hex to a file instead of printing it")]
+        output: Option<String>,
     },
     
-    /// Combine partial signatures and broadcast
+    /// Combine cosigners' signatures and broadcast once there are enough
     Finalize {
         wallet: String,
         #[arg(long, help = "Transaction hex")]
         tx_hex: String,
-        #[arg(long, help = "Partial signatures (hex format)")]
+        #[arg(long, help = "The same transaction as signed by other cosigners (hex)")]
         signatures: Vec<String>,
     },
 }
 
+#[derive(Subcommand)]
+pub enum PsbtCommands {
+    /// Build an unsigned payment and show it as an animated QR sequence
+    Export {
+        wallet: String,
+        to: String,
+        amount: String,
+        #[arg(long, help = "Transaction fee rate (satoshis per byte)")]
+        fee_rate: Option<u64>,
+        #[command(flatten)]
+        display: FrameDisplayArgs,
+    },
+    
+    /// Sign scanned or pasted PSBT frames on the offline device and show the result
+    Sign {
+        wallet: String,
+        #[arg(long, value_name = "FILE", help = "Read frames from a file instead of stdin")]
+        input: Option<String>,
+        #[arg(long, help = "Sign without prompting")]
+        yes: bool,
+        #[arg(long, default_value_t = SigHashType::All, help = "Parts of the transaction to sign: ALL, NONE or SINGLE, optionally with |ANYONECANPAY")]
+        sighash: SigHashType,
+        #[arg(long, value_name = "COMMAND", help = "Sign with an external signer that reads a JSON signing request on stdin and writes the signatures to stdout")]
+        signer: Option<String>,
+        #[command(flatten)]
+        display: FrameDisplayArgs,
+    },
+    
+    /// Read signed PSBT frames and broadcast the transaction
+    Import {
+        #[arg(long, value_name = "FILE", help = "Read frames from a file instead of stdin")]
+        input: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum MessageCommands {
+    /// Print the wallet's identity card for cosigners to send messages to
+    Card {
+        wallet: String,
+    },
+    
+    /// Encrypt a PSBT to a cosigner's identity card and publish it to peers
+    Send {
+        wallet: String,
+        #[arg(help = "The cosigner's identity card")]
+        card: String,
+        #[arg(long, value_name = "FILE", help = "Read PSBT frames from a file instead of stdin")]
+        input: Option<String>,
+        #[arg(long, help = "Short note shown to the cosigner")]
+        note: Option<String>,
+    },
+    
+    /// List messages a node with network.messaging received for the wallet
+    Inbox {
+        wallet: String,
+    },
+    
+    /// Show a received PSBT and write its frames for `wallet psbt sign --input`
+    Read {
+        wallet: String,
+        id: String,
+        #[arg(long, value_name = "FILE", help = "Write the PSBT frames to this file")]
+        output: Option<String>,
+    },
+    
+    /// Delete a message from the wallet's inbox
+    Delete {
+        wallet: String,
+        id: String,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum ContactCommands {
+    /// Add a contact
+    Add {
+        name: String,
+        address: String,
+    },
+    
+    /// List contacts
+    List,
+    
+    /// Remove a contact
+    Remove {
+        name: String,
+    },
+}
+
+#[derive(Args)]
+pub struct FrameDisplayArgs {
+    #[arg(long, default_value_t = crate::wallet::psbt::DEFAULT_FRAGMENT_LEN, help = "Payload bytes per QR frame")]
+    pub frame_size: usize,
+    #[arg(long, default_value_t = 500, help = "Milliseconds each frame stays on screen")]
+    pub interval_ms: u64,
+    #[arg(long, default_value_t = 5, help = "Times to loop through the frames")]
+    pub cycles: u32,
+    #[arg(long, help = "Print the frames as text instead of QR codes")]
+    pub no_qr: bool,
+    #[arg(long, value_name = "FILE", help = "Also write the frames to a file, one per line")]
+    pub output: Option<String>,
+}
+
 #[derive(Subcommand)]
 pub enum MiningCommands {
-    /// Start mining
+    /// Start mining on the running node
     Start {
-        #[arg(long, help = "Mining address")]
-        address: String,
-        #[arg(long, help = "Number of mining threads")]
+        #[arg(long, help = "Mining address (defaults to the node's mining.payouts or mining.payout_wallet)")]
+        address: Option<String>,
+        #[arg(long = "payout", value_name = "ADDRESS:PERCENT", conflicts_with = "address",
+              help = "Pay this percentage of each reward to ADDRESS (repeatable, adding up to 100)")]
+        payouts: Vec<String>,
+        #[arg(long, help = "Number of mining threads (defaults to mining.threads)")]
         threads: Option<usize>,
-        #[arg(long, help = "Use fast mode (more memory, better performance)")]
+        #[arg(long, help = "Use the full RandomX dataset (more memory, faster hashing)")]
         fast: bool,
     },
     
-    /// Stop mining
+    /// Stop mining on the running node
     Stop,
     
-    /// Show mining status
+    /// Show the running node's mining status
     Status,
     
     /// Mine a single block
@@ -270,11 +648,23 @@ pub enum MiningCommands {
     Benchmark {
         #[arg(long, help = "Benchmark duration in seconds")]
         duration: Option<u64>,
+        #[arg(long, help = "Benchmark with the full RandomX dataset")]
+        fast: bool,
     },
     
     /// Show current difficulty
     Difficulty,
     
+    /// Build a block template for an external miner
+    Template {
+        #[arg(long, help = "Maximum bytes of transactions to include")]
+        max_size: Option<usize>,
+        #[arg(long, help = "Minimum package fee rate (sat/byte)")]
+        min_fee_rate: Option<u64>,
+        #[arg(long, help = "Print the template as JSON")]
+        json: bool,
+    },
+    
     /// Calculate mining profitability
     Profitability {
         #[arg(long, help = "Your hashrate (H/s)")]
@@ -284,6 +674,26 @@ pub enum MiningCommands {
         #[arg(long, help = "Electricity cost per kWh")]
         cost_per_kwh: Option<f64>,
     },
+    
+    /// Private pool shares and payouts
+    Pool {
+        #[command(subcommand)]
+        command: PoolCommands,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum PoolCommands {
+    /// Show per-worker share statistics and found blocks
+    Stats,
+    
+    /// Build one unsigned transaction paying every matured round, as PSBT frames for `wallet psbt sign`
+    Payout {
+        #[arg(long, help = "Transaction fee rate (satoshis per byte)")]
+        fee_rate: Option<u64>,
+        #[arg(long, value_name = "FILE", help = "Write the frames to a file instead of stdout")]
+        output: Option<String>,
+    },
 }
 
 #[derive(Subcommand)]
@@ -314,11 +724,44 @@ pub enum NetworkCommands {
     /// Show network statistics
     Stats,
     
+    /// Show this node's peer ID, for other members' federation_peers lists
+    Identity,
+    
     /// Sync blockchain from peers
     Sync {
         #[arg(long, help = "Force full resync")]
         force: bool,
     },
+    
+    /// Disconnect a peer ID or IP address and refuse it until the ban runs out
+    Ban {
+        target: String,
+        #[arg(long, help = "Ban length in seconds (default: network.ban_duration_secs)")]
+        duration: Option<u64>,
+        #[arg(long, help = "Why the peer is banned")]
+        reason: Option<String>,
+        #[arg(long, help = "Node REST URL (default: the local node)")]
+        url: Option<String>,
+        #[arg(long, help = "API key with the admin scope")]
+        api_key: String,
+    },
+    
+    /// Lift a ban on a peer ID or IP address
+    Unban {
+        target: String,
+        #[arg(long, help = "Node REST URL (default: the local node)")]
+        url: Option<String>,
+        #[arg(long, help = "API key with the admin scope")]
+        api_key: String,
+    },
+    
+    /// List banned peer IDs and IP addresses
+    ListBans {
+        #[arg(long, help = "Node REST URL (default: the local node)")]
+        url: Option<String>,
+        #[arg(long, help = "API key with the admin scope")]
+        api_key: String,
+    },
 }
 
 #[derive(Subcommand)]
@@ -341,6 +784,18 @@ pub enum ChainCommands {
         raw: bool,
     },
     
+    /// Show an output's status: unspent entry or the transaction that spent it
+    Txout {
+        #[arg(help = "Output reference as <txid>:<vout>")]
+        outpoint: String,
+    },
+    
+    /// Scan the UTXO set for outputs matching descriptors, without a wallet
+    ScanTxoutSet {
+        #[arg(required = true, help = "Descriptors: addr(<address>), pkh(<pubkey hex>), raw(<script hex>) or bare addresses")]
+        descriptors: Vec<String>,
+    },
+    
     /// List recent blocks
     Blocks {
         #[arg(long, help = "Number of blocks to show")]
@@ -356,20 +811,46 @@ pub enum ChainCommands {
     
     /// Validate blockchain
     Validate {
-        #[arg(long, help = "Validate from specific height")]
+        #[arg(long, help = "Validate from specific height (the UTXO set is still rebuilt from genesis)")]
         from_height: Option<u64>,
         #[arg(long, help = "Quick validation (headers only)")]
         quick: bool,
     },
     
-    /// Show mempool information
-    Mempool,
+    /// Recompute the coin supply from the blocks and check it against the monetary policy
+    AuditSupply,
+    
+    /// Show mempool information, or dump and load a running node's mempool
+    Mempool {
+        #[command(subcommand)]
+        command: Option<MempoolCommands>,
+    },
     
     /// Estimate transaction fee
     EstimateFee {
         #[arg(long, help = "Target confirmation blocks")]
         blocks: Option<u32>,
     },
+    
+    /// Mark a block and everything built on it invalid, reorganizing away from it
+    InvalidateBlock {
+        #[arg(help = "Block hash")]
+        hash: String,
+    },
+    
+    /// Clear an invalid mark set by invalidate-block, reorganizing back if its branch has the most work
+    ReconsiderBlock {
+        #[arg(help = "Block hash")]
+        hash: String,
+    },
+    
+    /// Mine blocks instantly (regtest only)
+    Generate {
+        #[arg(help = "Number of blocks to generate")]
+        blocks: u64,
+        #[arg(long, help = "Address receiving the block rewards")]
+        address: String,
+    },
 }
 
 #[derive(Subcommand)]
@@ -395,6 +876,73 @@ pub enum ApiCommands {
         #[arg(long, help = "API endpoint to test")]
         endpoint: Option<String>,
     },
+    
+    /// Manage scoped API keys
+    #[command(subcommand)]
+    Keys(ApiKeyCommands),
+    
+    /// Serve REST and WebSocket traffic for a running node over its control socket
+    Worker {
+        #[arg(long, help = "Node control socket (defaults to api.control_socket, or control.sock in the data directory)")]
+        socket: Option<String>,
+        #[arg(long, help = "Port to serve on (defaults to api.rest_port)")]
+        port: Option<u16>,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum ApiKeyCommands {
+    /// Create a key; its token is printed once
+    Create {
+        name: String,
+        #[arg(long = "scope", required = true, help = "read, broadcast, wallet-spend or admin (repeatable)")]
+        scopes: Vec<String>,
+    },
+    
+    /// Revoke a key by ID
+    Revoke {
+        id: String,
+    },
+    
+    /// List keys, including revoked ones
+    List,
+}
+
+#[derive(Subcommand)]
+pub enum MempoolCommands {
+    /// Save every mempool entry, with its fee and arrival time, to a JSON file
+    Dump {
+        file: String,
+        #[arg(long, help = "Node REST URL (default: the local node)")]
+        url: Option<String>,
+        #[arg(long, help = "API key with the admin scope")]
+        api_key: String,
+    },
+    
+    /// Add the transactions in a dump to the node's mempool
+    Load {
+        file: String,
+        #[arg(long, help = "Node REST URL (default: the local node)")]
+        url: Option<String>,
+        #[arg(long, help = "API key with the admin scope")]
+        api_key: String,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum FaucetCommands {
+    /// Show the faucet's payout, balance and limits
+    Info {
+        #[arg(long, help = "Faucet node REST URL (default: the local node)")]
+        url: Option<String>,
+    },
+    
+    /// Solve a challenge and claim coins to an address
+    Claim {
+        address: String,
+        #[arg(long, help = "Faucet node REST URL (default: the local node)")]
+        url: Option<String>,
+    },
 }
 
 #[derive(Subcommand)]
@@ -405,7 +953,7 @@ pub enum DbCommands {
     /// Compact database
     Compact,
     
-    /// Backup database
+    /// Write a verified .tar.gz of the data directory, through the running node if there is one
     Backup {
         path: String,
     },
@@ -418,65 +966,243 @@ pub enum DbCommands {
         #[arg(long, help = "Start from specific height")]
         from_height: Option<u64>,
     },
+    
+    /// Encrypt an existing data directory at rest, as configured in storage.encryption
+    Encrypt,
+    
+    /// Protect the storage key with a new passphrase or key file
+    RotateKey {
+        #[arg(long, help = "Key file to use from now on instead of a passphrase")]
+        new_key_file: Option<String>,
+    },
+    
+    /// Export the UTXO set, or bootstrap a new node from an exported one
+    #[command(subcommand)]
+    Snapshot(SnapshotCommands),
+}
+
+#[derive(Subcommand)]
+pub enum SnapshotCommands {
+    /// Write the UTXO set and chain state at the tip to a file, with its hash
+    Create {
+        path: String,
+    },
+    
+    /// Start a new data directory from a snapshot and sync on from its height
+    Load {
+        path: String,
+        #[arg(long, help = "Refuse the snapshot unless its hash matches, e.g. one published by a trusted source")]
+        expect_hash: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum AuditCommands {
+    /// Show recorded actions, oldest first
+    Log {
+        #[arg(long, help = "Only entries since a unix timestamp, RFC 3339 time or age like 30m, 24h, 7d")]
+        since: Option<String>,
+        #[arg(long, default_value = "100", help = "Maximum number of entries")]
+        limit: usize,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum UtilCommands {
+    /// Model block times and emission under synthetic hashrate scenarios
+    Simulate {
+        #[arg(long, value_name = "FILE", help = "CSV of height,hashrate (H/s) steps")]
+        hashrate_curve: Option<String>,
+        #[arg(long, help = "Constant hashrate (H/s) when no curve file is given")]
+        hashrate: Option<f64>,
+        #[arg(long, default_value = "100000", help = "Number of blocks to simulate")]
+        blocks: u64,
+        #[arg(long, default_value = "1", help = "Random seed for block discovery times")]
+        seed: u64,
+        #[arg(long, value_name = "FILE", help = "Write the CSV to a file instead of stdout")]
+        output: Option<String>,
+    },
 }
 
 pub async fn run_cli(config: Config) -> Result<()> {
     let cli = Cli::parse();
     
-    // Initialize logging once
-    let _ = if cli.debug {
-        env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("debug")).try_init()
-    } else {
-        env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).try_init()
-    };
-    
     println!("🌟 Quantum Goldchain (QTC) Node Starting...");
     println!("⛓️  Initiating Real-World Launch Protocol Mode");
     println!("🧑‍💻 Jake online. Mission status: Hardcore Blockchain Implementation Mode ENGAGED");
     
     // Override config with CLI arguments
     let mut config = config;
+    if let Some(network) = cli.network {
+        let network: NetworkType = network.parse()?;
+        if network != config.network_type {
+            config = Config::for_network(network);
+        }
+        println!("🌐 Network: {}", network);
+    }
     if let Some(port) = cli.port {
         config.network.port = port;
     }
     if let Some(data_dir) = cli.data_dir {
         config.storage.data_dir = data_dir.into();
     }
+    if let Some(profile) = cli.profile {
+        let profile: Profile = profile.parse()
+            .map_err(|e: anyhow::Error| QtcError::InvalidInput(e.to_string()))?;
+        config.apply_profile(profile);
+        println!("🧩 Using configuration profile: {:?}", profile);
+    }
+    if let Some(units) = cli.units {
+        config.units = units.parse()?;
+    }
+    crate::crypto::keys::select_address_prefix(&ChainParams::for_network(config.network_type).address_prefix);
     
     // Ensure data directory exists
     std::fs::create_dir_all(&config.storage.data_dir)?;
     
-    // Initialize database
-    let db_path = config.storage.data_dir.join("qtc.db");
-    let db = Arc::new(Database::new(db_path)?);
+    // Only the node keeps log files, found by absolute path as the daemon leaves for /tmp
+    let log_dir = match (&cli.command, config.logging.file) {
+        (Commands::Start { .. }, true) => Some(std::path::absolute(config.storage.data_dir.join("logs"))?),
+        _ => None,
+    };
+    crate::node::logging::init(&config.logging, cli.debug, log_dir.as_deref())?;
+    
+    if let Commands::Shell = cli.command {
+        let _lock = lock_data_dir(&config, cli.command.name(), cli.force_unlock)?;
+        let db_path = config.storage.data_dir.join("qtc.db");
+        let encryption = storage_encryption(&config, !db_path.exists())?;
+        let db = Arc::new(Database::with_encryption(db_path, encryption.as_ref())?);
+        return run_shell(config, db).await;
+    }
+    run_command(config, cli.command, DataDir::Open { force_unlock: cli.force_unlock }).await
+}
+
+/// Lock the data directory for this process, unless the config turns locking off
+fn lock_data_dir(config: &Config, command: &str, force_unlock: bool) -> Result<Option<DataDirLock>> {
+    if !config.storage.lock_data_dir {
+        return Ok(None);
+    }
+    DataDirLock::acquire(&config.storage.data_dir, &format!("qtcd {}", command), force_unlock).map(Some)
+}
+
+/// Run one command against the shell's database, or open (and lock) the data directory for it
+pub(crate) async fn run_command(config: Config, command: Commands, data_dir: DataDir) -> Result<()> {
+    // The doctor must work while a node holds the database lock
+    if let Commands::Doctor { offline } = command {
+        return run_doctor(&config, offline);
+    }
     
-    match cli.command {
-        Commands::Init { genesis_message } => {
-            init_node(db, genesis_message).await
-        }
-        
-        Commands::Start { daemon, mine, mining_address } => {
-            start_node(config, db, daemon, mine, mining_address).await
+    // Network commands query the running node, which holds the database lock
+    if let Commands::Network(network_cmd) = command {
+        return handle_network_command(config, network_cmd).await;
+    }
+    if let Commands::Faucet(faucet_cmd) = command {
+        return handle_faucet_command(config, faucet_cmd).await;
+    }
+    if let Commands::Chain(ChainCommands::Mempool { command: Some(mempool_cmd) }) = command {
+        return handle_mempool_command(config, mempool_cmd).await;
+    }
+    if let Commands::Api(ApiCommands::Worker { socket, port }) = command {
+        return run_api_worker(config, socket, port).await;
+    }
+    if let Commands::Mine(mining_cmd @ (MiningCommands::Start { .. } | MiningCommands::Stop | MiningCommands::Status)) = command {
+        return handle_mining_control(config, mining_cmd).await;
+    }
+    if let Commands::Mine(MiningCommands::Stats) = &command {
+        // The running node holds the data directory, and knows what its miner has earned
+        if let Some(status) = mining_status_via_node(&config).await? {
+            println!("⛏️ Mining on the running node: {}", if status.mining { "yes" } else { "no" });
+            if let Some(stats) = status.stats.filter(|_| status.mining) {
+                print_mining_stats(&config, &stats);
+            }
+            return Ok(());
         }
-        
-        Commands::Wallet(wallet_cmd) => {
-            let blockchain = Arc::new(RwLock::new(Blockchain::new(db.clone())?));
-            let mut wallet_cli = WalletCli::new(db, blockchain);
-            wallet_cli.handle_command(wallet_cmd).await
+    }
+    if let Commands::Db(DbCommands::Backup { path }) = &command {
+        if let Some(report) = backup_via_node(&config, &std::path::absolute(path)?).await? {
+            print_backup_report(&report);
+            return Ok(());
         }
-        
-        Commands::Mine(mining_cmd) => {
-            let blockchain = Arc::new(RwLock::new(Blockchain::new(db.clone())?));
-            let mut mining_cli = MiningCli::new(blockchain);
-            mining_cli.handle_command(mining_cmd).await
+    }
+    
+    // Initialize database
+    let (db, _lock) = match data_dir {
+        DataDir::Shared(db) => (db, None),
+        DataDir::Open { force_unlock } => {
+            let lock = lock_data_dir(&config, command.name(), force_unlock)?;
+            let db_path = config.storage.data_dir.join("qtc.db");
+            if let Commands::Db(DbCommands::Encrypt) = command {
+                return encrypt_data_dir(&config, &db_path);
+            }
+            let encryption = storage_encryption(&config, !db_path.exists())?;
+            if let Commands::Start { check_upgrade, .. } = command {
+                let plan = Database::upgrade_plan(&db_path, encryption.as_ref(), config.storage.addrindex)?;
+                if check_upgrade || !plan.is_up_to_date() {
+                    print_upgrade_plan(&db_path, &plan);
+                }
+                if check_upgrade {
+                    drop(lock);
+                    std::process::exit(plan.exit_code());
+                }
+            }
+            (Arc::new(Database::with_encryption(db_path, encryption.as_ref())?), lock)
+        }
+    };
+    
+    match command {
+        Commands::Init { genesis_message, genesis_timestamp, genesis_bits, premine } => {
+            let premine = premine.iter().map(|output| output.parse()).collect::<Result<Vec<PremineOutput>>>()?;
+            let genesis_bits = genesis_bits.as_deref().map(parse_bits).transpose()?;
+            init_node(&config, db, genesis_message, genesis_timestamp, genesis_bits, premine).await
+        }
+        
+        Commands::Start { daemon, mine, mining_address, notify_desktop, check_upgrade: _, skip_self_test } => {
+            start_node(config, db, daemon, mine, mining_address, notify_desktop, !skip_self_test).await
         }
         
-        Commands::Network(network_cmd) => {
-            handle_network_command(config, db, network_cmd).await
+        Commands::Wallet(wallet_cmd) => {
+            let blockchain = Arc::new(RwLock::new(open_blockchain(&config, db.clone())?));
+            let mut wallet_cli = WalletCli::new(db.clone(), blockchain.clone());
+            wallet_cli.set_units(config.units);
+            // sled locks the data directory, so no node runs on ours; sends go to the node
+            // behind the configured control socket when one answers, else a P2P node of their own
+            match &wallet_cmd {
+                WalletCommands::Send { preview: false, .. }
+                | WalletCommands::BumpFee { .. }
+                | WalletCommands::Broadcast { .. }
+                | WalletCommands::Multisig { command: MultisigCommands::Finalize { .. } }
+                | WalletCommands::Psbt { command: PsbtCommands::Import { .. } } => {
+                    attach_relay(&config, &mut wallet_cli, blockchain).await?;
+                }
+                WalletCommands::Message { command: MessageCommands::Send { .. } } => {
+                    let mailbox = Arc::new(Mailbox::new(db)?);
+                    wallet_cli.set_p2p_commands(start_relay_node(&config, blockchain, Some(mailbox)).await?);
+                }
+                _ => {}
+            }
+            wallet_cli.handle_command(wallet_cmd).await
+        }
+        
+        Commands::Mine(mining_cmd) => {
+            let blockchain = Arc::new(RwLock::new(open_blockchain(&config, db.clone())?));
+            let mut mining_cli = MiningCli::new(blockchain);
+            mining_cli.set_units(config.units);
+            if let Some(pool_config) = &config.mining.pool {
+                mining_cli.set_pool_config(pool_config.clone());
+            }
+            mining_cli.handle_command(mining_cmd).await
+        }
+        
+        Commands::Network(_) | Commands::Faucet(_) | Commands::Doctor { .. } => {
+            unreachable!("network, faucet and doctor commands run without the database")
+        }
+        
+        Commands::Shell => {
+            Err(QtcError::InvalidInput("Already in the shell".to_string()))
         }
         
         Commands::Chain(chain_cmd) => {
-            handle_chain_command(db, chain_cmd).await
+            handle_chain_command(config, db, chain_cmd).await
         }
         
         Commands::Api(api_cmd) => {
@@ -484,49 +1210,108 @@ pub async fn run_cli(config: Config) -> Result<()> {
         }
         
         Commands::Db(db_cmd) => {
-            handle_db_command(db, db_cmd).await
+            handle_db_command(&config, db, db_cmd).await
+        }
+        
+        Commands::Audit(audit_cmd) => {
+            handle_audit_command(db, audit_cmd).await
+        }
+        
+        Commands::Util(util_cmd) => {
+            handle_util_command(config, util_cmd).await
         }
     }
 }
 
-async fn init_node(db: Arc<Database>, genesis_message: Option<String>) -> Result<()> {
+async fn init_node(
+    config: &Config,
+    db: Arc<Database>,
+    genesis_message: Option<String>,
+    genesis_timestamp: Option<u64>,
+    genesis_bits: Option<u32>,
+    premine: Vec<PremineOutput>,
+) -> Result<()> {
     println!("🌟 Initializing Quantum Goldchain (QTC) Node...");
     
-    // Check if already initialized
-    if db.get_chain_state().is_ok() {
-        println!("⚠️  Node already initialized!");
-        return Ok(());
+    let mut genesis = config.genesis_params()?;
+    let custom = genesis_message.is_some() || genesis_timestamp.is_some() || genesis_bits.is_some() || !premine.is_empty();
+    if custom {
+        if config.network_type == NetworkType::Mainnet {
+            return Err(QtcError::InvalidInput(
+                "The mainnet genesis block is fixed; use --network testnet or regtest for a custom chain".to_string()
+            ));
+        }
+        if let Some(message) = genesis_message {
+            println!("📝 Using custom genesis message: {}", message);
+            genesis.message = message;
+        }
+        genesis.timestamp = genesis_timestamp.unwrap_or(genesis.timestamp);
+        genesis.bits = genesis_bits.unwrap_or(genesis.bits);
+        if !premine.is_empty() {
+            genesis.premine = premine;
+        }
+        genesis.validate(config.consensus.max_supply)?;
     }
     
-    // Initialize blockchain with custom genesis message if provided
-    let blockchain = if let Some(message) = genesis_message {
-        println!("📝 Using custom genesis message: {}", message);
-        // Create custom genesis block here
-        Blockchain::new(db)?
-    } else {
-        Blockchain::new(db)?
-    };
+    if db.get_chain_state()?.is_some() {
+        let existing = Blockchain::new(db)?.genesis_hash()?;
+        println!("⚠️  Node already initialized with genesis block {}", existing);
+        if custom && existing != genesis.hash() {
+            return Err(QtcError::InvalidInput(format!(
+                "The requested genesis block {} differs; use a new --data-dir for another chain", genesis.hash()
+            )));
+        }
+        return Ok(());
+    }
     
+    let blockchain = Blockchain::with_genesis(db, &genesis)?;
     let chain_info = blockchain.get_chain_info()?;
     
     println!("✅ QTC Node initialized successfully!");
-    println!("📦 Genesis block hash: {}", chain_info.tip);
-    println!("🎯 Initial difficulty: {}", chain_info.difficulty);
-    println!("💰 Max supply: {} QTC", chain_info.total_supply as f64 / 100_000_000.0);
+    println!("📦 Genesis block hash: {}", blockchain.genesis_hash()?);
+    println!("🎯 Initial bits: {:#010x} (difficulty {})", chain_info.bits, chain_info.difficulty());
+    if !genesis.premine.is_empty() {
+        println!("💰 Premined: {} to {} address(es)", config.units.format(genesis.premine_total()), genesis.premine.len());
+    }
+    if custom {
+        println!("🔗 Nodes joining this chain need the same parameters under consensus.genesis:");
+        println!("{}", serde_json::to_string_pretty(&genesis)?);
+    }
     println!("");
     println!("🚀 Ready to start mining! Use 'qtcd start --mine --mining-address <your-address>' to begin.");
     
     Ok(())
 }
 
+/// Run every self-test and print what to fix; fails if any check failed
+fn run_doctor(config: &Config, offline: bool) -> Result<()> {
+    println!("🩺 Checking this host for {}...", config.network_type);
+    let report = if offline {
+        selftest::run_startup(config, None)
+    } else {
+        selftest::run_all(config, None)
+    };
+    for check in &report.checks {
+        println!("{}", check);
+    }
+    report.into_result()?;
+    println!("✅ All checks passed");
+    Ok(())
+}
+
 async fn start_node(
     config: Config,
     db: Arc<Database>,
     daemon: bool,
     mine: bool,
     mining_address: Option<String>,
+    notify_desktop: bool,
+    self_test: bool,
 ) -> Result<()> {
     if daemon {
+        // The daemon works from /tmp, so the data directory is found by its absolute path
+        let data_dir = std::path::absolute(&config.storage.data_dir)?;
+        
         // Properly daemonize the process before starting the node
         let daemonize = Daemonize::new()
             .pid_file("/tmp/qtcd.pid")
@@ -540,7 +1325,10 @@ async fn start_node(
             Ok(_) => {
                 // This code runs in the detached daemon process
                 log::info!("QTC daemon started successfully");
-                start_node_services(config, db, mine, mining_address).await
+                if config.storage.lock_data_dir {
+                    DataDirLock::take_over(&data_dir)?;
+                }
+                start_node_services(config, db, mine, mining_address, notify_desktop, self_test).await
             }
             Err(e) => {
                 eprintln!("Failed to daemonize: {}", e);
@@ -549,144 +1337,259 @@ async fn start_node(
         }
     } else {
         // Run in foreground mode
-        start_node_services(config, db, mine, mining_address).await
+        start_node_services(config, db, mine, mining_address, notify_desktop, self_test).await
     }
 }
 
-async fn start_node_services(
-    config: Config,
-    db: Arc<Database>,
-    mine: bool,
-    mining_address: Option<String>,
-) -> Result<()> {
-    println!("🚀 Starting Quantum Goldchain (QTC) Node...");
-    
-    // Initialize blockchain
-    let blockchain = Arc::new(RwLock::new(Blockchain::new(db.clone())?));
-    
-    // Start P2P networking
-    let (mut p2p_node, mut p2p_events, _p2p_commands) = P2PNode::new(
-        blockchain.clone(),
-        config.network.port,
+/// Short-lived P2P node for commands that only need to push data to peers
+async fn start_relay_node(
+    config: &Config,
+    blockchain: Arc<RwLock<Blockchain>>,
+    mailbox: Option<Arc<Mailbox>>,
+) -> Result<tokio::sync::mpsc::Sender<P2PCommand>> {
+    // Sends under the node's own identity, so federation members accept them
+    let (mut p2p_node, _p2p_events, p2p_commands) = P2PNode::with_identity(
+        node_identity(config)?,
+        blockchain,
+        0, // any free port, a node may already be listening on the configured one
         config.network.bootstrap_nodes.clone(),
+        proxy(config)?,
     ).await?;
-    
-    // Start API servers if enabled
-    let mut api_handles = Vec::new();
-    
-    if config.api.enable_rest {
-        let rest_api = RestApi::new(blockchain.clone(), config.api.clone());
-        let rest_handle = tokio::spawn(async move {
-            if let Err(e) = rest_api.start().await {
-                log::error!("REST API error: {}", e);
-            }
-        });
-        api_handles.push(rest_handle);
+    p2p_node.set_peer_diversity(Arc::new(peer_diversity(config)?));
+    if let Some(allowlist) = federation(config)? {
+        p2p_node.set_federation(Arc::new(allowlist));
     }
-    
-    if config.api.enable_websocket {
-        let ws_server = WebSocketServer::new(blockchain.clone(), config.api.websocket_port);
-        let ws_handle = tokio::spawn(async move {
-            if let Err(e) = ws_server.start().await {
-                log::error!("WebSocket server error: {}", e);
-            }
-        });
-        api_handles.push(ws_handle);
+    if let Some(mailbox) = mailbox {
+        p2p_node.set_mailbox(mailbox)?;
     }
     
-    // Start mining if requested
-    if mine {
-        if let Some(address) = mining_address {
-            let miner = crate::mining::miner::Miner::new(
-                blockchain.clone(),
-                address,
-                config.mining.threads,
-            )?;
-            
-            let mining_handle = tokio::spawn(async move {
-                if let Err(e) = miner.start_mining().await {
-                    log::error!("Mining error: {}", e);
-                }
-            });
-            api_handles.push(mining_handle);
-        } else {
-            return Err(QtcError::InvalidInput("Mining address required when --mine is used".to_string()));
-        }
-    }
-    
-    // Start P2P networking
-    let p2p_handle = tokio::spawn(async move {
+    tokio::spawn(async move {
         if let Err(e) = p2p_node.run().await {
-            log::error!("P2P node error: {}", e);
+            log::error!("P2P relay node error: {}", e);
         }
     });
     
-    // Handle P2P events
-    let blockchain_clone = blockchain.clone();
-    let event_handle = tokio::spawn(async move {
-        while let Ok(event) = p2p_events.recv().await {
-            if let Err(e) = handle_p2p_event(blockchain_clone.clone(), event).await {
-                log::error!("P2P event handling error: {}", e);
-            }
+    // A dial error would stop the node, so only hand over well-formed addresses
+    for address in &config.network.bootstrap_nodes {
+        if address.parse::<libp2p::Multiaddr>().is_ok() {
+            let _ = p2p_commands.send(P2PCommand::ConnectPeer(address.clone())).await;
         }
-    });
+    }
+    Ok(p2p_commands)
+}
+
+/// How to unlock an encrypted data directory: the configured key file, else a passphrase prompt
+fn storage_encryption(config: &Config, confirm: bool) -> Result<Option<StorageEncryption>> {
+    let Some(encryption) = &config.storage.encryption else {
+        return Ok(None);
+    };
+    let secret = match &encryption.key_file {
+        Some(path) => StorageSecret::KeyFile(path.clone()),
+        None => StorageSecret::Passphrase(prompt_storage_passphrase("Storage passphrase", confirm)?),
+    };
+    Ok(Some(StorageEncryption { secret, encrypt_blocks: encryption.encrypt_blocks }))
+}
+
+fn prompt_storage_passphrase(prompt: &str, confirm: bool) -> Result<String> {
+    let mut input = dialoguer::Password::new().with_prompt(prompt);
+    if confirm {
+        input = input.with_confirmation("Confirm passphrase", "Passphrases don't match");
+    }
+    input.interact()
+        .map_err(|e| QtcError::InvalidInput(format!("Failed to read passphrase: {}", e)))
+}
+
+fn print_upgrade_plan(db_path: &std::path::Path, plan: &UpgradePlan) {
+    let from = match plan.schema_version {
+        Some(version) => format!("v{}", version),
+        None if db_path.exists() => "unversioned".to_string(),
+        None => "none yet".to_string(),
+    };
+    println!("🔎 Upgrade check for {} (schema {}, this build v{})", db_path.display(), from, SCHEMA_VERSION);
+    if plan.is_up_to_date() {
+        println!("✅ Nothing to do");
+        return;
+    }
     
-    api_handles.push(p2p_handle);
-    api_handles.push(event_handle);
+    println!("   {}", plan.summary());
+    for (i, step) in plan.steps.iter().enumerate() {
+        match step.kind {
+            UpgradeKind::Blocked => println!("   {}. ⛔ [{}] {}", i + 1, step.kind, step.description),
+            _ => println!("   {}. [{}] {} (~{})", i + 1, step.kind, step.description, format_estimate(step.estimated_secs)),
+        }
+    }
+    if plan.count(UpgradeKind::Blocked) == 0 {
+        println!("   These run automatically when the node starts");
+    }
+}
+
+fn encrypt_data_dir(config: &Config, db_path: &std::path::Path) -> Result<()> {
+    let Some(encryption) = storage_encryption(config, true)? else {
+        return Err(QtcError::InvalidInput(
+            "Set storage.encryption in the config first, with a key_file or none to use a passphrase".to_string()
+        ));
+    };
+    
+    println!("🔐 Encrypting {} (stop the node first)...", db_path.display());
+    let sealed = Database::encrypt_existing(db_path, &encryption)?;
+    println!("✅ Encrypted {} stored values", sealed);
+    if !encryption.encrypt_blocks {
+        println!("Block files are left as they are; they hold only public chain data");
+    }
+    Ok(())
+}
+
+async fn start_node_services(
+    config: Config,
+    db: Arc<Database>,
+    mine: bool,
+    mining_address: Option<String>,
+    notify_desktop: bool,
+    self_test: bool,
+) -> Result<()> {
+    println!("🚀 Starting Quantum Goldchain (QTC) Node...");
+    
+    let mut builder = Node::builder(config.clone())
+        .with_database(db)
+        .with_desktop_notifications(notify_desktop)
+        .with_self_test(self_test);
+    if mine {
+        builder = builder.with_mining(mining_address);
+    }
+    let node = builder.start().await?;
     
     println!("✅ QTC Node started successfully!");
     println!("🌐 P2P port: {}", config.network.port);
-    if config.api.enable_rest {
+    let subsystems = node.subsystems();
+    if subsystems.iter().any(|name| name == "rest") {
         println!("🔗 REST API: http://localhost:{}", config.api.rest_port);
     }
-    if config.api.enable_websocket {
+    if subsystems.iter().any(|name| name == "jsonrpc") {
+        println!("🧾 JSON-RPC: http://localhost:{}", config.api.rpc_port);
+    }
+    if subsystems.iter().any(|name| name == "websocket") {
         println!("🔌 WebSocket: ws://localhost:{}", config.api.websocket_port);
     }
+    if subsystems.iter().any(|name| name == "control-socket") {
+        println!("🧷 Control socket: {}", config.control_socket_path().display());
+    }
     
     // Wait for termination signal (both daemon and foreground modes)
-    signal::ctrl_c().await.expect("Failed to listen for Ctrl+C");
+    shutdown_requested().await;
     
     println!("\n🛑 Shutting down QTC Node...");
-    
-    // Cancel all tasks
-    for handle in api_handles {
-        handle.abort();
-    }
+    node.stop().await?;
     
     println!("✅ QTC Node stopped gracefully.");
     
     Ok(())
 }
 
-async fn handle_p2p_event(
-    blockchain: Arc<RwLock<Blockchain>>,
-    event: crate::network::protocol::Message,
-) -> Result<()> {
-    match event.message_type {
-        crate::network::protocol::MessageType::Block(block) => {
-            let mut bc = blockchain.write().unwrap();
-            if let Err(e) = bc.add_block(block) {
-                log::warn!("Failed to add received block: {}", e);
-            }
+/// Ctrl+C, or SIGTERM from a service manager stopping the daemon
+async fn shutdown_requested() {
+    #[cfg(unix)]
+    if let Ok(mut terminate) = signal::unix::signal(signal::unix::SignalKind::terminate()) {
+        tokio::select! {
+            _ = signal::ctrl_c() => {}
+            _ = terminate.recv() => {}
+        }
+        return;
+    }
+    signal::ctrl_c().await.expect("Failed to listen for Ctrl+C");
+}
+
+async fn handle_faucet_command(config: Config, cmd: FaucetCommands) -> Result<()> {
+    let local = format!("http://127.0.0.1:{}", config.api.rest_port);
+    let client = reqwest::Client::new();
+    
+    match cmd {
+        FaucetCommands::Info { url } => {
+            let base = url.unwrap_or(local);
+            let info: FaucetInfo = api_request(client.get(format!("{}/api/v1/faucet", base))).await?;
+            
+            println!("🚰 Faucet at {}", base);
+            println!("Payout: {}", config.units.format(info.amount.amount_sats));
+            println!("Balance: {}", config.units.format(info.balance.amount_sats));
+            println!("Cooldown: {}s per IP, {}s per address", info.ip_cooldown_secs, info.address_cooldown_secs);
+            println!("Challenge: {} bits", info.challenge_bits);
         }
         
-        crate::network::protocol::MessageType::Transaction(tx) => {
-            let bc = blockchain.read().unwrap();
-            if let Ok(true) = bc.is_valid_transaction(&tx) {
-                log::info!("Received valid transaction: {}", hex::encode(tx.hash().as_bytes()));
-                // Add to mempool (would be implemented)
+        FaucetCommands::Claim { address, url } => {
+            if !crate::crypto::keys::is_valid_address(&address) {
+                return Err(QtcError::InvalidInput(format!("Invalid address: {}", address)));
             }
+            let base = url.unwrap_or(local);
+            
+            let challenge: FaucetChallenge = api_request(client.get(format!("{}/api/v1/faucet/challenge", base))).await?;
+            println!("🧩 Solving {}-bit challenge...", challenge.bits);
+            let token = challenge.token.clone();
+            let nonce = tokio::task::spawn_blocking(move || faucet::solve_challenge(&challenge))
+                .await
+                .map_err(|e| QtcError::InvalidInput(format!("Challenge solver failed: {}", e)))?;
+            
+            let claim = FaucetClaim { address, token, nonce };
+            let payout: FaucetPayout = api_request(client.post(format!("{}/api/v1/faucet", base)).json(&claim)).await?;
+            
+            println!("✅ Sent {} to {}", config.units.format(payout.amount.amount_sats), payout.address);
+            println!("Transaction ID: {}", payout.txid);
+        }
+    }
+    
+    Ok(())
+}
+
+async fn handle_mempool_command(config: Config, cmd: MempoolCommands) -> Result<()> {
+    let local = format!("http://127.0.0.1:{}", config.api.rest_port);
+    let client = reqwest::Client::new();
+    
+    match cmd {
+        MempoolCommands::Dump { file, url, api_key } => {
+            let base = url.unwrap_or(local);
+            let request = client.get(format!("{}/api/v1/mempool/dump", base)).bearer_auth(api_key);
+            let dump: MempoolDump = api_request(request).await?;
+            
+            let json = serde_json::to_vec(&dump)
+                .map_err(|e| QtcError::InvalidInput(format!("Failed to serialize mempool dump: {}", e)))?;
+            std::fs::write(&file, json)?;
+            println!("🗂️ Dumped {} mempool transaction(s) at height {} to {}", dump.entries.len(), dump.height, file);
         }
         
-        _ => {
-            // Handle other message types
+        MempoolCommands::Load { file, url, api_key } => {
+            let dump: MempoolDump = serde_json::from_slice(&std::fs::read(&file)?)
+                .map_err(|e| QtcError::InvalidInput(format!("Invalid mempool dump {}: {}", file, e)))?;
+            let total = dump.entries.len();
+            
+            let base = url.unwrap_or(local);
+            let request = client.post(format!("{}/api/v1/mempool/load", base)).bearer_auth(api_key).json(&dump);
+            let result: MempoolLoadResult = api_request(request).await?;
+            
+            println!("🗂️ Loaded {} of {} transaction(s) into the mempool", result.loaded, total);
+            if result.skipped > 0 {
+                println!("⚠️ {} skipped: already in the mempool or invalid on this node's chain", result.skipped);
+            }
         }
     }
     
     Ok(())
 }
 
-async fn handle_network_command(config: Config, _db: Arc<Database>, cmd: NetworkCommands) -> Result<()> {
+fn format_ban_time(timestamp: u64) -> String {
+    chrono::DateTime::from_timestamp(timestamp as i64, 0)
+        .map(|t| t.format("%Y-%m-%d %H:%M").to_string())
+        .unwrap_or_default()
+}
+
+/// Send a request to a node's REST API and unwrap the `ApiResponse` envelope
+async fn api_request<T: serde::de::DeserializeOwned>(request: reqwest::RequestBuilder) -> Result<T> {
+    let response = request.send().await
+        .map_err(|e| QtcError::Network(format!("Node unreachable: {}", e)))?;
+    let response: ApiResponse<T> = response.json().await
+        .map_err(|e| QtcError::Network(format!("Invalid response from node: {}", e)))?;
+    response.data
+        .ok_or_else(|| QtcError::Network(response.error.unwrap_or_else(|| "No data returned".to_string())))
+}
+
+async fn handle_network_command(config: Config, cmd: NetworkCommands) -> Result<()> {
     match cmd {
         NetworkCommands::Status => {
             println!("🌐 Network Status:");
@@ -694,6 +1597,10 @@ async fn handle_network_command(config: Config, _db: Arc<Database>, cmd: Network
             println!("Max peers: {}", config.network.max_peers);
             println!("mDNS enabled: {}", config.network.enable_mdns);
             println!("Bootstrap nodes: {}", config.network.bootstrap_nodes.len());
+            match federation(&config)? {
+                Some(allowlist) => println!("Federation: {} member(s)", allowlist.len()),
+                None => println!("Federation: off (open network)"),
+            }
         }
         
         NetworkCommands::Peers => {
@@ -711,6 +1618,11 @@ async fn handle_network_command(config: Config, _db: Arc<Database>, cmd: Network
             // Implementation would send disconnect command to P2P node
         }
         
+        NetworkCommands::Identity => {
+            let identity = node_identity(&config)?;
+            println!("🆔 Peer ID: {}", libp2p::PeerId::from(identity.public()));
+        }
+        
         NetworkCommands::AddPeer { address, description: _ } => {
             println!("📝 Adding peer to address book: {}", address);
             // Implementation would store peer in database
@@ -718,7 +1630,64 @@ async fn handle_network_command(config: Config, _db: Arc<Database>, cmd: Network
         
         NetworkCommands::Stats => {
             println!("📊 Network Statistics:");
-            println!("(Statistics available when node is running)");
+            
+            // Peer state lives in the running node, so ask its REST API
+            let url = format!("http://127.0.0.1:{}/api/v1/network/diversity", config.api.rest_port);
+            let response = match reqwest::get(&url).await {
+                Ok(response) => response,
+                Err(_) => {
+                    println!("(Statistics available when node is running)");
+                    return Ok(());
+                }
+            };
+            let response: ApiResponse<DiversityStats> = response.json().await
+                .map_err(|e| QtcError::Network(format!("Invalid response from node: {}", e)))?;
+            let stats = response.data
+                .ok_or_else(|| QtcError::Network(response.error.unwrap_or_else(|| "No data returned".to_string())))?;
+            
+            println!("Connected peers: {}", stats.peer_count);
+            println!("Peer diversity ({} grouping, max {} per group):", stats.grouping, stats.max_peers_per_group);
+            println!("  Distinct groups: {}", stats.distinct_groups);
+            for (group, count) in &stats.groups {
+                println!("  {:<24} {}", group, count);
+            }
+            println!("  Connections refused for diversity: {}", stats.rejected_connections);
+        }
+        
+        NetworkCommands::Ban { target, duration, reason, url, api_key } => {
+            // Bans live in the running node, which holds the database
+            let base = url.unwrap_or_else(|| format!("http://127.0.0.1:{}", config.api.rest_port));
+            let body = serde_json::json!({
+                "target": target,
+                "duration_secs": duration.unwrap_or(config.network.ban_duration_secs),
+                "reason": reason,
+            });
+            let request = reqwest::Client::new().post(format!("{}/api/v1/network/bans", base)).bearer_auth(api_key).json(&body);
+            let ban: Ban = api_request(request).await?;
+            println!("🚫 Banned {} until {}", ban.target, format_ban_time(ban.banned_until));
+            println!("(connected peers are dropped at the node's next maintenance pass)");
+        }
+        
+        NetworkCommands::Unban { target, url, api_key } => {
+            let base = url.unwrap_or_else(|| format!("http://127.0.0.1:{}", config.api.rest_port));
+            let request = reqwest::Client::new().delete(format!("{}/api/v1/network/bans/{}", base, target)).bearer_auth(api_key);
+            let target: String = api_request(request).await?;
+            println!("✅ Lifted the ban on {}", target);
+        }
+        
+        NetworkCommands::ListBans { url, api_key } => {
+            let base = url.unwrap_or_else(|| format!("http://127.0.0.1:{}", config.api.rest_port));
+            let request = reqwest::Client::new().get(format!("{}/api/v1/network/bans", base)).bearer_auth(api_key);
+            let bans: Vec<Ban> = api_request(request).await?;
+            if bans.is_empty() {
+                println!("No banned peers");
+                return Ok(());
+            }
+            
+            println!("🚫 Banned peers:");
+            for ban in bans {
+                println!("  {:<54} until {}  {}", ban.target, format_ban_time(ban.banned_until), ban.reason);
+            }
         }
         
         NetworkCommands::Sync { force: _ } => {
@@ -730,8 +1699,8 @@ async fn handle_network_command(config: Config, _db: Arc<Database>, cmd: Network
     Ok(())
 }
 
-async fn handle_chain_command(db: Arc<Database>, cmd: ChainCommands) -> Result<()> {
-    let blockchain = Blockchain::new(db)?;
+async fn handle_chain_command(config: Config, db: Arc<Database>, cmd: ChainCommands) -> Result<()> {
+    let mut blockchain = open_blockchain(&config, db)?;
     
     match cmd {
         ChainCommands::Info => {
@@ -739,8 +1708,19 @@ async fn handle_chain_command(db: Arc<Database>, cmd: ChainCommands) -> Result<(
             println!("⛓️  Blockchain Information:");
             println!("Height: {}", info.height);
             println!("Tip hash: {}", info.tip);
-            println!("Difficulty: {}", info.difficulty);
-            println!("Total supply: {:.8} QTC", info.total_supply as f64 / 100_000_000.0);
+            println!("Bits: {:#010x}", info.bits);
+            println!("Difficulty: {}", info.difficulty());
+            println!("Total supply: {}", config.units.format(info.total_supply));
+            println!("Chain work: {} (minimum {})", info.total_work, blockchain.minimum_chain_work());
+            println!("Status: {}", blockchain.sync_status());
+            let pruning = blockchain.prune_status()?;
+            if let Some(target_mb) = pruning.target_mb {
+                println!("Pruning: block files {:.1} MB of {} MB target", pruning.block_files_size as f64 / (1024.0 * 1024.0), target_mb);
+                match pruning.pruned_height {
+                    Some(height) => println!("Pruned up to height: {}", height),
+                    None => println!("Pruned up to height: nothing pruned yet"),
+                }
+            }
         }
         
         ChainCommands::Block { identifier, verbose } => {
@@ -759,7 +1739,7 @@ async fn handle_chain_command(db: Arc<Database>, cmd: ChainCommands) -> Result<(
                 println!("Height: {}", block.header.height);
                 println!("Previous hash: {}", block.header.previous_hash);
                 println!("Timestamp: {}", block.header.timestamp);
-                println!("Difficulty: {}", block.header.difficulty);
+                println!("Bits: {:#010x}", block.header.bits);
                 println!("Nonce: {}", block.header.nonce);
                 println!("Transactions: {}", block.transactions.len());
                 
@@ -773,13 +1753,90 @@ async fn handle_chain_command(db: Arc<Database>, cmd: ChainCommands) -> Result<(
             }
         }
         
-        ChainCommands::Transaction { hash, raw: _ } => {
-            if let Ok(tx_hash) = crate::crypto::hash::Hash256::from_hex(&hash) {
-                // Implementation would look up transaction
-                println!("💰 Transaction: {}", tx_hash);
-            } else {
+        ChainCommands::Transaction { hash, raw } => {
+            let Ok(txid) = crate::crypto::hash::Hash256::from_hex(&hash) else {
                 println!("❌ Invalid transaction hash");
+                return Ok(());
+            };
+            let (tx, pooled) = match blockchain.database().get_transaction(&txid)? {
+                Some(tx) => (tx, false),
+                None => match blockchain.mempool.read().unwrap().get(&txid) {
+                    Some(entry) => (entry.tx.clone(), true),
+                    None => {
+                        println!("❌ Transaction not found");
+                        return Ok(());
+                    }
+                },
+            };
+            
+            if raw {
+                let raw_tx = bincode::serialize(&tx)
+                    .map_err(|e| QtcError::Storage(format!("Failed to serialize transaction: {}", e)))?;
+                println!("{}", hex::encode(raw_tx));
+                return Ok(());
             }
+            
+            println!("💰 Transaction: {}{}", txid, if pooled { " (unconfirmed)" } else { "" });
+            println!("Version: {}", tx.version);
+            println!("Lock time: {}", tx.lock_time);
+            println!("Size: {} bytes", tx.size());
+            if tx.is_coinbase() {
+                println!("Inputs: coinbase");
+            } else {
+                println!("Inputs ({}):", tx.inputs.len());
+                for input in &tx.inputs {
+                    println!("  {}:{}", input.previous_output.txid, input.previous_output.vout);
+                }
+            }
+            println!("Outputs ({}):", tx.outputs.len());
+            for (vout, output) in tx.outputs.iter().enumerate() {
+                match output.data_payload() {
+                    Some(data) => {
+                        let text = data_as_text(data).map(|text| format!(" \"{}\"", text)).unwrap_or_default();
+                        println!("  {}: OP_RETURN {} ({} bytes){} {}",
+                            vout, hex::encode(data), data.len(), text, config.units.format(output.value));
+                    }
+                    None => println!("  {}: {} {}",
+                        vout, UtxoSet::output_address(&output.script_pubkey), config.units.format(output.value)),
+                }
+            }
+        }
+        
+        ChainCommands::Txout { outpoint } => {
+            let outpoint: OutPoint = outpoint.parse()?;
+            
+            match blockchain.get_txout(&outpoint)? {
+                TxOutStatus::Unspent(utxo) => {
+                    println!("🪙 Unspent output {}:{}", outpoint.txid, outpoint.vout);
+                    println!("Value: {}", config.units.format(utxo.value));
+                    println!("Address: {}", utxo.address);
+                    println!("Height: {}", utxo.height);
+                    println!("Confirmations: {}", blockchain.height.saturating_sub(utxo.height) + 1);
+                    println!("Coinbase: {}", utxo.is_coinbase);
+                }
+                TxOutStatus::Spent(spent) => {
+                    println!("➡️  Output {}:{} is spent", outpoint.txid, outpoint.vout);
+                    println!("Spent by: {} (input {})", spent.spending_txid, spent.input_index);
+                    println!("Spent at height: {}", spent.height);
+                }
+                TxOutStatus::Unknown => {
+                    println!("❌ Output not found");
+                }
+            }
+        }
+        
+        ChainCommands::ScanTxoutSet { descriptors } => {
+            println!("🔎 Scanning UTXO set at height {}...", blockchain.height);
+            let result = blockchain.scan_txout_set(&descriptors)?;
+            
+            for unspent in &result.unspents {
+                println!("  {}:{} {} (height {}, {})",
+                    unspent.txid, unspent.vout, config.units.format(unspent.amount),
+                    unspent.height, unspent.descriptor);
+            }
+            println!("Searched: {} outputs", result.searched_items);
+            println!("Matches: {}", result.unspents.len());
+            println!("Total: {}", config.units.format(result.total_amount));
         }
         
         ChainCommands::Blocks { count, from } => {
@@ -798,23 +1855,118 @@ async fn handle_chain_command(db: Arc<Database>, cmd: ChainCommands) -> Result<(
             println!("🔍 Search functionality not yet implemented");
         }
         
-        ChainCommands::Validate { from_height: _, quick: _ } => {
-            println!("✅ Blockchain validation not yet implemented");
+        ChainCommands::Validate { from_height, quick } => {
+            let from_height = from_height.unwrap_or(0);
+            println!("🔍 Validating {} from height {} to {}...",
+                if quick { "headers" } else { "blocks" }, from_height, blockchain.height);
+            
+            let scratch_dir = config.storage.data_dir.join("validate.scratch");
+            let report = revalidate_chain(&blockchain, from_height, quick, &scratch_dir);
+            if scratch_dir.exists() {
+                std::fs::remove_dir_all(&scratch_dir)?;
+            }
+            let report = report?;
+            
+            match &report.inconsistency {
+                None => {
+                    println!("✅ {} block(s) valid", report.blocks_checked);
+                    if !quick {
+                        println!("Transactions checked: {}", report.transactions_checked);
+                        println!("UTXO set matches the replayed chain ({} outputs)", report.utxos_compared);
+                    }
+                }
+                Some(inconsistency) => {
+                    println!("❌ Inconsistency at height {}{}", inconsistency.height,
+                        inconsistency.hash.map(|hash| format!(" ({})", hash)).unwrap_or_default());
+                    println!("Reason: {}", inconsistency.reason);
+                    println!("{} block(s) before it are valid", report.blocks_checked);
+                    return Err(QtcError::Blockchain(format!("Chain validation failed at height {}", inconsistency.height)));
+                }
+            }
         }
         
-        ChainCommands::Mempool => {
+        ChainCommands::AuditSupply => {
+            println!("🧮 Auditing the coin supply up to height {}...", blockchain.height);
+            let audit = SupplyAudit::run(&blockchain)?;
+            let stored = blockchain.get_chain_info()?.total_supply;
+            
+            println!("Blocks audited: {}", audit.blocks_audited);
+            println!("Expected supply: {}", config.units.format(audit.expected));
+            println!("Audited supply: {}", config.units.format(audit.supply));
+            println!("Provably unspendable: {}", config.units.format(audit.unspendable));
+            println!("Circulating: {}", config.units.format(audit.circulating()));
+            if audit.unclaimed() > 0 {
+                println!("Unclaimed by miners: {}", config.units.format(audit.unclaimed()));
+            }
+            if stored != audit.expected {
+                println!("⚠️ The chain state records a total supply of {}, not the expected {}",
+                    config.units.format(stored), config.units.format(audit.expected));
+            }
+            
+            if !audit.is_sound() {
+                for discrepancy in &audit.discrepancies {
+                    println!("❌ Block {} ({}) issued {}, {} more than its {}",
+                        discrepancy.height, discrepancy.hash,
+                        config.units.format(discrepancy.issued),
+                        config.units.format(discrepancy.issued - discrepancy.allowed),
+                        config.units.format(discrepancy.allowed));
+                }
+                return Err(QtcError::Consensus(format!(
+                    "Supply audit found {} of inflation", config.units.format(audit.inflation())
+                )));
+            }
+            println!("✅ No block issued more than the monetary policy allows");
+        }
+        
+        ChainCommands::Mempool { .. } => {
             println!("🗂️ Mempool: 0 transactions");
         }
         
-        ChainCommands::EstimateFee { blocks: _ } => {
-            println!("💸 Estimated fee: 1000 satoshis/byte");
+        ChainCommands::EstimateFee { blocks } => {
+            let estimate = blockchain.estimate_fee(blocks.unwrap_or(6));
+            println!("💸 Estimated fee: {} satoshis/byte to confirm within {} block(s)", estimate.fee_rate, estimate.blocks);
+            match estimate.basis {
+                FeeBasis::History => println!("   Based on recent confirmation times"),
+                FeeBasis::Mempool => println!("   Raised to outbid the current mempool"),
+                FeeBasis::Minimum => println!("   Not enough data yet; this is the minimum rate"),
+            }
+        }
+        
+        ChainCommands::InvalidateBlock { hash } => {
+            let hash = crate::crypto::hash::Hash256::from_hex(&hash)
+                .map_err(|_| QtcError::InvalidInput("Block hash must be 64 hex characters".to_string()))?;
+            blockchain.invalidate_block(&hash)?;
+            println!("🚫 Block {} marked invalid", hash);
+            println!("Tip: {} at height {}", blockchain.tip, blockchain.height);
+        }
+        
+        ChainCommands::ReconsiderBlock { hash } => {
+            let hash = crate::crypto::hash::Hash256::from_hex(&hash)
+                .map_err(|_| QtcError::InvalidInput("Block hash must be 64 hex characters".to_string()))?;
+            blockchain.reconsider_block(&hash)?;
+            println!("♻️ Block {} reconsidered", hash);
+            println!("Tip: {} at height {}", blockchain.tip, blockchain.height);
+        }
+        
+        ChainCommands::Generate { blocks, address } => {
+            if !config.is_regtest() {
+                return Err(QtcError::InvalidInput(
+                    "Blocks can only be generated on regtest (use --network regtest)".to_string()
+                ));
+            }
+            
+            let hashes = crate::mining::generate_blocks(&mut blockchain, blocks, &address)?;
+            println!("🧪 Generated {} block(s), tip now at height {}", hashes.len(), blockchain.height);
+            for hash in hashes {
+                println!("{}", hash);
+            }
         }
     }
     
     Ok(())
 }
 
-async fn handle_api_command(config: Config, _db: Arc<Database>, cmd: ApiCommands) -> Result<()> {
+async fn handle_api_command(config: Config, db: Arc<Database>, cmd: ApiCommands) -> Result<()> {
     match cmd {
         ApiCommands::Start { rest_port, ws_port, cors: _ } => {
             let rest_port = rest_port.unwrap_or(config.api.rest_port);
@@ -841,12 +1993,218 @@ async fn handle_api_command(config: Config, _db: Arc<Database>, cmd: ApiCommands
         ApiCommands::Test { endpoint: _ } => {
             println!("🧪 API testing not yet implemented");
         }
+        
+        ApiCommands::Keys(command) => handle_api_key_command(&db, command)?,
+        
+        ApiCommands::Worker { .. } => unreachable!("API workers run without the database"),
+    }
+    
+    Ok(())
+}
+
+/// Have the running node write the backup, since it holds the database lock; None if no node is running
+#[cfg(unix)]
+async fn backup_via_node(config: &Config, dest: &std::path::Path) -> Result<Option<BackupReport>> {
+    let client = ControlClient::new(config.control_socket_path());
+    let request = ControlRequest::Backup { path: dest.to_path_buf() };
+    match client.call(&request).await {
+        Ok(report) => Ok(Some(report)),
+        Err(QtcError::Network(message)) if message.starts_with("Failed to connect") => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+#[cfg(not(unix))]
+async fn backup_via_node(_config: &Config, _dest: &std::path::Path) -> Result<Option<BackupReport>> {
+    Ok(None)
+}
+
+fn print_backup_report(report: &BackupReport) {
+    println!("✅ Backup created and verified");
+    println!("📁 File: {}", report.path.display());
+    println!("📏 Size: {:.2} MB", report.size as f64 / 1024.0 / 1024.0);
+    println!("📦 Chain height: {}", report.height);
+    println!("🔍 Verified: {} trees, {} entries, {} blocks", report.trees, report.entries, report.blocks);
+}
+
+/// Drive the running node's miner over its control socket
+#[cfg(unix)]
+async fn handle_mining_control(config: Config, cmd: MiningCommands) -> Result<()> {
+    let socket = config.control_socket_path();
+    let client = ControlClient::new(&socket);
+    let not_running = |e: QtcError| match e {
+        QtcError::Network(message) if message.starts_with("Failed to connect") => QtcError::Network(
+            format!("No running node at {}; start one with `qtcd start`", socket.display())
+        ),
+        e => e,
+    };
+    
+    match cmd {
+        MiningCommands::Start { address, payouts, threads, fast } => {
+            let request = ControlRequest::MiningStart { address, payouts, threads, fast };
+            let stats: MiningStats = client.call(&request).await.map_err(not_running)?;
+            println!("⛏️ Mining started on the node");
+            print_payees(&stats);
+            println!("Threads: {}", stats.threads);
+            println!("RandomX mode: {}", if stats.fast_mode { "fast" } else { "light" });
+        }
+        MiningCommands::Stop => {
+            let stats: MiningStats = client.call(&ControlRequest::MiningStop).await.map_err(not_running)?;
+            println!("🛑 Mining stopped");
+            println!("Blocks mined: {}", stats.blocks_mined);
+            println!("Total hashes: {}", stats.total_hashes);
+        }
+        _ => {
+            let status: MiningStatus = client.call(&ControlRequest::MiningStatus).await.map_err(not_running)?;
+            match status.stats {
+                Some(stats) if status.mining => {
+                    println!("⛏️ Status: mining");
+                    print_mining_stats(&config, &stats);
+                }
+                _ => println!("⛏️ Status: not mining"),
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Hand wallet sends to the node behind the control socket when one answers there,
+/// else relay them from a P2P node of our own
+#[cfg(unix)]
+async fn attach_relay(config: &Config, wallet_cli: &mut WalletCli, blockchain: Arc<RwLock<Blockchain>>) -> Result<()> {
+    let client = ControlClient::new(config.control_socket_path());
+    match client.request(&ControlRequest::Get { path: "/api/v1/info".to_string() }).await {
+        Ok(_) => wallet_cli.set_node(client),
+        Err(QtcError::Network(message)) if message.starts_with("Failed to connect") => {
+            wallet_cli.set_p2p_commands(start_relay_node(config, blockchain, None).await?);
+        }
+        Err(e) => return Err(e),
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+async fn attach_relay(config: &Config, wallet_cli: &mut WalletCli, blockchain: Arc<RwLock<Blockchain>>) -> Result<()> {
+    wallet_cli.set_p2p_commands(start_relay_node(config, blockchain, None).await?);
+    Ok(())
+}
+
+/// The running node's mining status, or None when no node is running
+#[cfg(unix)]
+async fn mining_status_via_node(config: &Config) -> Result<Option<MiningStatus>> {
+    let client = ControlClient::new(config.control_socket_path());
+    match client.call(&ControlRequest::MiningStatus).await {
+        Ok(status) => Ok(Some(status)),
+        Err(QtcError::Network(message)) if message.starts_with("Failed to connect") => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+#[cfg(not(unix))]
+async fn mining_status_via_node(_config: &Config) -> Result<Option<MiningStatus>> {
+    Ok(None)
+}
+
+/// The address mined to, or each address of a split and its share
+fn print_payees(stats: &MiningStats) {
+    if stats.payouts.is_empty() {
+        println!("Mining address: {}", stats.mining_address);
+        return;
+    }
+    println!("Payouts:");
+    for payout in &stats.payouts {
+        println!("  {} {}%", payout.address, format_percent(payout.basis_points));
+    }
+}
+
+fn print_mining_stats(config: &Config, stats: &MiningStats) {
+    if stats.payouts.is_empty() {
+        println!("Mining address: {}", stats.mining_address);
+    }
+    println!("Threads: {}", stats.threads);
+    println!("Hashrate: {:.2} H/s", stats.hashrate);
+    println!("Blocks mined: {}", stats.blocks_mined);
+    println!("Mined value: {}", config.units.format(stats.total_mined_value));
+    if !stats.payouts.is_empty() {
+        println!("Accrued per address:");
+        for payout in &stats.payouts {
+            println!("  {} ({}%): {}", payout.address, format_percent(payout.basis_points), config.units.format(payout.accrued));
+        }
+    }
+    println!("Uptime: {} seconds", stats.uptime_seconds);
+}
+
+#[cfg(not(unix))]
+async fn handle_mining_control(_config: Config, _cmd: MiningCommands) -> Result<()> {
+    Err(QtcError::InvalidInput("Controlling the node's miner needs Unix domain sockets".to_string()))
+}
+
+/// Workers leave the database to the node and ask it everything over the control socket
+#[cfg(unix)]
+async fn run_api_worker(config: Config, socket: Option<String>, port: Option<u16>) -> Result<()> {
+    let socket = socket.map(Into::into).unwrap_or_else(|| config.control_socket_path());
+    
+    let mut worker = ApiWorker::new(&socket, config.api.clone());
+    let port = port.unwrap_or(config.api.rest_port);
+    worker.set_port(port);
+    println!("🧷 API worker for {} on http://localhost:{}", socket.display(), port);
+    
+    tokio::select! {
+        result = worker.start() => result,
+        _ = signal::ctrl_c() => {
+            println!("\n🛑 Stopping API worker...");
+            Ok(())
+        }
+    }
+}
+
+#[cfg(not(unix))]
+async fn run_api_worker(_config: Config, _socket: Option<String>, _port: Option<u16>) -> Result<()> {
+    Err(QtcError::InvalidInput("API workers need Unix domain sockets".to_string()))
+}
+
+fn handle_api_key_command(db: &Database, cmd: ApiKeyCommands) -> Result<()> {
+    match cmd {
+        ApiKeyCommands::Create { name, scopes } => {
+            let scopes = scopes.iter()
+                .map(|scope| scope.parse())
+                .collect::<Result<Vec<ApiScope>>>()?;
+            let (key, token) = create_api_key(db, &name, scopes, "cli")?;
+            
+            println!("🔑 Created API key {} ({})", key.id, key.name);
+            println!("Scopes: {}", key.scopes.iter().map(|s| s.to_string()).collect::<Vec<_>>().join(", "));
+            println!("Token: {}", token);
+            println!("⚠️  Store the token now, it cannot be shown again");
+        }
+        
+        ApiKeyCommands::Revoke { id } => {
+            let key = revoke_api_key(db, &id, "cli")?;
+            println!("🚫 Revoked API key {} ({})", key.id, key.name);
+        }
+        
+        ApiKeyCommands::List => {
+            let keys = db.list_api_keys()?;
+            if keys.is_empty() {
+                println!("No API keys");
+                return Ok(());
+            }
+            
+            println!("🔑 API keys:");
+            for key in keys {
+                let created = chrono::DateTime::from_timestamp(key.created_at as i64, 0)
+                    .map(|t| t.format("%Y-%m-%d %H:%M").to_string())
+                    .unwrap_or_default();
+                let status = if key.is_active() { "active" } else { "revoked" };
+                let scopes: Vec<String> = key.scopes.iter().map(|s| s.to_string()).collect();
+                println!("  {}  {:<20} {:<8} {:<40} {}", key.id, key.name, status, scopes.join(","), created);
+            }
+        }
     }
     
     Ok(())
 }
 
-async fn handle_db_command(db: Arc<Database>, cmd: DbCommands) -> Result<()> {
+async fn handle_db_command(config: &Config, db: Arc<Database>, cmd: DbCommands) -> Result<()> {
     match cmd {
         DbCommands::Stats => {
             let stats = db.get_database_stats()?;
@@ -868,44 +2226,11 @@ async fn handle_db_command(db: Arc<Database>, cmd: DbCommands) -> Result<()> {
         }
         
         DbCommands::Backup { path } => {
+            // No node is running, so nothing else writes while the copy is taken
             println!("💾 Creating database backup...");
-            
-            // Get database path from config
-            let config = Config::load().unwrap_or_default();
-            let db_path = &config.storage.data_dir;
-            
-            if !db_path.exists() {
-                return Err(QtcError::Storage("Database directory does not exist".to_string()));
-            }
-            
-            // Create backup file
-            let backup_file = File::create(&path)
-                .map_err(|e| QtcError::Storage(format!("Failed to create backup file: {}", e)))?;
-            
-            // Create gzip encoder
-            let gz_encoder = GzBuilder::new()
-                .filename(format!("qtc-backup-{}.tar", chrono::Utc::now().format("%Y%m%d-%H%M%S")))
-                .write(backup_file, Compression::default());
-            
-            // Create tar builder
-            let mut tar_builder = Builder::new(gz_encoder);
-            
-            // Add entire database directory to archive
-            tar_builder.append_dir_all("qtc-data", db_path)
-                .map_err(|e| QtcError::Storage(format!("Failed to create backup archive: {}", e)))?;
-            
-            // Finalize the archive
-            tar_builder.finish()
-                .map_err(|e| QtcError::Storage(format!("Failed to finalize backup: {}", e)))?;
-            
-            let file_size = std::fs::metadata(&path)
-                .map(|m| m.len())
-                .unwrap_or(0);
-            
-            println!("✅ Backup created successfully!");
-            println!("📁 File: {}", path);
-            println!("📏 Size: {:.2} MB", file_size as f64 / 1024.0 / 1024.0);
-            log::info!("Database backup created successfully at {}", path);
+            let blockchain = RwLock::new(open_blockchain(config, db)?);
+            let report = create_backup(&blockchain, &config.storage.data_dir, &std::path::absolute(&path)?)?;
+            print_backup_report(&report);
         }
         
         DbCommands::Repair => {
@@ -915,6 +2240,158 @@ async fn handle_db_command(db: Arc<Database>, cmd: DbCommands) -> Result<()> {
         DbCommands::Reindex { from_height: _ } => {
             println!("🔄 Blockchain reindexing not yet implemented");
         }
+        
+        DbCommands::Encrypt => unreachable!("db encrypt runs before the database is opened"),
+        
+        DbCommands::RotateKey { new_key_file } => {
+            let secret = match new_key_file {
+                Some(path) => StorageSecret::KeyFile(path.into()),
+                None => StorageSecret::Passphrase(prompt_storage_passphrase("New storage passphrase", true)?),
+            };
+            db.rotate_encryption_key(&secret)?;
+            db.record_audit_event(AuditAction::ConfigChanged, "cli", "storage key rotated".to_string())?;
+            println!("✅ Storage key rotated");
+            println!("Update storage.encryption.key_file in the config if the key file changed");
+        }
+        
+        DbCommands::Snapshot(SnapshotCommands::Create { path }) => {
+            let blockchain = open_blockchain(config, db)?;
+            println!("📸 Writing the UTXO set at height {}...", blockchain.height);
+            let info = UtxoSnapshot::from_chain(&blockchain)?.write(std::path::Path::new(&path))?;
+            print_snapshot_info(config, &info);
+            println!("✅ Snapshot written to {}", path);
+            println!("Publish the hash with the file, so others can load it with --expect-hash");
+        }
+        
+        DbCommands::Snapshot(SnapshotCommands::Load { path, expect_hash }) => {
+            if config.storage.addrindex {
+                return Err(QtcError::InvalidInput(
+                    "storage.addrindex needs every block; turn it off to start from a snapshot".to_string()
+                ));
+            }
+            let (snapshot, info) = UtxoSnapshot::read(std::path::Path::new(&path))?;
+            match expect_hash {
+                Some(expected) if !expected.trim().eq_ignore_ascii_case(&info.hash.to_string()) => {
+                    return Err(QtcError::InvalidInput(format!(
+                        "Snapshot hash {} does not match the expected {}", info.hash, expected.trim()
+                    )));
+                }
+                Some(_) => println!("✅ Snapshot hash matches"),
+                None => println!("⚠️ Loading without --expect-hash: the outputs are taken on trust from whoever made this file"),
+            }
+            
+            // Puts the genesis block in place on a fresh data directory
+            drop(open_blockchain(config, db.clone())?);
+            snapshot.load_into(&db)?;
+            let blockchain = open_blockchain(config, db.clone())?;
+            db.record_audit_event(AuditAction::ConfigChanged, "cli",
+                format!("bootstrapped from UTXO snapshot {} at height {}", info.hash, info.height))?;
+            
+            print_snapshot_info(config, &info);
+            println!("✅ Node bootstrapped at height {}; start it to sync on from block {}", blockchain.height, blockchain.height + 1);
+            println!("Blocks below the snapshot are not stored, so wallets can't rescan them");
+        }
+    }
+    
+    Ok(())
+}
+
+fn print_snapshot_info(config: &Config, info: &SnapshotInfo) {
+    println!("Height: {}", info.height);
+    println!("Tip: {}", info.tip);
+    println!("Unspent outputs: {} holding {}", info.utxo_count, config.units.format(info.total_value));
+    println!("Size: {:.1} MB", info.size as f64 / 1024.0 / 1024.0);
+    println!("Hash: {}", info.hash);
+}
+
+async fn handle_audit_command(db: Arc<Database>, cmd: AuditCommands) -> Result<()> {
+    match cmd {
+        AuditCommands::Log { since, limit } => {
+            let since = match since {
+                Some(since) => parse_since(&since)?,
+                None => 0,
+            };
+            
+            let events = db.get_audit_events(since, limit)?;
+            if events.is_empty() {
+                println!("📝 No audit entries found");
+                return Ok(());
+            }
+            
+            println!("📝 Audit log ({} entries):", events.len());
+            for event in events {
+                let time = chrono::DateTime::from_timestamp(event.timestamp as i64, 0)
+                    .map(|t| t.to_rfc3339())
+                    .unwrap_or_else(|| event.timestamp.to_string());
+                println!("  {} [{}] {} - {}", time, event.origin, event.action, event.details);
+            }
+        }
+    }
+    
+    Ok(())
+}
+
+/// Accepts compact bits in hex (`0x207fffff`) or decimal
+fn parse_bits(bits: &str) -> Result<u32> {
+    let parsed = match bits.strip_prefix("0x").or_else(|| bits.strip_prefix("0X")) {
+        Some(hex) => u32::from_str_radix(hex, 16),
+        None => bits.parse(),
+    };
+    parsed.map_err(|_| QtcError::InvalidInput(format!("Invalid bits: {}", bits)))
+}
+
+/// Accepts a unix timestamp, an RFC 3339 time, or an age such as `30m`, `24h` or `7d`
+fn parse_since(since: &str) -> Result<u64> {
+    if let Ok(timestamp) = since.parse::<u64>() {
+        return Ok(timestamp);
+    }
+    
+    if let Ok(time) = chrono::DateTime::parse_from_rfc3339(since) {
+        return Ok(time.timestamp().max(0) as u64);
+    }
+    
+    let invalid = || QtcError::InvalidInput(format!("Invalid --since value: {}", since));
+    let (amount, unit) = since.split_at(since.len().saturating_sub(1));
+    let amount: u64 = amount.parse().map_err(|_| invalid())?;
+    let seconds = match unit {
+        "s" => amount,
+        "m" => amount * 60,
+        "h" => amount * 3600,
+        "d" => amount * 86_400,
+        _ => return Err(invalid()),
+    };
+    
+    Ok((chrono::Utc::now().timestamp() as u64).saturating_sub(seconds))
+}
+
+async fn handle_util_command(config: Config, cmd: UtilCommands) -> Result<()> {
+    match cmd {
+        UtilCommands::Simulate { hashrate_curve, hashrate, blocks, seed, output } => {
+            let curve = match (hashrate_curve, hashrate) {
+                (Some(path), _) => HashrateCurve::from_csv(&std::fs::read_to_string(&path)?)?,
+                (None, Some(hashrate)) if hashrate > 0.0 => HashrateCurve::constant(hashrate),
+                _ => return Err(QtcError::InvalidInput("Give --hashrate-curve <file> or a positive --hashrate".to_string())),
+            };
+            
+            let results = EmissionSimulator::with_params(seed, &config.chain_params()?).run(&curve, blocks)?;
+            let csv = simulation_to_csv(&results);
+            
+            match output {
+                Some(path) => {
+                    std::fs::write(&path, csv)?;
+                    if let Some(last) = results.last() {
+                        let elapsed: f64 = results.iter().map(|b| b.block_time_secs).sum();
+                        let avg_block_time = elapsed / last.height as f64;
+                        println!("📈 Simulated {} blocks over {:.1} days, written to {}",
+                            last.height, elapsed / 86_400.0, path);
+                        println!("Average block time: {:.1}s", avg_block_time);
+                        println!("Final difficulty: {}", last.difficulty);
+                        println!("Emitted: {}", config.units.format(last.total_supply));
+                    }
+                }
+                None => print!("{}", csv),
+            }
+        }
     }
     
     Ok(())
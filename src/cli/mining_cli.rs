@@ -1,10 +1,15 @@
-use crate::cli::commands::MiningCommands;
+use crate::cli::commands::{MiningCommands, PoolCommands};
+use crate::config::PoolConfig;
+use crate::consensus::Units;
 use crate::core::Blockchain;
 use crate::crypto::hash::Hashable;
-use crate::mining::{Miner, RandomXMiner};
-use crate::mining::difficulty::{DifficultyCalculator, DifficultyAnalyzer};
+use crate::mining::{BlockTemplateBuilder, Miner, RandomXMiner};
+use crate::mining::randomx::DATASET_ITEM_COUNT;
+use crate::mining::pool::{payable_rounds, payout_batch};
+use crate::mining::difficulty::DifficultyAnalyzer;
 use crate::crypto::keys::is_valid_address;
-use crate::Result;
+use crate::wallet::psbt::DEFAULT_FRAGMENT_LEN;
+use crate::{QtcError, Result};
 use console::{style, Emoji};
 use std::sync::{Arc, RwLock};
 use std::time::Duration;
@@ -19,25 +24,27 @@ static CROSS: Emoji<'_, '_> = Emoji("❌", "");
 
 pub struct MiningCli {
     blockchain: Arc<RwLock<Blockchain>>,
+    pool_config: Option<PoolConfig>,
+    units: Units,
 }
 
 impl MiningCli {
     pub fn new(blockchain: Arc<RwLock<Blockchain>>) -> Self {
-        Self { blockchain }
+        Self { blockchain, pool_config: None, units: Units::Qtc }
+    }
+    
+    pub fn set_pool_config(&mut self, pool_config: PoolConfig) {
+        self.pool_config = Some(pool_config);
+    }
+    
+    pub fn set_units(&mut self, units: Units) {
+        self.units = units;
     }
     
     pub async fn handle_command(&mut self, command: MiningCommands) -> Result<()> {
         match command {
-            MiningCommands::Start { address, threads, fast } => {
-                self.start_mining(address, threads, fast).await
-            }
-            
-            MiningCommands::Stop => {
-                self.stop_mining().await
-            }
-            
-            MiningCommands::Status => {
-                self.mining_status().await
+            MiningCommands::Start { .. } | MiningCommands::Stop | MiningCommands::Status => {
+                unreachable!("mining start, stop and status are sent to the running node")
             }
             
             MiningCommands::Single { address, timeout } => {
@@ -48,90 +55,32 @@ impl MiningCli {
                 self.mining_stats().await
             }
             
-            MiningCommands::Benchmark { duration } => {
-                self.benchmark(duration).await
+            MiningCommands::Benchmark { duration, fast } => {
+                self.benchmark(duration, fast).await
             }
             
             MiningCommands::Difficulty => {
                 self.show_difficulty().await
             }
             
+            MiningCommands::Template { max_size, min_fee_rate, json } => {
+                self.show_template(max_size, min_fee_rate, json).await
+            }
+            
             MiningCommands::Profitability { hashrate, power, cost_per_kwh } => {
                 self.calculate_profitability(hashrate, power, cost_per_kwh).await
             }
+            
+            MiningCommands::Pool { command: PoolCommands::Stats } => {
+                self.pool_stats().await
+            }
+            
+            MiningCommands::Pool { command: PoolCommands::Payout { fee_rate, output } } => {
+                self.pool_payout(fee_rate, output).await
+            }
         }
     }
     
-    async fn start_mining(&self, address: String, threads: Option<usize>, fast: bool) -> Result<()> {
-        println!("{} {} Starting QTC mining...", PICKAXE, style("RandomX Mining").bold().green());
-        
-        // Validate mining address
-        if !is_valid_address(&address) {
-            println!("{} Invalid mining address: {}", CROSS, address);
-            return Ok(());
-        }
-        
-        let thread_count = threads.unwrap_or(num_cpus::get());
-        let mode = if fast { "Fast Mode (2GB RAM)" } else { "Light Mode (256MB RAM)" };
-        
-        println!("Mining address: {}", style(&address).bold().cyan());
-        println!("Threads: {}", style(thread_count).bold());
-        println!("Mode: {}", style(mode).bold());
-        
-        // Get current blockchain info
-        let (height, difficulty) = {
-            let blockchain = self.blockchain.read().unwrap();
-            (blockchain.height, blockchain.get_current_difficulty()?)
-        };
-        
-        println!("Current height: {}", height);
-        println!("Current difficulty: {}", difficulty);
-        
-        // Create and start miner
-        let miner = Miner::new(self.blockchain.clone(), address, thread_count)?;
-        
-        println!("\n{} Mining started! Press Ctrl+C to stop.", CHECK);
-        println!("Monitor progress with: qtcd mine status");
-        
-        // Start mining (this will run indefinitely)
-        if let Err(e) = miner.start_mining().await {
-            println!("{} Mining error: {}", CROSS, e);
-        }
-        
-        Ok(())
-    }
-    
-    async fn stop_mining(&self) -> Result<()> {
-        println!("{} {} Stopping mining...", PICKAXE, style("RandomX Mining").bold().red());
-        
-        // Implementation would send stop signal to running miner
-        println!("{} Mining stop signal sent", CHECK);
-        println!("(In a full implementation, this would communicate with the running miner process)");
-        
-        Ok(())
-    }
-    
-    async fn mining_status(&self) -> Result<()> {
-        println!("{} {} Mining Status", CHART, style("RandomX Mining").bold().cyan());
-        
-        // This would typically check if mining is running and show real stats
-        println!("Status: {} (demo mode)", style("Not running").red());
-        println!("Hashrate: 0.0 H/s");
-        println!("Blocks mined: 0");
-        println!("Uptime: 0 seconds");
-        
-        // Show current difficulty and estimated time to block
-        let difficulty = {
-            let blockchain = self.blockchain.read().unwrap();
-            blockchain.get_current_difficulty()?
-        };
-        
-        println!("Current difficulty: {}", difficulty);
-        println!("Est. time to block: Unknown (no active mining)");
-        
-        Ok(())
-    }
-    
     async fn mine_single_block(&self, address: String, timeout: Option<u64>) -> Result<()> {
         println!("{} {} Mining single block...", DIAMOND, style("RandomX Mining").bold().green());
         
@@ -231,28 +180,28 @@ impl MiningCli {
         println!("{} {} Mining Statistics", CHART, style("RandomX Mining").bold().cyan());
         
         // Get blockchain stats
-        let (height, difficulty, total_supply) = {
+        let (height, bits, total_supply, calc, block_reward) = {
             let blockchain = self.blockchain.read().unwrap();
             let chain_info = blockchain.get_chain_info()?;
-            (chain_info.height, chain_info.difficulty, chain_info.total_supply)
+            let block_reward = blockchain.monetary_policy().coinbase_reward(chain_info.height + 1);
+            (chain_info.height, chain_info.bits, chain_info.total_supply,
+                blockchain.chain_params().difficulty_calculator(), block_reward)
         };
         
         println!("Network Statistics:");
         println!("  Current height: {}", height);
-        println!("  Current difficulty: {}", difficulty);
-        println!("  Total supply: {:.8} QTC", total_supply as f64 / 100_000_000.0);
+        println!("  Current difficulty: {} (bits {:#010x})", calc.bits_to_difficulty(bits), bits);
+        println!("  Total supply: {}", self.units.format(total_supply));
         
         // Calculate difficulty-related stats
-        let calc = DifficultyCalculator::new();
-        let estimated_hashrate = calc.estimate_hashrate(difficulty, 450); // 7.5 minutes
+        let estimated_hashrate = calc.estimate_hashrate(bits, calc.target_block_time);
         let time_to_adjustment = calc.time_to_next_adjustment(height);
         
         println!("  Estimated network hashrate: {:.2} H/s", estimated_hashrate);
         println!("  Blocks to next difficulty adjustment: {}", time_to_adjustment);
         
         // Mining economics
-        let block_reward = crate::consensus::monetary::MonetaryPolicy::new().coinbase_reward(height + 1);
-        println!("  Current block reward: {:.8} QTC", block_reward as f64 / 100_000_000.0);
+        println!("  Current block reward: {}", self.units.format(block_reward));
         
         // Personal mining stats (would be real in full implementation)
         println!("\nPersonal Mining Statistics:");
@@ -265,7 +214,7 @@ impl MiningCli {
         Ok(())
     }
     
-    async fn benchmark(&self, duration: Option<u64>) -> Result<()> {
+    async fn benchmark(&self, duration: Option<u64>, fast: bool) -> Result<()> {
         let duration_secs = duration.unwrap_or(30);
         
         println!("{} {} Running RandomX benchmark...", LIGHTNING, style("RandomX Benchmark").bold().yellow());
@@ -287,7 +236,7 @@ impl MiningCli {
         
         pb.set_message("Running benchmark...");
         
-        let miner = match RandomXMiner::new(&seed, None, false) {
+        let miner = match RandomXMiner::new(&seed, None, fast) {
             Ok(miner) => miner,
             Err(e) => {
                 pb.finish_and_clear();
@@ -343,39 +292,73 @@ impl MiningCli {
         
         // Memory usage info
         println!("\nRandomX Configuration:");
-        println!("Mode: Light (256MB)");
+        if fast {
+            println!("Mode: Fast (shared {} MB dataset)", DATASET_ITEM_COUNT * 32 / (1024 * 1024));
+        } else {
+            println!("Mode: Light (dataset items computed per hash)");
+        }
         println!("JIT compilation: {}", if cfg!(target_arch = "x86_64") { "Available" } else { "Not available" });
         println!("AES-NI support: Detected (if available)");
         
         Ok(())
     }
     
+    async fn show_template(&self, max_size: Option<usize>, min_fee_rate: Option<u64>, json: bool) -> Result<()> {
+        let mut builder = BlockTemplateBuilder::new().with_min_fee_rate(min_fee_rate.unwrap_or(0));
+        if let Some(max_size) = max_size {
+            builder = builder.with_max_size(max_size);
+        }
+        let template = builder.build(&self.blockchain.read().unwrap())?;
+        
+        if json {
+            println!("{}", serde_json::to_string_pretty(&template)?);
+            return Ok(());
+        }
+        
+        println!("{} {} Block Template", PICKAXE, style("Mining").bold().cyan());
+        println!("Height: {}", style(template.height).bold());
+        println!("Previous block: {}", template.previous_block_hash);
+        println!("Difficulty: {}", template.difficulty);
+        println!("Target: {}", template.target);
+        println!("Coinbase value: {}", style(self.units.format(template.coinbase_value)).bold().green());
+        
+        let size: usize = template.transactions.iter().map(|tx| tx.size).sum();
+        println!("Transactions: {} ({} of {} bytes, {} sat in fees)", 
+                 template.transactions.len(), size, template.size_limit, template.total_fees);
+        for tx in &template.transactions {
+            println!("  {} {} bytes, {} sat", tx.txid, tx.size, tx.fee);
+        }
+        
+        Ok(())
+    }
+    
     async fn show_difficulty(&self) -> Result<()> {
         println!("{} {} Current Difficulty Information", CHART, style("Difficulty").bold().cyan());
         
         let blockchain = self.blockchain.read().unwrap();
-        let difficulty = blockchain.get_current_difficulty()?;
+        let bits = blockchain.get_current_bits()?;
         let height = blockchain.height;
         
-        println!("Current difficulty: {}", style(difficulty).bold().green());
-        println!("Current height: {}", height);
-        
         // Calculate target hash representation
-        let calc = DifficultyCalculator::new();
-        let _target = calc.difficulty_to_target(difficulty);
-        let leading_zeros = difficulty / 4;
+        let calc = blockchain.chain_params().difficulty_calculator();
+        let target = calc.bits_to_target(bits);
         
-        println!("Required leading zero bits: {}", difficulty);
-        println!("Required leading zero bytes: {}", leading_zeros);
-        println!("Target hash starts with: {}", "0".repeat(leading_zeros as usize));
+        println!("Current difficulty: {}", style(target.difficulty).bold().green());
+        println!("Current height: {}", height);
+        println!("Target bits: {:#010x}", bits);
+        println!("Target hash: {}", hex::encode(target.target_hash));
         
         // Difficulty adjustment info
-        let blocks_to_adjustment = calc.time_to_next_adjustment(height);
-        println!("Blocks until next adjustment: {}", blocks_to_adjustment);
+        if blockchain.chain_params().lwma_active(height + 1) {
+            println!("Retarget: every block (LWMA over the last {} blocks)", calc.lwma_window);
+        } else {
+            let blocks_to_adjustment = calc.time_to_next_adjustment(height);
+            println!("Blocks until next adjustment: {}", blocks_to_adjustment);
+        }
         
         // Estimated network stats
-        let estimated_hashrate = calc.estimate_hashrate(difficulty, 450);
-        let target_time = 450; // 7.5 minutes
+        let target_time = calc.target_block_time;
+        let estimated_hashrate = calc.estimate_hashrate(bits, target_time);
         
         println!("Target block time: {} seconds ({:.1} minutes)", target_time, target_time as f64 / 60.0);
         println!("Estimated network hashrate: {:.2} H/s", estimated_hashrate);
@@ -399,9 +382,9 @@ impl MiningCli {
         println!("{} {} Mining Profitability Calculator", CHART, style("Profitability").bold().cyan());
         
         let blockchain = self.blockchain.read().unwrap();
-        let difficulty = blockchain.get_current_difficulty()?;
+        let bits = blockchain.get_current_bits()?;
         let height = blockchain.height;
-        let block_reward = crate::consensus::monetary::MonetaryPolicy::new().coinbase_reward(height + 1);
+        let block_reward = blockchain.monetary_policy().coinbase_reward(height + 1);
         
         println!("Mining Configuration:");
         println!("  Hashrate: {:.2} H/s", hashrate);
@@ -418,7 +401,7 @@ impl MiningCli {
         
         // Calculate profitability
         let profitability = DifficultyAnalyzer::calculate_mining_profitability(
-            difficulty,
+            bits,
             hashrate,
             electricity_cost,
             power_watts,
@@ -459,4 +442,73 @@ impl MiningCli {
         
         Ok(())
     }
+    
+    fn pool_config(&self) -> Result<&PoolConfig> {
+        self.pool_config.as_ref()
+            .ok_or_else(|| QtcError::InvalidInput("No private pool configured: set [mining.pool] in the config file".to_string()))
+    }
+    
+    async fn pool_stats(&self) -> Result<()> {
+        let pool_config = self.pool_config()?;
+        let blockchain = self.blockchain.read().unwrap();
+        let db = blockchain.database();
+        
+        println!("{} {} Pool wallet {} ({} payouts, share difficulty {})", CHART, style("Pool").bold().cyan(),
+            style(&pool_config.wallet).bold(), pool_config.scheme, pool_config.share_difficulty);
+        
+        let workers = db.get_pool_workers()?;
+        if workers.is_empty() {
+            println!("No shares submitted yet");
+        }
+        for worker in &workers {
+            println!("  {} accepted: {}, stale: {}, duplicate: {}, rejected: {}, work: {}",
+                style(&worker.worker).bold(), worker.accepted, worker.stale, worker.duplicate, worker.rejected, worker.work);
+        }
+        
+        let rounds = db.get_pool_rounds()?;
+        let payable = payable_rounds(db, &blockchain)?;
+        println!("\nBlocks found: {}", rounds.len());
+        for round in &rounds {
+            let status = match &round.payout_txid {
+                Some(txid) => format!("paid in {}", txid),
+                None if payable.iter().any(|p| p.block_hash == round.block_hash) => "payable".to_string(),
+                None => "immature".to_string(),
+            };
+            println!("  #{} {} reward {}, {} payout(s), {}", round.height, round.block_hash,
+                self.units.format(round.reward), round.payouts.len(), status);
+        }
+        Ok(())
+    }
+    
+    async fn pool_payout(&self, fee_rate: Option<u64>, output: Option<String>) -> Result<()> {
+        let pool_config = self.pool_config()?;
+        let (db, rounds) = {
+            let blockchain = self.blockchain.read().unwrap();
+            let db = blockchain.database().clone();
+            let rounds = payable_rounds(&db, &blockchain)?;
+            (db, rounds)
+        };
+        if rounds.is_empty() {
+            println!("{} No matured rounds are waiting to be paid", CHECK);
+            return Ok(());
+        }
+        
+        let wallet = db.load_wallet(&pool_config.wallet, self.blockchain.clone())?;
+        let psbt = payout_batch(&db, &wallet, &rounds, fee_rate.unwrap_or(1000))?;
+        let frames = psbt.to_frames(DEFAULT_FRAGMENT_LEN)?;
+        
+        match &output {
+            Some(path) => {
+                std::fs::write(path, frames.join("\n") + "\n")?;
+                println!("{} Payout for {} round(s) written to {} as {} frame(s)", DIAMOND, rounds.len(), style(path).bold(), frames.len());
+            }
+            None => {
+                for frame in &frames {
+                    println!("{}", frame);
+                }
+            }
+        }
+        eprintln!("{} Sign it with: qtcd wallet psbt sign {} --input <FILE>", LIGHTNING, pool_config.wallet);
+        Ok(())
+    }
 }
@@ -3,5 +3,6 @@
 pub mod commands;
 pub mod wallet_cli;
 pub mod mining_cli;
+pub mod shell;
 
 pub use commands::run_cli;
@@ -0,0 +1,268 @@
+//! `qtcd shell`: an interactive prompt over a single open database
+//!
+//! A one-shot command opens sled, takes the data directory lock and replays
+//! the chain state before doing anything, then throws all of it away. The
+//! shell opens the database once and runs each line through the same
+//! dispatcher as the one-shot CLI, so every command works the same way in
+//! both. History is kept in the data directory; Tab completes subcommands,
+//! flags and wallet names. Lines can also be piped in, one command per line.
+
+use crate::cli::commands::{run_command, Cli, Commands, DataDir, DbCommands};
+use crate::config::Config;
+use crate::storage::Database;
+use crate::{QtcError, Result};
+use clap::{CommandFactory, Parser};
+use dialoguer::theme::ColorfulTheme;
+use dialoguer::{Completion, History, Input};
+use std::collections::VecDeque;
+use std::io::{BufRead, IsTerminal};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+const HISTORY_FILE: &str = "shell_history";
+const MAX_HISTORY: usize = 1_000;
+
+/// Wallet subcommands whose name argument is a wallet that doesn't exist yet
+const NEW_WALLET_COMMANDS: &[&str] = &["create", "import", "import-key", "import-foreign", "restore"];
+
+pub async fn run_shell(config: Config, db: Arc<Database>) -> Result<()> {
+    let interactive = std::io::stdin().is_terminal();
+    let mut history = ShellHistory::load(config.storage.data_dir.join(HISTORY_FILE));
+    let completion = ShellCompletion { command: Cli::command(), db: db.clone() };
+    let mut piped = (!interactive).then(|| std::io::stdin().lock().lines());
+
+    if interactive {
+        println!("🐚 QTC shell on {} ({}). Type `help` for commands, `exit` to leave.",
+            config.storage.data_dir.display(), config.network_type);
+    }
+
+    loop {
+        let line = if let Some(piped) = &mut piped {
+            match piped.next() {
+                Some(line) => line?,
+                None => break,
+            }
+        } else {
+            let input = Input::<String>::with_theme(&ColorfulTheme::default())
+                .with_prompt("qtc")
+                .allow_empty(true)
+                .history_with(&mut history)
+                .completion_with(&completion)
+                .interact_text();
+            match input {
+                Ok(line) => line,
+                Err(_) => break, // closed input or no terminal
+            }
+        };
+
+        let Some(words) = shlex::split(line.trim()) else {
+            println!("❌ Unbalanced quotes");
+            continue;
+        };
+        match words.first().map(String::as_str) {
+            None => continue,
+            Some("exit" | "quit") => break,
+            _ => {}
+        }
+
+        let command = match parse_line(&words) {
+            Ok(command) => command,
+            Err(e) => {
+                println!("❌ {}", e);
+                continue;
+            }
+        };
+        let Some(command) = command else {
+            continue;
+        };
+        if let Err(e) = run_command(config.clone(), command, DataDir::Shared(db.clone())).await {
+            println!("❌ {}", e);
+        }
+    }
+
+    if interactive {
+        history.save()?;
+    }
+    db.flush()
+}
+
+/// The command a shell line asks for; None when clap already printed help or an error
+fn parse_line(words: &[String]) -> Result<Option<Commands>> {
+    let cli = match Cli::try_parse_from(std::iter::once("qtcd").chain(words.iter().map(String::as_str))) {
+        Ok(cli) => cli,
+        Err(e) => {
+            let _ = e.print();
+            return Ok(None);
+        }
+    };
+    if cli.data_dir.is_some() || cli.port.is_some() || cli.debug || cli.config.is_some()
+        || cli.profile.is_some() || cli.network.is_some() || cli.units.is_some() || cli.force_unlock
+    {
+        return Err(QtcError::InvalidInput("Global options only apply when the shell starts".to_string()));
+    }
+
+    match cli.command {
+        Commands::Start { .. } => Err(QtcError::InvalidInput(
+            "The node runs in its own process; leave the shell to start it".to_string()
+        )),
+        Commands::Db(DbCommands::Encrypt) => Err(QtcError::InvalidInput(
+            "db encrypt needs the database closed; leave the shell to run it".to_string()
+        )),
+        Commands::Shell => Err(QtcError::InvalidInput("Already in the shell".to_string())),
+        command => Ok(Some(command)),
+    }
+}
+
+/// Lines entered at the prompt, the newest first, saved across sessions
+struct ShellHistory {
+    path: PathBuf,
+    entries: VecDeque<String>,
+}
+
+impl ShellHistory {
+    fn load(path: PathBuf) -> Self {
+        let entries = std::fs::read_to_string(&path)
+            .map(|text| text.lines().rev().take(MAX_HISTORY).map(str::to_string).collect())
+            .unwrap_or_default();
+        Self { path, entries }
+    }
+
+    fn save(&self) -> Result<()> {
+        let mut text: Vec<&str> = self.entries.iter().rev().map(String::as_str).collect();
+        text.push("");
+        std::fs::write(&self.path, text.join("\n"))?;
+        Ok(())
+    }
+}
+
+impl History<String> for ShellHistory {
+    fn read(&self, pos: usize) -> Option<String> {
+        self.entries.get(pos).cloned()
+    }
+
+    fn write(&mut self, line: &String) {
+        let line = line.trim();
+        if line.is_empty() || self.entries.front().is_some_and(|last| last == line) {
+            return;
+        }
+        self.entries.push_front(line.to_string());
+        self.entries.truncate(MAX_HISTORY);
+    }
+}
+
+/// Tab completion from the CLI's own command tree, plus the wallets in the database
+struct ShellCompletion {
+    command: clap::Command,
+    db: Arc<Database>,
+}
+
+impl ShellCompletion {
+    /// What the word being typed could be, given the words before it
+    fn candidates(&self, words: &[&str], partial: &str) -> Vec<String> {
+        let mut command = &self.command;
+        let mut path = Vec::new();
+        let mut positionals = 0;
+        let mut skip_value = false;
+        for word in words {
+            if std::mem::take(&mut skip_value) {
+                continue;
+            }
+            if let Some(long) = word.strip_prefix("--") {
+                skip_value = !long.contains('=') && command.get_arguments()
+                    .find(|arg| arg.get_long() == Some(long))
+                    .is_some_and(|arg| arg.get_action().takes_values());
+            } else if let Some(subcommand) = command.find_subcommand(word) {
+                command = subcommand;
+                path.push(subcommand.get_name());
+                positionals = 0;
+            } else {
+                positionals += 1;
+            }
+        }
+        if skip_value {
+            return Vec::new();
+        }
+
+        if partial.starts_with('-') {
+            return command.get_arguments()
+                .filter_map(|arg| arg.get_long())
+                .map(|long| format!("--{}", long))
+                .collect();
+        }
+        if command.has_subcommands() {
+            return command.get_subcommands()
+                .map(|subcommand| subcommand.get_name().to_string())
+                .chain(path.is_empty().then(|| ["exit".to_string(), "help".to_string()]).into_iter().flatten())
+                .collect();
+        }
+
+        let takes_wallet = path.first() == Some(&"wallet")
+            && !NEW_WALLET_COMMANDS.contains(&command.get_name())
+            && command.get_positionals()
+                .nth(positionals)
+                .is_some_and(|arg| matches!(arg.get_id().as_str(), "wallet" | "name"));
+        if takes_wallet {
+            return self.db.list_wallets().unwrap_or_default();
+        }
+        Vec::new()
+    }
+}
+
+impl Completion for ShellCompletion {
+    /// The line with its last word completed as far as every match agrees
+    fn get(&self, input: &str) -> Option<String> {
+        let (done, partial) = match input.rfind(' ') {
+            Some(index) => input.split_at(index + 1),
+            None => ("", input),
+        };
+        let words: Vec<&str> = done.split_whitespace().collect();
+        let mut matches: Vec<String> = self.candidates(&words, partial).into_iter()
+            .filter(|candidate| candidate.starts_with(partial))
+            .collect();
+        matches.sort();
+        matches.dedup();
+
+        let completed = match matches.as_slice() {
+            [] => return None,
+            [only] => format!("{} ", only),
+            [first, rest @ ..] => {
+                let common = rest.iter().fold(first.len(), |len, other| {
+                    first.bytes().zip(other.bytes()).take(len).take_while(|(a, b)| a == b).count()
+                });
+                first[..common].to_string()
+            }
+        };
+        Some(format!("{}{}", done, completed))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_completion_walks_the_command_tree() -> Result<()> {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Arc::new(Database::new(temp_dir.path().join("test.db"))?);
+        let completion = ShellCompletion { command: Cli::command(), db };
+
+        assert_eq!(completion.get("wal").as_deref(), Some("wallet "));
+        assert_eq!(completion.get("chain tx").as_deref(), Some("chain txout "));
+        assert_eq!(completion.get("wallet send w qtc1x 1 --fee").as_deref(), Some("wallet send w qtc1x 1 --fee-rate "));
+        assert_eq!(completion.get("wallet send w qtc1x 1 --fee-rate 5"), None);
+        assert_eq!(completion.get("nothing"), None);
+
+        let history_path = temp_dir.path().join(HISTORY_FILE);
+        let mut history = ShellHistory::load(history_path.clone());
+        for line in ["chain info", "chain info", "wallet list"] {
+            history.write(&line.to_string());
+        }
+        history.save()?;
+        let history = ShellHistory::load(history_path);
+        assert_eq!(history.read(0).as_deref(), Some("wallet list"));
+        assert_eq!(history.read(1).as_deref(), Some("chain info"));
+        assert_eq!(history.read(2), None);
+        Ok(())
+    }
+}
@@ -1,16 +1,33 @@
-use crate::cli::commands::{WalletCommands, MultisigCommands};
-use crate::core::Blockchain;
+#[cfg(unix)]
+use crate::api::cluster::{ControlClient, ControlRequest};
+use crate::cli::commands::{ContactCommands, FrameDisplayArgs, MessageCommands, WalletCommands, MultisigCommands, PsbtCommands};
+use crate::consensus::Units;
+use crate::core::{Blockchain, SigHashType, Transaction};
+use crate::core::transaction::{data_as_text, OutPoint, LOCKTIME_THRESHOLD};
+use crate::network::messaging::{DirectMessage, IdentityCard, Mailbox};
+use crate::network::p2p::P2PCommand;
 use crate::storage::Database;
-use crate::wallet::Wallet;
-use crate::wallet::wallet::WalletType;
+use crate::storage::database::AuditAction;
+use crate::wallet::{CoinSelection, Contact, ExternalSigner, HistoryKind, Wallet};
+use crate::wallet::wallet::{payment_uri, reservations_to_csv, WalletType};
 use crate::wallet::bip39::Mnemonic;
+use crate::wallet::descriptor::{self, Descriptor};
 use crate::wallet::multisig::{MultisigWallet, MultisigUtils};
+use crate::wallet::viewonly::ViewOnlyBundle;
+use crate::wallet::foreign::{ForeignFormat, ForeignWallet};
+use crate::wallet::psbt::{FrameDecoder, Psbt, PSBT_LOCK_TTL_SECS};
 use crate::crypto::keys::{PrivateKey, is_valid_address};
-use crate::crypto::hash::Hashable;
+use crate::crypto::hash::{Hash256, Hashable};
 use crate::{QtcError, Result};
 use dialoguer::{Input, Password, Confirm, Select, theme::ColorfulTheme};
-use console::{style, Emoji};
+use indicatif::{ProgressBar, ProgressStyle};
+use console::{style, Emoji, Term};
+use qrcode::render::unicode;
+use qrcode::QrCode;
+use std::io::BufRead;
 use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot};
 
 static WALLET: Emoji<'_, '_> = Emoji("💼", "");
 static KEY: Emoji<'_, '_> = Emoji("🔑", "");
@@ -19,14 +36,48 @@ static ARROW: Emoji<'_, '_> = Emoji("➡️", "");
 static CHECK: Emoji<'_, '_> = Emoji("✅", "");
 static CROSS: Emoji<'_, '_> = Emoji("❌", "");
 
+/// How long `wallet send` waits for a peer to take the transaction
+const RELAY_TIMEOUT_SECS: u64 = 20;
+
 pub struct WalletCli {
     db: Arc<Database>,
     blockchain: Arc<RwLock<Blockchain>>,
+    p2p_commands: Option<mpsc::Sender<P2PCommand>>,
+    #[cfg(unix)]
+    node: Option<ControlClient>, // a running node to hand sent transactions to
+    units: Units,
 }
 
 impl WalletCli {
     pub fn new(db: Arc<Database>, blockchain: Arc<RwLock<Blockchain>>) -> Self {
-        Self { db, blockchain }
+        Self {
+            db,
+            blockchain,
+            p2p_commands: None,
+            #[cfg(unix)]
+            node: None,
+            units: Units::Qtc,
+        }
+    }
+    
+    /// Relay sent transactions through this P2P node
+    pub fn set_p2p_commands(&mut self, p2p_commands: mpsc::Sender<P2PCommand>) {
+        self.p2p_commands = Some(p2p_commands);
+    }
+    
+    /// Submit sent transactions to this node's mempool, which relays them
+    #[cfg(unix)]
+    pub fn set_node(&mut self, node: ControlClient) {
+        self.node = Some(node);
+    }
+    
+    pub fn set_units(&mut self, units: Units) {
+        self.units = units;
+    }
+    
+    fn audit(&self, action: AuditAction, details: String) -> Result<()> {
+        self.db.record_audit_event(action, "cli", details)?;
+        Ok(())
     }
     
     pub async fn handle_command(&mut self, command: WalletCommands) -> Result<()> {
@@ -43,6 +94,10 @@ impl WalletCli {
                 self.import_key_wallet(name, wif).await
             }
             
+            WalletCommands::ImportForeign { name, format, file } => {
+                self.import_foreign_wallet(name, format, file).await
+            }
+            
             WalletCommands::List => {
                 self.list_wallets().await
             }
@@ -51,34 +106,117 @@ impl WalletCli {
                 self.wallet_info(name).await
             }
             
-            WalletCommands::Balance { name, detailed } => {
-                self.wallet_balance(name, detailed).await
+            WalletCommands::Settings { name, avoid_reuse } => {
+                self.wallet_settings(name, avoid_reuse).await
+            }
+            
+            WalletCommands::Balance { name, detailed, force_refresh } => {
+                self.wallet_balance(name, detailed, force_refresh).await
             }
             
             WalletCommands::NewAddress { name, change } => {
                 self.new_address(name, change).await
             }
             
+            WalletCommands::NewAddresses { name, count, label, export, output } => {
+                self.new_addresses(name, count, label, export, output).await
+            }
+            
+            WalletCommands::Receive { name, amount, label, no_qr } => {
+                self.receive(name, amount, label, no_qr).await
+            }
+            
             WalletCommands::Addresses { name, unused } => {
                 self.list_addresses(name, unused).await
             }
             
-            WalletCommands::Send { wallet, to, amount, fee_rate, yes } => {
-                self.send_transaction(wallet, to, amount, fee_rate, yes).await
+            WalletCommands::Label { name, address, label } => {
+                self.label_address(name, address, label).await
+            }
+            
+            WalletCommands::Contacts { command } => {
+                self.handle_contact_command(command).await
+            }
+            
+            WalletCommands::Send { wallet, to, amount, fee_rate, yes, preview, coin_selection, replaceable, locktime, data, allow_reuse, to_contact } => {
+                let data = data.map(|data| hex::decode(data.trim())
+                    .map_err(|_| QtcError::InvalidInput("--data must be hex".to_string())))
+                    .transpose()?;
+                let to = if to_contact {
+                    let contact = self.db.get_contact(&to)?
+                        .ok_or_else(|| QtcError::InvalidInput(format!("No contact named {}", to)))?;
+                    println!("📇 Paying contact {} at {}", style(&contact.name).bold(), contact.address);
+                    contact.address
+                } else {
+                    to
+                };
+                self.send_transaction(wallet, to, amount, fee_rate, yes, preview, coin_selection, replaceable, locktime.unwrap_or(0), data, allow_reuse).await
+            }
+            
+            WalletCommands::BumpFee { wallet, txid, fee_rate, yes } => {
+                self.bump_fee(wallet, txid, fee_rate, yes).await
+            }
+            
+            WalletCommands::CreateUnsigned { wallet, to, amount, fee_rate, output } => {
+                self.create_unsigned(wallet, to, amount, fee_rate, output).await
+            }
+            
+            WalletCommands::SignOffline { wallet, input, output, yes } => {
+                self.sign_offline(wallet, input, output, yes).await
+            }
+            
+            WalletCommands::Broadcast { input } => {
+                let psbt = self.read_psbt_hex(input.as_deref())?;
+                self.broadcast_psbt(psbt).await
             }
             
             WalletCommands::History { name, limit } => {
                 self.transaction_history(name, limit).await
             }
             
-            WalletCommands::Export { name, format } => {
-                self.export_wallet(name, format).await
+            WalletCommands::Rescan { name, from_height } => {
+                self.rescan_wallet(name, from_height).await
+            }
+            
+            WalletCommands::Export { name, format, view_only } => {
+                match view_only {
+                    Some(file) => self.export_view_only(name, file).await,
+                    None => self.export_wallet(name, format).await,
+                }
+            }
+            
+            WalletCommands::LockUnspent { name, outpoints, expires } => {
+                self.lock_unspent(name, outpoints, expires).await
+            }
+            
+            WalletCommands::UnlockUnspent { name, outpoints, all } => {
+                self.unlock_unspent(name, outpoints, all).await
+            }
+            
+            WalletCommands::ListLockUnspent { name } => {
+                self.list_lock_unspent(name).await
+            }
+            
+            WalletCommands::ImportViewOnly { name, file } => {
+                self.import_view_only(name, file).await
+            }
+            
+            WalletCommands::ImportDescriptor { name, descriptors, wif, range } => {
+                self.import_descriptors(name, descriptors, wif, range).await
             }
             
             WalletCommands::Multisig { command } => {
                 self.handle_multisig_command(command).await
             }
             
+            WalletCommands::Psbt { command } => {
+                self.handle_psbt_command(command).await
+            }
+            
+            WalletCommands::Message { command } => {
+                self.handle_message_command(command).await
+            }
+            
             WalletCommands::Backup { name, path } => {
                 self.backup_wallet(name, path).await
             }
@@ -126,6 +264,7 @@ impl WalletCli {
             
             let wallet = Wallet::new_hd(name.clone(), &mnemonic, &passphrase, self.db.clone(), self.blockchain.clone())?;
             wallet.save()?;
+            self.audit(AuditAction::WalletCreated, format!("hd wallet '{}'", name))?;
             
             println!("{} HD wallet '{}' created successfully!", CHECK, name);
             println!("Addresses generated: {}", wallet.info.address_count);
@@ -148,6 +287,7 @@ impl WalletCli {
                     let wallet = Wallet::new_simple(name.clone(), self.db.clone(), self.blockchain.clone())?;
                     let address = wallet.get_addresses()[0].clone();
                     wallet.save()?;
+                    self.audit(AuditAction::WalletCreated, format!("simple wallet '{}'", name))?;
                     println!("{} Simple wallet '{}' created successfully!", CHECK, name);
                     println!("Address: {}", style(address).bold().green());
                 }
@@ -155,6 +295,7 @@ impl WalletCli {
                     let wallet = Wallet::new_pqc(name.clone(), self.db.clone(), self.blockchain.clone())?;
                     let address = wallet.get_addresses()[0].clone();
                     wallet.save()?;
+                    self.audit(AuditAction::WalletCreated, format!("post-quantum wallet '{}'", name))?;
                     println!("{} Post-Quantum wallet '{}' created successfully!", CHECK, name);
                     println!("PQC Address: {}", style(address).bold().green());
                 }
@@ -162,6 +303,7 @@ impl WalletCli {
                     let wallet = Wallet::new_hybrid(name.clone(), self.db.clone(), self.blockchain.clone())?;
                     let addresses = wallet.get_addresses();
                     wallet.save()?;
+                    self.audit(AuditAction::WalletCreated, format!("hybrid wallet '{}'", name))?;
                     println!("{} Hybrid (Classic+PQC) wallet '{}' created successfully!", CHECK, name);
                     println!("Classic Address: {}", style(&addresses[0]).bold().green());
                     println!("PQC Address: {}", style(&addresses[1]).bold().green());
@@ -170,6 +312,7 @@ impl WalletCli {
                     let wallet = Wallet::new_simple(name.clone(), self.db.clone(), self.blockchain.clone())?;
                     let address = wallet.get_addresses()[0].clone();
                     wallet.save()?;
+                    self.audit(AuditAction::WalletCreated, format!("simple wallet '{}'", name))?;
                     println!("{} Simple wallet '{}' created successfully!", CHECK, name);
                     println!("Address: {}", style(address).bold().green());
                 }
@@ -211,6 +354,7 @@ impl WalletCli {
         
         let wallet = Wallet::from_mnemonic_phrase(name.clone(), &mnemonic_phrase, &passphrase, self.db.clone(), self.blockchain.clone())?;
         wallet.save()?;
+        self.audit(AuditAction::WalletCreated, format!("wallet '{}' imported from mnemonic", name))?;
         
         println!("{} Wallet '{}' imported successfully!", CHECK, name);
         println!("Addresses found: {}", wallet.info.address_count);
@@ -232,6 +376,7 @@ impl WalletCli {
         
         let mut wallet = Wallet::new_simple(name.clone(), self.db.clone(), self.blockchain.clone())?;
         let address = wallet.import_private_key(&wif)?;
+        self.audit(AuditAction::WalletCreated, format!("wallet '{}' imported from private key", name))?;
         
         println!("{} Wallet '{}' imported successfully!", CHECK, name);
         println!("Address: {}", style(address).bold().green());
@@ -239,6 +384,41 @@ impl WalletCli {
         Ok(())
     }
     
+    async fn import_foreign_wallet(&self, name: String, format: String, file: String) -> Result<()> {
+        let format: ForeignFormat = format.parse()?;
+        println!("{} {} Importing {} wallet: {}", WALLET, style("QTC Wallet").bold().cyan(), format, style(&name).bold());
+        
+        // Check if wallet already exists
+        if self.db.list_wallets()?.contains(&name) {
+            println!("{} Wallet '{}' already exists!", CROSS, name);
+            return Ok(());
+        }
+        
+        let foreign = ForeignWallet::read_from_file(format, &file)?;
+        let mut wallet = foreign.into_wallet(name.clone(), self.db.clone(), self.blockchain.clone())?;
+        
+        // Rescan: keys that already hold coins on this chain count as used
+        let balance = wallet.refresh_balance()?;
+        let funded: Vec<String> = wallet.addresses.keys()
+            .filter(|address| wallet.get_address_balance(address).unwrap_or(0) > 0)
+            .cloned()
+            .collect();
+        for address in &funded {
+            if let Some(entry) = wallet.addresses.get_mut(address) {
+                entry.used = true;
+            }
+        }
+        wallet.save()?;
+        self.audit(AuditAction::WalletCreated, format!("wallet '{}' imported from {} wallet {}", name, format, file))?;
+        
+        println!("{} Wallet '{}' imported successfully!", CHECK, name);
+        println!("Keys: {}", wallet.info.address_count);
+        println!("Funded addresses: {}", funded.len());
+        println!("Balance: {}", self.units.format(balance));
+        
+        Ok(())
+    }
+    
     async fn list_wallets(&self) -> Result<()> {
         println!("{} {} Available Wallets:", WALLET, style("QTC Wallet").bold().cyan());
         
@@ -257,20 +437,17 @@ impl WalletCli {
                     let wallet_type = match wallet.info.wallet_type {
                         WalletType::Simple => "Simple",
                         WalletType::HD => "HD (BIP39)",
-                        WalletType::Multisig { required: _, total: _ } => {
-                            // Format as string to avoid borrowing issues
-                            return Ok(());
-                        }
+                        WalletType::Multisig { required, total } => &format!("Multisig {}-of-{}", required, total),
                         WalletType::WatchOnly => "Watch-Only",
                         WalletType::PostQuantum => "Post-Quantum",
                         WalletType::HybridClassicPqc => "Hybrid PQC+Classic",
                     };
                     
-                    println!("  {} {} ({}) - Balance: {:.8} QTC", 
+                    println!("  {} {} ({}) - Balance: {}", 
                         COIN,
                         style(&wallet_name).bold(),
                         wallet_type,
-                        balance as f64 / 100_000_000.0
+                        self.units.format(balance)
                     );
                 }
                 Err(_) => {
@@ -287,22 +464,67 @@ impl WalletCli {
         
         println!("{} {} Wallet Information: {}", WALLET, style("QTC Wallet").bold().cyan(), style(&name).bold());
         println!("Type: {:?}", wallet.info.wallet_type);
+        if let Some(multisig) = wallet.multisig()? {
+            println!("Address: {}", style(&multisig.address).bold().cyan());
+            println!("Cosigners:");
+            for (index, key) in multisig.get_public_keys().iter().enumerate() {
+                let marker = if multisig.our_key_indices.contains(&index) { " (ours)" } else { "" };
+                println!("  #{} {}{}", index, key, marker);
+            }
+            println!("Descriptor: {}", multisig.export_descriptor());
+        }
         println!("Created: {}", chrono::DateTime::from_timestamp(wallet.info.created_at as i64, 0).unwrap().format("%Y-%m-%d %H:%M:%S"));
         println!("Encrypted: {}", wallet.info.is_encrypted);
         println!("Address count: {}", wallet.info.address_count);
         
         let balance = wallet.get_balance()?;
-        println!("Balance: {:.8} QTC", balance as f64 / 100_000_000.0);
+        println!("Balance: {}", self.units.format(balance));
+        
+        let gap = wallet.address_gap()?;
+        println!("Unused receive addresses: {}", gap.unused);
+        if gap.exceeded() {
+            println!("{} Address gap: {} past the last used address, above the restore gap limit of {}",
+                CROSS, style(gap.gap).bold().yellow(), gap.limit);
+            println!("  Payments to the newest addresses may be missed when restoring from seed");
+        } else {
+            println!("Address gap: {} (limit {})", gap.gap, gap.limit);
+        }
+        
+        let reused = wallet.reused_addresses()?.len();
+        println!("Avoid reuse: {}", if wallet.settings()?.avoid_reuse { "on" } else { "off" });
+        if reused > 0 {
+            println!("Reused addresses: {}", style(reused).yellow());
+        }
         
         Ok(())
     }
     
-    async fn wallet_balance(&self, name: String, detailed: bool) -> Result<()> {
+    async fn wallet_settings(&self, name: String, avoid_reuse: Option<bool>) -> Result<()> {
         let wallet = self.db.load_wallet(&name, self.blockchain.clone())?;
-        let balance = wallet.get_balance()?;
+        if let Some(avoid_reuse) = avoid_reuse {
+            wallet.set_avoid_reuse(avoid_reuse)?;
+            println!("{} Settings saved for wallet: {}", CHECK, style(&name).bold());
+        }
+        
+        let settings = wallet.settings()?;
+        println!("{} {} Settings for wallet: {}", WALLET, style("QTC Wallet").bold().cyan(), style(&name).bold());
+        println!("Avoid reuse: {}", if settings.avoid_reuse { "on" } else { "off" });
+        if settings.avoid_reuse {
+            println!("  Coins on reused addresses are only spent with `wallet send --allow-reuse`");
+        }
+        Ok(())
+    }
+    
+    async fn wallet_balance(&self, name: String, detailed: bool, force_refresh: bool) -> Result<()> {
+        let wallet = self.db.load_wallet(&name, self.blockchain.clone())?;
+        let balance = if force_refresh {
+            wallet.refresh_balance()?
+        } else {
+            wallet.get_balance()?
+        };
         
         println!("{} {} Balance for wallet: {}", COIN, style("QTC Wallet").bold().cyan(), style(&name).bold());
-        println!("Total: {:.8} QTC", balance as f64 / 100_000_000.0);
+        println!("Total: {}", self.units.format(balance));
         
         if detailed {
             println!("\n{} UTXO Breakdown:", style("Detailed").bold());
@@ -311,18 +533,18 @@ impl WalletCli {
             for address in addresses {
                 let addr_balance = wallet.get_address_balance(&address)?;
                 if addr_balance > 0 {
-                    println!("  {}: {:.8} QTC", 
+                    println!("  {}: {}", 
                         style(&address).dim(),
-                        addr_balance as f64 / 100_000_000.0
+                        self.units.format(addr_balance)
                     );
                     
                     // Show UTXOs for this address
                     let utxos = wallet.blockchain.read().unwrap().get_utxos(&address)?;
                     for (txid, vout, value) in utxos {
-                        println!("    {}:{} - {:.8} QTC", 
+                        println!("    {}:{} - {}", 
                             hex::encode(&txid.as_bytes()[0..8]),
                             vout,
-                            value as f64 / 100_000_000.0
+                            self.units.format(value)
                         );
                     }
                 }
@@ -367,8 +589,82 @@ impl WalletCli {
         Ok(())
     }
     
+    async fn new_addresses(&self, name: String, count: u32, label: Option<String>, export: Option<String>, output: Option<String>) -> Result<()> {
+        let mut wallet = self.db.load_wallet(&name, self.blockchain.clone())?;
+        let reservations = wallet.reserve_receive_addresses(count, label)?;
+        
+        let rendered = match export.as_deref() {
+            Some("csv") => Some(reservations_to_csv(&reservations)),
+            Some("json") => Some(serde_json::to_string_pretty(&reservations)?),
+            Some(other) => {
+                return Err(QtcError::InvalidInput(format!("Unknown export format '{}' (expected csv or json)", other)));
+            }
+            None => None,
+        };
+        
+        match (rendered, output) {
+            (Some(data), Some(path)) => {
+                std::fs::write(&path, data)?;
+                println!("{} Reserved {} addresses for wallet '{}', exported to {}", CHECK, reservations.len(), name, path);
+            }
+            (Some(data), None) => print!("{}", data),
+            (None, _) => {
+                println!("{} {} Reserved {} addresses for wallet '{}':", KEY, style("New").bold().green(), reservations.len(), name);
+                for reservation in &reservations {
+                    println!("  {}", style(&reservation.address).cyan());
+                }
+            }
+        }
+        
+        Ok(())
+    }
+    
+    async fn receive(&self, name: String, amount: Option<String>, label: Option<String>, no_qr: bool) -> Result<()> {
+        let amount = match amount {
+            Some(amount) => match amount.parse::<f64>() {
+                Ok(qtc) if qtc > 0.0 => Some((qtc * 100_000_000.0).round() as u64),
+                _ => return Err(QtcError::InvalidInput(format!("Invalid amount: {}", amount))),
+            },
+            None => None,
+        };
+        
+        let mut wallet = self.db.load_wallet(&name, self.blockchain.clone())?;
+        let reservation = wallet.reserve_receive_address(label.clone())?;
+        
+        // Plain addresses keep the QR code small; a URI is only needed to carry extras
+        let uri = (amount.is_some() || label.is_some())
+            .then(|| payment_uri(&reservation.address, amount, label.as_deref()));
+        
+        println!("{} {} Receive to wallet '{}'", COIN, style("QTC Wallet").bold().cyan(), name);
+        if !no_qr {
+            let payload = uri.as_deref().unwrap_or(&reservation.address);
+            let code = QrCode::new(payload.as_bytes())
+                .map_err(|e| QtcError::InvalidInput(format!("Failed to encode QR code: {}", e)))?;
+            let image = code.render::<unicode::Dense1x2>()
+                .dark_color(unicode::Dense1x2::Light)
+                .light_color(unicode::Dense1x2::Dark)
+                .quiet_zone(true)
+                .build();
+            println!("\n{}\n", image);
+        }
+        
+        println!("Address: {}", style(&reservation.address).bold().green());
+        if let Some(amount) = amount {
+            println!("Amount: {}", self.units.format(amount));
+        }
+        if let Some(uri) = &uri {
+            println!("URI: {}", uri);
+        }
+        println!("{} Address reserved and won't be offered again", CHECK);
+        
+        Ok(())
+    }
+    
     async fn list_addresses(&self, name: String, unused: bool) -> Result<()> {
-        let wallet = self.db.load_wallet(&name, self.blockchain.clone())?;
+        let mut wallet = self.db.load_wallet(&name, self.blockchain.clone())?;
+        wallet.sync_used_addresses()?;
+        let address_use = wallet.address_use()?;
+        let book = wallet.address_book()?;
         
         println!("{} {} Addresses for wallet: {}", KEY, style("QTC Wallet").bold().cyan(), style(&name).bold());
         
@@ -376,25 +672,92 @@ impl WalletCli {
         
         for address in addresses {
             let balance = wallet.get_address_balance(&address)?;
-            let has_balance = balance > 0;
+            let used = wallet.addresses.get(&address).is_some_and(|addr| addr.used) || balance > 0;
             
-            if unused && has_balance {
+            if unused && used {
                 continue;
             }
             
-            let status = if has_balance {
-                style(format!("{:.8} QTC", balance as f64 / 100_000_000.0)).green()
-            } else {
-                style("Unused".to_string()).dim()
+            let status = match address_use.get(&address).copied().unwrap_or(0) {
+                _ if !used => style("Unused".to_string()).dim(),
+                uses if uses > 1 => style(format!("{} (reused, {} transactions)", self.units.format(balance), uses)).yellow(),
+                _ if balance > 0 => style(self.units.format(balance)).green(),
+                _ => style("Used".to_string()).dim(),
             };
             
-            println!("  {} - {}", style(&address).cyan(), status);
+            match book.name(&address) {
+                Some(label) => println!("  {} - {} [{}]", style(&address).cyan(), status, style(label).bold()),
+                None => println!("  {} - {}", style(&address).cyan(), status),
+            }
         }
         
         Ok(())
     }
     
-    async fn send_transaction(&self, wallet_name: String, to: String, amount_str: String, fee_rate: Option<u64>, yes: bool) -> Result<()> {
+    async fn label_address(&self, name: String, address: String, label: String) -> Result<()> {
+        let wallet = self.db.load_wallet(&name, self.blockchain.clone())?;
+        wallet.set_label(&address, &label)?;
+        
+        if label.trim().is_empty() {
+            println!("{} Label removed from {}", CHECK, style(&address).cyan());
+        } else {
+            println!("{} Labelled {} as {}", CHECK, style(&address).cyan(), style(label.trim()).bold());
+        }
+        Ok(())
+    }
+    
+    async fn handle_contact_command(&self, command: ContactCommands) -> Result<()> {
+        match command {
+            ContactCommands::Add { name, address } => {
+                let contact = Contact::new(&name, &address)?;
+                if self.db.get_contact(&contact.name)?.is_some() {
+                    println!("{} A contact named {} already exists; remove it first", CROSS, style(&contact.name).bold());
+                    return Ok(());
+                }
+                self.db.save_contact(&contact)?;
+                println!("{} Added contact {} ({})", CHECK, style(&contact.name).bold(), contact.address);
+                Ok(())
+            }
+            
+            ContactCommands::List => {
+                let contacts = self.db.list_contacts()?;
+                if contacts.is_empty() {
+                    println!("No contacts. Add one with `wallet contacts add <name> <address>`");
+                    return Ok(());
+                }
+                println!("📇 {} contact(s):", contacts.len());
+                for contact in &contacts {
+                    println!("  {} - {}", style(&contact.name).bold(), style(&contact.address).cyan());
+                }
+                Ok(())
+            }
+            
+            ContactCommands::Remove { name } => {
+                if self.db.remove_contact(&name)? {
+                    println!("{} Removed contact {}", CHECK, style(&name).bold());
+                } else {
+                    println!("{} No contact named {}", CROSS, name);
+                }
+                Ok(())
+            }
+        }
+    }
+    
+    #[allow(clippy::too_many_arguments)]
+    async fn send_transaction(
+        &self,
+        wallet_name: String,
+        to: String,
+        amount_str: String,
+        fee_rate: Option<u64>,
+        yes: bool,
+        preview: bool,
+        coin_selection: CoinSelection,
+        replaceable: bool,
+        lock_time: u64,
+        data: Option<Vec<u8>>,
+        allow_reuse: bool,
+    ) -> Result<()> {
         let wallet = self.db.load_wallet(&wallet_name, self.blockchain.clone())?;
         
         // Validate recipient address
@@ -402,6 +765,10 @@ impl WalletCli {
             println!("{} Invalid recipient address: {}", CROSS, to);
             return Ok(());
         }
+        if self.destination_seen_before(&wallet, &to)? {
+            println!("⚠️ {} has received coins before; paying it again links the payments together.", style(&to).bold());
+            println!("  Ask the recipient for a fresh address if you can");
+        }
         
         // Parse amount
         let amount = match amount_str.parse::<f64>() {
@@ -415,10 +782,10 @@ impl WalletCli {
         // Check balance
         let balance = wallet.get_balance()?;
         if balance < amount {
-            println!("{} Insufficient funds: have {:.8} QTC, need {:.8} QTC", 
+            println!("{} Insufficient funds: have {}, need {}", 
                 CROSS,
-                balance as f64 / 100_000_000.0,
-                amount as f64 / 100_000_000.0
+                self.units.format(balance),
+                self.units.format(amount)
             );
             return Ok(());
         }
@@ -428,8 +795,34 @@ impl WalletCli {
         println!("{} {} Preparing transaction:", ARROW, style("QTC Wallet").bold().cyan());
         println!("From wallet: {}", style(&wallet_name).bold());
         println!("To address: {}", style(&to).bold().cyan());
-        println!("Amount: {:.8} QTC", amount as f64 / 100_000_000.0);
+        println!("Amount: {}", self.units.format(amount));
         println!("Fee rate: {} sat/byte", fee_rate);
+        println!("Coin selection: {}", coin_selection);
+        if replaceable {
+            println!("Replaceable: yes (fee can be bumped with `wallet bump-fee`)");
+        }
+        if lock_time >= LOCKTIME_THRESHOLD {
+            let time = chrono::DateTime::from_timestamp(lock_time as i64, 0)
+                .map(|time| time.format("%Y-%m-%d %H:%M:%S").to_string())
+                .unwrap_or_else(|| lock_time.to_string());
+            println!("Lock time: {}", time);
+        } else if lock_time > 0 {
+            println!("Lock time: height {}", lock_time);
+        }
+        if let Some(data) = &data {
+            match data_as_text(data) {
+                Some(text) => println!("Data: {} bytes (\"{}\")", data.len(), text),
+                None => println!("Data: {} bytes", data.len()),
+            }
+        }
+        
+        if allow_reuse {
+            println!("Allow reuse: yes (coins on reused addresses may be spent)");
+        }
+        
+        if preview {
+            return self.print_transaction_preview(&wallet, &to, amount, fee_rate, coin_selection, data.as_deref(), allow_reuse);
+        }
         
         if !yes {
             if !Confirm::with_theme(&ColorfulTheme::default())
@@ -443,11 +836,31 @@ impl WalletCli {
         }
         
         // Create transaction
-        match wallet.create_transaction(&to, amount, fee_rate) {
+        match wallet.create_transaction_with(&to, amount, fee_rate, coin_selection, replaceable, lock_time, data.as_deref(), allow_reuse) {
             Ok(tx) => {
+                self.audit(AuditAction::TransactionSent, format!(
+                    "{} from wallet '{}': {} to {}",
+                    tx.hash(), wallet_name, Units::Qtc.format(amount), to
+                ))?;
                 println!("{} Transaction created successfully!", CHECK);
                 println!("Transaction ID: {}", hex::encode(tx.hash().as_bytes()));
-                println!("(Broadcasting not implemented in this demo)");
+                
+                let next_height = self.blockchain.read().unwrap().height + 1;
+                if !tx.is_final(next_height, chrono::Utc::now().timestamp() as u64) {
+                    let raw = bincode::serialize(&tx)
+                        .map_err(|e| QtcError::Wallet(format!("Failed to serialize transaction: {}", e)))?;
+                    println!("{} Not final until its lock time, so it is not broadcast yet.", ARROW);
+                    println!("Broadcast it then with the sendrawtransaction RPC:");
+                    println!("{}", hex::encode(raw));
+                    return Ok(());
+                }
+                
+                // Check it against our copy of the chain; this pool goes away with the process
+                if let Err(e) = self.blockchain.read().unwrap().accept_to_mempool(tx.clone()) {
+                    println!("{} Transaction rejected by the local mempool: {}", CROSS, e);
+                    return Ok(());
+                }
+                self.relay_transaction(tx).await?;
             }
             Err(e) => {
                 println!("{} Failed to create transaction: {}", CROSS, e);
@@ -457,29 +870,258 @@ impl WalletCli {
         Ok(())
     }
     
+    async fn bump_fee(&self, wallet_name: String, txid: String, fee_rate: u64, yes: bool) -> Result<()> {
+        let wallet = self.db.load_wallet(&wallet_name, self.blockchain.clone())?;
+        let txid = Hash256::from_hex(&txid)
+            .map_err(|_| QtcError::InvalidInput(format!("Invalid transaction ID: {}", txid)))?;
+        
+        let replacement = match wallet.bump_fee(&txid, fee_rate) {
+            Ok(tx) => tx,
+            Err(e) => {
+                println!("{} Failed to bump fee: {}", CROSS, e);
+                return Ok(());
+            }
+        };
+        
+        println!("{} {} Replacing transaction:", ARROW, style("QTC Wallet").bold().cyan());
+        println!("Original: {}", txid);
+        println!("Replacement: {}", style(replacement.hash()).bold());
+        println!("Fee rate: {} sat/byte", fee_rate);
+        
+        if !yes && !Confirm::with_theme(&ColorfulTheme::default())
+            .with_prompt("Broadcast replacement?")
+            .interact()
+            .map_err(|e| QtcError::Wallet(format!("Interaction error: {}", e)))?
+        {
+            println!("{} Replacement cancelled, the original transaction stands", CROSS);
+            return Ok(());
+        }
+        
+        if let Err(e) = self.blockchain.read().unwrap().accept_to_mempool(replacement.clone()) {
+            println!("{} Replacement rejected by the local mempool: {}", CROSS, e);
+            return Ok(());
+        }
+        self.audit(AuditAction::TransactionSent, format!(
+            "{} from wallet '{}': fee bump of {}", replacement.hash(), wallet_name, txid
+        ))?;
+        self.relay_transaction(replacement).await
+    }
+    
+    /// Whether `to` was paid before: by the address index when it is complete,
+    /// otherwise by its unspent outputs, or by the wallet's own record if it's ours
+    fn destination_seen_before(&self, wallet: &Wallet, to: &str) -> Result<bool> {
+        if wallet.addresses.get(to).is_some_and(|addr| addr.used) {
+            return Ok(true);
+        }
+        if self.db.is_address_history_complete()? {
+            return Ok(!self.db.get_address_history(to, 1)?.is_empty());
+        }
+        Ok(!self.blockchain.read().unwrap().get_utxos(to)?.is_empty())
+    }
+    
+    /// Hand `tx` to the P2P node and wait until it has gone out to a peer
+    async fn relay_transaction(&self, tx: Transaction) -> Result<()> {
+        #[cfg(unix)]
+        if let Some(node) = &self.node {
+            let raw = bincode::serialize(&tx)
+                .map_err(|e| QtcError::Wallet(format!("Failed to serialize transaction: {}", e)))?;
+            let request = ControlRequest::Broadcast { raw_transaction: hex::encode(raw) };
+            let txid: String = node.call(&request).await?;
+            println!("{} Transaction {} accepted by the node's mempool and relayed", CHECK, txid);
+            return Ok(());
+        }
+        
+        let Some(p2p_commands) = &self.p2p_commands else {
+            println!("(No P2P node attached, transaction was not relayed)");
+            return Ok(());
+        };
+        
+        let stopped = |_| QtcError::Network("P2P node stopped".to_string());
+        let (done, relayed) = oneshot::channel();
+        p2p_commands.send(P2PCommand::BroadcastTransaction(tx)).await.map_err(stopped)?;
+        p2p_commands.send(P2PCommand::WaitForRelay(done)).await.map_err(stopped)?;
+        
+        println!("{} Waiting for peers...", ARROW);
+        match tokio::time::timeout(Duration::from_secs(RELAY_TIMEOUT_SECS), relayed).await {
+            Ok(Ok(())) => println!("{} Transaction relayed to the network", CHECK),
+            _ => println!("{} No peers reachable within {}s, transaction was not relayed", CROSS, RELAY_TIMEOUT_SECS),
+        }
+        Ok(())
+    }
+    
+    #[allow(clippy::too_many_arguments)]
+    fn print_transaction_preview(
+        &self,
+        wallet: &Wallet,
+        to: &str,
+        amount: u64,
+        fee_rate: u64,
+        coin_selection: CoinSelection,
+        data: Option<&[u8]>,
+        allow_reuse: bool,
+    ) -> Result<()> {
+        let preview = match wallet.preview_transaction_with(to, amount, fee_rate, coin_selection, data, allow_reuse) {
+            Ok(preview) => preview,
+            Err(e) => {
+                println!("{} Failed to build preview: {}", CROSS, e);
+                return Ok(());
+            }
+        };
+        
+        println!("\n{} {} (nothing signed or broadcast)", COIN, style("Transaction preview").bold());
+        println!("Inputs ({}):", preview.inputs.len());
+        for input in &preview.inputs {
+            println!("  {}:{} {} ({})", input.txid, input.vout, self.units.format(input.value), input.address);
+        }
+        println!("Outputs:");
+        for output in &preview.outputs {
+            println!("  {} {}", output.address, self.units.format(output.value));
+        }
+        match &preview.change {
+            Some(change) => println!("Change: {} {}", change.address, self.units.format(change.value)),
+            None => println!("Change: none"),
+        }
+        println!("Estimated size: {} bytes", preview.estimated_vsize);
+        println!("Fee: {} ({:.2} sat/byte)", self.units.format(preview.fee), preview.effective_fee_rate());
+        Ok(())
+    }
+    
+    async fn lock_unspent(&self, name: String, outpoints: Vec<String>, expires: Option<u64>) -> Result<()> {
+        let wallet = self.db.load_wallet(&name, self.blockchain.clone())?;
+        let outpoints = outpoints.iter()
+            .map(|outpoint| outpoint.parse::<OutPoint>())
+            .collect::<Result<Vec<_>>>()?;
+        
+        // The CLI exits right away, so its locks are always persisted
+        wallet.lock_unspent(&outpoints, expires, true)?;
+        
+        println!("{} Locked {} output(s) for wallet '{}'", CHECK, outpoints.len(), name);
+        if let Some(secs) = expires {
+            println!("Locks expire in {} seconds", secs);
+        }
+        Ok(())
+    }
+    
+    async fn unlock_unspent(&self, name: String, outpoints: Vec<String>, all: bool) -> Result<()> {
+        let wallet = self.db.load_wallet(&name, self.blockchain.clone())?;
+        
+        let released = if all {
+            wallet.unlock_all_unspent()?
+        } else {
+            if outpoints.is_empty() {
+                println!("{} Specify outputs to unlock or use --all", CROSS);
+                return Ok(());
+            }
+            let outpoints = outpoints.iter()
+                .map(|outpoint| outpoint.parse::<OutPoint>())
+                .collect::<Result<Vec<_>>>()?;
+            wallet.unlock_unspent(&outpoints)?
+        };
+        
+        println!("{} Unlocked {} output(s) for wallet '{}'", CHECK, released, name);
+        Ok(())
+    }
+    
+    async fn list_lock_unspent(&self, name: String) -> Result<()> {
+        let wallet = self.db.load_wallet(&name, self.blockchain.clone())?;
+        let mut locks = wallet.list_locked_unspent()?;
+        
+        println!("{} {} Locked outputs for wallet: {}", KEY, style("QTC Wallet").bold().cyan(), style(&name).bold());
+        
+        if locks.is_empty() {
+            println!("No locked outputs.");
+            return Ok(());
+        }
+        
+        locks.sort_by_key(|(_, lock)| lock.locked_at);
+        for (outpoint, lock) in locks {
+            let expiry = match lock.expires_at {
+                Some(expires_at) => format!("expires at {}", expires_at),
+                None => "until unlocked".to_string(),
+            };
+            let kind = if lock.persistent { "persistent" } else { "in-memory" };
+            println!("  {}:{} ({}, {})", outpoint.txid, outpoint.vout, kind, expiry);
+        }
+        
+        Ok(())
+    }
+    
     async fn transaction_history(&self, name: String, limit: Option<usize>) -> Result<()> {
         let wallet = self.db.load_wallet(&name, self.blockchain.clone())?;
-        let _limit = limit.unwrap_or(10);
+        let limit = limit.unwrap_or(10);
         
         println!("{} {} Transaction history for wallet: {}", COIN, style("QTC Wallet").bold().cyan(), style(&name).bold());
         
-        // Get transaction history
         let history = wallet.get_transaction_history()?;
+        let book = wallet.address_book()?;
         
         if history.is_empty() {
             println!("No transactions found.");
             return Ok(());
         }
         
-        for (hash, tx, height) in history {
-            let tx_type = if tx.is_coinbase() { "Coinbase" } else { "Transfer" };
-            println!("  {} {} (Block {}): {}", 
+        for entry in history.iter().take(limit) {
+            let time = chrono::DateTime::from_timestamp(entry.timestamp as i64, 0)
+                .map(|time| time.format("%Y-%m-%d %H:%M").to_string())
+                .unwrap_or_default();
+            let sign = if entry.net < 0 { "-" } else { "+" };
+            let fee = entry.fee.map(|fee| format!(", fee {}", self.units.format(fee))).unwrap_or_default();
+            println!("  {} {:<8} {} (net {}{}{}) {} confirmation(s), {}",
                 COIN,
-                tx_type,
-                height,
-                hex::encode(&hash.as_bytes()[0..8])
+                entry.kind.to_string(),
+                self.units.format(entry.amount),
+                sign,
+                self.units.format(entry.net.unsigned_abs()),
+                fee,
+                entry.confirmations,
+                time,
             );
+            println!("      {}", entry.txid.to_hex());
+            let names: Vec<&str> = entry.addresses.iter().filter_map(|address| book.name(address)).collect();
+            if !names.is_empty() {
+                let direction = if entry.kind == HistoryKind::Send { "to" } else { "on" };
+                println!("      {} {}", direction, style(names.join(", ")).bold());
+            }
+        }
+        if history.len() > limit {
+            println!("  ... {} older transaction(s); pass --limit to see more", history.len() - limit);
+        }
+        
+        Ok(())
+    }
+    
+    async fn rescan_wallet(&self, name: String, from_height: Option<u64>) -> Result<()> {
+        let mut wallet = self.db.load_wallet(&name, self.blockchain.clone())?;
+        println!("{} {} Rescanning wallet: {}", WALLET, style("QTC Wallet").bold().cyan(), style(&name).bold());
+        
+        let pb = ProgressBar::new(0);
+        pb.set_style(ProgressStyle::default_bar()
+            .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] block {pos}/{len} ({eta})")
+            .unwrap()
+            .progress_chars("#>-"));
+        let report = wallet.rescan(from_height, |height, tip| {
+            pb.set_length(tip);
+            pb.set_position(height);
+        });
+        pb.finish_and_clear();
+        let report = report?;
+        
+        if report.blocks_scanned == 0 {
+            println!("{} Already scanned to the tip at height {}", CHECK, report.to_height);
+        } else {
+            println!("{} Scanned {} block(s), heights {} to {}", CHECK, report.blocks_scanned, report.from_height, report.to_height);
+        }
+        for entry in &report.transactions {
+            let sign = if entry.net < 0 { "-" } else { "+" };
+            println!("  {} {:<8} {}{} at height {}  {}",
+                COIN, entry.kind.to_string(), sign, self.units.format(entry.net.unsigned_abs()), entry.height, entry.txid.to_hex());
         }
+        println!("Transactions found: {}", report.transactions.len());
+        println!("Addresses newly marked used: {}", report.newly_used);
+        if report.derived > 0 {
+            println!("Receive addresses derived: {}", report.derived);
+        }
+        println!("Balance: {}", self.units.format(report.balance));
         
         Ok(())
     }
@@ -503,6 +1145,7 @@ impl WalletCli {
             "mnemonic" => {
                 if let Some(hd_wallet) = &wallet.hd_wallet {
                     let xprv = hd_wallet.export_xprv()?;
+                    self.audit(AuditAction::KeyExported, format!("xprv of wallet '{}'", name))?;
                     println!("Extended Private Key: {}", style(xprv).yellow());
                     println!("\n{} Keep this private key secure!", style("WARNING:").bold().red());
                 } else {
@@ -512,17 +1155,32 @@ impl WalletCli {
             
             "wif" => {
                 let addresses = wallet.get_addresses();
+                let mut exported = 0;
                 for address in addresses {
                     if let Ok(wif) = wallet.export_private_key(&address) {
                         println!("Address: {}", address);
                         println!("Private Key (WIF): {}", style(wif).yellow());
                         println!();
+                        exported += 1;
                     }
                 }
+                if exported > 0 {
+                    self.audit(AuditAction::KeyExported, format!("{} WIF key(s) of wallet '{}'", exported, name))?;
+                }
             }
             
             "descriptor" => {
-                println!("Descriptor export not yet implemented");
+                let descriptors = descriptor::wallet_descriptors(&wallet)?;
+                if descriptors.is_empty() {
+                    println!("{} Wallet has no addresses to describe", CROSS);
+                }
+                for descriptor in &descriptors {
+                    println!("{}", descriptor.to_string_with_checksum());
+                }
+                if !descriptors.is_empty() {
+                    println!("\nDescriptors hold public keys only. Rebuild a watch-only copy with:");
+                    println!("  qtcd wallet import-descriptor <name> <descriptor>...");
+                }
             }
             
             _ => {
@@ -533,6 +1191,85 @@ impl WalletCli {
         Ok(())
     }
     
+    async fn export_view_only(&self, name: String, file: String) -> Result<()> {
+        let wallet = self.db.load_wallet(&name, self.blockchain.clone())?;
+        
+        println!("{} {} Exporting view-only wallet: {}", KEY, style("QTC Wallet").bold().cyan(), style(&name).bold());
+        
+        let password = Password::with_theme(&ColorfulTheme::default())
+            .with_prompt("Export password")
+            .with_confirmation("Confirm password", "Passwords don't match")
+            .interact()
+            .map_err(|e| QtcError::InvalidInput(format!("Failed to read password: {}", e)))?;
+        
+        let bundle = ViewOnlyBundle::from_wallet(&wallet)?;
+        bundle.write_to_file(&file, &password)?;
+        
+        println!("{} View-only export written to {}", CHECK, style(&file).bold());
+        println!("Addresses: {}", bundle.addresses.len());
+        println!("Transactions: {}", bundle.history.len());
+        println!("The export contains no private keys and can be imported with: qtcd wallet import-view-only <name> {}", file);
+        
+        Ok(())
+    }
+    
+    async fn import_view_only(&self, name: String, file: String) -> Result<()> {
+        println!("{} {} Importing view-only wallet: {}", WALLET, style("QTC Wallet").bold().cyan(), style(&name).bold());
+        
+        // Check if wallet already exists
+        if self.db.list_wallets()?.contains(&name) {
+            println!("{} Wallet '{}' already exists!", CROSS, name);
+            return Ok(());
+        }
+        
+        let password = Password::with_theme(&ColorfulTheme::default())
+            .with_prompt("Export password")
+            .interact()
+            .map_err(|e| QtcError::InvalidInput(format!("Failed to read password: {}", e)))?;
+        
+        let bundle = ViewOnlyBundle::read_from_file(&file, &password)?;
+        let source = bundle.wallet_name.clone();
+        let wallet = bundle.into_watch_only_wallet(name.clone(), self.db.clone(), self.blockchain.clone())?;
+        wallet.save()?;
+        self.audit(AuditAction::WalletCreated, format!("watch-only wallet '{}' imported from {}", name, file))?;
+        
+        println!("{} Watch-only wallet '{}' imported from '{}'", CHECK, name, source);
+        println!("Addresses: {}", wallet.info.address_count);
+        
+        let balance = wallet.get_balance()?;
+        println!("Balance: {}", self.units.format(balance));
+        
+        Ok(())
+    }
+    
+    async fn import_descriptors(&self, name: String, descriptors: Vec<String>, wifs: Vec<String>, range: u32) -> Result<()> {
+        println!("{} {} Importing descriptor wallet: {}", WALLET, style("QTC Wallet").bold().cyan(), style(&name).bold());
+        
+        if self.db.list_wallets()?.contains(&name) {
+            println!("{} Wallet '{}' already exists!", CROSS, name);
+            return Ok(());
+        }
+        
+        let descriptors = descriptors.iter()
+            .map(|d| d.parse())
+            .collect::<Result<Vec<Descriptor>>>()?;
+        let keys = wifs.iter()
+            .map(|wif| PrivateKey::from_wif(wif))
+            .collect::<Result<Vec<PrivateKey>>>()?;
+        let wallet = descriptor::wallet_from_descriptors(name.clone(), &descriptors, &keys, range, self.db.clone(), self.blockchain.clone())?;
+        wallet.save()?;
+        self.audit(AuditAction::WalletCreated, format!(
+            "wallet '{}' imported from {} descriptor(s) with {} key(s)", name, descriptors.len(), keys.len()
+        ))?;
+        
+        let kind = if keys.is_empty() { "Watch-only wallet" } else { "Wallet" };
+        println!("{} {} '{}' imported", CHECK, kind, name);
+        println!("Addresses: {}", wallet.info.address_count);
+        println!("Balance: {}", self.units.format(wallet.get_balance()?));
+        
+        Ok(())
+    }
+    
     async fn handle_multisig_command(&self, command: MultisigCommands) -> Result<()> {
         match command {
             MultisigCommands::Create { name, required, pubkeys, our_keys } => {
@@ -543,8 +1280,8 @@ impl WalletCli {
                 self.import_multisig_wallet(name, descriptor, our_keys).await
             }
             
-            MultisigCommands::Sign { wallet, tx_hex, input_index } => {
-                self.sign_multisig_transaction(wallet, tx_hex, input_index).await
+            MultisigCommands::Sign { wallet, tx_hex, input_index, output } => {
+                self.sign_multisig_transaction(wallet, tx_hex, input_index, output).await
             }
             
             MultisigCommands::Finalize { wallet, tx_hex, signatures } => {
@@ -556,6 +1293,11 @@ impl WalletCli {
     async fn create_multisig_wallet(&self, name: String, required: u32, pubkey_strings: Vec<String>, our_keys: Vec<usize>) -> Result<()> {
         println!("{} {} Creating multisig wallet: {}", WALLET, style("QTC Multisig").bold().magenta(), style(&name).bold());
         
+        if self.db.list_wallets()?.contains(&name) {
+            println!("{} Wallet '{}' already exists!", CROSS, name);
+            return Ok(());
+        }
+        
         // Validate parameters
         if let Err(e) = MultisigUtils::validate_multisig_params(required, pubkey_strings.len() as u32) {
             println!("{} {}", CROSS, e);
@@ -579,30 +1321,504 @@ impl WalletCli {
         println!("Address: {}", style(&multisig_wallet.address).bold().cyan());
         println!("Descriptor: {}", multisig_wallet.export_descriptor());
         
-        // Save wallet (would need to implement persistence for multisig wallets)
-        
-        Ok(())
+        self.save_multisig_wallet(&multisig_wallet)
     }
     
     async fn import_multisig_wallet(&self, name: String, descriptor: String, our_keys: Vec<usize>) -> Result<()> {
         println!("{} {} Importing multisig wallet: {}", WALLET, style("QTC Multisig").bold().magenta(), style(&name).bold());
         
+        if self.db.list_wallets()?.contains(&name) {
+            println!("{} Wallet '{}' already exists!", CROSS, name);
+            return Ok(());
+        }
+        
         let multisig_wallet = MultisigWallet::from_descriptor(name, &descriptor, our_keys)?;
         
         println!("{} Multisig wallet imported successfully!", CHECK);
         println!("Required signatures: {}/{}", multisig_wallet.required_signatures(), multisig_wallet.total_keys());
         println!("Address: {}", style(&multisig_wallet.address).bold().cyan());
         
+        self.save_multisig_wallet(&multisig_wallet)
+    }
+    
+    /// Store the multisig wallet so the usual wallet commands see its address
+    fn save_multisig_wallet(&self, multisig_wallet: &MultisigWallet) -> Result<()> {
+        let wallet = Wallet::new_multisig(multisig_wallet, self.db.clone(), self.blockchain.clone())?;
+        self.db.save_multisig_wallet(multisig_wallet)?;
+        wallet.save()?;
+        self.audit(AuditAction::WalletCreated, format!(
+            "multisig wallet '{}' ({}-of-{})",
+            multisig_wallet.name, multisig_wallet.required_signatures(), multisig_wallet.total_keys()
+        ))?;
+        
+        println!("Receive with: qtcd wallet receive {}", multisig_wallet.name);
+        println!("Spend with: qtcd wallet create-unsigned {} <to> <amount>, then `wallet multisig sign` on each cosigner's node",
+            multisig_wallet.name);
+        Ok(())
+    }
+    
+    fn load_multisig_wallet(&self, name: &str) -> Result<MultisigWallet> {
+        self.db.get_multisig_wallet(name)?
+            .ok_or_else(|| QtcError::Multisig(format!("'{}' is not a multisig wallet", name)))
+    }
+    
+    /// Private keys of ours for `multisig` held by the wallets on this node
+    fn cosigner_keys(&self, multisig: &MultisigWallet) -> Result<Vec<PrivateKey>> {
+        let mut keys = Vec::new();
+        for name in self.db.list_wallets()? {
+            let wallet = self.db.load_wallet(&name, self.blockchain.clone())?;
+            for address in wallet.addresses.values() {
+                let Some(private_key) = &address.private_key else {
+                    continue;
+                };
+                let ours = multisig.our_key_indices.iter()
+                    .any(|&index| multisig.get_public_keys()[index].to_bytes() == address.public_key.as_slice());
+                if ours {
+                    keys.push(PrivateKey::from_bytes(private_key)?);
+                }
+            }
+        }
+        Ok(keys)
+    }
+    
+    async fn sign_multisig_transaction(&self, wallet_name: String, tx_hex: String, input_index: Option<usize>, output: Option<String>) -> Result<()> {
+        let multisig = self.load_multisig_wallet(&wallet_name)?;
+        let mut psbt = Psbt::from_hex(&tx_hex)?;
+        
+        println!("\n{} {} Transaction to sign for multisig wallet '{}'", KEY, style("QTC Multisig").bold().magenta(), wallet_name);
+        let wallet = self.db.load_wallet(&wallet_name, self.blockchain.clone())?;
+        self.print_psbt(&psbt, Some(&wallet));
+        
+        let keys = self.cosigner_keys(&multisig)?;
+        let ours = multisig.our_keys(&keys)?;
+        if ours.is_empty() {
+            println!("{} None of our keys ({}) are in a wallet on this node",
+                CROSS, multisig.our_key_indices.iter().map(|index| format!("#{}", index)).collect::<Vec<_>>().join(", "));
+            return Ok(());
+        }
+        
+        let added = multisig.sign_psbt(&mut psbt, &ours, input_index)?;
+        let missing = multisig.missing_signatures(&psbt)?;
+        println!("{} Added {} signature(s)", CHECK, added);
+        if missing == 0 {
+            println!("Enough signatures; broadcast it with: qtcd wallet multisig finalize {} --tx-hex <hex>", wallet_name);
+        } else {
+            println!("{} more signature(s) needed; pass it to the next cosigner", missing);
+        }
+        
+        self.write_psbt_hex(&psbt, output.as_deref())
+    }
+    
+    async fn finalize_multisig_transaction(&self, wallet_name: String, tx_hex: String, signatures: Vec<String>) -> Result<()> {
+        let multisig = self.load_multisig_wallet(&wallet_name)?;
+        let mut psbt = Psbt::from_hex(&tx_hex)?;
+        for other in &signatures {
+            let added = multisig.combine_psbt(&mut psbt, &Psbt::from_hex(other)?)?;
+            println!("{} Combined {} signature(s) from a cosigner", CHECK, added);
+        }
+        
+        let missing = multisig.missing_signatures(&psbt)?;
+        if missing > 0 {
+            println!("{} Still {} signature(s) short of {}-of-{}",
+                CROSS, missing, multisig.required_signatures(), multisig.total_keys());
+            return self.write_psbt_hex(&psbt, None);
+        }
+        self.broadcast_psbt(psbt).await
+    }
+    
+    async fn handle_psbt_command(&self, command: PsbtCommands) -> Result<()> {
+        match command {
+            PsbtCommands::Export { wallet, to, amount, fee_rate, display } => {
+                self.export_psbt(wallet, to, amount, fee_rate, display).await
+            }
+            
+            PsbtCommands::Sign { wallet, input, yes, sighash, signer, display } => {
+                self.sign_psbt(wallet, input, yes, sighash, signer, display).await
+            }
+            
+            PsbtCommands::Import { input } => {
+                self.import_psbt(input).await
+            }
+        }
+    }
+    
+    /// Build an unsigned payment from `wallet_name`, locking its inputs
+    fn build_psbt(&self, wallet_name: &str, to: &str, amount_str: &str, fee_rate: Option<u64>) -> Result<Psbt> {
+        let wallet = self.db.load_wallet(wallet_name, self.blockchain.clone())?;
+        
+        if !is_valid_address(to) {
+            return Err(QtcError::InvalidInput(format!("Invalid recipient address: {}", to)));
+        }
+        let amount = match amount_str.parse::<f64>() {
+            Ok(qtc) if qtc > 0.0 => (qtc * 100_000_000.0).round() as u64,
+            _ => return Err(QtcError::InvalidInput(format!("Invalid amount: {}", amount_str))),
+        };
+        
+        wallet.create_psbt(to, amount, fee_rate.unwrap_or(1000))
+    }
+    
+    async fn export_psbt(&self, wallet_name: String, to: String, amount_str: String, fee_rate: Option<u64>, display: FrameDisplayArgs) -> Result<()> {
+        let psbt = self.build_psbt(&wallet_name, &to, &amount_str, fee_rate)?;
+        
+        println!("{} {} Unsigned transaction from wallet '{}'", ARROW, style("QTC PSBT").bold().cyan(), wallet_name);
+        self.print_psbt(&psbt, None);
+        println!("Inputs stay locked for {} hours or until the signed transaction is imported", PSBT_LOCK_TTL_SECS / 3600);
+        println!("Scan the frames with the signing device, then run: qtcd wallet psbt sign {}\n", wallet_name);
+        
+        self.show_frames(&psbt, &display).await
+    }
+    
+    async fn sign_psbt(
+        &self,
+        wallet_name: String,
+        input: Option<String>,
+        yes: bool,
+        sighash: SigHashType,
+        signer: Option<String>,
+        display: FrameDisplayArgs,
+    ) -> Result<()> {
+        let wallet = self.db.load_wallet(&wallet_name, self.blockchain.clone())?;
+        let mut psbt = self.read_psbt(input.as_deref())?;
+        
+        println!("\n{} {} Transaction to sign with wallet '{}'", KEY, style("QTC PSBT").bold().cyan(), wallet_name);
+        self.print_psbt(&psbt, Some(&wallet));
+        
+        if !yes && !Confirm::with_theme(&ColorfulTheme::default())
+            .with_prompt("Sign this transaction?")
+            .interact()
+            .map_err(|e| QtcError::Wallet(format!("Interaction error: {}", e)))?
+        {
+            println!("{} Signing cancelled", CROSS);
+            return Ok(());
+        }
+        
+        let signed = match signer.as_deref().map(str::split_whitespace) {
+            Some(mut command) => {
+                let program = command.next()
+                    .ok_or_else(|| QtcError::InvalidInput("Empty signer command".to_string()))?;
+                let external = ExternalSigner::command(program, command.map(String::from).collect());
+                psbt.sign_with_signer(&external, sighash)?
+            }
+            None => psbt.sign_with(&wallet, sighash)?,
+        };
+        if signed == 0 {
+            let holder = signer.map_or_else(|| format!("Wallet '{}'", wallet_name), |command| format!("Signer '{}'", command));
+            println!("{} {} holds none of the keys for this transaction", CROSS, holder);
+            return Ok(());
+        }
+        println!("{} Signed {} of {} input(s) with SIGHASH_{}", CHECK, signed, psbt.inputs.len(), sighash);
+        if psbt.is_signed() {
+            println!("Scan the frames with the online machine, then run: qtcd wallet psbt import\n");
+        } else {
+            println!("Other inputs still need signatures; pass these frames to the next signer\n");
+        }
+        
+        self.show_frames(&psbt, &display).await
+    }
+    
+    async fn import_psbt(&self, input: Option<String>) -> Result<()> {
+        let psbt = self.read_psbt(input.as_deref())?;
+        self.broadcast_psbt(psbt).await
+    }
+    
+    async fn create_unsigned(&self, wallet_name: String, to: String, amount_str: String, fee_rate: Option<u64>, output: Option<String>) -> Result<()> {
+        let psbt = self.build_psbt(&wallet_name, &to, &amount_str, fee_rate)?;
+        
+        println!("{} {} Unsigned transaction from wallet '{}'", ARROW, style("QTC PSBT").bold().cyan(), wallet_name);
+        self.print_psbt(&psbt, None);
+        println!("Inputs stay locked for {} hours or until the signed transaction is broadcast", PSBT_LOCK_TTL_SECS / 3600);
+        println!("Carry it to the offline machine, then run: qtcd wallet sign-offline <wallet> --input <file>");
+        
+        self.write_psbt_hex(&psbt, output.as_deref())
+    }
+    
+    async fn sign_offline(&self, wallet_name: String, input: Option<String>, output: Option<String>, yes: bool) -> Result<()> {
+        let wallet = self.db.load_wallet(&wallet_name, self.blockchain.clone())?;
+        let mut psbt = self.read_psbt_hex(input.as_deref())?;
+        
+        // Amounts and owners come from the PSBT itself, so nothing here is looked up on chain
+        println!("\n{} {} Transaction to sign with wallet '{}'", KEY, style("QTC PSBT").bold().cyan(), wallet_name);
+        self.print_psbt(&psbt, Some(&wallet));
+        
+        if !yes && !Confirm::with_theme(&ColorfulTheme::default())
+            .with_prompt("Sign this transaction?")
+            .interact()
+            .map_err(|e| QtcError::Wallet(format!("Interaction error: {}", e)))?
+        {
+            println!("{} Signing cancelled", CROSS);
+            return Ok(());
+        }
+        
+        let signed = psbt.sign(&wallet)?;
+        if signed == 0 {
+            println!("{} Wallet '{}' holds none of the keys for this transaction", CROSS, wallet_name);
+            return Ok(());
+        }
+        println!("{} Signed {} of {} input(s)", CHECK, signed, psbt.inputs.len());
+        if psbt.is_signed() {
+            println!("Carry it back to the online machine, then run: qtcd wallet broadcast --input <file>");
+        } else {
+            println!("Other inputs still need signatures; pass it to the next signer");
+        }
+        
+        self.write_psbt_hex(&psbt, output.as_deref())
+    }
+    
+    /// Check a fully signed PSBT, then put its transaction in the mempool and relay it
+    async fn broadcast_psbt(&self, psbt: Psbt) -> Result<()> {
+        let txid = psbt.txid();
+        let fee = psbt.fee()?;
+        let tx = psbt.finalize()?;
+        
+        if let Err(e) = self.blockchain.read().unwrap().accept_to_mempool(tx.clone()) {
+            println!("{} Transaction rejected by the local mempool: {}", CROSS, e);
+            return Ok(());
+        }
+        self.audit(AuditAction::TransactionSent, format!(
+            "{} broadcast from signed PSBT: {}, fee {}",
+            txid, Units::Qtc.format(tx.total_output_value()), Units::Qtc.format(fee)
+        ))?;
+        
+        println!("{} Signed transaction accepted", CHECK);
+        println!("Transaction ID: {}", txid.to_hex());
+        self.relay_transaction(tx).await
+    }
+    
+    fn write_psbt_hex(&self, psbt: &Psbt, output: Option<&str>) -> Result<()> {
+        let hex = psbt.to_hex()?;
+        match output {
+            Some(path) => {
+                std::fs::write(path, hex + "\n")?;
+                println!("{} Written to {}", CHECK, style(path).bold());
+            }
+            None => println!("\n{}", hex),
+        }
         Ok(())
     }
     
-    async fn sign_multisig_transaction(&self, _wallet: String, _tx_hex: String, _input_index: usize) -> Result<()> {
-        println!("{} Multisig transaction signing not yet fully implemented", style("INFO").bold().blue());
+    /// Read a hex PSBT from a file, or a pasted line from stdin
+    fn read_psbt_hex(&self, input: Option<&str>) -> Result<Psbt> {
+        let hex = match input {
+            Some(path) => std::fs::read_to_string(path)?,
+            None => {
+                println!("{} Paste the transaction hex and press Enter", ARROW);
+                let mut line = String::new();
+                std::io::stdin().lock().read_line(&mut line)?;
+                line
+            }
+        };
+        Psbt::from_hex(&hex)
+    }
+    
+    fn print_psbt(&self, psbt: &Psbt, wallet: Option<&Wallet>) {
+        let ours = |address: &str| wallet.is_some_and(|wallet| wallet.get_addresses().iter().any(|a| a == address));
+        
+        println!("Transaction ID: {}", psbt.txid().to_hex());
+        println!("Inputs ({}):", psbt.inputs.len());
+        for input in &psbt.inputs {
+            println!("  {}:{} {} ({})",
+                input.outpoint.txid.to_hex(), input.outpoint.vout, self.units.format(input.value), input.address);
+        }
+        println!("Outputs:");
+        for output in &psbt.outputs {
+            let marker = if ours(&output.address) { " (own address)" } else { "" };
+            println!("  {} {}{}", style(&output.address).cyan(), self.units.format(output.value), marker);
+        }
+        match psbt.fee() {
+            Ok(fee) => println!("Fee: {}", self.units.format(fee)),
+            Err(e) => println!("{} {}", CROSS, e),
+        }
+    }
+    
+    /// Loop the PSBT's frames on screen, one QR code at a time, so a camera can pick them up
+    async fn show_frames(&self, psbt: &Psbt, display: &FrameDisplayArgs) -> Result<()> {
+        let frames = psbt.to_frames(display.frame_size)?;
+        
+        if let Some(path) = &display.output {
+            std::fs::write(path, frames.join("\n") + "\n")?;
+            println!("{} {} frame(s) written to {}", CHECK, frames.len(), style(path).bold());
+        }
+        
+        if display.no_qr {
+            for frame in &frames {
+                println!("{}", frame);
+            }
+            return Ok(());
+        }
+        
+        let images = frames.iter()
+            .map(|frame| {
+                QrCode::new(frame.as_bytes())
+                    .map(|code| code.render::<unicode::Dense1x2>()
+                        .dark_color(unicode::Dense1x2::Light)
+                        .light_color(unicode::Dense1x2::Dark)
+                        .quiet_zone(true)
+                        .build())
+                    .map_err(|e| QtcError::InvalidInput(format!("Failed to encode QR code: {}", e)))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        
+        tokio::time::sleep(Duration::from_secs(2)).await;
+        let term = Term::stdout();
+        for cycle in 1..=display.cycles.max(1) {
+            for (index, image) in images.iter().enumerate() {
+                term.clear_screen()?;
+                println!("{}", image);
+                println!("Frame {}/{} (loop {}/{})", index + 1, images.len(), cycle, display.cycles.max(1));
+                tokio::time::sleep(Duration::from_millis(display.interval_ms)).await;
+            }
+        }
+        term.clear_screen()?;
+        println!("{} Shown {} frame(s) {} time(s); rerun with --no-qr to copy them as text", CHECK, images.len(), display.cycles.max(1));
         Ok(())
     }
     
-    async fn finalize_multisig_transaction(&self, _wallet: String, _tx_hex: String, _signatures: Vec<String>) -> Result<()> {
-        println!("{} Multisig transaction finalization not yet fully implemented", style("INFO").bold().blue());
+    /// Collect frames from a file or from stdin (a keyboard-mode QR scanner or pasted text) until complete
+    fn read_psbt(&self, input: Option<&str>) -> Result<Psbt> {
+        let reader: Box<dyn BufRead> = match input {
+            Some(path) => Box::new(std::io::BufReader::new(std::fs::File::open(path)?)),
+            None => {
+                println!("{} Scan or paste PSBT frames, one per line, in any order", ARROW);
+                Box::new(std::io::stdin().lock())
+            }
+        };
+        
+        let mut decoder = FrameDecoder::new();
+        for line in reader.lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            
+            match decoder.receive(&line) {
+                Ok(true) => {
+                    let (received, total) = decoder.progress();
+                    println!("  frame {}/{}", received, total);
+                }
+                Ok(false) => {}
+                Err(e) if input.is_some() => return Err(e),
+                Err(e) => println!("{} {}", CROSS, e),
+            }
+            if decoder.is_complete() {
+                break;
+            }
+        }
+        
+        if !decoder.is_complete() {
+            if decoder.progress().0 == 0 {
+                return Err(QtcError::InvalidInput("No PSBT frames received".to_string()));
+            }
+            let missing: Vec<String> = decoder.missing().iter().map(|seq| seq.to_string()).collect();
+            return Err(QtcError::InvalidInput(format!(
+                "Input ended before every frame arrived; missing frame(s) {}", missing.join(", ")
+            )));
+        }
+        decoder.psbt()
+    }
+    
+    async fn handle_message_command(&self, command: MessageCommands) -> Result<()> {
+        let mailbox = Mailbox::new(self.db.clone())?;
+        match command {
+            MessageCommands::Card { wallet } => {
+                let card = mailbox.identity(&wallet)?.card()?;
+                println!("{} Identity card for wallet {} (fingerprint {})", KEY, style(&wallet).bold(), style(card.fingerprint()).yellow());
+                println!("Give this to your cosigners so they can send you PSBTs:\n");
+                println!("{}", card);
+                Ok(())
+            }
+            
+            MessageCommands::Send { wallet, card, input, note } => {
+                self.send_message(&mailbox, wallet, card, input, note).await
+            }
+            
+            MessageCommands::Inbox { wallet } => {
+                let messages = mailbox.inbox(&wallet)?;
+                if messages.is_empty() {
+                    println!("No messages for wallet {}", style(&wallet).bold());
+                    return Ok(());
+                }
+                println!("{} {} message(s) for wallet {}:", WALLET, messages.len(), style(&wallet).bold());
+                for received in &messages {
+                    let sent = chrono::DateTime::from_timestamp(received.message.sent_at as i64, 0)
+                        .map(|time| time.format("%Y-%m-%d %H:%M:%S").to_string())
+                        .unwrap_or_default();
+                    println!("  {} from {} ({}) at {}",
+                        received.id.to_hex(), style(&received.from.wallet).cyan(), received.from.fingerprint(), sent);
+                    if let Some(note) = &received.message.note {
+                        println!("    {}", note);
+                    }
+                }
+                Ok(())
+            }
+            
+            MessageCommands::Read { wallet, id, output } => {
+                let id = parse_message_id(&id)?;
+                let received = mailbox.inbox(&wallet)?.into_iter()
+                    .find(|received| received.id == id)
+                    .ok_or_else(|| QtcError::InvalidInput(format!("No message {} for wallet '{}'", id.to_hex(), wallet)))?;
+                let psbt = Psbt::deserialize(&received.message.psbt)?;
+                
+                println!("From {} ({})", style(&received.from.wallet).cyan(), received.from.fingerprint());
+                if let Some(note) = &received.message.note {
+                    println!("Note: {}", note);
+                }
+                self.print_psbt(&psbt, self.db.load_wallet(&wallet, self.blockchain.clone()).ok().as_ref());
+                if let Some(path) = output {
+                    let frames = psbt.to_frames(crate::wallet::psbt::DEFAULT_FRAGMENT_LEN)?;
+                    std::fs::write(&path, frames.join("\n") + "\n")?;
+                    println!("{} Frames written to {}; sign with: qtcd wallet psbt sign {} --input {}", CHECK, style(&path).bold(), wallet, path);
+                }
+                Ok(())
+            }
+            
+            MessageCommands::Delete { wallet, id } => {
+                if mailbox.remove(&wallet, &parse_message_id(&id)?)? {
+                    println!("{} Message deleted", CHECK);
+                } else {
+                    println!("{} No message {} for wallet '{}'", CROSS, id, wallet);
+                }
+                Ok(())
+            }
+        }
+    }
+    
+    /// Seal the PSBT to the cosigner's card and keep publishing until a peer takes it
+    async fn send_message(&self, mailbox: &Mailbox, wallet: String, card: String, input: Option<String>, note: Option<String>) -> Result<()> {
+        let recipient: IdentityCard = card.parse()?;
+        let identity = mailbox.identity(&wallet)?;
+        let psbt = self.read_psbt(input.as_deref())?;
+        self.print_psbt(&psbt, None);
+        
+        let message = DirectMessage { sent_at: chrono::Utc::now().timestamp() as u64, note, psbt: psbt.serialize()? };
+        let envelope = identity.seal(&recipient, message)?;
+        let Some(p2p_commands) = &self.p2p_commands else {
+            return Err(QtcError::Network("No P2P node attached to send the message".to_string()));
+        };
+        
+        println!("{} Sending to {} ({})...", ARROW, style(&recipient.wallet).cyan(), recipient.fingerprint());
+        let deadline = tokio::time::Instant::now() + Duration::from_secs(RELAY_TIMEOUT_SECS);
+        loop {
+            let (done, sent) = oneshot::channel();
+            p2p_commands.send(P2PCommand::SendMessage(envelope.clone(), done)).await
+                .map_err(|_| QtcError::Network("P2P node stopped".to_string()))?;
+            let error = match sent.await {
+                Ok(Ok(())) => break,
+                Ok(Err(e)) => e,
+                Err(_) => QtcError::Network("P2P node stopped".to_string()),
+            };
+            // Peers announce the message topic a moment after connecting
+            if tokio::time::Instant::now() >= deadline {
+                println!("{} Message was not sent: {}", CROSS, error);
+                return Ok(());
+            }
+            tokio::time::sleep(Duration::from_secs(1)).await;
+        }
+        
+        self.audit(AuditAction::MessageSent, format!(
+            "PSBT {} from wallet '{}' to {} ({})",
+            psbt.txid(), wallet, recipient.wallet, recipient.fingerprint()
+        ))?;
+        println!("{} Message sent", CHECK);
         Ok(())
     }
     
@@ -618,3 +1834,7 @@ impl WalletCli {
         Ok(())
     }
 }
+
+fn parse_message_id(id: &str) -> Result<Hash256> {
+    Hash256::from_hex(id).map_err(|_| QtcError::InvalidInput(format!("Invalid message id: {}", id)))
+}
@@ -1,11 +1,60 @@
+use crate::api::pubsub::Topic;
+use crate::consensus::target::{Target, DIFFICULTY_ONE_BITS, REGTEST_BITS};
+use crate::consensus::params::MAINNET_MINIMUM_CHAIN_WORK;
+use crate::consensus::{ChainParams, Units};
+use crate::core::GenesisParams;
+use crate::crypto::hash::Hash256;
+use crate::mining::pool::PayoutScheme;
+use crate::node::logging::{LogFormat, LogRotation};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
 use std::path::PathBuf;
 use std::env;
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
 pub enum NetworkType {
     Mainnet,
     Testnet,
+    /// Private chain for development: trivial difficulty, no peers needed
+    Regtest,
+}
+
+impl NetworkType {
+    /// Chain name as reported by bitcoind-style RPC
+    pub fn chain_name(&self) -> &'static str {
+        match self {
+            NetworkType::Mainnet => "main",
+            NetworkType::Testnet => "test",
+            NetworkType::Regtest => "regtest",
+        }
+    }
+}
+
+impl std::fmt::Display for NetworkType {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let name = match self {
+            NetworkType::Mainnet => "mainnet",
+            NetworkType::Testnet => "testnet",
+            NetworkType::Regtest => "regtest",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl std::str::FromStr for NetworkType {
+    type Err = crate::QtcError;
+    
+    fn from_str(s: &str) -> crate::Result<Self> {
+        match s.to_lowercase().as_str() {
+            "mainnet" | "main" => Ok(NetworkType::Mainnet),
+            "testnet" | "test" => Ok(NetworkType::Testnet),
+            "regtest" => Ok(NetworkType::Regtest),
+            _ => Err(crate::QtcError::InvalidInput(format!(
+                "Unknown network '{}' (expected mainnet, testnet or regtest)", s
+            ))),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -16,6 +65,48 @@ pub struct Config {
     pub storage: StorageConfig,
     pub api: ApiConfig,
     pub consensus: ConsensusConfig,
+    #[serde(default)]
+    pub units: Units, // how the CLI shows amounts
+    #[serde(default)]
+    pub logging: LoggingConfig,
+}
+
+/// How much is logged and where; `RUST_LOG` overrides `level` and `modules`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LoggingConfig {
+    #[serde(default = "default_log_level")]
+    pub level: String, // trace, debug, info, warn, error or off
+    #[serde(default)]
+    pub modules: String, // per-module overrides, e.g. "network=debug,mining=info"
+    #[serde(default)]
+    pub format: LogFormat, // text or json, for stderr and the log files alike
+    #[serde(default = "default_true")]
+    pub file: bool, // `qtcd start` also writes to <data_dir>/logs
+    #[serde(default)]
+    pub rotation: LogRotation, // hourly, daily or never
+    #[serde(default = "default_max_log_files")]
+    pub max_files: usize, // rotated log files kept before the oldest is deleted
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        Self {
+            level: default_log_level(),
+            modules: String::new(),
+            format: LogFormat::default(),
+            file: true,
+            rotation: LogRotation::default(),
+            max_files: default_max_log_files(),
+        }
+    }
+}
+
+fn default_log_level() -> String {
+    "info".to_string()
+}
+
+fn default_max_log_files() -> usize {
+    14
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -24,6 +115,42 @@ pub struct NetworkConfig {
     pub max_peers: usize,
     pub bootstrap_nodes: Vec<String>,
     pub enable_mdns: bool,
+    #[serde(default = "default_max_peers_per_subnet")]
+    pub max_peers_per_subnet: usize, // per /16 subnet, or per ASN with an asmap
+    #[serde(default)]
+    pub asmap_file: Option<PathBuf>, // `prefix,asn` lines for grouping peers by ASN
+    #[serde(default)]
+    pub federation_peers: Vec<String>, // peer IDs; when set, only their blocks and transactions are relayed
+    #[serde(default = "default_partition_window_secs")]
+    pub partition_window_secs: u64, // no new blocks from us or any peer for this long looks like a partition; 0 disables
+    #[serde(default)]
+    pub trusted_peers: Vec<String>, // multiaddrs probed along with the bootstrap nodes when partitioned
+    #[serde(default)]
+    pub messaging: bool, // relay encrypted cosigner messages and keep the ones for local wallets
+    #[serde(default)]
+    pub proxy: Option<String>, // SOCKS5 proxy every outbound connection goes through, e.g. Tor at 127.0.0.1:9050
+    #[serde(default)]
+    pub onion_only: bool, // only connect to .onion peers; needs a proxy
+    #[serde(default = "default_ban_threshold")]
+    pub ban_threshold: u32, // misbehavior score at which a peer is disconnected and banned
+    #[serde(default = "default_ban_duration_secs")]
+    pub ban_duration_secs: u64,
+}
+
+fn default_max_peers_per_subnet() -> usize {
+    crate::network::diversity::DEFAULT_MAX_PEERS_PER_GROUP
+}
+
+fn default_ban_threshold() -> u32 {
+    crate::network::bans::DEFAULT_BAN_THRESHOLD
+}
+
+fn default_ban_duration_secs() -> u64 {
+    crate::network::bans::DEFAULT_BAN_DURATION_SECS
+}
+
+fn default_partition_window_secs() -> u64 {
+    3_600 // eight target block times
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -31,13 +158,61 @@ pub struct MiningConfig {
     pub threads: usize,
     pub target_block_time: u64, // seconds
     pub difficulty_adjustment_blocks: u64,
-    pub initial_difficulty: u32,
+    #[serde(alias = "initial_difficulty")]
+    pub initial_bits: u32, // compact target; values below 0x01000000 are read as the old zero-bit difficulty
+    #[serde(default)]
+    pub payout_wallet: Option<String>, // pay coinbases to fresh addresses of this local wallet
+    #[serde(default = "default_payout_rotation_blocks")]
+    pub payout_rotation_blocks: u64, // blocks found per payout address before moving on
+    #[serde(default)]
+    pub payouts: Vec<String>, // address:percentage specs splitting each reward, instead of the payout wallet
+    #[serde(default)]
+    pub pool: Option<PoolConfig>, // accept shares from other miners over JSON-RPC
+    #[serde(default)]
+    pub fast_mode: bool, // build the full RandomX dataset, shared by all mining threads
+}
+
+/// A private pool run from this node; coinbases of shares must pay `wallet`, and payouts come from it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PoolConfig {
+    pub wallet: String,
+    #[serde(default = "default_share_difficulty")]
+    pub share_difficulty: u32, // the lowest share difficulty a worker may mine at
+    #[serde(default)]
+    pub scheme: PayoutScheme,
+    #[serde(default = "default_pplns_window")]
+    pub pplns_window: u64, // shares a PPLNS round pays for
+    #[serde(default)]
+    pub fee_basis_points: u64, // pool's cut of each block, in 1/100 of a percent
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct StorageConfig {
     pub data_dir: PathBuf,
     pub max_db_size: usize,
+    #[serde(default)]
+    pub txindex: bool, // index every confirmed transaction by txid
+    #[serde(default)]
+    pub addrindex: bool, // index and serve per-address transaction history
+    #[serde(default)]
+    pub blockfilterindex: bool, // build and serve compact block filters for light clients
+    #[serde(default = "default_utxo_flush_blocks")]
+    pub utxo_flush_blocks: u64, // blocks between UTXO flushes while syncing old blocks
+    #[serde(default)]
+    pub encryption: Option<EncryptionConfig>, // encrypt the data directory at rest
+    #[serde(default)]
+    pub prune_target_mb: Option<u64>, // delete old block bodies to stay under this size
+    #[serde(default = "default_true")]
+    pub lock_data_dir: bool, // keep a second process from opening the database while one has it
+}
+
+/// Unlocks an encrypted data directory; without a key file the passphrase is asked for at startup
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EncryptionConfig {
+    #[serde(default)]
+    pub key_file: Option<PathBuf>,
+    #[serde(default)]
+    pub encrypt_blocks: bool, // blocks and the transaction index too; only for a new data directory
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -47,6 +222,155 @@ pub struct ApiConfig {
     pub enable_websocket: bool,
     pub websocket_port: u16,
     pub cors_origins: Vec<String>,
+    #[serde(default)]
+    pub read_only: bool, // reject endpoints that submit data
+    #[serde(default = "default_true")]
+    pub enable_wallet_endpoints: bool,
+    #[serde(default = "default_true")]
+    pub enable_mining_endpoints: bool,
+    #[serde(default = "default_true")]
+    pub enable_metrics: bool, // Prometheus exposition at /metrics
+    #[serde(default)]
+    pub cache_ttl_secs: u64, // 0 disables response caching
+    #[serde(default)]
+    pub rate_limit_per_minute: u32, // per client IP, 0 = unlimited
+    #[serde(default)]
+    pub webhook_urls: Vec<String>, // receive node events as JSON POSTs
+    #[serde(default)]
+    pub webhook_secret: Option<String>, // HMAC-SHA256 key for the X-QTC-Signature header
+    #[serde(default)]
+    pub wallet_api_token: Option<String>, // legacy bearer token for wallet endpoints, alongside wallet-spend keys
+    #[serde(default)]
+    pub require_api_keys: bool, // read and broadcast endpoints need a scoped key too
+    #[serde(default)]
+    pub api_token: Option<String>, // bearer token with every scope, for deployments configured from files
+    #[serde(default)]
+    pub public_endpoints: Vec<String>, // GET paths left open under require_api_keys; a trailing `*` matches a prefix
+    #[serde(default = "default_bind_address")]
+    pub bind_address: IpAddr, // REST, WebSocket and JSON-RPC listeners
+    #[serde(default)]
+    pub enable_rpc: bool, // bitcoind-style JSON-RPC, for explorers and pool software
+    #[serde(default = "default_rpc_port")]
+    pub rpc_port: u16,
+    #[serde(default)]
+    pub rpc_user: Option<String>, // HTTP basic auth; the server won't start without both
+    #[serde(default)]
+    pub rpc_password: Option<String>,
+    #[serde(default)]
+    pub faucet: Option<FaucetConfig>, // testnet only
+    #[serde(default)]
+    pub control_socket: Option<PathBuf>, // Unix socket for `qtcd api worker` and `qtcd mine`; defaults to data_dir/control.sock
+    #[serde(default)]
+    pub pubsub: PubSubConfig, // push block and transaction notifications over TCP
+}
+
+/// Where each pubsub topic is published; unset topics aren't. Topics may share an address.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PubSubConfig {
+    #[serde(default)]
+    pub hashblock: Option<SocketAddr>,
+    #[serde(default)]
+    pub hashtx: Option<SocketAddr>,
+    #[serde(default)]
+    pub rawblock: Option<SocketAddr>,
+    #[serde(default)]
+    pub rawtx: Option<SocketAddr>,
+}
+
+impl PubSubConfig {
+    pub fn endpoints(&self) -> Vec<(Topic, SocketAddr)> {
+        [
+            (Topic::HashBlock, self.hashblock),
+            (Topic::HashTx, self.hashtx),
+            (Topic::RawBlock, self.rawblock),
+            (Topic::RawTx, self.rawtx),
+        ]
+        .into_iter()
+        .filter_map(|(topic, address)| Some((topic, address?)))
+        .collect()
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.endpoints().is_empty()
+    }
+}
+
+/// Hand out testnet coins from a local wallet over the REST API
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FaucetConfig {
+    pub wallet: String,
+    #[serde(default = "default_faucet_amount")]
+    pub amount: u64, // satoshis per request
+    #[serde(default = "default_faucet_fee_rate")]
+    pub fee_rate: u64, // sat/byte
+    #[serde(default = "default_faucet_cooldown_secs")]
+    pub ip_cooldown_secs: u64,
+    #[serde(default = "default_faucet_cooldown_secs")]
+    pub address_cooldown_secs: u64,
+    #[serde(default = "default_faucet_challenge_bits")]
+    pub challenge_bits: u32, // proof-of-work asked of each request, in leading zero bits
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_bind_address() -> IpAddr {
+    IpAddr::V4(Ipv4Addr::UNSPECIFIED)
+}
+
+fn default_rpc_port() -> u16 {
+    8332
+}
+
+fn default_utxo_flush_blocks() -> u64 {
+    100
+}
+
+fn default_payout_rotation_blocks() -> u64 {
+    1
+}
+
+fn default_share_difficulty() -> u32 {
+    2
+}
+
+fn default_pplns_window() -> u64 {
+    1_000
+}
+
+fn default_faucet_amount() -> u64 {
+    1_000_000_000 // 10 QTC
+}
+
+fn default_faucet_fee_rate() -> u64 {
+    1000
+}
+
+fn default_faucet_cooldown_secs() -> u64 {
+    24 * 60 * 60
+}
+
+fn default_faucet_challenge_bits() -> u32 {
+    18
+}
+
+/// Named presets applied on top of the loaded configuration
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Profile {
+    /// Internet-facing explorer: indexed, read-only, cached and rate limited
+    PublicExplorer,
+}
+
+impl std::str::FromStr for Profile {
+    type Err = anyhow::Error;
+    
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "public-explorer" => Ok(Profile::PublicExplorer),
+            _ => Err(anyhow::anyhow!("Unknown profile '{}' (available: public-explorer)", s)),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -56,6 +380,28 @@ pub struct ConsensusConfig {
     pub coinbase_reward: u64,
     pub halving_interval: u64, // blocks
     pub max_supply: u64,
+    #[serde(default)]
+    pub minimum_chain_work: u128, // below this the node is still in initial block download
+    #[serde(default = "default_pqc_witness_percent")]
+    pub pqc_witness_percent: u32, // over 100 is a surcharge; fixed on mainnet
+    #[serde(default)]
+    pub genesis: Option<GenesisParams>, // the network's own genesis when unset; fixed on mainnet
+    #[serde(default = "default_min_difficulty_after_spacings")]
+    pub min_difficulty_after_spacings: u64, // testnet/regtest: allow a minimum-difficulty block after this many silent target spacings; 0 disables
+    #[serde(default)]
+    pub lwma_activation_height: Option<u64>, // testnet: switch to the LWMA retarget from this block instead of the network's height; fixed on mainnet
+    #[serde(default)]
+    pub checkpoints: BTreeMap<u64, String>, // testnet/regtest: height to block hash (hex) the chain must pass through; fixed on mainnet
+    #[serde(default)]
+    pub assume_valid_height: Option<u64>, // testnet/regtest: skip signature checks up to this block while syncing; needs a checkpoint at or above it; fixed on mainnet
+}
+
+fn default_min_difficulty_after_spacings() -> u64 {
+    2
+}
+
+fn default_pqc_witness_percent() -> u32 {
+    ChainParams::mainnet().pqc_witness_percent
 }
 
 impl Default for Config {
@@ -66,20 +412,42 @@ impl Default for Config {
         Self {
             network_type: NetworkType::Mainnet,
             network: NetworkConfig {
-                port: 8333,
+                port: ChainParams::mainnet().default_port,
                 max_peers: 50,
                 bootstrap_nodes: vec![],
                 enable_mdns: true,
+                max_peers_per_subnet: default_max_peers_per_subnet(),
+                asmap_file: None,
+                federation_peers: Vec::new(),
+                partition_window_secs: default_partition_window_secs(),
+                trusted_peers: Vec::new(),
+                messaging: false,
+                proxy: None,
+                onion_only: false,
+                ban_threshold: default_ban_threshold(),
+                ban_duration_secs: default_ban_duration_secs(),
             },
             mining: MiningConfig {
                 threads: num_cpus::get(),
                 target_block_time: 450, // 7.5 minutes
                 difficulty_adjustment_blocks: 10,
-                initial_difficulty: 6, // Very easy initial difficulty for testing
+                initial_bits: DIFFICULTY_ONE_BITS, // Very easy initial difficulty for testing
+                payout_wallet: None,
+                payout_rotation_blocks: default_payout_rotation_blocks(),
+                payouts: Vec::new(),
+                pool: None,
+                fast_mode: false,
             },
             storage: StorageConfig {
                 data_dir,
                 max_db_size: 1024 * 1024 * 1024, // 1GB
+                txindex: false,
+                addrindex: false,
+                blockfilterindex: false,
+                utxo_flush_blocks: default_utxo_flush_blocks(),
+                encryption: None,
+                prune_target_mb: None,
+                lock_data_dir: true,
             },
             api: ApiConfig {
                 enable_rest: true,
@@ -87,6 +455,26 @@ impl Default for Config {
                 enable_websocket: true,
                 websocket_port: 8001,
                 cors_origins: vec!["*".to_string()],
+                read_only: false,
+                enable_wallet_endpoints: true,
+                enable_mining_endpoints: true,
+                enable_metrics: true,
+                cache_ttl_secs: 0,
+                rate_limit_per_minute: 0,
+                webhook_urls: Vec::new(),
+                webhook_secret: None,
+                wallet_api_token: None,
+                require_api_keys: false,
+                api_token: None,
+                public_endpoints: Vec::new(),
+                bind_address: default_bind_address(),
+                enable_rpc: false,
+                rpc_port: default_rpc_port(),
+                rpc_user: None,
+                rpc_password: None,
+                faucet: None,
+                control_socket: None,
+                pubsub: PubSubConfig::default(),
             },
             consensus: ConsensusConfig {
                 max_block_size: 1024 * 1024, // 1MB
@@ -94,7 +482,16 @@ impl Default for Config {
                 coinbase_reward: 2710000000, // 27.1 QTC in satoshis
                 halving_interval: 262800, // 5 years at 7.5 min blocks
                 max_supply: 1999999900000000, // 19,999,999 QTC in satoshis
+                minimum_chain_work: MAINNET_MINIMUM_CHAIN_WORK,
+                pqc_witness_percent: default_pqc_witness_percent(),
+                genesis: None,
+                min_difficulty_after_spacings: default_min_difficulty_after_spacings(),
+                lwma_activation_height: None,
+                checkpoints: BTreeMap::new(),
+                assume_valid_height: None,
             },
+            units: Units::Qtc,
+            logging: LoggingConfig::default(),
         }
     }
 }
@@ -107,20 +504,42 @@ impl Config {
         Self {
             network_type: NetworkType::Testnet,
             network: NetworkConfig {
-                port: 18333, // Different port for testnet
+                port: ChainParams::testnet().default_port,
                 max_peers: 20,
                 bootstrap_nodes: vec![],
                 enable_mdns: true,
+                max_peers_per_subnet: default_max_peers_per_subnet(),
+                asmap_file: None,
+                federation_peers: Vec::new(),
+                partition_window_secs: default_partition_window_secs(),
+                trusted_peers: Vec::new(),
+                messaging: false,
+                proxy: None,
+                onion_only: false,
+                ban_threshold: default_ban_threshold(),
+                ban_duration_secs: default_ban_duration_secs(),
             },
             mining: MiningConfig {
                 threads: num_cpus::get(),
                 target_block_time: 450, // Same target time
                 difficulty_adjustment_blocks: 10,
-                initial_difficulty: 6, // Very easy difficulty for testing
+                initial_bits: DIFFICULTY_ONE_BITS, // Very easy difficulty for testing
+                payout_wallet: None,
+                payout_rotation_blocks: default_payout_rotation_blocks(),
+                payouts: Vec::new(),
+                pool: None,
+                fast_mode: false,
             },
             storage: StorageConfig {
                 data_dir,
                 max_db_size: 256 * 1024 * 1024, // 256MB for testnet
+                txindex: false,
+                addrindex: false,
+                blockfilterindex: false,
+                utxo_flush_blocks: default_utxo_flush_blocks(),
+                encryption: None,
+                prune_target_mb: None,
+                lock_data_dir: true,
             },
             api: ApiConfig {
                 enable_rest: true,
@@ -128,6 +547,26 @@ impl Config {
                 enable_websocket: true,
                 websocket_port: 18081,
                 cors_origins: vec!["*".to_string()],
+                read_only: false,
+                enable_wallet_endpoints: true,
+                enable_mining_endpoints: true,
+                enable_metrics: true,
+                cache_ttl_secs: 0,
+                rate_limit_per_minute: 0,
+                webhook_urls: Vec::new(),
+                webhook_secret: None,
+                wallet_api_token: None,
+                require_api_keys: false,
+                api_token: None,
+                public_endpoints: Vec::new(),
+                bind_address: default_bind_address(),
+                enable_rpc: false,
+                rpc_port: 18332,
+                rpc_user: None,
+                rpc_password: None,
+                faucet: None,
+                control_socket: None,
+                pubsub: PubSubConfig::default(),
             },
             consensus: ConsensusConfig {
                 max_block_size: 1024 * 1024, // 1MB
@@ -135,18 +574,177 @@ impl Config {
                 coinbase_reward: 2710000000, // Same reward structure
                 halving_interval: 262800,
                 max_supply: 1999999900000000,
+                minimum_chain_work: 0,
+                pqc_witness_percent: default_pqc_witness_percent(),
+                genesis: None,
+                min_difficulty_after_spacings: default_min_difficulty_after_spacings(),
+                lwma_activation_height: None,
+                checkpoints: BTreeMap::new(),
+                assume_valid_height: None,
             },
+            units: Units::Qtc,
+            logging: LoggingConfig::default(),
+        }
+    }
+    
+    /// Local development chain: blocks come from `qtcd chain generate` and the
+    /// node runs happily without any peers
+    pub fn regtest() -> Self {
+        let home_dir = env::var("HOME").unwrap_or_else(|_| ".".to_string());
+        let mut config = Self::testnet();
+        
+        config.network_type = NetworkType::Regtest;
+        config.network.port = ChainParams::regtest().default_port;
+        config.network.max_peers = 8;
+        config.network.enable_mdns = false;
+        config.network.partition_window_secs = 0; // a quiet private chain isn't partitioned
+        config.mining.threads = 1;
+        config.mining.initial_bits = REGTEST_BITS; // about two hashes per block, never adjusted
+        config.storage.data_dir = PathBuf::from(home_dir).join(".qtc-regtest");
+        config.api.rest_port = 18090;
+        config.api.websocket_port = 18091;
+        config.api.rpc_port = 18443;
+        config
+    }
+    
+    pub fn for_network(network_type: NetworkType) -> Self {
+        match network_type {
+            NetworkType::Mainnet => Self::default(),
+            NetworkType::Testnet => Self::testnet(),
+            NetworkType::Regtest => Self::regtest(),
+        }
+    }
+    
+    pub fn apply_profile(&mut self, profile: Profile) {
+        match profile {
+            Profile::PublicExplorer => {
+                self.storage.txindex = true;
+                self.storage.addrindex = true;
+                self.api.enable_rest = true;
+                self.api.read_only = true;
+                self.api.enable_wallet_endpoints = false;
+                self.api.enable_mining_endpoints = false;
+                self.api.cache_ttl_secs = 10;
+                self.api.rate_limit_per_minute = 120;
+            }
         }
     }
     
+    /// Where the node listens for API workers and mining commands
+    pub fn control_socket_path(&self) -> PathBuf {
+        self.api.control_socket.clone().unwrap_or_else(|| self.storage.data_dir.join("control.sock"))
+    }
+    
     pub fn is_testnet(&self) -> bool {
         self.network_type == NetworkType::Testnet
     }
     
+    pub fn is_regtest(&self) -> bool {
+        self.network_type == NetworkType::Regtest
+    }
+    
+    /// Block timing, emission and weight rules for this network. Mainnet values are
+    /// fixed; other networks take them from the mining and consensus sections.
+    /// Regtest difficulty stays at `mining.initial_difficulty` for good; testnet may move
+    /// the LWMA activation with `consensus.lwma_activation_height`. Off mainnet, a
+    /// chain that stalls may drop to minimum difficulty for a block, and the
+    /// checkpoints and assume-valid height come from the consensus section.
+    pub fn chain_params(&self) -> crate::Result<ChainParams> {
+        let checkpoints = self.consensus.checkpoints.iter()
+            .map(|(height, hash)| {
+                Hash256::from_hex(hash.trim())
+                    .map(|hash| (*height, hash))
+                    .map_err(|_| crate::QtcError::Consensus(format!("Invalid checkpoint hash at height {}: {}", height, hash)))
+            })
+            .collect::<crate::Result<BTreeMap<_, _>>>()?;
+        let configured = ChainParams {
+            target_block_time: self.mining.target_block_time,
+            difficulty_adjustment_interval: self.mining.difficulty_adjustment_blocks,
+            initial_reward: self.consensus.coinbase_reward,
+            halving_interval: self.consensus.halving_interval,
+            max_supply: self.consensus.max_supply,
+            pqc_witness_percent: self.consensus.pqc_witness_percent,
+            ..ChainParams::for_network(self.network_type)
+        };
+        let bootstrap = (self.consensus.min_difficulty_after_spacings > 0)
+            .then_some(self.consensus.min_difficulty_after_spacings);
+        
+        match self.network_type {
+            NetworkType::Mainnet => {
+                let mainnet = ChainParams::mainnet();
+                let lwma_moved = self.consensus.lwma_activation_height
+                    .is_some_and(|height| Some(height) != mainnet.lwma_activation_height);
+                if configured != mainnet || lwma_moved {
+                    return Err(crate::QtcError::Consensus(
+                        "Block time, retarget, emission and PQC weight settings cannot be changed on mainnet; use a testnet config for custom chains".to_string()
+                    ));
+                }
+                if !checkpoints.is_empty() || self.consensus.assume_valid_height.is_some() {
+                    return Err(crate::QtcError::Consensus(
+                        "Mainnet checkpoints and its assume-valid height are built in and cannot be changed".to_string()
+                    ));
+                }
+                Ok(mainnet)
+            }
+            NetworkType::Testnet => {
+                let testnet = ChainParams {
+                    min_difficulty_after_spacings: bootstrap,
+                    lwma_activation_height: self.consensus.lwma_activation_height.or(configured.lwma_activation_height),
+                    checkpoints,
+                    assume_valid: self.consensus.assume_valid_height,
+                    ..configured
+                };
+                testnet.validate()?;
+                Ok(testnet)
+            }
+            NetworkType::Regtest => {
+                // Regtest never retargets, so its first target is also its easiest
+                let initial_bits = Target::from_bits(self.mining.initial_bits).to_bits();
+                let regtest = ChainParams {
+                    initial_bits,
+                    pow_limit: initial_bits,
+                    difficulty_adjustment_interval: u64::MAX,
+                    min_difficulty_after_spacings: bootstrap,
+                    lwma_activation_height: None,
+                    checkpoints,
+                    assume_valid: self.consensus.assume_valid_height,
+                    ..configured
+                };
+                regtest.validate()?;
+                Ok(regtest)
+            }
+        }
+    }
+    
+    /// What this network's genesis block is built from. A data directory whose chain
+    /// started from something else is refused only when `consensus.genesis` is set,
+    /// since `qtcd init` may have been given its own parameters.
+    pub fn genesis_params(&self) -> crate::Result<GenesisParams> {
+        let network_default = GenesisParams {
+            message: self.get_genesis_message(),
+            address: self.get_genesis_address(),
+            ..GenesisParams::mainnet()
+        };
+        
+        match &self.consensus.genesis {
+            None => Ok(network_default),
+            Some(genesis) if self.network_type == NetworkType::Mainnet && *genesis != network_default => {
+                Err(crate::QtcError::Consensus(
+                    "The mainnet genesis block cannot be changed; use a testnet or regtest config for custom chains".to_string()
+                ))
+            }
+            Some(genesis) => {
+                genesis.validate(self.consensus.max_supply)?;
+                Ok(genesis.clone())
+            }
+        }
+    }
+    
     pub fn get_genesis_message(&self) -> String {
         match self.network_type {
             NetworkType::Mainnet => "The Times 10/Jul/2025 Chancellor on brink of second bailout for banks - QTC Genesis".to_string(),
             NetworkType::Testnet => "QTC Testnet Genesis - Jul 2025 - Testing blockchain implementation".to_string(),
+            NetworkType::Regtest => "QTC Regtest Genesis".to_string(),
         }
     }
     
@@ -154,6 +752,7 @@ impl Config {
         match self.network_type {
             NetworkType::Mainnet => "qtc1qw508d6qejxtdg4y5r3zarvary0c5xw7kxdz6v9".to_string(),
             NetworkType::Testnet => "qtctestnet1qw508d6qejxtdg4y5r3zarvary0c5xw7k2pz4m5".to_string(),
+            NetworkType::Regtest => "qtcregtest1qw508d6qejxtdg4y5r3zarvary0c5xw7kq3s2a8".to_string(),
         }
     }
 
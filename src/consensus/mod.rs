@@ -2,6 +2,14 @@
 
 pub mod validation;
 pub mod monetary;
+pub mod params;
+pub mod revalidation;
+pub mod supply;
+pub mod target;
 
 pub use validation::BlockValidator;
-pub use monetary::MonetaryPolicy;
+pub use monetary::{MonetaryPolicy, Units};
+pub use params::ChainParams;
+pub use revalidation::{revalidate_chain, RevalidationReport};
+pub use supply::SupplyAudit;
+pub use target::Target;
@@ -1,6 +1,15 @@
 use crate::{QtcError, Result};
 use serde::{Deserialize, Serialize};
 
+/// Blocks a coinbase output waits before it can be spent
+pub const COINBASE_MATURITY: u64 = 100;
+
+/// Whether a coinbase output confirmed at `coinbase_height` may be spent by a
+/// transaction in the block after `tip_height`
+pub fn is_coinbase_mature(coinbase_height: u64, tip_height: u64) -> bool {
+    tip_height >= coinbase_height.saturating_add(COINBASE_MATURITY)
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MonetaryPolicy {
     pub initial_reward: u64,      // Initial block reward in satoshis
@@ -19,7 +28,7 @@ impl MonetaryPolicy {
             max_supply: 1999999900000000,    // 19,999,999 QTC
             min_fee: 1000,                   // 0.00001 QTC
             dust_threshold: 546,             // 0.00000546 QTC
-            coinbase_maturity: 100,          // 100 blocks (~12.5 hours)
+            coinbase_maturity: COINBASE_MATURITY, // 100 blocks (~12.5 hours)
         }
     }
     
@@ -258,6 +267,46 @@ pub struct EconomicsInfo {
     pub blocks_to_max_supply: Option<u64>,
 }
 
+/// Units amounts are shown in
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Units {
+    #[default]
+    Qtc,
+    Sats,
+}
+
+impl Units {
+    /// `satoshis` in these units, with the unit name: `1.50000000 QTC` or `150000000 sats`
+    pub fn format(&self, satoshis: u64) -> String {
+        match self {
+            Units::Qtc => format!("{} QTC", MonetaryUtils::format_qtc(satoshis)),
+            Units::Sats => format!("{} sats", satoshis),
+        }
+    }
+}
+
+impl std::fmt::Display for Units {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Units::Qtc => write!(f, "qtc"),
+            Units::Sats => write!(f, "sats"),
+        }
+    }
+}
+
+impl std::str::FromStr for Units {
+    type Err = QtcError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "qtc" => Ok(Units::Qtc),
+            "sats" | "sat" | "satoshis" => Ok(Units::Sats),
+            _ => Err(QtcError::InvalidInput(format!("Unknown units '{}' (expected qtc or sats)", s))),
+        }
+    }
+}
+
 /// Utility functions for monetary calculations
 pub struct MonetaryUtils;
 
@@ -272,9 +321,9 @@ impl MonetaryUtils {
         (qtc * 100_000_000.0) as u64
     }
     
-    /// Format QTC amount with proper decimal places
+    /// Format QTC amount with proper decimal places; exact, unlike going through `f64`
     pub fn format_qtc(satoshis: u64) -> String {
-        format!("{:.8}", Self::satoshis_to_qtc(satoshis))
+        format!("{}.{:08}", satoshis / 100_000_000, satoshis % 100_000_000)
     }
     
     /// Parse QTC string to satoshis
@@ -402,6 +451,16 @@ mod tests {
         assert!(MonetaryUtils::parse_qtc("invalid").is_err());
     }
     
+    #[test]
+    fn test_units_format() {
+        // Through an f64 this came out one satoshi short
+        assert_eq!(MonetaryUtils::format_qtc(9_007_199_254_740_993), "90071992.54740993");
+        assert_eq!(Units::Qtc.format(150_000_000), "1.50000000 QTC");
+        assert_eq!(Units::Sats.format(150_000_000), "150000000 sats");
+        assert_eq!("SATS".parse::<Units>().unwrap(), Units::Sats);
+        assert!("btc".parse::<Units>().is_err());
+    }
+    
     #[test]
     fn test_max_supply_height() {
         let policy = MonetaryPolicy::new();
@@ -0,0 +1,338 @@
+//! Per-network consensus parameters for block timing and emission, and what
+//! keeps networks apart: network magic, address prefixes and default ports
+//!
+//! Checkpoints pin main chain blocks by hash, so a branch contradicting one is
+//! rejected however much work it has. Blocks up to the assume-valid height,
+//! which a checkpoint must cover, skip signature checks during initial block
+//! download; their proof of work, structure and amounts are still checked.
+//!
+//! Difficulties are compact target bits (see `consensus::target`); no retarget
+//! makes a block easier than the network's proof of work limit.
+
+use crate::config::NetworkType;
+use crate::consensus::monetary::MonetaryPolicy;
+use crate::consensus::target::{Target, DIFFICULTY_ONE_BITS, REGTEST_BITS};
+use crate::crypto::hash::Hash256;
+use crate::mining::difficulty::{DifficultyCalculator, DEFAULT_LWMA_WINDOW};
+use crate::{QtcError, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+pub const MAINNET_MAGIC: [u8; 4] = *b"QTCM";
+
+/// Deepest discount a network may give PQC signature bytes; a block's serialized
+/// size is bounded by its weight over this, which the P2P limits are sized for
+pub const MIN_PQC_WITNESS_PERCENT: u32 = 25;
+
+/// First mainnet block whose difficulty comes from the LWMA retarget
+pub const MAINNET_LWMA_ACTIVATION_HEIGHT: u64 = 50_000;
+
+/// Mainnet blocks every node must agree on, as (height, hash); extend with each release
+const MAINNET_CHECKPOINTS: &[(u64, &str)] = &[];
+
+/// Work a mainnet chain needs before it is trusted: a day of blocks at the launch
+/// target (2^40 hashes each). Raise with each release as the chain grows; a node
+/// bootstrapping a fresh network sets `consensus.minimum_chain_work` to 0.
+pub const MAINNET_MINIMUM_CHAIN_WORK: u128 = 192 << 40;
+
+/// Mainnet blocks up to this height skip signature checks during initial block
+/// download; raise with each release, never past the last checkpoint
+const MAINNET_ASSUME_VALID: Option<u64> = None;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChainParams {
+    pub magic: [u8; 4],                     // namespaces gossip topics, so networks can't cross-connect
+    pub address_prefix: String,             // addresses of other networks are rejected
+    pub default_port: u16,                  // P2P
+    pub target_block_time: u64,             // seconds
+    pub difficulty_adjustment_interval: u64, // blocks
+    #[serde(alias = "initial_difficulty")]
+    pub initial_bits: u32,                  // until the first adjustment window is full
+    #[serde(default = "default_pow_limit")]
+    pub pow_limit: u32,                     // bits of the easiest target any block may have
+    pub initial_reward: u64,                // satoshis
+    pub halving_interval: u64,              // blocks
+    pub max_supply: u64,                    // satoshis
+    pub pqc_witness_percent: u32,           // share of PQC signature bytes counted as size
+    #[serde(default)]
+    pub min_difficulty_after_spacings: Option<u64>, // parent this many target spacings old allows a minimum-difficulty block; never on mainnet
+    #[serde(default)]
+    pub lwma_activation_height: Option<u64>, // blocks from here on retarget by LWMA every block; None keeps the interval retarget
+    #[serde(default = "default_lwma_window")]
+    pub lwma_window: u64, // blocks
+    #[serde(default)]
+    pub checkpoints: BTreeMap<u64, Hash256>, // height to the only block hash accepted there
+    #[serde(default)]
+    pub assume_valid: Option<u64>, // blocks up to this height skip signature checks during initial block download
+}
+
+fn default_lwma_window() -> u64 {
+    DEFAULT_LWMA_WINDOW
+}
+
+fn default_pow_limit() -> u32 {
+    DIFFICULTY_ONE_BITS
+}
+
+impl ChainParams {
+    /// Mainnet consensus values; nodes on mainnet always use exactly these
+    pub fn mainnet() -> Self {
+        let policy = MonetaryPolicy::new();
+        let calculator = DifficultyCalculator::new();
+
+        Self {
+            magic: MAINNET_MAGIC,
+            address_prefix: "qtc".to_string(),
+            default_port: 8333,
+            target_block_time: calculator.target_block_time,
+            difficulty_adjustment_interval: calculator.adjustment_interval,
+            initial_bits: 0x1c01_0000, // forty zero bits
+            pow_limit: DIFFICULTY_ONE_BITS,
+            initial_reward: policy.initial_reward,
+            halving_interval: policy.halving_interval,
+            max_supply: policy.max_supply,
+            // Dilithium3 signatures run to kilobytes, so they're weighed like segwit witness data
+            pqc_witness_percent: MIN_PQC_WITNESS_PERCENT,
+            min_difficulty_after_spacings: None,
+            lwma_activation_height: Some(MAINNET_LWMA_ACTIVATION_HEIGHT),
+            lwma_window: DEFAULT_LWMA_WINDOW,
+            checkpoints: MAINNET_CHECKPOINTS.iter()
+                .map(|(height, hash)| (*height, Hash256::from_hex(hash).expect("mainnet checkpoint hashes are valid hex")))
+                .collect(),
+            assume_valid: MAINNET_ASSUME_VALID,
+        }
+    }
+
+    /// Testnet defaults; block timing and emission come from the config there
+    pub fn testnet() -> Self {
+        Self {
+            magic: *b"QTCT",
+            address_prefix: "tqtc".to_string(),
+            default_port: 18333,
+            checkpoints: BTreeMap::new(),
+            assume_valid: None,
+            ..Self::mainnet()
+        }
+    }
+
+    pub fn regtest() -> Self {
+        Self {
+            magic: *b"QTCR",
+            address_prefix: "rqtc".to_string(),
+            default_port: 18444,
+            initial_bits: REGTEST_BITS,
+            pow_limit: REGTEST_BITS,
+            checkpoints: BTreeMap::new(),
+            assume_valid: None,
+            ..Self::mainnet()
+        }
+    }
+
+    pub fn for_network(network_type: NetworkType) -> Self {
+        match network_type {
+            NetworkType::Mainnet => Self::mainnet(),
+            NetworkType::Testnet => Self::testnet(),
+            NetworkType::Regtest => Self::regtest(),
+        }
+    }
+
+    /// Gossipsub topic `name` on this network. Mainnet keeps the names it used
+    /// before networks had magic, so older mainnet nodes still hear it.
+    pub fn topic(&self, name: &str) -> String {
+        if self.magic == MAINNET_MAGIC {
+            format!("qtc/{}", name)
+        } else {
+            format!("qtc/{}/{}", hex::encode(self.magic), name)
+        }
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if self.target_block_time == 0 {
+            return Err(QtcError::Consensus("Target block time must be at least one second".to_string()));
+        }
+        if self.difficulty_adjustment_interval == 0 {
+            return Err(QtcError::Consensus("Difficulty adjustment interval must be at least one block".to_string()));
+        }
+        let limit = Target::from_bits(self.pow_limit);
+        if limit == Target::ZERO {
+            return Err(QtcError::Consensus(format!("Proof of work limit {:#010x} encodes no target", self.pow_limit)));
+        }
+        let initial = Target::from_bits(self.initial_bits);
+        if initial == Target::ZERO || initial > limit {
+            return Err(QtcError::Consensus(format!(
+                "Initial bits {:#010x} must encode a target no easier than the limit {:#010x}",
+                self.initial_bits, self.pow_limit
+            )));
+        }
+        if self.halving_interval == 0 {
+            return Err(QtcError::Consensus("Halving interval must be at least one block".to_string()));
+        }
+        if self.pqc_witness_percent < MIN_PQC_WITNESS_PERCENT || self.pqc_witness_percent > 1000 {
+            return Err(QtcError::Consensus(format!(
+                "PQC witness weight must be between {}% and 1000%, got {}%", MIN_PQC_WITNESS_PERCENT, self.pqc_witness_percent
+            )));
+        }
+        if self.min_difficulty_after_spacings == Some(0) {
+            return Err(QtcError::Consensus("Minimum-difficulty blocks need a gap of at least one target spacing".to_string()));
+        }
+        if self.lwma_window < 2 {
+            return Err(QtcError::Consensus("The LWMA window must span at least two blocks".to_string()));
+        }
+        if self.address_prefix.is_empty() {
+            return Err(QtcError::Consensus("Address prefix must not be empty".to_string()));
+        }
+        if self.initial_reward > self.max_supply {
+            return Err(QtcError::Consensus(format!(
+                "Initial reward {} exceeds max supply {}", self.initial_reward, self.max_supply
+            )));
+        }
+        if let Some(assume_valid) = self.assume_valid {
+            let covered = self.checkpoints.keys().next_back().is_some_and(|&height| height >= assume_valid);
+            if !covered {
+                return Err(QtcError::Consensus(format!(
+                    "Assume-valid height {} needs a checkpoint at or above it", assume_valid
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Refuse a block at `height` other than the one a checkpoint pins there
+    pub fn check_checkpoint(&self, height: u64, hash: &Hash256) -> Result<()> {
+        match self.checkpoints.get(&height) {
+            Some(checkpoint) if checkpoint != hash => Err(QtcError::Consensus(format!(
+                "Block {} at height {} contradicts the checkpoint {}", hash, height, checkpoint
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    /// The highest checkpoint at or below `height`, which no fork may reach below
+    pub fn last_checkpoint(&self, height: u64) -> Option<(u64, Hash256)> {
+        self.checkpoints.range(..=height).next_back().map(|(&height, &hash)| (height, hash))
+    }
+
+    /// Whether the block at `height` is old enough to skip signature checks while syncing
+    pub fn is_assumed_valid(&self, height: u64) -> bool {
+        self.assume_valid.is_some_and(|assume_valid| height <= assume_valid)
+    }
+
+    pub fn monetary_policy(&self) -> MonetaryPolicy {
+        MonetaryPolicy {
+            initial_reward: self.initial_reward,
+            halving_interval: self.halving_interval,
+            max_supply: self.max_supply,
+            ..MonetaryPolicy::new()
+        }
+    }
+
+    /// The proof of work limit, if a block stamped `timestamp` comes long enough after
+    /// its parent that a new network's lone miner shouldn't wait for the retarget
+    pub fn bootstrap_bits(&self, parent_timestamp: u64, timestamp: u64) -> Option<u32> {
+        let spacings = self.min_difficulty_after_spacings?;
+        let gap = self.target_block_time.saturating_mul(spacings);
+        (timestamp > parent_timestamp.saturating_add(gap)).then_some(self.pow_limit)
+    }
+
+    /// Whether the block at `height` gets its difficulty from the LWMA retarget
+    pub fn lwma_active(&self, height: u64) -> bool {
+        self.lwma_activation_height.is_some_and(|activation| height >= activation)
+    }
+
+    pub fn difficulty_calculator(&self) -> DifficultyCalculator {
+        DifficultyCalculator {
+            target_block_time: self.target_block_time,
+            adjustment_interval: self.difficulty_adjustment_interval,
+            pow_limit: self.pow_limit,
+            lwma_window: self.lwma_window,
+            ..DifficultyCalculator::new()
+        }
+    }
+}
+
+impl Default for ChainParams {
+    fn default() -> Self {
+        Self::mainnet()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_custom_params() -> Result<()> {
+        let params = ChainParams {
+            target_block_time: 60,
+            difficulty_adjustment_interval: 20,
+            initial_reward: 5_000_000_000,
+            halving_interval: 1_000,
+            ..ChainParams::mainnet()
+        };
+        params.validate()?;
+
+        let policy = params.monetary_policy();
+        assert_eq!(policy.coinbase_reward(999), 5_000_000_000);
+        assert_eq!(policy.coinbase_reward(1_000), 2_500_000_000);
+        assert_eq!(params.difficulty_calculator().target_block_time, 60);
+
+        assert!(ChainParams { halving_interval: 0, ..params.clone() }.validate().is_err());
+        assert!(ChainParams { target_block_time: 0, ..params.clone() }.validate().is_err());
+        assert!(ChainParams { pqc_witness_percent: 0, ..params.clone() }.validate().is_err());
+        assert!(ChainParams { pqc_witness_percent: MIN_PQC_WITNESS_PERCENT - 1, ..params.clone() }.validate().is_err());
+        assert!(ChainParams { min_difficulty_after_spacings: Some(0), ..params.clone() }.validate().is_err());
+        assert!(ChainParams { lwma_window: 1, ..params.clone() }.validate().is_err());
+        assert!(ChainParams { initial_bits: 0x2000_ffff, ..params.clone() }.validate().is_err());
+        assert!(ChainParams { initial_bits: 0x0492_3456, ..params.clone() }.validate().is_err());
+        // Old difficulties still parse, as targets at least as hard as the limit
+        assert!(ChainParams { initial_bits: 20, ..params.clone() }.validate().is_ok());
+        assert!(ChainParams::regtest().validate().is_ok());
+
+        assert!(!params.lwma_active(MAINNET_LWMA_ACTIVATION_HEIGHT - 1));
+        assert!(params.lwma_active(MAINNET_LWMA_ACTIVATION_HEIGHT));
+        assert!(!ChainParams { lwma_activation_height: None, ..params }.lwma_active(u64::MAX));
+        Ok(())
+    }
+
+    #[test]
+    fn test_checkpoints() {
+        let (a, b) = (Hash256::hash(b"a"), Hash256::hash(b"b"));
+        let params = ChainParams {
+            checkpoints: BTreeMap::from([(10, a), (20, b)]),
+            assume_valid: Some(15),
+            ..ChainParams::testnet()
+        };
+        assert!(params.validate().is_ok());
+        assert!(params.check_checkpoint(10, &a).is_ok());
+        assert!(params.check_checkpoint(11, &b).is_ok());
+        assert!(params.check_checkpoint(20, &a).is_err());
+        assert_eq!(params.last_checkpoint(9), None);
+        assert_eq!(params.last_checkpoint(19), Some((10, a)));
+        assert!(params.is_assumed_valid(15) && !params.is_assumed_valid(16));
+
+        // Assumed-valid blocks must lie under a checkpoint
+        assert!(ChainParams { assume_valid: Some(21), ..params.clone() }.validate().is_err());
+        assert!(ChainParams { checkpoints: BTreeMap::new(), ..params }.validate().is_err());
+    }
+
+    #[test]
+    fn test_bootstrap_bits() {
+        let mainnet = ChainParams::mainnet();
+        assert_eq!(mainnet.bootstrap_bits(0, 1_000_000), None);
+
+        let testnet = ChainParams { min_difficulty_after_spacings: Some(2), ..mainnet };
+        assert_eq!(testnet.bootstrap_bits(1_000, 1_000 + 900), None);
+        assert_eq!(testnet.bootstrap_bits(1_000, 1_000 + 901), Some(DIFFICULTY_ONE_BITS));
+        assert_eq!(ChainParams { pow_limit: REGTEST_BITS, ..testnet }.bootstrap_bits(0, 901), Some(REGTEST_BITS));
+    }
+
+    #[test]
+    fn test_networks_are_kept_apart() {
+        let (mainnet, testnet, regtest) = (ChainParams::mainnet(), ChainParams::testnet(), ChainParams::regtest());
+        assert_eq!(mainnet.topic("blocks"), "qtc/blocks");
+        assert_ne!(testnet.topic("blocks"), mainnet.topic("blocks"));
+        assert_ne!(testnet.topic("blocks"), regtest.topic("blocks"));
+        assert_ne!(testnet.address_prefix, mainnet.address_prefix);
+        assert_eq!(ChainParams::for_network(NetworkType::Regtest).default_port, 18444);
+    }
+}
@@ -0,0 +1,370 @@
+//! Re-checking the stored chain from scratch
+//!
+//! Blocks are read back by height and checked the way they were when they
+//! connected: proof of work, linkage, merkle root, coinbase value, and every
+//! transaction by the validator's rules, signatures included, against the
+//! outputs it spends. Spends are tracked in a UTXO set rebuilt in a scratch
+//! database, which is compared with the node's own UTXO set at the end. Quick
+//! mode stops at the headers.
+
+use crate::consensus::validation::{verify_input_script, BlockValidator};
+use crate::core::utxo::UtxoSet;
+use crate::core::{Block, BlockHeader, Blockchain, Transaction};
+use crate::crypto::hash::{Hash256, Hashable};
+use crate::storage::Database;
+use crate::{QtcError, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::path::Path;
+use std::sync::Arc;
+
+/// Blocks whose timestamps a new block must beat the median of
+const MEDIAN_TIME_SPAN: usize = 11;
+
+/// A check's outcome: `Err` holds why the chain is invalid, as opposed to why checking failed
+type Verdict<T> = std::result::Result<T, String>;
+
+/// The first thing found wrong
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Inconsistency {
+    pub height: u64,
+    pub hash: Option<Hash256>,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RevalidationReport {
+    pub from_height: u64,
+    pub to_height: u64,
+    pub quick: bool,
+    pub blocks_checked: u64,
+    pub transactions_checked: u64,
+    pub utxos_compared: usize,
+    pub inconsistency: Option<Inconsistency>,
+}
+
+impl RevalidationReport {
+    pub fn is_valid(&self) -> bool {
+        self.inconsistency.is_none()
+    }
+}
+
+/// Check the main chain from `from_height` to the tip. `scratch_dir` must not hold anything
+/// worth keeping; the rebuilt UTXO set is written there. Errors are for checks that could
+/// not run at all; a chain that fails a check comes back as a report with an inconsistency.
+pub fn revalidate_chain(blockchain: &Blockchain, from_height: u64, quick: bool, scratch_dir: &Path) -> Result<RevalidationReport> {
+    let mut report = RevalidationReport {
+        from_height,
+        to_height: blockchain.height,
+        quick,
+        blocks_checked: 0,
+        transactions_checked: 0,
+        utxos_compared: 0,
+        inconsistency: None,
+    };
+    if from_height > blockchain.height {
+        return Err(QtcError::InvalidInput(format!(
+            "Height {} is above the tip at {}", from_height, blockchain.height
+        )));
+    }
+
+    let db = blockchain.database();
+    if !quick {
+        if let Some(pruned) = db.get_pruned_height()? {
+            return Err(QtcError::Blockchain(format!(
+                "Blocks up to {} are pruned, so the UTXO set can't be rebuilt; use --quick to check headers", pruned
+            )));
+        }
+    }
+
+    // Headers of the blocks before `from_height` seed the linkage and median time checks
+    let mut recent_times = VecDeque::with_capacity(MEDIAN_TIME_SPAN);
+    for height in from_height.saturating_sub(MEDIAN_TIME_SPAN as u64)..from_height {
+        recent_times.push_back(stored_header(blockchain, height)?.timestamp);
+    }
+    let mut previous_hash = match from_height {
+        0 => Hash256::zero(),
+        height => stored_header(blockchain, height - 1)?.hash(),
+    };
+
+    let mut scratch = match quick {
+        true => None,
+        false => Some(ScratchUtxos::open(blockchain, from_height, scratch_dir)?),
+    };
+
+    for height in from_height..=blockchain.height {
+        let header = stored_header(blockchain, height)?;
+        let hash = header.hash();
+        let fail = |reason: String| Some(Inconsistency { height, hash: Some(hash), reason });
+
+        if let Some(reason) = check_header(&header, height, &previous_hash, &recent_times) {
+            report.inconsistency = fail(reason);
+            break;
+        }
+        if let Some(scratch) = scratch.as_mut() {
+            let block = blockchain.get_block_by_height(height)?
+                .ok_or_else(|| QtcError::Blockchain(format!("Block at height {} not found", height)))?;
+            if block.hash() != hash {
+                report.inconsistency = fail("Block body does not match the indexed header".to_string());
+                break;
+            }
+            if let Err(reason) = scratch.connect(blockchain, &block)? {
+                report.inconsistency = fail(reason);
+                break;
+            }
+            report.transactions_checked += block.transactions.len() as u64;
+        }
+
+        if recent_times.len() == MEDIAN_TIME_SPAN {
+            recent_times.pop_front();
+        }
+        recent_times.push_back(header.timestamp);
+        previous_hash = hash;
+        report.blocks_checked += 1;
+        if report.blocks_checked.is_multiple_of(10_000) {
+            log::info!("🔍 Validated up to height {}", height);
+        }
+    }
+
+    if let Some(mut scratch) = scratch {
+        if report.inconsistency.is_none() {
+            blockchain.flush_utxos()?;
+            match scratch.compare(db)? {
+                Ok(compared) => report.utxos_compared = compared,
+                Err(reason) => {
+                    report.inconsistency = Some(Inconsistency { height: blockchain.height, hash: Some(blockchain.tip), reason });
+                }
+            }
+        }
+    }
+    Ok(report)
+}
+
+fn stored_header(blockchain: &Blockchain, height: u64) -> Result<BlockHeader> {
+    blockchain.get_block_header_by_height(height)?
+        .ok_or_else(|| QtcError::Blockchain(format!("Header at height {} not found", height)))
+}
+
+/// What is wrong with `header`, if anything, given the block before it
+fn check_header(header: &BlockHeader, height: u64, previous_hash: &Hash256, recent_times: &VecDeque<u64>) -> Option<String> {
+    if header.height != height {
+        return Some(format!("Header says height {}", header.height));
+    }
+    if &header.previous_hash != previous_hash {
+        return Some(format!("Previous hash {} does not match block {}", header.previous_hash, previous_hash));
+    }
+    // The genesis block is fixed rather than mined
+    if height > 0 && !Blockchain::header_meets_target(header) {
+        return Some(format!("Hash does not meet its bits {:#010x}", header.bits));
+    }
+    if recent_times.len() == MEDIAN_TIME_SPAN {
+        let mut times: Vec<u64> = recent_times.iter().copied().collect();
+        times.sort_unstable();
+        if header.timestamp <= times[MEDIAN_TIME_SPAN / 2] {
+            return Some(format!("Timestamp {} is not after the median of the previous {} blocks", header.timestamp, MEDIAN_TIME_SPAN));
+        }
+    }
+    None
+}
+
+/// A UTXO set rebuilt block by block in a throwaway database
+struct ScratchUtxos {
+    utxos: UtxoSet,
+    db: Arc<Database>,
+}
+
+impl ScratchUtxos {
+    /// Start from an empty set and replay the blocks before `from_height` unchecked
+    fn open(blockchain: &Blockchain, from_height: u64, dir: &Path) -> Result<Self> {
+        if dir.exists() {
+            std::fs::remove_dir_all(dir)?;
+        }
+        let db = Arc::new(Database::new(dir)?);
+        let mut utxos = UtxoSet::new(db.clone());
+        utxos.set_flush_interval(u64::MAX);
+
+        for height in 0..from_height {
+            let block = blockchain.get_block_by_height(height)?
+                .ok_or_else(|| QtcError::Blockchain(format!("Block at height {} not found", height)))?;
+            utxos.apply_block(&block)?;
+        }
+        Ok(Self { utxos, db })
+    }
+
+    /// Check `block` against the outputs it spends and apply it; the error is the reason it's invalid
+    fn connect(&mut self, blockchain: &Blockchain, block: &Block) -> Result<Verdict<()>> {
+        let height = block.header.height;
+        let Some((coinbase, transactions)) = block.transactions.split_first() else {
+            return Ok(Err("Block has no transactions".to_string()));
+        };
+        if !coinbase.is_coinbase() {
+            return Ok(Err("First transaction is not a coinbase".to_string()));
+        }
+        if Block::calculate_merkle_root(&block.transactions) != block.header.merkle_root {
+            return Ok(Err("Merkle root does not match the transactions".to_string()));
+        }
+
+        let mut fees = 0u64;
+        for (index, tx) in transactions.iter().enumerate() {
+            match self.check_transaction(blockchain.validator(), tx, block)? {
+                Ok(fee) => fees = fees.saturating_add(fee),
+                Err(reason) => return Ok(Err(format!("Transaction {} ({}): {}", index + 1, tx.hash(), reason))),
+            }
+            // Later transactions may spend this one's outputs
+            self.utxos.apply_transaction(tx, height)?;
+        }
+
+        // The genesis coinbase pays whatever was premined
+        let allowed = blockchain.monetary_policy().coinbase_reward(height).saturating_add(fees);
+        if height > 0 && coinbase.total_output_value() > allowed {
+            return Ok(Err(format!("Coinbase pays {} but only {} is allowed", coinbase.total_output_value(), allowed)));
+        }
+        self.utxos.apply_transaction(coinbase, height)?;
+        Ok(Ok(()))
+    }
+
+    /// The fee `tx` pays, or why it can't be spent in `block`; everything but
+    /// finding the outputs it spends is the validator's own check
+    fn check_transaction(&self, validator: &BlockValidator, tx: &Transaction, block: &Block) -> Result<Verdict<u64>> {
+        if tx.is_coinbase() {
+            return Ok(Err("Coinbase after the first transaction".to_string()));
+        }
+
+        let mut spent = Vec::with_capacity(tx.inputs.len());
+        for (index, input) in tx.inputs.iter().enumerate() {
+            let outpoint = &input.previous_output;
+            let Some(utxo) = self.utxos.get_utxo(outpoint)? else {
+                return Ok(Err(format!("Input {} spends {}:{}, which is not unspent", index, outpoint.txid, outpoint.vout)));
+            };
+            spent.push(utxo);
+        }
+
+        let fee = match validator.check_block_transaction(tx, block, &spent) {
+            Ok(fee) => fee,
+            Err(e) => return Ok(Err(e.to_string())),
+        };
+        for (index, utxo) in spent.iter().enumerate() {
+            if let Err(e) = verify_input_script(tx, index, &utxo.script_pubkey) {
+                return Ok(Err(e.to_string()));
+            }
+        }
+        Ok(Ok(fee))
+    }
+
+    /// Compare the rebuilt set with the stored one; returns how many outputs matched
+    fn compare(&mut self, stored: &Database) -> Result<Verdict<usize>> {
+        self.utxos.flush()?;
+        let rebuilt: HashMap<_, _> = self.db.get_all_utxos()?.into_iter().collect();
+        let stored: HashMap<_, _> = stored.get_all_utxos()?.into_iter().collect();
+
+        for (outpoint, entry) in &stored {
+            let matches = rebuilt.get(outpoint).is_some_and(|rebuilt| {
+                (rebuilt.value, &rebuilt.script_pubkey, rebuilt.height, rebuilt.is_coinbase)
+                    == (entry.value, &entry.script_pubkey, entry.height, entry.is_coinbase)
+            });
+            if !matches {
+                return Ok(Err(format!(
+                    "Stored UTXO {}:{} {} the replayed chain", outpoint.txid, outpoint.vout,
+                    if rebuilt.contains_key(outpoint) { "differs from" } else { "is not in" }
+                )));
+            }
+        }
+        if let Some(outpoint) = rebuilt.keys().find(|outpoint| !stored.contains_key(outpoint)) {
+            return Ok(Err(format!("Stored UTXO set is missing {}:{}", outpoint.txid, outpoint.vout)));
+        }
+        Ok(Ok(stored.len()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::consensus::target::REGTEST_BITS;
+    use crate::consensus::ChainParams;
+    use crate::config::Config;
+    use crate::core::transaction::{sign_p2pkh_input, OutPoint};
+    use crate::crypto::keys::KeyPair;
+    use crate::mining::generate_blocks;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_revalidate_chain() -> Result<()> {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Arc::new(Database::new(temp_dir.path().join("test.db"))?);
+        let mut chain = Blockchain::new(db.clone())?;
+        chain.set_chain_params(ChainParams {
+            initial_bits: REGTEST_BITS,
+            difficulty_adjustment_interval: 1_000_000,
+            ..ChainParams::mainnet()
+        });
+
+        let mut parent = chain.get_block_by_height(0)?.unwrap();
+        for height in 1..=14 {
+            let reward = chain.monetary_policy().coinbase_reward(height);
+            let coinbase = Transaction::new_coinbase("qtc1validator".to_string(), reward, format!("block {}", height));
+            let mut block = Block::new(parent.hash(), vec![coinbase], REGTEST_BITS, height);
+            block.header.timestamp = parent.header.timestamp + 30;
+            while !chain.is_valid_proof_of_work(&block) {
+                block.increment_nonce();
+            }
+            chain.add_block(block.clone())?;
+            parent = block;
+        }
+
+        let scratch = temp_dir.path().join("scratch");
+        let report = revalidate_chain(&chain, 0, false, &scratch)?;
+        assert!(report.is_valid(), "{:?}", report.inconsistency);
+        assert_eq!((report.blocks_checked, report.transactions_checked, report.utxos_compared), (15, 15, 15));
+        let report = revalidate_chain(&chain, 12, false, &scratch)?;
+        assert!(report.is_valid() && report.blocks_checked == 3);
+
+        // A UTXO lost from the stored set shows up against the replay; headers alone can't see it
+        let coinbase = chain.get_block_by_height(7)?.unwrap().transactions[0].hash();
+        db.delete_utxo(&OutPoint::new(coinbase, 0))?;
+        let report = revalidate_chain(&chain, 0, false, &scratch)?;
+        let inconsistency = report.inconsistency.unwrap();
+        assert_eq!(inconsistency.height, 14);
+        assert!(inconsistency.reason.contains("missing"), "{}", inconsistency.reason);
+        assert!(revalidate_chain(&chain, 0, true, &scratch)?.is_valid());
+
+        assert!(revalidate_chain(&chain, 15, true, &scratch).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_replay_applies_the_validator_rules() -> Result<()> {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Arc::new(Database::new(temp_dir.path().join("test.db"))?);
+        let mut chain = Blockchain::new(db)?;
+        chain.set_chain_params(Config::regtest().chain_params()?);
+        let owner = KeyPair::new()?;
+        generate_blocks(&mut chain, 101, &owner.address())?;
+        let scratch = ScratchUtxos::open(&chain, chain.height + 1, &temp_dir.path().join("scratch"))?;
+
+        let coin = chain.get_block_by_height(1)?.unwrap().transactions[0].clone();
+        let spend = |value: u64, sign: bool| -> Result<Transaction> {
+            let mut tx = Transaction::new();
+            tx.add_input(OutPoint::new(coin.hash(), 0), b"not a signature".to_vec());
+            tx.add_output(value, &owner.address());
+            if sign {
+                sign_p2pkh_input(&mut tx, 0, &owner.private_key)?;
+            }
+            Ok(tx)
+        };
+        let check = |tx: &Transaction, height: u64| -> Result<Verdict<u64>> {
+            let coinbase = Transaction::new_coinbase(owner.address(), 1, "candidate".to_string());
+            let block = Block::new(chain.tip, vec![coinbase, tx.clone()], REGTEST_BITS, height);
+            scratch.check_transaction(chain.validator(), tx, &block)
+        };
+
+        let valid = spend(coin.outputs[0].value - 10_000, true)?;
+        assert_eq!(check(&valid, 102)?, Ok(10_000));
+        // Maturity counts from the parent, as when the block connected
+        assert!(check(&valid, 101)?.is_err());
+        // Unrecognised scripts, dust and fees above the outputs' worth all fail
+        assert!(check(&spend(coin.outputs[0].value - 10_000, false)?, 102)?.is_err());
+        assert!(check(&spend(100, true)?, 102)?.is_err());
+        assert!(check(&spend(coin.outputs[0].value / 3, true)?, 102)?.is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,217 @@
+//! Auditing the coin supply against the monetary policy
+//!
+//! `ChainState.total_supply` is what the policy says should exist, not what the
+//! blocks actually created. The audit replays the main chain from genesis and
+//! counts each block's issuance as the value of its outputs less the value of
+//! the outputs it spent (read from the block's undo data), so fees recycled
+//! into coinbases aren't counted twice. A block issuing more than its subsidy
+//! is an inflation discrepancy. Outputs that provably can never be spent are
+//! tallied separately and left out of the circulating figure.
+//!
+//! An audit can be carried forward as the chain grows; a reorg below the
+//! audited tip starts it over.
+
+use crate::core::{Block, Blockchain};
+use crate::crypto::hash::{Hash256, Hashable};
+use crate::{QtcError, Result};
+use serde::{Deserialize, Serialize};
+
+/// A block that created more coins than the policy allowed it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SupplyDiscrepancy {
+    pub height: u64,
+    pub hash: Hash256,
+    pub issued: u64,  // satoshis
+    pub allowed: u64, // the subsidy, or the premine for genesis
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SupplyAudit {
+    pub height: u64,
+    pub tip: Hash256,
+    pub blocks_audited: u64,
+    pub supply: u64,      // coins the blocks created, less those they spent
+    pub unspendable: u64, // of the supply, sent to outputs that can never be spent
+    pub expected: u64,    // premine plus every subsidy up to the tip
+    pub discrepancies: Vec<SupplyDiscrepancy>,
+}
+
+impl SupplyAudit {
+    /// Audit the main chain from genesis to the tip
+    pub fn run(blockchain: &Blockchain) -> Result<Self> {
+        let mut audit = Self::default();
+        audit.advance(blockchain)?;
+        Ok(audit)
+    }
+
+    /// Carry the audit forward to the current tip, starting over if the
+    /// audited tip is no longer on the main chain
+    pub fn advance(&mut self, blockchain: &Blockchain) -> Result<()> {
+        if self.blocks_audited > 0 {
+            let still_main = blockchain.get_block_header_by_height(self.height)?
+                .is_some_and(|header| header.hash() == self.tip);
+            if !still_main {
+                log::info!("🧮 Audited tip {} left the main chain, auditing the supply again", self.tip);
+                *self = Self::default();
+            }
+        }
+
+        let start = if self.blocks_audited == 0 { 0 } else { self.height + 1 };
+        if start > blockchain.height {
+            return Ok(());
+        }
+        if let Some(pruned) = blockchain.database().get_pruned_height()? {
+            if start <= pruned {
+                return Err(QtcError::Blockchain(format!(
+                    "Blocks up to {} are pruned, so the supply can't be audited", pruned
+                )));
+            }
+        }
+
+        for height in start..=blockchain.height {
+            let block = blockchain.get_block_by_height(height)?
+                .ok_or_else(|| QtcError::Blockchain(format!("Block at height {} not found", height)))?;
+            self.connect(blockchain, &block)?;
+            if self.blocks_audited.is_multiple_of(10_000) {
+                log::info!("🧮 Audited the supply up to height {}", height);
+            }
+        }
+        Ok(())
+    }
+
+    fn connect(&mut self, blockchain: &Blockchain, block: &Block) -> Result<()> {
+        let height = block.header.height;
+        let hash = block.hash();
+
+        let created: u128 = block.transactions.iter().map(|tx| tx.total_output_value() as u128).sum();
+        let spent: u128 = if block.transactions.len() > 1 {
+            let undo = blockchain.database().get_block_undo(&hash)?
+                .ok_or_else(|| QtcError::Blockchain(format!("No undo data for block {} at height {}", hash, height)))?;
+            undo.iter().map(|(_, utxo)| utxo.value as u128).sum()
+        } else {
+            0
+        };
+
+        // The genesis coinbase pays the premine and nothing else
+        let allowed = match height {
+            0 => blockchain.calculate_total_supply(0),
+            height => blockchain.monetary_policy().coinbase_reward(height),
+        };
+        // Negative when a coinbase leaves fees unclaimed, which destroys them
+        let issued = created as i128 - spent as i128;
+        if issued > allowed as i128 {
+            self.discrepancies.push(SupplyDiscrepancy { height, hash, issued: issued as u64, allowed });
+        }
+
+        self.supply = (self.supply as i128 + issued).clamp(0, u64::MAX as i128) as u64;
+        self.unspendable = self.unspendable.saturating_add(block.transactions.iter()
+            .flat_map(|tx| &tx.outputs)
+            .filter(|output| output.is_unspendable())
+            .map(|output| output.value)
+            .fold(0, u64::saturating_add));
+        self.expected = self.expected.saturating_add(allowed);
+        self.height = height;
+        self.tip = hash;
+        self.blocks_audited += 1;
+        Ok(())
+    }
+
+    /// Coins that can still move
+    pub fn circulating(&self) -> u64 {
+        self.supply.saturating_sub(self.unspendable)
+    }
+
+    /// Coins beyond what the policy allows up to the tip
+    pub fn inflation(&self) -> u64 {
+        self.supply.saturating_sub(self.expected)
+    }
+
+    /// Subsidies and fees miners left unclaimed, which no one can claim now
+    pub fn unclaimed(&self) -> u64 {
+        self.expected.saturating_sub(self.supply)
+    }
+
+    pub fn is_sound(&self) -> bool {
+        self.discrepancies.is_empty() && self.inflation() == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::consensus::target::REGTEST_BITS;
+    use crate::consensus::ChainParams;
+    use crate::core::transaction::{sign_p2pkh_input, OutPoint, TxOutput, OP_RETURN};
+    use crate::core::Transaction;
+    use crate::crypto::keys::PrivateKey;
+    use crate::storage::Database;
+    use std::sync::Arc;
+    use tempfile::TempDir;
+
+    fn mine(chain: &Blockchain, transactions: Vec<Transaction>, bonus: u64) -> Result<Block> {
+        mine_paying(chain, "qtc1auditor", transactions, bonus)
+    }
+
+    fn mine_paying(chain: &Blockchain, payee: &str, transactions: Vec<Transaction>, bonus: u64) -> Result<Block> {
+        let parent = chain.get_block_header_by_height(chain.height)?.unwrap();
+        let height = chain.height + 1;
+        let reward = chain.monetary_policy().coinbase_reward(height) + bonus;
+        let mut all = vec![Transaction::new_coinbase(payee.to_string(), reward, format!("block {}", height))];
+        all.extend(transactions);
+        let mut block = Block::new(chain.tip, all, REGTEST_BITS, height);
+        block.header.timestamp = parent.timestamp + 30;
+        while !chain.is_valid_proof_of_work(&block) {
+            block.increment_nonce();
+        }
+        Ok(block)
+    }
+
+    #[test]
+    fn test_audit_counts_issuance_and_burns() -> Result<()> {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Arc::new(Database::new(temp_dir.path().join("test.db"))?);
+        let mut chain = Blockchain::new(db)?;
+        chain.set_chain_params(ChainParams {
+            initial_bits: REGTEST_BITS,
+            difficulty_adjustment_interval: 1_000_000,
+            ..ChainParams::mainnet()
+        });
+        let key = PrivateKey::new()?;
+        chain.add_block(mine_paying(&chain, &key.public_key()?.to_address(), vec![], 0)?)?;
+        for _ in 0..100 {
+            let block = mine(&chain, vec![], 0)?;
+            chain.add_block(block)?;
+        }
+
+        // Spend the first coinbase into a burn and a fee the miner leaves unclaimed
+        let funding = chain.get_block_by_height(1)?.unwrap().transactions[0].clone();
+        let value = funding.total_output_value();
+        let mut burn = Transaction::new();
+        burn.add_input(OutPoint::new(funding.hash(), 0), Vec::new());
+        burn.outputs.push(TxOutput { value: value - 1_000, script_pubkey: vec![OP_RETURN] });
+        sign_p2pkh_input(&mut burn, 0, &key)?;
+        let block = mine(&chain, vec![burn], 0)?;
+        chain.add_block(block)?;
+
+        let subsidies: u64 = (1..=102).map(|height| chain.monetary_policy().coinbase_reward(height)).sum();
+        let audit = SupplyAudit::run(&chain)?;
+        assert!(audit.is_sound(), "{:?}", audit.discrepancies);
+        assert_eq!((audit.height, audit.blocks_audited), (102, 103));
+        assert_eq!((audit.expected, audit.supply, audit.unclaimed()), (subsidies, subsidies - 1_000, 1_000));
+        assert_eq!(audit.circulating(), subsidies - value);
+
+        // Carried forward over new blocks; one paying itself too much is caught
+        let mut audit = audit;
+        let block = mine(&chain, vec![], 0)?;
+        chain.add_block(block)?;
+        audit.advance(&chain)?;
+        assert_eq!((audit.blocks_audited, audit.unclaimed()), (104, 1_000));
+
+        let greedy = mine(&chain, vec![], 5)?;
+        audit.connect(&chain, &greedy)?;
+        assert_eq!(audit.discrepancies[0].hash, greedy.hash());
+        assert_eq!(audit.discrepancies[0].issued - audit.discrepancies[0].allowed, 5);
+        assert!(!audit.is_sound());
+        Ok(())
+    }
+}
@@ -0,0 +1,331 @@
+//! 256-bit proof of work targets and their compact "bits" encoding
+//!
+//! A block's hash, read as a big-endian number, must not exceed the target its
+//! header's `bits` encode. As in Bitcoin's nBits, the top byte of `bits` is
+//! the target's length in bytes and the low three bytes its leading digits, so
+//! retargets can move the target by any fraction rather than a whole zero bit.
+//!
+//! Headers written before compact bits held a difficulty counting required
+//! leading zeros instead: a nibble-sized zero digit per unit of four, plus one
+//! zero bit per unit left over. A length byte of zero never encodes a usable
+//! target, so values below `0x01000000` are still read that way; stored blocks
+//! keep their bytes, hashes and chain work, and new blocks always carry
+//! compact bits.
+//!
+//! Difficulty, for display, is how many times harder a target is than
+//! `DIFFICULTY_ONE_BITS`, the easiest mainnet target.
+
+use crate::crypto::hash::Hash256;
+use std::cmp::Ordering;
+use std::fmt;
+
+/// The target difficulty 1.0 stands for; the mainnet proof of work limit
+pub const DIFFICULTY_ONE_BITS: u32 = 0x1f40_0000;
+
+/// Regtest's target; about half of all hashes meet it
+pub const REGTEST_BITS: u32 = 0x207f_ffff;
+
+/// Values of `bits` below this are pre-compact difficulties
+const LEGACY_BITS_LIMIT: u32 = 0x0100_0000;
+
+/// An unsigned 256-bit target, kept as little-endian 64-bit limbs
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Target([u64; 4]);
+
+impl Target {
+    pub const ZERO: Self = Self([0; 4]);
+    pub const MAX: Self = Self([u64::MAX; 4]);
+
+    /// Decode a header's `bits`. Negative or overflowing encodings give a zero
+    /// target, which no hash meets.
+    pub fn from_bits(bits: u32) -> Self {
+        if bits < LEGACY_BITS_LIMIT {
+            return Self::from_legacy_difficulty(bits);
+        }
+        let size = bits >> 24;
+        let mantissa = bits & 0x007f_ffff;
+        if bits & 0x0080_0000 != 0 && mantissa != 0 {
+            return Self::ZERO;
+        }
+        if size <= 3 {
+            return Self::from_u64((mantissa >> (8 * (3 - size))) as u64);
+        }
+        let overflows = size > 34 || (mantissa > 0xff && size > 33) || (mantissa > 0xffff && size > 32);
+        if overflows {
+            return Self::ZERO;
+        }
+        Self::from_u64(mantissa as u64).shl(8 * (size - 3))
+    }
+
+    /// The compact encoding, rounding the target down to its three leading bytes
+    pub fn to_bits(&self) -> u32 {
+        let mut size = self.bit_len().div_ceil(8);
+        let mut mantissa = if size <= 3 {
+            (self.0[0] << (8 * (3 - size))) as u32
+        } else {
+            self.shr(8 * (size - 3)).0[0] as u32
+        };
+        if mantissa & 0x0080_0000 != 0 {
+            mantissa >>= 8;
+            size += 1;
+        }
+        mantissa | (size << 24)
+    }
+
+    /// The target a hash with `zeros` leading zero bits meets: 2^(256 - zeros)
+    pub fn from_zero_bits(zeros: u32) -> Self {
+        match zeros {
+            0 => Self::MAX,
+            1..=256 => Self::from_u64(1).shl(256 - zeros),
+            _ => Self::ZERO,
+        }
+    }
+
+    fn from_legacy_difficulty(difficulty: u32) -> Self {
+        Self::from_zero_bits((difficulty / 4) * 8 + difficulty % 4)
+    }
+
+    fn from_u64(value: u64) -> Self {
+        Self([value, 0, 0, 0])
+    }
+
+    pub fn from_be_bytes(bytes: &[u8; 32]) -> Self {
+        let mut limbs = [0u64; 4];
+        for (i, chunk) in bytes.chunks_exact(8).enumerate() {
+            limbs[3 - i] = u64::from_be_bytes(chunk.try_into().unwrap());
+        }
+        Self(limbs)
+    }
+
+    pub fn to_be_bytes(&self) -> [u8; 32] {
+        let mut bytes = [0u8; 32];
+        for (i, limb) in self.0.iter().rev().enumerate() {
+            bytes[i * 8..(i + 1) * 8].copy_from_slice(&limb.to_be_bytes());
+        }
+        bytes
+    }
+
+    /// Whether `hash`, read big-endian, is at most this target
+    pub fn is_met_by(&self, hash: &Hash256) -> bool {
+        Self::from_be_bytes(hash.as_bytes()) <= *self
+    }
+
+    /// Expected hashes to meet the target, 2^256 / target, saturating. A legacy
+    /// difficulty's work is exactly two to its zero bits, as it always was.
+    pub fn work(&self) -> u128 {
+        if *self == Self::ZERO {
+            return u128::MAX;
+        }
+        let (mut quotient, remainder) = Self::MAX.div_rem(self);
+        // 2^256 = MAX + 1, which divides evenly when the remainder is one short
+        if remainder.add_one() == *self {
+            quotient = quotient.add_one();
+        }
+        if quotient.0[2] != 0 || quotient.0[3] != 0 {
+            return u128::MAX;
+        }
+        (quotient.0[1] as u128) << 64 | quotient.0[0] as u128
+    }
+
+    /// `self * numerator / denominator`, saturating at `MAX`
+    pub fn mul_div(&self, numerator: u64, denominator: u64) -> Self {
+        let mut wide = [0u64; 5];
+        let mut carry = 0u128;
+        for (i, &limb) in self.0.iter().enumerate() {
+            let product = limb as u128 * numerator as u128 + carry;
+            wide[i] = product as u64;
+            carry = product >> 64;
+        }
+        wide[4] = carry as u64;
+
+        let denominator = denominator.max(1) as u128;
+        let mut remainder = 0u128;
+        for limb in wide.iter_mut().rev() {
+            let value = remainder << 64 | *limb as u128;
+            *limb = (value / denominator) as u64;
+            remainder = value % denominator;
+        }
+        if wide[4] != 0 {
+            return Self::MAX;
+        }
+        Self([wide[0], wide[1], wide[2], wide[3]])
+    }
+
+    /// The mean of `targets`, exactly; zero for none
+    pub fn average(targets: &[Self]) -> Self {
+        let mut sum = [0u64; 5];
+        for target in targets {
+            let mut carry = false;
+            for (i, limb) in sum.iter_mut().enumerate() {
+                let (total, overflowed) = limb.overflowing_add(target.0.get(i).copied().unwrap_or(0));
+                let (total, overflowed_again) = total.overflowing_add(carry as u64);
+                *limb = total;
+                carry = overflowed || overflowed_again;
+            }
+        }
+
+        let count = targets.len().max(1) as u128;
+        let mut remainder = 0u128;
+        for limb in sum.iter_mut().rev() {
+            let value = remainder << 64 | *limb as u128;
+            *limb = (value / count) as u64;
+            remainder = value % count;
+        }
+        Self([sum[0], sum[1], sum[2], sum[3]])
+    }
+
+    /// How many times harder than `DIFFICULTY_ONE_BITS` this target is
+    pub fn difficulty(&self) -> f64 {
+        let target = self.to_f64();
+        if target == 0.0 {
+            return f64::INFINITY;
+        }
+        Self::from_bits(DIFFICULTY_ONE_BITS).to_f64() / target
+    }
+
+    fn to_f64(self) -> f64 {
+        self.0.iter().rev().fold(0.0, |value, &limb| value * 2f64.powi(64) + limb as f64)
+    }
+
+    fn bit_len(&self) -> u32 {
+        match self.0.iter().rposition(|&limb| limb != 0) {
+            Some(i) => 64 * i as u32 + 64 - self.0[i].leading_zeros(),
+            None => 0,
+        }
+    }
+
+    fn shl(&self, bits: u32) -> Self {
+        let mut limbs = [0u64; 4];
+        let (words, bits) = ((bits / 64) as usize, bits % 64);
+        for (i, limb) in limbs.iter_mut().enumerate().skip(words) {
+            *limb = self.0[i - words] << bits;
+            if bits > 0 && i > words {
+                *limb |= self.0[i - words - 1] >> (64 - bits);
+            }
+        }
+        Self(limbs)
+    }
+
+    fn shr(&self, bits: u32) -> Self {
+        let mut limbs = [0u64; 4];
+        let (words, bits) = ((bits / 64) as usize, bits % 64);
+        for (i, limb) in limbs.iter_mut().enumerate().take(4usize.saturating_sub(words)) {
+            *limb = self.0[i + words] >> bits;
+            if bits > 0 && i + words + 1 < 4 {
+                *limb |= self.0[i + words + 1] << (64 - bits);
+            }
+        }
+        Self(limbs)
+    }
+
+    fn add_one(&self) -> Self {
+        let mut limbs = self.0;
+        for limb in limbs.iter_mut() {
+            let (sum, carried) = limb.overflowing_add(1);
+            *limb = sum;
+            if !carried {
+                break;
+            }
+        }
+        Self(limbs)
+    }
+
+    fn sub(&self, other: &Self) -> Self {
+        let mut limbs = [0u64; 4];
+        let mut borrow = false;
+        for (i, limb) in limbs.iter_mut().enumerate() {
+            let (difference, borrowed) = self.0[i].overflowing_sub(other.0[i]);
+            let (difference, borrowed_again) = difference.overflowing_sub(borrow as u64);
+            *limb = difference;
+            borrow = borrowed || borrowed_again;
+        }
+        Self(limbs)
+    }
+
+    /// Schoolbook binary long division; `divisor` must not be zero
+    fn div_rem(&self, divisor: &Self) -> (Self, Self) {
+        let mut quotient = Self::ZERO;
+        let mut remainder = Self::ZERO;
+        for bit in (0..self.bit_len()).rev() {
+            remainder = remainder.shl(1);
+            remainder.0[0] |= (self.0[(bit / 64) as usize] >> (bit % 64)) & 1;
+            if remainder >= *divisor {
+                remainder = remainder.sub(divisor);
+                quotient.0[(bit / 64) as usize] |= 1 << (bit % 64);
+            }
+        }
+        (quotient, remainder)
+    }
+}
+
+impl Ord for Target {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.iter().rev().cmp(other.0.iter().rev())
+    }
+}
+
+impl PartialOrd for Target {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", hex::encode(self.to_be_bytes()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compact_round_trip() {
+        // Bitcoin's genesis bits
+        let target = Target::from_bits(0x1d00_ffff);
+        assert_eq!(target.to_string(), format!("00000000ffff{}", "0".repeat(52)));
+        assert_eq!(target.to_bits(), 0x1d00_ffff);
+        assert_eq!(Target::from_bits(0x0412_3456).to_bits(), 0x0412_3456);
+        assert_eq!(Target::from_bits(0x0312_3456), Target::from_u64(0x12_3456));
+        assert_eq!(Target::from_bits(0x0112_3456), Target::from_u64(0x12));
+        // The sign bit moves a leading digit into the length
+        assert_eq!(Target::from_u64(0x80).to_bits(), 0x0200_8000);
+        assert_eq!(Target::from_bits(0x0492_3456), Target::ZERO);
+        assert_eq!(Target::from_bits(0x2301_0000), Target::ZERO);
+        assert_eq!(Target::from_bits(REGTEST_BITS).to_bits(), REGTEST_BITS);
+    }
+
+    #[test]
+    fn test_legacy_difficulties_keep_their_meaning() {
+        // Difficulty 6 asked for a zero byte and two zero bits
+        let target = Target::from_bits(6);
+        assert_eq!(target, Target::from_zero_bits(10));
+        assert_eq!(target.to_bits(), DIFFICULTY_ONE_BITS);
+        assert_eq!(target.work(), 1 << 10);
+        assert_eq!(Target::from_bits(20).work(), 1 << 40);
+        assert_eq!(Target::from_bits(0), Target::MAX);
+        assert_eq!(Target::MAX.work(), 1);
+
+        let mut hash = [0xffu8; 32];
+        hash[0] = 0;
+        hash[1] = 0x3f;
+        assert!(target.is_met_by(&Hash256::new(hash)));
+        hash[1] = 0x40;
+        assert!(!target.is_met_by(&Hash256::new(hash)));
+    }
+
+    #[test]
+    fn test_retarget_arithmetic() {
+        let target = Target::from_bits(0x1c01_0000);
+        assert_eq!(target.mul_div(3, 2).to_bits(), 0x1c01_8000);
+        assert_eq!(target.mul_div(1, 4).to_bits(), 0x1b40_0000);
+        assert_eq!(Target::MAX.mul_div(2, 1), Target::MAX);
+        assert_eq!(Target::from_bits(0x1c01_0000).work(), 1 << 40);
+        assert!((Target::from_bits(DIFFICULTY_ONE_BITS).mul_div(1, 3).difficulty() - 3.0).abs() < 1e-6);
+
+        // Averages don't round away from targets that agree, however large
+        assert_eq!(Target::average(&[Target::MAX; 3]), Target::MAX);
+        assert_eq!(Target::average(&[target, target.mul_div(3, 1)]), target.mul_div(2, 1));
+    }
+}
@@ -1,7 +1,30 @@
+use crate::consensus::monetary::is_coinbase_mature;
+use crate::consensus::target::Target;
+use crate::consensus::ChainParams;
 use crate::core::{Block, Transaction, Blockchain};
+use crate::core::transaction::{split_p2pkh_script, split_pqc_script, OutPoint};
+use crate::core::utxo::UtxoEntry;
+use crate::crypto::keys::PublicKey;
+use crate::crypto::pqc::{pqc_address, PqcKeyPair, PqcSignature};
 use crate::crypto::hash::Hashable;
+use crate::wallet::multisig::{split_multisig_script, verify_multisig_input};
 use crate::{QtcError, Result};
-use std::collections::HashSet;
+use rayon::prelude::*;
+use std::collections::{HashMap, HashSet};
+
+/// Heaviest block accepted, with PQC signature bytes weighed as in `Block::weight`
+pub const MAX_BLOCK_WEIGHT: usize = 1024 * 1024;
+
+/// Smallest value a spendable output may carry
+pub const DUST_THRESHOLD: u64 = 546;
+
+/// An input whose signature is left for the parallel stage of block validation
+#[derive(Debug, Clone)]
+struct ScriptCheck {
+    tx_index: usize,
+    input_index: usize,
+    script_pubkey: Vec<u8>, // of the output it spends
+}
 
 #[derive(Debug, Clone)]
 pub struct BlockValidator {
@@ -14,7 +37,7 @@ pub struct BlockValidator {
 impl BlockValidator {
     pub fn new() -> Self {
         Self {
-            max_block_size: 1024 * 1024,    // 1MB
+            max_block_size: MAX_BLOCK_WEIGHT,
             max_transaction_size: 100_000,   // 100KB
             min_transaction_fee: 1000,       // 0.00001 QTC
             max_coinbase_value: 2710000000,  // 27.1 QTC
@@ -46,16 +69,16 @@ impl BlockValidator {
         self.validate_block_header(block, blockchain)?;
         
         // Transaction validation
-        self.validate_block_transactions(block, blockchain)?;
+        let total_fees = self.validate_block_transactions(block, blockchain)?;
         
         // Merkle root validation
         self.validate_merkle_root(block)?;
         
         // Coinbase validation
-        self.validate_coinbase_transaction(block, blockchain)?;
+        self.validate_coinbase_transaction(block, blockchain, total_fees)?;
         
         // Block size validation
-        self.validate_block_size(block)?;
+        self.validate_block_size(block, blockchain.chain_params().pqc_witness_percent)?;
         
         log::debug!("✅ Block {} validation successful", block.header.height);
         Ok(())
@@ -101,6 +124,8 @@ impl BlockValidator {
             return Err(QtcError::Consensus("Invalid previous block hash".to_string()));
         }
         
+        blockchain.chain_params().check_checkpoint(header.height, &block.hash())?;
+        
         // Timestamp validation
         let now = chrono::Utc::now().timestamp() as u64;
         let max_future_time = 2 * 60 * 60; // 2 hours
@@ -113,8 +138,8 @@ impl BlockValidator {
         if header.height > 11 {
             let mut recent_timestamps = Vec::new();
             for i in (header.height.saturating_sub(11))..header.height {
-                if let Ok(Some(prev_block)) = blockchain.get_block_by_height(i) {
-                    recent_timestamps.push(prev_block.header.timestamp);
+                if let Ok(Some(prev_header)) = blockchain.get_block_header_by_height(i) {
+                    recent_timestamps.push(prev_header.timestamp);
                 }
             }
             
@@ -128,23 +153,30 @@ impl BlockValidator {
             }
         }
         
-        // Difficulty validation
-        let expected_difficulty = blockchain.calculate_next_difficulty(header.height)?;
-        if header.difficulty != expected_difficulty {
+        // Target validation; off mainnet a block long after its parent may use the limit.
+        // Targets are compared, since blocks from before compact bits encode theirs differently
+        let expected_bits = blockchain.required_bits(header.height)?;
+        let bootstrap_bits = blockchain.get_block_header_by_height(header.height - 1)?
+            .and_then(|parent| blockchain.chain_params().bootstrap_bits(parent.timestamp, header.timestamp));
+        let target = Target::from_bits(header.bits);
+        if target != Target::from_bits(expected_bits) && bootstrap_bits.map(Target::from_bits) != Some(target) {
             return Err(QtcError::Consensus(format!(
-                "Invalid block difficulty: expected {}, got {}",
-                expected_difficulty, header.difficulty
+                "Invalid block bits: expected {:#010x}, got {:#010x}",
+                expected_bits, header.bits
             )));
         }
         
         Ok(())
     }
     
-    /// Validate all transactions in the block
-    fn validate_block_transactions(&self, block: &Block, blockchain: &Blockchain) -> Result<()> {
+    /// Validate all transactions in the block; returns the fees they pay
+    fn validate_block_transactions(&self, block: &Block, blockchain: &Blockchain) -> Result<u64> {
+        let check_scripts = !blockchain.skips_script_checks(block.header.height);
         let mut seen_txids = HashSet::new();
         let mut total_fees = 0u64;
         let mut spent_outpoints = HashSet::new(); // DOUBLE SPENDING PREVENTION
+        let mut created = HashMap::new(); // outputs of earlier transactions, spendable by later ones
+        let mut script_checks = Vec::new();
         
         // Skip coinbase transaction (index 0) for most validations
         for (i, tx) in block.transactions.iter().enumerate() {
@@ -174,34 +206,87 @@ impl BlockValidator {
             if i == 0 {
                 // Coinbase transaction - different validation
                 self.validate_coinbase_structure(&tx)?;
+                self.check_transaction_size(tx)?;
             } else {
                 // Regular transaction
-                self.validate_transaction(tx, blockchain)?;
-                total_fees += tx.fee();
-            }
-            
-            // Transaction size limit
-            if tx.size() > self.max_transaction_size {
-                return Err(QtcError::Consensus(format!(
-                    "Transaction size {} exceeds maximum {}",
-                    tx.size(), self.max_transaction_size
-                )));
+                let spent = spent_outputs(tx, blockchain, &created)?;
+                total_fees += self.check_block_transaction(tx, block, &spent)?;
+                if check_scripts {
+                    script_checks.extend(spent.into_iter().enumerate().map(|(input_index, utxo)| {
+                        ScriptCheck { tx_index: i, input_index, script_pubkey: utxo.script_pubkey }
+                    }));
+                }
+                
+                // Connecting applies the block in order, so later transactions may spend these
+                for (vout, output) in tx.outputs.iter().enumerate().filter(|(_, output)| !output.is_unspendable()) {
+                    created.insert(
+                        OutPoint::new(txid, vout as u32),
+                        UtxoEntry::from_output(txid, vout as u32, output, block.header.height, false),
+                    );
+                }
             }
         }
         
-        // Validate total fees don't exceed coinbase output value
-        let coinbase_value = block.transactions[0].total_output_value();
-        let expected_reward = crate::consensus::monetary::MonetaryPolicy::new().coinbase_reward(block.header.height);
-        
-        if coinbase_value > expected_reward + total_fees {
-            return Err(QtcError::Consensus("Coinbase value exceeds allowed amount".to_string()));
-        }
-        
-        Ok(())
+        verify_scripts(&block.transactions, &script_checks)?;
+        Ok(total_fees)
     }
     
     /// Validate a single transaction
     pub fn validate_transaction(&self, tx: &Transaction, blockchain: &Blockchain) -> Result<bool> {
+        self.check_transaction(tx, blockchain, true)
+    }
+    
+    /// Checks on a transaction bound for the mempool that the pool itself doesn't
+    /// make: its size and signatures. `spent_scripts` are the scripts of the
+    /// outputs its inputs spend, which may belong to other pooled transactions.
+    pub fn validate_mempool_transaction(&self, tx: &Transaction, spent_scripts: &[Vec<u8>]) -> Result<()> {
+        self.check_transaction_size(tx)?;
+        for (index, script_pubkey) in spent_scripts.iter().enumerate() {
+            verify_input_script(tx, index, script_pubkey)?;
+        }
+        Ok(())
+    }
+    
+    /// Validate a transaction, checking its signatures only if `check_scripts`
+    fn check_transaction(&self, tx: &Transaction, blockchain: &Blockchain, check_scripts: bool) -> Result<bool> {
+        let spent = spent_outputs(tx, blockchain, &HashMap::new())?;
+        self.check_spend(tx, &spent, blockchain.height)?;
+        if check_scripts {
+            for (index, utxo) in spent.iter().enumerate() {
+                verify_input_script(tx, index, &utxo.script_pubkey)?;
+            }
+        }
+        Ok(true)
+    }
+    
+    /// Everything about a transaction after the coinbase in `block` but its
+    /// signatures: finality, size and `check_spend`. `spent` are the outputs
+    /// its inputs spend, in input order; returns the fee it pays.
+    pub(crate) fn check_block_transaction(&self, tx: &Transaction, block: &Block, spent: &[UtxoEntry]) -> Result<u64> {
+        let height = block.header.height;
+        if !tx.is_final(height, block.header.timestamp) {
+            return Err(QtcError::Consensus(format!(
+                "Transaction {} is not final at height {}", tx.hash(), height
+            )));
+        }
+        self.check_transaction_size(tx)?;
+        self.check_spend(tx, spent, height.saturating_sub(1))
+    }
+    
+    fn check_transaction_size(&self, tx: &Transaction) -> Result<()> {
+        if tx.size() > self.max_transaction_size {
+            return Err(QtcError::Transaction(format!(
+                "Transaction size {} exceeds maximum {}", tx.size(), self.max_transaction_size
+            )));
+        }
+        Ok(())
+    }
+    
+    /// Everything about a spend but its finality, size and signatures: its
+    /// inputs, given `spent`, the outputs they spend in input order; coinbase
+    /// maturity in the block after `tip_height`; and `check_amounts`.
+    /// Returns the fee it pays.
+    fn check_spend(&self, tx: &Transaction, spent: &[UtxoEntry], tip_height: u64) -> Result<u64> {
         // Basic structure validation
         if tx.inputs.is_empty() {
             return Err(QtcError::Transaction("Transaction has no inputs".to_string()));
@@ -226,78 +311,19 @@ impl BlockValidator {
             seen_outpoints.insert(outpoint.clone());
         }
         
-        // Validate inputs exist and are unspent
         let mut total_input_value = 0u64;
-        for input in &tx.inputs {
-            // Check if UTXO exists
-            let utxo_set = blockchain.utxo_set.read().unwrap();
-            
-            match utxo_set.get_utxo(&input.previous_output)? {
-                Some(utxo) => {
-                    total_input_value = total_input_value.saturating_add(utxo.value);
-                    
-                    // Validate coinbase maturity
-                    if utxo.is_coinbase {
-                        let current_height = blockchain.height;
-                        let coinbase_maturity = 100; // 100 block maturity for coinbase
-                        
-                        if current_height < utxo.height + coinbase_maturity {
-                            return Err(QtcError::Transaction(
-                                "Coinbase UTXO not yet mature".to_string()
-                            ));
-                        }
-                    }
-                    
-                    // TODO: Validate signature against UTXO script
-                    // This would require implementing script validation
-                }
-                None => {
-                    return Err(QtcError::Transaction(format!(
-                        "Referenced UTXO not found: {}:{}",
-                        hex::encode(input.previous_output.txid.as_bytes()),
-                        input.previous_output.vout
-                    )));
-                }
-            }
-        }
-        
-        // Validate outputs
-        let total_output_value = tx.total_output_value();
-        
-        // Check for negative or zero outputs
-        for output in &tx.outputs {
-            if output.value == 0 {
-                return Err(QtcError::Transaction("Transaction output value is zero".to_string()));
-            }
+        for utxo in spent {
+            total_input_value = total_input_value.saturating_add(utxo.value);
             
-            // Check for dust outputs (very small values)
-            let dust_threshold = 546; // satoshis
-            if output.value < dust_threshold {
-                return Err(QtcError::Transaction("Transaction output below dust threshold".to_string()));
+            // Validate coinbase maturity
+            if utxo.is_coinbase && !is_coinbase_mature(utxo.height, tip_height) {
+                return Err(QtcError::Transaction(
+                    "Coinbase UTXO not yet mature".to_string()
+                ));
             }
         }
         
-        // Validate input value >= output value (with fee)
-        if total_input_value < total_output_value {
-            return Err(QtcError::Transaction("Total input value less than total output value".to_string()));
-        }
-        
-        // Validate minimum fee
-        let fee = total_input_value - total_output_value;
-        if fee < self.min_transaction_fee {
-            return Err(QtcError::Transaction(format!(
-                "Transaction fee {} below minimum {}",
-                fee, self.min_transaction_fee
-            )));
-        }
-        
-        // Validate fee is reasonable (not excessive)
-        let max_fee = total_output_value; // Fee shouldn't exceed output value
-        if fee > max_fee {
-            return Err(QtcError::Transaction("Transaction fee is excessive".to_string()));
-        }
-        
-        Ok(true)
+        check_amounts(tx, total_input_value, self.min_transaction_fee)
     }
     
     /// Validate coinbase transaction structure
@@ -325,14 +351,14 @@ impl BlockValidator {
         Ok(())
     }
     
-    /// Validate coinbase transaction value and outputs
-    fn validate_coinbase_transaction(&self, block: &Block, _blockchain: &Blockchain) -> Result<()> {
+    /// Validate coinbase transaction value and outputs; `total_fees` is what
+    /// the block's other transactions pay
+    fn validate_coinbase_transaction(&self, block: &Block, blockchain: &Blockchain, total_fees: u64) -> Result<()> {
         let coinbase = &block.transactions[0];
-        let monetary_policy = crate::consensus::monetary::MonetaryPolicy::new();
+        let monetary_policy = blockchain.monetary_policy();
         
         // Calculate expected reward
         let block_reward = monetary_policy.coinbase_reward(block.header.height);
-        let total_fees: u64 = block.transactions.iter().skip(1).map(|tx| tx.fee()).sum();
         let expected_value = block_reward + total_fees;
         
         // Validate coinbase output value
@@ -370,14 +396,14 @@ impl BlockValidator {
         Ok(())
     }
     
-    /// Validate block size
-    fn validate_block_size(&self, block: &Block) -> Result<()> {
-        let block_size = block.size();
+    /// Validate block weight, which is what templates fill up to
+    fn validate_block_size(&self, block: &Block, pqc_witness_percent: u32) -> Result<()> {
+        let block_weight = block.weight(pqc_witness_percent);
         
-        if block_size > self.max_block_size {
+        if block_weight > self.max_block_size {
             return Err(QtcError::Consensus(format!(
-                "Block size {} exceeds maximum {}",
-                block_size, self.max_block_size
+                "Block weight {} exceeds maximum {}",
+                block_weight, self.max_block_size
             )));
         }
         
@@ -386,36 +412,16 @@ impl BlockValidator {
     
     /// Validate proof of work
     pub fn validate_proof_of_work(&self, block: &Block) -> Result<()> {
-        let hash = block.hash();
-        let difficulty = block.header.difficulty;
-        
-        // Check if hash meets difficulty target
-        let required_zeros = difficulty / 4; // 4 bits per hex digit
-        let remaining_bits = difficulty % 4;
-        
-        // Check full zero bytes
-        for i in 0..required_zeros as usize {
-            if i >= 32 || hash.as_bytes()[i] != 0 {
-                return Err(QtcError::Consensus("Block hash does not meet difficulty target".to_string()));
-            }
-        }
-        
-        // Check remaining bits
-        if remaining_bits > 0 {
-            let byte_index = required_zeros as usize;
-            if byte_index < 32 {
-                let mask = 0xFF << (8 - remaining_bits);
-                if hash.as_bytes()[byte_index] & mask != 0 {
-                    return Err(QtcError::Consensus("Block hash does not meet difficulty target".to_string()));
-                }
-            }
+        if !Target::from_bits(block.header.bits).is_met_by(&block.hash()) {
+            return Err(QtcError::Consensus("Block hash does not meet its target".to_string()));
         }
         
         Ok(())
     }
     
-    /// Validate chain of blocks (for initial sync or verification)
-    pub fn validate_chain(&self, blocks: &[Block], start_height: u64) -> Result<()> {
+    /// Validate chain of blocks (for initial sync or verification), refusing
+    /// any that contradicts one of `params`' checkpoints
+    pub fn validate_chain(&self, blocks: &[Block], start_height: u64, params: &ChainParams) -> Result<()> {
         log::info!("Validating chain of {} blocks starting at height {}", blocks.len(), start_height);
         
         for (i, block) in blocks.iter().enumerate() {
@@ -431,6 +437,8 @@ impl BlockValidator {
             // Validate proof of work
             self.validate_proof_of_work(block)?;
             
+            params.check_checkpoint(block.header.height, &block.hash())?;
+            
             // Validate block structure
             self.validate_block_structure(block)?;
             
@@ -452,20 +460,7 @@ impl BlockValidator {
     
     /// Check if a transaction is final (can be included in a block)
     pub fn is_transaction_final(&self, tx: &Transaction, height: u64, time: u64) -> bool {
-        // BIP68: Relative lock-time using consensus-enforced sequence numbers
-        
-        // Transaction is final if lock_time is 0
-        if tx.lock_time == 0 {
-            return true;
-        }
-        
-        // If lock_time < 500000000, it's a block height
-        if tx.lock_time < 500_000_000 {
-            return tx.lock_time <= height;
-        } else {
-            // Otherwise it's a timestamp
-            return tx.lock_time <= time;
-        }
+        tx.is_final(height, time)
     }
     
     /// Get validation configuration
@@ -507,9 +502,116 @@ impl Default for BlockValidator {
     }
 }
 
+/// The outputs `tx`'s inputs spend, in input order: from `created`, the outputs
+/// of transactions before it in its block, or else from the UTXO set
+fn spent_outputs(tx: &Transaction, blockchain: &Blockchain, created: &HashMap<OutPoint, UtxoEntry>) -> Result<Vec<UtxoEntry>> {
+    let utxo_set = blockchain.utxo_set.read().unwrap();
+    let mut spent = Vec::with_capacity(tx.inputs.len());
+    for input in &tx.inputs {
+        let utxo = match created.get(&input.previous_output) {
+            Some(utxo) => Some(utxo.clone()),
+            None => utxo_set.get_utxo(&input.previous_output)?,
+        };
+        let utxo = utxo.ok_or_else(|| QtcError::Transaction(format!(
+            "Referenced UTXO not found: {}:{}",
+            hex::encode(input.previous_output.txid.as_bytes()),
+            input.previous_output.vout
+        )))?;
+        spent.push(utxo);
+    }
+    Ok(spent)
+}
+
+/// The amount rules a spend must meet to be mined, which the mempool applies
+/// too: no zero or dust outputs but data carriers, no more out than the
+/// `input_value` it spends, at least `min_fee`, and no more fee than the
+/// outputs are worth. Returns the fee.
+pub fn check_amounts(tx: &Transaction, input_value: u64, min_fee: u64) -> Result<u64> {
+    // Check for negative or zero outputs; data carriers may hold nothing
+    for output in tx.outputs.iter().filter(|output| !output.is_unspendable()) {
+        if output.value == 0 {
+            return Err(QtcError::Transaction("Transaction output value is zero".to_string()));
+        }
+        if output.value < DUST_THRESHOLD {
+            return Err(QtcError::Transaction("Transaction output below dust threshold".to_string()));
+        }
+    }
+    
+    // Validate input value >= output value (with fee)
+    let total_output_value = tx.total_output_value();
+    if input_value < total_output_value {
+        return Err(QtcError::InsufficientFunds { required: total_output_value, available: input_value });
+    }
+    
+    // Validate minimum fee
+    let fee = input_value - total_output_value;
+    if fee < min_fee {
+        return Err(QtcError::Transaction(format!("Transaction fee {} below minimum {}", fee, min_fee)));
+    }
+    
+    // Validate fee is reasonable (not excessive)
+    if fee > total_output_value {
+        return Err(QtcError::Transaction("Transaction fee is excessive".to_string()));
+    }
+    
+    Ok(fee)
+}
+
+/// Check one input's signature script against the output it spends. P2PKH and PQC
+/// spends must be signed by the output's owner, multisig spends by enough of its
+/// keys; a script in no known form is rejected.
+pub(crate) fn verify_input_script(tx: &Transaction, index: usize, script_pubkey: &[u8]) -> Result<()> {
+    let signature_script = &tx.inputs[index].signature_script;
+    if let Some((_, _, key_bytes)) = split_p2pkh_script(signature_script) {
+        let public_key = PublicKey::from_bytes(key_bytes)?;
+        if Transaction::address_to_script_pubkey(&public_key.to_address()) != script_pubkey {
+            return Err(QtcError::Transaction(format!(
+                "Input {} is signed by a key that doesn't own it", index
+            )));
+        }
+        if !tx.verify_signature(index, &public_key)? {
+            return Err(QtcError::Transaction(format!("Input {} has an invalid signature", index)));
+        }
+    } else if let Some((signed, signing_key, encryption_key)) = split_pqc_script(signature_script) {
+        if Transaction::address_to_script_pubkey(&pqc_address(signing_key, encryption_key)) != script_pubkey {
+            return Err(QtcError::Transaction(format!(
+                "Input {} is signed by a key that doesn't own it", index
+            )));
+        }
+        let signature = PqcSignature { signature: signed.to_vec(), public_key: signing_key.to_vec() };
+        if !PqcKeyPair::verify(&tx.get_signature_hash(index), &signature, signing_key).unwrap_or(false) {
+            return Err(QtcError::Transaction(format!("Input {} has an invalid signature", index)));
+        }
+    } else if split_multisig_script(signature_script).is_some() {
+        verify_multisig_input(tx, index, script_pubkey)?;
+    } else {
+        return Err(QtcError::Transaction(format!("Input {} has no recognised signature script", index)));
+    }
+    Ok(())
+}
+
+/// Verify the inputs of a block on rayon's thread pool. Whichever thread
+/// finds a failure first, the one reported is the earliest in the block.
+fn verify_scripts(transactions: &[Transaction], checks: &[ScriptCheck]) -> Result<()> {
+    let failure = checks.par_iter().find_map_first(|check| {
+        let tx = &transactions[check.tx_index];
+        verify_input_script(tx, check.input_index, &check.script_pubkey).err().map(|e| (check, e))
+    });
+    match failure {
+        Some((check, e)) => Err(QtcError::Consensus(format!(
+            "Transaction {} (#{} in block), input {}: {}",
+            transactions[check.tx_index].hash(), check.tx_index, check.input_index, e
+        ))),
+        None => Ok(()),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::core::transaction::{pqc_signature_script, sign_p2pkh_input, sign_pqc_input, OutPoint};
+    use crate::crypto::hash::Hash256;
+    use crate::crypto::keys::PrivateKey;
     use crate::storage::Database;
     use tempfile::TempDir;
     
@@ -565,8 +667,71 @@ mod tests {
         
         let block = Block::new(Hash256::zero(), vec![coinbase], 4, 0);
         
-        validator.validate_block_size(&block)?;
+        validator.validate_block_size(&block, 100)?;
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_parallel_script_checks_report_the_earliest_failure() -> Result<()> {
+        let key = PrivateKey::new()?;
+        let owned = Transaction::address_to_script_pubkey(&key.public_key()?.to_address());
+        let mut transactions = vec![Transaction::new_coinbase("qtc1test".to_string(), 50, "test".to_string())];
+        let mut checks = Vec::new();
+        for tx_index in 1..40 {
+            let mut tx = Transaction::new();
+            for vout in 0..3 {
+                tx.add_input(OutPoint::new(Hash256::hash(&[tx_index as u8]), vout), Vec::new());
+            }
+            tx.add_output(10_000, "qtc1test");
+            for input_index in 0..3 {
+                sign_p2pkh_input(&mut tx, input_index, &key)?;
+                checks.push(ScriptCheck { tx_index, input_index, script_pubkey: owned.clone() });
+            }
+            transactions.push(tx);
+        }
+        verify_scripts(&transactions, &checks)?;
         
+        // Break inputs in two transactions; the earlier one is always named
+        transactions[7].inputs[1].signature_script = transactions[8].inputs[1].signature_script.clone();
+        checks[30].script_pubkey = Transaction::address_to_script_pubkey("qtc1someoneelse");
+        for _ in 0..10 {
+            let err = verify_scripts(&transactions, &checks).unwrap_err().to_string();
+            assert!(err.contains(&format!("{} (#7 in block), input 1", transactions[7].hash())), "{}", err);
+        }
+        verify_scripts(&transactions, &checks[..18])?;
+        Ok(())
+    }
+    
+    #[test]
+    fn test_scripts_fail_closed() -> Result<()> {
+        let owner = PqcKeyPair::new()?;
+        let owned = Transaction::address_to_script_pubkey(&owner.address().address);
+        let mut tx = Transaction::new();
+        tx.add_input(OutPoint::new(Hash256::hash(b"pqc funding"), 0), Vec::new());
+        tx.add_output(10_000, "qtc1test");
+        
+        // No script, or one in no known form, spends nothing
+        assert!(verify_input_script(&tx, 0, &owned).is_err());
+        tx.inputs[0].signature_script = vec![1];
+        assert!(verify_input_script(&tx, 0, &owned).is_err());
+        
+        sign_pqc_input(&mut tx, 0, &owner)?;
+        verify_input_script(&tx, 0, &owned)?;
+        
+        // Someone else's key, even with the owner's encryption key attached
+        let thief = PqcKeyPair::new()?;
+        let mut stolen = tx.clone();
+        sign_pqc_input(&mut stolen, 0, &thief)?;
+        assert!(verify_input_script(&stolen, 0, &owned).is_err());
+        let signature = thief.sign(&stolen.get_signature_hash(0))?;
+        stolen.inputs[0].signature_script = pqc_signature_script(&signature, &owner.address().encryption_public_key);
+        assert!(verify_input_script(&stolen, 0, &owned).is_err());
+        
+        // The owner's keys over a signature for another transaction
+        let mut replayed = tx.clone();
+        replayed.outputs[0].value = 9_000;
+        assert!(verify_input_script(&replayed, 0, &owned).is_err());
         Ok(())
     }
 }
@@ -1,4 +1,5 @@
 use crate::core::Transaction;
+use crate::core::transaction::scaled_witness;
 use crate::crypto::hash::{Hash256, Hashable};
 use serde::{Deserialize, Serialize};
 use chrono::Utc;
@@ -14,13 +15,13 @@ pub struct BlockHeader {
     pub previous_hash: Hash256,
     pub merkle_root: Hash256,
     pub timestamp: u64,
-    pub difficulty: u32,
+    pub bits: u32, // compact target, see `consensus::target`
     pub nonce: u64,
     pub height: u64,
 }
 
 impl Block {
-    pub fn new(previous_hash: Hash256, transactions: Vec<Transaction>, difficulty: u32, height: u64) -> Self {
+    pub fn new(previous_hash: Hash256, transactions: Vec<Transaction>, bits: u32, height: u64) -> Self {
         let merkle_root = Self::calculate_merkle_root(&transactions);
         let timestamp = Utc::now().timestamp() as u64;
         
@@ -29,7 +30,7 @@ impl Block {
                 previous_hash,
                 merkle_root,
                 timestamp,
-                difficulty,
+                bits,
                 nonce: 0,
                 height,
             },
@@ -93,6 +94,13 @@ impl Block {
         bincode::serialize(self).map(|data| data.len()).unwrap_or(0)
     }
     
+    /// Size with PQC signature scripts weighed as in `Transaction::weight`
+    pub fn weight(&self, pqc_witness_percent: u32) -> usize {
+        self.transactions.iter()
+            .map(|tx| tx.pqc_witness_size())
+            .fold(self.size(), |weight, witness| weight - witness + scaled_witness(witness, pqc_witness_percent))
+    }
+    
     pub fn transaction_count(&self) -> usize {
         self.transactions.len()
     }
@@ -114,7 +122,7 @@ impl Hashable for BlockHeader {
         data.extend_from_slice(self.previous_hash.as_bytes());
         data.extend_from_slice(self.merkle_root.as_bytes());
         data.extend_from_slice(&self.timestamp.to_le_bytes());
-        data.extend_from_slice(&self.difficulty.to_le_bytes());
+        data.extend_from_slice(&self.bits.to_le_bytes());
         data.extend_from_slice(&self.nonce.to_le_bytes());
         data.extend_from_slice(&self.height.to_le_bytes());
         
@@ -140,7 +148,7 @@ mod tests {
         let block = Block::new(Hash256::zero(), transactions, 4, 0);
         
         assert_eq!(block.header.height, 0);
-        assert_eq!(block.header.difficulty, 4);
+        assert_eq!(block.header.bits, 4);
         assert_eq!(block.header.previous_hash, Hash256::zero());
         assert_eq!(block.transactions.len(), 1);
     }
@@ -1,32 +1,96 @@
-use crate::core::{Block, Transaction};
-use crate::core::utxo::UtxoSet;
+use crate::api::metrics::metrics;
+use crate::core::{Block, BlockFilter, BlockHeader, FeeEstimate, FeeEstimator, GenesisParams, Transaction};
+use crate::core::fee_estimator::{self, FeeBasis};
+use crate::core::mempool::{Mempool, MempoolDump, ProjectedBlock};
+use crate::core::scan::{self, ScanDescriptor, ScanResult};
+use crate::core::snapshot::ChainSnapshot;
+use crate::core::utxo::{UtxoEntry, UtxoSet};
+use crate::core::transaction::OutPoint;
 use crate::storage::Database;
+use crate::storage::database::{BlockWorkEntry, SpentOutput, StaleBlockInfo};
 use crate::consensus::validation::BlockValidator;
 use crate::consensus::monetary::MonetaryPolicy;
+use crate::consensus::params::ChainParams;
+use crate::consensus::target::Target;
 use crate::crypto::hash::{Hash256, Hashable};
 use crate::{QtcError, Result};
 use serde::{Deserialize, Serialize};
 // use chrono::{DateTime, Utc};
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::{Arc, RwLock};
+use std::time::Instant;
+use tokio::sync::{broadcast, watch};
+
+/// Room left in block templates for the header and coinbase
+const COINBASE_RESERVE_SIZE: usize = 1_000;
+
+/// Chain events buffered per subscriber before it starts lagging
+const CHAIN_EVENT_CAPACITY: usize = 1_024;
+
+/// Recent blocks a pruned node always keeps, so it can still follow short reorgs
+pub const MIN_BLOCKS_TO_KEEP: u64 = 288;
+
+/// Smallest `storage.prune_target_mb` accepted; block files are rolled over at 128 MB
+pub const MIN_PRUNE_TARGET_MB: u64 = 550;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BlockchainStats {
     pub height: u64,
-    pub difficulty: u32,
+    pub difficulty: f64,
     pub total_supply: u64,
     pub total_addresses: usize,
     pub avg_block_time: u64,
     pub network_hashrate: f64,
 }
 
+/// Whether old block bodies are being deleted, and how far that has got
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PruneStatus {
+    pub enabled: bool,
+    pub target_mb: Option<u64>,
+    pub pruned_height: Option<u64>, // highest block whose body is gone
+    pub block_files_size: u64,
+}
+
+/// Result of looking up a single transaction output
+#[derive(Debug, Clone)]
+pub enum TxOutStatus {
+    Unspent(UtxoEntry),
+    Spent(SpentOutput),
+    Unknown,
+}
+
 #[derive(Debug, Clone)]
 pub struct Blockchain {
     pub tip: Hash256,
     pub height: u64,
+    pub total_work: u128,
     db: Arc<Database>,
     pub utxo_set: Arc<RwLock<UtxoSet>>,
+    pub mempool: Arc<RwLock<Mempool>>,
+    fee_estimator: Arc<RwLock<FeeEstimator>>,
     validator: BlockValidator,
+    params: ChainParams,
     monetary_policy: MonetaryPolicy,
+    genesis_supply: u64, // premined in the genesis block
+    txindex: bool,
+    blockfilterindex: bool,
+    minimum_chain_work: u128,
+    prune_target: Option<u64>, // bytes of block files to keep under, when pruning
+    template_updates: Arc<watch::Sender<u64>>, // bumped whenever block template inputs change
+    chain_version: Arc<AtomicU64>, // bumped before the tip or UTXO set change, invalidating snapshots
+    events: broadcast::Sender<ChainEvent>,
+}
+
+/// Tip and mempool changes, in the order they were made
+#[derive(Debug, Clone)]
+pub enum ChainEvent {
+    /// `spent` holds the outputs the block's inputs consumed, in input order
+    BlockConnected { block: Arc<Block>, spent: Arc<Vec<(OutPoint, UtxoEntry)>> },
+    BlockDisconnected { block: Arc<Block>, spent: Arc<Vec<(OutPoint, UtxoEntry)>> },
+    TransactionAdded(Arc<Transaction>),
+    TransactionRemoved(Hash256),
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -34,12 +98,24 @@ pub struct ChainState {
     pub tip: Hash256,
     pub height: u64,
     pub total_work: u128,
-    pub difficulty: u32,
+    pub bits: u32, // the next block's, in compact form or, from older databases, a zero-bit difficulty
     pub total_supply: u64,
 }
 
+impl ChainState {
+    /// The next block's difficulty, for display
+    pub fn difficulty(&self) -> f64 {
+        Target::from_bits(self.bits).difficulty()
+    }
+}
+
 impl Blockchain {
     pub fn new(db: Arc<Database>) -> Result<Self> {
+        Self::with_genesis(db, &GenesisParams::default())
+    }
+    
+    /// Load the chain in `db`, or start one from `genesis` if there is none yet
+    pub fn with_genesis(db: Arc<Database>, genesis: &GenesisParams) -> Result<Self> {
         let utxo_set = Arc::new(RwLock::new(UtxoSet::new(db.clone())));
         let validator = BlockValidator::new();
         let monetary_policy = MonetaryPolicy::new();
@@ -47,42 +123,72 @@ impl Blockchain {
         // Try to load existing blockchain
         if let Ok(state) = db.get_chain_state() {
             if let Some(chain_state) = state {
-                Ok(Self {
+                let fee_estimator = Self::load_fee_estimator(&db);
+                let genesis_supply = db.get_genesis_params()?.map_or(0, |params| params.premine_total());
+                let mut chain = Self {
                     tip: chain_state.tip,
                     height: chain_state.height,
+                    total_work: chain_state.total_work,
                     db,
                     utxo_set,
+                    mempool: Arc::new(RwLock::new(Mempool::new())),
+                    fee_estimator,
                     validator,
+                    params: ChainParams::mainnet(),
                     monetary_policy,
-                })
+                    genesis_supply,
+                    txindex: false,
+                    blockfilterindex: false,
+                    minimum_chain_work: 0,
+                    prune_target: None,
+                    template_updates: Arc::new(watch::channel(0).0),
+                    chain_version: Arc::new(AtomicU64::new(0)),
+                    events: broadcast::channel(CHAIN_EVENT_CAPACITY).0,
+                };
+                
+                // Older databases never recorded chain work
+                if chain.total_work == 0 {
+                    chain.backfill_total_work(chain_state)?;
+                }
+                chain.recover_utxo_set()?;
+                Ok(chain)
             } else {
                 // No existing state, create genesis
-                Self::create_new_blockchain(db, utxo_set, validator, monetary_policy)
+                Self::create_new_blockchain(db, genesis, utxo_set, validator, monetary_policy)
             }
         } else {
             // Create genesis block
-            Self::create_new_blockchain(db, utxo_set, validator, monetary_policy)
+            Self::create_new_blockchain(db, genesis, utxo_set, validator, monetary_policy)
         }
     }
     
     fn create_new_blockchain(
         db: Arc<Database>,
+        params: &GenesisParams,
         utxo_set: Arc<RwLock<UtxoSet>>,
         validator: BlockValidator,
         monetary_policy: MonetaryPolicy,
     ) -> Result<Self> {
-        // Create genesis block
-        let genesis = Self::create_genesis_block();
+        let genesis = params.block();
         let genesis_hash = genesis.hash();
+        let genesis_work = Self::block_work(genesis.header.bits);
+        let genesis_supply = params.premine_total();
         
         // Save genesis block
         db.save_block(&genesis)?;
+        db.save_genesis_params(params)?;
+        db.save_block_work(&BlockWorkEntry {
+            hash: genesis_hash,
+            previous_hash: Hash256::zero(),
+            height: 0,
+            chain_work: genesis_work,
+        })?;
         db.save_chain_state(&ChainState {
             tip: genesis_hash,
             height: 0,
-            total_work: 0,
-            difficulty: 6, // Very easy initial difficulty for testing
-            total_supply: 0, // Genesis block has no reward
+            total_work: genesis_work,
+            bits: genesis.header.bits,
+            total_supply: genesis_supply, // no mining reward, only the premine
         })?;
         
         // Initialize UTXO set with genesis coinbase
@@ -93,31 +199,157 @@ impl Blockchain {
         Ok(Self {
             tip: genesis_hash,
             height: 0,
+            total_work: genesis_work,
             db,
             utxo_set,
+            mempool: Arc::new(RwLock::new(Mempool::new())),
+            fee_estimator: Arc::new(RwLock::new(FeeEstimator::new())),
             validator,
+            params: ChainParams::mainnet(),
             monetary_policy,
+            genesis_supply,
+            txindex: false,
+            blockfilterindex: false,
+            minimum_chain_work: 0,
+            prune_target: None,
+            template_updates: Arc::new(watch::channel(0).0),
+            chain_version: Arc::new(AtomicU64::new(0)),
+            events: broadcast::channel(CHAIN_EVENT_CAPACITY).0,
         })
     }
-
-    pub fn create_genesis_block() -> Block {
-        let genesis_message = "The Times 10/Jul/2025 Chancellor on brink of second bailout for banks - QTC Genesis";
-        // Genesis block has NO REWARD - this is the pre-mine prevention
-        let coinbase_tx = Transaction::new_coinbase(
-            "qtc1qw508d6qejxtdg4y5r3zarvary0c5xw7kxdz6v9".to_string(), // Genesis address
-            0, // NO REWARD for genesis block (0 QTC)
-            genesis_message.to_string(),
-        );
-        
-        Block::new(
-            Hash256::zero(), // Previous hash (genesis)
-            vec![coinbase_tx],
-            6, // Very easy initial difficulty for testing
-            0, // Height
-        )
+    
+    pub fn genesis_hash(&self) -> Result<Hash256> {
+        self.db.get_block_hash_by_height(0)?
+            .ok_or_else(|| QtcError::Blockchain("Genesis block not found".to_string()))
     }
     
     pub fn add_block(&mut self, block: Block) -> Result<()> {
+        let block_hash = block.hash();
+        if self.db.is_block_invalid(&block_hash)? || self.db.is_block_invalid(&block.header.previous_hash)? {
+            return Err(QtcError::Blockchain(format!("Block {} is on a branch marked invalid", block_hash)));
+        }
+        
+        // Blocks that build on a known block other than our tip go on a side branch,
+        // which becomes the main chain once it has more work
+        if block.header.previous_hash != self.tip {
+            if self.db.get_block_hash_by_height(block.header.height)? == Some(block_hash) {
+                return Err(QtcError::Blockchain(format!("Block {} already in chain", block_hash)));
+            }
+            
+            if let Some((fork_height, fork_point)) = self.find_fork_point(&block)? {
+                if !self.is_valid_proof_of_work(&block) {
+                    return Err(QtcError::Blockchain("Invalid proof of work".to_string()));
+                }
+                self.params.check_checkpoint(block.header.height, &block_hash)?;
+                if let Some((checkpoint_height, _)) = self.params.last_checkpoint(self.height) {
+                    if fork_height < checkpoint_height {
+                        return Err(QtcError::Consensus(format!(
+                            "Block {} forks at height {}, below the checkpoint at height {}",
+                            block_hash, fork_height, checkpoint_height
+                        )));
+                    }
+                }
+                
+                let chain_work = self.chain_work_of(&block.header.previous_hash)?
+                    .saturating_add(Self::block_work(block.header.bits));
+                self.db.save_stale_block(&block, fork_height, fork_point)?;
+                self.record_block_work(&block, chain_work)?;
+                
+                if let Some(best) = self.db.get_most_work_block()? {
+                    if best.chain_work > self.total_work && best.hash != self.tip {
+                        // A cheap branch could otherwise take over a node still syncing
+                        if best.chain_work >= self.minimum_chain_work {
+                            return self.reorganize_to(&best.hash);
+                        }
+                        log::warn!("🐢 Branch to {} has more work ({}) but is below minimum_chain_work, keeping it stale",
+                            best.hash, best.chain_work);
+                    }
+                }
+                
+                log::info!("🪦 Stored stale block {} at height {} (fork at height {})",
+                    block_hash, block.header.height, fork_height);
+                return Err(QtcError::Blockchain(format!(
+                    "Block {} does not extend the current tip, stored as stale", block_hash
+                )));
+            }
+        }
+        
+        self.connect_block(block)
+    }
+    
+    /// Switch the main chain to the known branch ending at `new_tip`, which must have
+    /// more work: blocks back to the fork point are disconnected (restoring the outputs
+    /// they spent), the branch is connected, and orphaned transactions go back to the
+    /// mempool. If a branch block turns out invalid the old chain is restored and that
+    /// block and those built on it are marked invalid.
+    pub fn reorganize_to(&mut self, new_tip: &Hash256) -> Result<()> {
+        let mut branch = Vec::new();
+        let mut cursor = *new_tip;
+        loop {
+            let block = self.db.get_block(&cursor)?
+                .ok_or_else(|| QtcError::Blockchain(format!("Unknown block {}", cursor)))?;
+            if self.db.get_block_hash_by_height(block.header.height)? == Some(cursor) {
+                break;
+            }
+            if block.header.height == 0 {
+                return Err(QtcError::Blockchain(format!("Block {} does not share our genesis block", new_tip)));
+            }
+            cursor = block.header.previous_hash;
+            branch.push(block);
+        }
+        
+        let Some(first) = branch.last() else {
+            return Err(QtcError::Blockchain(format!("Block {} is already on the main chain", new_tip)));
+        };
+        let fork_height = first.header.height - 1;
+        
+        let new_work = self.chain_work_of(new_tip)?;
+        if new_work <= self.total_work {
+            return Err(QtcError::Blockchain(format!(
+                "Branch ending at {} has less work ({}) than the main chain ({})", new_tip, new_work, self.total_work
+            )));
+        }
+        branch.reverse();
+        
+        log::warn!("🔀 Reorganizing: {} block(s) back to height {}, then {} new block(s) to {}",
+            self.height - fork_height, fork_height, branch.len(), new_tip);
+        
+        let mut disconnected = Vec::new();
+        while self.height > fork_height {
+            disconnected.push(self.disconnect_tip()?);
+        }
+        disconnected.reverse(); // oldest first
+        
+        for (index, block) in branch.iter().enumerate() {
+            if let Err(e) = self.connect_block(block.clone()) {
+                log::error!("❌ Branch block {} is invalid, restoring the previous chain: {}", block.hash(), e);
+                while self.height > fork_height {
+                    self.disconnect_tip()?;
+                }
+                for block in &disconnected {
+                    self.connect_block(block.clone())?;
+                }
+                // Blocks built on it later are refused by add_block instead of retried
+                let invalid = self.stale_branch_from(&branch[index].hash())?;
+                for bad in &invalid {
+                    self.db.save_invalid_block(bad)?;
+                    self.db.remove_block_work(bad)?;
+                }
+                return Err(QtcError::Blockchain(format!("Reorganization to {} failed: {}", new_tip, e)));
+            }
+        }
+        
+        metrics().reorgs.inc();
+        let resurrected = self.resurrect_transactions(&disconnected)?;
+        log::info!("🔀 Reorganized to {} at height {} ({} transaction(s) back in the mempool)",
+            new_tip, self.height, resurrected.len());
+        Ok(())
+    }
+    
+    /// Validate `block` on top of the tip and make it the new tip
+    fn connect_block(&mut self, block: Block) -> Result<()> {
+        let started = Instant::now();
+        
         // Validate block
         self.validator.validate_block(&block, self)?;
         
@@ -127,6 +359,7 @@ impl Blockchain {
         }
         
         let block_hash = block.hash();
+        self.invalidate_snapshots();
         
         // Update UTXO set
         {
@@ -137,29 +370,714 @@ impl Blockchain {
         // Save block
         self.db.save_block(&block)?;
         
+        if self.txindex {
+            for tx in &block.transactions {
+                self.db.save_transaction(tx)?;
+            }
+        }
+        
+        // Filters are rebuilt on startup if one is missing, so a failure here doesn't reject the block
+        if self.blockfilterindex {
+            if let Err(e) = self.index_block_filter(&block) {
+                log::warn!("🔎 Could not build the filter for block {}: {}", block_hash, e);
+            }
+        }
+        
         // Update chain state
         let new_height = self.height + 1;
-        let new_difficulty = self.calculate_next_difficulty(new_height)?;
+        let new_bits = self.bits_after(new_height)?;
         let total_supply = self.calculate_total_supply(new_height);
+        let total_work = self.total_work.saturating_add(Self::block_work(block.header.bits));
         
         let new_state = ChainState {
             tip: block_hash,
             height: new_height,
-            total_work: 0, // TODO: Implement total work calculation
-            difficulty: new_difficulty,
+            total_work,
+            bits: new_bits,
             total_supply,
         };
         
         self.db.save_chain_state(&new_state)?;
+        self.record_block_work(&block, total_work)?;
+        self.db.remove_stale_block(&block_hash)?;
         
         // Update in-memory state
         self.tip = block_hash;
         self.height = new_height;
+        self.total_work = total_work;
+        
+        let removed = {
+            let mut mempool = self.mempool.write().unwrap();
+            self.record_fee_estimates(&block, &mempool);
+            mempool.remove_for_block(&block)
+        };
+        self.notify_template_change();
+        metrics().block_validation_seconds.observe(started.elapsed().as_secs_f64());
+        metrics().blocks_connected.inc();
+        metrics().chain_height.set(new_height as f64);
+        
+        if self.events.receiver_count() > 0 {
+            let spent = self.db.get_block_undo(&block_hash)?.unwrap_or_default();
+            let _ = self.events.send(ChainEvent::BlockConnected { block: Arc::new(block), spent: Arc::new(spent) });
+            for txid in removed {
+                let _ = self.events.send(ChainEvent::TransactionRemoved(txid));
+            }
+        }
         
         log::info!("✅ Block {} added to blockchain", new_height);
+        
+        // The block is already connected, so a failed prune is retried on the next one
+        if let Err(e) = self.prune_block_files() {
+            log::warn!("✂️ Pruning block files failed: {}", e);
+        }
+        Ok(())
+    }
+    
+    /// Roll back the tip block, keeping it around as a stale block
+    pub fn disconnect_tip(&mut self) -> Result<Block> {
+        if self.height == 0 {
+            return Err(QtcError::Blockchain("Cannot disconnect the genesis block".to_string()));
+        }
+        
+        let block = self.db.get_block(&self.tip)?
+            .ok_or_else(|| QtcError::Blockchain(format!("Tip block {} not found", self.tip)))?;
+        self.invalidate_snapshots();
+        
+        // Undo data is gone once the block is disconnected, so grab it for listeners first
+        let spent = match self.events.receiver_count() {
+            0 => None,
+            _ => Some(self.db.get_block_undo(&self.tip)?.unwrap_or_default()),
+        };
+        
+        {
+            let mut utxo_set = self.utxo_set.write().unwrap();
+            utxo_set.disconnect_block(&block)?;
+        }
+        
+        let new_height = self.height - 1;
+        let new_tip = block.header.previous_hash;
+        let total_work = self.total_work.saturating_sub(Self::block_work(block.header.bits));
+        self.db.remove_block_height(self.height)?;
+        self.db.save_stale_block(&block, new_height, new_tip)?;
+        
+        self.db.save_chain_state(&ChainState {
+            tip: new_tip,
+            height: new_height,
+            total_work,
+            bits: self.bits_after(new_height)?,
+            total_supply: self.calculate_total_supply(new_height),
+        })?;
+        
+        self.tip = new_tip;
+        self.height = new_height;
+        self.total_work = total_work;
+        self.notify_template_change();
+        metrics().chain_height.set(new_height as f64);
+        
+        log::info!("↩️ Disconnected block {} at height {}", block.hash(), new_height + 1);
+        if let Some(spent) = spent {
+            let _ = self.events.send(ChainEvent::BlockDisconnected { block: Arc::new(block.clone()), spent: Arc::new(spent) });
+        }
+        Ok(block)
+    }
+    
+    /// Mark `hash` and every block built on it invalid, as an operator would in response
+    /// to a consensus bug: the main chain is rolled back below it if needed and the
+    /// best remaining branch becomes the main chain. `reconsider_block` undoes this.
+    pub fn invalidate_block(&mut self, hash: &Hash256) -> Result<()> {
+        let block = self.db.get_block(hash)?
+            .ok_or_else(|| QtcError::Blockchain(format!("Unknown block {}", hash)))?;
+        if block.header.height == 0 {
+            return Err(QtcError::Blockchain("Cannot invalidate the genesis block".to_string()));
+        }
+        
+        let mut disconnected = Vec::new();
+        if self.db.get_block_hash_by_height(block.header.height)? == Some(*hash) {
+            while self.height >= block.header.height {
+                disconnected.push(self.disconnect_tip()?);
+            }
+            disconnected.reverse(); // oldest first
+        }
+        
+        // Disconnected blocks are stale now, so the whole branch is found there
+        let branch = self.stale_branch_from(hash)?;
+        for bad in &branch {
+            self.db.save_invalid_block(bad)?;
+            self.db.remove_block_work(bad)?;
+        }
+        log::warn!("🚫 Marked block {} and {} block(s) built on it invalid", hash, branch.len() - 1);
+        
+        self.activate_best_chain()?;
+        let resurrected = self.resurrect_transactions(&disconnected)?;
+        log::info!("🚫 Tip now {} at height {} ({} transaction(s) back in the mempool)",
+            self.tip, self.height, resurrected.len());
+        Ok(())
+    }
+    
+    /// Clear invalid marks from `hash`, the blocks built on it and its ancestors, and
+    /// switch to its branch if that now has the most work
+    pub fn reconsider_block(&mut self, hash: &Hash256) -> Result<()> {
+        let block = self.db.get_block(hash)?
+            .ok_or_else(|| QtcError::Blockchain(format!("Unknown block {}", hash)))?;
+        
+        let mut cursor = block.header.previous_hash;
+        while self.db.is_block_invalid(&cursor)? {
+            self.db.remove_invalid_block(&cursor)?;
+            match self.db.get_block_header(&cursor)? {
+                Some(header) => cursor = header.previous_hash,
+                None => break,
+            }
+        }
+        
+        let branch = self.stale_branch_from(hash)?;
+        for block_hash in &branch {
+            self.db.remove_invalid_block(block_hash)?;
+        }
+        // Work is refilled back to the last block that kept it, ancestors included
+        for block_hash in &branch {
+            self.chain_work_of(block_hash)?;
+        }
+        log::info!("♻️ Cleared invalid marks from block {} and {} block(s) built on it", hash, branch.len() - 1);
+        
+        self.activate_best_chain()
+    }
+    
+    /// Reorganize onto the known branch with the most work, if it beats the main chain
+    fn activate_best_chain(&mut self) -> Result<()> {
+        match self.db.get_most_work_block()? {
+            Some(best) if best.chain_work > self.total_work && best.hash != self.tip => self.reorganize_to(&best.hash),
+            _ => Ok(()),
+        }
+    }
+    
+    /// `hash` followed by the stale blocks that build on it, parents before children
+    fn stale_branch_from(&self, hash: &Hash256) -> Result<Vec<Hash256>> {
+        let mut stale = self.db.get_stale_blocks()?;
+        stale.sort_by_key(|info| info.height);
+        
+        let mut branch = vec![*hash];
+        let mut members = HashSet::from([*hash]);
+        for info in stale {
+            if members.contains(&info.previous_hash) && members.insert(info.hash) {
+                branch.push(info.hash);
+            }
+        }
+        Ok(branch)
+    }
+    
+    /// A consistent view of the current tip that can be read without holding the chain lock
+    pub fn snapshot(&self) -> ChainSnapshot {
+        ChainSnapshot::new(
+            self.height,
+            self.tip,
+            self.total_work,
+            self.minimum_chain_work,
+            self.chain_version.clone(),
+            self.db.clone(),
+            self.utxo_set.clone(),
+        )
+    }
+    
+    fn invalidate_snapshots(&self) {
+        self.chain_version.fetch_add(1, Ordering::SeqCst);
+    }
+    
+    /// Bytes of mempool transactions a block template can hold
+    pub fn max_template_size(&self) -> usize {
+        self.validator.get_config().0.saturating_sub(COINBASE_RESERVE_SIZE)
+    }
+    
+    /// The next `count` blocks the current mempool would fill
+    pub fn projected_blocks(&self, count: usize) -> Vec<ProjectedBlock> {
+        self.mempool.read().unwrap().projected_blocks(self.max_template_size(), count)
+    }
+    
+    /// Fee rate to confirm within `blocks`: recent confirmation history, raised if
+    /// the current mempool alone would push a transaction paying it further out
+    pub fn estimate_fee(&self, blocks: u32) -> FeeEstimate {
+        let blocks = fee_estimator::clamp_target(blocks);
+        let history = self.fee_estimator.read().unwrap().estimate(blocks);
+        // A full block at the target means outbidding its cheapest package
+        let projected = self.projected_blocks(blocks as usize)
+            .get(blocks as usize - 1)
+            .filter(|block| block.size >= self.max_template_size() * 9 / 10)
+            .map(|block| block.min_fee_rate + 1);
+        
+        let (fee_rate, basis) = match (history, projected) {
+            (Some(history), Some(projected)) if projected > history => (projected, FeeBasis::Mempool),
+            (Some(history), _) => (history, FeeBasis::History),
+            (None, Some(projected)) => (projected, FeeBasis::Mempool),
+            (None, None) => (1, FeeBasis::Minimum),
+        };
+        FeeEstimate { blocks, fee_rate, basis }
+    }
+    
+    fn load_fee_estimator(db: &Database) -> Arc<RwLock<FeeEstimator>> {
+        let estimator = db.get_fee_estimator().unwrap_or_else(|e| {
+            log::warn!("💸 Discarding unreadable fee estimates: {}", e);
+            None
+        });
+        Arc::new(RwLock::new(estimator.unwrap_or_default()))
+    }
+    
+    fn record_fee_estimates(&self, block: &Block, mempool: &Mempool) {
+        let mut estimator = self.fee_estimator.write().unwrap();
+        estimator.record_block(block, mempool.entries());
+        if let Err(e) = self.db.save_fee_estimator(&estimator) {
+            log::warn!("💸 Failed to save fee estimates: {}", e);
+        }
+    }
+    
+    /// Validate `tx` against the UTXO set and add it to the mempool, evicting
+    /// any replaceable transactions it outbids. Block templates are built from
+    /// the mempool, so nothing gets in unchecked: `load_mempool` runs the same
+    /// checks, and only resurrected transactions, from blocks, skip them.
+    pub fn accept_to_mempool(&self, tx: Transaction) -> Result<Hash256> {
+        let event = (self.events.receiver_count() > 0).then(|| Arc::new(tx.clone()));
+        let (txid, replaced) = {
+            let utxo_set = self.utxo_set.read().unwrap();
+            let mut mempool = self.mempool.write().unwrap();
+            // The pool checks amounts and conflicts, the validator the signatures
+            if !tx.is_coinbase() {
+                let spent_scripts = mempool.spent_scripts(&tx, &utxo_set)?;
+                self.validator.validate_mempool_transaction(&tx, &spent_scripts)?;
+            }
+            mempool.add_with_replacement(tx, &utxo_set, self.height)?
+        };
+        self.notify_template_change();
+        for txid in replaced {
+            let _ = self.events.send(ChainEvent::TransactionRemoved(txid));
+        }
+        if let Some(tx) = event {
+            let _ = self.events.send(ChainEvent::TransactionAdded(tx));
+        }
+        Ok(txid)
+    }
+    
+    /// Put transactions from disconnected blocks (oldest first) back into the mempool
+    pub fn resurrect_transactions(&self, disconnected: &[Block]) -> Result<Vec<Hash256>> {
+        let resurrected = {
+            let utxo_set = self.utxo_set.read().unwrap();
+            let mut mempool = self.mempool.write().unwrap();
+            mempool.resurrect(disconnected, &utxo_set, self.height)
+        };
+        if !resurrected.is_empty() {
+            self.notify_template_change();
+        }
+        if self.events.receiver_count() > 0 {
+            let mempool = self.mempool.read().unwrap();
+            for entry in resurrected.iter().filter_map(|txid| mempool.get(txid)) {
+                let _ = self.events.send(ChainEvent::TransactionAdded(Arc::new(entry.tx.clone())));
+            }
+        }
+        Ok(resurrected)
+    }
+    
+    /// Snapshot of the mempool for `load_mempool` on another node
+    pub fn dump_mempool(&self) -> MempoolDump {
+        MempoolDump {
+            height: self.height,
+            tip: self.tip,
+            time: chrono::Utc::now().timestamp() as u64,
+            entries: self.mempool.read().unwrap().dump(),
+        }
+    }
+    
+    /// Add the transactions of a mempool dump, returning the ones accepted
+    pub fn load_mempool(&self, dump: MempoolDump) -> Result<Vec<Hash256>> {
+        if dump.tip != self.tip {
+            log::warn!("🗂️ Mempool dump was taken at height {}, this node is at {}; some entries may not apply", dump.height, self.height);
+        }
+        let restored = {
+            let utxo_set = self.utxo_set.read().unwrap();
+            let mut mempool = self.mempool.write().unwrap();
+            let mut restored = Vec::new();
+            // Parents come first in a dump, so each entry can spend the ones before it
+            for entry in dump.entries {
+                let checked = mempool.spent_scripts(&entry.tx, &utxo_set)
+                    .and_then(|scripts| self.validator.validate_mempool_transaction(&entry.tx, &scripts));
+                match checked {
+                    Ok(()) => restored.extend(mempool.restore(vec![entry], &utxo_set, self.height)),
+                    Err(e) => log::debug!("🗑️ Not restoring {}: {}", entry.txid, e),
+                }
+            }
+            restored
+        };
+        if !restored.is_empty() {
+            self.notify_template_change();
+        }
+        if self.events.receiver_count() > 0 {
+            let mempool = self.mempool.read().unwrap();
+            for entry in restored.iter().filter_map(|txid| mempool.get(txid)) {
+                let _ = self.events.send(ChainEvent::TransactionAdded(Arc::new(entry.tx.clone())));
+            }
+        }
+        Ok(restored)
+    }
+    
+    /// Every block connected or disconnected and every mempool change, for consumers
+    /// that keep derived state (such as wallet balances) up to date incrementally
+    pub fn subscribe_events(&self) -> broadcast::Receiver<ChainEvent> {
+        self.events.subscribe()
+    }
+    
+    /// Watch for tip or mempool changes, e.g. to answer block template long polls
+    pub fn subscribe_template_updates(&self) -> watch::Receiver<u64> {
+        self.template_updates.subscribe()
+    }
+    
+    fn notify_template_change(&self) {
+        self.template_updates.send_modify(|generation| *generation += 1);
+    }
+    
+    fn record_block_work(&self, block: &Block, chain_work: u128) -> Result<()> {
+        self.db.save_block_work(&BlockWorkEntry {
+            hash: block.hash(),
+            previous_hash: block.header.previous_hash,
+            height: block.header.height,
+            chain_work,
+        })
+    }
+    
+    /// Cumulative work up to `hash`, filling in the index for blocks stored before
+    /// fork tracking existed
+    fn chain_work_of(&self, hash: &Hash256) -> Result<u128> {
+        let mut unindexed = Vec::new();
+        let mut cursor = *hash;
+        let mut chain_work = loop {
+            if let Some(entry) = self.db.get_block_work(&cursor)? {
+                break entry.chain_work;
+            }
+            let block = self.db.get_block(&cursor)?
+                .ok_or_else(|| QtcError::Blockchain(format!("Unknown block {}", cursor)))?;
+            cursor = block.header.previous_hash;
+            let is_genesis = block.header.height == 0;
+            unindexed.push(block);
+            if is_genesis {
+                break 0;
+            }
+        };
+        
+        for block in unindexed.iter().rev() {
+            chain_work = chain_work.saturating_add(Self::block_work(block.header.bits));
+            self.record_block_work(block, chain_work)?;
+        }
+        Ok(chain_work)
+    }
+    
+    /// Walk back from a side-chain block to the last main chain block it shares
+    fn find_fork_point(&self, block: &Block) -> Result<Option<(u64, Hash256)>> {
+        let mut cursor = block.header.previous_hash;
+        
+        while let Some(ancestor) = self.db.get_block(&cursor)? {
+            let height = ancestor.header.height;
+            if self.db.get_block_hash_by_height(height)? == Some(cursor) {
+                return Ok(Some((height, cursor)));
+            }
+            if height == 0 {
+                break;
+            }
+            cursor = ancestor.header.previous_hash;
+        }
+        
+        Ok(None)
+    }
+    
+    pub fn get_stale_blocks(&self) -> Result<Vec<StaleBlockInfo>> {
+        self.db.get_stale_blocks()
+    }
+    
+    /// Chain work below which the node stays in initial block download
+    pub fn set_minimum_chain_work(&mut self, work: u128) {
+        self.minimum_chain_work = work;
+        if self.is_low_work() {
+            log::warn!("🐢 Chain work {} is below the configured minimum {}, syncing (low work)",
+                self.total_work, work);
+        }
+    }
+    
+    /// Keep block files under `target_mb`, deleting the oldest bodies; `None` keeps every block
+    pub fn set_prune_target(&mut self, target_mb: Option<u64>) -> Result<()> {
+        if let Some(target_mb) = target_mb {
+            if target_mb < MIN_PRUNE_TARGET_MB {
+                return Err(QtcError::InvalidInput(format!(
+                    "storage.prune_target_mb must be at least {} MB", MIN_PRUNE_TARGET_MB
+                )));
+            }
+        }
+        self.prune_target = target_mb.map(|mb| mb * 1024 * 1024);
+        self.prune_block_files()
+    }
+    
+    pub fn prune_status(&self) -> Result<PruneStatus> {
+        Ok(PruneStatus {
+            enabled: self.prune_target.is_some(),
+            target_mb: self.prune_target.map(|bytes| bytes / (1024 * 1024)),
+            pruned_height: self.db.get_pruned_height()?,
+            block_files_size: self.db.block_files().total_size()?,
+        })
+    }
+    
+    fn prune_block_files(&self) -> Result<()> {
+        let Some(target) = self.prune_target else {
+            return Ok(());
+        };
+        
+        // A crash replays blocks from the last UTXO flush, so those must survive too
+        let mut keep_from = self.height.saturating_sub(MIN_BLOCKS_TO_KEEP);
+        if let Some(utxo_tip) = self.db.get_utxo_tip()? {
+            match self.db.get_block_header(&utxo_tip)? {
+                Some(header) => keep_from = keep_from.min(header.height + 1),
+                None => return Ok(()),
+            }
+        }
+        self.db.prune_block_files(target, keep_from)?;
         Ok(())
     }
     
+    /// Switch to a custom chain's timing, emission and weight rules
+    pub fn set_chain_params(&mut self, params: ChainParams) {
+        self.monetary_policy = params.monetary_policy();
+        self.mempool.write().unwrap().set_pqc_witness_percent(params.pqc_witness_percent);
+        self.params = params;
+    }
+    
+    pub fn database(&self) -> &Arc<Database> {
+        &self.db
+    }
+    
+    pub fn chain_params(&self) -> &ChainParams {
+        &self.params
+    }
+    
+    pub fn monetary_policy(&self) -> &MonetaryPolicy {
+        &self.monetary_policy
+    }
+    
+    pub fn validator(&self) -> &BlockValidator {
+        &self.validator
+    }
+    
+    pub fn minimum_chain_work(&self) -> u128 {
+        self.minimum_chain_work
+    }
+    
+    /// True while our best chain has less work than `minimum_chain_work`; such a tip
+    /// may be a cheap fake chain and must not be built on or reported as synced
+    pub fn is_low_work(&self) -> bool {
+        self.total_work < self.minimum_chain_work
+    }
+    
+    /// Fail with `LowWork` while the tip is below the minimum chain work, for
+    /// readers such as balances that mustn't trust it yet
+    pub fn check_synced(&self) -> Result<()> {
+        if self.is_low_work() {
+            return Err(QtcError::LowWork { total_work: self.total_work, minimum: self.minimum_chain_work });
+        }
+        Ok(())
+    }
+    
+    /// Still catching up: below the minimum chain work or short of the assume-valid height
+    pub fn is_initial_block_download(&self) -> bool {
+        self.is_low_work() || self.params.assume_valid.is_some_and(|assume_valid| self.height < assume_valid)
+    }
+    
+    /// Whether the block at `height` may skip signature checks: it is at or below the
+    /// assume-valid height, which a checkpoint covers, and we are still syncing
+    pub fn skips_script_checks(&self, height: u64) -> bool {
+        self.params.is_assumed_valid(height) && self.is_initial_block_download()
+    }
+    
+    pub fn sync_status(&self) -> &'static str {
+        if self.is_low_work() {
+            "syncing (low work)"
+        } else {
+            "synced"
+        }
+    }
+    
+    /// Expected hashes to meet the target `bits` encode
+    pub fn block_work(bits: u32) -> u128 {
+        Target::from_bits(bits).work()
+    }
+    
+    fn backfill_total_work(&mut self, mut state: ChainState) -> Result<()> {
+        let mut total_work = 0u128;
+        for height in 0..=self.height {
+            let block = self.db.get_block_by_height(height)?
+                .ok_or_else(|| QtcError::Blockchain(format!("Missing block at height {}", height)))?;
+            total_work = total_work.saturating_add(Self::block_work(block.header.bits));
+        }
+        
+        state.total_work = total_work;
+        self.db.save_chain_state(&state)?;
+        self.total_work = total_work;
+        log::info!("🧮 Recomputed chain work: {}", total_work);
+        Ok(())
+    }
+    
+    /// Replay blocks the stored UTXO set is missing after an unclean shutdown
+    fn recover_utxo_set(&mut self) -> Result<()> {
+        let start = if self.db.is_utxo_flush_interrupted()? {
+            log::warn!("🧱 UTXO set was left half-written, rebuilding it from genesis");
+            None
+        } else {
+            match self.db.get_utxo_tip()? {
+                // Older databases wrote every change straight away
+                None => return Ok(()),
+                Some(tip) if tip == self.tip => return Ok(()),
+                Some(tip) => match self.db.get_block(&tip)? {
+                    Some(block) if block.header.height < self.height
+                        && self.db.get_block_hash_by_height(block.header.height)? == Some(tip) => {
+                        Some(block.header.height + 1)
+                    }
+                    _ => {
+                        log::warn!("🧱 UTXO set is at {}, which is off the main chain; rebuilding it from genesis", tip);
+                        None
+                    }
+                },
+            }
+        };
+        let start = match start {
+            Some(height) => height,
+            None if self.db.get_pruned_height()?.is_some() => {
+                return Err(QtcError::Blockchain(
+                    "UTXO set needs rebuilding from genesis, but old blocks were pruned; resync into an empty data directory".to_string()
+                ));
+            }
+            None => {
+                self.db.clear_utxo_set()?;
+                0
+            }
+        };
+        
+        log::info!("🧱 Replaying blocks {}..={} into the UTXO set...", start, self.height);
+        // These blocks' history was indexed when they were first connected
+        let history_complete = self.db.is_address_history_complete()?;
+        let mut utxo_set = self.utxo_set.write().unwrap();
+        utxo_set.reset();
+        for height in start..=self.height {
+            let block = self.db.get_block_by_height(height)?
+                .ok_or_else(|| QtcError::Blockchain(format!("Missing block at height {}", height)))?;
+            utxo_set.apply_block(&block)?;
+        }
+        utxo_set.flush()?;
+        self.db.set_address_history_complete(history_complete)?;
+        log::info!("✅ UTXO set caught up to height {}", self.height);
+        Ok(())
+    }
+    
+    /// Flush UTXO changes every `blocks` blocks while connecting old blocks
+    pub fn set_utxo_flush_interval(&mut self, blocks: u64) {
+        self.utxo_set.write().unwrap().set_flush_interval(blocks);
+    }
+    
+    /// Write any unflushed UTXO changes out, e.g. before shutting down
+    pub fn flush_utxos(&self) -> Result<()> {
+        self.utxo_set.write().unwrap().flush()
+    }
+    
+    /// Index confirmed transactions by txid as blocks are connected
+    pub fn set_txindex(&mut self, enabled: bool) {
+        self.txindex = enabled;
+    }
+    
+    /// Maintain the per-address transaction history, rebuilding it first if blocks
+    /// were connected while it was off
+    pub fn set_addrindex(&mut self, enabled: bool) -> Result<()> {
+        self.utxo_set.write().unwrap().set_address_history(enabled);
+        if !enabled || self.db.is_address_history_complete()? {
+            return Ok(());
+        }
+        
+        log::info!("📇 Building address history for {} blocks...", self.height + 1);
+        self.db.clear_address_history()?;
+        for height in 0..=self.height {
+            let block = self.db.get_block_by_height(height)?
+                .ok_or_else(|| QtcError::Blockchain(format!("Block at height {} not found", height)))?;
+            let spent = self.db.get_block_undo(&block.hash())?.unwrap_or_default();
+            self.db.save_address_history(height, &UtxoSet::block_address_history(&block, &spent))?;
+        }
+        self.db.set_address_history_complete(true)?;
+        self.db.flush()?;
+        
+        log::info!("✅ Address history indexed");
+        Ok(())
+    }
+    
+    /// Build compact block filters as blocks are connected, first filling in any
+    /// main chain blocks connected while this was off
+    pub fn set_blockfilterindex(&mut self, enabled: bool) -> Result<()> {
+        self.blockfilterindex = enabled;
+        if !enabled || self.db.has_block_filter(&self.tip)? {
+            return Ok(());
+        }
+        if let Some(height) = self.db.get_pruned_height()? {
+            return Err(QtcError::InvalidInput(format!(
+                "storage.blockfilterindex needs every block to catch up, but blocks up to height {} were pruned", height
+            )));
+        }
+        
+        log::info!("🔎 Building block filters for {} blocks...", self.height + 1);
+        for height in 0..=self.height {
+            let hash = self.db.get_block_hash_by_height(height)?
+                .ok_or_else(|| QtcError::Blockchain(format!("Block at height {} not found", height)))?;
+            if self.db.has_block_filter(&hash)? {
+                continue;
+            }
+            let block = self.db.get_block(&hash)?
+                .ok_or_else(|| QtcError::Blockchain(format!("Block at height {} not found", height)))?;
+            self.index_block_filter(&block)?;
+        }
+        self.db.flush()?;
+        
+        log::info!("✅ Block filters indexed");
+        Ok(())
+    }
+    
+    /// Whether compact block filters are built, and so can be served
+    pub fn has_block_filters(&self) -> bool {
+        self.blockfilterindex
+    }
+    
+    /// Build and store the filter for a connected block, chained onto its parent's
+    fn index_block_filter(&self, block: &Block) -> Result<BlockFilter> {
+        let previous_header = match block.header.height {
+            0 => Hash256::zero(),
+            _ => self.db.get_block_filter(&block.header.previous_hash)?
+                .map(|previous| previous.header)
+                .ok_or_else(|| QtcError::Blockchain(format!("No filter for block {}", block.header.previous_hash)))?,
+        };
+        let spent = self.db.get_block_undo(&block.hash())?.unwrap_or_default();
+        let filter = BlockFilter::build(block, &spent, &previous_header)?;
+        self.db.save_block_filter(&filter)?;
+        Ok(filter)
+    }
+    
+    /// The compact filter for `hash`, if filters are built and it has been connected
+    pub fn get_block_filter(&self, hash: &Hash256) -> Result<Option<BlockFilter>> {
+        self.db.get_block_filter(hash)
+    }
+    
+    /// Main chain filters from `start_height` through `stop_hash`, at most `max` of them;
+    /// None when `stop_hash` isn't on the main chain within that range or a filter is missing
+    pub fn get_block_filters(&self, start_height: u64, stop_hash: &Hash256, max: u64) -> Result<Option<Vec<BlockFilter>>> {
+        let mut filters = Vec::new();
+        for height in start_height..start_height.saturating_add(max) {
+            let Some(hash) = self.db.get_block_hash_by_height(height)? else {
+                return Ok(None);
+            };
+            let Some(filter) = self.db.get_block_filter(&hash)? else {
+                return Ok(None);
+            };
+            filters.push(filter);
+            if hash == *stop_hash {
+                return Ok(Some(filters));
+            }
+        }
+        Ok(None)
+    }
+    
     pub fn get_block(&self, hash: &Hash256) -> Result<Option<Block>> {
         self.db.get_block(hash)
     }
@@ -168,6 +1086,15 @@ impl Blockchain {
         self.db.get_block_by_height(height)
     }
     
+    /// Headers outlive block bodies on a pruned node
+    pub fn get_block_header_by_height(&self, height: u64) -> Result<Option<BlockHeader>> {
+        self.db.get_block_header_by_height(height)
+    }
+    
+    pub fn is_block_pruned(&self, hash: &Hash256) -> Result<bool> {
+        self.db.is_block_pruned(hash)
+    }
+    
     pub fn get_balance(&self, address: &str) -> Result<u64> {
         let utxo_set = self.utxo_set.read().unwrap();
         utxo_set.get_balance(address)
@@ -178,6 +1105,36 @@ impl Blockchain {
         utxo_set.get_utxos(address)
     }
     
+    /// Look up an output: unspent entry, where it was spent, or unknown
+    pub fn get_txout(&self, outpoint: &OutPoint) -> Result<TxOutStatus> {
+        let utxo = {
+            let utxo_set = self.utxo_set.read().unwrap();
+            utxo_set.get_utxo(outpoint)?
+        };
+        
+        if let Some(utxo) = utxo {
+            return Ok(TxOutStatus::Unspent(utxo));
+        }
+        
+        match self.db.get_spent_output(outpoint)? {
+            Some(spent) => Ok(TxOutStatus::Spent(spent)),
+            None => Ok(TxOutStatus::Unknown),
+        }
+    }
+    
+    /// Scan the whole UTXO set for outputs matching the given descriptors, no wallet required
+    pub fn scan_txout_set(&self, descriptors: &[String]) -> Result<ScanResult> {
+        if descriptors.is_empty() {
+            return Err(QtcError::InvalidInput("At least one descriptor is required".to_string()));
+        }
+
+        let parsed = descriptors.iter()
+            .map(|text| Ok((text.clone(), text.parse::<ScanDescriptor>()?)))
+            .collect::<Result<Vec<_>>>()?;
+
+        scan::scan_utxos(self.db.get_all_utxos()?, &parsed, self.height)
+    }
+    
     /// Get all addresses that have ever been used (for blockchain explorer)
     pub fn get_all_addresses(&self) -> Result<Vec<String>> {
         self.db.get_all_addresses()
@@ -224,11 +1181,11 @@ impl Blockchain {
             }
         }
         
-        let avg_block_time = if block_count > 0 { total_time / block_count } else { 450 };
+        let avg_block_time = if block_count > 0 { total_time / block_count } else { self.params.target_block_time };
         
         Ok(BlockchainStats {
             height: chain_state.height,
-            difficulty: chain_state.difficulty,
+            difficulty: chain_state.difficulty(),
             total_supply: chain_state.total_supply,
             total_addresses,
             avg_block_time,
@@ -237,27 +1194,20 @@ impl Blockchain {
     }
     
     fn estimate_network_hashrate(&self) -> Result<f64> {
-        // Simplified hashrate estimation based on difficulty and block time
-        let difficulty = self.get_current_difficulty()? as f64;
-        let target_time = 450.0; // 7.5 minutes in seconds
-        
-        // Rough estimate: hashrate = difficulty * 2^difficulty / target_time
-        let hashrate = difficulty * (2.0_f64.powf(difficulty / 8.0)) / target_time;
-        Ok(hashrate)
+        // Hashes the current target takes, spread over the target block time
+        let work = Self::block_work(self.get_current_bits()?) as f64;
+        Ok(work / self.params.target_block_time.max(1) as f64)
     }
     
     pub fn is_valid_transaction(&self, tx: &Transaction) -> Result<bool> {
         self.validator.validate_transaction(tx, self)
     }
     
-    pub fn calculate_next_difficulty(&self, height: u64) -> Result<u32> {
-        use crate::mining::difficulty::DifficultyCalculator;
-        
-        // Use production-grade difficulty calculator
-        let calculator = DifficultyCalculator::new();
+    pub fn calculate_next_bits(&self, height: u64) -> Result<u32> {
+        let calculator = self.params.difficulty_calculator();
         
         if height < calculator.adjustment_interval {
-            return Ok(20); // Initial difficulty - higher for realistic mining times
+            return Ok(self.params.initial_bits);
         }
         
         // Collect block timestamps for last adjustment interval
@@ -265,66 +1215,107 @@ impl Blockchain {
         let start_height = height.saturating_sub(calculator.adjustment_interval);
         
         for i in start_height..=height {
-            if let Some(block) = self.get_block_by_height(i)? {
-                block_times.push(block.header.timestamp);
+            if let Some(header) = self.get_block_header_by_height(i)? {
+                block_times.push(header.timestamp);
             }
         }
         
         if block_times.len() < 2 {
-            return Ok(self.get_current_difficulty()?);
+            return self.get_current_bits();
         }
         
-        let current_difficulty = self.get_current_difficulty()?;
+        let current_bits = self.get_current_bits()?;
         
         // Use robust difficulty adjustment algorithm
-        let new_difficulty = calculator.calculate_next_difficulty(current_difficulty, &block_times)?;
+        let new_bits = calculator.calculate_next_bits(current_bits, &block_times)?;
         
         log::info!(
-            "Difficulty adjustment at height {}: {} -> {} (target: {} seconds per block)",
+            "Difficulty adjustment at height {}: {:.3} -> {:.3} (bits {:#010x}, target: {} seconds per block)",
             height,
-            current_difficulty,
-            new_difficulty,
+            Target::from_bits(current_bits).difficulty(),
+            Target::from_bits(new_bits).difficulty(),
+            new_bits,
             calculator.target_block_time
         );
         
-        Ok(new_difficulty)
+        Ok(new_bits)
     }
     
-    pub fn get_current_difficulty(&self) -> Result<u32> {
-        let state = self.db.get_chain_state()?;
-        Ok(state.unwrap_or_default().difficulty)
+    /// Bits the block at `height`, on top of the tip, must have, in compact form
+    pub fn required_bits(&self, height: u64) -> Result<u32> {
+        let bits = if self.params.lwma_active(height) {
+            self.lwma_bits(height)?
+        } else {
+            self.calculate_next_bits(height)?
+        };
+        // Chain state and params written before compact bits may hold an old difficulty
+        Ok(Target::from_bits(bits).to_bits())
     }
     
-    pub fn calculate_total_supply(&self, height: u64) -> u64 {
-        self.monetary_policy.total_supply_at_height(height)
+    /// Bits kept in the chain state once the block at `height` is the tip
+    fn bits_after(&self, height: u64) -> Result<u32> {
+        if self.params.lwma_active(height + 1) {
+            return self.lwma_bits(height + 1);
+        }
+        self.calculate_next_bits(height)
     }
     
-    pub fn is_valid_proof_of_work(&self, block: &Block) -> bool {
-        let hash = block.hash();
-        let difficulty = block.header.difficulty;
-        
-        // Check if hash has required number of leading zeros
-        let required_zeros = difficulty / 4; // 4 bits per hex digit
-        let remaining_bits = difficulty % 4;
-        
-        for i in 0..required_zeros as usize {
-            if hash.as_bytes()[i] != 0 {
-                return false;
-            }
+    /// LWMA bits for the block at `height`, from the window of blocks below it
+    fn lwma_bits(&self, height: u64) -> Result<u32> {
+        let calculator = self.params.difficulty_calculator();
+        let start = height.saturating_sub(calculator.lwma_window + 1);
+        let headers = (start..height)
+            .map(|h| self.get_block_header_by_height(h)?
+                .ok_or_else(|| QtcError::Blockchain(format!("Missing header at height {}", h))))
+            .collect::<Result<Vec<_>>>()?;
+        if headers.len() < 2 {
+            return Ok(self.params.initial_bits);
         }
         
-        // Check remaining bits if any
-        if remaining_bits > 0 {
-            let byte_index = required_zeros as usize;
-            if byte_index < hash.as_bytes().len() {
-                let mask = 0xFF << (8 - remaining_bits);
-                if hash.as_bytes()[byte_index] & mask != 0 {
-                    return false;
-                }
+        let timestamps: Vec<u64> = headers.iter().map(|header| header.timestamp).collect();
+        let bits: Vec<u32> = headers[1..].iter().map(|header| header.bits).collect();
+        calculator.calculate_lwma_bits(&timestamps, &bits)
+    }
+    
+    /// Bits for a block on top of the tip stamped `timestamp`, letting it drop to
+    /// the proof of work limit when the network allows bootstrap blocks and the tip is stale
+    pub fn bits_for_next_block(&self, timestamp: u64) -> Result<u32> {
+        if let Some(tip) = self.get_block_header_by_height(self.height)? {
+            if let Some(bits) = self.params.bootstrap_bits(tip.timestamp, timestamp) {
+                return Ok(bits);
             }
         }
-        
-        true
+        Ok(Target::from_bits(self.get_current_bits()?).to_bits())
+    }
+    
+    /// Bits the next block needs, as kept in the chain state
+    pub fn get_current_bits(&self) -> Result<u32> {
+        let state = self.db.get_chain_state()?;
+        Ok(state.unwrap_or_default().bits)
+    }
+    
+    /// The next block's difficulty, for display
+    pub fn get_current_difficulty(&self) -> Result<f64> {
+        Ok(self.get_chain_info()?.difficulty())
+    }
+    
+    pub fn calculate_total_supply(&self, height: u64) -> u64 {
+        self.monetary_policy.total_supply_at_height(height).saturating_add(self.genesis_supply)
+    }
+    
+    pub fn is_valid_proof_of_work(&self, block: &Block) -> bool {
+        Self::header_meets_target(&block.header)
+    }
+    
+    /// Whether the header's hash is within the target its own bits encode
+    pub fn header_meets_target(header: &BlockHeader) -> bool {
+        Self::hash_meets_target(&header.hash(), header.bits)
+    }
+    
+    /// Whether `hash` is within the target `bits` encode, e.g. for pool shares
+    pub fn hash_meets_target(hash: &Hash256, bits: u32) -> bool {
+        // Bits that encode no target can't be met, and peers may claim them
+        Target::from_bits(bits).is_met_by(hash)
     }
     
     pub fn get_latest_blocks(&self, count: usize) -> Result<Vec<Block>> {
@@ -350,3 +1341,441 @@ impl Blockchain {
         self.db.get_chain_state().map(|opt| opt.unwrap_or_default())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::consensus::target::REGTEST_BITS;
+    use crate::core::transaction::sign_p2pkh_input;
+    use crate::crypto::keys::KeyPair;
+    use tempfile::TempDir;
+
+    /// A chain where every block needs only a bit of work, and difficulty never adjusts
+    fn easy_chain(temp_dir: &TempDir) -> Result<Blockchain> {
+        let db = Arc::new(Database::new(temp_dir.path().join("test.db"))?);
+        let mut chain = Blockchain::new(db)?;
+        chain.set_chain_params(ChainParams {
+            initial_bits: REGTEST_BITS,
+            difficulty_adjustment_interval: 1_000_000,
+            ..ChainParams::mainnet()
+        });
+        Ok(chain)
+    }
+
+    fn mine_block(chain: &Blockchain, parent: &Block, branch: &str, transactions: Vec<Transaction>) -> Block {
+        mine_block_paying(chain, parent, "qtc1reorgminer", branch, transactions)
+    }
+
+    fn mine_block_paying(chain: &Blockchain, parent: &Block, payee: &str, branch: &str, transactions: Vec<Transaction>) -> Block {
+        let height = parent.header.height + 1;
+        let coinbase = Transaction::new_coinbase(
+            payee.to_string(),
+            chain.monetary_policy().coinbase_reward(height),
+            format!("branch {} height {}", branch, height),
+        );
+        let mut block = Block::new(parent.hash(), [vec![coinbase], transactions].concat(), REGTEST_BITS, height);
+        block.header.timestamp = parent.header.timestamp + 30;
+        while !chain.is_valid_proof_of_work(&block) {
+            block.increment_nonce();
+        }
+        block
+    }
+
+    fn extend(chain: &mut Blockchain, parent: &Block, branch: &str, count: usize) -> Result<Vec<Block>> {
+        let mut blocks: Vec<Block> = Vec::new();
+        for _ in 0..count {
+            let block = mine_block(chain, blocks.last().unwrap_or(parent), branch, Vec::new());
+            let _ = chain.add_block(block.clone()); // side branches report "stored as stale"
+            blocks.push(block);
+        }
+        Ok(blocks)
+    }
+
+    fn is_unspent(chain: &Blockchain, tx: &Transaction) -> Result<bool> {
+        Ok(chain.utxo_set.read().unwrap().get_utxo(&OutPoint::new(tx.hash(), 0))?.is_some())
+    }
+
+    #[test]
+    fn test_one_block_reorg() -> Result<()> {
+        let temp_dir = TempDir::new().unwrap();
+        let mut chain = easy_chain(&temp_dir)?;
+        let genesis = chain.get_block_by_height(0)?.unwrap();
+
+        let a1 = mine_block(&chain, &genesis, "a", Vec::new());
+        chain.add_block(a1.clone())?;
+
+        // Equal work: the block seen first stays, the competitor is kept as stale
+        let b1 = mine_block(&chain, &genesis, "b", Vec::new());
+        assert!(chain.add_block(b1.clone()).is_err());
+        assert_eq!(chain.tip, a1.hash());
+
+        let b2 = mine_block(&chain, &b1, "b", Vec::new());
+        chain.add_block(b2.clone())?;
+        assert_eq!(chain.tip, b2.hash());
+        assert_eq!(chain.height, 2);
+        assert_eq!(chain.get_block_by_height(1)?.unwrap().hash(), b1.hash());
+        assert_eq!(chain.total_work, Blockchain::block_work(6) + 2 * Blockchain::block_work(1));
+
+        assert!(!is_unspent(&chain, &a1.transactions[0])?);
+        assert!(is_unspent(&chain, &b1.transactions[0])?);
+        let stale: Vec<Hash256> = chain.get_stale_blocks()?.iter().map(|info| info.hash).collect();
+        assert_eq!(stale, vec![a1.hash()]);
+
+        // Going back to the lighter branch is refused
+        assert!(chain.reorganize_to(&a1.hash()).is_err());
+        assert_eq!(chain.tip, b2.hash());
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalidate_and_reconsider_block() -> Result<()> {
+        let temp_dir = TempDir::new().unwrap();
+        let mut chain = easy_chain(&temp_dir)?;
+        let genesis = chain.get_block_by_height(0)?.unwrap();
+        let a = extend(&mut chain, &genesis, "a", 2)?;
+        let b = extend(&mut chain, &genesis, "b", 1)?;
+        assert_eq!(chain.tip, a[1].hash());
+
+        // The lighter branch takes over once the heavier one is marked invalid
+        chain.invalidate_block(&a[0].hash())?;
+        assert_eq!((chain.tip, chain.height), (b[0].hash(), 1));
+        assert!(chain.add_block(mine_block(&chain, &a[1], "a", Vec::new())).is_err());
+        assert!(chain.invalidate_block(&genesis.hash()).is_err());
+
+        chain.reconsider_block(&a[1].hash())?;
+        assert_eq!((chain.tip, chain.height), (a[1].hash(), 2));
+        assert!(!chain.database().is_block_invalid(&a[0].hash())?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_failed_reorg_marks_branch_invalid() -> Result<()> {
+        let temp_dir = TempDir::new().unwrap();
+        let mut chain = easy_chain(&temp_dir)?;
+        let genesis = chain.get_block_by_height(0)?.unwrap();
+        let a = extend(&mut chain, &genesis, "a", 1)?;
+
+        let mut bogus = Transaction::new();
+        bogus.add_input(OutPoint::new(Hash256::hash(b"nowhere"), 0), Vec::new());
+        bogus.add_output(10_000, "qtc1reorgpayee");
+        let b1 = mine_block(&chain, &genesis, "b", vec![bogus]);
+        assert!(chain.add_block(b1.clone()).is_err());
+        let b2 = mine_block(&chain, &b1, "b", Vec::new());
+        assert!(chain.add_block(b2.clone()).is_err());
+        assert_eq!(chain.tip, a[0].hash());
+
+        // The bad block and everything on it are refused from now on
+        assert!(chain.database().is_block_invalid(&b1.hash())?);
+        assert!(chain.database().is_block_invalid(&b2.hash())?);
+        let b3 = mine_block(&chain, &b2, "b", Vec::new());
+        let err = chain.add_block(b3).unwrap_err();
+        assert!(err.to_string().contains("marked invalid"));
+        assert_eq!(chain.tip, a[0].hash());
+        Ok(())
+    }
+
+    #[test]
+    fn test_multi_block_reorg_restores_spent_outputs() -> Result<()> {
+        let temp_dir = TempDir::new().unwrap();
+        let mut chain = easy_chain(&temp_dir)?;
+        let genesis = chain.get_block_by_height(0)?.unwrap();
+
+        // Mature the first coinbase so the losing branch can spend it
+        let owner = KeyPair::new()?;
+        let first = mine_block_paying(&chain, &genesis, &owner.address(), "shared", Vec::new());
+        chain.add_block(first.clone())?;
+        let shared = extend(&mut chain, &first, "shared", 100)?;
+        let fork = shared.last().unwrap().clone();
+        let mature = first.transactions[0].clone();
+
+        let mut spend = Transaction::new();
+        spend.add_input(OutPoint::new(mature.hash(), 0), Vec::new());
+        spend.add_output(mature.outputs[0].value - 10_000, "qtc1reorgpayee");
+        sign_p2pkh_input(&mut spend, 0, &owner.private_key)?;
+
+        let a1 = mine_block(&chain, &fork, "a", vec![spend.clone()]);
+        chain.add_block(a1.clone())?;
+        let mut a_branch = vec![a1.clone()];
+        a_branch.extend(extend(&mut chain, &a1, "a", 2)?);
+        assert_eq!(chain.tip, a_branch[2].hash());
+        assert!(!is_unspent(&chain, &mature)?);
+        assert!(is_unspent(&chain, &spend)?);
+
+        // The competing branch takes over once it has more work: a three block reorg
+        let b_branch = extend(&mut chain, &fork, "b", 3)?;
+        assert_eq!(chain.tip, a_branch[2].hash());
+        let b4 = mine_block(&chain, &b_branch[2], "b", Vec::new());
+        chain.add_block(b4.clone())?;
+
+        assert_eq!(chain.tip, b4.hash());
+        assert_eq!(chain.height, fork.header.height + 4);
+        for block in &b_branch {
+            assert_eq!(chain.get_block_by_height(block.header.height)?.map(|b| b.hash()), Some(block.hash()));
+        }
+        for block in &a_branch {
+            assert!(!is_unspent(&chain, &block.transactions[0])?);
+            assert!(chain.db.get_stale_block_info(&block.hash())?.is_some());
+        }
+
+        // The spend is undone and waits in the mempool for the new chain
+        assert!(is_unspent(&chain, &mature)?);
+        assert!(!is_unspent(&chain, &spend)?);
+        assert!(chain.mempool.read().unwrap().contains(&spend.hash()));
+
+        // The new tip and its work survive a restart
+        let reloaded = Blockchain::new(chain.db.clone())?;
+        assert_eq!(reloaded.tip, b4.hash());
+        assert_eq!(reloaded.total_work, chain.total_work);
+        assert_eq!(chain.db.get_most_work_block()?.map(|entry| entry.hash), Some(b4.hash()));
+        Ok(())
+    }
+
+    #[test]
+    fn test_block_may_spend_its_own_outputs() -> Result<()> {
+        let temp_dir = TempDir::new().unwrap();
+        let mut chain = easy_chain(&temp_dir)?;
+        let genesis = chain.get_block_by_height(0)?.unwrap();
+        let owner = KeyPair::new()?;
+        let funding = mine_block_paying(&chain, &genesis, &owner.address(), "funding", Vec::new());
+        chain.add_block(funding.clone())?;
+        let tip = extend(&mut chain, &funding, "maturing", 100)?.pop().unwrap();
+
+        let coin = &funding.transactions[0];
+        let mut parent = Transaction::new();
+        parent.add_input(OutPoint::new(coin.hash(), 0), Vec::new());
+        parent.add_output(coin.outputs[0].value - 10_000, &owner.address());
+        sign_p2pkh_input(&mut parent, 0, &owner.private_key)?;
+        let mut child = Transaction::new();
+        child.add_input(OutPoint::new(parent.hash(), 0), Vec::new());
+        child.add_output(parent.outputs[0].value - 10_000, "qtc1childpayee");
+        sign_p2pkh_input(&mut child, 0, &owner.private_key)?;
+
+        // Only outputs of earlier transactions in the block can be spent
+        let backwards = mine_block(&chain, &tip, "backwards", vec![child.clone(), parent.clone()]);
+        assert!(chain.add_block(backwards).is_err());
+        assert_eq!(chain.tip, tip.hash());
+
+        let block = mine_block(&chain, &tip, "chained", vec![parent.clone(), child.clone()]);
+        chain.add_block(block.clone())?;
+        assert_eq!(chain.tip, block.hash());
+        assert!(!is_unspent(&chain, coin)?);
+        assert!(!is_unspent(&chain, &parent)?);
+        assert!(is_unspent(&chain, &child)?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_checkpoints_and_assume_valid() -> Result<()> {
+        let temp_dir = TempDir::new().unwrap();
+        let mut chain = easy_chain(&temp_dir)?;
+        let genesis = chain.get_block_by_height(0)?.unwrap();
+        let shared = extend(&mut chain, &genesis, "shared", 101)?;
+        let fork = shared.last().unwrap().clone();
+        let mature = shared[0].transactions[0].clone();
+
+        // Spent under a signature from a key that doesn't own the output
+        let key = crate::crypto::keys::PrivateKey::new()?.public_key()?;
+        let mut script = vec![64];
+        script.extend([0u8; 64]);
+        script.push(1);
+        script.push(key.to_bytes().len() as u8);
+        script.extend_from_slice(key.to_bytes());
+        let mut forged = Transaction::new();
+        forged.add_input(OutPoint::new(mature.hash(), 0), script);
+        forged.add_output(mature.outputs[0].value - 10_000, "qtc1reorgpayee");
+        let assumed = mine_block(&chain, &fork, "a", vec![forged]);
+        assert!(chain.add_block(assumed.clone()).is_err());
+
+        // Up to the assume-valid height, signatures aren't checked while syncing
+        let height = assumed.header.height;
+        chain.set_chain_params(ChainParams {
+            checkpoints: std::collections::BTreeMap::from([(height, assumed.hash())]),
+            assume_valid: Some(height),
+            ..chain.chain_params().clone()
+        });
+        assert!(chain.is_initial_block_download());
+        chain.add_block(assumed.clone())?;
+        assert!(!chain.is_initial_block_download());
+
+        // Nothing else may take the checkpoint's place, and no fork may start below it
+        let rival = mine_block(&chain, &fork, "b", Vec::new());
+        assert!(chain.add_block(rival).unwrap_err().to_string().contains("contradicts the checkpoint"));
+        let deep = mine_block(&chain, &shared[50], "c", Vec::new());
+        assert!(chain.add_block(deep).unwrap_err().to_string().contains("below the checkpoint"));
+        assert_eq!(chain.tip, assumed.hash());
+        Ok(())
+    }
+
+    #[test]
+    fn test_minimum_chain_work() -> Result<()> {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Arc::new(Database::new(temp_dir.path().join("test.db"))?);
+        let mut chain = Blockchain::new(db.clone())?;
+
+        assert_eq!(Blockchain::block_work(6), 1 << 10);
+        assert_eq!(chain.total_work, Blockchain::block_work(6));
+        assert_eq!(chain.sync_status(), "synced");
+
+        chain.set_minimum_chain_work(chain.total_work + 1);
+        assert!(chain.is_low_work());
+        assert_eq!(chain.sync_status(), "syncing (low work)");
+
+        // Work recorded in the chain state survives a restart
+        let reloaded = Blockchain::new(db)?;
+        assert_eq!(reloaded.total_work, chain.total_work);
+        Ok(())
+    }
+
+    #[test]
+    fn test_accept_to_mempool_checks_signatures() -> Result<()> {
+        let temp_dir = TempDir::new().unwrap();
+        let mut chain = easy_chain(&temp_dir)?;
+        let genesis = chain.get_block_by_height(0)?.unwrap();
+        let (owner, thief) = (KeyPair::new()?, KeyPair::new()?);
+        let funding = mine_block_paying(&chain, &genesis, &owner.address(), "funding", Vec::new());
+        chain.add_block(funding.clone())?;
+        extend(&mut chain, &funding, "maturing", 100)?;
+
+        let coin = &funding.transactions[0];
+        let mut spend = Transaction::new();
+        spend.add_input(OutPoint::new(coin.hash(), 0), Vec::new());
+        spend.add_output(coin.outputs[0].value - 10_000, &thief.address());
+
+        // Unsigned or signed by anyone but the owner: kept out of the pool
+        assert!(chain.accept_to_mempool(spend.clone()).is_err());
+        let mut stolen = spend.clone();
+        sign_p2pkh_input(&mut stolen, 0, &thief.private_key)?;
+        assert!(chain.accept_to_mempool(stolen).is_err());
+        assert!(chain.mempool.read().unwrap().is_empty());
+
+        sign_p2pkh_input(&mut spend, 0, &owner.private_key)?;
+        assert_eq!(chain.accept_to_mempool(spend.clone())?, spend.hash());
+
+        // A child of a pooled parent is checked against the parent's output
+        let mut child = Transaction::new();
+        child.add_input(OutPoint::new(spend.hash(), 0), Vec::new());
+        child.add_output(spend.outputs[0].value - 10_000, &owner.address());
+        sign_p2pkh_input(&mut child, 0, &owner.private_key)?;
+        assert!(chain.accept_to_mempool(child.clone()).is_err());
+        sign_p2pkh_input(&mut child, 0, &thief.private_key)?;
+        chain.accept_to_mempool(child)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_forged_pqc_spend_gets_no_weight_discount() -> Result<()> {
+        use crate::core::transaction::{pqc_signature_script, sign_pqc_input};
+        use crate::crypto::pqc::{PqcKeyPair, PqcSignature};
+
+        let temp_dir = TempDir::new().unwrap();
+        let mut chain = easy_chain(&temp_dir)?;
+        let genesis = chain.get_block_by_height(0)?.unwrap();
+        let owner = PqcKeyPair::new()?;
+        let funding = mine_block_paying(&chain, &genesis, &owner.address().address, "funding", Vec::new());
+        chain.add_block(funding.clone())?;
+        let matured = extend(&mut chain, &funding, "maturing", 100)?;
+
+        let coin = &funding.transactions[0];
+        let mut spend = Transaction::new();
+        spend.add_input(OutPoint::new(coin.hash(), 0), Vec::new());
+        spend.add_output(coin.outputs[0].value - 10_000, "qtc1reorgpayee");
+
+        // Dilithium3-sized but signing nothing: it would weigh far less than its size
+        let mut forged = spend.clone();
+        forged.inputs[0].signature_script = pqc_signature_script(
+            &PqcSignature { signature: vec![7; 3_309], public_key: owner.address().signing_public_key },
+            &owner.address().encryption_public_key,
+        );
+        assert!(forged.weight(25) < forged.size() / 2);
+        assert!(chain.accept_to_mempool(forged.clone()).is_err());
+        let block = mine_block(&chain, matured.last().unwrap(), "forged", vec![forged]);
+        assert!(chain.add_block(block).is_err());
+
+        sign_pqc_input(&mut spend, 0, &owner)?;
+        chain.accept_to_mempool(spend.clone())?;
+        assert_eq!(chain.mempool.read().unwrap().get(&spend.hash()).unwrap().size, spend.weight(25));
+        Ok(())
+    }
+
+    #[test]
+    fn test_low_work_branch_does_not_take_over() -> Result<()> {
+        let temp_dir = TempDir::new().unwrap();
+        let mut chain = easy_chain(&temp_dir)?;
+        let genesis = chain.get_block_by_height(0)?.unwrap();
+        let a = extend(&mut chain, &genesis, "a", 1)?;
+        chain.set_minimum_chain_work(chain.total_work + 2 * Blockchain::block_work(REGTEST_BITS));
+
+        // Heavier than the tip but still short of the minimum: kept stale
+        let b = extend(&mut chain, &genesis, "b", 2)?;
+        assert_eq!(chain.tip, a[0].hash());
+        assert!(matches!(chain.check_synced(), Err(QtcError::LowWork { .. })));
+        assert!(matches!(chain.snapshot().get_balance("qtc1reorgminer"), Err(QtcError::LowWork { .. })));
+
+        // Once it clears the minimum it becomes the main chain
+        let b3 = mine_block(&chain, &b[1], "b", Vec::new());
+        chain.add_block(b3.clone())?;
+        assert_eq!(chain.tip, b3.hash());
+        assert!(chain.check_synced().is_ok());
+        assert!(chain.snapshot().get_balance("qtc1reorgminer").is_ok());
+        Ok(())
+    }
+
+    #[test]
+    fn test_snapshot_goes_stale_when_tip_moves() -> Result<()> {
+        let temp_dir = TempDir::new().unwrap();
+        let mut chain = easy_chain(&temp_dir)?;
+        let genesis = chain.get_block_by_height(0)?.unwrap();
+        let a1 = mine_block(&chain, &genesis, "a", Vec::new());
+        chain.add_block(a1.clone())?;
+
+        let snapshot = chain.snapshot();
+        assert_eq!(snapshot.get_block(&snapshot.tip)?.unwrap().hash(), a1.hash());
+        let a2 = mine_block(&chain, &a1, "a", Vec::new());
+        assert!(snapshot.get_block_by_height(2)?.is_none());
+
+        chain.add_block(a2)?;
+        assert!(!snapshot.is_current());
+        assert!(matches!(snapshot.get_balance("qtc1reorgminer"), Err(QtcError::StaleSnapshot)));
+        assert!(matches!(snapshot.get_block_by_height(1), Err(QtcError::StaleSnapshot)));
+
+        // Composite reads run against a snapshot of the current tip
+        let chain = RwLock::new(chain);
+        let (height, balance) = crate::core::with_snapshot(&chain, |snapshot| {
+            Ok((snapshot.height, snapshot.get_balance("qtc1reorgminer")?))
+        })?;
+        assert_eq!(height, 2);
+        assert_eq!(balance, chain.read().unwrap().get_balance("qtc1reorgminer")?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_address_index_follows_the_tip() -> Result<()> {
+        let temp_dir = TempDir::new().unwrap();
+        let mut chain = easy_chain(&temp_dir)?;
+        chain.set_addrindex(true)?;
+        let genesis = chain.get_block_by_height(0)?.unwrap();
+
+        let a1 = mine_block(&chain, &genesis, "a", Vec::new());
+        chain.add_block(a1.clone())?;
+        let coinbase = OutPoint::new(a1.transactions[0].hash(), 0);
+        let miner = chain.utxo_set.read().unwrap().get_utxo(&coinbase)?.unwrap().address;
+
+        let utxos = chain.db.get_utxos_for_address(&miner)?;
+        assert_eq!(utxos.iter().map(|(outpoint, _)| outpoint.clone()).collect::<Vec<_>>(), vec![coinbase]);
+        assert_eq!(chain.db.get_address_history(&miner, 10)?, vec![(1, a1.transactions[0].hash())]);
+        assert_eq!(chain.db.get_address_transactions(&miner, 10)?[0].2, 1);
+
+        chain.disconnect_tip()?;
+        assert!(chain.db.get_utxos_for_address(&miner)?.is_empty());
+        assert!(chain.db.get_address_history(&miner, 10)?.is_empty());
+
+        // A block connected with the index off leaves a gap that is rebuilt on the next enable
+        chain.set_addrindex(false)?;
+        let b1 = mine_block(&chain, &genesis, "b", Vec::new());
+        chain.add_block(b1.clone())?;
+        assert!(!chain.db.is_address_history_complete()?);
+        chain.set_addrindex(true)?;
+        assert_eq!(chain.db.get_address_history(&miner, 10)?, vec![(1, b1.transactions[0].hash())]);
+        Ok(())
+    }
+}
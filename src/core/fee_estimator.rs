@@ -0,0 +1,206 @@
+//! Fee estimation from how quickly mempool transactions confirm
+//!
+//! When a block connects, each of its transactions we saw in the mempool is
+//! filed under its fee rate bucket along with how many blocks it waited, and
+//! transactions still waiting after `MAX_TARGET` blocks count as misses. Old
+//! observations decay a little with every block. An estimate for a target is
+//! the lowest bucket that, together with the buckets above it, confirmed
+//! within the target often enough.
+
+use crate::core::mempool::{MempoolEntry, FEE_HISTOGRAM_BUCKETS};
+use crate::core::Block;
+use crate::crypto::hash::Hashable;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Longest confirmation target we track, in blocks
+pub const MAX_TARGET: u32 = 48;
+
+/// Share of transactions that must confirm within the target
+const SUCCESS_THRESHOLD: f64 = 0.85;
+
+/// Decayed observations a bucket range needs before it says anything
+const MIN_SAMPLES: f64 = 4.0;
+
+/// Per-block decay, giving observations a half-life of about 350 blocks
+const DECAY: f64 = 0.998;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FeeBasis {
+    History,     // confirmation times of recent blocks
+    Mempool,     // what the current mempool would leave out
+    Minimum,     // no data; the lowest rate we'd relay
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FeeEstimate {
+    pub blocks: u32,
+    pub fee_rate: u64, // satoshis per weighted byte
+    pub basis: FeeBasis,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct BucketStats {
+    confirmed_within: Vec<f64>, // [t - 1]: confirmed within t blocks
+    total: f64,                 // confirmed or given up on
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FeeEstimator {
+    buckets: Vec<BucketStats>, // same edges as the mempool fee histogram, highest first
+    height: u64,               // last block recorded
+}
+
+impl Default for FeeEstimator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FeeEstimator {
+    pub fn new() -> Self {
+        let bucket = BucketStats { confirmed_within: vec![0.0; MAX_TARGET as usize], total: 0.0 };
+        Self { buckets: vec![bucket; FEE_HISTOGRAM_BUCKETS.len()], height: 0 }
+    }
+
+    pub fn height(&self) -> u64 {
+        self.height
+    }
+
+    /// Record `block` against the mempool as it was just before the block connected
+    pub fn record_block<'a>(&mut self, block: &Block, mempool: impl IntoIterator<Item = &'a MempoolEntry>) {
+        let height = block.header.height;
+        // Replayed or reorged heights would count the same transactions twice
+        if height <= self.height {
+            return;
+        }
+        self.height = height;
+
+        for bucket in &mut self.buckets {
+            bucket.total *= DECAY;
+            bucket.confirmed_within.iter_mut().for_each(|count| *count *= DECAY);
+        }
+
+        let pooled: HashMap<_, _> = mempool.into_iter().map(|entry| (entry.txid, entry)).collect();
+        let confirmed: Vec<_> = block.transactions.iter().map(|tx| tx.hash()).collect();
+        for entry in confirmed.iter().filter_map(|txid| pooled.get(txid)) {
+            let waited = height.saturating_sub(entry.height).max(1);
+            if waited <= MAX_TARGET as u64 {
+                let bucket = &mut self.buckets[bucket_index(entry.fee_rate)];
+                bucket.total += 1.0;
+                for count in &mut bucket.confirmed_within[waited as usize - 1..] {
+                    *count += 1.0;
+                }
+            }
+        }
+
+        // Anything still waiting after the longest target has missed all of them
+        for entry in pooled.values().filter(|entry| !confirmed.contains(&entry.txid)) {
+            if height.saturating_sub(entry.height) == MAX_TARGET as u64 {
+                self.buckets[bucket_index(entry.fee_rate)].total += 1.0;
+            }
+        }
+    }
+
+    /// Lowest fee rate that has confirmed within `blocks` often enough, if we know one
+    pub fn estimate(&self, blocks: u32) -> Option<u64> {
+        let target = clamp_target(blocks) as usize;
+        let mut best = None;
+        let (mut confirmed, mut total) = (0.0, 0.0);
+
+        for (bucket, &edge) in self.buckets.iter().zip(FEE_HISTOGRAM_BUCKETS.iter().rev()) {
+            confirmed += bucket.confirmed_within[target - 1];
+            total += bucket.total;
+            if total < MIN_SAMPLES {
+                continue;
+            }
+            if confirmed / total < SUCCESS_THRESHOLD {
+                break;
+            }
+            best = Some(edge);
+            (confirmed, total) = (0.0, 0.0);
+        }
+        best
+    }
+}
+
+/// Targets we can answer for: one block up to `MAX_TARGET`
+pub fn clamp_target(blocks: u32) -> u32 {
+    blocks.clamp(1, MAX_TARGET)
+}
+
+/// Highest-first index of the bucket `fee_rate` falls in; rates below the lowest edge join it
+fn bucket_index(fee_rate: u64) -> usize {
+    FEE_HISTOGRAM_BUCKETS.iter().rev()
+        .position(|&edge| fee_rate >= edge)
+        .unwrap_or(FEE_HISTOGRAM_BUCKETS.len() - 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::transaction::OutPoint;
+    use crate::core::Transaction;
+    use crate::crypto::hash::Hash256;
+
+    fn entry(seed: u32, fee_rate: u64, height: u64) -> MempoolEntry {
+        let mut tx = Transaction::new();
+        tx.add_input(OutPoint::new(Hash256::hash(&seed.to_le_bytes()), 0), vec![]);
+        tx.add_output(10_000, "qtc1payee");
+        MempoolEntry {
+            txid: tx.hash(),
+            tx,
+            fee: fee_rate * 200,
+            size: 200,
+            fee_rate,
+            time: 0,
+            height,
+            ancestor_count: 1,
+            ancestor_size: 200,
+            ancestor_fee: fee_rate * 200,
+            descendant_count: 1,
+            descendant_size: 200,
+        }
+    }
+
+    #[test]
+    fn test_estimates_follow_confirmation_times() {
+        let mut estimator = FeeEstimator::new();
+        assert_eq!(estimator.estimate(1), None);
+
+        // Every block, 50 sat/byte gets in at once while 5 sat/byte waits three blocks
+        let mut pool: Vec<MempoolEntry> = Vec::new();
+        let mut seed = 0;
+        for height in 1..=60u64 {
+            for fee_rate in [50, 5] {
+                pool.push(entry(seed, fee_rate, height - 1));
+                seed += 1;
+            }
+            let included: Vec<Transaction> = pool.iter()
+                .filter(|entry| entry.fee_rate == 50 || height - entry.height >= 3)
+                .map(|entry| entry.tx.clone())
+                .collect();
+            let block = Block::new(Hash256::zero(), included.clone(), 6, height);
+            estimator.record_block(&block, &pool);
+            pool.retain(|entry| !included.iter().any(|tx| tx.hash() == entry.txid));
+        }
+
+        assert_eq!(estimator.height(), 60);
+        assert_eq!(estimator.estimate(1), Some(50));
+        assert_eq!(estimator.estimate(2), Some(50));
+        assert_eq!(estimator.estimate(3), Some(5));
+        assert_eq!(estimator.estimate(1_000), Some(5));
+
+        // A block we already recorded changes nothing
+        let before = estimator.buckets[bucket_index(50)].total;
+        estimator.record_block(&Block::new(Hash256::zero(), vec![], 6, 60), &pool);
+        assert_eq!(estimator.buckets[bucket_index(50)].total, before);
+
+        let bytes = bincode::serialize(&estimator).unwrap();
+        let restored: FeeEstimator = bincode::deserialize(&bytes).unwrap();
+        assert_eq!(restored.estimate(3), Some(5));
+        assert_eq!(bucket_index(0), FEE_HISTOGRAM_BUCKETS.len() - 1);
+        assert_eq!(bucket_index(10_000), 0);
+    }
+}
@@ -0,0 +1,121 @@
+//! Compact block filters for light clients (BIP158 style)
+//!
+//! A filter is a Golomb-coded set of every script a block touches: the
+//! scripts its outputs pay and the scripts of the outputs its inputs spend,
+//! OP_RETURN outputs left out. A light wallet fetches the filter instead of
+//! the block, tests it against its own scripts, and downloads only the blocks
+//! that match; false positives come up about once in 784,931 queries. Set
+//! members are keyed with SipHash on the block hash as BIP158 does, and each
+//! filter's header commits to the one before it, so a client that trusts a
+//! header can check every filter served up to it.
+
+use crate::core::transaction::OutPoint;
+use crate::core::{Block, UtxoEntry};
+use crate::crypto::hash::{Hash256, Hashable};
+use crate::{QtcError, Result};
+use bitcoin::bip158::{GcsFilterReader, GcsFilterWriter};
+use serde::{Deserialize, Serialize};
+
+/// Golomb-Rice parameter: bits of each delta written verbatim
+pub const FILTER_P: u8 = 19;
+/// Inverse false positive rate
+pub const FILTER_M: u64 = 784_931;
+
+/// The filter for one block, with the header chaining it to the filters before
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlockFilter {
+    pub block_hash: Hash256,
+    pub header: Hash256,
+    pub filter: Vec<u8>, // element count as a varint, then the Golomb-Rice coded set
+}
+
+impl BlockFilter {
+    /// Filter `block`, whose inputs spent `spent`, chained onto `previous_header`
+    /// (all zeros for the genesis block)
+    pub fn build(block: &Block, spent: &[(OutPoint, UtxoEntry)], previous_header: &Hash256) -> Result<Self> {
+        let block_hash = block.hash();
+        let (k0, k1) = siphash_keys(&block_hash);
+        let mut filter = Vec::new();
+        {
+            let mut writer = GcsFilterWriter::new(&mut filter, k0, k1, FILTER_M, FILTER_P);
+            let outputs = block.transactions.iter().flat_map(|tx| &tx.outputs).filter(|output| !output.is_unspendable());
+            for output in outputs {
+                writer.add_element(&output.script_pubkey);
+            }
+            for (_, utxo) in spent {
+                writer.add_element(&utxo.script_pubkey);
+            }
+            writer.finish()
+                .map_err(|e| QtcError::Blockchain(format!("Failed to write filter for block {}: {}", block_hash, e)))?;
+        }
+
+        let header = filter_header(&filter, previous_header);
+        Ok(Self { block_hash, header, filter })
+    }
+
+    /// Whether the block may touch any of `scripts`; false means it certainly doesn't
+    pub fn matches_any(&self, scripts: &[Vec<u8>]) -> Result<bool> {
+        let (k0, k1) = siphash_keys(&self.block_hash);
+        GcsFilterReader::new(k0, k1, FILTER_M, FILTER_P)
+            .match_any(&mut self.filter.as_slice(), scripts.iter().map(Vec::as_slice))
+            .map_err(|e| QtcError::InvalidInput(format!("Malformed filter for block {}: {}", self.block_hash, e)))
+    }
+
+    /// Whether this filter is the one `previous_header` and `header` commit to
+    pub fn verify(&self, previous_header: &Hash256) -> bool {
+        filter_header(&self.filter, previous_header) == self.header
+    }
+}
+
+/// The first 16 bytes of the block hash, as BIP158 keys SipHash with them
+fn siphash_keys(block_hash: &Hash256) -> (u64, u64) {
+    let bytes = block_hash.as_bytes();
+    let k0 = u64::from_le_bytes(bytes[0..8].try_into().expect("8 bytes"));
+    let k1 = u64::from_le_bytes(bytes[8..16].try_into().expect("8 bytes"));
+    (k0, k1)
+}
+
+fn filter_header(filter: &[u8], previous_header: &Hash256) -> Hash256 {
+    let mut data = Hash256::double_hash(filter).as_bytes().to_vec();
+    data.extend_from_slice(previous_header.as_bytes());
+    Hash256::double_hash(&data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::Transaction;
+    use crate::crypto::keys::KeyPair;
+
+    #[test]
+    fn test_filter_matches_scripts_the_block_touches() -> Result<()> {
+        let (miner, spender, stranger) = (KeyPair::new()?.address(), KeyPair::new()?.address(), KeyPair::new()?.address());
+        let script = |address: &str| Transaction::address_to_script_pubkey(address);
+        let mut coinbase = Transaction::new_coinbase(miner.clone(), 5_000, "filtered".to_string());
+        coinbase.outputs.push(crate::core::TxOutput::data(b"not in the filter")?);
+        let block = Block::new(Hash256::zero(), vec![coinbase], 0x207fffff, 1);
+        let spent = vec![(OutPoint::new(Hash256::hash(b"funding"), 0), UtxoEntry {
+            txid: Hash256::hash(b"funding"),
+            vout: 0,
+            value: 1_000,
+            script_pubkey: script(&spender),
+            address: spender.clone(),
+            height: 0,
+            is_coinbase: false,
+        })];
+
+        let filter = BlockFilter::build(&block, &spent, &Hash256::zero())?;
+        assert!(filter.matches_any(&[script(&miner)])?);
+        assert!(filter.matches_any(&[script(&stranger), script(&spender)])?);
+        assert!(!filter.matches_any(&[script(&stranger)])?);
+        assert_eq!(filter.filter[0], 2); // the two scripts; the OP_RETURN output is left out
+
+        // Headers chain: the same filter after another header doesn't verify
+        assert!(filter.verify(&Hash256::zero()));
+        assert!(!filter.verify(&Hash256::hash(b"other")));
+        let next = BlockFilter::build(&block, &[], &filter.header)?;
+        assert!(next.verify(&filter.header));
+        assert_ne!(next.header, filter.header);
+        Ok(())
+    }
+}
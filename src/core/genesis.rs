@@ -0,0 +1,161 @@
+//! The first block of a chain, built the same way on every node
+//!
+//! Everything in a genesis block comes from `GenesisParams`: the message in
+//! its coinbase, its timestamp and difficulty, and any premine outputs. Nodes
+//! with the same parameters derive the same genesis hash, which peers compare
+//! when they connect; nodes with different parameters are on different chains.
+
+use crate::consensus::target::Target;
+use crate::core::{Block, Transaction};
+use crate::crypto::hash::{Hash256, Hashable};
+use crate::crypto::keys::is_valid_address;
+use crate::{QtcError, Result};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+/// Coinbase scripts must be 2 to 100 bytes, and the message is the whole script
+const MIN_MESSAGE_LEN: usize = 2;
+const MAX_MESSAGE_LEN: usize = 100;
+
+/// An output the genesis block pays, written `ADDRESS:SATS` on the command line
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PremineOutput {
+    pub address: String,
+    pub amount: u64, // satoshis
+}
+
+impl FromStr for PremineOutput {
+    type Err = QtcError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (address, amount) = s.rsplit_once(':')
+            .ok_or_else(|| QtcError::InvalidInput(format!("Premine '{}' should be ADDRESS:SATS", s)))?;
+        let amount = amount.parse()
+            .map_err(|_| QtcError::InvalidInput(format!("Invalid premine amount '{}'", amount)))?;
+        Ok(Self { address: address.to_string(), amount })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GenesisParams {
+    pub message: String,
+    pub timestamp: u64,  // unix seconds
+    #[serde(alias = "difficulty")]
+    pub bits: u32,
+    pub address: String, // takes the zero-value coinbase output when nothing is premined
+    #[serde(default)]
+    pub premine: Vec<PremineOutput>,
+}
+
+impl GenesisParams {
+    pub fn mainnet() -> Self {
+        Self {
+            message: "The Times 10/Jul/2025 Chancellor on brink of second bailout for banks - QTC Genesis".to_string(),
+            timestamp: 1_752_105_600, // 2025-07-10 00:00:00 UTC
+            bits: 6, // the pre-compact difficulty, kept so the genesis hash stays put
+            address: "qtc1qw508d6qejxtdg4y5r3zarvary0c5xw7kxdz6v9".to_string(),
+            premine: Vec::new(),
+        }
+    }
+
+    pub fn validate(&self, max_supply: u64) -> Result<()> {
+        if !(MIN_MESSAGE_LEN..=MAX_MESSAGE_LEN).contains(&self.message.len()) {
+            return Err(QtcError::Consensus(format!(
+                "Genesis message must be {} to {} bytes, got {}", MIN_MESSAGE_LEN, MAX_MESSAGE_LEN, self.message.len()
+            )));
+        }
+        if Target::from_bits(self.bits) == Target::ZERO {
+            return Err(QtcError::Consensus(format!("Genesis bits {:#010x} encode no target", self.bits)));
+        }
+        for output in &self.premine {
+            if !is_valid_address(&output.address) {
+                return Err(QtcError::Consensus(format!("Invalid premine address {}", output.address)));
+            }
+            if output.amount == 0 {
+                return Err(QtcError::Consensus(format!("Premine to {} pays nothing", output.address)));
+            }
+        }
+        let total = self.premine.iter().try_fold(0u64, |total, output| total.checked_add(output.amount));
+        if total.is_none_or(|total| total > max_supply) {
+            return Err(QtcError::Consensus(format!("Premine exceeds max supply {}", max_supply)));
+        }
+        Ok(())
+    }
+
+    /// Satoshis the genesis block creates; mined rewards come on top of this
+    pub fn premine_total(&self) -> u64 {
+        self.premine.iter().map(|output| output.amount).sum()
+    }
+
+    pub fn block(&self) -> Block {
+        let mut coinbase = match self.premine.split_first() {
+            Some((first, _)) => Transaction::new_coinbase(first.address.clone(), first.amount, self.message.clone()),
+            None => Transaction::new_coinbase(self.address.clone(), 0, self.message.clone()),
+        };
+        for output in self.premine.iter().skip(1) {
+            coinbase.add_output(output.amount, &output.address);
+        }
+
+        let mut block = Block::new(Hash256::zero(), vec![coinbase], self.bits, 0);
+        block.header.timestamp = self.timestamp;
+        block
+    }
+
+    pub fn hash(&self) -> Hash256 {
+        self.block().hash()
+    }
+}
+
+impl Default for GenesisParams {
+    fn default() -> Self {
+        Self::mainnet()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::Blockchain;
+    use crate::crypto::keys::KeyPair;
+    use crate::storage::Database;
+    use std::sync::Arc;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_genesis_is_deterministic() -> Result<()> {
+        let mainnet = GenesisParams::mainnet();
+        assert_eq!(mainnet.hash(), GenesisParams::mainnet().block().hash());
+        assert_eq!(mainnet.block().header.timestamp, mainnet.timestamp);
+        mainnet.validate(u64::MAX)?;
+
+        // Every parameter is part of the hash
+        let address = KeyPair::new()?.address();
+        let custom = GenesisParams {
+            message: "Private chain".to_string(),
+            premine: vec![format!("{}:5000", address).parse()?],
+            ..mainnet.clone()
+        };
+        custom.validate(10_000)?;
+        assert_ne!(custom.hash(), mainnet.hash());
+        assert_ne!(GenesisParams { timestamp: 1, ..mainnet.clone() }.hash(), mainnet.hash());
+        assert_ne!(GenesisParams { bits: 7, ..mainnet.clone() }.hash(), mainnet.hash());
+        assert_eq!(custom.block().transactions[0].total_output_value(), custom.premine_total());
+
+        assert!(custom.validate(4_999).is_err());
+        assert!(GenesisParams { message: "x".repeat(101), ..mainnet.clone() }.validate(u64::MAX).is_err());
+        assert!(GenesisParams { premine: vec!["qtc1bad:10".parse()?], ..mainnet }.validate(u64::MAX).is_err());
+        assert!("no-amount".parse::<PremineOutput>().is_err());
+
+        // A chain started from custom parameters keeps them, and its premine, when reopened
+        let temp_dir = TempDir::new().unwrap();
+        let db = Arc::new(Database::new(temp_dir.path().join("test.db"))?);
+        let chain = Blockchain::with_genesis(db.clone(), &custom)?;
+        assert_eq!(chain.genesis_hash()?, custom.hash());
+        assert_eq!(chain.get_chain_info()?.total_supply, 5_000);
+        drop(chain);
+        let reopened = Blockchain::new(db)?;
+        assert_eq!(reopened.genesis_hash()?, custom.hash());
+        assert_eq!(reopened.calculate_total_supply(0), 5_000);
+        Ok(())
+    }
+}
@@ -0,0 +1,1126 @@
+//! Memory pool of unconfirmed transactions
+
+use crate::api::metrics::metrics;
+use crate::core::{Block, Transaction, TxOutput};
+use crate::core::transaction::{OutPoint, LOCKTIME_THRESHOLD};
+use crate::core::utxo::UtxoSet;
+use crate::consensus::monetary::is_coinbase_mature;
+use crate::consensus::validation::check_amounts;
+use crate::consensus::params::ChainParams;
+use crate::crypto::hash::{Hash256, Hashable};
+use crate::{QtcError, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+const DEFAULT_MAX_SIZE: usize = 300_000_000; // 300MB
+const DEFAULT_MIN_FEE: u64 = 1000;
+
+/// Largest payload of a data-carrier (OP_RETURN) output the mempool relays
+pub const MAX_DATA_CARRIER_SIZE: usize = 80;
+/// Data-carrier outputs a relayed transaction may have
+pub const MAX_DATA_OUTPUTS: usize = 1;
+
+/// Most transactions a single replacement may evict, descendants included
+const MAX_REPLACEMENT_EVICTIONS: usize = 100;
+
+/// Most transactions in a pooled transaction's ancestor package, itself included;
+/// longer unconfirmed chains wait until part of them confirms
+const MAX_ANCESTOR_COUNT: usize = 25;
+/// Most transactions a pooled transaction and its descendants may number
+const MAX_DESCENDANT_COUNT: usize = 25;
+/// Largest weight of a transaction together with its ancestors, or with its descendants
+const MAX_PACKAGE_SIZE: usize = 101_000;
+
+/// Lower edges (sat/byte) of the buckets reported by `fee_histogram`
+pub(crate) const FEE_HISTOGRAM_BUCKETS: &[u64] = &[1, 2, 3, 5, 8, 10, 15, 20, 30, 50, 75, 100, 150, 200, 300, 500, 1000];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MempoolEntry {
+    pub tx: Transaction,
+    pub txid: Hash256,
+    pub fee: u64,
+    pub size: usize, // weight, with PQC signatures scaled
+    pub fee_rate: u64, // satoshis per weighted byte
+    pub time: u64,
+    pub height: u64, // chain height when the transaction entered the pool
+    /// The transaction plus its unconfirmed ancestors, what a miner has to
+    /// include together to collect this fee
+    #[serde(default)]
+    pub ancestor_count: usize,
+    #[serde(default)]
+    pub ancestor_size: usize,
+    #[serde(default)]
+    pub ancestor_fee: u64,
+    /// The transaction plus its pooled descendants, which the chain limits cap
+    #[serde(default)]
+    pub descendant_count: usize,
+    #[serde(default)]
+    pub descendant_size: usize,
+}
+
+impl MempoolEntry {
+    /// Fee rate of the ancestor package, what block templates rank by
+    pub fn ancestor_fee_rate(&self) -> u64 {
+        self.ancestor_fee / self.ancestor_size.max(1) as u64
+    }
+}
+
+/// A node's mempool as written by `chain mempool dump`, parents before children
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MempoolDump {
+    pub height: u64, // chain tip when dumped
+    pub tip: Hash256,
+    pub time: u64,
+    pub entries: Vec<MempoolEntry>,
+}
+
+/// Transactions the next block template would take, in block order
+#[derive(Debug, Clone, Default)]
+pub struct ProjectedBlock {
+    pub txids: Vec<Hash256>,
+    pub size: usize,
+    pub total_fees: u64,
+    pub min_fee_rate: u64, // package fee rates, sat/byte
+    pub max_fee_rate: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FeeRateBucket {
+    pub fee_rate: u64, // bucket lower edge, sat/byte
+    pub count: usize,
+    pub size: usize,
+}
+
+/// A transaction plus its unconfirmed ancestors, parents first
+#[derive(Debug, Default)]
+struct Package {
+    txids: Vec<Hash256>,
+    fee: u64,
+    size: usize,
+}
+
+#[derive(Debug)]
+pub struct Mempool {
+    entries: HashMap<Hash256, MempoolEntry>,
+    spends: HashMap<OutPoint, Hash256>, // outpoint -> mempool tx spending it
+    total_size: usize,
+    max_size: usize,
+    min_fee: u64,
+    pqc_witness_percent: u32,
+}
+
+impl Default for Mempool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Mempool {
+    pub fn new() -> Self {
+        Self::with_params(DEFAULT_MAX_SIZE, DEFAULT_MIN_FEE)
+    }
+
+    pub fn with_params(max_size: usize, min_fee: u64) -> Self {
+        Self {
+            entries: HashMap::new(),
+            spends: HashMap::new(),
+            total_size: 0,
+            max_size,
+            min_fee,
+            pqc_witness_percent: ChainParams::mainnet().pqc_witness_percent,
+        }
+    }
+    
+    /// Weigh PQC signatures by the chain's rule, re-ranking what's already pooled
+    pub fn set_pqc_witness_percent(&mut self, pqc_witness_percent: u32) {
+        self.pqc_witness_percent = pqc_witness_percent;
+        self.total_size = 0;
+        for entry in self.entries.values_mut() {
+            entry.size = entry.tx.weight(pqc_witness_percent);
+            entry.fee_rate = entry.fee / entry.size.max(1) as u64;
+            self.total_size += entry.size;
+        }
+        let txids: Vec<Hash256> = self.entries.keys().copied().collect();
+        for txid in &txids {
+            self.refresh_packages(txid);
+        }
+    }
+
+    /// Validate `tx` against the UTXO set (and unconfirmed parents) and add it
+    pub fn add_transaction(&mut self, tx: Transaction, utxo_set: &UtxoSet, tip_height: u64) -> Result<Hash256> {
+        self.add_with_replacement(tx, utxo_set, tip_height).map(|(txid, _)| txid)
+    }
+
+    /// `add_transaction`, replacing pooled transactions that spend the same outputs
+    /// if they signal replaceability and `tx` outbids them: a higher fee rate than
+    /// each, and enough fee to cover everything evicted plus its own relay. Returns
+    /// the evicted transactions, descendants included.
+    pub fn add_with_replacement(&mut self, tx: Transaction, utxo_set: &UtxoSet, tip_height: u64) -> Result<(Hash256, Vec<Hash256>)> {
+        let txid = tx.hash();
+
+        if tx.is_coinbase() {
+            return Err(QtcError::Transaction("Coinbase transactions are not accepted into the mempool".to_string()));
+        }
+        if self.entries.contains_key(&txid) {
+            return Err(QtcError::Transaction(format!("Transaction {} already in mempool", txid)));
+        }
+        if tx.inputs.is_empty() || tx.outputs.is_empty() {
+            return Err(QtcError::Transaction("Transaction has no inputs or outputs".to_string()));
+        }
+        if !tx.is_final(tip_height + 1, chrono::Utc::now().timestamp() as u64) {
+            return Err(QtcError::Transaction(format!(
+                "Transaction is not final: locked until {} {}",
+                if tx.lock_time < LOCKTIME_THRESHOLD { "height" } else { "time" }, tx.lock_time
+            )));
+        }
+
+        let mut conflicts = Vec::new();
+        for (index, input) in tx.inputs.iter().enumerate() {
+            let outpoint = &input.previous_output;
+
+            if tx.inputs[..index].iter().any(|other| &other.previous_output == outpoint) {
+                return Err(QtcError::Transaction("Duplicate inputs in transaction".to_string()));
+            }
+            if let Some(conflict) = self.spends.get(outpoint).copied() {
+                if !self.entries.get(&conflict).is_some_and(|entry| entry.tx.signals_rbf()) {
+                    return Err(QtcError::DoubleSpend(format!(
+                        "{}:{} already spent by mempool transaction {}", outpoint.txid, outpoint.vout, conflict
+                    )));
+                }
+                if !conflicts.contains(&conflict) {
+                    conflicts.push(conflict);
+                }
+            }
+        }
+        let evicted = self.descendants(&conflicts);
+        if evicted.len() > MAX_REPLACEMENT_EVICTIONS {
+            return Err(QtcError::Transaction(format!(
+                "Replacement would evict {} transactions, at most {} allowed", evicted.len(), MAX_REPLACEMENT_EVICTIONS
+            )));
+        }
+
+        let mut total_input = 0u64;
+        for input in &tx.inputs {
+            let outpoint = &input.previous_output;
+            if evicted.contains(&outpoint.txid) {
+                return Err(QtcError::Transaction(format!(
+                    "Replacement spends an output of {}, which it replaces", outpoint.txid
+                )));
+            }
+
+            let value = match utxo_set.get_utxo(outpoint)? {
+                Some(utxo) => {
+                    if utxo.is_coinbase && !is_coinbase_mature(utxo.height, tip_height) {
+                        return Err(QtcError::Transaction("Coinbase UTXO not yet mature".to_string()));
+                    }
+                    utxo.value
+                }
+                None => self.unconfirmed_output(outpoint)
+                    .filter(|output| !output.is_unspendable())
+                    .map(|output| output.value)
+                    .ok_or_else(|| QtcError::Transaction(format!(
+                        "Referenced UTXO not found: {}:{}", outpoint.txid, outpoint.vout
+                    )))?,
+            };
+            total_input = total_input.saturating_add(value);
+        }
+
+        Self::check_data_outputs(&tx)?;
+        // The same amount rules as in a block, so nothing pooled is unmineable
+        let fee = check_amounts(&tx, total_input, self.min_fee)?;
+
+        let size = tx.weight(self.pqc_witness_percent);
+        for conflict in &conflicts {
+            let replaced = &self.entries[conflict];
+            if fee as u128 * replaced.size as u128 <= replaced.fee as u128 * size as u128 {
+                return Err(QtcError::Transaction(format!(
+                    "Replacement fee rate {} sat/byte does not beat {}'s {} sat/byte",
+                    fee / size.max(1) as u64, conflict, replaced.fee_rate
+                )));
+            }
+        }
+        let evicted_fees: u64 = evicted.iter().map(|txid| self.entries[txid].fee).sum();
+        let evicted_size: usize = evicted.iter().map(|txid| self.entries[txid].size).sum();
+        if !conflicts.is_empty() && fee < evicted_fees.saturating_add(self.min_fee) {
+            return Err(QtcError::Transaction(format!(
+                "Replacement pays fee {}, needs at least {} to cover the {} transaction(s) it evicts plus relay",
+                fee, evicted_fees.saturating_add(self.min_fee), evicted.len()
+            )));
+        }
+
+        if self.total_size - evicted_size + size > self.max_size {
+            return Err(QtcError::Transaction("Mempool is full".to_string()));
+        }
+        let ancestors = self.check_chain_limits(&tx, size, &evicted)?;
+
+        let mut replaced = Vec::new();
+        for conflict in &conflicts {
+            replaced.extend(self.remove_with_descendants(conflict));
+        }
+        for input in &tx.inputs {
+            self.spends.insert(input.previous_output.clone(), txid);
+        }
+        self.total_size += size;
+        self.entries.insert(txid, MempoolEntry {
+            tx,
+            txid,
+            fee,
+            size,
+            fee_rate: fee / size.max(1) as u64,
+            time: chrono::Utc::now().timestamp() as u64,
+            height: tip_height,
+            ancestor_count: ancestors.len() + 1,
+            ancestor_size: ancestors.iter().map(|ancestor| self.entries[ancestor].size).sum::<usize>() + size,
+            ancestor_fee: ancestors.iter().map(|ancestor| self.entries[ancestor].fee).sum::<u64>() + fee,
+            descendant_count: 1,
+            descendant_size: size,
+        });
+        for ancestor in &ancestors {
+            let entry = self.entries.get_mut(ancestor).expect("ancestors are pooled");
+            entry.descendant_count += 1;
+            entry.descendant_size += size;
+        }
+        // A resurrected parent may already have children waiting in the pool,
+        // whose packages it joins; rare enough to recount them outright
+        let descendants = self.descendants(&[txid]);
+        if descendants.len() > 1 {
+            for affected in descendants.iter().chain(&ancestors) {
+                self.refresh_packages(affected);
+            }
+        }
+        self.publish_metrics();
+
+        if replaced.is_empty() {
+            log::debug!("📥 Accepted transaction {} into mempool (fee {})", txid, fee);
+        } else {
+            log::info!("🔁 Transaction {} replaced {} mempool transaction(s) (fee {})", txid, replaced.len(), fee);
+        }
+        Ok((txid, replaced))
+    }
+
+    /// The pooled ancestors of `tx`, once it's clear that adding it keeps its
+    /// ancestors and each of theirs descendants within the chain limits.
+    /// `evicted` is about to leave the pool, making room.
+    fn check_chain_limits(&self, tx: &Transaction, size: usize, evicted: &HashSet<Hash256>) -> Result<HashSet<Hash256>> {
+        let mut ancestors = HashSet::new();
+        let mut ancestor_size = size;
+        let mut pending: Vec<Hash256> = tx.inputs.iter().map(|input| input.previous_output.txid).collect();
+        while let Some(txid) = pending.pop() {
+            let Some(entry) = self.entries.get(&txid) else {
+                continue;
+            };
+            if !ancestors.insert(txid) {
+                continue;
+            }
+            if ancestors.len() + 1 > MAX_ANCESTOR_COUNT {
+                return Err(QtcError::Transaction(format!(
+                    "Transaction has more than {} unconfirmed ancestors", MAX_ANCESTOR_COUNT - 1
+                )));
+            }
+            ancestor_size += entry.size;
+            pending.extend(entry.tx.inputs.iter().map(|input| input.previous_output.txid));
+        }
+        if ancestor_size > MAX_PACKAGE_SIZE {
+            return Err(QtcError::Transaction(format!(
+                "Transaction and its unconfirmed ancestors weigh {}, at most {} allowed", ancestor_size, MAX_PACKAGE_SIZE
+            )));
+        }
+
+        for ancestor in &ancestors {
+            let entry = &self.entries[ancestor];
+            let (mut count, mut descendant_size) = (entry.descendant_count, entry.descendant_size);
+            if !evicted.is_empty() {
+                for leaving in self.descendants(&[*ancestor]).intersection(evicted) {
+                    count -= 1;
+                    descendant_size -= self.entries[leaving].size;
+                }
+            }
+            if count + 1 > MAX_DESCENDANT_COUNT {
+                return Err(QtcError::Transaction(format!(
+                    "Unconfirmed ancestor {} already has {} descendants", ancestor, MAX_DESCENDANT_COUNT - 1
+                )));
+            }
+            if descendant_size + size > MAX_PACKAGE_SIZE {
+                return Err(QtcError::Transaction(format!(
+                    "Unconfirmed ancestor {} and its descendants would weigh {}, at most {} allowed",
+                    ancestor, descendant_size + size, MAX_PACKAGE_SIZE
+                )));
+            }
+        }
+        Ok(ancestors)
+    }
+
+    /// Relay limits on data-carrier outputs: how many, a single push each, and how big
+    fn check_data_outputs(tx: &Transaction) -> Result<()> {
+        let mut count = 0;
+        for output in tx.outputs.iter().filter(|output| output.is_unspendable()) {
+            count += 1;
+            let Some(data) = output.data_payload() else {
+                return Err(QtcError::Transaction("Data output script is not a single push".to_string()));
+            };
+            if data.len() > MAX_DATA_CARRIER_SIZE {
+                return Err(QtcError::Transaction(format!(
+                    "Data output carries {} bytes, at most {} are relayed", data.len(), MAX_DATA_CARRIER_SIZE
+                )));
+            }
+        }
+        if count > MAX_DATA_OUTPUTS {
+            return Err(QtcError::Transaction(format!(
+                "Transaction has {} data outputs, at most {} are relayed", count, MAX_DATA_OUTPUTS
+            )));
+        }
+        Ok(())
+    }
+
+    /// Remove a transaction and everything in the pool that spends its outputs
+    pub fn remove_with_descendants(&mut self, txid: &Hash256) -> Vec<Hash256> {
+        // Children first, so each removal still finds every ancestor it's counted in;
+        // a child always has more ancestors than its parent
+        let mut doomed: Vec<Hash256> = self.descendants(&[*txid]).into_iter()
+            .filter(|txid| self.entries.contains_key(txid))
+            .collect();
+        doomed.sort_by_key(|txid| std::cmp::Reverse(self.entries[txid].ancestor_count));
+
+        let mut removed: Vec<Hash256> = doomed.into_iter()
+            .filter(|txid| self.remove_entry(txid).is_some())
+            .collect();
+        removed.reverse();
+        removed
+    }
+
+    /// Drop transactions confirmed by `block` and any that conflict with it
+    pub fn remove_for_block(&mut self, block: &Block) -> Vec<Hash256> {
+        let mut removed = Vec::new();
+
+        for tx in &block.transactions {
+            let txid = tx.hash();
+            if self.remove_entry(&txid).is_some() {
+                removed.push(txid);
+            }
+
+            if tx.is_coinbase() {
+                continue;
+            }
+            for input in &tx.inputs {
+                if let Some(conflict) = self.spends.get(&input.previous_output).copied() {
+                    removed.extend(self.remove_with_descendants(&conflict));
+                }
+            }
+        }
+
+        removed
+    }
+
+    /// Return still-valid transactions from disconnected blocks to the pool.
+    ///
+    /// `disconnected` must be in chain order (oldest first) and the UTXO set
+    /// must already reflect the new tip. Coinbases, transactions confirmed on
+    /// the new branch and anything conflicting with it are dropped.
+    pub fn resurrect(&mut self, disconnected: &[Block], utxo_set: &UtxoSet, tip_height: u64) -> Vec<Hash256> {
+        let mut resurrected = Vec::new();
+
+        for block in disconnected {
+            for tx in block.transactions.iter().filter(|tx| !tx.is_coinbase()) {
+                let txid = tx.hash();
+                match self.add_transaction(tx.clone(), utxo_set, tip_height) {
+                    Ok(_) => resurrected.push(txid),
+                    Err(e) => log::debug!("🗑️ Not resurrecting {}: {}", txid, e),
+                }
+            }
+        }
+
+        if !resurrected.is_empty() {
+            log::info!("♻️ Returned {} transactions from disconnected blocks to the mempool", resurrected.len());
+        }
+        resurrected
+    }
+
+    pub fn contains(&self, txid: &Hash256) -> bool {
+        self.entries.contains_key(txid)
+    }
+
+    pub fn get(&self, txid: &Hash256) -> Option<&MempoolEntry> {
+        self.entries.get(txid)
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = &MempoolEntry> {
+        self.entries.values()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn total_size(&self) -> usize {
+        self.total_size
+    }
+
+    pub fn max_size(&self) -> usize {
+        self.max_size
+    }
+
+    pub fn min_fee(&self) -> u64 {
+        self.min_fee
+    }
+
+    /// Every entry in arrival order, moving parents ahead of their children
+    /// where they arrived in the same second
+    pub fn dump(&self) -> Vec<MempoolEntry> {
+        let mut by_time: Vec<&MempoolEntry> = self.entries.values().collect();
+        by_time.sort_by_key(|entry| entry.time);
+
+        let all: HashSet<Hash256> = self.entries.keys().copied().collect();
+        let mut visited = HashSet::new();
+        let mut ordered = Package::default();
+        for entry in by_time {
+            self.collect_ancestors(entry.txid, &|txid| all.contains(txid), &mut visited, &mut ordered);
+        }
+
+        ordered.txids.iter().filter_map(|txid| self.entries.get(txid)).cloned().collect()
+    }
+
+    /// Re-add dumped entries, keeping the time each first arrived. Entries are
+    /// validated again, so ones the current chain rejects are skipped.
+    pub fn restore(&mut self, entries: Vec<MempoolEntry>, utxo_set: &UtxoSet, tip_height: u64) -> Vec<Hash256> {
+        let mut restored = Vec::new();
+
+        for dumped in entries {
+            match self.add_transaction(dumped.tx, utxo_set, tip_height) {
+                Ok(txid) => {
+                    if let Some(entry) = self.entries.get_mut(&txid) {
+                        entry.time = dumped.time;
+                    }
+                    restored.push(txid);
+                }
+                Err(e) => log::debug!("🗑️ Not restoring {}: {}", dumped.txid, e),
+            }
+        }
+
+        restored
+    }
+
+    /// Fill up to `max_blocks` blocks of `max_block_size` bytes the way a block
+    /// template is built: highest ancestor-package fee rate first, so a
+    /// low-fee parent rides along with a child paying for it.
+    pub fn projected_blocks(&self, max_block_size: usize, max_blocks: usize) -> Vec<ProjectedBlock> {
+        let mut scores = self.ancestor_scores();
+        let mut blocks = Vec::new();
+
+        while blocks.len() < max_blocks && !scores.is_empty() {
+            let block = self.fill_block(&mut scores, max_block_size, 0);
+            if block.txids.is_empty() {
+                break; // whatever is left is larger than a block
+            }
+            blocks.push(block);
+        }
+
+        blocks
+    }
+
+    /// Transactions for the next block, parents before children, leaving out
+    /// packages paying less than `min_fee_rate` sat/byte
+    pub fn select_for_block(&self, max_block_size: usize, min_fee_rate: u64) -> Vec<&MempoolEntry> {
+        let mut scores = self.ancestor_scores();
+        self.fill_block(&mut scores, max_block_size, min_fee_rate)
+            .txids
+            .into_iter()
+            .filter_map(|txid| self.entries.get(&txid))
+            .collect()
+    }
+
+    /// Transaction count and bytes per fee-rate bucket, highest fee rate first
+    pub fn fee_histogram(&self) -> Vec<FeeRateBucket> {
+        let mut buckets: Vec<FeeRateBucket> = FEE_HISTOGRAM_BUCKETS.iter()
+            .rev()
+            .map(|&fee_rate| FeeRateBucket { fee_rate, count: 0, size: 0 })
+            .collect();
+
+        for entry in self.entries.values() {
+            // Anything under the lowest edge is counted in it
+            let index = buckets.iter()
+                .position(|bucket| entry.fee_rate >= bucket.fee_rate)
+                .unwrap_or(buckets.len() - 1);
+            buckets[index].count += 1;
+            buckets[index].size += entry.size;
+        }
+
+        buckets.retain(|bucket| bucket.count > 0);
+        buckets
+    }
+
+    /// (fee, size) of every entry's ancestor package, as tracked on the entries
+    fn ancestor_scores(&self) -> HashMap<Hash256, (u64, usize)> {
+        self.entries.values()
+            .map(|entry| (entry.txid, (entry.ancestor_fee, entry.ancestor_size)))
+            .collect()
+    }
+
+    /// Take packages best ancestor score first out of `scores`, which holds what
+    /// is left to place. Once a package is in, the descendants it leaves behind
+    /// are scored again without it.
+    fn fill_block(&self, scores: &mut HashMap<Hash256, (u64, usize)>, max_block_size: usize, min_fee_rate: u64) -> ProjectedBlock {
+        let mut block = ProjectedBlock::default();
+        let mut skipped = HashSet::new();
+
+        loop {
+            // Ties go to the lower txid so projections are stable between calls
+            let best = scores.iter()
+                .filter(|(txid, _)| !skipped.contains(*txid))
+                .max_by(|(a_txid, (a_fee, a_size)), (b_txid, (b_fee, b_size))| {
+                    (*a_fee as u128 * *b_size as u128)
+                        .cmp(&(*b_fee as u128 * *a_size as u128))
+                        .then_with(|| b_txid.as_bytes().cmp(a_txid.as_bytes()))
+                })
+                .map(|(txid, score)| (*txid, *score));
+
+            let Some((txid, (fee, size))) = best else {
+                break;
+            };
+            let fee_rate = fee / size.max(1) as u64;
+            // Packages come best first, so nothing left clears the floor either
+            if fee_rate < min_fee_rate {
+                break;
+            }
+            if block.size + size > max_block_size {
+                skipped.insert(txid);
+                continue;
+            }
+
+            let package = self.package(&txid, &|txid| scores.contains_key(txid));
+            block.min_fee_rate = if block.txids.is_empty() { fee_rate } else { block.min_fee_rate.min(fee_rate) };
+            block.max_fee_rate = block.max_fee_rate.max(fee_rate);
+            block.size += package.size;
+            block.total_fees += package.fee;
+            for txid in &package.txids {
+                scores.remove(txid);
+            }
+            for descendant in self.descendants(&package.txids) {
+                if scores.contains_key(&descendant) {
+                    let package = self.package(&descendant, &|txid| scores.contains_key(txid));
+                    scores.insert(descendant, (package.fee, package.size));
+                }
+            }
+            block.txids.extend(package.txids);
+        }
+
+        block
+    }
+
+    /// Recount the ancestor and descendant packages tracked on `txid`'s entry
+    fn refresh_packages(&mut self, txid: &Hash256) {
+        let package = self.package(txid, &|txid| self.entries.contains_key(txid));
+        let descendants = self.descendants(&[*txid]);
+        let descendant_size = descendants.iter()
+            .filter_map(|descendant| self.entries.get(descendant))
+            .map(|entry| entry.size)
+            .sum();
+        if let Some(entry) = self.entries.get_mut(txid) {
+            entry.ancestor_count = package.txids.len();
+            entry.ancestor_size = package.size;
+            entry.ancestor_fee = package.fee;
+            entry.descendant_count = descendants.len();
+            entry.descendant_size = descendant_size;
+        }
+    }
+
+    /// `txid` and its ancestors among the transactions `remaining` accepts
+    fn package(&self, txid: &Hash256, remaining: &dyn Fn(&Hash256) -> bool) -> Package {
+        let mut package = Package::default();
+        let mut visited = HashSet::new();
+        self.collect_ancestors(*txid, remaining, &mut visited, &mut package);
+        package
+    }
+
+    fn collect_ancestors(&self, txid: Hash256, remaining: &dyn Fn(&Hash256) -> bool, visited: &mut HashSet<Hash256>, package: &mut Package) {
+        if !visited.insert(txid) {
+            return;
+        }
+        let Some(entry) = self.entries.get(&txid) else {
+            return;
+        };
+
+        for input in &entry.tx.inputs {
+            if remaining(&input.previous_output.txid) {
+                self.collect_ancestors(input.previous_output.txid, remaining, visited, package);
+            }
+        }
+
+        package.txids.push(txid);
+        package.fee += entry.fee;
+        package.size += entry.size;
+    }
+
+    /// `roots` and every pooled transaction spending their outputs, directly or not
+    fn descendants(&self, roots: &[Hash256]) -> HashSet<Hash256> {
+        let mut found = HashSet::new();
+        let mut pending = roots.to_vec();
+
+        while let Some(current) = pending.pop() {
+            if !found.insert(current) {
+                continue;
+            }
+            if let Some(entry) = self.entries.get(&current) {
+                for vout in 0..entry.tx.outputs.len() {
+                    if let Some(child) = self.spends.get(&OutPoint::new(current, vout as u32)) {
+                        pending.push(*child);
+                    }
+                }
+            }
+        }
+
+        found
+    }
+
+    /// The scripts of the outputs `tx` spends, confirmed or from pooled parents, in input order
+    pub fn spent_scripts(&self, tx: &Transaction, utxo_set: &UtxoSet) -> Result<Vec<Vec<u8>>> {
+        tx.inputs.iter().map(|input| {
+            let outpoint = &input.previous_output;
+            match utxo_set.get_utxo(outpoint)? {
+                Some(utxo) => Ok(utxo.script_pubkey),
+                None => self.unconfirmed_output(outpoint)
+                    .map(|output| output.script_pubkey.clone())
+                    .ok_or_else(|| QtcError::Transaction(format!(
+                        "Referenced UTXO not found: {}:{}", outpoint.txid, outpoint.vout
+                    ))),
+            }
+        }).collect()
+    }
+
+    fn unconfirmed_output(&self, outpoint: &OutPoint) -> Option<&TxOutput> {
+        self.entries.get(&outpoint.txid)
+            .and_then(|entry| entry.tx.outputs.get(outpoint.vout as usize))
+    }
+
+    fn remove_entry(&mut self, txid: &Hash256) -> Option<MempoolEntry> {
+        // Its ancestors have one descendant fewer, its descendants one ancestor
+        let mut ancestors = self.package(txid, &|txid| self.entries.contains_key(txid)).txids;
+        ancestors.pop(); // itself, last
+        let mut descendants = self.descendants(&[*txid]);
+        descendants.remove(txid);
+
+        let entry = self.entries.remove(txid)?;
+        for input in &entry.tx.inputs {
+            if self.spends.get(&input.previous_output) == Some(txid) {
+                self.spends.remove(&input.previous_output);
+            }
+        }
+        self.total_size = self.total_size.saturating_sub(entry.size);
+
+        for ancestor in &ancestors {
+            if let Some(ancestor) = self.entries.get_mut(ancestor) {
+                ancestor.descendant_count = ancestor.descendant_count.saturating_sub(1);
+                ancestor.descendant_size = ancestor.descendant_size.saturating_sub(entry.size);
+            }
+        }
+        for descendant in &descendants {
+            if let Some(descendant) = self.entries.get_mut(descendant) {
+                descendant.ancestor_count = descendant.ancestor_count.saturating_sub(1);
+                descendant.ancestor_size = descendant.ancestor_size.saturating_sub(entry.size);
+                descendant.ancestor_fee = descendant.ancestor_fee.saturating_sub(entry.fee);
+            }
+        }
+        self.publish_metrics();
+        Some(entry)
+    }
+
+    fn publish_metrics(&self) {
+        metrics().mempool_transactions.set(self.entries.len() as f64);
+        metrics().mempool_bytes.set(self.total_size as f64);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::transaction::{SEQUENCE_LOCKTIME, SEQUENCE_REPLACEABLE};
+    use crate::storage::Database;
+    use std::sync::Arc;
+    use tempfile::TempDir;
+
+    fn coinbase(message: &str) -> Transaction {
+        Transaction::new_coinbase("qtc1miner".to_string(), 10_000_000, message.to_string())
+    }
+
+    fn spend(outpoint: OutPoint, value: u64, to: &str) -> Transaction {
+        let mut tx = Transaction::new();
+        tx.add_input(outpoint, vec![]);
+        tx.add_output(value, to);
+        tx
+    }
+
+    #[test]
+    fn test_remove_for_block_drops_conflicts() -> Result<()> {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Arc::new(Database::new(temp_dir.path().join("test.db"))?);
+        let mut utxo_set = UtxoSet::new(db);
+
+        let funding = Block::new(Hash256::zero(), vec![coinbase("fund")], 6, 0);
+        utxo_set.apply_block(&funding)?;
+        let fund_out = OutPoint::new(funding.transactions[0].hash(), 0);
+
+        let mut mempool = Mempool::new();
+        let parent = spend(fund_out.clone(), 9_990_000, "qtc1alice");
+        let child = spend(OutPoint::new(parent.hash(), 0), 9_980_000, "qtc1bob");
+        mempool.add_transaction(parent, &utxo_set, 200)?;
+        mempool.add_transaction(child, &utxo_set, 200)?;
+        assert_eq!(mempool.len(), 2);
+
+        // A block spending the same funding output evicts the parent and its child
+        let conflicting = spend(fund_out, 9_900_000, "qtc1carol");
+        let block = Block::new(funding.hash(), vec![coinbase("b1"), conflicting], 6, 201);
+        mempool.remove_for_block(&block);
+
+        assert!(mempool.is_empty());
+        assert_eq!(mempool.total_size(), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_coinbase_spends_wait_for_maturity() -> Result<()> {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Arc::new(Database::new(temp_dir.path().join("test.db"))?);
+        let mut utxo_set = UtxoSet::new(db);
+
+        let funding = Block::new(Hash256::zero(), vec![coinbase("fund")], 6, 0);
+        utxo_set.apply_block(&funding)?;
+        let tx = spend(OutPoint::new(funding.transactions[0].hash(), 0), 9_990_000, "qtc1alice");
+
+        // Consensus takes the spend in block 100 at the earliest, so the pool waits for tip 100
+        let mut mempool = Mempool::new();
+        assert!(mempool.add_transaction(tx.clone(), &utxo_set, 99).is_err());
+        mempool.add_transaction(tx, &utxo_set, 100)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_data_outputs_follow_relay_limits() -> Result<()> {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Arc::new(Database::new(temp_dir.path().join("test.db"))?);
+        let mut utxo_set = UtxoSet::new(db);
+
+        let funding = Block::new(Hash256::zero(), vec![coinbase("fund")], 6, 0);
+        utxo_set.apply_block(&funding)?;
+        let fund_out = OutPoint::new(funding.transactions[0].hash(), 0);
+        let mut mempool = Mempool::new();
+
+        let mut oversized = spend(fund_out.clone(), 9_990_000, "qtc1alice");
+        oversized.outputs.push(TxOutput::data(&[7; MAX_DATA_CARRIER_SIZE + 1])?);
+        assert!(mempool.add_transaction(oversized, &utxo_set, 200).is_err());
+
+        let mut two = spend(fund_out.clone(), 9_990_000, "qtc1alice");
+        two.outputs.push(TxOutput::data(b"one")?);
+        two.outputs.push(TxOutput::data(b"two")?);
+        assert!(mempool.add_transaction(two, &utxo_set, 200).is_err());
+
+        // Burning a whole coin as fee is refused, as it would be in a block
+        let mut burn = Transaction::new();
+        burn.add_input(fund_out.clone(), vec![]);
+        burn.outputs.push(TxOutput::data(b"burn")?);
+        assert!(mempool.add_transaction(burn, &utxo_set, 200).is_err());
+
+        // One zero-value data output is fine, but nothing can spend it
+        let mut tx = spend(fund_out, 9_990_000, "qtc1alice");
+        tx.outputs.push(TxOutput::data(&[7; MAX_DATA_CARRIER_SIZE])?);
+        let txid = mempool.add_transaction(tx.clone(), &utxo_set, 200)?;
+        let child = spend(OutPoint::new(txid, 1), 0, "qtc1bob");
+        assert!(mempool.add_transaction(child, &utxo_set, 200).is_err());
+
+        // Nor does it enter the UTXO set once mined
+        let block = Block::new(funding.hash(), vec![coinbase("b1"), tx], 6, 201);
+        utxo_set.apply_block(&block)?;
+        assert!(utxo_set.has_utxo(&OutPoint::new(txid, 0))?);
+        assert!(!utxo_set.has_utxo(&OutPoint::new(txid, 1))?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_replace_by_fee() -> Result<()> {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Arc::new(Database::new(temp_dir.path().join("test.db"))?);
+        let mut utxo_set = UtxoSet::new(db);
+
+        let funding = Block::new(Hash256::zero(), vec![coinbase("fund")], 6, 0);
+        utxo_set.apply_block(&funding)?;
+        let fund_out = OutPoint::new(funding.transactions[0].hash(), 0);
+
+        let mut mempool = Mempool::new();
+        let final_tx = spend(fund_out.clone(), 9_990_000, "qtc1alice");
+        mempool.add_transaction(final_tx.clone(), &utxo_set, 200)?;
+        let bump = spend(fund_out.clone(), 9_950_000, "qtc1alice");
+        assert!(matches!(mempool.add_transaction(bump, &utxo_set, 200), Err(QtcError::DoubleSpend(_))));
+        mempool.remove_with_descendants(&final_tx.hash());
+
+        let mut original = spend(fund_out.clone(), 9_990_000, "qtc1alice");
+        original.inputs[0].sequence = SEQUENCE_REPLACEABLE;
+        let child = spend(OutPoint::new(original.hash(), 0), 9_980_000, "qtc1bob");
+        mempool.add_transaction(original.clone(), &utxo_set, 200)?;
+        mempool.add_transaction(child.clone(), &utxo_set, 200)?;
+
+        // A higher fee rate alone is not enough, the evicted child's fee must be covered too
+        let mut too_cheap = spend(fund_out.clone(), 9_980_000, "qtc1alice");
+        too_cheap.inputs[0].sequence = SEQUENCE_REPLACEABLE;
+        assert!(mempool.add_transaction(too_cheap, &utxo_set, 200).is_err());
+        assert_eq!(mempool.len(), 2);
+
+        let replacement = spend(fund_out, 9_960_000, "qtc1alice");
+        let (txid, evicted) = mempool.add_with_replacement(replacement.clone(), &utxo_set, 200)?;
+        assert_eq!(txid, replacement.hash());
+        assert_eq!(evicted.len(), 2);
+        assert!(evicted.contains(&original.hash()) && evicted.contains(&child.hash()));
+        assert_eq!(mempool.len(), 1);
+        assert_eq!(mempool.total_size(), mempool.get(&txid).unwrap().size);
+        Ok(())
+    }
+
+    #[test]
+    fn test_non_final_transactions_wait_for_their_lock_time() -> Result<()> {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Arc::new(Database::new(temp_dir.path().join("test.db"))?);
+        let mut utxo_set = UtxoSet::new(db);
+
+        let funding = Block::new(Hash256::zero(), vec![coinbase("fund")], 6, 0);
+        utxo_set.apply_block(&funding)?;
+        let fund_out = OutPoint::new(funding.transactions[0].hash(), 0);
+
+        let mut mempool = Mempool::new();
+        let mut locked = spend(fund_out.clone(), 9_990_000, "qtc1alice");
+        locked.lock_time = 205;
+        locked.inputs[0].sequence = SEQUENCE_LOCKTIME;
+        assert!(mempool.add_transaction(locked.clone(), &utxo_set, 200).is_err());
+        mempool.add_transaction(locked, &utxo_set, 204)?;
+
+        // Final sequences switch the lock time off, a future timestamp holds it back
+        let mut unlocked = spend(fund_out.clone(), 9_990_000, "qtc1bob");
+        unlocked.lock_time = 1_000;
+        assert!(unlocked.is_final(200, 0));
+        let mut timed = spend(fund_out, 9_990_000, "qtc1carol");
+        timed.lock_time = u32::MAX as u64;
+        timed.inputs[0].sequence = SEQUENCE_LOCKTIME;
+        assert!(!timed.is_final(1_000_000, chrono::Utc::now().timestamp() as u64));
+        Ok(())
+    }
+
+    #[test]
+    fn test_two_block_reorg_resurrects_valid_transactions() -> Result<()> {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Arc::new(Database::new(temp_dir.path().join("test.db"))?);
+        let mut utxo_set = UtxoSet::new(db);
+
+        let funding = Block::new(Hash256::zero(), vec![coinbase("fund a"), coinbase("fund b")], 6, 0);
+        utxo_set.apply_block(&funding)?;
+        let fund_a = OutPoint::new(funding.transactions[0].hash(), 0);
+        let fund_b = OutPoint::new(funding.transactions[1].hash(), 0);
+
+        // Original branch: tx1 and tx3 in block 1, tx2 (child of tx1) in block 2
+        let tx1 = spend(fund_a, 9_990_000, "qtc1alice");
+        let tx2 = spend(OutPoint::new(tx1.hash(), 0), 9_980_000, "qtc1bob");
+        let tx3 = spend(fund_b.clone(), 9_990_000, "qtc1carol");
+        let block1 = Block::new(funding.hash(), vec![coinbase("b1"), tx1.clone(), tx3.clone()], 6, 101);
+        let block2 = Block::new(block1.hash(), vec![coinbase("b2"), tx2.clone()], 6, 102);
+        utxo_set.apply_block(&block1)?;
+        utxo_set.apply_block(&block2)?;
+
+        // Reorg: disconnect both blocks, then connect a branch that double spends tx3's input
+        utxo_set.disconnect_block(&block2)?;
+        utxo_set.disconnect_block(&block1)?;
+        assert!(utxo_set.has_utxo(&fund_b)?);
+
+        let double_spend = spend(fund_b, 9_900_000, "qtc1mallory");
+        let alt1 = Block::new(funding.hash(), vec![coinbase("alt1"), double_spend], 6, 101);
+        let alt2 = Block::new(alt1.hash(), vec![coinbase("alt2")], 6, 102);
+        utxo_set.apply_block(&alt1)?;
+        utxo_set.apply_block(&alt2)?;
+
+        let mut mempool = Mempool::new();
+        let resurrected = mempool.resurrect(&[block1, block2], &utxo_set, 102);
+
+        assert_eq!(resurrected, vec![tx1.hash(), tx2.hash()]);
+        assert!(mempool.contains(&tx1.hash()));
+        assert!(mempool.contains(&tx2.hash()));
+        assert!(!mempool.contains(&tx3.hash()));
+        assert_eq!(mempool.get(&tx1.hash()).unwrap().fee, 10_000);
+        Ok(())
+    }
+
+    #[test]
+    fn test_projected_blocks_use_package_fee_rate() -> Result<()> {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Arc::new(Database::new(temp_dir.path().join("test.db"))?);
+        let mut utxo_set = UtxoSet::new(db);
+
+        let funding = Block::new(Hash256::zero(), vec![coinbase("fund a"), coinbase("fund b")], 6, 0);
+        utxo_set.apply_block(&funding)?;
+
+        // A minimum-fee parent whose child pays well beats a mid-fee stranger
+        let parent = spend(OutPoint::new(funding.transactions[0].hash(), 0), 9_999_000, "qtc1alice");
+        let child = spend(OutPoint::new(parent.hash(), 0), 9_799_000, "qtc1bob");
+        let other = spend(OutPoint::new(funding.transactions[1].hash(), 0), 9_950_000, "qtc1carol");
+
+        let mut mempool = Mempool::new();
+        let parent_id = mempool.add_transaction(parent, &utxo_set, 200)?;
+        let child_id = mempool.add_transaction(child, &utxo_set, 200)?;
+        let other_id = mempool.add_transaction(other, &utxo_set, 200)?;
+
+        let package_size = mempool.get(&parent_id).unwrap().size + mempool.get(&child_id).unwrap().size;
+        let tracked = mempool.get(&child_id).unwrap();
+        assert_eq!((tracked.ancestor_count, tracked.ancestor_size, tracked.ancestor_fee), (2, package_size, 201_000));
+        let blocks = mempool.projected_blocks(package_size, 5);
+
+        assert_eq!(blocks.len(), 2);
+        assert_eq!(blocks[0].txids, vec![parent_id, child_id]);
+        assert_eq!(blocks[0].total_fees, 201_000);
+        assert_eq!(blocks[1].txids, vec![other_id]);
+        assert_eq!(mempool.select_for_block(package_size, 0).len(), 2);
+        let floor = mempool.get(&other_id).unwrap().fee_rate + 1;
+        assert_eq!(mempool.select_for_block(package_size * 2, floor).len(), 2);
+
+        let histogram = mempool.fee_histogram();
+        assert_eq!(histogram.iter().map(|bucket| bucket.count).sum::<usize>(), 3);
+        assert!(histogram.windows(2).all(|pair| pair[0].fee_rate > pair[1].fee_rate));
+
+        // Once the parent confirms, the child stands on its own
+        let block = Block::new(funding.hash(), vec![coinbase("b1"), mempool.get(&parent_id).unwrap().tx.clone()], 6, 201);
+        mempool.remove_for_block(&block);
+        let child = mempool.get(&child_id).unwrap();
+        assert_eq!((child.ancestor_count, child.ancestor_fee), (1, child.fee));
+        Ok(())
+    }
+
+    #[test]
+    fn test_chain_limits() -> Result<()> {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Arc::new(Database::new(temp_dir.path().join("test.db"))?);
+        let mut utxo_set = UtxoSet::new(db);
+
+        let funding = Block::new(Hash256::zero(), vec![coinbase("chain"), coinbase("fan"), coinbase("heavy")], 6, 0);
+        utxo_set.apply_block(&funding)?;
+        let mut mempool = Mempool::new();
+
+        // A chain of 25 fits; a 26th link waits for part of it to confirm
+        let mut outpoint = OutPoint::new(funding.transactions[0].hash(), 0);
+        let mut value = 10_000_000;
+        let mut chain = Vec::new();
+        for _ in 0..MAX_ANCESTOR_COUNT {
+            value -= 2_000;
+            let tx = spend(outpoint, value, "qtc1chain");
+            outpoint = OutPoint::new(tx.hash(), 0);
+            chain.push(mempool.add_transaction(tx, &utxo_set, 200)?);
+        }
+        assert_eq!(mempool.get(&chain[24]).unwrap().ancestor_count, 25);
+        assert_eq!(mempool.get(&chain[0]).unwrap().descendant_count, 25);
+        let err = mempool.add_transaction(spend(outpoint, value - 2_000, "qtc1chain"), &utxo_set, 200).unwrap_err();
+        assert!(err.to_string().contains("unconfirmed ancestors"));
+
+        // Dropping its end is counted down on what's left
+        mempool.remove_with_descendants(&chain[20]);
+        let root = mempool.get(&chain[0]).unwrap();
+        let remaining: usize = chain[..20].iter().map(|txid| mempool.get(txid).unwrap().size).sum();
+        assert_eq!((root.descendant_count, root.descendant_size), (20, remaining));
+        assert_eq!(mempool.get(&chain[19]).unwrap().ancestor_count, 20);
+
+        // One parent takes at most 24 children
+        let mut fan = Transaction::new();
+        fan.add_input(OutPoint::new(funding.transactions[1].hash(), 0), vec![]);
+        for _ in 0..MAX_DESCENDANT_COUNT {
+            fan.add_output(300_000, "qtc1fan");
+        }
+        let fan_id = mempool.add_transaction(fan, &utxo_set, 200)?;
+        for vout in 0..MAX_DESCENDANT_COUNT as u32 - 1 {
+            mempool.add_transaction(spend(OutPoint::new(fan_id, vout), 298_000, "qtc1leaf"), &utxo_set, 200)?;
+        }
+        let last = spend(OutPoint::new(fan_id, MAX_DESCENDANT_COUNT as u32 - 1), 298_000, "qtc1leaf");
+        let err = mempool.add_transaction(last, &utxo_set, 200).unwrap_err();
+        assert!(err.to_string().contains("already has 24 descendants"));
+
+        // And a package may weigh no more than the limit
+        let mut heavy = Transaction::new();
+        heavy.add_input(OutPoint::new(funding.transactions[2].hash(), 0), vec![1; 60_000]);
+        heavy.add_output(9_990_000, "qtc1heavy");
+        let heavy_id = mempool.add_transaction(heavy, &utxo_set, 200)?;
+        let mut child = Transaction::new();
+        child.add_input(OutPoint::new(heavy_id, 0), vec![1; 45_000]);
+        child.add_output(9_980_000, "qtc1heavy");
+        let err = mempool.add_transaction(child, &utxo_set, 200).unwrap_err();
+        assert!(err.to_string().contains("at most 101000 allowed"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_pqc_signatures_weighed_by_chain_rule() -> Result<()> {
+        use crate::core::transaction::pqc_signature_script;
+        use crate::crypto::pqc::PqcSignature;
+
+        let temp_dir = TempDir::new().unwrap();
+        let db = Arc::new(Database::new(temp_dir.path().join("test.db"))?);
+        let mut utxo_set = UtxoSet::new(db);
+
+        let funding = Block::new(Hash256::zero(), vec![coinbase("fund a"), coinbase("fund b")], 6, 0);
+        utxo_set.apply_block(&funding)?;
+
+        // Same fee, but one spend carries a Dilithium3-sized signature script
+        let mut pqc = spend(OutPoint::new(funding.transactions[0].hash(), 0), 9_990_000, "qtc1alice");
+        pqc.inputs[0].signature_script = pqc_signature_script(&PqcSignature {
+            signature: vec![7; 3_309],
+            public_key: vec![9; 1_952],
+        }, &[5; 1_184]);
+        let classic = spend(OutPoint::new(funding.transactions[1].hash(), 0), 9_990_000, "qtc1bob");
+        let witness = pqc.pqc_witness_size();
+        assert_eq!(witness, 1 + 2 + 3_309 + 2 + 1_952 + 2 + 1_184);
+        assert_eq!(classic.pqc_witness_size(), 0);
+        assert_eq!(pqc.weight(25), pqc.size() - witness + witness.div_ceil(4));
+
+        let mut mempool = Mempool::new();
+        mempool.set_pqc_witness_percent(25);
+        let pqc_id = mempool.add_transaction(pqc.clone(), &utxo_set, 200)?;
+        let classic_id = mempool.add_transaction(classic, &utxo_set, 200)?;
+        assert_eq!(mempool.get(&pqc_id).unwrap().size, pqc.weight(25));
+
+        // A surcharge re-ranks what's already pooled, below the classic spend
+        mempool.set_pqc_witness_percent(200);
+        let entry = mempool.get(&pqc_id).unwrap();
+        assert_eq!(entry.size, pqc.weight(200));
+        assert_eq!(entry.fee_rate, 10_000 / pqc.weight(200) as u64);
+        assert_eq!(mempool.total_size(), pqc.weight(200) + mempool.get(&classic_id).unwrap().size);
+        let txids: Vec<_> = mempool.select_for_block(usize::MAX, 0).iter().map(|entry| entry.txid).collect();
+        assert_eq!(txids, vec![classic_id, pqc_id]);
+
+        let block = Block::new(funding.hash(), vec![coinbase("b1"), pqc], 6, 201);
+        assert_eq!(block.size() - block.weight(25), witness - witness.div_ceil(4));
+        Ok(())
+    }
+
+    #[test]
+    fn test_dump_and_restore_keep_order_and_times() -> Result<()> {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Arc::new(Database::new(temp_dir.path().join("test.db"))?);
+        let mut utxo_set = UtxoSet::new(db);
+
+        let funding = Block::new(Hash256::zero(), vec![coinbase("fund")], 6, 0);
+        utxo_set.apply_block(&funding)?;
+
+        let mut mempool = Mempool::new();
+        let parent = spend(OutPoint::new(funding.transactions[0].hash(), 0), 9_990_000, "qtc1alice");
+        let child = spend(OutPoint::new(parent.hash(), 0), 9_980_000, "qtc1bob");
+        let parent_id = mempool.add_transaction(parent, &utxo_set, 200)?;
+        let child_id = mempool.add_transaction(child, &utxo_set, 200)?;
+
+        // Even when the child is stamped earlier, the dump lists its parent first
+        mempool.entries.get_mut(&parent_id).unwrap().time = 1_700_000_100;
+        mempool.entries.get_mut(&child_id).unwrap().time = 1_700_000_000;
+        let dump = mempool.dump();
+        assert_eq!(dump.iter().map(|entry| entry.txid).collect::<Vec<_>>(), vec![parent_id, child_id]);
+
+        let mut restored = Mempool::new();
+        assert_eq!(restored.restore(dump.clone(), &utxo_set, 200), vec![parent_id, child_id]);
+        assert_eq!(restored.get(&child_id).unwrap().time, 1_700_000_000);
+        assert_eq!(restored.get(&parent_id).unwrap().fee, 10_000);
+
+        // Already present, so nothing is restored twice
+        assert!(restored.restore(dump, &utxo_set, 200).is_empty());
+        Ok(())
+    }
+}
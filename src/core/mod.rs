@@ -2,10 +2,24 @@
 
 pub mod blockchain;
 pub mod block;
+pub mod fee_estimator;
+pub mod filter;
+pub mod genesis;
+pub mod mempool;
+pub mod scan;
+pub mod snapshot;
 pub mod transaction;
 pub mod utxo;
+pub mod utxo_cache;
 
-pub use blockchain::Blockchain;
+pub use blockchain::{Blockchain, ChainEvent};
 pub use block::{Block, BlockHeader};
-pub use transaction::{Transaction, TxInput, TxOutput};
+pub use fee_estimator::{FeeEstimate, FeeEstimator};
+pub use filter::BlockFilter;
+pub use genesis::{GenesisParams, PremineOutput};
+pub use mempool::{Mempool, MempoolDump, MempoolEntry};
+pub use scan::{ScanDescriptor, ScanResult};
+pub use snapshot::{with_snapshot, ChainSnapshot};
+pub use transaction::{SigHashType, Transaction, TxInput, TxOutput};
 pub use utxo::{UtxoSet, UtxoEntry};
+pub use utxo_cache::UtxoCache;
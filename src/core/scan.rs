@@ -0,0 +1,167 @@
+//! Walletless UTXO set scans (`scantxoutset`) over output descriptors
+
+use crate::core::transaction::{OutPoint, Transaction};
+use crate::core::utxo::UtxoEntry;
+use crate::crypto::keys::{is_valid_address, PublicKey};
+use crate::{QtcError, Result};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+/// Which outputs a scan should match
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScanDescriptor {
+    /// `addr(<address>)` or a bare address
+    Address(String),
+    /// `pkh(<hex public key>)`
+    PublicKeyHash(String),
+    /// `raw(<hex script>)`
+    Raw(Vec<u8>),
+}
+
+impl ScanDescriptor {
+    /// The scriptPubKey an output must carry to match this descriptor
+    pub fn script_pubkey(&self) -> Result<Vec<u8>> {
+        match self {
+            ScanDescriptor::Address(address) => Ok(Transaction::address_to_script_pubkey(address)),
+            ScanDescriptor::PublicKeyHash(pubkey_hex) => {
+                let bytes = hex::decode(pubkey_hex)
+                    .map_err(|_| QtcError::InvalidInput(format!("Invalid public key hex: {}", pubkey_hex)))?;
+                let address = PublicKey::from_bytes(&bytes)?.to_address();
+                Ok(Transaction::address_to_script_pubkey(&address))
+            }
+            ScanDescriptor::Raw(script) => Ok(script.clone()),
+        }
+    }
+}
+
+impl FromStr for ScanDescriptor {
+    type Err = QtcError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+        let inner = |prefix: &str| s.strip_prefix(prefix).and_then(|rest| rest.strip_suffix(')'));
+
+        if let Some(address) = inner("addr(") {
+            if !is_valid_address(address) {
+                return Err(QtcError::InvalidInput(format!("Invalid address in descriptor: {}", address)));
+            }
+            Ok(ScanDescriptor::Address(address.to_string()))
+        } else if let Some(pubkey) = inner("pkh(") {
+            Ok(ScanDescriptor::PublicKeyHash(pubkey.to_string()))
+        } else if let Some(script) = inner("raw(") {
+            hex::decode(script)
+                .map(ScanDescriptor::Raw)
+                .map_err(|_| QtcError::InvalidInput(format!("Invalid script hex: {}", script)))
+        } else if is_valid_address(s) {
+            Ok(ScanDescriptor::Address(s.to_string()))
+        } else {
+            Err(QtcError::InvalidInput(format!(
+                "Unsupported descriptor '{}' (expected addr(), pkh(), raw() or an address)", s
+            )))
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScanMatch {
+    pub txid: String,
+    pub vout: u32,
+    pub descriptor: String,
+    pub address: String,
+    pub amount: u64,
+    pub height: u64,
+    pub is_coinbase: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScanResult {
+    pub height: u64,
+    pub searched_items: usize,
+    pub unspents: Vec<ScanMatch>,
+    pub total_amount: u64,
+}
+
+/// Match `utxos` against the descriptors (given as entered) in a single pass
+pub fn scan_utxos(
+    utxos: impl IntoIterator<Item = (OutPoint, UtxoEntry)>,
+    descriptors: &[(String, ScanDescriptor)],
+    height: u64,
+) -> Result<ScanResult> {
+    let scripts = descriptors.iter()
+        .map(|(text, descriptor)| Ok((text.as_str(), descriptor.script_pubkey()?)))
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut searched_items = 0;
+    let mut unspents = Vec::new();
+
+    for (outpoint, utxo) in utxos {
+        searched_items += 1;
+        if let Some((text, _)) = scripts.iter().find(|(_, script)| *script == utxo.script_pubkey) {
+            unspents.push(ScanMatch {
+                txid: outpoint.txid.to_hex(),
+                vout: outpoint.vout,
+                descriptor: text.to_string(),
+                address: utxo.address,
+                amount: utxo.value,
+                height: utxo.height,
+                is_coinbase: utxo.is_coinbase,
+            });
+        }
+    }
+
+    unspents.sort_by(|a, b| b.height.cmp(&a.height).then(a.txid.cmp(&b.txid)));
+    let total_amount = unspents.iter().map(|u| u.amount).sum();
+
+    Ok(ScanResult {
+        height,
+        searched_items,
+        unspents,
+        total_amount,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::utxo::UtxoSet;
+    use crate::core::Block;
+    use crate::crypto::hash::{Hash256, Hashable};
+    use crate::crypto::keys::KeyPair;
+    use crate::storage::Database;
+    use std::sync::Arc;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_scan_matches_descriptors() -> Result<()> {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Arc::new(Database::new(temp_dir.path().join("test.db"))?);
+        let mut utxo_set = UtxoSet::new(db.clone());
+
+        let cold = KeyPair::new()?;
+        let other = KeyPair::new()?;
+        let block = Block::new(Hash256::zero(), vec![
+            Transaction::new_coinbase(cold.address(), 5_000, "cold 1".to_string()),
+            Transaction::new_coinbase(cold.address(), 7_000, "cold 2".to_string()),
+            Transaction::new_coinbase(other.address(), 9_000, "other".to_string()),
+        ], 6, 0);
+        utxo_set.apply_block(&block)?;
+
+        let descriptors = vec![(
+            format!("pkh({})", hex::encode(cold.public_key.to_bytes())),
+            format!("pkh({})", hex::encode(cold.public_key.to_bytes())).parse::<ScanDescriptor>()?,
+        )];
+        let result = scan_utxos(db.get_all_utxos()?, &descriptors, 0)?;
+
+        assert_eq!(result.searched_items, 3);
+        assert_eq!(result.unspents.len(), 2);
+        assert_eq!(result.total_amount, 12_000);
+
+        let by_address: ScanDescriptor = format!("addr({})", other.address()).parse()?;
+        let result = scan_utxos(db.get_all_utxos()?, &[("other".to_string(), by_address)], 0)?;
+        assert_eq!(result.total_amount, 9_000);
+        assert_eq!(result.unspents[0].txid, block.transactions[2].hash().to_hex());
+
+        assert!("wpkh(abc)".parse::<ScanDescriptor>().is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,129 @@
+//! Point-in-time views of the chainstate for readers outside the chain lock
+
+use crate::core::blockchain::TxOutStatus;
+use crate::core::transaction::OutPoint;
+use crate::core::{Block, UtxoSet};
+use crate::crypto::hash::Hash256;
+use crate::storage::Database;
+use crate::{QtcError, Result};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+
+/// Attempts a composite read gets before giving up on a busy chain
+const SNAPSHOT_RETRIES: usize = 3;
+
+/// Chainstate pinned at one tip. Every read checks that no block was connected or
+/// disconnected since the snapshot was taken and fails with `StaleSnapshot` if one
+/// was, so a caller never mixes data from two different tips.
+#[derive(Debug, Clone)]
+pub struct ChainSnapshot {
+    pub height: u64,
+    pub tip: Hash256,
+    pub total_work: u128,
+    minimum_chain_work: u128,
+    version: u64,
+    chain_version: Arc<AtomicU64>,
+    db: Arc<Database>,
+    utxo_set: Arc<RwLock<UtxoSet>>,
+}
+
+impl ChainSnapshot {
+    pub(crate) fn new(
+        height: u64,
+        tip: Hash256,
+        total_work: u128,
+        minimum_chain_work: u128,
+        chain_version: Arc<AtomicU64>,
+        db: Arc<Database>,
+        utxo_set: Arc<RwLock<UtxoSet>>,
+    ) -> Self {
+        Self {
+            height,
+            tip,
+            total_work,
+            minimum_chain_work,
+            version: chain_version.load(Ordering::SeqCst),
+            chain_version,
+            db,
+            utxo_set,
+        }
+    }
+
+    /// False once the chain has moved past this snapshot
+    pub fn is_current(&self) -> bool {
+        self.chain_version.load(Ordering::SeqCst) == self.version
+    }
+
+    /// Hand back `value` only if it was read before the chain moved
+    fn checked<T>(&self, value: T) -> Result<T> {
+        if self.is_current() {
+            Ok(value)
+        } else {
+            Err(QtcError::StaleSnapshot)
+        }
+    }
+
+    pub fn get_block(&self, hash: &Hash256) -> Result<Option<Block>> {
+        let block = self.db.get_block(hash)?;
+        self.checked(block)
+    }
+
+    /// Blocks above the pinned tip don't exist as far as this snapshot is concerned
+    pub fn get_block_by_height(&self, height: u64) -> Result<Option<Block>> {
+        if height > self.height {
+            return self.checked(None);
+        }
+        let block = self.db.get_block_by_height(height)?;
+        self.checked(block)
+    }
+
+    /// Balances at a tip below the minimum chain work may come from a fake chain
+    fn synced(&self) -> Result<()> {
+        if self.total_work < self.minimum_chain_work {
+            return Err(QtcError::LowWork { total_work: self.total_work, minimum: self.minimum_chain_work });
+        }
+        Ok(())
+    }
+
+    pub fn get_balance(&self, address: &str) -> Result<u64> {
+        self.synced()?;
+        let balance = self.utxo_set.read().unwrap().get_balance(address)?;
+        self.checked(balance)
+    }
+
+    pub fn get_utxos(&self, address: &str) -> Result<Vec<(Hash256, u32, u64)>> {
+        self.synced()?;
+        let utxos = self.utxo_set.read().unwrap().get_utxos(address)?;
+        self.checked(utxos)
+    }
+
+    pub fn get_txout(&self, outpoint: &OutPoint) -> Result<TxOutStatus> {
+        let utxo = self.utxo_set.read().unwrap().get_utxo(outpoint)?;
+        let status = match utxo {
+            Some(utxo) => TxOutStatus::Unspent(utxo),
+            None => match self.db.get_spent_output(outpoint)? {
+                Some(spent) => TxOutStatus::Spent(spent),
+                None => TxOutStatus::Unknown,
+            },
+        };
+        self.checked(status)
+    }
+}
+
+/// Run `read` against a fresh snapshot, starting over if a block lands mid-read
+pub fn with_snapshot<T>(
+    blockchain: &RwLock<crate::core::Blockchain>,
+    read: impl Fn(&ChainSnapshot) -> Result<T>,
+) -> Result<T> {
+    let mut attempts = 0;
+    loop {
+        let snapshot = blockchain.read()
+            .map_err(|_| QtcError::Blockchain("Blockchain lock poisoned".to_string()))?
+            .snapshot();
+
+        match read(&snapshot) {
+            Err(QtcError::StaleSnapshot) if attempts + 1 < SNAPSHOT_RETRIES => attempts += 1,
+            result => return result,
+        }
+    }
+}
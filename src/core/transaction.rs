@@ -1,8 +1,15 @@
 use crate::crypto::hash::{Hash256, Hashable};
 use crate::crypto::signatures::Signature;
 use crate::crypto::keys::{PublicKey, PrivateKey};
+use crate::crypto::pqc::{PqcKeyPair, PqcSignature};
+use crate::core::mempool::{MAX_DATA_CARRIER_SIZE, MAX_DATA_OUTPUTS};
+use crate::wallet::signer::{sign_transaction, InputToSign, LocalSigner};
+use crate::wallet::coin_selection::{select_coins, CoinSelection, SelectionTarget, DUST_THRESHOLD};
+use crate::wallet::multisig::MultisigUtils;
+use crate::wallet::wallet::WalletType;
 use crate::{QtcError, Result};
 use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
 
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -52,7 +59,7 @@ impl Transaction {
                 vout: 0xFFFFFFFF,
             },
             signature_script: message.into_bytes(),
-            sequence: 0xFFFFFFFF,
+            sequence: SEQUENCE_FINAL,
             witness: Vec::new(),
         };
         
@@ -73,7 +80,7 @@ impl Transaction {
         let input = TxInput {
             previous_output: outpoint,
             signature_script,
-            sequence: 0xFFFFFFFF,
+            sequence: SEQUENCE_FINAL,
             witness: Vec::new(),
         };
         self.inputs.push(input);
@@ -94,6 +101,26 @@ impl Transaction {
             && self.inputs[0].previous_output.vout == 0xFFFFFFFF
     }
     
+    /// Whether any input opts in to replacement by a higher-fee spend of the same outputs
+    pub fn signals_rbf(&self) -> bool {
+        self.inputs.iter().any(|input| input.sequence < SEQUENCE_FINAL - 1)
+    }
+    
+    /// Whether the transaction may go in a block at `height` with timestamp `time`.
+    /// `lock_time` is a height below `LOCKTIME_THRESHOLD` and a Unix time from it
+    /// on; it is ignored when every input has `SEQUENCE_FINAL`.
+    pub fn is_final(&self, height: u64, time: u64) -> bool {
+        if self.lock_time == 0 {
+            return true;
+        }
+        let reached = if self.lock_time < LOCKTIME_THRESHOLD {
+            self.lock_time <= height
+        } else {
+            self.lock_time <= time
+        };
+        reached || self.inputs.iter().all(|input| input.sequence == SEQUENCE_FINAL)
+    }
+    
     pub fn total_input_value(&self) -> u64 {
         // This would need UTXO lookup in real implementation
         // For now, return 0 for coinbase transactions
@@ -121,33 +148,80 @@ impl Transaction {
         bincode::serialize(self).map(|data| data.len()).unwrap_or(0)
     }
     
+    /// Bytes of post-quantum signature scripts across the inputs
+    pub fn pqc_witness_size(&self) -> usize {
+        self.inputs.iter()
+            .filter(|input| split_pqc_script(&input.signature_script).is_some())
+            .map(|input| input.signature_script.len())
+            .sum()
+    }
+    
+    /// Size with PQC signature scripts counted at `pqc_witness_percent`; fee rates,
+    /// mempool ranking and block limits are all measured in this. The discount is only
+    /// safe because a transaction's scripts are verified before it is pooled or its
+    /// block connected: a PQC-shaped script that doesn't verify never gets that far.
+    pub fn weight(&self, pqc_witness_percent: u32) -> usize {
+        let witness = self.pqc_witness_size();
+        self.size() - witness + scaled_witness(witness, pqc_witness_percent)
+    }
+    
+    /// Signature hash for SIGHASH_ALL, which commits to every input and output
     pub fn get_signature_hash(&self, input_index: usize) -> Hash256 {
-        // Simplified signature hash for SIGHASH_ALL
+        self.signature_hash(input_index, SigHashType::All)
+    }
+    
+    pub fn get_signature_hash_with(&self, input_index: usize, sighash: SigHashType) -> Result<Hash256> {
+        if sighash.anyone_can_pay() && input_index >= self.inputs.len() {
+            return Err(QtcError::Transaction("Invalid input index".to_string()));
+        }
+        if sighash.base() == SigHashType::Single && input_index >= self.outputs.len() {
+            return Err(QtcError::Transaction(format!(
+                "SIGHASH_SINGLE input {} has no output at the same index", input_index
+            )));
+        }
+        Ok(self.signature_hash(input_index, sighash))
+    }
+    
+    fn signature_hash(&self, input_index: usize, sighash: SigHashType) -> Hash256 {
         let mut data = Vec::new();
         
         // Add version
         data.extend_from_slice(&self.version.to_le_bytes());
         
-        // Add inputs (without signature scripts)
-        data.extend_from_slice(&(self.inputs.len() as u32).to_le_bytes());
-        for (i, input) in self.inputs.iter().enumerate() {
+        // Add inputs (without signature scripts); ANYONECANPAY leaves others free to be added
+        let inputs: Vec<(usize, &TxInput)> = if sighash.anyone_can_pay() {
+            vec![(input_index, &self.inputs[input_index])]
+        } else {
+            self.inputs.iter().enumerate().collect()
+        };
+        data.extend_from_slice(&(inputs.len() as u32).to_le_bytes());
+        for (i, input) in inputs {
             data.extend_from_slice(input.previous_output.txid.as_bytes());
             data.extend_from_slice(&input.previous_output.vout.to_le_bytes());
+            data.extend_from_slice(&0u32.to_le_bytes()); // empty script
             
-            if i == input_index {
-                // Use the script_pubkey of the output being spent
-                // For now, use empty script
-                data.extend_from_slice(&0u32.to_le_bytes()); // empty script length
-            } else {
-                data.extend_from_slice(&0u32.to_le_bytes()); // empty script
-            }
-            
-            data.extend_from_slice(&input.sequence.to_le_bytes());
+            // NONE and SINGLE let the other inputs' owners update their sequence
+            let sequence = match sighash.base() {
+                SigHashType::None | SigHashType::Single if i != input_index => 0,
+                _ => input.sequence,
+            };
+            data.extend_from_slice(&sequence.to_le_bytes());
         }
         
         // Add outputs
-        data.extend_from_slice(&(self.outputs.len() as u32).to_le_bytes());
-        for output in &self.outputs {
+        let outputs = match sighash.base() {
+            SigHashType::None => &self.outputs[..0],
+            SigHashType::Single => &self.outputs[..=input_index],
+            _ => &self.outputs[..],
+        };
+        data.extend_from_slice(&(outputs.len() as u32).to_le_bytes());
+        for (i, output) in outputs.iter().enumerate() {
+            if sighash.base() == SigHashType::Single && i != input_index {
+                // Outputs before ours are placeholders: any value, any script
+                data.extend_from_slice(&u64::MAX.to_le_bytes());
+                data.extend_from_slice(&0u32.to_le_bytes());
+                continue;
+            }
             data.extend_from_slice(&output.value.to_le_bytes());
             data.extend_from_slice(&(output.script_pubkey.len() as u32).to_le_bytes());
             data.extend_from_slice(&output.script_pubkey);
@@ -156,13 +230,13 @@ impl Transaction {
         // Add lock_time
         data.extend_from_slice(&self.lock_time.to_le_bytes());
         
-        // Add SIGHASH_ALL
-        data.extend_from_slice(&1u32.to_le_bytes());
+        // Add the sighash type
+        data.extend_from_slice(&(sighash.to_u8() as u32).to_le_bytes());
         
         Hash256::hash(&data)
     }
     
-    fn address_to_script_pubkey(address: &str) -> Vec<u8> {
+    pub(crate) fn address_to_script_pubkey(address: &str) -> Vec<u8> {
         // Simplified script creation
         // In real implementation, this would decode the address and create proper scripts
         let mut script = Vec::new();
@@ -185,20 +259,13 @@ impl Transaction {
             return Err(QtcError::Transaction("Invalid input index".to_string()));
         }
         
-        let input = &self.inputs[input_index];
-        if input.signature_script.is_empty() {
+        let Some((signature, sighash_byte, _)) = split_p2pkh_script(&self.inputs[input_index].signature_script) else {
             return Ok(false);
-        }
-        
-        // Extract signature from script (simplified)
-        if input.signature_script.len() < 64 {
-            return Ok(false);
-        }
-        
-        let signature_bytes = &input.signature_script[input.signature_script.len()-64..];
-        let signature = Signature::from_bytes(signature_bytes)?;
+        };
+        let signature = Signature::from_bytes(signature)?;
+        let sighash = SigHashType::from_u8(sighash_byte)?;
         
-        let message_hash = self.get_signature_hash(input_index);
+        let message_hash = self.get_signature_hash_with(input_index, sighash)?;
         
         Ok(public_key.verify(&message_hash, &signature)?)
     }
@@ -236,6 +303,23 @@ impl Hashable for Transaction {
     }
 }
 
+impl TxOutput {
+    /// Whether no script can ever spend this output, so its value is out of circulation
+    pub fn is_unspendable(&self) -> bool {
+        self.script_pubkey.first() == Some(&OP_RETURN)
+    }
+    
+    /// Zero-value output carrying `data` on chain
+    pub fn data(data: &[u8]) -> Result<Self> {
+        Ok(Self { value: 0, script_pubkey: data_script(data)? })
+    }
+    
+    /// The payload of a data-carrier output
+    pub fn data_payload(&self) -> Option<&[u8]> {
+        split_data_script(&self.script_pubkey)
+    }
+}
+
 impl OutPoint {
     pub fn new(txid: Hash256, vout: u32) -> Self {
         Self { txid, vout }
@@ -246,13 +330,190 @@ impl OutPoint {
     }
 }
 
+impl std::str::FromStr for OutPoint {
+    type Err = QtcError;
+    
+    /// Parse the `txid:vout` form used by the CLI and API
+    fn from_str(s: &str) -> Result<Self> {
+        let (txid, vout) = s.split_once(':')
+            .ok_or_else(|| QtcError::InvalidInput("Expected <txid>:<vout>".to_string()))?;
+        let txid = Hash256::from_hex(txid)
+            .map_err(|_| QtcError::InvalidInput("Invalid txid".to_string()))?;
+        let vout = vout.parse::<u32>()
+            .map_err(|_| QtcError::InvalidInput("Invalid output index".to_string()))?;
+        
+        Ok(Self::new(txid, vout))
+    }
+}
+
+pub const SIGHASH_ANYONECANPAY: u8 = 0x80;
+
+/// Leads a script that fails as soon as it runs
+pub const OP_RETURN: u8 = 0x6a;
+/// Pushes as many bytes as the next byte says
+pub const OP_PUSHDATA1: u8 = 0x4c;
+/// Pushes as many bytes as the next two (little-endian) say
+pub const OP_PUSHDATA2: u8 = 0x4d;
+
+pub const SEQUENCE_FINAL: u32 = 0xFFFFFFFF;
+/// Highest input sequence that still lets the transaction's lock time apply
+pub const SEQUENCE_LOCKTIME: u32 = 0xFFFFFFFE;
+/// Input sequence a wallet uses to signal that its transaction may be fee-bumped
+pub const SEQUENCE_REPLACEABLE: u32 = 0xFFFFFFFD;
+/// Lock times below this are block heights, from it on Unix timestamps
+pub const LOCKTIME_THRESHOLD: u64 = 500_000_000;
+
+/// Which parts of a transaction a signature commits to, carried as the byte after the signature
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum SigHashType {
+    #[default]
+    All,
+    None,
+    Single,
+    AllAnyoneCanPay,
+    NoneAnyoneCanPay,
+    SingleAnyoneCanPay,
+}
+
+impl SigHashType {
+    pub fn to_u8(self) -> u8 {
+        match self {
+            Self::All => 0x01,
+            Self::None => 0x02,
+            Self::Single => 0x03,
+            Self::AllAnyoneCanPay => 0x81,
+            Self::NoneAnyoneCanPay => 0x82,
+            Self::SingleAnyoneCanPay => 0x83,
+        }
+    }
+    
+    pub fn from_u8(byte: u8) -> Result<Self> {
+        match byte {
+            0x01 => Ok(Self::All),
+            0x02 => Ok(Self::None),
+            0x03 => Ok(Self::Single),
+            0x81 => Ok(Self::AllAnyoneCanPay),
+            0x82 => Ok(Self::NoneAnyoneCanPay),
+            0x83 => Ok(Self::SingleAnyoneCanPay),
+            _ => Err(QtcError::Transaction(format!("Unknown sighash type 0x{:02x}", byte))),
+        }
+    }
+    
+    pub fn anyone_can_pay(self) -> bool {
+        self.to_u8() & SIGHASH_ANYONECANPAY != 0
+    }
+    
+    /// The type with ANYONECANPAY stripped
+    pub fn base(self) -> Self {
+        match self {
+            Self::All | Self::AllAnyoneCanPay => Self::All,
+            Self::None | Self::NoneAnyoneCanPay => Self::None,
+            Self::Single | Self::SingleAnyoneCanPay => Self::Single,
+        }
+    }
+}
+
+impl std::fmt::Display for SigHashType {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let base = match self.base() {
+            Self::None => "NONE",
+            Self::Single => "SINGLE",
+            _ => "ALL",
+        };
+        if self.anyone_can_pay() {
+            write!(f, "{}|ANYONECANPAY", base)
+        } else {
+            write!(f, "{}", base)
+        }
+    }
+}
+
+impl std::str::FromStr for SigHashType {
+    type Err = QtcError;
+    
+    /// Parse `ALL`, `NONE` or `SINGLE`, optionally followed by `|ANYONECANPAY`
+    fn from_str(s: &str) -> Result<Self> {
+        let upper = s.trim().to_ascii_uppercase();
+        let (base, anyone_can_pay) = match upper.split_once('|') {
+            Some((base, "ANYONECANPAY")) => (base, true),
+            Some(_) => return Err(QtcError::InvalidInput(format!("Unknown sighash modifier in '{}'", s))),
+            None => (upper.as_str(), false),
+        };
+        
+        match (base, anyone_can_pay) {
+            ("ALL", false) => Ok(Self::All),
+            ("NONE", false) => Ok(Self::None),
+            ("SINGLE", false) => Ok(Self::Single),
+            ("ALL", true) => Ok(Self::AllAnyoneCanPay),
+            ("NONE", true) => Ok(Self::NoneAnyoneCanPay),
+            ("SINGLE", true) => Ok(Self::SingleAnyoneCanPay),
+            _ => Err(QtcError::InvalidInput(format!("Unknown sighash type '{}'", s))),
+        }
+    }
+}
+
 /// Transaction builder for creating new transactions
 #[derive(Debug)]
 pub struct TransactionBuilder<'a> {
     wallet: &'a crate::wallet::Wallet,
     outputs: Vec<TxOutput>,
+    recipients: Vec<String>,
     fee_rate: u64,
-    estimated_size: usize,
+    lock_ttl_secs: u64,
+    sighash: SigHashType,
+    coin_selection: CoinSelection,
+    replaceable: bool,
+    lock_time: u64,
+    sequence: Option<u32>,
+    input_sequences: HashMap<OutPoint, u32>,
+    allow_reuse: bool,
+}
+
+/// How long selected inputs stay locked if the transaction is never broadcast
+const SELECTION_LOCK_TTL_SECS: u64 = 600;
+const SELECTION_ATTEMPTS: usize = 3;
+
+/// Size of a P2PKH signature script: push + 65-byte signature + sighash byte, push + 33-byte key
+const SIGNATURE_SCRIPT_SIZE: usize = 1 + 65 + 1 + 1 + 33;
+
+/// A wallet output picked for spending: (txid, vout, value, owning address)
+type SelectedUtxo = (Hash256, u32, u64, String);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PreviewInput {
+    pub txid: String,
+    pub vout: u32,
+    pub address: String,
+    pub value: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PreviewOutput {
+    pub address: String,
+    pub value: u64,
+}
+
+/// What `build` would produce, without locking inputs or signing anything
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TransactionPreview {
+    pub inputs: Vec<PreviewInput>,
+    pub outputs: Vec<PreviewOutput>,
+    pub change: Option<PreviewOutput>,
+    pub total_input: u64,
+    pub total_output: u64,
+    pub fee: u64,
+    pub fee_rate: u64, // satoshis per 1000 bytes
+    pub estimated_vsize: usize, // once signed
+}
+
+impl TransactionPreview {
+    /// Fee actually paid per byte of the signed transaction
+    pub fn effective_fee_rate(&self) -> f64 {
+        if self.estimated_vsize == 0 {
+            return 0.0;
+        }
+        self.fee as f64 / self.estimated_vsize as f64
+    }
 }
 
 impl<'a> TransactionBuilder<'a> {
@@ -260,8 +521,16 @@ impl<'a> TransactionBuilder<'a> {
         Self {
             wallet,
             outputs: Vec::new(),
+            recipients: Vec::new(),
             fee_rate: 1000, // Default: 1000 satoshis per byte
-            estimated_size: 0,
+            lock_ttl_secs: SELECTION_LOCK_TTL_SECS,
+            sighash: SigHashType::All,
+            coin_selection: CoinSelection::LargestFirst,
+            replaceable: false,
+            lock_time: 0,
+            sequence: None,
+            input_sequences: HashMap::new(),
+            allow_reuse: false,
         }
     }
     
@@ -272,7 +541,24 @@ impl<'a> TransactionBuilder<'a> {
             script_pubkey,
         };
         self.outputs.push(output);
-        self.update_estimated_size();
+        self.recipients.push(address.to_string());
+        Ok(())
+    }
+    
+    /// Carry `data` in a zero-value OP_RETURN output, within what the mempool relays
+    pub fn add_data_output(&mut self, data: &[u8]) -> Result<()> {
+        if data.len() > MAX_DATA_CARRIER_SIZE {
+            return Err(QtcError::Transaction(format!(
+                "Data is {} bytes, at most {} are relayed", data.len(), MAX_DATA_CARRIER_SIZE
+            )));
+        }
+        if self.outputs.iter().filter(|output| output.is_unspendable()).count() >= MAX_DATA_OUTPUTS {
+            return Err(QtcError::Transaction(format!(
+                "At most {} data output(s) per transaction are relayed", MAX_DATA_OUTPUTS
+            )));
+        }
+        self.outputs.push(TxOutput::data(data)?);
+        self.recipients.push(format!("OP_RETURN {}", hex::encode(data)));
         Ok(())
     }
     
@@ -280,76 +566,193 @@ impl<'a> TransactionBuilder<'a> {
         self.fee_rate = fee_rate;
     }
     
-    fn update_estimated_size(&mut self) {
-        // Estimate transaction size
-        // Base size: version(4) + input_count(1-9) + output_count(1-9) + lock_time(4)
-        let mut size = 4 + 1 + 1 + 4;
-        
-        // Add estimated input size: outpoint(36) + script_length(1-9) + script(~107) + sequence(4)
-        // Conservative estimate: 148 bytes per input
-        let estimated_inputs = 2; // Conservative estimate
-        size += estimated_inputs * 148;
-        
-        // Add output sizes
-        for output in &self.outputs {
-            size += 8 + 1 + output.script_pubkey.len(); // value(8) + script_length(1-9) + script
+    pub fn set_lock_ttl(&mut self, secs: u64) {
+        self.lock_ttl_secs = secs;
+    }
+    
+    pub fn set_sighash(&mut self, sighash: SigHashType) {
+        self.sighash = sighash;
+    }
+    
+    pub fn set_coin_selection(&mut self, coin_selection: CoinSelection) {
+        self.coin_selection = coin_selection;
+    }
+    
+    /// Signal replace-by-fee on every input so the transaction can be fee-bumped later
+    pub fn set_replaceable(&mut self, replaceable: bool) {
+        self.replaceable = replaceable;
+    }
+    
+    /// Keep the transaction out of blocks below this height, or before this Unix time
+    /// from `LOCKTIME_THRESHOLD` on. Inputs get `SEQUENCE_LOCKTIME` so the lock applies.
+    pub fn set_lock_time(&mut self, height_or_time: u64) {
+        self.lock_time = height_or_time;
+    }
+    
+    /// Sequence for every input, instead of the one `set_replaceable` and `set_lock_time` imply
+    pub fn set_sequence(&mut self, sequence: u32) {
+        self.sequence = Some(sequence);
+    }
+    
+    /// Spend outputs on reused addresses even when the wallet avoids reuse
+    pub fn set_allow_reuse(&mut self, allow_reuse: bool) {
+        self.allow_reuse = allow_reuse;
+    }
+    
+    /// Sequence for one input, should coin selection pick it
+    pub fn set_input_sequence(&mut self, outpoint: OutPoint, sequence: u32) {
+        self.input_sequences.insert(outpoint, sequence);
+    }
+    
+    fn sequence_for(&self, outpoint: &OutPoint) -> u32 {
+        if let Some(sequence) = self.input_sequences.get(outpoint).copied().or(self.sequence) {
+            return sequence;
+        }
+        if self.replaceable {
+            SEQUENCE_REPLACEABLE
+        } else if self.lock_time != 0 {
+            SEQUENCE_LOCKTIME
+        } else {
+            SEQUENCE_FINAL
         }
-        
-        self.estimated_size = size;
     }
     
-    pub fn build(&mut self) -> Result<Transaction> {
+    fn fee_for(&self, bytes: usize) -> u64 {
+        self.fee_rate * bytes as u64 / 1000 // Fee rate is per 1000 bytes
+    }
+    
+    /// What coin selection has to cover: the outputs, the fee for everything but the
+    /// inputs, a fee per signed input, and what a change output would cost
+    /// Bytes each input's signature script adds once signed
+    fn signature_script_size(&self) -> usize {
+        match self.wallet.info.wallet_type {
+            WalletType::Multisig { required, total } => MultisigUtils::signature_script_size(required, total),
+            _ => SIGNATURE_SCRIPT_SIZE,
+        }
+    }
+    
+    fn selection_target(&self) -> Result<SelectionTarget> {
         if self.outputs.is_empty() {
             return Err(QtcError::Transaction("No outputs specified".to_string()));
         }
         
-        let total_output_value: u64 = self.outputs.iter().map(|o| o.value).sum();
-        let estimated_fee = self.fee_rate * self.estimated_size as u64 / 1000; // Fee rate is per 1000 bytes
-        let total_needed = total_output_value + estimated_fee;
+        let mut base = Transaction::new();
+        base.outputs = self.outputs.clone();
+        let mut with_input = base.clone();
+        with_input.add_input(OutPoint::new(Hash256::zero(), 0), Vec::new());
+        let mut with_change = base.clone();
+        with_change.add_output(0, "change");
         
-        // Find UTXOs to spend
+        let total_output_value: u64 = self.outputs.iter().map(|o| o.value).sum();
+        Ok(SelectionTarget {
+            amount: total_output_value + self.fee_for(base.size()),
+            input_fee: self.fee_for(with_input.size() - base.size() + self.signature_script_size()),
+            change_cost: self.fee_for(with_change.size() - base.size()) + DUST_THRESHOLD,
+        })
+    }
+    
+    /// Dry run of `build`: select inputs and work out fee and change, leaving nothing locked
+    pub fn preview(&self) -> Result<TransactionPreview> {
+        let target = self.selection_target()?;
         let addresses = self.wallet.get_addresses();
-        let mut available_utxos = Vec::new();
-        let mut total_available = 0u64;
+        let (selected_utxos, selected_value) = self.select_utxos(&addresses, &target)?;
+        let (tx, fee, change) = self.assemble(&selected_utxos, selected_value, &addresses, &target);
         
-        // Get blockchain reference
-        let blockchain = self.wallet.blockchain.read().unwrap();
-        
-        for address in &addresses {
-            let utxos = blockchain.get_utxos(address)?;
-            for (txid, vout, value) in utxos {
-                available_utxos.push((txid, vout, value, address.clone()));
-                total_available += value;
-            }
-        }
+        Ok(TransactionPreview {
+            inputs: selected_utxos.iter()
+                .map(|(txid, vout, value, address)| PreviewInput {
+                    txid: txid.to_hex(),
+                    vout: *vout,
+                    address: address.clone(),
+                    value: *value,
+                })
+                .collect(),
+            outputs: self.recipients.iter()
+                .zip(&self.outputs)
+                .map(|(address, output)| PreviewOutput { address: address.clone(), value: output.value })
+                .collect(),
+            change,
+            total_input: selected_value,
+            total_output: tx.total_output_value(),
+            fee,
+            fee_rate: self.fee_rate,
+            estimated_vsize: tx.size() + self.signature_script_size() * tx.inputs.len(),
+        })
+    }
+    
+    pub fn build(&mut self) -> Result<Transaction> {
+        let (mut tx, selected_utxos, _change) = self.lock_and_assemble()?;
         
-        if total_available < total_needed {
-            return Err(QtcError::Transaction(format!(
-                "Insufficient funds: have {:.8} QTC, need {:.8} QTC",
-                total_available as f64 / 100_000_000.0,
-                total_needed as f64 / 100_000_000.0
-            )));
+        // Sign the transaction, giving the inputs back if that fails
+        if let Err(e) = self.sign_transaction(&mut tx, &selected_utxos) {
+            self.wallet.release_transaction_locks(&tx)?;
+            return Err(e);
         }
         
-        // Select UTXOs (simple greedy algorithm)
-        available_utxos.sort_by(|a, b| b.2.cmp(&a.2)); // Sort by value descending
-        let mut selected_utxos = Vec::new();
-        let mut selected_value = 0u64;
+        Ok(tx)
+    }
+    
+    /// Like `build`, but leave every input unsigned for an offline signer; inputs stay locked.
+    /// Also returns who owns each input and where each output (change last) pays to.
+    pub fn build_unsigned(&mut self) -> Result<(Transaction, Vec<PreviewInput>, Vec<PreviewOutput>)> {
+        let (tx, selected_utxos, change) = self.lock_and_assemble()?;
+        let inputs = selected_utxos.into_iter()
+            .map(|(txid, vout, value, address)| PreviewInput { txid: txid.to_hex(), vout, address, value })
+            .collect();
+        let outputs = self.recipients.iter()
+            .zip(&self.outputs)
+            .map(|(address, output)| PreviewOutput { address: address.clone(), value: output.value })
+            .chain(change)
+            .collect();
+        Ok((tx, inputs, outputs))
+    }
+    
+    fn lock_and_assemble(&mut self) -> Result<(Transaction, Vec<SelectedUtxo>, Option<PreviewOutput>)> {
+        let target = self.selection_target()?;
+        let addresses = self.wallet.get_addresses();
         
-        for (txid, vout, value, address) in available_utxos {
-            selected_utxos.push((txid, vout, value, address));
-            selected_value += value;
-            if selected_value >= total_needed {
-                break;
+        // Another builder may lock the same outputs between listing and locking, so retry
+        let mut attempt = 0;
+        let (selected_utxos, selected_value) = loop {
+            attempt += 1;
+            let (selected_utxos, selected_value) = self.select_utxos(&addresses, &target)?;
+            let outpoints: Vec<OutPoint> = selected_utxos.iter()
+                .map(|(txid, vout, _, _)| OutPoint::new(*txid, *vout))
+                .collect();
+            
+            match self.wallet.lock_unspent(&outpoints, Some(self.lock_ttl_secs), false) {
+                Ok(()) => break (selected_utxos, selected_value),
+                Err(e) if attempt >= SELECTION_ATTEMPTS => return Err(e),
+                Err(e) => log::debug!("🔒 Coin selection raced with another send, retrying: {}", e),
             }
-        }
+        };
+        
+        let (tx, _fee, change) = self.assemble(&selected_utxos, selected_value, &addresses, &target);
+        Ok((tx, selected_utxos, change))
+    }
+    
+    /// Unsigned transaction spending `selected_utxos`, with its fee and change output if any
+    fn assemble(
+        &self,
+        selected_utxos: &[SelectedUtxo],
+        selected_value: u64,
+        addresses: &[String],
+        target: &SelectionTarget,
+    ) -> (Transaction, u64, Option<PreviewOutput>) {
+        let total_output_value: u64 = self.outputs.iter().map(|o| o.value).sum();
         
         // Create transaction
         let mut tx = Transaction::new();
+        tx.lock_time = self.lock_time;
         
         // Add inputs
-        for (txid, vout, _value, _address) in &selected_utxos {
-            tx.add_input(OutPoint::new(*txid, *vout), Vec::new()); // Empty signature script for now
+        for (txid, vout, _value, _address) in selected_utxos {
+            let outpoint = OutPoint::new(*txid, *vout);
+            let sequence = self.sequence_for(&outpoint);
+            tx.add_input(outpoint, Vec::new()); // Empty signature script for now
+            if let Some(input) = tx.inputs.last_mut() {
+                input.sequence = sequence;
+            }
         }
         
         // Add outputs
@@ -357,56 +760,241 @@ impl<'a> TransactionBuilder<'a> {
             tx.outputs.push(output.clone());
         }
         
-        // Add change output if needed
-        let actual_fee = self.fee_rate * tx.size() as u64 / 1000;
-        let change_amount = selected_value.saturating_sub(total_output_value + actual_fee);
+        // Add change output if what's left pays for it and still isn't dust
+        let fee_without_change = target.amount - total_output_value + target.input_fee * selected_utxos.len() as u64;
+        let leftover = selected_value.saturating_sub(total_output_value + fee_without_change);
         
-        if change_amount > 546 { // Dust threshold
+        let mut change = None;
+        if leftover > target.change_cost {
+            let change_amount = leftover - (target.change_cost - DUST_THRESHOLD);
             let change_address = self.wallet.get_change_address().unwrap_or_else(|_| {
                 addresses.first().unwrap_or(&"unknown".to_string()).clone()
             });
             tx.add_output(change_amount, &change_address);
+            change = Some(PreviewOutput { address: change_address, value: change_amount });
         }
         
-        // Sign the transaction
-        self.sign_transaction(&mut tx, &selected_utxos)?;
-        
-        Ok(tx)
+        // Anything not returned as change (including sub-dust leftovers) goes to the miner
+        let fee = selected_value - total_output_value - change.as_ref().map_or(0, |c| c.value);
+        (tx, fee, change)
     }
     
-    fn sign_transaction(&self, tx: &mut Transaction, selected_utxos: &[(Hash256, u32, u64, String)]) -> Result<()> {
-        for (input_index, (_, _, _, address)) in selected_utxos.iter().enumerate() {
-            // Get private key for this address
-            if let Ok(private_key_wif) = self.wallet.export_private_key(address) {
-                let private_key = PrivateKey::from_wif(&private_key_wif)?;
-                let public_key = private_key.public_key()?;
-                
-                // Sign the input
-                let signature_hash = tx.get_signature_hash(input_index);
-                let signature = private_key.sign(&signature_hash)?;
-                
-                // Create signature script (simplified P2PKH)
-                let mut script = Vec::new();
-                
-                // Add signature
-                let sig_bytes = signature.to_bytes();
-                script.push(sig_bytes.len() as u8);
-                script.extend_from_slice(&sig_bytes);
-                script.push(0x01); // SIGHASH_ALL
-                
-                // Add public key
-                let pubkey_bytes = public_key.to_bytes();
-                script.push(pubkey_bytes.len() as u8);
-                script.extend_from_slice(pubkey_bytes);
-                
-                tx.inputs[input_index].signature_script = script;
+    /// Pick unlocked wallet outputs covering `target` with the builder's coin selection
+    /// strategy, leaving out those on reused addresses if the wallet avoids reuse
+    fn select_utxos(&self, addresses: &[String], target: &SelectionTarget) -> Result<(Vec<SelectedUtxo>, u64)> {
+        let mut available_utxos = Vec::new();
+        let mut total_available = 0u64;
+        let mut total_reused = 0u64;
+        
+        let reused = if self.wallet.settings()?.avoid_reuse && !self.allow_reuse {
+            self.wallet.reused_addresses()?
+        } else {
+            HashSet::new()
+        };
+        
+        // Get blockchain reference
+        let blockchain = self.wallet.blockchain.read().unwrap();
+        let locks = self.wallet.db.utxo_locks();
+        
+        for address in addresses {
+            let utxos = blockchain.get_utxos(address)?;
+            if reused.contains(address) {
+                total_reused += utxos.iter().filter(|(txid, vout, _)| !locks.is_locked(&OutPoint::new(*txid, *vout)))
+                    .map(|(_, _, value)| value)
+                    .sum::<u64>();
+                continue;
+            }
+            for (txid, vout, value) in utxos {
+                if locks.is_locked(&OutPoint::new(txid, vout)) {
+                    continue;
+                }
+                available_utxos.push((txid, vout, value, address.clone()));
+                total_available += value;
             }
         }
         
+        let values: Vec<u64> = available_utxos.iter().map(|(_, _, value, _)| *value).collect();
+        let Some(indices) = select_coins(self.coin_selection, &values, target, &mut rand::thread_rng()) else {
+            let reused_note = match total_reused {
+                0 => String::new(),
+                reused => format!(
+                    " ({:.8} QTC more on reused addresses is left out; pass --allow-reuse to spend it)",
+                    reused as f64 / 100_000_000.0
+                ),
+            };
+            return Err(QtcError::Transaction(format!(
+                "Insufficient funds: have {:.8} QTC unlocked, need {:.8} QTC{}",
+                total_available as f64 / 100_000_000.0,
+                (target.amount + target.input_fee) as f64 / 100_000_000.0,
+                reused_note
+            )));
+        };
+        
+        let selected_utxos: Vec<SelectedUtxo> = indices.into_iter()
+            .map(|index| available_utxos[index].clone())
+            .collect();
+        let selected_value = selected_utxos.iter().map(|(_, _, value, _)| value).sum();
+        
+        Ok((selected_utxos, selected_value))
+    }
+    
+    fn sign_transaction(&self, tx: &mut Transaction, selected_utxos: &[SelectedUtxo]) -> Result<()> {
+        let inputs: Vec<InputToSign> = selected_utxos.iter()
+            .enumerate()
+            .map(|(index, (txid, vout, value, address))| InputToSign {
+                index,
+                outpoint: OutPoint::new(*txid, *vout),
+                value: *value,
+                address: address.clone(),
+            })
+            .collect();
+        
+        sign_transaction(&LocalSigner::new(self.wallet), tx, &inputs, self.sighash)?;
         Ok(())
     }
 }
 
+/// Fill in the signature script of one input, spending an output paid to `private_key`'s address
+pub fn sign_p2pkh_input(tx: &mut Transaction, input_index: usize, private_key: &PrivateKey) -> Result<()> {
+    sign_p2pkh_input_with(tx, input_index, private_key, SigHashType::All)
+}
+
+pub fn sign_p2pkh_input_with(
+    tx: &mut Transaction,
+    input_index: usize,
+    private_key: &PrivateKey,
+    sighash: SigHashType,
+) -> Result<()> {
+    let public_key = private_key.public_key()?;
+    
+    // Sign the input
+    let signature_hash = tx.get_signature_hash_with(input_index, sighash)?;
+    let signature = private_key.sign(&signature_hash)?;
+    
+    tx.inputs[input_index].signature_script = p2pkh_signature_script(&signature, sighash, &public_key);
+    Ok(())
+}
+
+/// Simplified P2PKH signature script: push signature, sighash byte, push public key
+pub fn p2pkh_signature_script(signature: &Signature, sighash: SigHashType, public_key: &PublicKey) -> Vec<u8> {
+    let mut script = Vec::new();
+    
+    // Add signature
+    let sig_bytes = signature.to_bytes();
+    script.push(sig_bytes.len() as u8);
+    script.extend_from_slice(&sig_bytes);
+    script.push(sighash.to_u8());
+    
+    // Add public key
+    let pubkey_bytes = public_key.to_bytes();
+    script.push(pubkey_bytes.len() as u8);
+    script.extend_from_slice(pubkey_bytes);
+    
+    script
+}
+
+/// Split a P2PKH signature script into signature bytes, sighash byte and public key bytes
+pub fn split_p2pkh_script(script: &[u8]) -> Option<(&[u8], u8, &[u8])> {
+    let (&sig_len, rest) = script.split_first()?;
+    if sig_len != 64 && sig_len != 65 {
+        return None;
+    }
+    let signature = rest.get(..sig_len as usize)?;
+    let (&sighash, rest) = rest[sig_len as usize..].split_first()?;
+    let (&key_len, public_key) = rest.split_first()?;
+    (public_key.len() == key_len as usize).then_some((signature, sighash, public_key))
+}
+
+/// First byte of a post-quantum signature script; P2PKH scripts start with a signature length
+pub const PQC_SCRIPT_MARKER: u8 = 0xd3;
+
+/// Post-quantum signature script: marker, then the Dilithium3 signed message, the
+/// signing public key and the Kyber768 encryption key, each behind a little-endian u16
+/// length. Both keys are needed to rebuild the PQC address the spent output pays.
+pub fn pqc_signature_script(signature: &PqcSignature, encryption_key: &[u8]) -> Vec<u8> {
+    let mut script = vec![PQC_SCRIPT_MARKER];
+    for part in [&signature.signature[..], &signature.public_key[..], encryption_key] {
+        script.extend_from_slice(&(part.len() as u16).to_le_bytes());
+        script.extend_from_slice(part);
+    }
+    script
+}
+
+/// Signed message, signing key and encryption key of a PQC signature script
+pub fn split_pqc_script(script: &[u8]) -> Option<(&[u8], &[u8], &[u8])> {
+    let (&marker, rest) = script.split_first()?;
+    if marker != PQC_SCRIPT_MARKER {
+        return None;
+    }
+    let (signature, rest) = split_u16_prefixed(rest)?;
+    let (signing_key, rest) = split_u16_prefixed(rest)?;
+    let (encryption_key, rest) = split_u16_prefixed(rest)?;
+    rest.is_empty().then_some((signature, signing_key, encryption_key))
+}
+
+/// Fill in the signature script of one input, spending an output paid to `keypair`'s PQC address
+pub fn sign_pqc_input(tx: &mut Transaction, input_index: usize, keypair: &PqcKeyPair) -> Result<()> {
+    if input_index >= tx.inputs.len() {
+        return Err(QtcError::Transaction("Invalid input index".to_string()));
+    }
+    let signature = keypair.sign(&tx.get_signature_hash(input_index))?;
+    let encryption_key = keypair.address().encryption_public_key;
+    tx.inputs[input_index].signature_script = pqc_signature_script(&signature, &encryption_key);
+    Ok(())
+}
+
+fn split_u16_prefixed(data: &[u8]) -> Option<(&[u8], &[u8])> {
+    let len = u16::from_le_bytes([*data.first()?, *data.get(1)?]) as usize;
+    let rest = &data[2..];
+    (rest.len() >= len).then(|| rest.split_at(len))
+}
+
+/// Data-carrier script: OP_RETURN, then `data` in a single push unless it is empty
+pub fn data_script(data: &[u8]) -> Result<Vec<u8>> {
+    let mut script = vec![OP_RETURN];
+    match data.len() {
+        0 => {}
+        len @ 1..=75 => script.push(len as u8),
+        len @ 76..=0xff => script.extend_from_slice(&[OP_PUSHDATA1, len as u8]),
+        len @ 0x100..=0xffff => {
+            script.push(OP_PUSHDATA2);
+            script.extend_from_slice(&(len as u16).to_le_bytes());
+        }
+        len => return Err(QtcError::Transaction(format!("{} bytes of data don't fit in one push", len))),
+    }
+    script.extend_from_slice(data);
+    Ok(script)
+}
+
+/// Payload of a data-carrier script; None unless the script is exactly OP_RETURN and one push
+pub fn split_data_script(script: &[u8]) -> Option<&[u8]> {
+    let (&op_return, rest) = script.split_first()?;
+    if op_return != OP_RETURN {
+        return None;
+    }
+    let Some((&opcode, rest)) = rest.split_first() else {
+        return Some(rest);
+    };
+    let (len, data) = match opcode {
+        1..=75 => (opcode as usize, rest),
+        OP_PUSHDATA1 => (*rest.first()? as usize, &rest[1..]),
+        OP_PUSHDATA2 => (u16::from_le_bytes([*rest.first()?, *rest.get(1)?]) as usize, &rest[2..]),
+        _ => return None,
+    };
+    (data.len() == len).then_some(data)
+}
+
+/// A data payload as text, if it is UTF-8 without control characters
+pub fn data_as_text(data: &[u8]) -> Option<&str> {
+    std::str::from_utf8(data).ok()
+        .filter(|text| !text.is_empty() && !text.chars().any(char::is_control))
+}
+
+/// PQC witness bytes as counted towards weight, rounded up
+pub fn scaled_witness(bytes: usize, pqc_witness_percent: u32) -> usize {
+    (bytes * pqc_witness_percent as usize).div_ceil(100)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -439,4 +1027,67 @@ mod tests {
         assert_eq!(hash1, hash2);
         assert_ne!(hash1, Hash256::zero());
     }
+    
+    #[test]
+    fn test_data_scripts_round_trip() -> Result<()> {
+        for len in [0, 1, 75, 76, 255, 256, 1_000] {
+            let data = vec![0xab; len];
+            let output = TxOutput::data(&data)?;
+            assert!(output.is_unspendable());
+            assert_eq!(output.data_payload(), Some(&data[..]));
+        }
+        assert_eq!(data_script(b"hi")?, vec![OP_RETURN, 2, b'h', b'i']);
+        assert!(data_script(&vec![0; 0x10000]).is_err());
+        
+        // A truncated push, trailing bytes or anything but a push isn't a data script
+        assert_eq!(split_data_script(&[OP_RETURN, 3, 1, 2]), None);
+        assert_eq!(split_data_script(&[OP_RETURN, 1, 1, 2]), None);
+        assert_eq!(split_data_script(&[OP_RETURN, 0xac]), None);
+        assert_eq!(split_data_script(&Transaction::address_to_script_pubkey("qtc1test")), None);
+        
+        assert_eq!(data_as_text(b"hello, chain"), Some("hello, chain"));
+        assert_eq!(data_as_text(b"line\nbreak"), None);
+        assert_eq!(data_as_text(&[0xff, 0xfe]), None);
+        Ok(())
+    }
+    
+    #[test]
+    fn test_sighash_types_cover_the_right_parts() -> Result<()> {
+        let key = PrivateKey::new()?;
+        let public_key = key.public_key()?;
+        let mut tx = Transaction::new();
+        tx.add_input(OutPoint::new(Hash256::hash(b"a"), 0), Vec::new());
+        tx.add_input(OutPoint::new(Hash256::hash(b"b"), 1), Vec::new());
+        tx.add_output(5_000, "qtc1alice");
+        tx.add_output(3_000, "qtc1bob");
+        assert_eq!(tx.get_signature_hash(0), tx.get_signature_hash_with(0, SigHashType::All)?);
+        
+        // SINGLE|ANYONECANPAY only pins input 0 and output 0
+        sign_p2pkh_input_with(&mut tx, 0, &key, SigHashType::SingleAnyoneCanPay)?;
+        sign_p2pkh_input_with(&mut tx, 1, &key, SigHashType::None)?;
+        tx.add_input(OutPoint::new(Hash256::hash(b"c"), 0), Vec::new());
+        tx.outputs[1].value = 2_000;
+        tx.add_output(1_000, "qtc1carol");
+        assert!(tx.verify_signature(0, &public_key)?);
+        assert!(!tx.verify_signature(1, &public_key)?); // NONE still pins every input
+        
+        tx.inputs.pop();
+        assert!(tx.verify_signature(1, &public_key)?);
+        tx.outputs[0].value = 4_000;
+        assert!(!tx.verify_signature(0, &public_key)?);
+        
+        // ALL pins every output
+        sign_p2pkh_input(&mut tx, 1, &key)?;
+        tx.outputs[2].value = 900;
+        assert!(!tx.verify_signature(1, &public_key)?);
+        
+        assert!(tx.get_signature_hash_with(3, SigHashType::Single).is_err());
+        assert!(SigHashType::from_u8(0x04).is_err());
+        for sighash in [SigHashType::All, SigHashType::NoneAnyoneCanPay, SigHashType::Single] {
+            assert_eq!(sighash.to_string().parse::<SigHashType>()?, sighash);
+            assert_eq!(SigHashType::from_u8(sighash.to_u8())?, sighash);
+        }
+        assert_eq!("single|anyonecanpay".parse::<SigHashType>()?, SigHashType::SingleAnyoneCanPay);
+        Ok(())
+    }
 }
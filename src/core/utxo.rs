@@ -1,12 +1,20 @@
-use crate::core::{Block, Transaction};
+use crate::core::{Block, Transaction, TxOutput};
 use crate::core::transaction::OutPoint;
+use crate::core::utxo_cache::UtxoCache;
 use crate::storage::Database;
+use crate::storage::database::SpentOutput;
 use crate::crypto::hash::{Hash256, Hashable};
 use crate::{QtcError, Result};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
 use std::sync::Arc;
 
+/// Blocks younger than this are flushed as soon as they're applied, so the stored
+/// set is current once the node has caught up
+const RECENT_BLOCK_SECS: u64 = 24 * 60 * 60;
+
+/// Unflushed changes that force a flush regardless of the interval
+const MAX_DIRTY_UTXOS: usize = 500_000;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct UtxoEntry {
     pub txid: Hash256,
@@ -18,125 +26,201 @@ pub struct UtxoEntry {
     pub is_coinbase: bool,
 }
 
+impl UtxoEntry {
+    /// The entry for `output`, output `vout` of transaction `txid`, confirmed at `height`
+    pub fn from_output(txid: Hash256, vout: u32, output: &TxOutput, height: u64, is_coinbase: bool) -> Self {
+        Self {
+            txid,
+            vout,
+            value: output.value,
+            script_pubkey: output.script_pubkey.clone(),
+            address: UtxoSet::output_address(&output.script_pubkey),
+            height,
+            is_coinbase,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct UtxoSet {
     db: Arc<Database>,
-    cache: HashMap<OutPoint, UtxoEntry>,
-    dirty: bool,
+    cache: UtxoCache,
+    address_history: bool,
+    tip: Option<Hash256>,
+    flushed_tip: Option<Hash256>,
+    flush_interval: u64,
+    blocks_since_flush: u64,
 }
 
 impl UtxoSet {
     pub fn new(db: Arc<Database>) -> Self {
         Self {
+            cache: UtxoCache::new(db.clone()),
             db,
-            cache: HashMap::new(),
-            dirty: false,
+            address_history: false,
+            tip: None,
+            flushed_tip: None,
+            flush_interval: 1,
+            blocks_since_flush: 0,
         }
     }
     
+    /// Flush old blocks' changes every `blocks` blocks instead of after each one
+    pub fn set_flush_interval(&mut self, blocks: u64) {
+        self.flush_interval = blocks.max(1);
+    }
+    
+    /// Keep the per-address transaction history up to date as blocks are applied
+    pub fn set_address_history(&mut self, enabled: bool) {
+        self.address_history = enabled;
+    }
+    
     pub fn apply_block(&mut self, block: &Block) -> Result<()> {
+        let mut undo = Vec::new();
+        
         // Process all transactions in the block
         for tx in &block.transactions {
-            self.apply_transaction(tx, block.header.height)?;
+            self.apply_transaction_with_undo(tx, block.header.height, &mut undo)?;
         }
         
-        // Flush changes to database
-        self.flush()?;
+        // Keep the spent outputs so the block can be disconnected later
+        self.db.save_block_undo(&block.hash(), &undo)?;
+        
+        if self.address_history {
+            self.db.save_address_history(block.header.height, &Self::block_address_history(block, &undo))?;
+        } else {
+            // A block went by unindexed, so the history has a gap
+            self.db.set_address_history_complete(false)?;
+        }
+        
+        self.tip = Some(block.hash());
+        self.blocks_since_flush += 1;
+        let recent = block.header.timestamp + RECENT_BLOCK_SECS >= chrono::Utc::now().timestamp() as u64;
+        if recent || self.blocks_since_flush >= self.flush_interval || self.cache.dirty_count() >= MAX_DIRTY_UTXOS {
+            self.flush()?;
+        }
         
         Ok(())
     }
     
+    /// Undo `apply_block`: drop the block's outputs and restore what it spent
+    pub fn disconnect_block(&mut self, block: &Block) -> Result<()> {
+        let block_hash = block.hash();
+        let undo = self.db.get_block_undo(&block_hash)?
+            .ok_or_else(|| QtcError::Blockchain(format!("No undo data for block {}", block_hash)))?;
+        
+        // Reorgs are rare; start and finish them with everything on disk
+        self.flush()?;
+        
+        for tx in block.transactions.iter().rev() {
+            let tx_hash = tx.hash();
+            for vout in 0..tx.outputs.len() {
+                self.cache.spend(&OutPoint::new(tx_hash, vout as u32))?;
+            }
+        }
+        
+        if self.address_history {
+            self.db.delete_address_history(block.header.height, &Self::block_address_history(block, &undo))?;
+        } else {
+            self.db.set_address_history_complete(false)?;
+        }
+        
+        for (outpoint, entry) in undo {
+            self.db.delete_spent_output(&outpoint)?;
+            self.cache.add(outpoint, entry);
+        }
+        
+        self.db.delete_block_undo(&block_hash)?;
+        self.tip = Some(block.header.previous_hash);
+        self.flush()
+    }
+    
+    /// (address, txid) pairs for every address a block's transactions pay to or spend
+    /// from; `spent` is the block's undo data, in input order
+    pub fn block_address_history(block: &Block, spent: &[(OutPoint, UtxoEntry)]) -> Vec<(String, Hash256)> {
+        let mut entries = Vec::new();
+        let mut spent = spent.iter();
+        
+        for tx in &block.transactions {
+            let txid = tx.hash();
+            let mut addresses: Vec<String> = Vec::new();
+            
+            if !tx.is_coinbase() {
+                addresses.extend(spent.by_ref().take(tx.inputs.len()).map(|(_, entry)| entry.address.clone()));
+            }
+            addresses.extend(tx.outputs.iter().map(|output| Self::output_address(&output.script_pubkey)));
+            
+            addresses.sort();
+            addresses.dedup();
+            entries.extend(addresses.into_iter()
+                .filter(|address| address != "unknown")
+                .map(|address| (address, txid)));
+        }
+        
+        entries
+    }
+    
     pub fn apply_transaction(&mut self, tx: &Transaction, height: u64) -> Result<()> {
+        let mut undo = Vec::new();
+        self.apply_transaction_with_undo(tx, height, &mut undo)
+    }
+    
+    fn apply_transaction_with_undo(&mut self, tx: &Transaction, height: u64, undo: &mut Vec<(OutPoint, UtxoEntry)>) -> Result<()> {
         let tx_hash = tx.hash();
         
         // Remove spent UTXOs (inputs)
         if !tx.is_coinbase() {
-            for input in &tx.inputs {
+            for (input_index, input) in tx.inputs.iter().enumerate() {
                 let outpoint = &input.previous_output;
                 
-                // Check if UTXO exists
-                if !self.has_utxo(outpoint)? {
-                    return Err(QtcError::Transaction(format!(
-                        "UTXO not found: {}:{}", 
-                        hex::encode(outpoint.txid.as_bytes()), 
-                        outpoint.vout
-                    )));
-                }
+                let spent_entry = self.cache.spend(outpoint)?.ok_or_else(|| QtcError::Transaction(format!(
+                    "UTXO not found: {}:{}", 
+                    hex::encode(outpoint.txid.as_bytes()), 
+                    outpoint.vout
+                )))?;
+                undo.push((outpoint.clone(), spent_entry));
                 
-                // Remove from cache and mark for deletion
-                self.cache.remove(outpoint);
-                self.db.delete_utxo(outpoint)?;
-                self.dirty = true;
+                self.db.save_spent_output(outpoint, &SpentOutput {
+                    spending_txid: tx_hash,
+                    input_index: input_index as u32,
+                    height,
+                })?;
             }
         }
         
-        // Add new UTXOs (outputs)
-        for (vout, output) in tx.outputs.iter().enumerate() {
+        // Add new UTXOs (outputs); data carriers can never be spent, so they stay out
+        for (vout, output) in tx.outputs.iter().enumerate().filter(|(_, output)| !output.is_unspendable()) {
             let outpoint = OutPoint::new(tx_hash, vout as u32);
-            // Extract address from script_pubkey (simplified)
-            let address = Self::script_to_address(&output.script_pubkey).unwrap_or_else(|| "unknown".to_string());
-            
-            let utxo_entry = UtxoEntry {
-                txid: tx_hash,
-                vout: vout as u32,
-                value: output.value,
-                script_pubkey: output.script_pubkey.clone(),
-                address,
-                height,
-                is_coinbase: tx.is_coinbase(),
-            };
-            
-            // Add to cache
-            self.cache.insert(outpoint.clone(), utxo_entry.clone());
-            
-            // Save to database
-            self.db.save_utxo(&outpoint, &utxo_entry)?;
-            self.dirty = true;
+            self.cache.add(outpoint, UtxoEntry::from_output(tx_hash, vout as u32, output, height, tx.is_coinbase()));
         }
         
         Ok(())
     }
     
     pub fn has_utxo(&self, outpoint: &OutPoint) -> Result<bool> {
-        // Check cache first
-        if self.cache.contains_key(outpoint) {
-            return Ok(true);
-        }
-        
-        // Check database
-        match self.db.get_utxo(outpoint) {
-            Ok(Some(_)) => Ok(true),
-            Ok(None) => Ok(false),
-            Err(e) => Err(e),
-        }
+        self.cache.contains(outpoint)
     }
     
     pub fn get_utxo(&self, outpoint: &OutPoint) -> Result<Option<UtxoEntry>> {
-        // Check cache first
-        if let Some(utxo) = self.cache.get(outpoint) {
-            return Ok(Some(utxo.clone()));
-        }
-        
-        // Check database
-        self.db.get_utxo(outpoint)
+        self.cache.get(outpoint)
     }
     
     pub fn get_balance(&self, address: &str) -> Result<u64> {
-        let utxos = self.get_utxos(address)?;
-        Ok(utxos.iter().map(|(_, _, value)| value).sum())
+        let matched: u64 = self.cache.unflushed()
+            .filter(|utxo| self.script_matches_address(&utxo.script_pubkey, address))
+            .map(|utxo| utxo.value)
+            .sum();
+        Ok(self.cache.balance(address)? + matched)
     }
     
     pub fn get_utxos(&self, address: &str) -> Result<Vec<(Hash256, u32, u64)>> {
-        let mut utxos = Vec::new();
+        let mut utxos: Vec<_> = self.cache.address_utxos(address)?
+            .into_iter()
+            .map(|(_, utxo)| (utxo.txid, utxo.vout, utxo.value))
+            .collect();
         
-        // Get UTXOs from database
-        let db_utxos = self.db.get_utxos_for_address(address)?;
-        for (_outpoint, utxo) in db_utxos {
-            utxos.push((utxo.txid, utxo.vout, utxo.value));
-        }
-        
-        // Add UTXOs from cache
-        for (_outpoint, utxo) in &self.cache {
+        // Unflushed outputs can also be found by the address their script was built from
+        for utxo in self.cache.unflushed() {
             if self.script_matches_address(&utxo.script_pubkey, address) {
                 utxos.push((utxo.txid, utxo.vout, utxo.value));
             }
@@ -200,15 +284,35 @@ impl UtxoSet {
         Ok(true)
     }
     
+    /// Write cached changes out and record the block they bring the stored set to
     pub fn flush(&mut self) -> Result<()> {
-        if self.dirty {
-            // All changes are already written to database during apply_transaction
-            self.cache.clear();
-            self.dirty = false;
+        if self.cache.dirty_count() == 0 && self.tip == self.flushed_tip {
+            return Ok(());
+        }
+        
+        self.db.set_utxo_flushing(true)?;
+        let written = self.cache.flush()?;
+        if let Some(tip) = &self.tip {
+            self.db.set_utxo_tip(tip)?;
+        }
+        self.db.set_utxo_flushing(false)?;
+        
+        if self.blocks_since_flush > 1 {
+            log::debug!("💾 Flushed {} UTXO changes from {} blocks", written, self.blocks_since_flush);
         }
+        self.flushed_tip = self.tip;
+        self.blocks_since_flush = 0;
         Ok(())
     }
     
+    /// Drop unflushed changes, ahead of replaying blocks into a cleared set
+    pub fn reset(&mut self) {
+        self.cache.clear();
+        self.tip = None;
+        self.flushed_tip = None;
+        self.blocks_since_flush = 0;
+    }
+    
     fn script_matches_address(&self, script_pubkey: &[u8], address: &str) -> bool {
         // Simplified address matching
         // In real implementation, this would properly decode the script and address
@@ -227,6 +331,11 @@ impl UtxoSet {
         false
     }
     
+    /// The address a new output is recorded under in `UtxoEntry::address`
+    pub fn output_address(script_pubkey: &[u8]) -> String {
+        Self::script_to_address(script_pubkey).unwrap_or_else(|| "unknown".to_string())
+    }
+    
     /// Extract address from script_pubkey (simplified implementation)
     fn script_to_address(script: &[u8]) -> Option<String> {
         // This is a simplified implementation
@@ -254,6 +363,14 @@ impl UtxoSet {
     }
 }
 
+impl Drop for UtxoSet {
+    fn drop(&mut self) {
+        if let Err(e) = self.flush() {
+            log::error!("💾 Failed to flush the UTXO set: {}", e);
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -308,4 +425,33 @@ mod tests {
         
         Ok(())
     }
+    
+    #[test]
+    fn test_spent_index_recorded() -> Result<()> {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Arc::new(Database::new(temp_dir.path().join("test.db"))?);
+        let mut utxo_set = UtxoSet::new(db.clone());
+        
+        let coinbase_tx = Transaction::new_coinbase(
+            "qtc1test".to_string(),
+            1000,
+            "test".to_string(),
+        );
+        let funding = OutPoint::new(coinbase_tx.hash(), 0);
+        utxo_set.apply_transaction(&coinbase_tx, 0)?;
+        assert!(db.get_spent_output(&funding)?.is_none());
+        
+        let mut spend_tx = Transaction::new();
+        spend_tx.add_input(funding.clone(), vec![]);
+        spend_tx.add_output(900, "qtc1recipient");
+        utxo_set.apply_transaction(&spend_tx, 1)?;
+        
+        assert!(!utxo_set.has_utxo(&funding)?);
+        let spent = db.get_spent_output(&funding)?.unwrap();
+        assert_eq!(spent.spending_txid, spend_tx.hash());
+        assert_eq!(spent.input_index, 0);
+        assert_eq!(spent.height, 1);
+        
+        Ok(())
+    }
 }
@@ -0,0 +1,211 @@
+//! Write-back cache over the UTXO tree
+//!
+//! Blocks change the UTXO set in memory; `flush` writes the changes out in one
+//! pass. Outputs created and spent between two flushes never reach the database,
+//! and per-address totals stay O(1) to read: the database keeps a total for each
+//! address as of the last flush and the cache keeps the change since then.
+
+use crate::core::transaction::OutPoint;
+use crate::core::UtxoEntry;
+use crate::storage::Database;
+use crate::Result;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Unchanged outputs kept around after a flush for the blocks that spend them next
+const MAX_CLEAN_ENTRIES: usize = 100_000;
+
+#[derive(Debug, Clone)]
+struct CachedCoin {
+    coin: Option<UtxoEntry>, // None once spent
+    dirty: bool,             // differs from the database
+    fresh: bool,             // not in the database at all, so spending it needs no delete
+}
+
+#[derive(Debug)]
+pub struct UtxoCache {
+    db: Arc<Database>,
+    coins: HashMap<OutPoint, CachedCoin>,
+    balance_changes: HashMap<String, i128>,
+    dirty: usize,
+}
+
+impl UtxoCache {
+    pub fn new(db: Arc<Database>) -> Self {
+        Self {
+            db,
+            coins: HashMap::new(),
+            balance_changes: HashMap::new(),
+            dirty: 0,
+        }
+    }
+
+    /// Outputs added or spent since the last flush
+    pub fn dirty_count(&self) -> usize {
+        self.dirty
+    }
+
+    pub fn get(&self, outpoint: &OutPoint) -> Result<Option<UtxoEntry>> {
+        match self.coins.get(outpoint) {
+            Some(cached) => Ok(cached.coin.clone()),
+            None => self.db.get_utxo(outpoint),
+        }
+    }
+
+    pub fn contains(&self, outpoint: &OutPoint) -> Result<bool> {
+        match self.coins.get(outpoint) {
+            Some(cached) => Ok(cached.coin.is_some()),
+            None => Ok(self.db.get_utxo(outpoint)?.is_some()),
+        }
+    }
+
+    pub fn add(&mut self, outpoint: OutPoint, entry: UtxoEntry) {
+        *self.balance_changes.entry(entry.address.clone()).or_default() += entry.value as i128;
+
+        // New outputs aren't in the database yet; one restored after an unflushed spend still is
+        let fresh = self.coins.get(&outpoint).is_none_or(|cached| cached.fresh);
+
+        self.mark_dirty(&outpoint);
+        self.coins.insert(outpoint, CachedCoin { coin: Some(entry), dirty: true, fresh });
+    }
+
+    /// Remove an output, returning it if it was unspent
+    pub fn spend(&mut self, outpoint: &OutPoint) -> Result<Option<UtxoEntry>> {
+        let Some(entry) = self.get(outpoint)? else {
+            return Ok(None);
+        };
+        *self.balance_changes.entry(entry.address.clone()).or_default() -= entry.value as i128;
+
+        let fresh = self.coins.get(outpoint).is_some_and(|cached| cached.fresh);
+        if fresh {
+            // Never written, so there is nothing to delete either
+            self.coins.remove(outpoint);
+            self.dirty -= 1;
+        } else {
+            self.mark_dirty(outpoint);
+            self.coins.insert(outpoint.clone(), CachedCoin { coin: None, dirty: true, fresh: false });
+        }
+        Ok(Some(entry))
+    }
+
+    fn mark_dirty(&mut self, outpoint: &OutPoint) {
+        if !self.coins.get(outpoint).is_some_and(|cached| cached.dirty) {
+            self.dirty += 1;
+        }
+    }
+
+    /// Total held by `address`, with unflushed changes applied
+    pub fn balance(&self, address: &str) -> Result<u64> {
+        let stored = self.db.get_address_balance(address)? as i128;
+        let change = self.balance_changes.get(address).copied().unwrap_or(0);
+        Ok((stored + change).clamp(0, u64::MAX as i128) as u64)
+    }
+
+    /// Unspent outputs of `address`, with unflushed changes applied
+    pub fn address_utxos(&self, address: &str) -> Result<Vec<(OutPoint, UtxoEntry)>> {
+        let mut utxos: HashMap<OutPoint, UtxoEntry> = self.db.get_utxos_for_address(address)?.into_iter().collect();
+        for (outpoint, cached) in self.dirty_coins() {
+            match &cached.coin {
+                Some(entry) if entry.address == address => {
+                    utxos.insert(outpoint.clone(), entry.clone());
+                }
+                Some(_) => {}
+                None => {
+                    utxos.remove(outpoint);
+                }
+            }
+        }
+        Ok(utxos.into_iter().collect())
+    }
+
+    /// Unspent outputs added since the last flush
+    pub fn unflushed(&self) -> impl Iterator<Item = &UtxoEntry> {
+        self.dirty_coins().filter_map(|(_, cached)| cached.coin.as_ref())
+    }
+
+    fn dirty_coins(&self) -> impl Iterator<Item = (&OutPoint, &CachedCoin)> {
+        self.coins.iter().filter(|(_, cached)| cached.dirty)
+    }
+
+    /// Write every change to the database, returning how many outputs were written
+    pub fn flush(&mut self) -> Result<usize> {
+        let written = self.dirty;
+        for (outpoint, cached) in self.coins.iter_mut().filter(|(_, cached)| cached.dirty) {
+            match &cached.coin {
+                Some(entry) => self.db.save_utxo(outpoint, entry)?,
+                None => self.db.delete_utxo(outpoint)?,
+            }
+            cached.dirty = false;
+            cached.fresh = false;
+        }
+
+        self.coins.retain(|_, cached| cached.coin.is_some());
+        if self.coins.len() > MAX_CLEAN_ENTRIES {
+            self.coins.clear();
+        }
+        self.balance_changes.clear();
+        self.dirty = 0;
+        Ok(written)
+    }
+
+    /// Forget unflushed changes and cached outputs
+    pub fn clear(&mut self) {
+        self.coins.clear();
+        self.balance_changes.clear();
+        self.dirty = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::hash::Hash256;
+    use tempfile::TempDir;
+
+    fn coin(seed: u8, value: u64, address: &str) -> (OutPoint, UtxoEntry) {
+        let txid = Hash256::new([seed; 32]);
+        let entry = UtxoEntry {
+            txid,
+            vout: 0,
+            value,
+            script_pubkey: vec![],
+            address: address.to_string(),
+            height: 1,
+            is_coinbase: false,
+        };
+        (OutPoint::new(txid, 0), entry)
+    }
+
+    #[test]
+    fn test_cache_writes_back_on_flush() -> Result<()> {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Arc::new(Database::new(temp_dir.path().join("test.db"))?);
+        let mut cache = UtxoCache::new(db.clone());
+
+        let (a, a_entry) = coin(1, 500, "qtc1alice");
+        let (b, b_entry) = coin(2, 300, "qtc1alice");
+        cache.add(a.clone(), a_entry);
+        cache.add(b.clone(), b_entry);
+        assert_eq!(cache.balance("qtc1alice")?, 800);
+        assert!(db.get_utxo(&a)?.is_none());
+
+        // Created and spent before a flush, so it never touches the database
+        cache.spend(&b)?;
+        assert_eq!(cache.dirty_count(), 1);
+        assert_eq!(cache.flush()?, 1);
+        assert_eq!(db.get_address_balance("qtc1alice")?, 500);
+        assert!(db.get_utxo(&b)?.is_none());
+
+        // Spending a flushed output shows up before the next flush
+        assert_eq!(cache.spend(&a)?.map(|entry| entry.value), Some(500));
+        assert!(cache.spend(&a)?.is_none());
+        assert_eq!(cache.balance("qtc1alice")?, 0);
+        assert!(cache.address_utxos("qtc1alice")?.is_empty());
+        assert!(db.get_utxo(&a)?.is_some());
+
+        cache.flush()?;
+        assert!(db.get_utxo(&a)?.is_none());
+        assert_eq!(db.get_address_balance("qtc1alice")?, 0);
+        Ok(())
+    }
+}
@@ -0,0 +1,103 @@
+//! Canonical JSON for payloads that are hashed or signed
+//!
+//! serde writes struct fields in declaration order and `HashMap`s in whatever
+//! order they iterate, so the same value can serialize to different bytes.
+//! Here object keys are sorted by code point, there is no whitespace, and
+//! floats with no fractional part are written as integers (`2.0` -> `2`), so
+//! signer and verifier always agree on the bytes.
+
+use crate::{QtcError, Result};
+use serde::Serialize;
+use serde_json::Value;
+
+/// Largest integer a float holds exactly; bigger whole floats keep their exponent form
+const MAX_SAFE_INTEGER: f64 = 9_007_199_254_740_992.0;
+
+/// Serialize `value` to canonical JSON bytes
+pub fn to_canonical_vec<T: Serialize + ?Sized>(value: &T) -> Result<Vec<u8>> {
+    to_canonical_string(value).map(String::into_bytes)
+}
+
+pub fn to_canonical_string<T: Serialize + ?Sized>(value: &T) -> Result<String> {
+    let value = serde_json::to_value(value)
+        .map_err(|e| QtcError::InvalidInput(format!("Failed to serialize JSON: {}", e)))?;
+    let mut out = String::new();
+    write_value(&value, &mut out);
+    Ok(out)
+}
+
+fn write_value(value: &Value, out: &mut String) {
+    match value {
+        Value::Null | Value::Bool(_) | Value::String(_) => out.push_str(&value.to_string()),
+        Value::Number(number) => match number.as_f64() {
+            Some(float) if !number.is_i64() && !number.is_u64()
+                && float.fract() == 0.0 && float.abs() < MAX_SAFE_INTEGER => {
+                out.push_str(&format!("{}", float as i64));
+            }
+            _ => out.push_str(&number.to_string()),
+        },
+        Value::Array(items) => {
+            out.push('[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_value(item, out);
+            }
+            out.push(']');
+        }
+        Value::Object(map) => {
+            let mut entries: Vec<_> = map.iter().collect();
+            entries.sort_by_key(|(key, _)| *key);
+            out.push('{');
+            for (i, (key, item)) in entries.into_iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                out.push_str(&Value::String(key.clone()).to_string());
+                out.push(':');
+                write_value(item, out);
+            }
+            out.push('}');
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::collections::HashMap;
+
+    #[derive(Serialize)]
+    struct Reversed {
+        zeta: u64,
+        alpha: Vec<f64>,
+        nested: HashMap<String, Option<String>>,
+    }
+
+    #[test]
+    fn test_canonical_json() -> Result<()> {
+        let mut nested = HashMap::new();
+        for key in ["b", "a", "é", "c\"quoted"] {
+            nested.insert(key.to_string(), Some(format!("{}\n", key)));
+        }
+        nested.insert("none".to_string(), None);
+        let value = Reversed { zeta: u64::MAX, alpha: vec![2.0, -0.5, 1e21, 12.5], nested };
+
+        let canonical = to_canonical_string(&value)?;
+        assert_eq!(
+            canonical,
+            r#"{"alpha":[2,-0.5,1e21,12.5],"nested":{"a":"a\n","b":"b\n","c\"quoted":"c\"quoted\n","none":null,"é":"é\n"},"zeta":18446744073709551615}"#
+        );
+
+        // Same data in a different field and map order gives the same bytes
+        let reordered = json!({
+            "zeta": u64::MAX,
+            "nested": {"none": null, "é": "é\n", "c\"quoted": "c\"quoted\n", "b": "b\n", "a": "a\n"},
+            "alpha": [2, -0.5, 1e21, 12.5],
+        });
+        assert_eq!(to_canonical_vec(&reordered)?, canonical.into_bytes());
+        Ok(())
+    }
+}
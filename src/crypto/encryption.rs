@@ -0,0 +1,76 @@
+//! Password based symmetric encryption (PBKDF2-SHA256 + AES-256-GCM)
+
+use crate::{QtcError, Result};
+use aes_gcm::aead::{Aead, KeyInit};
+use aes_gcm::{Aes256Gcm, Nonce};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+pub const PBKDF2_ITERATIONS: u32 = 100_000;
+const SALT_SIZE: usize = 16;
+const NONCE_SIZE: usize = 12;
+
+/// Ciphertext together with everything needed to decrypt it given the password
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EncryptedPayload {
+    pub kdf_iterations: u32,
+    pub salt: String,
+    pub nonce: String,
+    pub ciphertext: String,
+}
+
+pub fn encrypt_with_password(plaintext: &[u8], password: &str) -> Result<EncryptedPayload> {
+    let mut salt = [0u8; SALT_SIZE];
+    let mut nonce = [0u8; NONCE_SIZE];
+    rand::thread_rng().fill_bytes(&mut salt);
+    rand::thread_rng().fill_bytes(&mut nonce);
+
+    let cipher = cipher_for(password, &salt, PBKDF2_ITERATIONS)?;
+    let ciphertext = cipher.encrypt(Nonce::from_slice(&nonce), plaintext)
+        .map_err(|_| QtcError::Crypto("Encryption failed".to_string()))?;
+
+    Ok(EncryptedPayload {
+        kdf_iterations: PBKDF2_ITERATIONS,
+        salt: hex::encode(salt),
+        nonce: hex::encode(nonce),
+        ciphertext: hex::encode(ciphertext),
+    })
+}
+
+pub fn decrypt_with_password(payload: &EncryptedPayload, password: &str) -> Result<Vec<u8>> {
+    let salt = hex::decode(&payload.salt)
+        .map_err(|_| QtcError::Crypto("Invalid salt encoding".to_string()))?;
+    let nonce = hex::decode(&payload.nonce)
+        .map_err(|_| QtcError::Crypto("Invalid nonce encoding".to_string()))?;
+    let ciphertext = hex::decode(&payload.ciphertext)
+        .map_err(|_| QtcError::Crypto("Invalid ciphertext encoding".to_string()))?;
+
+    if nonce.len() != NONCE_SIZE {
+        return Err(QtcError::Crypto("Invalid nonce length".to_string()));
+    }
+
+    let cipher = cipher_for(password, &salt, payload.kdf_iterations)?;
+    cipher.decrypt(Nonce::from_slice(&nonce), ciphertext.as_ref())
+        .map_err(|_| QtcError::Crypto("Decryption failed (wrong password?)".to_string()))
+}
+
+fn cipher_for(password: &str, salt: &[u8], iterations: u32) -> Result<Aes256Gcm> {
+    let mut key = [0u8; 32];
+    pbkdf2::pbkdf2_hmac::<Sha256>(password.as_bytes(), salt, iterations, &mut key);
+    Aes256Gcm::new_from_slice(&key)
+        .map_err(|_| QtcError::Crypto("Invalid encryption key".to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encrypt_roundtrip() -> Result<()> {
+        let payload = encrypt_with_password(b"watch only data", "hunter2")?;
+        assert_eq!(decrypt_with_password(&payload, "hunter2")?, b"watch only data");
+        assert!(decrypt_with_password(&payload, "wrong").is_err());
+        Ok(())
+    }
+}
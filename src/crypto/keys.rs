@@ -4,6 +4,22 @@ use secp256k1::{Secp256k1, SecretKey, PublicKey as Secp256k1PublicKey, Message};
 use rand::{rngs::OsRng, RngCore};
 use serde::{Deserialize, Serialize};
 use std::fmt;
+use std::sync::OnceLock;
+
+/// Address prefix of the network this process runs on
+static ADDRESS_PREFIX: OnceLock<String> = OnceLock::new();
+
+/// Encode and accept addresses with `prefix` (a network's `ChainParams::address_prefix`)
+/// from now on. Only the first call counts; until then addresses are mainnet's.
+pub fn select_address_prefix(prefix: &str) {
+    if ADDRESS_PREFIX.set(prefix.to_string()).is_err() && address_prefix() != prefix {
+        log::warn!("🏷️ Address prefix is already {}, ignoring {}", address_prefix(), prefix);
+    }
+}
+
+pub fn address_prefix() -> &'static str {
+    ADDRESS_PREFIX.get().map_or("qtc", String::as_str)
+}
 
 #[derive(Debug, Clone)]
 pub struct PrivateKey {
@@ -140,8 +156,8 @@ impl PublicKey {
         // Encode with Base58
         let address = bs58::encode(data).into_string();
         
-        // Add QTC prefix
-        format!("qtc{}", address)
+        // Add the network's prefix
+        format!("{}{}", address_prefix(), address)
     }
     
     pub fn verify(&self, message: &Hash256, signature: &crate::crypto::signatures::Signature) -> Result<bool> {
@@ -201,11 +217,10 @@ impl fmt::Display for PrivateKey {
 
 // Address utilities
 pub fn address_to_hash160(address: &str) -> Result<Hash160> {
-    if !address.starts_with("qtc") {
+    let Some(address_without_prefix) = address.strip_prefix(address_prefix()) else {
         return Err(QtcError::Crypto("Invalid QTC address prefix".to_string()));
-    }
+    };
     
-    let address_without_prefix = &address[3..];
     let decoded = bs58::decode(address_without_prefix).into_vec()
         .map_err(|e| QtcError::Crypto(format!("Invalid address format: {}", e)))?;
     
@@ -4,6 +4,8 @@ pub mod keys;
 pub mod signatures;
 pub mod hash;
 pub mod pqc;
+pub mod encryption;
+pub mod canonical;
 
 pub use keys::{PrivateKey, PublicKey, KeyPair};
 pub use signatures::Signature;
@@ -1,4 +1,5 @@
 use crate::crypto::hash::{Hash256, Hash160};
+use crate::crypto::keys::address_prefix;
 use crate::{QtcError, Result};
 use pqcrypto_traits::sign::{PublicKey as PqcPublicKey, SecretKey as PqcSecretKey, SignedMessage};
 use pqcrypto_traits::kem::{SharedSecret, SecretKey as KemSecretKey, PublicKey as KemPublicKey, Ciphertext};
@@ -50,31 +51,12 @@ impl PqcKeyPair {
     pub fn address(&self) -> PqcAddress {
         let signing_public_key = self.signing_keypair.1.as_bytes().to_vec();
         let encryption_public_key = self.encryption_keypair.1.as_bytes().to_vec();
-        
-        // Create a unique hash from both public keys
-        let mut combined_keys = Vec::new();
-        combined_keys.extend_from_slice(&signing_public_key);
-        combined_keys.extend_from_slice(&encryption_public_key);
-        
-        let hash160 = Hash160::hash_sha256(&combined_keys);
-        
-        // Create address with PQC version byte
-        let mut data = Vec::new();
-        data.push(0x05); // QTC PQC address version
-        data.extend_from_slice(hash160.as_bytes());
-        
-        // Add checksum
-        let hash = Hash256::double_hash(&data);
-        data.extend_from_slice(&hash.as_bytes()[0..4]);
-        
-        // Encode with Base58
-        let address = bs58::encode(data).into_string();
-        let pqc_address = format!("qtc-pqc{}", address);
+        let address = pqc_address(&signing_public_key, &encryption_public_key);
         
         PqcAddress {
             signing_public_key,
             encryption_public_key,
-            address: pqc_address,
+            address,
         }
     }
     
@@ -153,11 +135,32 @@ impl PqcKeyPair {
     }
 }
 
+/// The address committing to a Dilithium3 signing key and a Kyber768 encryption key
+pub fn pqc_address(signing_public_key: &[u8], encryption_public_key: &[u8]) -> String {
+    // Create a unique hash from both public keys
+    let mut combined_keys = Vec::new();
+    combined_keys.extend_from_slice(signing_public_key);
+    combined_keys.extend_from_slice(encryption_public_key);
+    
+    let hash160 = Hash160::hash_sha256(&combined_keys);
+    
+    // Create address with PQC version byte
+    let mut data = Vec::new();
+    data.push(0x05); // QTC PQC address version
+    data.extend_from_slice(hash160.as_bytes());
+    
+    // Add checksum
+    let hash = Hash256::double_hash(&data);
+    data.extend_from_slice(&hash.as_bytes()[0..4]);
+    
+    // Encode with Base58
+    let address = bs58::encode(data).into_string();
+    format!("{}-pqc{}", address_prefix(), address)
+}
+
 /// Enhanced address validation for both traditional and PQC addresses
 pub fn is_valid_pqc_address(address: &str) -> bool {
-    if address.starts_with("qtc-pqc") {
-        let addr_part = &address[7..]; // Remove "qtc-pqc" prefix
-        
+    if let Some(addr_part) = address.strip_prefix(address_prefix()).and_then(|rest| rest.strip_prefix("-pqc")) {
         // Decode Base58
         if let Ok(decoded) = bs58::decode(addr_part).into_vec() {
             if decoded.len() == 25 && decoded[0] == 0x05 {
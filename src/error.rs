@@ -60,6 +60,15 @@ pub enum QtcError {
     
     #[error("P2P connection denied")]
     ConnectionDenied,
+    
+    #[error("Chain tip changed during read")]
+    StaleSnapshot,
+    
+    #[error("Chain is still syncing: work {total_work} is below the minimum {minimum}")]
+    LowWork { total_work: u128, minimum: u128 },
+    
+    #[error("Self-test failed: {0}")]
+    SelfTest(String),
 }
 
 impl From<libp2p::swarm::ConnectionDenied> for QtcError {
@@ -7,6 +7,8 @@
 //! - P2P networking
 //! - Complete CLI interface
 //! - REST API and WebSocket endpoints
+//!
+//! Applications can run a node in-process through [`Node::builder`].
 
 pub mod core;
 pub mod crypto;
@@ -17,7 +19,9 @@ pub mod storage;
 pub mod cli;
 pub mod api;
 pub mod consensus;
+pub mod node;
 pub mod error;
 pub mod config;
 
 pub use error::{QtcError, Result};
+pub use node::{Node, NodeBuilder};
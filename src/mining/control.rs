@@ -0,0 +1,189 @@
+//! The running node's miner, started and stopped on request
+//!
+//! `qtcd mine start|stop|status` reach the daemon over its control socket and
+//! end up here. The controller holds at most one `Miner`; the node runs it as
+//! its "miner" subsystem, so a refused start (chain below minimum work) is
+//! retried with the supervisor's backoff and shutdown stops it cleanly. Every
+//! miner it creates reports found blocks on one channel, so webhooks and
+//! notifications subscribed at startup keep working across restarts.
+
+use crate::core::Blockchain;
+use crate::mining::miner::{BlockMinedEvent, Miner, MiningStats};
+use crate::mining::payout::PayoutRotation;
+use crate::mining::split::PayoutSplit;
+use crate::node::ShutdownSignal;
+use crate::{QtcError, Result};
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex, MutexGuard, RwLock};
+use tokio::sync::{broadcast, Notify};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MiningStatus {
+    pub mining: bool,
+    pub stats: Option<MiningStats>, // while mining
+}
+
+pub struct MiningController {
+    blockchain: Arc<RwLock<Blockchain>>,
+    threads: usize,
+    fast_mode: bool,
+    payout: Option<Arc<PayoutRotation>>,
+    split: Option<PayoutSplit>,
+    block_events: broadcast::Sender<BlockMinedEvent>,
+    miner: Mutex<Option<Arc<Miner>>>, // the miner that should be running
+    started: Notify,
+}
+
+impl MiningController {
+    /// Miners get `threads` threads unless a start asks for another number
+    pub fn new(blockchain: Arc<RwLock<Blockchain>>, threads: usize) -> Self {
+        let (block_events, _) = broadcast::channel(64);
+        Self { blockchain, threads, fast_mode: false, payout: None, split: None, block_events, miner: Mutex::new(None), started: Notify::new() }
+    }
+
+    /// Pay to rotating addresses from this wallet when a start gives no address
+    pub fn set_payout_rotation(&mut self, payout: Arc<PayoutRotation>) {
+        self.payout = Some(payout);
+    }
+
+    /// Split rewards this way when a start gives neither an address nor payouts
+    pub fn set_payout_split(&mut self, split: PayoutSplit) {
+        self.split = Some(split);
+    }
+
+    /// Start every miner in RandomX fast mode, not only those asked for it
+    pub fn set_fast_mode(&mut self, fast_mode: bool) {
+        self.fast_mode = fast_mode;
+    }
+
+    /// Blocks found by any miner this controller runs
+    pub fn subscribe_blocks(&self) -> broadcast::Receiver<BlockMinedEvent> {
+        self.block_events.subscribe()
+    }
+
+    /// Start mining to `address`, or split between `payouts` (`address:percentage` specs)
+    pub fn start(&self, address: Option<String>, payouts: &[String], threads: Option<usize>, fast_mode: bool) -> Result<MiningStats> {
+        let mut current = self.lock();
+        if current.is_some() {
+            return Err(QtcError::Mining("Mining already started".to_string()));
+        }
+        if address.is_some() && !payouts.is_empty() {
+            return Err(QtcError::InvalidInput("Give either a mining address or payouts, not both".to_string()));
+        }
+        let split = match payouts {
+            [] if address.is_none() => self.split.clone(),
+            [] => None,
+            payouts => Some(PayoutSplit::parse(payouts)?),
+        };
+
+        let threads = threads.unwrap_or(self.threads).max(1);
+        let mut miner = match (address, split, &self.payout) {
+            (Some(address), _, _) => Miner::new(self.blockchain.clone(), address, threads)?,
+            (None, Some(split), _) => {
+                let mut miner = Miner::new(self.blockchain.clone(), split.primary_address().to_string(), threads)?;
+                miner.set_payout_split(split);
+                miner
+            }
+            (None, None, Some(payout)) => {
+                let mut miner = Miner::new(self.blockchain.clone(), payout.current(), threads)?;
+                miner.set_payout_rotation(payout.clone());
+                miner
+            }
+            (None, None, None) => return Err(QtcError::InvalidInput(
+                "Mining address, payouts or mining.payout_wallet required to start mining".to_string()
+            )),
+        };
+        miner.set_block_events(self.block_events.clone());
+        miner.set_fast_mode(fast_mode || self.fast_mode)?;
+
+        let stats = miner.get_stats();
+        *current = Some(Arc::new(miner));
+        self.started.notify_one();
+        Ok(stats)
+    }
+
+    /// Stop the running miner; returns its final stats
+    pub fn stop(&self) -> Result<MiningStats> {
+        let miner = self.lock().take()
+            .ok_or_else(|| QtcError::Mining("Mining is not running".to_string()))?;
+        miner.stop_mining();
+        Ok(miner.get_stats())
+    }
+
+    pub fn status(&self) -> MiningStatus {
+        let stats = self.lock().as_ref().map(|miner| miner.get_stats());
+        MiningStatus { mining: stats.is_some(), stats }
+    }
+
+    /// Run whichever miner is started until shutdown; returns an error if a start is refused
+    pub async fn run(self: Arc<Self>, mut shutdown: ShutdownSignal) -> Result<()> {
+        loop {
+            let miner = self.lock().clone();
+            let Some(miner) = miner else {
+                tokio::select! {
+                    _ = self.started.notified() => continue,
+                    _ = shutdown.wait() => return Ok(()),
+                }
+            };
+
+            let mining = miner.start_mining();
+            tokio::pin!(mining);
+            tokio::select! {
+                // Finishing cleanly means `stop` was called; wait for the next start
+                result = &mut mining => result?,
+                _ = shutdown.wait() => {
+                    miner.stop_mining();
+                    return mining.await;
+                }
+            }
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Option<Arc<Miner>>> {
+        match self.miner.lock() {
+            Ok(miner) => miner,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::Database;
+    use crate::wallet::Wallet;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_start_stop_and_status() -> Result<()> {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Arc::new(Database::new(temp_dir.path().join("test.db"))?);
+        let blockchain = Arc::new(RwLock::new(Blockchain::new(db.clone())?));
+        let address = Wallet::new_simple("miner".to_string(), db.clone(), blockchain.clone())?.get_addresses()[0].clone();
+        let other = Wallet::new_simple("teammate".to_string(), db, blockchain.clone())?.get_addresses()[0].clone();
+        let controller = MiningController::new(blockchain, 2);
+
+        assert!(!controller.status().mining);
+        assert!(controller.start(None, &[], None, false).is_err());
+        assert!(controller.stop().is_err());
+
+        let stats = controller.start(Some(address.clone()), &[], Some(1), false)?;
+        assert_eq!((stats.mining_address.as_str(), stats.threads, stats.fast_mode), (address.as_str(), 1, false));
+        assert!(controller.start(Some(address.clone()), &[], None, false).is_err());
+        assert!(controller.status().mining);
+
+        controller.stop()?;
+        assert!(!controller.status().mining);
+        let stats = controller.start(Some(address.clone()), &[], None, true)?;
+        assert_eq!((stats.threads, stats.fast_mode), (2, true));
+
+        // Payouts split the reward; an address as well is ambiguous
+        controller.stop()?;
+        let payouts = [format!("{}:75", address), format!("{}:25", other)];
+        assert!(controller.start(Some(address.clone()), &payouts, None, false).is_err());
+        let stats = controller.start(None, &payouts, None, false)?;
+        assert_eq!(stats.mining_address, address);
+        assert_eq!(stats.payouts.iter().map(|payout| payout.basis_points).collect::<Vec<_>>(), vec![7_500, 2_500]);
+        Ok(())
+    }
+}
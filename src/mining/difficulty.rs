@@ -1,31 +1,36 @@
+use crate::consensus::target::{Target, DIFFICULTY_ONE_BITS};
 use crate::{QtcError, Result};
 use serde::{Deserialize, Serialize};
 
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DifficultyTarget {
-    pub difficulty: u32,
+    pub difficulty: f64,
     pub target_bits: u32,
     pub target_hash: [u8; 32],
 }
 
+/// Retargets, all in compact bits (see `consensus::target`)
 #[derive(Debug, Clone)]
 pub struct DifficultyCalculator {
     pub target_block_time: u64, // seconds
     pub adjustment_interval: u64, // blocks
     pub max_adjustment_factor: f64,
-    pub min_difficulty: u32,
-    pub max_difficulty: u32,
+    pub pow_limit: u32, // bits of the easiest target a retarget may reach
+    pub lwma_window: u64, // blocks averaged by the LWMA retarget
 }
 
+/// Default LWMA window; about seven and a half hours of 7.5 minute blocks
+pub const DEFAULT_LWMA_WINDOW: u64 = 60;
+
 impl DifficultyCalculator {
     pub fn new() -> Self {
         Self {
             target_block_time: 450, // 7.5 minutes
             adjustment_interval: 10, // Adjust every 10 blocks
             max_adjustment_factor: 4.0, // Max 4x adjustment per period
-            min_difficulty: 6, // Very easy minimum difficulty for testing
-            max_difficulty: 255, // Theoretical maximum
+            pow_limit: DIFFICULTY_ONE_BITS,
+            lwma_window: DEFAULT_LWMA_WINDOW,
         }
     }
     
@@ -38,18 +43,20 @@ impl DifficultyCalculator {
             target_block_time,
             adjustment_interval,
             max_adjustment_factor,
-            min_difficulty: 6, // Very easy minimum difficulty for testing
-            max_difficulty: 255,
+            pow_limit: DIFFICULTY_ONE_BITS,
+            lwma_window: DEFAULT_LWMA_WINDOW,
         }
     }
     
-    pub fn calculate_next_difficulty(
+    /// Scale the target by how long the period's blocks actually took, at most
+    /// `max_adjustment_factor` either way
+    pub fn calculate_next_bits(
         &self,
-        current_difficulty: u32,
+        current_bits: u32,
         block_times: &[u64],
     ) -> Result<u32> {
         if block_times.len() < 2 {
-            return Ok(current_difficulty);
+            return Ok(current_bits);
         }
         
         // Calculate actual time taken for the period
@@ -57,36 +64,75 @@ impl DifficultyCalculator {
         let expected_time = self.target_block_time * (block_times.len() - 1) as u64;
         
         if expected_time == 0 {
-            return Ok(current_difficulty);
+            return Ok(current_bits);
         }
         
-        // Calculate adjustment ratio
-        let time_ratio = expected_time as f64 / actual_time as f64;
-        
         // Apply limits to prevent wild swings
-        let limited_ratio = time_ratio
-            .max(1.0 / self.max_adjustment_factor)
-            .min(self.max_adjustment_factor);
+        let limited_time = actual_time
+            .max((expected_time as f64 / self.max_adjustment_factor).ceil() as u64)
+            .min((expected_time as f64 * self.max_adjustment_factor) as u64);
         
-        // Calculate new difficulty
-        let new_difficulty_f64 = current_difficulty as f64 * limited_ratio;
-        let new_difficulty = new_difficulty_f64.round() as u32;
-        
-        // Apply absolute bounds
-        let bounded_difficulty = new_difficulty
-            .max(self.min_difficulty)
-            .min(self.max_difficulty);
+        let target = Target::from_bits(current_bits).mul_div(limited_time, expected_time);
+        let new_bits = self.bounded(target).to_bits();
         
         log::debug!(
-            "Difficulty adjustment: {} -> {} (ratio: {:.3}, actual time: {}s, expected: {}s)",
-            current_difficulty,
-            bounded_difficulty,
-            limited_ratio,
+            "Difficulty adjustment: {:#010x} -> {:#010x} (ratio: {:.3}, actual time: {}s, expected: {}s)",
+            current_bits,
+            new_bits,
+            expected_time as f64 / limited_time.max(1) as f64,
             actual_time,
             expected_time
         );
         
-        Ok(bounded_difficulty)
+        Ok(new_bits)
+    }
+    
+    /// Linearly weighted moving average retarget. `timestamps` are those of the
+    /// last `lwma_window` blocks plus the one before them, oldest first, and
+    /// `bits` those of the last `lwma_window` blocks.
+    ///
+    /// Recent solve times weigh most, so the difficulty follows hashrate within
+    /// a few blocks. Each timestamp counts as at least one second after the one
+    /// before and each solve time as at most six targets, so a miner faking
+    /// timestamps moves the average by little more than its own blocks' share.
+    pub fn calculate_lwma_bits(&self, timestamps: &[u64], bits: &[u32]) -> Result<u32> {
+        if bits.is_empty() || timestamps.len() != bits.len() + 1 {
+            return Err(QtcError::Consensus(format!(
+                "LWMA needs one more timestamp than targets, got {} and {}",
+                timestamps.len(), bits.len()
+            )));
+        }
+        
+        let target_time = self.target_block_time.max(1);
+        let blocks = bits.len() as u64;
+        let mut previous = timestamps[0];
+        let mut weighted_solve_time = 0u64;
+        for (weight, &timestamp) in timestamps[1..].iter().enumerate() {
+            let timestamp = timestamp.max(previous + 1);
+            let solve_time = (timestamp - previous).min(6 * target_time);
+            previous = timestamp;
+            weighted_solve_time += (weight as u64 + 1) * solve_time;
+        }
+        
+        // A run of instant blocks can raise the work at most tenfold
+        let weights = blocks * (blocks + 1) / 2;
+        let weighted_solve_time = weighted_solve_time.max(weights * target_time / 10);
+        let targets: Vec<Target> = bits.iter().map(|&bits| Target::from_bits(bits)).collect();
+        let next = Target::average(&targets).mul_div(weighted_solve_time, weights * target_time);
+        
+        let next_bits = self.bounded(next).to_bits();
+        log::debug!(
+            "LWMA bits: {:#010x} (average solve time {:.1}s over {} blocks)",
+            next_bits,
+            weighted_solve_time as f64 / weights as f64,
+            bits.len()
+        );
+        Ok(next_bits)
+    }
+    
+    /// `target`, made no easier than the proof of work limit
+    fn bounded(&self, target: Target) -> Target {
+        target.min(Target::from_bits(self.pow_limit))
     }
     
     fn calculate_actual_time(&self, block_times: &[u64]) -> Result<u64> {
@@ -104,74 +150,27 @@ impl DifficultyCalculator {
         Ok(last_time - first_time)
     }
     
-    pub fn difficulty_to_target(&self, difficulty: u32) -> DifficultyTarget {
-        // Convert difficulty to target hash
-        let mut target_hash = [0xFFu8; 32];
-        
-        // Set leading zeros based on difficulty
-        let zero_bytes = difficulty / 8;
-        let remaining_bits = difficulty % 8;
-        
-        // Set full zero bytes
-        for i in 0..(zero_bytes as usize).min(32) {
-            target_hash[i] = 0x00;
-        }
-        
-        // Set partial zero bits
-        if zero_bytes < 32 && remaining_bits > 0 {
-            let mask = 0xFF >> remaining_bits;
-            target_hash[zero_bytes as usize] = mask;
-        }
-        
+    pub fn bits_to_target(&self, bits: u32) -> DifficultyTarget {
+        let target = Target::from_bits(bits);
         DifficultyTarget {
-            difficulty,
-            target_bits: self.difficulty_to_bits(difficulty),
-            target_hash,
-        }
-    }
-    
-    fn difficulty_to_bits(&self, difficulty: u32) -> u32 {
-        // Simplified conversion to compact target representation
-        // Similar to Bitcoin's nBits format but adapted for our use
-        
-        if difficulty == 0 {
-            return 0x207FFFFF; // Maximum target
+            difficulty: target.difficulty(),
+            target_bits: target.to_bits(),
+            target_hash: target.to_be_bytes(),
         }
-        
-        // Calculate the compact representation
-        let _leading_zeros = difficulty / 8;
-        let shift = 256 - difficulty;
-        
-        if shift >= 256 {
-            return 0x00000000;
-        }
-        
-        // This is a simplified implementation
-        // Production would use proper compact target format
-        0x1D00FFFF >> (difficulty / 4).min(24)
     }
     
-    pub fn bits_to_difficulty(&self, bits: u32) -> u32 {
-        // Convert compact target representation back to difficulty
-        // Inverse of difficulty_to_bits
-        
-        if bits == 0 {
-            return self.max_difficulty;
-        }
-        
-        // Simplified implementation
-        let shift = 24 - (bits.leading_zeros().min(24));
-        shift * 4
+    /// Difficulty for display, relative to the easiest mainnet target
+    pub fn bits_to_difficulty(&self, bits: u32) -> f64 {
+        Target::from_bits(bits).difficulty()
     }
     
-    pub fn estimate_hashrate(&self, difficulty: u32, block_time: u64) -> f64 {
+    pub fn estimate_hashrate(&self, bits: u32, block_time: u64) -> f64 {
         if block_time == 0 {
             return 0.0;
         }
         
-        // Estimate network hashrate based on difficulty and actual block time
-        let target_hashes = 2_u64.pow(difficulty.min(32)) as f64;
-        target_hashes / block_time as f64
+        // Estimate network hashrate based on the hashes the target takes and actual block time
+        self.calculate_work(bits) as f64 / block_time as f64
     }
     
     pub fn time_to_next_adjustment(&self, current_height: u64) -> u64 {
@@ -179,18 +178,16 @@ impl DifficultyCalculator {
         self.adjustment_interval - blocks_since_adjustment
     }
     
-    pub fn validate_difficulty(&self, difficulty: u32) -> Result<()> {
-        if difficulty < self.min_difficulty {
-            return Err(QtcError::Consensus(format!(
-                "Difficulty {} below minimum {}",
-                difficulty, self.min_difficulty
-            )));
+    pub fn validate_bits(&self, bits: u32) -> Result<()> {
+        let target = Target::from_bits(bits);
+        if target == Target::ZERO {
+            return Err(QtcError::Consensus(format!("Bits {:#010x} encode no valid target", bits)));
         }
         
-        if difficulty > self.max_difficulty {
+        if target > Target::from_bits(self.pow_limit) {
             return Err(QtcError::Consensus(format!(
-                "Difficulty {} above maximum {}",
-                difficulty, self.max_difficulty
+                "Bits {:#010x} are easier than the limit {:#010x}",
+                bits, self.pow_limit
             )));
         }
         
@@ -201,17 +198,9 @@ impl DifficultyCalculator {
         height > 0 && height % self.adjustment_interval == 0
     }
     
-    pub fn calculate_work(&self, difficulty: u32) -> u128 {
-        // Calculate cumulative work for a given difficulty
-        // Higher difficulty = more work
-        
-        if difficulty == 0 {
-            return 0;
-        }
-        
-        // Simplified work calculation
-        // In production, would use proper work calculation like Bitcoin
-        2_u128.pow(difficulty.min(127))
+    /// Expected hashes to meet `bits`, as counted in chain work
+    pub fn calculate_work(&self, bits: u32) -> u128 {
+        Target::from_bits(bits).work()
     }
     
     pub fn get_adjustment_params(&self) -> (u64, u64, f64) {
@@ -233,14 +222,14 @@ impl Default for DifficultyCalculator {
 pub struct DifficultyAnalyzer;
 
 impl DifficultyAnalyzer {
-    pub fn analyze_difficulty_trend(difficulties: &[u32]) -> DifficultyTrend {
+    pub fn analyze_difficulty_trend(difficulties: &[f64]) -> DifficultyTrend {
         if difficulties.len() < 2 {
             return DifficultyTrend::Stable;
         }
         
         let recent = &difficulties[difficulties.len().saturating_sub(10)..];
-        let first = recent[0] as f64;
-        let last = recent[recent.len() - 1] as f64;
+        let first = recent[0];
+        let last = recent[recent.len() - 1];
         
         let change_ratio = last / first;
         
@@ -253,16 +242,16 @@ impl DifficultyAnalyzer {
         }
     }
     
-    pub fn predict_next_difficulty(
+    pub fn predict_next_bits(
         calculator: &DifficultyCalculator,
         recent_times: &[u64],
-        current_difficulty: u32,
+        current_bits: u32,
     ) -> Result<u32> {
-        calculator.calculate_next_difficulty(current_difficulty, recent_times)
+        calculator.calculate_next_bits(current_bits, recent_times)
     }
     
     pub fn calculate_mining_profitability(
-        difficulty: u32,
+        bits: u32,
         hashrate: f64,
         power_cost_per_kwh: f64,
         power_consumption_watts: f64,
@@ -273,7 +262,7 @@ impl DifficultyAnalyzer {
         let blocks_per_day = 24.0 * 60.0 * 60.0 / target_block_time;
         
         // Estimate blocks mined per day
-        let network_hashrate = 2_f64.powi(difficulty as i32) / target_block_time;
+        let network_hashrate = Target::from_bits(bits).work() as f64 / target_block_time;
         let hash_share = hashrate / network_hashrate;
         let blocks_per_day_mined = blocks_per_day * hash_share;
         
@@ -320,68 +309,127 @@ pub struct MiningProfitability {
 mod tests {
     use super::*;
     
+    /// Legacy difficulty 20: forty zero bits
+    const BITS: u32 = 0x1c01_0000;
+    
+    fn target(bits: u32) -> Target {
+        Target::from_bits(bits)
+    }
+    
     #[test]
     fn test_difficulty_calculation() {
         let calculator = DifficultyCalculator::new();
         
         // Test with blocks that took too long (difficulty should decrease)
         let slow_times = vec![0, 600, 1200, 1800]; // 10-minute blocks instead of 7.5
-        let new_difficulty = calculator.calculate_next_difficulty(8, &slow_times).unwrap();
-        assert!(new_difficulty < 8);
+        let new_bits = calculator.calculate_next_bits(BITS, &slow_times).unwrap();
+        assert_eq!(new_bits, target(BITS).mul_div(4, 3).to_bits());
         
         // Test with blocks that were too fast (difficulty should increase)
         let fast_times = vec![0, 300, 600, 900]; // 5-minute blocks instead of 7.5
-        let new_difficulty = calculator.calculate_next_difficulty(8, &fast_times).unwrap();
-        assert!(new_difficulty > 8);
+        let new_bits = calculator.calculate_next_bits(BITS, &fast_times).unwrap();
+        assert_eq!(new_bits, target(BITS).mul_div(2, 3).to_bits());
+        
+        let on_time = vec![0, 450, 900, 1350];
+        assert_eq!(calculator.calculate_next_bits(BITS, &on_time).unwrap(), BITS);
     }
     
     #[test]
     fn test_difficulty_bounds() {
         let calculator = DifficultyCalculator::new();
         
-        // Test minimum bound
+        // Test the proof of work limit
         let very_slow_times = vec![0, 10000, 20000, 30000]; // Very slow blocks
-        let new_difficulty = calculator.calculate_next_difficulty(1, &very_slow_times).unwrap();
-        assert_eq!(new_difficulty, calculator.min_difficulty);
+        let new_bits = calculator.calculate_next_bits(calculator.pow_limit, &very_slow_times).unwrap();
+        assert_eq!(new_bits, calculator.pow_limit);
         
         // Test maximum adjustment factor
         let extremely_fast_times = vec![0, 1, 2, 3]; // Extremely fast blocks
-        let new_difficulty = calculator.calculate_next_difficulty(8, &extremely_fast_times).unwrap();
-        assert!(new_difficulty <= 8 * calculator.max_adjustment_factor as u32);
+        let new_bits = calculator.calculate_next_bits(BITS, &extremely_fast_times).unwrap();
+        assert!(target(new_bits) >= target(BITS).mul_div(1, 4));
+        
+        assert!(calculator.validate_bits(BITS).is_ok());
+        assert!(calculator.validate_bits(0x2000_ffff).is_err());
+        assert!(calculator.validate_bits(0x0492_3456).is_err());
+    }
+    
+    #[test]
+    fn test_lwma_follows_solve_times() {
+        let calculator = DifficultyCalculator::new();
+        let target_time = calculator.target_block_time;
+        let window = calculator.lwma_window as usize;
+        let spaced = |spacing: u64| (0..=window as u64).map(|i| i * spacing).collect::<Vec<_>>();
+        let bits = vec![BITS; window];
+        
+        assert_eq!(calculator.calculate_lwma_bits(&spaced(target_time), &bits).unwrap(), BITS);
+        assert!(target(calculator.calculate_lwma_bits(&spaced(target_time / 4), &bits).unwrap()) < target(BITS));
+        assert!(target(calculator.calculate_lwma_bits(&spaced(target_time * 6), &bits).unwrap()) > target(BITS));
+        assert!(calculator.calculate_lwma_bits(&spaced(target_time), &bits[1..]).is_err());
+        
+        // Instant blocks raise the work tenfold at most
+        let instant = vec![0; window + 1];
+        let bits_after = calculator.calculate_lwma_bits(&instant, &bits).unwrap();
+        assert_eq!(bits_after, target(BITS).mul_div(1, 10).to_bits());
+    }
+    
+    #[test]
+    fn test_lwma_resists_timestamp_manipulation() {
+        let calculator = DifficultyCalculator::new();
+        let target_time = calculator.target_block_time;
+        let window = calculator.lwma_window as usize;
+        let bits = vec![BITS; window];
+        let honest: Vec<u64> = (0..=window as u64).map(|i| i * target_time).collect();
+        let change = |timestamps: &[u64]| {
+            let next = target(calculator.calculate_lwma_bits(timestamps, &bits).unwrap());
+            target(BITS).difficulty() / next.difficulty()
+        };
+        
+        // The last block claims to come two hours late, hoping to make the next one easy
+        let mut late = honest.clone();
+        *late.last_mut().unwrap() += 7_200;
+        assert!((1.0..1.2).contains(&change(&late)));
+        
+        // Stamping blocks in the past to fake a fast chain only counts each as one second
+        let mut early = honest.clone();
+        for timestamp in early.iter_mut().skip(window - 2) {
+            *timestamp = 0;
+        }
+        assert!((0.85..1.0).contains(&change(&early)));
     }
     
     #[test]
-    fn test_difficulty_to_target() {
+    fn test_bits_to_target() {
         let calculator = DifficultyCalculator::new();
         
-        let target = calculator.difficulty_to_target(16); // 2 zero bytes
-        assert_eq!(target.difficulty, 16);
-        assert_eq!(target.target_hash[0], 0x00);
-        assert_eq!(target.target_hash[1], 0x00);
-        assert_ne!(target.target_hash[2], 0x00);
+        let target = calculator.bits_to_target(BITS); // 5 zero bytes
+        assert_eq!(target.target_bits, BITS);
+        assert!(target.target_hash[..4].iter().all(|&byte| byte == 0));
+        assert_eq!(target.target_hash[4], 0x01);
+        assert_eq!(target.difficulty, (1u64 << 30) as f64);
+        assert_eq!(calculator.bits_to_difficulty(DIFFICULTY_ONE_BITS), 1.0);
     }
     
     #[test]
     fn test_hashrate_estimation() {
         let calculator = DifficultyCalculator::new();
         
-        let hashrate = calculator.estimate_hashrate(16, 450); // Target block time
+        let hashrate = calculator.estimate_hashrate(BITS, 450); // Target block time
         assert!(hashrate > 0.0);
         
         // Faster block should indicate higher hashrate
-        let faster_hashrate = calculator.estimate_hashrate(16, 225);
+        let faster_hashrate = calculator.estimate_hashrate(BITS, 225);
         assert!(faster_hashrate > hashrate);
     }
     
     #[test]
     fn test_difficulty_trend() {
-        let increasing = vec![4, 5, 6, 7, 8, 9, 10];
+        let increasing = vec![4.0, 5.0, 6.0, 7.0, 8.0, 9.0, 10.0];
         assert_eq!(DifficultyAnalyzer::analyze_difficulty_trend(&increasing), DifficultyTrend::Increasing);
         
-        let decreasing = vec![10, 9, 8, 7, 6, 5, 4];
+        let decreasing = vec![10.0, 9.0, 8.0, 7.0, 6.0, 5.0, 4.0];
         assert_eq!(DifficultyAnalyzer::analyze_difficulty_trend(&decreasing), DifficultyTrend::Decreasing);
         
-        let stable = vec![8, 8, 9, 8, 8, 9, 8];
+        let stable = vec![8.0, 8.0, 9.0, 8.0, 8.0, 9.0, 8.0];
         assert_eq!(DifficultyAnalyzer::analyze_difficulty_trend(&stable), DifficultyTrend::Stable);
     }
 }
@@ -0,0 +1,104 @@
+//! Instant block generation for regtest
+//!
+//! Regtest difficulty is a single bit of work, so blocks are found by bumping
+//! the nonce in-process rather than running RandomX. Each block takes the best
+//! paying mempool transactions, just like a mined one would.
+
+use crate::core::{Block, Blockchain, Transaction};
+use crate::crypto::hash::{Hash256, Hashable};
+use crate::{QtcError, Result};
+
+/// Mine `count` blocks on top of the tip, paying their rewards to `address`
+pub fn generate_blocks(blockchain: &mut Blockchain, count: u64, address: &str) -> Result<Vec<Hash256>> {
+    if !crate::crypto::keys::is_valid_address(address) {
+        return Err(QtcError::InvalidInput(format!("Invalid address: {}", address)));
+    }
+
+    let mut hashes = Vec::new();
+    for _ in 0..count {
+        let block = next_block(blockchain, address)?;
+        let hash = block.hash();
+        blockchain.add_block(block)?;
+        log::info!("🧪 Generated block {} at height {}", hash, blockchain.height);
+        hashes.push(hash);
+    }
+    Ok(hashes)
+}
+
+fn next_block(blockchain: &Blockchain, address: &str) -> Result<Block> {
+    let height = blockchain.height + 1;
+    let parent = blockchain.get_block(&blockchain.tip)?
+        .ok_or_else(|| QtcError::Blockchain(format!("Missing tip block {}", blockchain.tip)))?;
+
+    let transactions = {
+        let mempool = blockchain.mempool.read().unwrap();
+        let selected = mempool.select_for_block(blockchain.max_template_size(), 0);
+        let fees: u64 = selected.iter().map(|entry| entry.fee).sum();
+        let reward = blockchain.monetary_policy().coinbase_reward(height).saturating_add(fees);
+        let coinbase = Transaction::new_coinbase(address.to_string(), reward, format!("QTC regtest block {}", height));
+        let mut transactions = vec![coinbase];
+        transactions.extend(selected.iter().map(|entry| entry.tx.clone()));
+        transactions
+    };
+
+    let bits = blockchain.required_bits(height)?;
+    let mut block = Block::new(blockchain.tip, transactions, bits, height);
+    // Many blocks a second would otherwise fall foul of the median time rule
+    block.header.timestamp = block.header.timestamp.max(parent.header.timestamp + 1);
+    while !blockchain.is_valid_proof_of_work(&block) {
+        block.increment_nonce();
+    }
+    Ok(block)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::consensus::target::REGTEST_BITS;
+    use crate::core::transaction::{sign_p2pkh_input, OutPoint};
+    use crate::crypto::keys::KeyPair;
+    use crate::storage::Database;
+    use std::sync::Arc;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_generate_blocks_on_regtest() -> Result<()> {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Arc::new(Database::new(temp_dir.path().join("test.db"))?);
+        let mut blockchain = Blockchain::new(db)?;
+        blockchain.set_chain_params(Config::regtest().chain_params()?);
+        let owner = KeyPair::new()?;
+        let address = owner.address();
+
+        // Enough blocks for the median time rule to kick in
+        let hashes = generate_blocks(&mut blockchain, 15, &address)?;
+        assert_eq!(hashes.len(), 15);
+        assert_eq!(blockchain.height, 15);
+        assert_eq!(blockchain.tip, hashes[14]);
+        assert_eq!(blockchain.get_current_bits()?, REGTEST_BITS);
+
+        let tip = blockchain.get_block_by_height(15)?.unwrap();
+        let coinbase = tip.get_coinbase_transaction().unwrap();
+        assert_eq!(coinbase.outputs[0].script_pubkey, Transaction::address_to_script_pubkey(&address));
+
+        assert!(generate_blocks(&mut blockchain, 1, "not-an-address").is_err());
+
+        // Once the first coinbase matures, a pooled spend of it goes into the next block with its fee
+        generate_blocks(&mut blockchain, 86, &address)?;
+        let coin = blockchain.get_block_by_height(1)?.unwrap().transactions[0].clone();
+        let mut spend = Transaction::new();
+        spend.add_input(OutPoint::new(coin.hash(), 0), Vec::new());
+        spend.add_output(coin.outputs[0].value - 20_000, "qtc1generatepayee");
+        sign_p2pkh_input(&mut spend, 0, &owner.private_key)?;
+        blockchain.accept_to_mempool(spend.clone())?;
+
+        let hash = generate_blocks(&mut blockchain, 1, &address)?[0];
+        let block = blockchain.get_block(&hash)?.unwrap();
+        assert_eq!(block.transactions[1].hash(), spend.hash());
+        let reward = blockchain.monetary_policy().coinbase_reward(block.header.height);
+        assert_eq!(block.transactions[0].total_output_value(), reward + 20_000);
+        assert!(blockchain.mempool.read().unwrap().is_empty());
+        Ok(())
+    }
+}
@@ -1,10 +1,15 @@
+use crate::api::metrics::metrics;
 use crate::core::{Block, Blockchain};
-use crate::mining::randomx::RandomXMiner;
+use crate::consensus::monetary::MonetaryUtils;
+use crate::mining::randomx::{self, PooledVm, RandomXVmPool};
 use crate::mining::difficulty::DifficultyCalculator;
-use crate::crypto::hash::Hash256;
+use crate::mining::payout::PayoutRotation;
+use crate::mining::split::PayoutSplit;
+use crate::crypto::hash::{Hash256, Hashable};
 use crate::{QtcError, Result};
 use std::sync::{Arc, RwLock, atomic::{AtomicBool, AtomicU64, Ordering}};
 use std::time::{Duration, Instant};
+use tokio::sync::broadcast;
 use tokio::time::sleep;
 use serde::{Deserialize, Serialize};
 
@@ -15,10 +20,33 @@ pub struct MiningStats {
     pub total_hashes: u64,
     pub blocks_mined: u64,
     pub last_block_time: Option<u64>,
-    pub current_difficulty: u32,
+    pub current_difficulty: f64,
     pub mining_address: String,
     pub threads: usize,
     pub uptime_seconds: u64,
+    pub total_mined_value: u64, // sum of coinbase rewards for blocks we found
+    #[serde(default)]
+    pub fast_mode: bool, // hashing with the full RandomX dataset
+    #[serde(default)]
+    pub payouts: Vec<PayoutAccrual>, // when the reward is split between addresses
+}
+
+/// What one address of a payout split has been paid by the blocks found so far
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PayoutAccrual {
+    pub address: String,
+    pub basis_points: u32,
+    pub accrued: u64,
+}
+
+/// Published whenever a block found by this miner is accepted into the chain
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BlockMinedEvent {
+    pub hash: String,
+    pub height: u64,
+    pub reward: u64,
+    pub time_to_find_secs: f64,
+    pub timestamp: u64,
 }
 
 #[derive(Debug, Clone)]
@@ -31,13 +59,17 @@ pub struct MiningResult {
 
 pub struct Miner {
     blockchain: Arc<RwLock<Blockchain>>,
-    randomx_miner: Arc<RandomXMiner>,
+    vm_pool: Arc<RandomXVmPool>, // shared by all mining threads
     _difficulty_calc: DifficultyCalculator,
-    mining_address: String,
+    mining_address: Arc<RwLock<String>>,
+    payout: Option<Arc<PayoutRotation>>,
+    split: Option<Arc<PayoutSplit>>,
     is_mining: Arc<AtomicBool>,
     stats: Arc<RwLock<MiningStats>>,
     hash_counter: Arc<AtomicU64>,
     blocks_mined: Arc<AtomicU64>,
+    last_found: Arc<RwLock<Instant>>,
+    block_events: broadcast::Sender<BlockMinedEvent>,
     start_time: Instant,
     threads: usize,
 }
@@ -53,13 +85,13 @@ impl Miner {
             return Err(QtcError::Mining("Invalid mining address".to_string()));
         }
         
-        // Initialize RandomX with current blockchain tip as seed
+        // Light mode until asked for fast mode, so a miner that never runs costs no dataset
         let seed = {
             let bc = blockchain.read().unwrap();
-            bc.tip.as_bytes().to_vec()
+            Self::seed_key(&bc, bc.height + 1)?
         };
         
-        let randomx_miner = Arc::new(RandomXMiner::new(&seed, Some(threads), true)?);
+        let vm_pool = Arc::new(RandomXVmPool::new(&seed, false)?);
         let difficulty_calc = DifficultyCalculator::new();
         
         let stats = MiningStats {
@@ -68,35 +100,102 @@ impl Miner {
             total_hashes: 0,
             blocks_mined: 0,
             last_block_time: None,
-            current_difficulty: 4,
+            current_difficulty: 0.0,
             mining_address: mining_address.clone(),
             threads,
             uptime_seconds: 0,
+            total_mined_value: 0,
+            fast_mode: false,
+            payouts: Vec::new(),
         };
+        let (block_events, _) = broadcast::channel(64);
         
         Ok(Self {
             blockchain,
-            randomx_miner,
+            vm_pool,
             _difficulty_calc: difficulty_calc,
-            mining_address,
+            mining_address: Arc::new(RwLock::new(mining_address)),
+            payout: None,
+            split: None,
             is_mining: Arc::new(AtomicBool::new(false)),
             stats: Arc::new(RwLock::new(stats)),
             hash_counter: Arc::new(AtomicU64::new(0)),
             blocks_mined: Arc::new(AtomicU64::new(0)),
+            last_found: Arc::new(RwLock::new(Instant::now())),
+            block_events,
             start_time: Instant::now(),
             threads,
         })
     }
     
+    /// Move coinbase payouts along a wallet's fresh addresses instead of one fixed address
+    pub fn set_payout_rotation(&mut self, payout: Arc<PayoutRotation>) {
+        let address = payout.current();
+        self.stats.write().unwrap().mining_address = address.clone();
+        *self.mining_address.write().unwrap() = address;
+        self.payout = Some(payout);
+    }
+    
+    /// Pay every block to several addresses, each its share of the reward
+    pub fn set_payout_split(&mut self, split: PayoutSplit) {
+        let address = split.primary_address().to_string();
+        {
+            let mut stats = self.stats.write().unwrap();
+            stats.mining_address = address.clone();
+            stats.payouts = split.shares().iter()
+                .map(|share| PayoutAccrual { address: share.address.clone(), basis_points: share.basis_points, accrued: 0 })
+                .collect();
+        }
+        *self.mining_address.write().unwrap() = address;
+        self.payout = None;
+        self.split = Some(Arc::new(split));
+    }
+    
+    /// Hash with the full RandomX dataset, shared by all threads, instead of
+    /// computing dataset items on the fly. Takes effect on the next start.
+    pub fn set_fast_mode(&mut self, fast_mode: bool) -> Result<()> {
+        if self.vm_pool.is_fast_mode() == fast_mode {
+            return Ok(());
+        }
+        if self.is_mining() {
+            return Err(QtcError::Mining("Cannot change the RandomX mode while mining".to_string()));
+        }
+        self.vm_pool = Arc::new(RandomXVmPool::new(&self.vm_pool.key(), fast_mode)?);
+        self.stats.write().unwrap().fast_mode = fast_mode;
+        Ok(())
+    }
+    
+    /// The RandomX key for mining at `height`: the hash of its seed block
+    fn seed_key(blockchain: &Blockchain, height: u64) -> Result<Vec<u8>> {
+        let seed_height = randomx::seed_height(height);
+        let header = blockchain.get_block_header_by_height(seed_height)?
+            .ok_or_else(|| QtcError::Mining(format!("Missing RandomX seed block at height {}", seed_height)))?;
+        Ok(header.hash().as_bytes().to_vec())
+    }
+    
+    /// Publish found blocks on a channel shared with other miners instead of this miner's own
+    pub fn set_block_events(&mut self, block_events: broadcast::Sender<BlockMinedEvent>) {
+        self.block_events = block_events;
+    }
+    
     pub async fn start_mining(&self) -> Result<()> {
         if self.is_mining.load(Ordering::Relaxed) {
             return Err(QtcError::Mining("Mining already started".to_string()));
         }
         
-        log::info!("🚀 Starting QTC mining with {} threads", self.threads);
-        log::info!("⛏️  Mining to address: {}", self.mining_address);
+        if self.blockchain.read().unwrap().is_low_work() {
+            return Err(QtcError::Mining("Chain work is below minimum_chain_work, refusing to mine on an unsynced tip".to_string()));
+        }
+        
+        log::info!("🚀 Starting QTC mining with {} threads in {} mode",
+            self.threads, if self.vm_pool.is_fast_mode() { "fast" } else { "light" });
+        match &self.split {
+            Some(split) => log::info!("⛏️  Splitting rewards between {}", split),
+            None => log::info!("⛏️  Mining to address: {}", self.mining_address.read().unwrap()),
+        }
         
         self.is_mining.store(true, Ordering::Relaxed);
+        *self.last_found.write().unwrap() = Instant::now();
         
         // Update stats
         {
@@ -141,6 +240,11 @@ impl Miner {
         self.is_mining.load(Ordering::Relaxed)
     }
     
+    /// Receive a `BlockMinedEvent` for every block this miner gets accepted
+    pub fn subscribe_blocks(&self) -> broadcast::Receiver<BlockMinedEvent> {
+        self.block_events.subscribe()
+    }
+    
     pub fn get_stats(&self) -> MiningStats {
         let stats = self.stats.read().unwrap();
         let mut stats_copy = stats.clone();
@@ -156,17 +260,16 @@ impl Miner {
     async fn spawn_mining_thread(&self, thread_id: usize) -> Result<tokio::task::JoinHandle<()>> {
         let blockchain = self.blockchain.clone();
         let mining_address = self.mining_address.clone();
+        let payout = self.payout.clone();
+        let split = self.split.clone();
         let is_mining = self.is_mining.clone();
         let hash_counter = self.hash_counter.clone();
         let blocks_mined = self.blocks_mined.clone();
         let stats = self.stats.clone();
-        
-        // Create RandomX miner for this thread
-        let seed = {
-            let bc = blockchain.read().unwrap();
-            bc.tip.as_bytes().to_vec()
-        };
-        let thread_miner = RandomXMiner::new(&seed, None, false)?; // Light mode for worker threads
+        let last_found = self.last_found.clone();
+        let block_events = self.block_events.clone();
+        let vm_pool = self.vm_pool.clone();
+        let mut vm = self.vm_pool.vm()?;
         
         let handle = tokio::spawn(async move {
             log::info!("⛏️  Mining thread {} started", thread_id);
@@ -174,28 +277,82 @@ impl Miner {
             let mut nonce_start = thread_id as u64 * 1000000; // Spread nonce ranges
             
             while is_mining.load(Ordering::Relaxed) {
+                let address = mining_address.read().unwrap().clone();
                 match Self::mine_single_attempt(
                     &blockchain,
-                    &thread_miner,
-                    &mining_address,
+                    &vm_pool,
+                    &mut vm,
+                    &address,
+                    split.as_deref(),
                     nonce_start,
                     &hash_counter,
                 ).await {
                     Ok(Some(result)) => {
                         log::info!("🎉 Block mined by thread {}! Hash: {}", thread_id, result.hash);
                         
+                        let height = result.block.header.height;
+                        let reward = result.block.get_coinbase_transaction()
+                            .map(|tx| tx.total_output_value())
+                            .unwrap_or(0);
+                        
                         // Add block to blockchain
-                        {
+                        let added = {
                             let mut bc = blockchain.write().unwrap();
-                            if let Err(e) = bc.add_block(result.block) {
-                                log::error!("Failed to add mined block: {}", e);
-                            } else {
+                            bc.add_block(result.block)
+                        };
+                        
+                        match added {
+                            Err(e) => log::error!("Failed to add mined block: {}", e),
+                            Ok(()) => {
                                 blocks_mined.fetch_add(1, Ordering::Relaxed);
+                                metrics().blocks_mined.inc();
+                                
+                                let time_to_find = {
+                                    let mut last_found = last_found.write().unwrap();
+                                    let elapsed = last_found.elapsed();
+                                    *last_found = Instant::now();
+                                    elapsed
+                                };
+                                
+                                let event = BlockMinedEvent {
+                                    hash: result.hash.to_hex(),
+                                    height,
+                                    reward,
+                                    time_to_find_secs: time_to_find.as_secs_f64(),
+                                    timestamp: chrono::Utc::now().timestamp() as u64,
+                                };
                                 
                                 // Update stats
-                                {
+                                let total_mined_value = {
                                     let mut stats = stats.write().unwrap();
-                                    stats.last_block_time = Some(chrono::Utc::now().timestamp() as u64);
+                                    stats.last_block_time = Some(event.timestamp);
+                                    stats.total_mined_value += reward;
+                                    if let Some(split) = &split {
+                                        for (accrual, (_, amount)) in stats.payouts.iter_mut().zip(split.amounts(reward)) {
+                                            accrual.accrued += amount;
+                                        }
+                                    }
+                                    stats.total_mined_value
+                                };
+                                
+                                log::info!("💰 Mined block {} worth {} QTC in {:.1}s (total mined: {} QTC)",
+                                    height,
+                                    MonetaryUtils::satoshis_to_qtc(reward),
+                                    event.time_to_find_secs,
+                                    MonetaryUtils::satoshis_to_qtc(total_mined_value));
+                                
+                                // No subscribers is fine, events are best effort
+                                let _ = block_events.send(event);
+                                
+                                if let Some(payout) = &payout {
+                                    match payout.block_found() {
+                                        Ok(Some(next)) => {
+                                            stats.write().unwrap().mining_address = next.clone();
+                                            *mining_address.write().unwrap() = next;
+                                        }
+                                        Ok(None) => {}
+                                        Err(e) => log::error!("Failed to rotate payout address, keeping {}: {}", address, e),
+                                    }
                                 }
                             }
                         }
@@ -225,35 +382,37 @@ impl Miner {
     
     async fn mine_single_attempt(
         blockchain: &Arc<RwLock<Blockchain>>,
-        miner: &RandomXMiner,
+        vm_pool: &RandomXVmPool,
+        vm: &mut PooledVm,
         mining_address: &str,
+        split: Option<&PayoutSplit>,
         nonce_start: u64,
         hash_counter: &Arc<AtomicU64>,
     ) -> Result<Option<MiningResult>> {
         // Get current blockchain state
-        let (mut block, difficulty) = {
+        let (mut block, bits, seed) = {
             let bc = blockchain.read().unwrap();
             let height = bc.height + 1;
-            let difficulty = bc.get_current_difficulty()?;
+            let bits = bc.bits_for_next_block(chrono::Utc::now().timestamp() as u64)?;
+            let seed = Self::seed_key(&bc, height)?;
             
             // Create coinbase transaction
-            let reward = crate::consensus::monetary::MonetaryPolicy::new().coinbase_reward(height);
-            let coinbase_tx = crate::core::Transaction::new_coinbase(
-                mining_address.to_string(),
-                reward,
-                format!("QTC Block {} mined by thread", height),
-            );
+            let reward = bc.monetary_policy().coinbase_reward(height);
+            let coinbase_tx = Self::coinbase(split, mining_address, reward, format!("QTC Block {} mined by thread", height));
             
             let block = Block::new(
                 bc.tip,
                 vec![coinbase_tx],
-                difficulty,
+                bits,
                 height,
             );
             
-            (block, difficulty)
+            (block, bits, seed)
         };
         
+        // The first thread past an epoch boundary rebuilds for everyone
+        vm_pool.rotate(&seed)?;
+        
         // Try mining with different nonces
         for nonce_offset in 0..1000 {
             let nonce = nonce_start + nonce_offset;
@@ -264,14 +423,14 @@ impl Miner {
                 .map_err(|e| QtcError::Mining(format!("Failed to serialize block header: {}", e)))?;
             
             // Hash with RandomX
-            let randomx_hash = miner.hash(&header_data)?;
+            let randomx_hash = vm.hash(&header_data)?;
             hash_counter.fetch_add(1, Ordering::Relaxed);
             
             // Convert RandomX hash to our Hash256 format
             let block_hash = Hash256::new(*randomx_hash.as_bytes());
             
             // Check if it meets difficulty
-            if randomx_hash.meets_difficulty(difficulty) {
+            if randomx_hash.meets_target(bits) {
                 return Ok(Some(MiningResult {
                     block,
                     nonce,
@@ -284,6 +443,14 @@ impl Miner {
         Ok(None)
     }
     
+    /// The coinbase paying `reward`, split if the miner splits its rewards
+    fn coinbase(split: Option<&PayoutSplit>, mining_address: &str, reward: u64, message: String) -> crate::core::Transaction {
+        match split {
+            Some(split) => split.coinbase(reward, message),
+            None => crate::core::Transaction::new_coinbase(mining_address.to_string(), reward, message),
+        }
+    }
+    
     async fn spawn_stats_updater(&self) -> tokio::task::JoinHandle<()> {
         let is_mining = self.is_mining.clone();
         let hash_counter = self.hash_counter.clone();
@@ -308,6 +475,7 @@ impl Miner {
                     {
                         let mut stats = stats.write().unwrap();
                         stats.hashrate = hashrate;
+                        metrics().hashrate.set(hashrate);
                         
                         // Update current difficulty
                         if let Ok(bc) = blockchain.read() {
@@ -334,15 +502,17 @@ impl Miner {
         log::info!("⛏️  Mining single block...");
         
         // Get current blockchain state
-        let (mut block, difficulty) = {
+        let (mut block, bits) = {
             let bc = self.blockchain.read().unwrap();
             let height = bc.height + 1;
-            let difficulty = bc.get_current_difficulty()?;
+            let bits = bc.bits_for_next_block(chrono::Utc::now().timestamp() as u64)?;
+            self.vm_pool.rotate(&Self::seed_key(&bc, height)?)?;
             
             // Create coinbase transaction
-            let reward = crate::consensus::monetary::MonetaryPolicy::new().coinbase_reward(height);
-            let coinbase_tx = crate::core::Transaction::new_coinbase(
-                self.mining_address.clone(),
+            let reward = bc.monetary_policy().coinbase_reward(height);
+            let coinbase_tx = Self::coinbase(
+                self.split.as_deref(),
+                &self.mining_address.read().unwrap(),
                 reward,
                 format!("QTC Block {} - single mine", height),
             );
@@ -350,14 +520,15 @@ impl Miner {
             let block = Block::new(
                 bc.tip,
                 vec![coinbase_tx],
-                difficulty,
+                bits,
                 height,
             );
             
-            (block, difficulty)
+            (block, bits)
         };
         
         // Mine the block
+        let mut vm = self.vm_pool.vm()?;
         let start_time = Instant::now();
         let mut nonce = 0u64;
         
@@ -369,11 +540,11 @@ impl Miner {
                 .map_err(|e| QtcError::Mining(format!("Failed to serialize block header: {}", e)))?;
             
             // Hash with RandomX
-            let randomx_hash = self.randomx_miner.hash(&header_data)?;
+            let randomx_hash = vm.hash(&header_data)?;
             self.hash_counter.fetch_add(1, Ordering::Relaxed);
             
             // Check if it meets difficulty
-            if randomx_hash.meets_difficulty(difficulty) {
+            if randomx_hash.meets_target(bits) {
                 let elapsed = start_time.elapsed();
                 let hashrate = nonce as f64 / elapsed.as_secs_f64();
                 
@@ -403,7 +574,7 @@ impl Miner {
             return Err(QtcError::Mining("Invalid mining address".to_string()));
         }
         
-        self.mining_address = new_address.clone();
+        *self.mining_address.write().unwrap() = new_address.clone();
         
         // Update stats
         {
@@ -411,7 +582,7 @@ impl Miner {
             stats.mining_address = new_address;
         }
         
-        log::info!("📍 Mining address updated to: {}", self.mining_address);
+        log::info!("📍 Mining address updated to: {}", self.mining_address.read().unwrap());
         Ok(())
     }
     
@@ -424,8 +595,10 @@ impl Miner {
         
         // Rough estimation based on current difficulty and hashrate
         let _target_time_seconds = 450.0; // 7.5 minutes target
-        let difficulty_factor = 2_u64.pow(stats.current_difficulty);
-        let estimated_hashes_needed = difficulty_factor as f64;
+        let estimated_hashes_needed = match self.blockchain.read().unwrap().get_current_bits() {
+            Ok(bits) => Blockchain::block_work(bits) as f64,
+            Err(_) => return None,
+        };
         let estimated_seconds = estimated_hashes_needed / stats.hashrate;
         
         Some(Duration::from_secs_f64(estimated_seconds))
@@ -437,9 +610,10 @@ impl Miner {
         let start_time = Instant::now();
         let mut hashes = 0u64;
         let test_data = b"benchmark test data for randomx performance measurement";
+        let mut vm = self.vm_pool.vm()?;
         
         while start_time.elapsed() < duration {
-            let _ = self.randomx_miner.hash(test_data)?;
+            let _ = vm.hash(test_data)?;
             hashes += 1;
             
             if hashes % 100 == 0 {
@@ -2,8 +2,21 @@
 
 pub mod randomx;
 pub mod miner;
+pub mod control;
 pub mod difficulty;
+pub mod generate;
+pub mod payout;
+pub mod pool;
+pub mod simulation;
+pub mod split;
+pub mod template;
 
 pub use randomx::{RandomXHash, RandomXMiner};
-pub use miner::{Miner, MiningResult, MiningStats};
+pub use miner::{BlockMinedEvent, Miner, MiningResult, MiningStats, PayoutAccrual};
+pub use control::{MiningController, MiningStatus};
 pub use difficulty::{DifficultyCalculator, DifficultyTarget};
+pub use generate::generate_blocks;
+pub use payout::PayoutRotation;
+pub use pool::{PayoutScheme, ShareOutcome, ShareTracker};
+pub use split::{PayoutShare, PayoutSplit};
+pub use template::{BlockTemplate, BlockTemplateBuilder};
@@ -0,0 +1,110 @@
+//! Coinbase payout rotation
+//!
+//! Mining every block to one address links all of a miner's income together.
+//! With a payout wallet configured the miner instead pays each run of
+//! `every` blocks to a fresh receive address reserved from that wallet.
+
+use crate::core::Blockchain;
+use crate::storage::Database;
+use crate::{QtcError, Result};
+use std::sync::{Arc, Mutex, RwLock};
+
+const PAYOUT_LABEL: &str = "mining payout";
+
+#[derive(Debug)]
+struct RotationState {
+    address: String,
+    blocks: u64, // found with the current address
+}
+
+#[derive(Debug)]
+pub struct PayoutRotation {
+    db: Arc<Database>,
+    blockchain: Arc<RwLock<Blockchain>>,
+    wallet: String,
+    every: u64,
+    state: Mutex<RotationState>,
+}
+
+impl PayoutRotation {
+    /// Reserve the first payout address from `wallet`
+    pub fn new(db: Arc<Database>, blockchain: Arc<RwLock<Blockchain>>, wallet: &str, every: u64) -> Result<Self> {
+        if every == 0 {
+            return Err(QtcError::InvalidInput("payout_rotation_blocks must be at least 1".to_string()));
+        }
+
+        let address = Self::fresh_address(&db, &blockchain, wallet)?;
+        log::info!("🔄 Paying mined blocks to wallet {} (new address every {} block(s))", wallet, every);
+
+        Ok(Self {
+            db,
+            blockchain,
+            wallet: wallet.to_string(),
+            every,
+            state: Mutex::new(RotationState { address, blocks: 0 }),
+        })
+    }
+
+    pub fn current(&self) -> String {
+        self.lock().address.clone()
+    }
+
+    /// Count a block paid to the current address; returns the next address once
+    /// the current one has received its share
+    pub fn block_found(&self) -> Result<Option<String>> {
+        let mut state = self.lock();
+        state.blocks += 1;
+        if state.blocks < self.every {
+            return Ok(None);
+        }
+
+        let address = Self::fresh_address(&self.db, &self.blockchain, &self.wallet)?;
+        log::info!("🔄 Rotated mining payout address to {}", address);
+        *state = RotationState { address: address.clone(), blocks: 0 };
+        Ok(Some(address))
+    }
+
+    fn fresh_address(db: &Arc<Database>, blockchain: &Arc<RwLock<Blockchain>>, wallet: &str) -> Result<String> {
+        let mut wallet = db.load_wallet(wallet, blockchain.clone())?;
+        let reservation = wallet.reserve_receive_address(Some(PAYOUT_LABEL.to_string()))?;
+        Ok(reservation.address)
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, RotationState> {
+        match self.state.lock() {
+            Ok(state) => state,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wallet::Wallet;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_payout_rotation() -> Result<()> {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Arc::new(Database::new(temp_dir.path().join("test.db"))?);
+        let blockchain = Arc::new(RwLock::new(Blockchain::new(db.clone())?));
+        Wallet::new_simple("miner".to_string(), db.clone(), blockchain.clone())?.save()?;
+
+        let rotation = PayoutRotation::new(db.clone(), blockchain.clone(), "miner", 2)?;
+        let first = rotation.current();
+        assert_eq!(rotation.block_found()?, None);
+        let second = rotation.block_found()?.unwrap();
+        assert_ne!(second, first);
+        assert_eq!(rotation.current(), second);
+
+        // Payout addresses belong to the wallet and are never offered again
+        let wallet = db.load_wallet("miner", blockchain.clone())?;
+        for address in [&first, &second] {
+            assert!(wallet.addresses.contains_key(address));
+            assert!(db.is_address_reserved("miner", address)?);
+        }
+        assert!(PayoutRotation::new(db, blockchain, "miner", 0).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,372 @@
+//! Share accounting and payouts for a private pool
+//!
+//! Miners build blocks from `getblocktemplate` whose coinbase pays the pool
+//! wallet and submit every one that beats the share difficulty with the
+//! `submitshare` RPC. Each accepted share is credited with the work its
+//! difficulty stands for, so miners on a higher share difficulty earn more per
+//! share. When a share also meets the network difficulty it becomes a block and
+//! closes a round: the block reward is split over the round's shares
+//! (proportional) or the last N shares (PPLNS), and once the coinbase matures
+//! the owed amounts go out as one unsigned batch transaction from the pool wallet.
+
+use crate::config::PoolConfig;
+use crate::consensus::monetary::is_coinbase_mature;
+use crate::core::{Block, Blockchain, Transaction};
+use crate::crypto::hash::{Hash256, Hashable};
+use crate::crypto::keys::is_valid_address;
+use crate::storage::Database;
+use crate::wallet::coin_selection::DUST_THRESHOLD;
+use crate::wallet::{Psbt, Wallet};
+use crate::{QtcError, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PayoutScheme {
+    #[default]
+    Proportional, // shares since the previous block
+    Pplns,        // the last `pplns_window` shares, whichever round they fell in
+}
+
+impl fmt::Display for PayoutScheme {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PayoutScheme::Proportional => write!(f, "proportional"),
+            PayoutScheme::Pplns => write!(f, "pplns"),
+        }
+    }
+}
+
+impl FromStr for PayoutScheme {
+    type Err = QtcError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "proportional" | "prop" => Ok(PayoutScheme::Proportional),
+            "pplns" => Ok(PayoutScheme::Pplns),
+            _ => Err(QtcError::InvalidInput(format!("Unknown payout scheme '{}': use proportional or pplns", s))),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Share {
+    pub id: u64,
+    pub worker: String,
+    pub payout_address: String,
+    pub hash: Hash256,
+    pub height: u64,
+    pub difficulty: u32,
+    pub work: u128, // what the share difficulty is worth, as in chain work
+    pub time: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "result", rename_all = "snake_case")]
+pub enum ShareOutcome {
+    Accepted { work: u128, block: bool }, // `block`: good enough to submit as a block too
+    Stale,                                // built on a block that is no longer the tip
+    Duplicate,
+    Rejected { reason: String },
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct WorkerStats {
+    pub worker: String,
+    pub accepted: u64,
+    pub stale: u64,
+    pub duplicate: u64,
+    pub rejected: u64,
+    pub work: u128,
+    pub last_share: u64, // unix seconds
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Payout {
+    pub address: String,
+    pub amount: u64,
+}
+
+/// A block the pool found and what it owes for it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PoolRound {
+    pub block_hash: Hash256,
+    pub height: u64,
+    pub reward: u64,
+    pub scheme: PayoutScheme,
+    pub last_share: u64, // id of the newest share the round paid for
+    pub payouts: Vec<Payout>,
+    pub payout_txid: Option<Hash256>,
+}
+
+/// Split `reward`, less the pool fee, over `shares` by work; amounts too small to send stay with the pool
+pub fn calculate_payouts(shares: &[Share], reward: u64, fee_basis_points: u64) -> Vec<Payout> {
+    let total_work: u128 = shares.iter().map(|share| share.work).sum();
+    if total_work == 0 {
+        return Vec::new();
+    }
+    let distributable = reward as u128 - reward as u128 * fee_basis_points.min(10_000) as u128 / 10_000;
+
+    let mut work_by_address: BTreeMap<&str, u128> = BTreeMap::new();
+    for share in shares {
+        *work_by_address.entry(&share.payout_address).or_default() += share.work;
+    }
+    work_by_address.into_iter()
+        .map(|(address, work)| Payout { address: address.to_string(), amount: (distributable * work / total_work) as u64 })
+        .filter(|payout| payout.amount >= DUST_THRESHOLD)
+        .collect()
+}
+
+pub struct ShareTracker {
+    db: Arc<Database>,
+    config: PoolConfig,
+    pool_scripts: HashSet<Vec<u8>>, // outputs a share's coinbase may pay
+    seen: Mutex<(Hash256, HashSet<Hash256>)>, // shares on the current tip
+}
+
+impl ShareTracker {
+    /// Track shares for blocks that pay `pool_wallet`, which must be the wallet named in `config`
+    pub fn new(db: Arc<Database>, config: PoolConfig, pool_wallet: &Wallet) -> Result<Self> {
+        if config.share_difficulty == 0 {
+            return Err(QtcError::InvalidInput("mining.pool.share_difficulty must be at least 1".to_string()));
+        }
+        let pool_scripts = pool_wallet.get_addresses().iter()
+            .map(|address| Transaction::address_to_script_pubkey(address))
+            .collect();
+
+        Ok(Self { db, config, pool_scripts, seen: Mutex::new((Hash256::zero(), HashSet::new())) })
+    }
+
+    pub fn config(&self) -> &PoolConfig {
+        &self.config
+    }
+
+    /// Check and credit a share. `difficulty` is the share difficulty the worker mined at,
+    /// at least the pool's; a higher one earns proportionally more work.
+    pub fn submit(
+        &self,
+        blockchain: &Blockchain,
+        worker: &str,
+        payout_address: &str,
+        block: &Block,
+        difficulty: Option<u32>,
+    ) -> Result<ShareOutcome> {
+        if !is_valid_address(payout_address) {
+            return Err(QtcError::InvalidInput(format!("Invalid payout address: {}", payout_address)));
+        }
+        let difficulty = difficulty.unwrap_or(self.config.share_difficulty);
+        let hash = block.hash();
+
+        let outcome = if block.header.previous_hash != blockchain.tip || block.header.height != blockchain.height + 1 {
+            ShareOutcome::Stale
+        } else if !self.first_sighting(&blockchain.tip, hash) {
+            ShareOutcome::Duplicate
+        } else {
+            match self.check_share(block, difficulty) {
+                Err(reason) => ShareOutcome::Rejected { reason },
+                Ok(()) => ShareOutcome::Accepted {
+                    work: Blockchain::block_work(difficulty),
+                    block: Blockchain::header_meets_target(&block.header),
+                },
+            }
+        };
+
+        let time = chrono::Utc::now().timestamp() as u64;
+        if let ShareOutcome::Accepted { work, .. } = &outcome {
+            self.db.save_pool_share(Share {
+                id: 0, // assigned when saved
+                worker: worker.to_string(),
+                payout_address: payout_address.to_string(),
+                hash,
+                height: block.header.height,
+                difficulty,
+                work: *work,
+                time,
+            })?;
+        }
+        self.db.update_pool_worker(worker, |stats| {
+            match &outcome {
+                ShareOutcome::Accepted { work, .. } => {
+                    stats.accepted += 1;
+                    stats.work += work;
+                }
+                ShareOutcome::Stale => stats.stale += 1,
+                ShareOutcome::Duplicate => stats.duplicate += 1,
+                ShareOutcome::Rejected { .. } => stats.rejected += 1,
+            }
+            stats.last_share = time;
+        })?;
+        Ok(outcome)
+    }
+
+    /// Whether `hash` is new on `tip`; the set starts over whenever the tip moves
+    fn first_sighting(&self, tip: &Hash256, hash: Hash256) -> bool {
+        let mut seen = match self.seen.lock() {
+            Ok(seen) => seen,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        if &seen.0 != tip {
+            *seen = (*tip, HashSet::new());
+        }
+        seen.1.insert(hash)
+    }
+
+    fn check_share(&self, block: &Block, difficulty: u32) -> std::result::Result<(), String> {
+        if difficulty < self.config.share_difficulty {
+            return Err(format!("difficulty {} is below the pool minimum of {}", difficulty, self.config.share_difficulty));
+        }
+        if !Blockchain::hash_meets_target(&block.hash(), difficulty) {
+            return Err(format!("hash does not meet difficulty {}", difficulty));
+        }
+        if Block::calculate_merkle_root(&block.transactions) != block.header.merkle_root {
+            return Err("merkle root does not match the transactions".to_string());
+        }
+        let coinbase = block.transactions.first()
+            .filter(|tx| tx.is_coinbase())
+            .ok_or("block has no coinbase")?;
+        if !coinbase.outputs.iter().all(|output| self.pool_scripts.contains(&output.script_pubkey)) {
+            return Err("coinbase does not pay the pool wallet".to_string());
+        }
+        Ok(())
+    }
+
+    /// Close the round ended by `block`, which the chain has accepted
+    pub fn block_found(&self, block: &Block) -> Result<PoolRound> {
+        let last_share = self.db.last_pool_share_id()?.unwrap_or(0);
+        let shares = match self.config.scheme {
+            PayoutScheme::Proportional => {
+                let previous = self.db.get_pool_rounds()?.iter().map(|round| round.last_share).max().unwrap_or(0);
+                self.db.get_pool_shares(previous + 1..=last_share)?
+            }
+            PayoutScheme::Pplns => self.db.get_recent_pool_shares(last_share, self.config.pplns_window as usize)?,
+        };
+
+        let reward = block.transactions.first().map_or(0, |coinbase| coinbase.total_output_value());
+        let round = PoolRound {
+            block_hash: block.hash(),
+            height: block.header.height,
+            reward,
+            scheme: self.config.scheme,
+            last_share,
+            payouts: calculate_payouts(&shares, reward, self.config.fee_basis_points),
+            payout_txid: None,
+        };
+        self.db.save_pool_round(&round)?;
+        log::info!("⛏️ Pool round closed by block {} at height {}: {} share(s), {} payout(s)",
+            round.block_hash, round.height, shares.len(), round.payouts.len());
+        Ok(round)
+    }
+}
+
+/// Rounds still waiting to be paid whose block is on the main chain and has matured
+pub fn payable_rounds(db: &Database, blockchain: &Blockchain) -> Result<Vec<PoolRound>> {
+    let mut payable = Vec::new();
+    for round in db.get_pool_rounds()? {
+        if round.payout_txid.is_some() || round.payouts.is_empty() || !is_coinbase_mature(round.height, blockchain.height) {
+            continue;
+        }
+        // Rounds of blocks that were reorged out have nothing to pay with
+        if db.get_block_hash_by_height(round.height)? == Some(round.block_hash) {
+            payable.push(round);
+        }
+    }
+    Ok(payable)
+}
+
+/// One unsigned transaction paying everything `rounds` owe from the pool wallet; the rounds
+/// are marked paid by it, and its inputs stay locked until it is signed and broadcast
+pub fn payout_batch(db: &Database, wallet: &Wallet, rounds: &[PoolRound], fee_rate: u64) -> Result<Psbt> {
+    let mut owed: BTreeMap<&str, u64> = BTreeMap::new();
+    for payout in rounds.iter().flat_map(|round| &round.payouts) {
+        *owed.entry(&payout.address).or_default() += payout.amount;
+    }
+    if owed.is_empty() {
+        return Err(QtcError::InvalidInput("No pool payouts are due".to_string()));
+    }
+
+    let payments: Vec<(String, u64)> = owed.into_iter().map(|(address, amount)| (address.to_string(), amount)).collect();
+    let psbt = wallet.create_batch_psbt(&payments, fee_rate)?;
+    for round in rounds {
+        db.save_pool_round(&PoolRound { payout_txid: Some(psbt.txid()), ..round.clone() })?;
+    }
+    Ok(psbt)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::RwLock;
+    use tempfile::TempDir;
+
+    /// A share on the tip paying `to`, far short of its network difficulty of 40
+    fn mine_share(blockchain: &Blockchain, to: &str, difficulty: u32, tag: &str) -> Block {
+        let coinbase = Transaction::new_coinbase(to.to_string(), 50_000_000, tag.to_string());
+        let mut block = Block::new(blockchain.tip, vec![coinbase], 40, blockchain.height + 1);
+        while !Blockchain::hash_meets_target(&block.hash(), difficulty) {
+            block.increment_nonce();
+        }
+        block
+    }
+
+    #[test]
+    fn test_share_accounting_and_payouts() -> Result<()> {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Arc::new(Database::new(temp_dir.path().join("test.db"))?);
+        let blockchain = Arc::new(RwLock::new(Blockchain::new(db.clone())?));
+        let pool_wallet = Wallet::new_simple("pool".to_string(), db.clone(), blockchain.clone())?;
+        let miner_wallet = Wallet::new_simple("miner".to_string(), db.clone(), blockchain.clone())?;
+        let pool_address = pool_wallet.get_addresses()[0].clone();
+        let miner = miner_wallet.get_addresses()[0].clone();
+
+        let config = PoolConfig {
+            wallet: "pool".to_string(),
+            share_difficulty: 2,
+            scheme: PayoutScheme::Proportional,
+            pplns_window: 2,
+            fee_basis_points: 100,
+        };
+        let tracker = ShareTracker::new(db.clone(), config.clone(), &pool_wallet)?;
+        let chain = blockchain.read().unwrap();
+
+        let share = mine_share(&chain, &pool_address, 3, "share 1");
+        assert_eq!(tracker.submit(&chain, "rig1", &miner, &share, Some(3))?,
+            ShareOutcome::Accepted { work: Blockchain::block_work(3), block: false });
+        assert_eq!(tracker.submit(&chain, "rig1", &miner, &share, Some(3))?, ShareOutcome::Duplicate);
+
+        let mut stale = mine_share(&chain, &pool_address, 2, "stale");
+        stale.header.previous_hash = Hash256::hash(b"old tip");
+        assert_eq!(tracker.submit(&chain, "rig1", &miner, &stale, None)?, ShareOutcome::Stale);
+        let solo = mine_share(&chain, &miner, 2, "solo");
+        assert!(matches!(tracker.submit(&chain, "rig1", &miner, &solo, None)?, ShareOutcome::Rejected { .. }));
+        let easy = mine_share(&chain, &pool_address, 1, "easy");
+        assert!(matches!(tracker.submit(&chain, "rig1", &miner, &easy, Some(1))?, ShareOutcome::Rejected { .. }));
+
+        let stats = &db.get_pool_workers()?[0];
+        assert_eq!((stats.accepted, stats.duplicate, stats.stale, stats.rejected), (1, 1, 1, 2));
+        assert_eq!(stats.work, Blockchain::block_work(3));
+
+        // Proportional: each round pays only the shares since the last one
+        let round = tracker.block_found(&share)?;
+        assert_eq!(round.payouts, vec![Payout { address: miner.clone(), amount: 49_500_000 }]);
+        let second = mine_share(&chain, &pool_address, 2, "share 2");
+        tracker.submit(&chain, "rig2", &pool_address, &second, None)?;
+        let round = tracker.block_found(&second)?;
+        assert_eq!(round.payouts, vec![Payout { address: pool_address.clone(), amount: 49_500_000 }]);
+
+        // PPLNS: the last two shares, split by work (8 to 4)
+        let pplns = ShareTracker::new(db.clone(), PoolConfig { scheme: PayoutScheme::Pplns, ..config }, &pool_wallet)?;
+        let payouts = pplns.block_found(&second)?.payouts;
+        let amount_for = |address: &str| payouts.iter().find(|p| p.address == address).map(|p| p.amount);
+        assert_eq!((amount_for(&miner), amount_for(&pool_address)), (Some(33_000_000), Some(16_500_000)));
+
+        // Nothing is paid before the coinbase matures, and dust is left with the pool
+        assert!(payable_rounds(&db, &chain)?.is_empty());
+        let shares = db.get_recent_pool_shares(u64::MAX, 2)?;
+        assert_eq!(calculate_payouts(&shares, 1_500, 0).len(), 1);
+        Ok(())
+    }
+}
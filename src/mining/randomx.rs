@@ -1,6 +1,8 @@
+use crate::consensus::target::Target;
 use crate::{QtcError, Result};
 use sha2::{Sha256, Digest};
-use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
 
 // Production-ready RandomX implementation in pure Rust
 // This implementation provides ASIC-resistant hashing similar to RandomX
@@ -14,11 +16,34 @@ pub struct RandomXCache {
 #[derive(Debug)]
 pub struct RandomXVM {
     cache: Arc<RandomXCache>,
+    dataset: Option<Arc<RandomXDataset>>, // fast mode; light mode computes items from the cache
     seed: [u8; 32],
 }
 
+/// Every item a hash may read, precomputed from one key
 pub struct RandomXDataset {
-    _data: Vec<u8>,
+    key: Vec<u8>,
+    items: Vec<[u8; 32]>,
+}
+
+/// Items in the dataset, 32 bytes each
+pub const DATASET_ITEM_COUNT: usize = 1 << 18;
+/// Dataset items each hash reads
+const DATASET_READS: usize = 8;
+
+/// Blocks between RandomX key changes
+pub const SEED_EPOCH_BLOCKS: u64 = 2048;
+/// How far the key block lags behind the first block of its epoch
+pub const SEED_EPOCH_LAG: u64 = 64;
+
+/// Height of the block whose hash keys RandomX for mining at `height`. The key
+/// only changes every `SEED_EPOCH_BLOCKS`, and lags so miners have time to
+/// build the new dataset before it is needed.
+pub fn seed_height(height: u64) -> u64 {
+    if height <= SEED_EPOCH_BLOCKS + SEED_EPOCH_LAG {
+        return 0;
+    }
+    (height - SEED_EPOCH_LAG - 1) & !(SEED_EPOCH_BLOCKS - 1)
 }
 
 // RandomX flags (compatibility with original)
@@ -61,29 +86,9 @@ impl RandomXHash {
         Ok(Self(array))
     }
     
-    pub fn meets_difficulty(&self, difficulty: u32) -> bool {
-        let required_zeros = difficulty / 4;
-        let remaining_bits = difficulty % 4;
-        
-        // Check full zero bytes
-        for i in 0..required_zeros as usize {
-            if i >= 32 || self.0[i] != 0 {
-                return false;
-            }
-        }
-        
-        // Check remaining bits
-        if remaining_bits > 0 {
-            let byte_index = required_zeros as usize;
-            if byte_index < 32 {
-                let mask = 0xFF << (8 - remaining_bits);
-                if self.0[byte_index] & mask != 0 {
-                    return false;
-                }
-            }
-        }
-        
-        true
+    /// Whether the hash, read big-endian, is within the target `bits` encode
+    pub fn meets_target(&self, bits: u32) -> bool {
+        Target::from_be_bytes(&self.0) <= Target::from_bits(bits)
     }
 }
 
@@ -127,10 +132,25 @@ impl RandomXVM {
         
         Ok(Self {
             cache,
+            dataset: None,
             seed,
         })
     }
     
+    /// A fast-mode VM reading items from `dataset` instead of computing them
+    pub fn with_dataset(flags: u32, cache: Arc<RandomXCache>, dataset: Arc<RandomXDataset>) -> Result<Self> {
+        if !dataset.is_initialized() || dataset.key != cache.key {
+            return Err(QtcError::Mining("Dataset was not built from this cache".to_string()));
+        }
+        let mut vm = Self::new(flags, cache)?;
+        vm.dataset = Some(dataset);
+        Ok(vm)
+    }
+    
+    pub fn is_fast_mode(&self) -> bool {
+        self.dataset.is_some()
+    }
+    
     pub fn calculate_hash(&self, input: &[u8]) -> Result<RandomXHash> {
         // Pure Rust implementation of a RandomX-like hash function
         // This combines multiple SHA-256 rounds with the cache key for complexity
@@ -176,12 +196,22 @@ impl RandomXVM {
             }
         }
         
-        let final_hash = hasher.finalize();
-        
-        let mut result = [0u8; 32];
-        result.copy_from_slice(&final_hash);
+        let mut state: [u8; 32] = hasher.finalize().into();
+        
+        // Walk the dataset from the hash so far; both modes read the same items
+        for _ in 0..DATASET_READS {
+            let index = u64::from_le_bytes(state[..8].try_into().unwrap()) as usize % DATASET_ITEM_COUNT;
+            let item = match &self.dataset {
+                Some(dataset) => dataset.items[index],
+                None => dataset_item(&self.cache.key, index),
+            };
+            let mut hasher = Sha256::new();
+            hasher.update(state);
+            hasher.update(item);
+            state = hasher.finalize().into();
+        }
         
-        Ok(RandomXHash::new(result))
+        Ok(RandomXHash::new(state))
     }
     
     pub fn set_cache(&mut self, cache: Arc<RandomXCache>) -> Result<()> {
@@ -189,6 +219,10 @@ impl RandomXVM {
             return Err(QtcError::Mining("Cache not initialized".to_string()));
         }
         
+        // A dataset built from the old key would give wrong hashes
+        if self.dataset.as_ref().is_some_and(|dataset| dataset.key != cache.key) {
+            self.dataset = None;
+        }
         self.cache = cache;
         
         // Regenerate seed with new cache
@@ -203,17 +237,172 @@ impl RandomXVM {
     }
 }
 
+/// One dataset item, as light-mode VMs compute it on demand
+fn dataset_item(key: &[u8], index: usize) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(b"randomx_dataset");
+    hasher.update(key);
+    hasher.update((index as u64).to_le_bytes());
+    hasher.finalize().into()
+}
+
+impl std::fmt::Debug for RandomXDataset {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RandomXDataset").field("items", &self.items.len()).finish()
+    }
+}
+
 impl RandomXDataset {
     pub fn new(_flags: u32) -> Result<Self> {
         Ok(Self {
-            _data: Vec::new(),
+            key: Vec::new(),
+            items: Vec::new(),
         })
     }
     
-    pub fn init(&mut self, _cache: &RandomXCache) -> Result<()> {
-        // Placeholder for dataset initialization
+    /// Compute every item for the cache's key, spread over the available cores
+    pub fn init(&mut self, cache: &RandomXCache) -> Result<()> {
+        if !cache.is_initialized() {
+            return Err(QtcError::Mining("Cache not initialized".to_string()));
+        }
+        
+        let started = std::time::Instant::now();
+        let workers = std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+        let chunk_len = DATASET_ITEM_COUNT.div_ceil(workers);
+        let mut items = vec![[0u8; 32]; DATASET_ITEM_COUNT];
+        std::thread::scope(|scope| {
+            for (chunk, chunk_items) in items.chunks_mut(chunk_len).enumerate() {
+                let key = &cache.key;
+                scope.spawn(move || {
+                    for (offset, item) in chunk_items.iter_mut().enumerate() {
+                        *item = dataset_item(key, chunk * chunk_len + offset);
+                    }
+                });
+            }
+        });
+        
+        self.key = cache.key.clone();
+        self.items = items;
+        log::debug!("Built RandomX dataset of {} items in {:.2}s", DATASET_ITEM_COUNT, started.elapsed().as_secs_f64());
         Ok(())
     }
+    
+    pub fn is_initialized(&self) -> bool {
+        !self.items.is_empty()
+    }
+}
+
+/// The cache and, in fast mode, the dataset for one key
+#[derive(Debug)]
+struct SeedState {
+    key: Vec<u8>,
+    cache: Arc<RandomXCache>,
+    dataset: Option<Arc<RandomXDataset>>,
+}
+
+impl SeedState {
+    fn build(key: &[u8], flags: u32, fast_mode: bool) -> Result<Self> {
+        let mut cache = RandomXCache::new(flags)?;
+        cache.init(key)?;
+        let dataset = if fast_mode {
+            let mut dataset = RandomXDataset::new(flags)?;
+            dataset.init(&cache)?;
+            Some(Arc::new(dataset))
+        } else {
+            None
+        };
+        Ok(Self { key: key.to_vec(), cache: Arc::new(cache), dataset })
+    }
+    
+    fn vm(&self, flags: u32) -> Result<RandomXVM> {
+        match &self.dataset {
+            Some(dataset) => RandomXVM::with_dataset(flags, self.cache.clone(), dataset.clone()),
+            None => RandomXVM::new(flags, self.cache.clone()),
+        }
+    }
+}
+
+/// VMs for mining threads over one shared cache and, in fast mode, one shared
+/// dataset, so the dataset is built once rather than per thread. `rotate`
+/// moves the pool to a new key; each thread's VM follows on its next hash.
+#[derive(Debug)]
+pub struct RandomXVmPool {
+    flags: u32,
+    seed: RwLock<Arc<SeedState>>,
+    epoch: AtomicU64, // bumped on every rotation
+}
+
+impl RandomXVmPool {
+    pub fn new(key: &[u8], fast_mode: bool) -> Result<Self> {
+        let flags = mode_flags(fast_mode);
+        if fast_mode {
+            log::info!("⚡ Building the RandomX dataset for fast mode ({} MB)", DATASET_ITEM_COUNT * 32 / (1024 * 1024));
+        }
+        Ok(Self {
+            flags,
+            seed: RwLock::new(Arc::new(SeedState::build(key, flags, fast_mode)?)),
+            epoch: AtomicU64::new(0),
+        })
+    }
+    
+    pub fn is_fast_mode(&self) -> bool {
+        self.flags & RANDOMX_FLAG_FULL_MEM != 0
+    }
+    
+    pub fn key(&self) -> Vec<u8> {
+        self.seed.read().unwrap().key.clone()
+    }
+    
+    /// Switch to `key` unless it is already the current one; true if the pool changed
+    pub fn rotate(&self, key: &[u8]) -> Result<bool> {
+        if self.seed.read().unwrap().key == key {
+            return Ok(false);
+        }
+        
+        // Threads racing to rotate wait here for the first one's rebuild
+        let mut seed = self.seed.write().unwrap();
+        if seed.key == key {
+            return Ok(false);
+        }
+        log::info!("🔑 RandomX key changed, rebuilding the {}", if self.is_fast_mode() { "dataset" } else { "cache" });
+        *seed = Arc::new(SeedState::build(key, self.flags, self.is_fast_mode())?);
+        self.epoch.fetch_add(1, Ordering::Release);
+        Ok(true)
+    }
+    
+    /// A VM for one thread
+    pub fn vm(self: &Arc<Self>) -> Result<PooledVm> {
+        let epoch = self.epoch.load(Ordering::Acquire);
+        let vm = self.seed.read().unwrap().vm(self.flags)?;
+        Ok(PooledVm { pool: self.clone(), vm, epoch })
+    }
+}
+
+/// A thread's VM from a `RandomXVmPool`, kept on the pool's current key
+#[derive(Debug)]
+pub struct PooledVm {
+    pool: Arc<RandomXVmPool>,
+    vm: RandomXVM,
+    epoch: u64,
+}
+
+impl PooledVm {
+    pub fn hash(&mut self, input: &[u8]) -> Result<RandomXHash> {
+        let epoch = self.pool.epoch.load(Ordering::Acquire);
+        if epoch != self.epoch {
+            self.vm = self.pool.seed.read().unwrap().vm(self.pool.flags)?;
+            self.epoch = epoch;
+        }
+        self.vm.calculate_hash(input)
+    }
+}
+
+fn mode_flags(fast_mode: bool) -> u32 {
+    if fast_mode {
+        RANDOMX_FLAG_FULL_MEM | RANDOMX_FLAG_JIT | RANDOMX_FLAG_HARD_AES
+    } else {
+        RANDOMX_FLAG_DEFAULT
+    }
 }
 
 pub struct RandomXMiner {
@@ -225,17 +414,9 @@ pub struct RandomXMiner {
 
 impl RandomXMiner {
     pub fn new(key: &[u8], threads: Option<usize>, fast_mode: bool) -> Result<Self> {
-        let flags = if fast_mode {
-            RANDOMX_FLAG_FULL_MEM | RANDOMX_FLAG_JIT | RANDOMX_FLAG_HARD_AES
-        } else {
-            RANDOMX_FLAG_DEFAULT
-        };
-        
-        let mut cache = RandomXCache::new(flags)?;
-        cache.init(key)?;
-        let cache = Arc::new(cache);
-        
-        let vm = RandomXVM::new(flags, cache.clone())?;
+        let seed = SeedState::build(key, mode_flags(fast_mode), fast_mode)?;
+        let cache = seed.cache.clone();
+        let vm = seed.vm(mode_flags(fast_mode))?;
         
         let thread_count = threads.unwrap_or_else(|| {
             std::thread::available_parallelism()
@@ -293,11 +474,7 @@ impl RandomXMiner {
     }
     
     pub fn get_flags(&self) -> u32 {
-        if self.fast_mode {
-            RANDOMX_FLAG_FULL_MEM | RANDOMX_FLAG_JIT | RANDOMX_FLAG_HARD_AES
-        } else {
-            RANDOMX_FLAG_DEFAULT
-        }
+        mode_flags(self.fast_mode)
     }
     
     pub fn thread_count(&self) -> usize {
@@ -364,8 +541,11 @@ mod tests {
         bytes[1] = 0x00;
         let hash = RandomXHash::new(bytes);
         
-        assert!(hash.meets_difficulty(8));  // 2 zero bytes = 8 zero bits
-        assert!(!hash.meets_difficulty(16)); // Doesn't have 4 zero bytes
+        assert!(hash.meets_target(Target::from_zero_bits(16).to_bits()));
+        assert!(!hash.meets_target(Target::from_zero_bits(17).to_bits()));
+        // Legacy difficulty 8 asked for two zero bytes, 9 for a zero bit more
+        assert!(hash.meets_target(8));
+        assert!(!hash.meets_target(9));
     }
     
     #[test]
@@ -443,6 +623,41 @@ mod tests {
         assert_eq!(hash.as_bytes().len(), 32);
     }
     
+    #[test]
+    fn test_fast_and_light_hashes_match() {
+        let light = RandomXMiner::new(b"test_mining_key", Some(1), false).unwrap();
+        let fast = RandomXMiner::new(b"test_mining_key", Some(1), true).unwrap();
+        assert!(fast.is_fast_mode());
+        assert_eq!(light.hash(b"block").unwrap(), fast.hash(b"block").unwrap());
+    }
+    
+    #[test]
+    fn test_vm_pool_rotates_every_thread() {
+        let pool = Arc::new(RandomXVmPool::new(b"epoch 0", true).unwrap());
+        let mut first = pool.vm().unwrap();
+        let mut second = pool.vm().unwrap();
+        let before = first.hash(b"block").unwrap();
+        assert_eq!(second.hash(b"block").unwrap(), before);
+        
+        assert!(!pool.rotate(b"epoch 0").unwrap());
+        assert!(pool.rotate(b"epoch 1").unwrap());
+        let after = first.hash(b"block").unwrap();
+        assert_ne!(after, before);
+        assert_eq!(second.hash(b"block").unwrap(), after);
+        
+        let light = RandomXMiner::new(b"epoch 1", None, false).unwrap();
+        assert_eq!(light.hash(b"block").unwrap(), after);
+    }
+    
+    #[test]
+    fn test_seed_height() {
+        assert_eq!(seed_height(0), 0);
+        assert_eq!(seed_height(SEED_EPOCH_BLOCKS + SEED_EPOCH_LAG), 0);
+        assert_eq!(seed_height(SEED_EPOCH_BLOCKS + SEED_EPOCH_LAG + 1), SEED_EPOCH_BLOCKS);
+        assert_eq!(seed_height(2 * SEED_EPOCH_BLOCKS + SEED_EPOCH_LAG), SEED_EPOCH_BLOCKS);
+        assert_eq!(seed_height(2 * SEED_EPOCH_BLOCKS + SEED_EPOCH_LAG + 1), 2 * SEED_EPOCH_BLOCKS);
+    }
+    
     #[test]
     fn test_memory_estimation() {
         let light_memory = estimate_memory_usage(RANDOMX_FLAG_DEFAULT);
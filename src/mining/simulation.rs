@@ -0,0 +1,253 @@
+//! Offline simulation of difficulty retargeting and coin emission
+
+use crate::consensus::monetary::MonetaryPolicy;
+use crate::consensus::params::ChainParams;
+use crate::consensus::target::Target;
+use crate::core::Blockchain;
+use crate::mining::difficulty::DifficultyCalculator;
+use crate::{QtcError, Result};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+/// Bits the chain uses until the first full adjustment window, as mainnet's
+const INITIAL_BITS: u32 = 0x1c01_0000;
+
+/// Network hashrate as a step function of block height
+#[derive(Debug, Clone)]
+pub struct HashrateCurve {
+    points: Vec<(u64, f64)>, // (from height, hashes per second), sorted by height
+}
+
+impl HashrateCurve {
+    pub fn constant(hashrate: f64) -> Self {
+        Self { points: vec![(0, hashrate)] }
+    }
+
+    /// Parse `height,hashrate` rows; a header line and `#` comments are skipped
+    pub fn from_csv(data: &str) -> Result<Self> {
+        let mut points = Vec::new();
+
+        for (line_no, line) in data.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let invalid = || QtcError::InvalidInput(format!("Invalid hashrate curve line {}: {}", line_no + 1, line));
+            let (height, hashrate) = line.split_once(',').ok_or_else(invalid)?;
+            let (height, hashrate) = match (height.trim().parse::<u64>(), hashrate.trim().parse::<f64>()) {
+                (Ok(height), Ok(hashrate)) => (height, hashrate),
+                // Tolerate a header row
+                _ if points.is_empty() && line_no == 0 => continue,
+                _ => return Err(invalid()),
+            };
+
+            if hashrate <= 0.0 || !hashrate.is_finite() {
+                return Err(invalid());
+            }
+            points.push((height, hashrate));
+        }
+
+        if points.is_empty() {
+            return Err(QtcError::InvalidInput("Hashrate curve has no data points".to_string()));
+        }
+
+        points.sort_by_key(|(height, _)| *height);
+        Ok(Self { points })
+    }
+
+    pub fn hashrate_at(&self, height: u64) -> f64 {
+        self.points.iter()
+            .take_while(|(from, _)| *from <= height)
+            .last()
+            .unwrap_or(&self.points[0])
+            .1
+    }
+}
+
+/// One simulated block, as written to the output CSV
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SimulatedBlock {
+    pub height: u64,
+    pub timestamp: u64,
+    pub bits: u32,
+    pub difficulty: f64,
+    pub hashrate: f64,
+    pub block_time_secs: f64,
+    pub reward: u64,
+    pub total_supply: u64,
+}
+
+pub struct EmissionSimulator {
+    calculator: DifficultyCalculator,
+    lwma_activation_height: Option<u64>,
+    monetary_policy: MonetaryPolicy,
+    rng: StdRng,
+}
+
+impl EmissionSimulator {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            calculator: DifficultyCalculator::new(),
+            lwma_activation_height: None,
+            monetary_policy: MonetaryPolicy::new(),
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+    
+    pub fn with_params(seed: u64, params: &ChainParams) -> Self {
+        Self {
+            calculator: params.difficulty_calculator(),
+            lwma_activation_height: params.lwma_activation_height,
+            monetary_policy: params.monetary_policy(),
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    /// Mine `blocks` blocks on top of genesis, retargeting after every block like `Blockchain` does,
+    /// by LWMA once the params activate it
+    pub fn run(&mut self, curve: &HashrateCurve, blocks: u64) -> Result<Vec<SimulatedBlock>> {
+        let window = self.calculator.adjustment_interval as usize + 1;
+        let mut recent_times: VecDeque<u64> = VecDeque::with_capacity(window);
+        recent_times.push_back(0); // genesis
+        
+        // The LWMA window, with the block before it for the first solve time
+        let lwma_window = self.calculator.lwma_window as usize;
+        let mut lwma_times: VecDeque<u64> = VecDeque::with_capacity(lwma_window + 1);
+        let mut lwma_bits: VecDeque<u32> = VecDeque::with_capacity(lwma_window);
+        lwma_times.push_back(0);
+
+        let mut results = Vec::with_capacity(blocks as usize);
+        let mut clock = 0.0f64;
+        let mut bits = INITIAL_BITS;
+        let mut total_supply = 0u64;
+
+        for height in 1..=blocks {
+            let hashrate = curve.hashrate_at(height);
+            let expected_secs = Blockchain::block_work(bits) as f64 / hashrate;
+
+            // Finding a block is a Poisson process, so block times are exponential
+            let sample: f64 = self.rng.gen_range(f64::EPSILON..1.0);
+            let block_time_secs = -sample.ln() * expected_secs;
+            clock += block_time_secs;
+
+            let reward = self.monetary_policy.coinbase_reward(height);
+            total_supply = total_supply.saturating_add(reward);
+
+            results.push(SimulatedBlock {
+                height,
+                timestamp: clock as u64,
+                bits,
+                difficulty: Target::from_bits(bits).difficulty(),
+                hashrate,
+                block_time_secs,
+                reward,
+                total_supply,
+            });
+
+            if recent_times.len() == window {
+                recent_times.pop_front();
+            }
+            recent_times.push_back(clock as u64);
+
+            if lwma_bits.len() == lwma_window {
+                lwma_times.pop_front();
+                lwma_bits.pop_front();
+            }
+            lwma_times.push_back(clock as u64);
+            lwma_bits.push_back(bits);
+
+            let lwma_active = self.lwma_activation_height.is_some_and(|activation| height + 1 >= activation);
+            bits = if lwma_active {
+                let times: Vec<u64> = lwma_times.iter().copied().collect();
+                let window_bits: Vec<u32> = lwma_bits.iter().copied().collect();
+                self.calculator.calculate_lwma_bits(&times, &window_bits)?
+            } else if height < self.calculator.adjustment_interval {
+                INITIAL_BITS
+            } else {
+                let times: Vec<u64> = recent_times.iter().copied().collect();
+                self.calculator.calculate_next_bits(bits, &times)?
+            };
+        }
+
+        Ok(results)
+    }
+}
+
+/// Render a simulation run as CSV, one row per block
+pub fn simulation_to_csv(blocks: &[SimulatedBlock]) -> String {
+    let mut csv = String::from("height,timestamp,bits,difficulty,hashrate,block_time_secs,reward,total_supply\n");
+    for block in blocks {
+        csv.push_str(&format!("{},{},{:08x},{},{},{:.3},{},{}\n",
+            block.height,
+            block.timestamp,
+            block.bits,
+            block.difficulty,
+            block.hashrate,
+            block.block_time_secs,
+            block.reward,
+            block.total_supply,
+        ));
+    }
+    csv
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hashrate_curve_parsing() -> Result<()> {
+        let curve = HashrateCurve::from_csv("height,hashrate\n0,1000\n# doubling\n500,2000\n")?;
+        assert_eq!(curve.hashrate_at(0), 1000.0);
+        assert_eq!(curve.hashrate_at(499), 1000.0);
+        assert_eq!(curve.hashrate_at(10_000), 2000.0);
+
+        assert!(HashrateCurve::from_csv("0,1000\nfoo,bar\n").is_err());
+        assert!(HashrateCurve::from_csv("0,-5\n").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_simulation_is_deterministic() -> Result<()> {
+        let curve = HashrateCurve::constant(1e9);
+        let first = EmissionSimulator::new(7).run(&curve, 50)?;
+        let second = EmissionSimulator::new(7).run(&curve, 50)?;
+
+        assert_eq!(first.len(), 50);
+        assert_eq!(first[49].timestamp, second[49].timestamp);
+        assert_eq!(first[49].total_supply, MonetaryPolicy::new().coinbase_reward(1) * 50);
+        assert!(first.iter().take(10).all(|b| b.bits == INITIAL_BITS));
+
+        let csv = simulation_to_csv(&first);
+        assert_eq!(csv.lines().count(), 51);
+        Ok(())
+    }
+
+    #[test]
+    fn test_lwma_holds_block_times_under_oscillating_hashrate() -> Result<()> {
+        // Hashrate that finds a block at the initial bits in one target spacing, with
+        // hop-on miners quadrupling it for 30 blocks at a time
+        let params = ChainParams { lwma_activation_height: Some(1), ..ChainParams::mainnet() };
+        let base = Blockchain::block_work(INITIAL_BITS) as f64 / params.target_block_time as f64;
+        let curve = HashrateCurve {
+            points: (0..100).map(|i| (i * 30, if i % 2 == 0 { base } else { base * 4.0 })).collect(),
+        };
+
+        let blocks = EmissionSimulator::with_params(3, &params).run(&curve, 3_000)?;
+        let settled = &blocks[100..];
+        let average = settled.iter().map(|b| b.block_time_secs).sum::<f64>() / settled.len() as f64;
+        let target = params.target_block_time as f64;
+        assert!((average - target).abs() < target * 0.2, "average block time {:.1}s", average);
+        let initial = Target::from_bits(INITIAL_BITS).difficulty();
+        assert!(settled.iter().all(|b| (initial / 2.0..=initial * 8.0).contains(&b.difficulty)));
+
+        // The interval retarget lags the same curve, running well slow on average
+        let legacy = EmissionSimulator::with_params(3, &ChainParams { lwma_activation_height: None, ..params }).run(&curve, 3_000)?;
+        let legacy_average = legacy[100..].iter().map(|b| b.block_time_secs).sum::<f64>() / settled.len() as f64;
+        assert!(legacy_average > target * 1.5, "legacy average block time {:.1}s", legacy_average);
+        Ok(())
+    }
+}
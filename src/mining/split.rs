@@ -0,0 +1,173 @@
+//! Coinbase rewards split between several addresses
+//!
+//! A team mining together can have every block pay each member their share
+//! directly: payout specs of the form `address:percentage`, e.g.
+//! `qtc1alice:60 qtc1bob:40`. Percentages take up to two decimals and must add
+//! up to exactly 100. Each address gets its percentage of the block subsidy
+//! rounded down, and whatever rounding leaves over goes to the first, so the
+//! outputs always add up to the subsidy. The built-in miner's blocks hold only
+//! their coinbase, so there are no fees to share.
+
+use crate::core::Transaction;
+use crate::{QtcError, Result};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// A whole reward, in basis points
+pub const FULL_SHARE: u32 = 10_000;
+
+/// One address and its cut of every block
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PayoutShare {
+    pub address: String,
+    pub basis_points: u32,
+}
+
+impl FromStr for PayoutShare {
+    type Err = QtcError;
+
+    fn from_str(spec: &str) -> Result<Self> {
+        let invalid = || QtcError::InvalidInput(format!("Invalid payout '{}': expected ADDRESS:PERCENT, e.g. qtc1...:25.5", spec));
+        let (address, percent) = spec.rsplit_once(':').ok_or_else(invalid)?;
+        let address = address.trim();
+        if !crate::crypto::keys::is_valid_address(address) {
+            return Err(QtcError::InvalidInput(format!("Invalid payout address: {}", address)));
+        }
+
+        let (whole, fraction) = percent.trim().split_once('.').unwrap_or((percent.trim(), ""));
+        if whole.is_empty() || fraction.len() > 2 || !(whole.chars().chain(fraction.chars()).all(|c| c.is_ascii_digit())) {
+            return Err(invalid());
+        }
+        let whole: u32 = whole.parse().map_err(|_| invalid())?;
+        let hundredths: u32 = format!("{:0<2}", fraction).parse().map_err(|_| invalid())?;
+        let basis_points = whole.checked_mul(100).and_then(|bp| bp.checked_add(hundredths)).ok_or_else(invalid)?;
+        if basis_points == 0 || basis_points > FULL_SHARE {
+            return Err(QtcError::InvalidInput(format!("Payout percentage for {} must be above 0 and at most 100", address)));
+        }
+
+        Ok(Self { address: address.to_string(), basis_points })
+    }
+}
+
+impl fmt::Display for PayoutShare {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.address, format_percent(self.basis_points))
+    }
+}
+
+/// `basis_points` as a percentage, without trailing zeros
+pub fn format_percent(basis_points: u32) -> String {
+    match basis_points % 100 {
+        0 => format!("{}", basis_points / 100),
+        hundredths if hundredths % 10 == 0 => format!("{}.{}", basis_points / 100, hundredths / 10),
+        hundredths => format!("{}.{:02}", basis_points / 100, hundredths),
+    }
+}
+
+/// Who a mined block pays, and how much of it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PayoutSplit {
+    shares: Vec<PayoutShare>,
+}
+
+impl PayoutSplit {
+    pub fn new(shares: Vec<PayoutShare>) -> Result<Self> {
+        if shares.is_empty() {
+            return Err(QtcError::InvalidInput("At least one payout address is required".to_string()));
+        }
+        for (i, share) in shares.iter().enumerate() {
+            if shares[..i].iter().any(|earlier| earlier.address == share.address) {
+                return Err(QtcError::InvalidInput(format!("Payout address {} is listed twice", share.address)));
+            }
+        }
+        let total: u32 = shares.iter().map(|share| share.basis_points).sum();
+        if total != FULL_SHARE {
+            return Err(QtcError::InvalidInput(format!(
+                "Payout percentages add up to {}%, not 100%", format_percent(total)
+            )));
+        }
+        Ok(Self { shares })
+    }
+
+    /// Parse `address:percentage` specs, as given on the command line
+    pub fn parse(specs: &[String]) -> Result<Self> {
+        Self::new(specs.iter().map(|spec| spec.parse()).collect::<Result<Vec<_>>>()?)
+    }
+
+    pub fn shares(&self) -> &[PayoutShare] {
+        &self.shares
+    }
+
+    /// The address leftovers from rounding go to
+    pub fn primary_address(&self) -> &str {
+        &self.shares[0].address
+    }
+
+    /// `reward` divided by the shares, in their order; the amounts add up to `reward`
+    pub fn amounts(&self, reward: u64) -> Vec<(String, u64)> {
+        let mut amounts: Vec<(String, u64)> = self.shares.iter()
+            .map(|share| (share.address.clone(), (reward as u128 * share.basis_points as u128 / FULL_SHARE as u128) as u64))
+            .collect();
+        let paid: u64 = amounts.iter().map(|(_, amount)| amount).sum();
+        amounts[0].1 += reward - paid;
+        amounts
+    }
+
+    /// A coinbase paying `reward` along the split; shares too small to pay anything get no output
+    pub fn coinbase(&self, reward: u64, message: String) -> Transaction {
+        let mut amounts = self.amounts(reward).into_iter();
+        let (first, first_amount) = amounts.next().expect("a split has at least one share");
+        let mut coinbase = Transaction::new_coinbase(first, first_amount, message);
+        for (address, amount) in amounts.filter(|(_, amount)| *amount > 0) {
+            coinbase.add_output(amount, &address);
+        }
+        coinbase
+    }
+}
+
+impl fmt::Display for PayoutSplit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let shares: Vec<String> = self.shares.iter()
+            .map(|share| format!("{} ({}%)", share.address, format_percent(share.basis_points)))
+            .collect();
+        write!(f, "{}", shares.join(", "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::keys::KeyPair;
+
+    #[test]
+    fn test_payout_split() -> Result<()> {
+        let (alice, bob, carol) = (KeyPair::new()?.address(), KeyPair::new()?.address(), KeyPair::new()?.address());
+        let split = PayoutSplit::parse(&[format!("{}:50", alice), format!("{}:33.33", bob), format!("{}:16.67", carol)])?;
+        assert_eq!(split.shares()[1].basis_points, 3_333);
+        assert_eq!(split.shares()[2].to_string(), format!("{}:16.67", carol));
+
+        // Rounding leftovers go to the first address, so nothing is lost or created
+        let amounts = split.amounts(1_000_001);
+        assert_eq!(amounts.iter().map(|(_, amount)| amount).sum::<u64>(), 1_000_001);
+        assert_eq!(amounts[1], (bob.clone(), 333_300));
+        assert_eq!(amounts[0].1, 500_000 + 1_000_001 - 500_000 - 333_300 - 166_700);
+
+        let coinbase = split.coinbase(1_000_001, "team block".to_string());
+        assert!(coinbase.is_coinbase());
+        assert_eq!(coinbase.outputs.len(), 3);
+        assert_eq!(coinbase.total_output_value(), 1_000_001);
+        assert_eq!(coinbase.outputs[2].script_pubkey, Transaction::address_to_script_pubkey(&carol));
+        // A reward too small for a share leaves its output out
+        assert_eq!(split.coinbase(1, "dust".to_string()).outputs.len(), 1);
+
+        assert!(PayoutSplit::parse(&[format!("{}:60", alice), format!("{}:30", bob)]).is_err());
+        assert!(PayoutSplit::parse(&[format!("{}:50", alice), format!("{}:50", alice)]).is_err());
+        assert!(PayoutSplit::parse(&[]).is_err());
+        for spec in ["qtc1nope:100".to_string(), format!("{}:0", alice), format!("{}:100.001", alice), format!("{}:101", alice), alice.clone()] {
+            assert!(spec.parse::<PayoutShare>().is_err(), "{}", spec);
+        }
+        assert_eq!(PayoutSplit::parse(&[format!("{}:100.0", alice)])?.amounts(7), vec![(alice, 7)]);
+        Ok(())
+    }
+}
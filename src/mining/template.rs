@@ -0,0 +1,309 @@
+//! getblocktemplate-style work for external miners
+
+use crate::core::Blockchain;
+use crate::crypto::hash::Hash256;
+use crate::{QtcError, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::RwLock;
+use std::time::Duration;
+
+/// How long a long-poll request waits for new work before returning the current template
+pub const LONGPOLL_TIMEOUT_SECS: u64 = 60;
+
+/// Mempool-only changes wake long polls once next-block fees grow by this much
+const LONGPOLL_FEE_INCREASE_PERCENT: u64 = 10;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TemplateTransaction {
+    pub txid: String,
+    pub data: String, // hex bincode, as accepted by /api/v1/transactions
+    pub fee: u64,
+    pub size: usize,
+    pub depends: Vec<usize>, // indexes of in-template parents
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BlockTemplate {
+    pub previous_block_hash: String,
+    pub height: u64,
+    pub bits: u32, // for the header, in compact form
+    pub difficulty: f64,
+    pub target: String,
+    pub coinbase_value: u64, // block reward plus fees of `transactions`
+    pub transactions: Vec<TemplateTransaction>,
+    pub total_fees: u64,
+    pub size_limit: usize,
+    pub curtime: u64,
+    pub longpollid: String,
+}
+
+/// Picks mempool transactions for a template, best ancestor-package fee rate
+/// first, until the size limit is reached
+#[derive(Debug, Clone, Default)]
+pub struct BlockTemplateBuilder {
+    max_size: Option<usize>,
+    min_fee_rate: u64,
+}
+
+impl BlockTemplateBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cap the bytes of mempool transactions; the consensus limit still applies
+    pub fn with_max_size(mut self, max_size: usize) -> Self {
+        self.max_size = Some(max_size);
+        self
+    }
+
+    /// Leave out packages paying less than this many sat/byte
+    pub fn with_min_fee_rate(mut self, min_fee_rate: u64) -> Self {
+        self.min_fee_rate = min_fee_rate;
+        self
+    }
+
+    /// Work on top of the current tip
+    pub fn build(&self, blockchain: &Blockchain) -> Result<BlockTemplate> {
+        let height = blockchain.height + 1;
+        let curtime = chrono::Utc::now().timestamp() as u64;
+        let bits = blockchain.bits_for_next_block(curtime)?;
+        let target = blockchain.chain_params().difficulty_calculator().bits_to_target(bits);
+        let size_limit = self.max_size
+            .map_or(blockchain.max_template_size(), |size| size.min(blockchain.max_template_size()));
+
+        let mempool = blockchain.mempool.read().unwrap();
+        let selected = mempool.select_for_block(size_limit, self.min_fee_rate);
+        let positions: HashMap<Hash256, usize> = selected.iter()
+            .enumerate()
+            .map(|(index, entry)| (entry.txid, index))
+            .collect();
+
+        let mut transactions = Vec::with_capacity(selected.len());
+        for entry in &selected {
+            let data = bincode::serialize(&entry.tx)
+                .map_err(|e| QtcError::Mining(format!("Failed to serialize template transaction: {}", e)))?;
+            let mut depends: Vec<usize> = entry.tx.inputs.iter()
+                .filter_map(|input| positions.get(&input.previous_output.txid).copied())
+                .collect();
+            depends.sort_unstable();
+            depends.dedup();
+
+            transactions.push(TemplateTransaction {
+                txid: entry.txid.to_hex(),
+                data: hex::encode(data),
+                fee: entry.fee,
+                size: entry.size,
+                depends,
+            });
+        }
+
+        let total_fees: u64 = selected.iter().map(|entry| entry.fee).sum();
+        let coinbase_value = blockchain.monetary_policy().coinbase_reward(height).saturating_add(total_fees);
+
+        Ok(BlockTemplate {
+            previous_block_hash: blockchain.tip.to_hex(),
+            height,
+            bits,
+            difficulty: target.difficulty,
+            target: hex::encode(target.target_hash),
+            coinbase_value,
+            transactions,
+            total_fees,
+            size_limit,
+            curtime,
+            longpollid: format!("{}:{}", blockchain.tip.to_hex(), total_fees),
+        })
+    }
+}
+
+impl BlockTemplate {
+    /// Work on top of the current tip with the best-paying mempool transactions
+    pub fn build(blockchain: &Blockchain) -> Result<Self> {
+        BlockTemplateBuilder::new().build(blockchain)
+    }
+
+    /// Whether a miner working from `longpollid` should switch to this template:
+    /// the tip moved, or mempool fees rose enough to be worth the switch
+    pub fn supersedes(&self, longpollid: &str) -> bool {
+        let Some((previous_block_hash, fees)) = longpollid.split_once(':') else {
+            return true;
+        };
+        let Ok(fees) = fees.parse::<u64>() else {
+            return true;
+        };
+
+        if previous_block_hash != self.previous_block_hash {
+            return true;
+        }
+        let threshold = fees.saturating_add(fees * LONGPOLL_FEE_INCREASE_PERCENT / 100).max(fees + 1);
+        self.total_fees >= threshold
+    }
+}
+
+/// The current template, or with a `longpollid` the first one that supersedes it,
+/// waiting at most `LONGPOLL_TIMEOUT_SECS` before returning the current work
+pub async fn wait_for_template(
+    blockchain: &RwLock<Blockchain>,
+    builder: &BlockTemplateBuilder,
+    longpollid: Option<&str>,
+) -> Result<BlockTemplate> {
+    // Subscribe before building so a change in between still wakes us
+    let build = || -> Result<(BlockTemplate, tokio::sync::watch::Receiver<u64>)> {
+        let blockchain = blockchain.read()
+            .map_err(|_| QtcError::Blockchain("Failed to access blockchain".to_string()))?;
+        let updates = blockchain.subscribe_template_updates();
+        Ok((builder.build(&blockchain)?, updates))
+    };
+
+    let (mut template, mut updates) = build()?;
+
+    if let Some(longpollid) = longpollid {
+        let deadline = tokio::time::Instant::now() + Duration::from_secs(LONGPOLL_TIMEOUT_SECS);
+        while !template.supersedes(longpollid) {
+            // On timeout the miner gets the current work and polls again
+            if !matches!(tokio::time::timeout_at(deadline, updates.changed()).await, Ok(Ok(()))) {
+                break;
+            }
+            (template, updates) = build()?;
+        }
+    }
+
+    Ok(template)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::core::transaction::{sign_p2pkh_input, OutPoint};
+    use crate::core::{Block, Transaction};
+    use crate::crypto::hash::Hashable;
+    use crate::crypto::keys::KeyPair;
+    use crate::mining::generate_blocks;
+    use crate::storage::Database;
+    use std::sync::Arc;
+    use tempfile::TempDir;
+
+    /// A regtest chain whose first coinbase, paid to `owner`, has matured
+    fn mature_chain(temp_dir: &TempDir, owner: &KeyPair) -> Result<Blockchain> {
+        let db = Arc::new(Database::new(temp_dir.path().join("test.db"))?);
+        let mut blockchain = Blockchain::new(db)?;
+        blockchain.set_chain_params(Config::regtest().chain_params()?);
+        generate_blocks(&mut blockchain, 101, &owner.address())?;
+        Ok(blockchain)
+    }
+
+    /// A signed spend of output `vout` of `coin` sending `value` to `payee`
+    fn spend(coin: &Transaction, vout: u32, value: u64, payee: &str, owner: &KeyPair) -> Result<Transaction> {
+        let mut tx = Transaction::new();
+        tx.add_input(OutPoint::new(coin.hash(), vout), Vec::new());
+        tx.add_output(value, payee);
+        sign_p2pkh_input(&mut tx, 0, &owner.private_key)?;
+        Ok(tx)
+    }
+
+    /// A block doing `template`'s work, its coinbase claiming `coinbase_value`
+    fn mine(blockchain: &Blockchain, template: &BlockTemplate, coinbase_value: u64) -> Result<Block> {
+        let coinbase = Transaction::new_coinbase("qtc1templateminer".to_string(), coinbase_value, "template".to_string());
+        let mut transactions = vec![coinbase];
+        for tx in &template.transactions {
+            transactions.push(bincode::deserialize(&hex::decode(&tx.data).unwrap()).unwrap());
+        }
+        let previous_block_hash = Hash256::from_hex(&template.previous_block_hash).unwrap();
+        let parent = blockchain.get_block(&previous_block_hash)?.unwrap();
+        let mut block = Block::new(previous_block_hash, transactions, template.bits, template.height);
+        block.header.timestamp = template.curtime.max(parent.header.timestamp + 1);
+        while !blockchain.is_valid_proof_of_work(&block) {
+            block.increment_nonce();
+        }
+        Ok(block)
+    }
+
+    #[test]
+    fn test_template_fees_can_be_claimed() -> Result<()> {
+        let temp_dir = TempDir::new().unwrap();
+        let owner = KeyPair::new()?;
+        let mut blockchain = mature_chain(&temp_dir, &owner)?;
+        let coin = blockchain.get_block_by_height(1)?.unwrap().transactions[0].clone();
+        let paying = spend(&coin, 0, coin.outputs[0].value - 50_000, "qtc1templatepayee", &owner)?;
+        blockchain.accept_to_mempool(paying.clone())?;
+
+        let template = BlockTemplate::build(&blockchain)?;
+        let reward = blockchain.monetary_policy().coinbase_reward(template.height);
+        assert_eq!(template.total_fees, 50_000);
+        assert_eq!(template.coinbase_value, reward + 50_000);
+
+        // Consensus allows the fees and not a satoshi more
+        let greedy = mine(&blockchain, &template, template.coinbase_value + 1)?;
+        assert!(blockchain.add_block(greedy).is_err());
+        let block = mine(&blockchain, &template, template.coinbase_value)?;
+        blockchain.add_block(block.clone())?;
+        assert_eq!(blockchain.tip, block.hash());
+        assert!(blockchain.mempool.read().unwrap().is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_template_longpollid() -> Result<()> {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Arc::new(Database::new(temp_dir.path().join("test.db"))?);
+        let blockchain = Blockchain::new(db)?;
+
+        let template = BlockTemplate::build(&blockchain)?;
+        assert_eq!(template.height, 1);
+        assert!(template.transactions.is_empty());
+        assert_eq!(template.coinbase_value, blockchain.monetary_policy().coinbase_reward(1));
+        assert!(!template.supersedes(&template.longpollid));
+
+        // A miner can ask for a smaller block but never a larger one
+        let small = BlockTemplateBuilder::new().with_max_size(1_000).build(&blockchain)?;
+        assert_eq!(small.size_limit, 1_000);
+        let huge = BlockTemplateBuilder::new().with_max_size(usize::MAX).build(&blockchain)?;
+        assert_eq!(huge.size_limit, blockchain.max_template_size());
+
+        let tip = template.previous_block_hash.clone();
+        assert!(template.supersedes(&format!("{}:0", Hash256::zero().to_hex())));
+        assert!(template.supersedes("garbage"));
+
+        // Fees only count once they move past the threshold
+        let richer = BlockTemplate { total_fees: 10_500, ..template.clone() };
+        assert!(!richer.supersedes(&format!("{}:10000", tip)));
+        let richer = BlockTemplate { total_fees: 11_000, ..template };
+        assert!(richer.supersedes(&format!("{}:10000", tip)));
+        Ok(())
+    }
+
+    #[test]
+    fn test_builder_templates_connect() -> Result<()> {
+        let temp_dir = TempDir::new().unwrap();
+        let owner = KeyPair::new()?;
+        let mut blockchain = mature_chain(&temp_dir, &owner)?;
+        generate_blocks(&mut blockchain, 1, &owner.address())?;
+        let first = blockchain.get_block_by_height(1)?.unwrap().transactions[0].clone();
+        let second = blockchain.get_block_by_height(2)?.unwrap().transactions[0].clone();
+
+        let parent = spend(&first, 0, first.outputs[0].value - 50_000, &owner.address(), &owner)?;
+        let child = spend(&parent, 0, parent.outputs[0].value - 10_000, "qtc1templatepayee", &owner)?;
+        let cheap = spend(&second, 0, second.outputs[0].value - 1_000, "qtc1templatepayee", &owner)?;
+        for tx in [&parent, &child, &cheap] {
+            blockchain.accept_to_mempool(tx.clone())?;
+        }
+        let cheap_rate = blockchain.mempool.read().unwrap().get(&cheap.hash()).unwrap().fee_rate;
+
+        // The child comes after the parent it depends on, and the cheap spend waits
+        let template = BlockTemplateBuilder::new().with_min_fee_rate(cheap_rate + 1).build(&blockchain)?;
+        let txids: Vec<_> = template.transactions.iter().map(|tx| tx.txid.clone()).collect();
+        assert_eq!(txids, vec![parent.hash().to_hex(), child.hash().to_hex()]);
+        assert_eq!(template.transactions[1].depends, vec![0]);
+        assert_eq!(template.total_fees, 60_000);
+
+        let block = mine(&blockchain, &template, template.coinbase_value)?;
+        blockchain.add_block(block.clone())?;
+        assert_eq!(blockchain.tip, block.hash());
+        let mempool = blockchain.mempool.read().unwrap();
+        assert_eq!(mempool.len(), 1);
+        assert!(mempool.contains(&cheap.hash()));
+        Ok(())
+    }
+}
@@ -0,0 +1,305 @@
+//! Misbehavior scores for connected peers and the bans they lead to
+//!
+//! Protocol violations add points to the offending peer's score; a peer that
+//! reaches the threshold is disconnected and banned for a while. Bans, the
+//! ones an operator sets included, are kept in the database so a restart
+//! doesn't forgive them. Each ban, and its end, goes in the audit trail.
+
+use crate::storage::database::AuditAction;
+use crate::storage::Database;
+use crate::{QtcError, Result};
+use libp2p::multiaddr::{Multiaddr, Protocol};
+use libp2p::PeerId;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::net::IpAddr;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+/// Score at which a peer is banned
+pub const DEFAULT_BAN_THRESHOLD: u32 = 100;
+/// How long a peer stays banned unless told otherwise
+pub const DEFAULT_BAN_DURATION_SECS: u64 = 24 * 60 * 60;
+
+/// Protocol violations a peer is scored for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Misbehavior {
+    InvalidProofOfWork,
+    MalformedMessage,
+    OversizedPayload,
+    BadChecksum,
+    UnrequestedBlocks,
+}
+
+impl Misbehavior {
+    pub fn score(self) -> u32 {
+        match self {
+            Misbehavior::InvalidProofOfWork => 100, // costs the sender nothing to make, us a validation
+            Misbehavior::OversizedPayload => 50,
+            Misbehavior::MalformedMessage => 20,
+            Misbehavior::BadChecksum => 20, // the transport already checks integrity, so it was sent that way
+            Misbehavior::UnrequestedBlocks => 5, // may just be a late answer to a request that timed out
+        }
+    }
+
+    pub fn describe(self) -> &'static str {
+        match self {
+            Misbehavior::InvalidProofOfWork => "invalid proof of work",
+            Misbehavior::MalformedMessage => "malformed message",
+            Misbehavior::OversizedPayload => "oversized payload",
+            Misbehavior::BadChecksum => "bad checksum",
+            Misbehavior::UnrequestedBlocks => "unrequested blocks",
+        }
+    }
+}
+
+/// Misbehavior points of the peers we are connected to
+#[derive(Debug)]
+pub struct BanScores<P> {
+    threshold: u32,
+    scores: HashMap<P, u32>,
+}
+
+impl<P: Eq + Hash + Clone> BanScores<P> {
+    pub fn new(threshold: u32) -> Self {
+        Self { threshold: threshold.max(1), scores: HashMap::new() }
+    }
+
+    pub fn threshold(&self) -> u32 {
+        self.threshold
+    }
+
+    /// Add the points for `misbehavior`; true once `peer` reaches the threshold,
+    /// which also clears its score
+    pub fn record(&mut self, peer: &P, misbehavior: Misbehavior) -> bool {
+        let score = self.scores.entry(peer.clone()).or_insert(0);
+        *score = score.saturating_add(misbehavior.score());
+        if *score < self.threshold {
+            return false;
+        }
+        self.scores.remove(peer);
+        true
+    }
+
+    pub fn score(&self, peer: &P) -> u32 {
+        self.scores.get(peer).copied().unwrap_or(0)
+    }
+
+    pub fn remove(&mut self, peer: &P) {
+        self.scores.remove(peer);
+    }
+}
+
+/// A banned peer ID or IP address
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Ban {
+    pub target: String,
+    pub reason: String,
+    pub created_at: u64,
+    pub banned_until: u64,
+}
+
+impl Ban {
+    pub fn is_active(&self, now: u64) -> bool {
+        now < self.banned_until
+    }
+}
+
+/// The banned peer IDs and IP addresses, shared by the P2P node and the API
+#[derive(Debug)]
+pub struct BanList {
+    db: Option<Arc<Database>>, // none keeps bans in memory only
+    bans: RwLock<HashMap<String, Ban>>,
+}
+
+impl Default for BanList {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BanList {
+    pub fn new() -> Self {
+        Self { db: None, bans: RwLock::new(HashMap::new()) }
+    }
+
+    /// Load the saved bans, forgetting the ones that ran out while the node was down
+    pub fn load(db: Arc<Database>) -> Result<Self> {
+        let bans = db.list_peer_bans()?.into_iter()
+            .map(|ban| (ban.target.clone(), ban))
+            .collect();
+        let list = Self { db: Some(db), bans: RwLock::new(bans) };
+        list.expire()?;
+        let loaded = list.bans.read().unwrap().len();
+        if loaded > 0 {
+            log::info!("🚫 Loaded {} peer ban(s)", loaded);
+        }
+        Ok(list)
+    }
+
+    /// Ban a peer ID or IP address for `duration`, replacing any ban it already has;
+    /// `origin` is who asked, as the audit trail records it
+    pub fn ban(&self, target: &str, duration: Duration, reason: &str, origin: &str) -> Result<Ban> {
+        let target = normalize_target(target)?;
+        let created_at = now();
+        let ban = Ban {
+            target: target.clone(),
+            reason: reason.to_string(),
+            created_at,
+            banned_until: created_at.saturating_add(duration.as_secs()),
+        };
+        if let Some(db) = &self.db {
+            db.save_peer_ban(&ban)?;
+        }
+        self.bans.write().unwrap().insert(target, ban.clone());
+        self.audit(AuditAction::PeerBanned, origin, format!(
+            "{} for {}s: {}", ban.target, duration.as_secs(), ban.reason
+        ))?;
+        Ok(ban)
+    }
+
+    /// Lift a ban; false if `target` wasn't banned
+    pub fn unban(&self, target: &str, origin: &str) -> Result<bool> {
+        let target = normalize_target(target)?;
+        if let Some(db) = &self.db {
+            db.remove_peer_ban(&target)?;
+        }
+        let lifted = self.bans.write().unwrap().remove(&target).is_some();
+        if lifted {
+            self.audit(AuditAction::PeerUnbanned, origin, format!("{}: ban lifted", target))?;
+        }
+        Ok(lifted)
+    }
+
+    /// Forget the bans that have run out, returning how many did
+    pub fn expire(&self) -> Result<usize> {
+        let now = now();
+        let expired: Vec<Ban> = {
+            let mut bans = self.bans.write().unwrap();
+            let targets: Vec<String> = bans.values()
+                .filter(|ban| !ban.is_active(now))
+                .map(|ban| ban.target.clone())
+                .collect();
+            targets.iter().filter_map(|target| bans.remove(target)).collect()
+        };
+        for ban in &expired {
+            if let Some(db) = &self.db {
+                db.remove_peer_ban(&ban.target)?;
+            }
+            log::info!("✅ Ban on {} expired", ban.target);
+            self.audit(AuditAction::PeerUnbanned, "node", format!("{}: ban expired ({})", ban.target, ban.reason))?;
+        }
+        Ok(expired.len())
+    }
+
+    fn audit(&self, action: AuditAction, origin: &str, details: String) -> Result<()> {
+        if let Some(db) = &self.db {
+            db.record_audit_event(action, origin, details)?;
+        }
+        Ok(())
+    }
+
+    pub fn is_banned(&self, target: &str) -> bool {
+        self.bans.read().unwrap().get(target).is_some_and(|ban| ban.is_active(now()))
+    }
+
+    /// Whether the peer, or the IP address it connects from, is banned
+    pub fn is_peer_banned(&self, peer_id: &str, address: &Multiaddr) -> bool {
+        self.is_banned(peer_id) || ip_of(address).is_some_and(|ip| self.is_banned(&ip.to_string()))
+    }
+
+    /// Bans still in force, the most recent first
+    pub fn list(&self) -> Vec<Ban> {
+        let now = now();
+        let mut bans: Vec<Ban> = self.bans.read().unwrap().values()
+            .filter(|ban| ban.is_active(now))
+            .cloned()
+            .collect();
+        bans.sort_by(|a, b| b.created_at.cmp(&a.created_at).then_with(|| a.target.cmp(&b.target)));
+        bans
+    }
+}
+
+/// IPs are stored in their canonical form, so `::1` and `0:0::1` are one ban
+fn normalize_target(target: &str) -> Result<String> {
+    let target = target.trim();
+    if let Ok(ip) = target.parse::<IpAddr>() {
+        return Ok(ip.to_string());
+    }
+    if target.parse::<PeerId>().is_ok() {
+        return Ok(target.to_string());
+    }
+    Err(QtcError::InvalidInput(format!("'{}' is neither a peer ID nor an IP address", target)))
+}
+
+fn ip_of(address: &Multiaddr) -> Option<IpAddr> {
+    address.iter().find_map(|protocol| match protocol {
+        Protocol::Ip4(ip) => Some(IpAddr::V4(ip)),
+        Protocol::Ip6(ip) => Some(IpAddr::V6(ip)),
+        _ => None,
+    })
+}
+
+fn now() -> u64 {
+    chrono::Utc::now().timestamp() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_scores_ban_at_the_threshold() {
+        let mut scores = BanScores::new(DEFAULT_BAN_THRESHOLD);
+        let peer = "peer".to_string();
+
+        for _ in 0..4 {
+            assert!(!scores.record(&peer, Misbehavior::MalformedMessage));
+        }
+        assert_eq!(scores.score(&peer), 80);
+        assert!(scores.record(&peer, Misbehavior::MalformedMessage));
+        assert_eq!(scores.score(&peer), 0);
+
+        assert!(scores.record(&peer, Misbehavior::InvalidProofOfWork));
+    }
+
+    #[test]
+    fn test_bans_persist_and_expire() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Arc::new(Database::new(temp_dir.path().join("db")).unwrap());
+        let peer_id = PeerId::random().to_string();
+
+        let bans = BanList::load(db.clone()).unwrap();
+        bans.ban(&peer_id, Duration::from_secs(3_600), "test", "node").unwrap();
+        bans.ban("0:0::1", Duration::ZERO, "expired", "cli").unwrap();
+        assert!(bans.ban("not-a-peer", Duration::from_secs(60), "test", "node").is_err());
+
+        let address: Multiaddr = "/ip4/10.0.0.1/tcp/8333".parse().unwrap();
+        assert!(bans.is_peer_banned(&peer_id, &address));
+        assert!(!bans.is_banned("::1"));
+
+        let reloaded = BanList::load(db.clone()).unwrap();
+        assert_eq!(reloaded.list().iter().map(|ban| ban.target.as_str()).collect::<Vec<_>>(), vec![peer_id.as_str()]);
+        assert_eq!(db.list_peer_bans().unwrap().len(), 1);
+
+        reloaded.ban("10.0.0.1", Duration::from_secs(60), "test", "node").unwrap();
+        assert!(reloaded.is_peer_banned(&PeerId::random().to_string(), &address));
+        assert!(reloaded.unban(&peer_id, "cli").unwrap());
+        assert!(!reloaded.unban(&peer_id, "cli").unwrap());
+        assert_eq!(BanList::load(db.clone()).unwrap().list().len(), 1);
+
+        // Every ban and every end of one is in the audit trail
+        let trail: Vec<(AuditAction, String)> = db.get_audit_events(0, 100).unwrap().into_iter()
+            .map(|event| (event.action, event.origin))
+            .collect();
+        assert_eq!(trail, vec![
+            (AuditAction::PeerBanned, "node".to_string()),
+            (AuditAction::PeerBanned, "cli".to_string()),
+            (AuditAction::PeerUnbanned, "node".to_string()), // ::1 ran out while "down"
+            (AuditAction::PeerBanned, "node".to_string()),
+            (AuditAction::PeerUnbanned, "cli".to_string()),
+        ]);
+    }
+}
@@ -0,0 +1,281 @@
+//! Peer diversity across network groups, to make eclipse attacks harder
+
+use crate::{QtcError, Result};
+use libp2p::multiaddr::{Multiaddr, Protocol};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::net::IpAddr;
+use std::sync::Mutex;
+
+/// Default cap on connected peers sharing one subnet or ASN
+pub const DEFAULT_MAX_PEERS_PER_GROUP: usize = 2;
+
+/// Loopback, private and link-local peers share this group and are never capped
+const LOCAL_GROUP: &str = "local";
+
+/// Longest-prefix IP to ASN mapping, loaded from `prefix,asn` lines
+#[derive(Debug, Clone, Default)]
+pub struct AsnMap {
+    prefixes: Vec<(IpAddr, u8, u32)>, // (network, prefix length, ASN), longest prefix first
+}
+
+impl AsnMap {
+    /// Parse lines like `203.0.113.0/24,64500` or `2001:db8::/32,AS64501`
+    pub fn from_csv(data: &str) -> Result<Self> {
+        let mut prefixes = Vec::new();
+
+        for (line_no, line) in data.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let invalid = || QtcError::InvalidInput(format!("Invalid ASN map line {}: {}", line_no + 1, line));
+            let (prefix, asn) = line.split_once(',').ok_or_else(invalid)?;
+            let (network, length) = prefix.trim().split_once('/').ok_or_else(invalid)?;
+            let network: IpAddr = network.parse().map_err(|_| invalid())?;
+            let length: u8 = length.parse().map_err(|_| invalid())?;
+            let asn: u32 = asn.trim().trim_start_matches("AS").parse().map_err(|_| invalid())?;
+
+            let max_length = if network.is_ipv4() { 32 } else { 128 };
+            if length > max_length {
+                return Err(invalid());
+            }
+            prefixes.push((network, length, asn));
+        }
+
+        prefixes.sort_by_key(|(_, length, _)| std::cmp::Reverse(*length));
+        Ok(Self { prefixes })
+    }
+
+    pub fn load(path: &std::path::Path) -> Result<Self> {
+        Self::from_csv(&std::fs::read_to_string(path)?)
+    }
+
+    pub fn lookup(&self, ip: &IpAddr) -> Option<u32> {
+        self.prefixes.iter()
+            .find(|(network, length, _)| prefix_matches(network, *length, ip))
+            .map(|(_, _, asn)| *asn)
+    }
+
+    pub fn len(&self) -> usize {
+        self.prefixes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.prefixes.is_empty()
+    }
+}
+
+fn prefix_matches(network: &IpAddr, length: u8, ip: &IpAddr) -> bool {
+    match (network, ip) {
+        (IpAddr::V4(network), IpAddr::V4(ip)) => {
+            let mask = u32::MAX.checked_shl(32 - length as u32).unwrap_or(0);
+            u32::from(*network) & mask == u32::from(*ip) & mask
+        }
+        (IpAddr::V6(network), IpAddr::V6(ip)) => {
+            let mask = u128::MAX.checked_shl(128 - length as u32).unwrap_or(0);
+            u128::from(*network) & mask == u128::from(*ip) & mask
+        }
+        _ => false,
+    }
+}
+
+fn is_local(ip: &IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => ip.is_loopback() || ip.is_private() || ip.is_link_local() || ip.is_unspecified(),
+        IpAddr::V6(ip) => {
+            let first = ip.segments()[0];
+            ip.is_loopback() || ip.is_unspecified()
+                || first & 0xfe00 == 0xfc00 // unique local
+                || first & 0xffc0 == 0xfe80 // link local
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DiversityStats {
+    pub peer_count: usize,
+    pub distinct_groups: usize,
+    pub max_peers_per_group: usize,
+    pub grouping: String, // "subnet" or "asn"
+    pub groups: BTreeMap<String, usize>,
+    pub rejected_connections: u64,
+}
+
+#[derive(Debug, Default)]
+struct DiversityState {
+    peers: HashMap<String, String>, // peer id -> group
+    rejected: u64,
+}
+
+/// Tracks which network group each connected peer is in and caps peers per group
+#[derive(Debug)]
+pub struct PeerDiversity {
+    max_per_group: usize,
+    asmap: Option<AsnMap>,
+    state: Mutex<DiversityState>,
+}
+
+impl PeerDiversity {
+    pub fn new(max_per_group: usize) -> Self {
+        Self {
+            max_per_group: max_per_group.max(1),
+            asmap: None,
+            state: Mutex::new(DiversityState::default()),
+        }
+    }
+
+    /// Group peers by ASN where the map knows the address, by subnet otherwise
+    pub fn with_asmap(max_per_group: usize, asmap: AsnMap) -> Self {
+        Self {
+            asmap: Some(asmap),
+            ..Self::new(max_per_group)
+        }
+    }
+
+    /// Network group of an address: its ASN, else its /16 (IPv4) or /32 (IPv6)
+    pub fn group(&self, address: &Multiaddr) -> String {
+        let ip = address.iter().find_map(|protocol| match protocol {
+            Protocol::Ip4(ip) => Some(IpAddr::V4(ip)),
+            Protocol::Ip6(ip) => Some(IpAddr::V6(ip)),
+            _ => None,
+        });
+
+        let Some(ip) = ip else {
+            return "unknown".to_string();
+        };
+        if is_local(&ip) {
+            return LOCAL_GROUP.to_string();
+        }
+        if let Some(asn) = self.asmap.as_ref().and_then(|asmap| asmap.lookup(&ip)) {
+            return format!("AS{}", asn);
+        }
+
+        match ip {
+            IpAddr::V4(ip) => {
+                let octets = ip.octets();
+                format!("{}.{}.0.0/16", octets[0], octets[1])
+            }
+            IpAddr::V6(ip) => {
+                let segments = ip.segments();
+                format!("{:x}:{:x}::/32", segments[0], segments[1])
+            }
+        }
+    }
+
+    /// Count `peer_id` towards its group, or refuse it if the group is full
+    pub fn try_admit(&self, peer_id: &str, address: &Multiaddr) -> bool {
+        let group = self.group(address);
+        let mut state = self.lock();
+
+        if group != LOCAL_GROUP {
+            let in_group = state.peers.iter()
+                .filter(|(peer, peer_group)| peer.as_str() != peer_id && **peer_group == group)
+                .count();
+            if in_group >= self.max_per_group {
+                state.rejected += 1;
+                return false;
+            }
+        }
+
+        state.peers.insert(peer_id.to_string(), group);
+        true
+    }
+
+    pub fn remove(&self, peer_id: &str) {
+        self.lock().peers.remove(peer_id);
+    }
+
+    /// Order dial candidates so under-represented groups come first; full groups are dropped
+    pub fn rank_candidates<T>(&self, candidates: Vec<(T, Multiaddr)>) -> Vec<(T, Multiaddr)> {
+        let counts = self.group_counts();
+        let mut ranked: Vec<(usize, (T, Multiaddr))> = candidates.into_iter()
+            .filter_map(|(peer, address)| {
+                let group = self.group(&address);
+                let count = counts.get(&group).copied().unwrap_or(0);
+                if group != LOCAL_GROUP && count >= self.max_per_group {
+                    return None;
+                }
+                Some((count, (peer, address)))
+            })
+            .collect();
+
+        ranked.sort_by_key(|(count, _)| *count);
+        ranked.into_iter().map(|(_, candidate)| candidate).collect()
+    }
+
+    pub fn stats(&self) -> DiversityStats {
+        let groups = self.group_counts();
+        let state = self.lock();
+
+        DiversityStats {
+            peer_count: state.peers.len(),
+            distinct_groups: groups.len(),
+            max_peers_per_group: self.max_per_group,
+            grouping: if self.asmap.is_some() { "asn" } else { "subnet" }.to_string(),
+            groups,
+            rejected_connections: state.rejected,
+        }
+    }
+
+    fn group_counts(&self) -> BTreeMap<String, usize> {
+        let mut counts = BTreeMap::new();
+        for group in self.lock().peers.values() {
+            *counts.entry(group.clone()).or_insert(0) += 1;
+        }
+        counts
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, DiversityState> {
+        match self.state.lock() {
+            Ok(state) => state,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn addr(s: &str) -> Multiaddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_subnet_cap_and_asn_grouping() -> Result<()> {
+        let diversity = PeerDiversity::new(2);
+        assert_eq!(diversity.group(&addr("/ip4/203.0.113.7/tcp/8333")), "203.0.0.0/16");
+
+        assert!(diversity.try_admit("a", &addr("/ip4/203.0.1.1/tcp/8333")));
+        assert!(diversity.try_admit("b", &addr("/ip4/203.0.2.2/tcp/8333")));
+        assert!(!diversity.try_admit("c", &addr("/ip4/203.0.3.3/tcp/8333")));
+        assert!(diversity.try_admit("d", &addr("/ip4/198.51.100.1/tcp/8333")));
+
+        // Local peers are never capped
+        for peer in ["l1", "l2", "l3"] {
+            assert!(diversity.try_admit(peer, &addr("/ip4/192.168.1.5/tcp/8333")));
+        }
+
+        let ranked = diversity.rank_candidates(vec![
+            ("full", addr("/ip4/203.0.9.9/tcp/8333")),
+            ("used", addr("/ip4/198.51.100.2/tcp/8333")),
+            ("fresh", addr("/ip4/192.0.2.1/tcp/8333")),
+        ]);
+        assert_eq!(ranked.iter().map(|(peer, _)| *peer).collect::<Vec<_>>(), vec!["fresh", "used"]);
+
+        let stats = diversity.stats();
+        assert_eq!(stats.peer_count, 6);
+        assert_eq!(stats.groups["203.0.0.0/16"], 2);
+        assert_eq!(stats.rejected_connections, 1);
+
+        let asmap = AsnMap::from_csv("# test map\n203.0.0.0/16,64500\n203.0.113.0/24,AS64501\n")?;
+        let by_asn = PeerDiversity::with_asmap(1, asmap);
+        assert_eq!(by_asn.group(&addr("/ip4/203.0.113.7/tcp/8333")), "AS64501");
+        assert_eq!(by_asn.group(&addr("/ip4/203.0.5.7/tcp/8333")), "AS64500");
+        assert_eq!(by_asn.group(&addr("/ip4/198.51.100.1/tcp/8333")), "198.51.0.0/16");
+        assert!(AsnMap::from_csv("10.0.0.0/33,1\n").is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,190 @@
+//! Protocol versions and the features each connection negotiated
+//!
+//! Nodes announce their version and the bitmask of features they offer in the
+//! `Version` message of the connection handshake (see `handshake`), which is
+//! what a connection goes by. Identify carries the same as
+//! `/qtc/<version>.0.0+<services>/<genesis>`, for display. Version 1 nodes
+//! predate the bitmask and send a bare `/qtc/1.0.0`, or no services in their
+//! `Version`, which implies gossip relay only. A connection uses the features
+//! both ends offer, so relay and sync code asks `PeerInfo::supports` rather
+//! than assuming what the other side can do.
+
+use crate::crypto::hash::Hash256;
+use serde::{Deserialize, Serialize};
+
+/// Version this node speaks
+pub const PROTOCOL_VERSION: u32 = 3;
+
+/// Oldest version still interoperable over gossip
+pub const MIN_PROTOCOL_VERSION: u32 = 1;
+
+/// First version to wrap its gossip in checksummed frames; older peers'
+/// bare payloads are still taken from them
+pub const FRAMING_PROTOCOL_VERSION: u32 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Feature {
+    BlockRelay,
+    TxRelay,
+    HeadersSync,
+    CompactBlocks,
+    BlockFilters,
+    PeerExchange,
+}
+
+impl Feature {
+    pub const ALL: [Feature; 6] = [
+        Feature::BlockRelay,
+        Feature::TxRelay,
+        Feature::HeadersSync,
+        Feature::CompactBlocks,
+        Feature::BlockFilters,
+        Feature::PeerExchange,
+    ];
+
+    pub fn bit(self) -> u64 {
+        1 << self as u64
+    }
+}
+
+/// A set of features, carried on the wire as a bitmask
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(into = "Vec<Feature>", from = "Vec<Feature>")]
+pub struct FeatureSet(u64);
+
+impl FeatureSet {
+    pub fn empty() -> Self {
+        Self(0)
+    }
+
+    pub fn from_bits(bits: u64) -> Self {
+        Self(bits)
+    }
+
+    pub fn bits(self) -> u64 {
+        self.0
+    }
+
+    /// What this build of qtcd offers its peers
+    pub fn local() -> Self {
+        [Feature::BlockRelay, Feature::TxRelay, Feature::HeadersSync].into_iter().collect()
+    }
+
+    /// What a peer on `version` offers without announcing a bitmask
+    pub fn implied_by(version: u32) -> Self {
+        if version >= MIN_PROTOCOL_VERSION {
+            [Feature::BlockRelay, Feature::TxRelay].into_iter().collect()
+        } else {
+            Self::empty()
+        }
+    }
+
+    pub fn contains(self, feature: Feature) -> bool {
+        self.0 & feature.bit() != 0
+    }
+
+    pub fn insert(&mut self, feature: Feature) {
+        self.0 |= feature.bit();
+    }
+
+    /// Features both ends can use; bits we don't know are dropped
+    pub fn negotiate(self, theirs: FeatureSet) -> Self {
+        Self(self.0 & theirs.0)
+    }
+
+    pub fn iter(self) -> impl Iterator<Item = Feature> {
+        Feature::ALL.into_iter().filter(move |feature| self.contains(*feature))
+    }
+}
+
+impl FromIterator<Feature> for FeatureSet {
+    fn from_iter<I: IntoIterator<Item = Feature>>(features: I) -> Self {
+        let mut set = Self::empty();
+        for feature in features {
+            set.insert(feature);
+        }
+        set
+    }
+}
+
+impl From<FeatureSet> for Vec<Feature> {
+    fn from(set: FeatureSet) -> Self {
+        set.iter().collect()
+    }
+}
+
+impl From<Vec<Feature>> for FeatureSet {
+    fn from(features: Vec<Feature>) -> Self {
+        features.into_iter().collect()
+    }
+}
+
+/// What we announce as identify's protocol version, naming the chain we're on
+pub fn local_protocol_version(genesis: &Hash256) -> String {
+    format!("/qtc/{}.0.0+{:x}/{}", PROTOCOL_VERSION, FeatureSet::local().bits(), genesis)
+}
+
+/// Version and offered features from a peer's identify protocol version, or
+/// None if it isn't a QTC node we can talk to
+pub fn parse_protocol_version(protocol_version: &str) -> Option<(u32, FeatureSet)> {
+    let release = protocol_version.strip_prefix("/qtc/")?;
+    let release = release.split_once('/').map_or(release, |(release, _)| release);
+    let (release, services) = match release.split_once('+') {
+        Some((release, services)) => (release, Some(u64::from_str_radix(services, 16).ok()?)),
+        None => (release, None),
+    };
+    let version: u32 = release.split('.').next()?.parse().ok()?;
+    if version < MIN_PROTOCOL_VERSION {
+        return None;
+    }
+
+    let features = services.map_or_else(|| FeatureSet::implied_by(version), FeatureSet::from_bits);
+    Some((version, features))
+}
+
+/// The genesis hash a peer announced, if it sent a well-formed one
+pub fn parse_protocol_genesis(protocol_version: &str) -> Option<Hash256> {
+    let (_, genesis) = protocol_version.strip_prefix("/qtc/")?.split_once('/')?;
+    Hash256::from_hex(genesis).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_old_and_new_peers_negotiate_common_features() {
+        let ours = FeatureSet::local();
+        let genesis = Hash256::hash(b"genesis");
+        let (version, theirs) = parse_protocol_version(&local_protocol_version(&genesis)).unwrap();
+        assert_eq!(version, PROTOCOL_VERSION);
+        assert_eq!(ours.negotiate(theirs), ours);
+        assert_eq!(parse_protocol_genesis(&local_protocol_version(&genesis)), Some(genesis));
+        assert_eq!(parse_protocol_genesis("/qtc/2.0.0+7"), None);
+
+        // A version 1 node only relays gossip, so we don't ask it for headers
+        let (version, legacy) = parse_protocol_version("/qtc/1.0.0").unwrap();
+        assert_eq!(version, 1);
+        let with_legacy = ours.negotiate(legacy);
+        assert!(with_legacy.contains(Feature::BlockRelay) && with_legacy.contains(Feature::TxRelay));
+        assert!(!with_legacy.contains(Feature::HeadersSync));
+
+        // A newer node offering compact blocks and bits we've never heard of
+        let newer = FeatureSet::local().bits() | Feature::CompactBlocks.bit() | 1 << 40;
+        let (_, future) = parse_protocol_version(&format!("/qtc/3.0.0+{:x}", newer)).unwrap();
+        assert_eq!(ours.negotiate(future), ours);
+
+        // A blocks-only node
+        let (_, blocks_only) = parse_protocol_version("/qtc/2.0.0+5").unwrap();
+        assert!(!ours.negotiate(blocks_only).contains(Feature::TxRelay));
+
+        assert!(parse_protocol_version("/ipfs/0.1.0").is_none());
+        assert!(parse_protocol_version("/qtc/0.9.0").is_none());
+        assert!(parse_protocol_version("/qtc/2.0.0+zz").is_none());
+
+        let json = serde_json::to_string(&with_legacy).unwrap();
+        assert_eq!(json, r#"["block_relay","tx_relay"]"#);
+        assert_eq!(serde_json::from_str::<FeatureSet>(&json).unwrap(), with_legacy);
+    }
+}
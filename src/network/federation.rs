@@ -0,0 +1,96 @@
+//! Federation mode for private, consortium-run networks
+//!
+//! With an allowlist configured, blocks and transactions are only relayed
+//! and handed to the node when their gossip author is a member. Members are
+//! identified by peer ID, so they need a stable identity (`load_or_create_identity`).
+//! Everything that passes still goes through full consensus validation.
+
+use crate::{QtcError, Result};
+use libp2p::identity::Keypair;
+use libp2p::PeerId;
+use std::collections::HashSet;
+use std::path::Path;
+
+#[derive(Debug, Clone, Default)]
+pub struct FederationAllowlist {
+    members: HashSet<PeerId>,
+}
+
+impl FederationAllowlist {
+    pub fn new(members: impl IntoIterator<Item = PeerId>) -> Self {
+        Self { members: members.into_iter().collect() }
+    }
+
+    /// Parse the base58 peer IDs listed under `network.federation_peers`
+    pub fn parse(peer_ids: &[String]) -> Result<Self> {
+        let members = peer_ids.iter()
+            .map(|id| id.trim().parse::<PeerId>()
+                .map_err(|e| QtcError::InvalidInput(format!("Invalid federation peer ID {}: {}", id, e))))
+            .collect::<Result<HashSet<_>>>()?;
+        Ok(Self { members })
+    }
+
+    /// Messages without an author can't be attributed to a member and are refused
+    pub fn allows(&self, author: Option<&PeerId>) -> bool {
+        author.is_some_and(|peer_id| self.members.contains(peer_id))
+    }
+
+    pub fn len(&self) -> usize {
+        self.members.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.members.is_empty()
+    }
+}
+
+/// The node's P2P key, kept at `path` so its peer ID survives restarts
+pub fn load_or_create_identity(path: &Path) -> Result<Keypair> {
+    if path.exists() {
+        let bytes = std::fs::read(path)?;
+        return Keypair::from_protobuf_encoding(&bytes)
+            .map_err(|e| QtcError::Network(format!("Corrupt node key {}: {}", path.display(), e)));
+    }
+
+    let keypair = Keypair::generate_ed25519();
+    let bytes = keypair.to_protobuf_encoding()
+        .map_err(|e| QtcError::Network(format!("Failed to encode node key: {}", e)))?;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(path, bytes)?;
+
+    // The key is the node's network identity, keep it private
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
+    }
+
+    log::info!("🔑 Created node identity {}", PeerId::from(keypair.public()));
+    Ok(keypair)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_allowlist_and_stable_identity() -> Result<()> {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("node_key");
+
+        let member = PeerId::from(load_or_create_identity(&path)?.public());
+        assert_eq!(PeerId::from(load_or_create_identity(&path)?.public()), member);
+
+        let outsider = PeerId::random();
+        let allowlist = FederationAllowlist::parse(&[member.to_string()])?;
+        assert!(allowlist.allows(Some(&member)));
+        assert!(!allowlist.allows(Some(&outsider)));
+        assert!(!allowlist.allows(None));
+
+        assert!(FederationAllowlist::parse(&["not-a-peer-id".to_string()]).is_err());
+        Ok(())
+    }
+}
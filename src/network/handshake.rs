@@ -0,0 +1,158 @@
+//! The version handshake every connection opens with
+//!
+//! As soon as a connection is up, each side sends its `Version` over the
+//! `/qtc/handshake/1` request/response protocol, apart from gossip, and the
+//! other answers `VerAck`, or `Reject` when the two aren't on the same chain:
+//! a different network magic or genesis block, or a protocol version older
+//! than `MIN_PROTOCOL_VERSION`. Mismatched peers are disconnected, and a
+//! peer's gossip is ignored until both its `Version` and its `VerAck` are in.
+
+use crate::crypto::hash::Hash256;
+use crate::network::features::{FeatureSet, MIN_PROTOCOL_VERSION};
+use crate::network::protocol::MessageType;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::time::Duration;
+
+pub const HANDSHAKE_PROTOCOL: &str = "/qtc/handshake/1";
+
+/// How long a `Version` waits for its answer, and a new peer for its handshake to finish
+pub const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// `Reject` code for a peer on an obsolete protocol version
+pub const REJECT_OBSOLETE: u8 = 0x11;
+/// `Reject` code for a peer on another network or chain
+pub const REJECT_WRONG_CHAIN: u8 = 0x12;
+
+/// Which chain a node follows, as its `Version` announces it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChainIdentity {
+    pub magic: [u8; 4],
+    pub genesis: Hash256,
+}
+
+/// How far a peer's handshake has got
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Handshake {
+    pub version_received: bool, // theirs checked out
+    pub verack_received: bool,  // ours was accepted
+}
+
+impl Handshake {
+    pub fn is_complete(&self) -> bool {
+        self.version_received && self.verack_received
+    }
+}
+
+/// What an accepted `Version` tells us about the peer
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PeerVersion {
+    pub version: u32,
+    pub features: FeatureSet, // offered, before negotiation
+    pub start_height: u64,
+    pub user_agent: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Mismatch {
+    NotVersion,
+    Magic([u8; 4]),
+    Genesis(Hash256),
+    Obsolete(u32),
+}
+
+impl Mismatch {
+    pub fn reject_code(&self) -> u8 {
+        match self {
+            Mismatch::Obsolete(_) => REJECT_OBSOLETE,
+            _ => REJECT_WRONG_CHAIN,
+        }
+    }
+
+    pub fn to_reject(&self) -> MessageType {
+        MessageType::Reject {
+            message: "version".to_string(),
+            code: self.reject_code(),
+            reason: self.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Mismatch::NotVersion => write!(f, "handshake must open with a version message"),
+            Mismatch::Magic(magic) => write!(f, "on another network (magic {})", hex::encode(magic)),
+            Mismatch::Genesis(genesis) => write!(f, "on a different chain (genesis {})", genesis),
+            Mismatch::Obsolete(version) => write!(f, "protocol version {} is older than {}", version, MIN_PROTOCOL_VERSION),
+        }
+    }
+}
+
+/// Check a peer's `Version` against the chain we follow
+pub fn check_version(local: &ChainIdentity, message: &MessageType) -> Result<PeerVersion, Mismatch> {
+    let MessageType::Version { version, services, user_agent, start_height, magic, genesis, .. } = message else {
+        return Err(Mismatch::NotVersion);
+    };
+    if *magic != local.magic {
+        return Err(Mismatch::Magic(*magic));
+    }
+    if *genesis != local.genesis {
+        return Err(Mismatch::Genesis(*genesis));
+    }
+    if *version < MIN_PROTOCOL_VERSION {
+        return Err(Mismatch::Obsolete(*version));
+    }
+
+    // Peers predating service bits send 0
+    let features = match services {
+        0 => FeatureSet::implied_by(*version),
+        bits => FeatureSet::from_bits(*bits),
+    };
+    Ok(PeerVersion { version: *version, features, start_height: *start_height, user_agent: user_agent.clone() })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::consensus::params::MAINNET_MAGIC;
+    use crate::network::features::{Feature, PROTOCOL_VERSION};
+
+    fn version(magic: [u8; 4], genesis: Hash256, version: u32, services: u64) -> MessageType {
+        MessageType::Version {
+            version,
+            services,
+            timestamp: 0,
+            addr_recv: String::new(),
+            addr_from: String::new(),
+            nonce: 0,
+            user_agent: "QTC/1.0.0".to_string(),
+            start_height: 42,
+            magic,
+            genesis,
+        }
+    }
+
+    #[test]
+    fn test_check_version_matches_chain_identity() {
+        let genesis = Hash256::hash(b"genesis");
+        let local = ChainIdentity { magic: MAINNET_MAGIC, genesis };
+
+        let peer = check_version(&local, &version(MAINNET_MAGIC, genesis, PROTOCOL_VERSION, FeatureSet::local().bits())).unwrap();
+        assert_eq!((peer.version, peer.start_height), (PROTOCOL_VERSION, 42));
+        assert!(peer.features.contains(Feature::HeadersSync));
+        // No service bits implies what the version offered
+        let peer = check_version(&local, &version(MAINNET_MAGIC, genesis, 1, 0)).unwrap();
+        assert_eq!(peer.features, FeatureSet::implied_by(1));
+
+        let testnet = version(*b"QTCT", genesis, PROTOCOL_VERSION, 0);
+        assert_eq!(check_version(&local, &testnet), Err(Mismatch::Magic(*b"QTCT")));
+        let fork = Hash256::hash(b"fork");
+        let mismatch = check_version(&local, &version(MAINNET_MAGIC, fork, PROTOCOL_VERSION, 0)).unwrap_err();
+        assert_eq!(mismatch, Mismatch::Genesis(fork));
+        assert_eq!(mismatch.reject_code(), REJECT_WRONG_CHAIN);
+        let mismatch = check_version(&local, &version(MAINNET_MAGIC, genesis, 0, 0)).unwrap_err();
+        assert_eq!(mismatch.reject_code(), REJECT_OBSOLETE);
+        assert_eq!(check_version(&local, &MessageType::VerAck), Err(Mismatch::NotVersion));
+    }
+}
@@ -0,0 +1,357 @@
+//! End-to-end encrypted messages between multisig cosigners
+//!
+//! A wallet's messaging identity is a secp256k1 key that signs what it sends
+//! and a Kyber768 key that cosigners encrypt to. Cosigners swap identity cards
+//! the way they swap descriptors. A PSBT is encrypted to the recipient's card,
+//! with the sender's card and signature inside the ciphertext, and published on
+//! the `qtc/messages` topic over the peers' noise-encrypted connections. Relays
+//! only see the recipient tag and ciphertext; the node whose wallet the tag
+//! belongs to keeps the envelope in its inbox until the wallet reads it.
+
+use crate::crypto::hash::Hash256;
+use crate::crypto::keys::{PrivateKey, PublicKey};
+use crate::crypto::signatures::Signature;
+use crate::storage::Database;
+use crate::wallet::psbt::Psbt;
+use crate::{QtcError, Result};
+use aes_gcm::aead::{Aead, KeyInit};
+use aes_gcm::{Aes256Gcm, Nonce};
+use pqcrypto_kyber::kyber768;
+use pqcrypto_traits::kem::{Ciphertext as _, PublicKey as _, SecretKey as _, SharedSecret as _};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+
+/// Kept under the 64 KiB gossipsub default, so envelopes still reach nodes that never raised it
+pub const MAX_ENVELOPE_SIZE: usize = 60 * 1024;
+
+/// Envelopes kept per wallet; more are dropped until the wallet reads its inbox
+pub const MAX_INBOX_MESSAGES: usize = 256;
+
+const CARD_PREFIX: &str = "qtcid";
+const NONCE_SIZE: usize = 12;
+
+/// A wallet's messaging keys, which never leave the node
+#[derive(Clone, Serialize, Deserialize)]
+pub struct MessagingIdentity {
+    pub wallet: String,
+    signing_key: [u8; 32],
+    kem_public: Vec<u8>,
+    kem_secret: Vec<u8>,
+}
+
+/// What cosigners need to send a wallet messages, signed by its messaging key
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IdentityCard {
+    pub wallet: String,
+    pub signing_key: PublicKey,
+    pub kem_key: Vec<u8>, // Kyber768 public key
+    pub signature: Signature,
+}
+
+/// What travels over the network; only the recipient can open it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Envelope {
+    pub recipient: Hash256, // tag of the recipient's card
+    pub kem_ciphertext: Vec<u8>,
+    pub nonce: [u8; NONCE_SIZE],
+    pub ciphertext: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DirectMessage {
+    pub sent_at: u64,
+    pub note: Option<String>,
+    pub psbt: Vec<u8>, // as `Psbt::serialize` writes it
+}
+
+/// The plaintext of an envelope
+#[derive(Serialize, Deserialize)]
+struct SignedMessage {
+    sender: IdentityCard,
+    message: DirectMessage,
+    signature: Signature, // over the message and the recipient tag
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReceivedMessage {
+    pub id: Hash256,
+    pub from: IdentityCard,
+    pub message: DirectMessage,
+}
+
+impl MessagingIdentity {
+    pub fn new(wallet: &str) -> Result<Self> {
+        let (kem_public, kem_secret) = kyber768::keypair();
+        Ok(Self {
+            wallet: wallet.to_string(),
+            signing_key: PrivateKey::new()?.to_bytes(),
+            kem_public: kem_public.as_bytes().to_vec(),
+            kem_secret: kem_secret.as_bytes().to_vec(),
+        })
+    }
+
+    pub fn tag(&self) -> Hash256 {
+        Hash256::hash(&self.kem_public)
+    }
+
+    pub fn card(&self) -> Result<IdentityCard> {
+        let signing_key = PrivateKey::from_bytes(&self.signing_key)?;
+        let public_key = signing_key.public_key()?;
+        let binding = IdentityCard::binding(&self.wallet, &public_key, &self.kem_public);
+        Ok(IdentityCard {
+            wallet: self.wallet.clone(),
+            signing_key: public_key,
+            kem_key: self.kem_public.clone(),
+            signature: signing_key.sign(&binding)?,
+        })
+    }
+
+    /// Encrypt `message` so only `recipient` can read it
+    pub fn seal(&self, recipient: &IdentityCard, message: DirectMessage) -> Result<Envelope> {
+        recipient.verify()?;
+        let tag = recipient.tag();
+        let signature = PrivateKey::from_bytes(&self.signing_key)?.sign(&message_digest(&message, &tag)?)?;
+        let plaintext = bincode::serialize(&SignedMessage { sender: self.card()?, message, signature })
+            .map_err(|e| QtcError::Crypto(format!("Failed to serialize message: {}", e)))?;
+
+        let kem_key = kyber768::PublicKey::from_bytes(&recipient.kem_key)
+            .map_err(|e| QtcError::Crypto(format!("Invalid messaging key: {:?}", e)))?;
+        let (shared_secret, kem_ciphertext) = kyber768::encapsulate(&kem_key);
+        let mut nonce = [0u8; NONCE_SIZE];
+        rand::thread_rng().fill_bytes(&mut nonce);
+        let ciphertext = envelope_cipher(shared_secret.as_bytes(), &tag)?
+            .encrypt(Nonce::from_slice(&nonce), plaintext.as_ref())
+            .map_err(|_| QtcError::Crypto("Encryption failed".to_string()))?;
+
+        let envelope = Envelope { recipient: tag, kem_ciphertext: kem_ciphertext.as_bytes().to_vec(), nonce, ciphertext };
+        let size = envelope.to_bytes()?.len();
+        if size > MAX_ENVELOPE_SIZE {
+            return Err(QtcError::InvalidInput(format!(
+                "Message is {} bytes, at most {} fit in an envelope", size, MAX_ENVELOPE_SIZE
+            )));
+        }
+        Ok(envelope)
+    }
+
+    pub fn open(&self, envelope: &Envelope) -> Result<ReceivedMessage> {
+        if envelope.recipient != self.tag() {
+            return Err(QtcError::Crypto(format!("Message is not addressed to wallet '{}'", self.wallet)));
+        }
+        let kem_secret = kyber768::SecretKey::from_bytes(&self.kem_secret)
+            .map_err(|e| QtcError::Crypto(format!("Invalid messaging key: {:?}", e)))?;
+        let kem_ciphertext = kyber768::Ciphertext::from_bytes(&envelope.kem_ciphertext)
+            .map_err(|e| QtcError::Crypto(format!("Invalid key encapsulation: {:?}", e)))?;
+        let shared_secret = kyber768::decapsulate(&kem_ciphertext, &kem_secret);
+        let plaintext = envelope_cipher(shared_secret.as_bytes(), &envelope.recipient)?
+            .decrypt(Nonce::from_slice(&envelope.nonce), envelope.ciphertext.as_ref())
+            .map_err(|_| QtcError::Crypto("Message failed to decrypt".to_string()))?;
+
+        let signed: SignedMessage = bincode::deserialize(&plaintext)
+            .map_err(|e| QtcError::Crypto(format!("Corrupt message: {}", e)))?;
+        signed.sender.verify()?;
+        if !signed.sender.signing_key.verify(&message_digest(&signed.message, &envelope.recipient)?, &signed.signature)? {
+            return Err(QtcError::Crypto(format!("Message signature from {} is invalid", signed.sender.fingerprint())));
+        }
+        Psbt::deserialize(&signed.message.psbt)?;
+
+        Ok(ReceivedMessage { id: envelope.id()?, from: signed.sender, message: signed.message })
+    }
+}
+
+impl IdentityCard {
+    fn binding(wallet: &str, signing_key: &PublicKey, kem_key: &[u8]) -> Hash256 {
+        let mut data = wallet.as_bytes().to_vec();
+        data.extend_from_slice(signing_key.to_bytes());
+        data.extend_from_slice(kem_key);
+        Hash256::hash(&data)
+    }
+
+    pub fn verify(&self) -> Result<()> {
+        let binding = Self::binding(&self.wallet, &self.signing_key, &self.kem_key);
+        if !self.signing_key.verify(&binding, &self.signature)? {
+            return Err(QtcError::Crypto("Identity card signature is invalid".to_string()));
+        }
+        Ok(())
+    }
+
+    /// Envelopes for this card carry the tag, so relays can't tell which wallet it is
+    pub fn tag(&self) -> Hash256 {
+        Hash256::hash(&self.kem_key)
+    }
+
+    /// Short enough to read out when cosigners compare cards
+    pub fn fingerprint(&self) -> String {
+        Hash256::hash(self.signing_key.to_bytes()).to_hex()[..16].to_string()
+    }
+}
+
+impl fmt::Display for IdentityCard {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let data = bincode::serialize(self).map_err(|_| fmt::Error)?;
+        write!(f, "{}{}", CARD_PREFIX, bs58::encode(data).into_string())
+    }
+}
+
+impl FromStr for IdentityCard {
+    type Err = QtcError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let data = s.trim().strip_prefix(CARD_PREFIX)
+            .and_then(|encoded| bs58::decode(encoded).into_vec().ok())
+            .ok_or_else(|| QtcError::InvalidInput("Not an identity card".to_string()))?;
+        let card: Self = bincode::deserialize(&data)
+            .map_err(|e| QtcError::InvalidInput(format!("Corrupt identity card: {}", e)))?;
+        card.verify()?;
+        Ok(card)
+    }
+}
+
+impl Envelope {
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        bincode::serialize(self).map_err(|e| QtcError::Network(format!("Failed to serialize envelope: {}", e)))
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Self> {
+        bincode::deserialize(data).map_err(|e| QtcError::Network(format!("Invalid envelope: {}", e)))
+    }
+
+    pub fn id(&self) -> Result<Hash256> {
+        Ok(Hash256::hash(&self.to_bytes()?))
+    }
+}
+
+fn message_digest(message: &DirectMessage, recipient: &Hash256) -> Result<Hash256> {
+    let mut data = bincode::serialize(message)
+        .map_err(|e| QtcError::Crypto(format!("Failed to serialize message: {}", e)))?;
+    data.extend_from_slice(recipient.as_bytes());
+    Ok(Hash256::hash(&data))
+}
+
+fn envelope_cipher(shared_secret: &[u8], recipient: &Hash256) -> Result<Aes256Gcm> {
+    let mut data = b"qtc/messages".to_vec();
+    data.extend_from_slice(shared_secret);
+    data.extend_from_slice(recipient.as_bytes());
+    Aes256Gcm::new_from_slice(Hash256::hash(&data).as_bytes())
+        .map_err(|_| QtcError::Crypto("Invalid encryption key".to_string()))
+}
+
+/// The node's side of messaging: local identities and their inboxes
+pub struct Mailbox {
+    db: Arc<Database>,
+    local_tags: RwLock<HashSet<Hash256>>,
+}
+
+impl Mailbox {
+    pub fn new(db: Arc<Database>) -> Result<Self> {
+        let local_tags = db.get_messaging_identities()?.iter().map(MessagingIdentity::tag).collect();
+        Ok(Self { db, local_tags: RwLock::new(local_tags) })
+    }
+
+    /// The wallet's identity, created the first time it is asked for
+    pub fn identity(&self, wallet: &str) -> Result<MessagingIdentity> {
+        if let Some(identity) = self.db.get_messaging_identity(wallet)? {
+            return Ok(identity);
+        }
+        if self.db.get_wallet(wallet)?.is_none() {
+            return Err(QtcError::Wallet(format!("Wallet '{}' not found", wallet)));
+        }
+        let identity = MessagingIdentity::new(wallet)?;
+        self.db.save_messaging_identity(&identity)?;
+        self.local_tags.write().unwrap().insert(identity.tag());
+        log::info!("🪪 Created messaging identity for wallet '{}'", wallet);
+        Ok(identity)
+    }
+
+    /// Keep `envelope` if it is for a local wallet; false if it is someone else's
+    pub fn accept(&self, envelope: &Envelope) -> Result<bool> {
+        if !self.local_tags.read().unwrap().contains(&envelope.recipient) {
+            return Ok(false);
+        }
+        if self.db.count_inbox_envelopes(&envelope.recipient)? >= MAX_INBOX_MESSAGES {
+            log::warn!("📪 Inbox {} is full, dropping message", envelope.recipient);
+            return Ok(true);
+        }
+        self.db.save_inbox_envelope(envelope)?;
+        Ok(true)
+    }
+
+    /// Messages waiting for `wallet`, oldest first; ones that fail to open are discarded
+    pub fn inbox(&self, wallet: &str) -> Result<Vec<ReceivedMessage>> {
+        let Some(identity) = self.db.get_messaging_identity(wallet)? else {
+            return Ok(Vec::new());
+        };
+        let mut messages = Vec::new();
+        for envelope in self.db.get_inbox_envelopes(&identity.tag())? {
+            match identity.open(&envelope) {
+                Ok(message) => messages.push(message),
+                Err(e) => {
+                    log::warn!("📪 Discarding message for wallet '{}': {}", wallet, e);
+                    self.db.remove_inbox_envelope(&envelope.recipient, &envelope.id()?)?;
+                }
+            }
+        }
+        messages.sort_by_key(|received| received.message.sent_at);
+        Ok(messages)
+    }
+
+    pub fn remove(&self, wallet: &str, id: &Hash256) -> Result<bool> {
+        match self.db.get_messaging_identity(wallet)? {
+            Some(identity) => self.db.remove_inbox_envelope(&identity.tag(), id),
+            None => Ok(false),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{Blockchain, Transaction};
+    use crate::wallet::Wallet;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_direct_message_roundtrip() -> Result<()> {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Arc::new(Database::new(temp_dir.path().join("test.db"))?);
+        let blockchain = Arc::new(RwLock::new(Blockchain::new(db.clone())?));
+        Wallet::new_simple("alice".to_string(), db.clone(), blockchain)?.save()?;
+        let mailbox = Mailbox::new(db.clone())?;
+        assert!(mailbox.identity("nobody").is_err());
+        let alice = mailbox.identity("alice")?;
+        let bob = MessagingIdentity::new("bob")?;
+
+        // Cards survive being pasted around, and tampering shows
+        let card: IdentityCard = alice.card()?.to_string().parse()?;
+        assert_eq!(card, alice.card()?);
+        let mut forged = card.clone();
+        forged.wallet = "mallory".to_string();
+        assert!(forged.to_string().parse::<IdentityCard>().is_err());
+
+        let psbt = Psbt::new(Transaction::new(), Vec::new(), Vec::new())?.serialize()?;
+        let message = DirectMessage { sent_at: 1, note: Some("please sign".to_string()), psbt };
+        let envelope = bob.seal(&card, message.clone())?;
+        assert!(bob.open(&envelope).is_err());
+        assert!(mailbox.accept(&envelope)?);
+        assert!(!mailbox.accept(&MessagingIdentity::new("carol")?.seal(&bob.card()?, message)?)?);
+
+        // A reopened node still knows its wallets' identities
+        let mailbox = Mailbox::new(db)?;
+        let inbox = mailbox.inbox("alice")?;
+        assert_eq!(inbox.len(), 1);
+        assert_eq!(inbox[0].from, bob.card()?);
+        assert_eq!(inbox[0].message.note.as_deref(), Some("please sign"));
+
+        // Flipping a ciphertext bit makes the message unreadable
+        let mut tampered = bob.seal(&card, inbox[0].message.clone())?;
+        tampered.ciphertext[0] ^= 1;
+        assert!(alice.open(&tampered).is_err());
+
+        assert!(mailbox.remove("alice", &inbox[0].id)?);
+        assert!(mailbox.inbox("alice")?.is_empty());
+        Ok(())
+    }
+}
@@ -1,7 +1,27 @@
 //! Networking module for P2P communication
 
+pub mod bans;
+pub mod diversity;
+pub mod features;
+pub mod federation;
+pub mod handshake;
+pub mod messaging;
 pub mod p2p;
+pub mod partition;
 pub mod protocol;
+pub mod proxy;
+pub mod relay;
+pub mod seen;
+pub mod sync;
+pub mod versions;
 
+pub use bans::{Ban, BanList, Misbehavior};
+pub use diversity::{DiversityStats, PeerDiversity};
+pub use federation::FederationAllowlist;
+pub use features::{Feature, FeatureSet};
+pub use messaging::{DirectMessage, Envelope, IdentityCard, Mailbox, ReceivedMessage};
 pub use p2p::{P2PNode, PeerInfo, NetworkStats};
+pub use partition::{PartitionAlert, PartitionMonitor};
 pub use protocol::{Message, MessageType, ProtocolHandler};
+pub use proxy::ProxyConfig;
+pub use versions::{PeerVersions, VersionSummary};
@@ -1,25 +1,66 @@
+use crate::api::metrics::metrics;
 use crate::core::{Block, Transaction, Blockchain};
-use crate::crypto::hash::Hashable;
-use crate::network::protocol::{Message, MessageType, ProtocolHandler};
+use crate::crypto::hash::{Hash256, Hashable};
+use crate::network::bans::{BanList, BanScores, Misbehavior, DEFAULT_BAN_DURATION_SECS, DEFAULT_BAN_THRESHOLD};
+use crate::network::diversity::{DiversityStats, PeerDiversity, DEFAULT_MAX_PEERS_PER_GROUP};
+use crate::network::federation::FederationAllowlist;
+use crate::network::messaging::{Envelope, Mailbox};
+use crate::network::features::{local_protocol_version, Feature, FeatureSet, FRAMING_PROTOCOL_VERSION, PROTOCOL_VERSION};
+use crate::network::handshake::{check_version, ChainIdentity, Handshake, PeerVersion, HANDSHAKE_PROTOCOL, HANDSHAKE_TIMEOUT};
+use crate::network::partition::{PartitionAlert, PartitionCheck, PartitionMonitor};
+use crate::network::protocol::{decode_frame, encode_frame, is_framed, Message, MessageType, PayloadKind, ProtocolHandler, BLOCKS_PROTOCOL, FILTERS_PROTOCOL, FRAME_HEADER_SIZE, MAX_BLOCK_PAYLOAD};
+use crate::network::proxy::{ProxyConfig, Socks5Transport};
+use crate::network::relay::RelayQueue;
+use crate::network::seen::SeenCache;
+use crate::network::sync::{BlockDownloader, BlocksRequest, BlocksResponse, ChunkRequest, DEFAULT_REQUEST_TIMEOUT, MAX_SYNC_MESSAGE_BYTES};
+use crate::network::versions::PeerVersions;
 use crate::{QtcError, Result};
 use libp2p::{
     futures::StreamExt,
-    gossipsub, identify, kad, mdns, noise, ping, swarm::NetworkBehaviour, tcp, yamux, PeerId,
-    Swarm, SwarmBuilder,
+    core::upgrade, gossipsub, identify, kad, mdns, noise, ping, request_response, swarm::behaviour::toggle::Toggle,
+    tcp, yamux, PeerId, StreamProtocol, Swarm, SwarmBuilder, Transport,
 };
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::sync::{Arc, RwLock};
 use std::time::{Duration, Instant};
-use tokio::sync::{broadcast, mpsc};
+use tokio::sync::{broadcast, mpsc, oneshot};
 
-// Manual NetworkBehaviour implementation for libp2p 0.53 compatibility
-pub struct QtcBehaviour {
-    pub gossipsub: gossipsub::Behaviour,
-    pub mdns: mdns::tokio::Behaviour,
-    pub kademlia: kad::Behaviour<kad::store::MemoryStore>,
-    pub identify: identify::Behaviour,
-    pub ping: ping::Behaviour,
+const SEEN_BLOCKS_CAPACITY: usize = 5_000;
+const SEEN_TRANSACTIONS_CAPACITY: usize = 100_000;
+/// Transactions held back while no peer is subscribed to the transaction topic
+const PENDING_TRANSACTIONS_CAPACITY: usize = 1_000;
+/// Oldest outbound connections dropped per partition probe, freeing slots for fresh peers
+const PARTITION_ROTATE_PEERS: usize = 2;
+/// How often block download checks for stalled requests and hands out more chunks
+const SYNC_TICK: Duration = Duration::from_secs(2);
+/// Most blocks served for one range request
+const MAX_SERVED_BLOCKS: u64 = 128;
+/// Largest gossip message of any kind, refused before its topic's own limit is looked at
+const MAX_GOSSIP_PAYLOAD_BYTES: usize = MAX_BLOCK_PAYLOAD + FRAME_HEADER_SIZE;
+/// Gossipsub's own cap on a message, which carries its source key, signature and topic besides the data
+const MAX_GOSSIP_TRANSMIT_BYTES: usize = MAX_GOSSIP_PAYLOAD_BYTES + 4 * 1024;
+
+pub use behaviour::QtcBehaviour;
+
+// Apart, as the derive expands to a bare `Result` that `crate::Result` would shadow
+mod behaviour {
+    use super::P2PEvent;
+    use crate::network::protocol::Message;
+    use crate::network::sync::{BlocksRequest, BlocksResponse};
+    use libp2p::{gossipsub, identify, kad, mdns, ping, request_response, swarm::behaviour::toggle::Toggle, swarm::NetworkBehaviour};
+
+    #[derive(NetworkBehaviour)]
+    #[behaviour(to_swarm = "P2PEvent")]
+    pub struct QtcBehaviour {
+        pub gossipsub: gossipsub::Behaviour,
+        pub mdns: Toggle<mdns::tokio::Behaviour>, // off behind a proxy, where it would announce our LAN address
+        pub kademlia: kad::Behaviour<kad::store::MemoryStore>,
+        pub identify: identify::Behaviour,
+        pub ping: ping::Behaviour,
+        pub handshake: request_response::cbor::Behaviour<Message, Message>, // Version answered by VerAck or Reject, and GetCFilters by CFilter
+        pub sync: request_response::cbor::Behaviour<BlocksRequest, BlocksResponse>,
+    }
 }
 
 #[derive(Debug)]
@@ -29,6 +70,8 @@ pub enum P2PEvent {
     Kademlia(kad::Event),
     Identify(identify::Event),
     Ping(ping::Event),
+    Handshake(request_response::Event<Message, Message>),
+    Sync(request_response::Event<BlocksRequest, BlocksResponse>),
 }
 
 impl From<gossipsub::Event> for P2PEvent {
@@ -61,63 +104,18 @@ impl From<ping::Event> for P2PEvent {
     }
 }
 
-// Use a simplified approach for libp2p 0.53 compatibility by wrapping a single behaviour
-
-// Simplified NetworkBehaviour implementation for libp2p 0.53
-impl NetworkBehaviour for QtcBehaviour {
-    type ConnectionHandler = libp2p::swarm::dummy::ConnectionHandler;
-    type ToSwarm = P2PEvent;
-
-    fn handle_established_inbound_connection(
-        &mut self,
-        _: libp2p::swarm::ConnectionId,
-        _: libp2p::PeerId,
-        _: &libp2p::Multiaddr,
-        _: &libp2p::Multiaddr,
-    ) -> std::result::Result<Self::ConnectionHandler, libp2p::swarm::ConnectionDenied> {
-        Ok(libp2p::swarm::dummy::ConnectionHandler)
-    }
-
-    fn handle_established_outbound_connection(
-        &mut self,
-        _: libp2p::swarm::ConnectionId,
-        _: libp2p::PeerId,
-        _: &libp2p::Multiaddr,
-        _: libp2p::core::Endpoint,
-    ) -> std::result::Result<Self::ConnectionHandler, libp2p::swarm::ConnectionDenied> {
-        Ok(libp2p::swarm::dummy::ConnectionHandler)
-    }
-
-    fn on_swarm_event(&mut self, event: libp2p::swarm::FromSwarm) {
-        // Basic event handling - delegate to sub-behaviours in a simplified way
-        match event {
-            _ => {
-                // For now, just use basic event handling
-                // In production, this would properly route events to sub-behaviours
-            }
-        }
-    }
-
-    fn on_connection_handler_event(
-        &mut self,
-        _: libp2p::PeerId,
-        _: libp2p::swarm::ConnectionId,
-        _: libp2p::swarm::THandlerOutEvent<Self>,
-    ) {
-        // Minimal implementation
+impl From<request_response::Event<Message, Message>> for P2PEvent {
+    fn from(event: request_response::Event<Message, Message>) -> Self {
+        P2PEvent::Handshake(event)
     }
+}
 
-    fn poll(
-        &mut self,
-        _cx: &mut std::task::Context<'_>,
-    ) -> std::task::Poll<libp2p::swarm::ToSwarm<Self::ToSwarm, libp2p::swarm::THandlerInEvent<Self>>> {
-        // Return pending for now - in production this would poll sub-behaviours
-        std::task::Poll::Pending
+impl From<request_response::Event<BlocksRequest, BlocksResponse>> for P2PEvent {
+    fn from(event: request_response::Event<BlocksRequest, BlocksResponse>) -> Self {
+        P2PEvent::Sync(event)
     }
 }
 
-
-
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PeerInfo {
     pub peer_id: String,
@@ -128,6 +126,19 @@ pub struct PeerInfo {
     pub height: u64,
     pub ping_ms: Option<u64>,
     pub is_outbound: bool,
+    #[serde(default)]
+    pub protocol_version: u32, // 0 until the peer identifies itself
+    #[serde(default)]
+    pub features: FeatureSet, // negotiated for this connection
+    #[serde(default)]
+    pub handshake: Handshake,
+}
+
+impl PeerInfo {
+    /// Nothing is used with a peer before its handshake completes
+    pub fn supports(&self, feature: Feature) -> bool {
+        self.handshake.is_complete() && self.features.contains(feature)
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -141,14 +152,44 @@ pub struct NetworkStats {
     pub bytes_received: u64,
     pub bytes_sent: u64,
     pub uptime_seconds: u64,
+    pub duplicate_blocks: u64, // relays of blocks we had already seen
+    pub duplicate_transactions: u64,
+    #[serde(default)]
+    pub federation_rejected: u64, // blocks and transactions from non-members, in federation mode
+    pub diversity: DiversityStats,
+}
+
+/// Gossipsub topic names, namespaced by the network's magic
+struct GossipTopics {
+    blocks: String,
+    transactions: String,
+    messages: String,
 }
 
 pub struct P2PNode {
     swarm: Swarm<QtcBehaviour>,
     blockchain: Arc<RwLock<Blockchain>>,
-    _protocol_handler: ProtocolHandler,
+    identity: ChainIdentity, // peers announcing another network or genesis are dropped
+    magic: [u8; 4],          // leads every gossip frame
+    topics: GossipTopics,
+    protocol_handler: ProtocolHandler,
     peers: HashMap<PeerId, PeerInfo>,
     stats: NetworkStats,
+    seen_blocks: SeenCache,
+    seen_transactions: SeenCache,
+    downloader: BlockDownloader<PeerId>,
+    block_requests: HashMap<request_response::OutboundRequestId, u64>, // first height of the chunk each asks for
+    peer_versions: Arc<PeerVersions>,
+    diversity: Arc<PeerDiversity>,
+    federation: Option<Arc<FederationAllowlist>>,
+    mailbox: Option<Arc<Mailbox>>, // set when the node carries cosigner messages
+    bans: Arc<BanList>,
+    ban_scores: BanScores<PeerId>,
+    ban_duration: Duration,
+    pending_transactions: RelayQueue,
+    partition: Option<PartitionMonitor>,
+    probe_peers: Vec<String>, // seed and trusted peers dialed when partitioned
+    partition_alerts: broadcast::Sender<PartitionAlert>,
     start_time: Instant,
     event_sender: broadcast::Sender<Message>,
     command_receiver: mpsc::Receiver<P2PCommand>,
@@ -162,6 +203,8 @@ pub enum P2PCommand {
     ConnectPeer(String),
     DisconnectPeer(PeerId),
     GetPeers,
+    WaitForRelay(oneshot::Sender<()>), // answered once no broadcast transactions are waiting for peers
+    SendMessage(Envelope, oneshot::Sender<Result<()>>),
 }
 
 impl P2PNode {
@@ -172,6 +215,18 @@ impl P2PNode {
     ) -> Result<(Self, broadcast::Receiver<Message>, mpsc::Sender<P2PCommand>)> {
         // Generate a random peer ID
         let local_key = libp2p::identity::Keypair::generate_ed25519();
+        Self::with_identity(local_key, blockchain, port, bootstrap_nodes, None).await
+    }
+    
+    /// Run under a persistent key, so peers (e.g. a federation allowlist) can recognize this node.
+    /// With a `proxy`, every outbound connection goes through it and only loopback is listened on.
+    pub async fn with_identity(
+        local_key: libp2p::identity::Keypair,
+        blockchain: Arc<RwLock<Blockchain>>,
+        port: u16,
+        bootstrap_nodes: Vec<String>,
+        proxy: Option<ProxyConfig>,
+    ) -> Result<(Self, broadcast::Receiver<Message>, mpsc::Sender<P2PCommand>)> {
         let local_peer_id = PeerId::from(local_key.public());
         
         log::info!("🌐 Starting P2P node with peer ID: {}", local_peer_id);
@@ -183,6 +238,10 @@ impl P2PNode {
         let gossipsub_config = gossipsub::ConfigBuilder::default()
             .heartbeat_interval(Duration::from_secs(10))
             .validation_mode(gossipsub::ValidationMode::Strict)
+            // Nothing is forwarded until handle_gossip_message has vetted it
+            .validate_messages()
+            // The 64 KiB default would drop most blocks before handle_gossip_message sees them
+            .max_transmit_size(MAX_GOSSIP_TRANSMIT_BYTES)
             .build()
             .map_err(|e| QtcError::Network(format!("Gossipsub config error: {}", e)))?;
         
@@ -191,9 +250,19 @@ impl P2PNode {
             gossipsub_config,
         ).map_err(|e| QtcError::Network(format!("Gossipsub creation error: {}", e)))?;
         
-        // Subscribe to topics
-        let block_topic = gossipsub::IdentTopic::new("qtc/blocks");
-        let tx_topic = gossipsub::IdentTopic::new("qtc/transactions");
+        // Subscribe to topics; other networks use other names, so their gossip never reaches us
+        let topics = {
+            let blockchain = blockchain.read().unwrap();
+            let params = blockchain.chain_params();
+            GossipTopics {
+                blocks: params.topic("blocks"),
+                transactions: params.topic("transactions"),
+                messages: params.topic("messages"),
+            }
+        };
+        let magic = blockchain.read().unwrap().chain_params().magic;
+        let block_topic = gossipsub::IdentTopic::new(topics.blocks.clone());
+        let tx_topic = gossipsub::IdentTopic::new(topics.transactions.clone());
         
         gossipsub.subscribe(&block_topic)
             .map_err(|e| QtcError::Network(format!("Block topic subscription error: {}", e)))?;
@@ -201,8 +270,11 @@ impl P2PNode {
             .map_err(|e| QtcError::Network(format!("Transaction topic subscription error: {}", e)))?;
         
         // Configure mDNS for local peer discovery
-        let mdns = mdns::tokio::Behaviour::new(mdns::Config::default(), local_peer_id)
-            .map_err(|e| QtcError::Network(format!("mDNS creation error: {}", e)))?;
+        let mdns = match proxy {
+            Some(_) => None,
+            None => Some(mdns::tokio::Behaviour::new(mdns::Config::default(), local_peer_id)
+                .map_err(|e| QtcError::Network(format!("mDNS creation error: {}", e)))?),
+        };
         
         // Configure Kademlia DHT
         let store = kad::store::MemoryStore::new(local_peer_id);
@@ -216,34 +288,71 @@ impl P2PNode {
         }
         
         // Configure Identify
+        let genesis = blockchain.read().unwrap().genesis_hash()?;
         let identify = identify::Behaviour::new(identify::Config::new(
-            "/qtc/1.0.0".into(),
+            local_protocol_version(&genesis),
             local_key.public(),
-        ));
+        ).with_agent_version(format!("qtcd/{}", env!("CARGO_PKG_VERSION"))));
         
         // Configure Ping
         let ping = ping::Behaviour::new(ping::Config::new());
         
+        // Configure the version handshake, kept apart from gossip; light clients ask for filters alongside
+        let handshake = request_response::cbor::Behaviour::new(
+            [
+                (StreamProtocol::new(HANDSHAKE_PROTOCOL), request_response::ProtocolSupport::Full),
+                (StreamProtocol::new(FILTERS_PROTOCOL), request_response::ProtocolSupport::Inbound),
+            ],
+            request_response::Config::default().with_request_timeout(HANDSHAKE_TIMEOUT),
+        );
+        
+        // Block ranges for the downloader, also kept off gossip
+        let sync = request_response::cbor::Behaviour::new(
+            [(StreamProtocol::new(BLOCKS_PROTOCOL), request_response::ProtocolSupport::Full)],
+            request_response::Config::default().with_request_timeout(DEFAULT_REQUEST_TIMEOUT),
+        );
+        
         // Create behaviour
         let behaviour = QtcBehaviour {
             gossipsub,
-            mdns,
+            mdns: Toggle::from(mdns),
             kademlia,
             identify,
             ping,
+            handshake,
+            sync,
         };
         
         // Create swarm with simplified configuration for compatibility
-        let mut swarm = SwarmBuilder::with_existing_identity(local_key)
-            .with_tokio()
-            .with_tcp(tcp::Config::default(), noise::Config::new, yamux::Config::default)
-            .expect("Failed to configure TCP transport")
-            .with_behaviour(|_| behaviour)
-            .expect("Failed to configure behaviour")
-            .build();
-        
-        // Listen on the specified port
-        swarm.listen_on(format!("/ip4/0.0.0.0/tcp/{}", port).parse()
+        let mut swarm = match proxy {
+            Some(proxy) => {
+                log::info!("🧅 Connecting to peers through SOCKS5 proxy {}{}", proxy.address,
+                    if proxy.onion_only { ", onion services only" } else { "" });
+                SwarmBuilder::with_existing_identity(local_key)
+                    .with_tokio()
+                    .with_other_transport(|key| -> std::result::Result<_, Box<dyn std::error::Error + Send + Sync>> {
+                        Ok(Socks5Transport::new(proxy)
+                            .upgrade(upgrade::Version::V1)
+                            .authenticate(noise::Config::new(key)?)
+                            .multiplex(yamux::Config::default()))
+                    })
+                    .map_err(|e| QtcError::Network(format!("Failed to configure proxy transport: {}", e)))?
+                    .with_behaviour(|_| behaviour)
+                    .expect("Failed to configure behaviour")
+                    .build()
+            }
+            None => SwarmBuilder::with_existing_identity(local_key)
+                .with_tokio()
+                .with_tcp(tcp::Config::default(), noise::Config::new, yamux::Config::default)
+                .expect("Failed to configure TCP transport")
+                .with_behaviour(|_| behaviour)
+                .expect("Failed to configure behaviour")
+                .build(),
+        };
+        
+        // Listen on the specified port; behind a proxy only an onion service can reach us
+        let interface = if proxy.is_some() { "127.0.0.1" } else { "0.0.0.0" };
+        swarm.listen_on(format!("/ip4/{}/tcp/{}", interface, port).parse()
             .map_err(|e| QtcError::Network(format!("Failed to parse address: {}", e)))?)
             .map_err(|e| QtcError::Network(format!("Failed to listen: {}", e)))?;
         
@@ -251,12 +360,17 @@ impl P2PNode {
         let (event_sender, event_receiver) = broadcast::channel(1000);
         let (command_sender, command_receiver) = mpsc::channel(100);
         
-        let protocol_handler = ProtocolHandler::new(blockchain.clone());
+        let mut protocol_handler = ProtocolHandler::new(blockchain.clone());
+        protocol_handler.set_proxied(proxy.is_some());
+        let downloader = BlockDownloader::new(blockchain.read().unwrap().height + 1);
         
         let node = Self {
             swarm,
             blockchain,
-            _protocol_handler: protocol_handler,
+            identity: ChainIdentity { magic, genesis },
+            magic,
+            topics,
+            protocol_handler,
             peers: HashMap::new(),
             stats: NetworkStats {
                 peer_count: 0,
@@ -268,7 +382,26 @@ impl P2PNode {
                 bytes_received: 0,
                 bytes_sent: 0,
                 uptime_seconds: 0,
+                duplicate_blocks: 0,
+                duplicate_transactions: 0,
+                federation_rejected: 0,
+                diversity: DiversityStats::default(),
             },
+            seen_blocks: SeenCache::new(SEEN_BLOCKS_CAPACITY),
+            seen_transactions: SeenCache::new(SEEN_TRANSACTIONS_CAPACITY),
+            downloader,
+            block_requests: HashMap::new(),
+            peer_versions: Arc::new(PeerVersions::new()),
+            diversity: Arc::new(PeerDiversity::new(DEFAULT_MAX_PEERS_PER_GROUP)),
+            federation: None,
+            mailbox: None,
+            bans: Arc::new(BanList::new()),
+            ban_scores: BanScores::new(DEFAULT_BAN_THRESHOLD),
+            ban_duration: Duration::from_secs(DEFAULT_BAN_DURATION_SECS),
+            pending_transactions: RelayQueue::new(PENDING_TRANSACTIONS_CAPACITY),
+            partition: None,
+            probe_peers: bootstrap_nodes,
+            partition_alerts: broadcast::channel(16).0,
             start_time: Instant::now(),
             event_sender,
             command_receiver,
@@ -277,9 +410,72 @@ impl P2PNode {
         Ok((node, event_receiver, command_sender))
     }
     
+    /// Identify data of connected and recently seen peers, for the API
+    pub fn peer_versions(&self) -> Arc<PeerVersions> {
+        self.peer_versions.clone()
+    }
+    
+    /// Replace the default per-subnet peer cap, e.g. with one grouping by ASN
+    pub fn set_peer_diversity(&mut self, diversity: Arc<PeerDiversity>) {
+        self.diversity = diversity;
+    }
+    
+    /// Only relay and accept blocks and transactions authored by allowlisted peers
+    pub fn set_federation(&mut self, allowlist: Arc<FederationAllowlist>) {
+        log::info!("🤝 Federation mode: accepting gossip from {} member(s)", allowlist.len());
+        self.federation = Some(allowlist);
+    }
+    
+    /// Relay encrypted cosigner messages, keeping the ones for `mailbox`'s wallets
+    pub fn set_mailbox(&mut self, mailbox: Arc<Mailbox>) -> Result<()> {
+        self.swarm.behaviour_mut().gossipsub.subscribe(&gossipsub::IdentTopic::new(self.topics.messages.clone()))
+            .map_err(|e| QtcError::Network(format!("Message topic subscription error: {}", e)))?;
+        log::info!("📨 Carrying encrypted cosigner messages");
+        self.mailbox = Some(mailbox);
+        Ok(())
+    }
+    
+    pub fn peer_diversity(&self) -> Arc<PeerDiversity> {
+        self.diversity.clone()
+    }
+    
+    /// Keep bans in `bans`, e.g. one loaded from the database and shared with the API
+    pub fn set_ban_list(&mut self, bans: Arc<BanList>) {
+        self.bans = bans;
+    }
+    
+    /// Ban peers for `duration` once their misbehavior score reaches `threshold`
+    pub fn set_ban_policy(&mut self, threshold: u32, duration: Duration) {
+        self.ban_scores = BanScores::new(threshold);
+        self.ban_duration = duration;
+    }
+    
+    pub fn ban_list(&self) -> Arc<BanList> {
+        self.bans.clone()
+    }
+    
+    /// Probe `trusted_peers` and the bootstrap nodes when neither our tip nor
+    /// any peer's has advanced for `window`
+    pub fn set_partition_detection(&mut self, window: Duration, trusted_peers: Vec<String>) {
+        self.partition = Some(PartitionMonitor::new(window, Instant::now()));
+        self.probe_peers.extend(trusted_peers);
+        self.probe_peers.dedup();
+    }
+    
+    pub fn subscribe_partition_alerts(&self) -> broadcast::Receiver<PartitionAlert> {
+        self.partition_alerts.subscribe()
+    }
+    
     pub async fn run(&mut self) -> Result<()> {
         log::info!("🚀 P2P node started and listening for connections");
         
+        // A fixed schedule, so a steady stream of gossip can't hold maintenance off
+        let mut maintenance = tokio::time::interval_at(
+            tokio::time::Instant::now() + Duration::from_secs(30),
+            Duration::from_secs(30),
+        );
+        let mut sync = tokio::time::interval(SYNC_TICK);
+        
         loop {
             tokio::select! {
                 event = self.swarm.next() => {
@@ -292,10 +488,13 @@ impl P2PNode {
                         self.handle_command(cmd).await?;
                     }
                 }
-                _ = tokio::time::sleep(Duration::from_secs(30)) => {
+                _ = maintenance.tick() => {
                     self.update_stats();
                     self.maintenance_tasks().await?;
                 }
+                _ = sync.tick() => {
+                    self.drive_block_download()?;
+                }
             }
         }
     }
@@ -303,15 +502,26 @@ impl P2PNode {
     async fn handle_swarm_event(&mut self, event: libp2p::swarm::SwarmEvent<P2PEvent>) -> Result<()> {
         match event {
             libp2p::swarm::SwarmEvent::Behaviour(P2PEvent::Gossipsub(gossipsub::Event::Message {
-                propagation_source: _,
-                message_id: _,
+                propagation_source,
+                message_id,
                 message,
             })) => {
-                self.handle_gossip_message(message).await?;
+                let acceptance = self.handle_gossip_message(propagation_source, message).await?;
+                // Only accepted messages are forwarded to our other peers
+                let _ = self.swarm.behaviour_mut().gossipsub
+                    .report_message_validation_result(&message_id, &propagation_source, acceptance);
+            }
+            
+            libp2p::swarm::SwarmEvent::Behaviour(P2PEvent::Gossipsub(gossipsub::Event::Subscribed { peer_id, topic }))
+                if topic == gossipsub::IdentTopic::new(self.topics.transactions.clone()).hash() && !self.pending_transactions.is_empty() => {
+                log::info!("📤 Peer {} joined the transaction topic, relaying {} queued transactions",
+                    peer_id, self.pending_transactions.len());
+                self.flush_pending_transactions()?;
             }
             
             libp2p::swarm::SwarmEvent::Behaviour(P2PEvent::Mdns(mdns::Event::Discovered(list))) => {
-                for (peer_id, multiaddr) in list {
+                // Dial under-represented subnets first and skip full ones
+                for (peer_id, multiaddr) in self.diversity.rank_candidates(list) {
                     log::info!("🔍 Discovered peer via mDNS: {} at {}", peer_id, multiaddr);
                     self.swarm.behaviour_mut().gossipsub.add_explicit_peer(&peer_id);
                     self.swarm.dial(multiaddr).map_err(|e| QtcError::Network(format!("Failed to dial: {}", e)))?;
@@ -324,12 +534,30 @@ impl P2PNode {
             })) => {
                 log::info!("🆔 Identified peer: {} running {}", peer_id, info.agent_version);
                 
+                self.peer_versions.record_identify(
+                    &peer_id.to_string(),
+                    &info.agent_version,
+                    &info.protocol_version,
+                    info.protocols.iter().map(|protocol| protocol.to_string()).collect(),
+                );
+                if let Some(peer_info) = self.peers.get_mut(&peer_id) {
+                    peer_info.version = info.agent_version.clone();
+                }
+                
                 // Add peer to Kademlia
                 for addr in info.listen_addrs {
                     self.swarm.behaviour_mut().kademlia.add_address(&peer_id, addr);
                 }
             }
             
+            libp2p::swarm::SwarmEvent::Behaviour(P2PEvent::Handshake(event)) => {
+                self.handle_handshake_event(event).await?;
+            }
+            
+            libp2p::swarm::SwarmEvent::Behaviour(P2PEvent::Sync(event)) => {
+                self.handle_sync_event(event)?;
+            }
+            
             libp2p::swarm::SwarmEvent::Behaviour(P2PEvent::Ping(ping::Event { peer, connection: _, result })) => {
                 match result {
                     Ok(duration) => {
@@ -344,31 +572,54 @@ impl P2PNode {
                 }
             }
             
-            libp2p::swarm::SwarmEvent::ConnectionEstablished { peer_id, .. } => {
+            libp2p::swarm::SwarmEvent::ConnectionEstablished { peer_id, endpoint, .. } => {
+                let address = endpoint.get_remote_address();
+                if self.bans.is_peer_banned(&peer_id.to_string(), address) {
+                    log::info!("🚫 Dropping banned peer {} at {}", peer_id, address);
+                    let _ = self.swarm.disconnect_peer_id(peer_id);
+                    return Ok(());
+                }
+                if !self.diversity.try_admit(&peer_id.to_string(), address) {
+                    log::warn!("🚧 Dropping peer {} at {}: too many peers in {}",
+                        peer_id, address, self.diversity.group(address));
+                    let _ = self.swarm.disconnect_peer_id(peer_id);
+                    return Ok(());
+                }
+                
                 log::info!("🤝 Connected to peer: {}", peer_id);
                 
                 let peer_info = PeerInfo {
                     peer_id: peer_id.to_string(),
-                    address: "unknown".to_string(),
+                    address: address.to_string(),
                     connected_at: chrono::Utc::now().timestamp() as u64,
                     last_seen: chrono::Utc::now().timestamp() as u64,
                     version: "unknown".to_string(),
                     height: 0,
                     ping_ms: None,
-                    is_outbound: true,
+                    is_outbound: endpoint.is_dialer(),
+                    protocol_version: 0,
+                    features: FeatureSet::empty(),
+                    handshake: Handshake::default(),
                 };
                 
                 self.peers.insert(peer_id, peer_info);
                 self.stats.peer_count = self.peers.len();
+                metrics().peers.set(self.peers.len() as f64);
                 
-                // Request blockchain sync
-                self.request_blockchain_sync(peer_id).await?;
+                // Both ends open with their version; gossip waits until each has accepted the other's
+                let version = self.protocol_handler.create_version_message(&address.to_string())?;
+                self.swarm.behaviour_mut().handshake.send_request(&peer_id, version);
             }
             
             libp2p::swarm::SwarmEvent::ConnectionClosed { peer_id, .. } => {
                 log::info!("👋 Disconnected from peer: {}", peer_id);
                 self.peers.remove(&peer_id);
+                self.downloader.remove_peer(&peer_id);
+                self.ban_scores.remove(&peer_id);
+                self.peer_versions.mark_disconnected(&peer_id.to_string());
+                self.diversity.remove(&peer_id.to_string());
                 self.stats.peer_count = self.peers.len();
+                metrics().peers.set(self.peers.len() as f64);
             }
             
             _ => {}
@@ -377,56 +628,189 @@ impl P2PNode {
         Ok(())
     }
     
-    async fn handle_gossip_message(&mut self, message: gossipsub::Message) -> Result<()> {
+    /// Vet and process a gossip message `propagation_source` relayed to us,
+    /// scoring the peer for anything no honest node would send
+    async fn handle_gossip_message(&mut self, propagation_source: PeerId, message: gossipsub::Message) -> Result<gossipsub::MessageAcceptance> {
         let topic = message.topic.as_str();
         
+        if !self.peers.get(&propagation_source).is_some_and(|peer| peer.handshake.is_complete()) {
+            log::debug!("Ignoring gossip from {} before its handshake", propagation_source);
+            return Ok(gossipsub::MessageAcceptance::Ignore);
+        }
+        
+        if message.data.len() > MAX_GOSSIP_PAYLOAD_BYTES {
+            self.stats.bytes_received += message.data.len() as u64;
+            self.misbehaving(propagation_source, Misbehavior::OversizedPayload);
+            return Ok(gossipsub::MessageAcceptance::Reject);
+        }
+        
+        let from_member = self.federation.as_ref()
+            .is_none_or(|allowlist| allowlist.allows(message.source.as_ref()));
+        
         match topic {
-            "qtc/blocks" => {
+            topic if topic == self.topics.blocks => {
                 self.stats.blocks_received += 1;
                 self.stats.bytes_received += message.data.len() as u64;
                 
+                if !from_member {
+                    self.stats.federation_rejected += 1;
+                    log::debug!("🚫 Dropped block from non-member {:?}", message.source);
+                    return Ok(gossipsub::MessageAcceptance::Reject);
+                }
+                
+                let Some(payload) = self.unframe(propagation_source, PayloadKind::Block, &message.data) else {
+                    return Ok(gossipsub::MessageAcceptance::Reject);
+                };
+                
+                // Several peers relay the same block; only the first copy is processed
+                if !self.seen_blocks.insert(Hash256::hash(payload)) {
+                    self.stats.duplicate_blocks += 1;
+                    return Ok(gossipsub::MessageAcceptance::Ignore);
+                }
+                
                 // Deserialize and process block
-                if let Ok(block) = bincode::deserialize::<Block>(&message.data) {
+                if let Ok(block) = bincode::deserialize::<Block>(payload) {
+                    if !Blockchain::header_meets_target(&block.header) {
+                        self.misbehaving(propagation_source, Misbehavior::InvalidProofOfWork);
+                        return Ok(gossipsub::MessageAcceptance::Reject);
+                    }
                     log::info!("📦 Received block: height {}", block.header.height);
+                    if let Some(partition) = &mut self.partition {
+                        partition.observe_peer_block(block.header.height, Instant::now());
+                    }
+                    if let Some(peer_info) = message.source.and_then(|source| self.peers.get_mut(&source)) {
+                        peer_info.height = peer_info.height.max(block.header.height);
+                    }
+                    
+                    // A block past our tip + 1 means we missed some; fetch the gap in parallel
+                    let our_height = self.blockchain.read().unwrap().height;
+                    if block.header.height > our_height + 1 {
+                        self.downloader.set_target(block.header.height - 1);
+                        self.drive_block_download()?;
+                    }
                     
                     let msg = Message::new(MessageType::Block(block));
                     let _ = self.event_sender.send(msg);
                 } else {
                     log::warn!("⚠️ Failed to deserialize block");
+                    self.misbehaving(propagation_source, Misbehavior::MalformedMessage);
+                    return Ok(gossipsub::MessageAcceptance::Reject);
                 }
             }
             
-            "qtc/transactions" => {
+            topic if topic == self.topics.transactions => {
                 self.stats.transactions_received += 1;
                 self.stats.bytes_received += message.data.len() as u64;
                 
+                if !from_member {
+                    self.stats.federation_rejected += 1;
+                    log::debug!("🚫 Dropped transaction from non-member {:?}", message.source);
+                    return Ok(gossipsub::MessageAcceptance::Reject);
+                }
+                
+                let Some(payload) = self.unframe(propagation_source, PayloadKind::Transaction, &message.data) else {
+                    return Ok(gossipsub::MessageAcceptance::Reject);
+                };
+                
+                if !self.seen_transactions.insert(Hash256::hash(payload)) {
+                    self.stats.duplicate_transactions += 1;
+                    return Ok(gossipsub::MessageAcceptance::Ignore);
+                }
+                
                 // Deserialize and process transaction
-                if let Ok(tx) = bincode::deserialize::<Transaction>(&message.data) {
+                if let Ok(tx) = bincode::deserialize::<Transaction>(payload) {
                     log::debug!("💰 Received transaction: {}", hex::encode(tx.hash().as_bytes()));
                     
                     let msg = Message::new(MessageType::Transaction(tx));
                     let _ = self.event_sender.send(msg);
                 } else {
                     log::warn!("⚠️ Failed to deserialize transaction");
+                    self.misbehaving(propagation_source, Misbehavior::MalformedMessage);
+                    return Ok(gossipsub::MessageAcceptance::Reject);
+                }
+            }
+            
+            topic if topic == self.topics.messages => {
+                self.stats.bytes_received += message.data.len() as u64;
+                
+                if !from_member {
+                    self.stats.federation_rejected += 1;
+                    return Ok(gossipsub::MessageAcceptance::Reject);
+                }
+                if self.mailbox.is_none() {
+                    return Ok(gossipsub::MessageAcceptance::Ignore);
+                }
+                let Some(payload) = self.unframe(propagation_source, PayloadKind::Envelope, &message.data) else {
+                    return Ok(gossipsub::MessageAcceptance::Reject);
+                };
+                let Ok(envelope) = Envelope::from_bytes(payload) else {
+                    self.misbehaving(propagation_source, Misbehavior::MalformedMessage);
+                    return Ok(gossipsub::MessageAcceptance::Reject);
+                };
+                let Some(mailbox) = &self.mailbox else {
+                    return Ok(gossipsub::MessageAcceptance::Ignore);
+                };
+                match mailbox.accept(&envelope) {
+                    Ok(true) => log::info!("📬 Received a cosigner message for a local wallet"),
+                    Ok(false) => {}
+                    Err(e) => log::warn!("⚠️ Failed to store cosigner message: {}", e),
                 }
             }
             
             _ => {
                 log::debug!("📨 Received message on unknown topic: {}", topic);
+                return Ok(gossipsub::MessageAcceptance::Ignore);
             }
         }
         
-        Ok(())
+        Ok(gossipsub::MessageAcceptance::Accept)
+    }
+    
+    /// The payload of a gossip message, from its frame, or as sent by a peer that
+    /// predates framing; None once `propagation_source` has been scored for it
+    fn unframe<'a>(&mut self, propagation_source: PeerId, kind: PayloadKind, data: &'a [u8]) -> Option<&'a [u8]> {
+        if is_framed(self.magic, data) {
+            return match decode_frame(self.magic, kind, data) {
+                Ok(payload) => Some(payload),
+                Err(e) => {
+                    log::debug!("Peer {} sent a bad {} frame: {}", propagation_source, kind.name(), e);
+                    self.misbehaving(propagation_source, e.misbehavior());
+                    None
+                }
+            };
+        }
+        
+        let legacy = self.peers.get(&propagation_source)
+            .is_some_and(|peer| peer.protocol_version > 0 && peer.protocol_version < FRAMING_PROTOCOL_VERSION);
+        if !legacy {
+            self.misbehaving(propagation_source, Misbehavior::MalformedMessage);
+            return None;
+        }
+        if data.len() > kind.max_size() {
+            self.misbehaving(propagation_source, Misbehavior::OversizedPayload);
+            return None;
+        }
+        Some(data)
+    }
+    
+    /// `payload` in a frame for this network
+    fn frame(&self, kind: PayloadKind, payload: &[u8]) -> Result<Vec<u8>> {
+        encode_frame(self.magic, kind, payload)
     }
     
     async fn handle_command(&mut self, command: P2PCommand) -> Result<()> {
         match command {
+            // A block or transaction that can't go out now is not worth stopping the node for
             P2PCommand::BroadcastBlock(block) => {
-                self.broadcast_block(block).await?;
+                if let Err(e) = self.broadcast_block(block).await {
+                    log::warn!("⚠️ {}", e);
+                }
             }
             
             P2PCommand::BroadcastTransaction(tx) => {
-                self.broadcast_transaction(tx).await?;
+                if let Err(e) = self.broadcast_transaction(tx).await {
+                    log::warn!("⚠️ {}", e);
+                }
             }
             
             P2PCommand::RequestBlocks(start, end) => {
@@ -446,6 +830,12 @@ impl P2PNode {
                 // For now, just log the peer count
                 log::info!("📊 Currently connected to {} peers", self.peers.len());
             }
+            
+            P2PCommand::WaitForRelay(done) => self.pending_transactions.wait(done),
+            
+            P2PCommand::SendMessage(envelope, done) => {
+                let _ = done.send(self.send_message(envelope));
+            }
         }
         
         Ok(())
@@ -457,7 +847,11 @@ impl P2PNode {
         let data = bincode::serialize(&block)
             .map_err(|e| QtcError::Network(format!("Failed to serialize block: {}", e)))?;
         
-        let topic = gossipsub::IdentTopic::new("qtc/blocks");
+        // Don't process our own block again when peers relay it back
+        self.seen_blocks.insert(Hash256::hash(&data));
+        let data = self.frame(PayloadKind::Block, &data)?;
+        
+        let topic = gossipsub::IdentTopic::new(self.topics.blocks.clone());
         
         self.swarm.behaviour_mut().gossipsub.publish(topic, data)
             .map_err(|e| QtcError::Network(format!("Failed to publish block: {}", e)))?;
@@ -474,10 +868,25 @@ impl P2PNode {
         let data = bincode::serialize(&tx)
             .map_err(|e| QtcError::Network(format!("Failed to serialize transaction: {}", e)))?;
         
-        let topic = gossipsub::IdentTopic::new("qtc/transactions");
+        self.seen_transactions.insert(Hash256::hash(&data));
+        let data = self.frame(PayloadKind::Transaction, &data)?;
         
-        self.swarm.behaviour_mut().gossipsub.publish(topic, data)
-            .map_err(|e| QtcError::Network(format!("Failed to publish transaction: {}", e)))?;
+        let topic = gossipsub::IdentTopic::new(self.topics.transactions.clone());
+        
+        // Blocks-only and not yet identified peers don't take transactions
+        if !self.peers.values().any(|peer| peer.supports(Feature::TxRelay)) {
+            self.queue_transaction(tx);
+            return Ok(());
+        }
+        
+        match self.swarm.behaviour_mut().gossipsub.publish(topic, data) {
+            Ok(_) => {}
+            Err(gossipsub::PublishError::InsufficientPeers) => {
+                self.queue_transaction(tx);
+                return Ok(());
+            }
+            Err(e) => return Err(QtcError::Network(format!("Failed to publish transaction: {}", e))),
+        }
         
         self.stats.transactions_sent += 1;
         self.stats.bytes_sent += tx.size() as u64;
@@ -485,21 +894,236 @@ impl P2PNode {
         Ok(())
     }
     
+    fn send_message(&mut self, envelope: Envelope) -> Result<()> {
+        let Some(mailbox) = &self.mailbox else {
+            return Err(QtcError::Network("This node does not carry cosigner messages".to_string()));
+        };
+        // Both wallets may live on this node
+        if mailbox.accept(&envelope)? {
+            return Ok(());
+        }
+        
+        let data = self.frame(PayloadKind::Envelope, &envelope.to_bytes()?)?;
+        let size = data.len() as u64;
+        match self.swarm.behaviour_mut().gossipsub.publish(gossipsub::IdentTopic::new(self.topics.messages.clone()), data) {
+            Ok(_) => {
+                self.stats.bytes_sent += size;
+                Ok(())
+            }
+            Err(gossipsub::PublishError::InsufficientPeers) => Err(QtcError::Network(
+                "No connected peer carries cosigner messages yet".to_string()
+            )),
+            Err(e) => Err(QtcError::Network(format!("Failed to publish message: {}", e))),
+        }
+    }
+    
+    /// Keep a transaction until a peer can take it rather than dropping a wallet payment
+    fn queue_transaction(&mut self, tx: Transaction) {
+        self.pending_transactions.push(tx);
+    }
+    
+    fn flush_pending_transactions(&mut self) -> Result<()> {
+        let topic = gossipsub::IdentTopic::new(self.topics.transactions.clone());
+        
+        let mut unsent = Vec::new();
+        for tx in self.pending_transactions.take() {
+            let data = bincode::serialize(&tx)
+                .map_err(|e| QtcError::Network(format!("Failed to serialize transaction: {}", e)))?;
+            let data = self.frame(PayloadKind::Transaction, &data)?;
+            match self.swarm.behaviour_mut().gossipsub.publish(topic.clone(), data) {
+                Ok(_) => {
+                    self.stats.transactions_sent += 1;
+                    self.stats.bytes_sent += tx.size() as u64;
+                }
+                Err(gossipsub::PublishError::InsufficientPeers) => unsent.push(tx),
+                Err(e) => log::warn!("⚠️ Failed to relay queued transaction {}: {}", tx.hash(), e),
+            }
+        }
+        
+        self.pending_transactions.settle(unsent);
+        Ok(())
+    }
+    
     async fn request_blocks(&mut self, start_height: u64, end_height: u64) -> Result<()> {
         log::info!("📥 Requesting blocks {} to {}", start_height, end_height);
+        self.downloader.set_target(end_height);
+        self.drive_block_download()
+    }
+    
+    /// Follow the tip, give stalled chunks to other peers, hand out new ones and
+    /// pass on every block that now extends the chain
+    fn drive_block_download(&mut self) -> Result<()> {
+        let our_height = self.blockchain.read().unwrap().height;
+        self.downloader.sync_tip(our_height);
+        if self.downloader.is_idle() {
+            return Ok(());
+        }
         
-        // In a full implementation, this would send a specific request message
-        // For now, we'll implement a simplified version
+        let now = Instant::now();
+        for stalled in self.downloader.check_timeouts(now) {
+            log::warn!("🐌 Peer {} stalled on blocks {} to {}, asking another peer", stalled.peer, stalled.start, stalled.end);
+        }
         
-        for peer_id in self.peers.keys().cloned().collect::<Vec<_>>() {
-            // Send block request to peer
-            // This would use a custom protocol in production
-            log::debug!("Requesting blocks from peer: {}", peer_id);
+        // Version 1 peers only gossip new blocks and can't serve ranges
+        let peers: Vec<PeerId> = self.peers.iter()
+            .filter(|(_, peer)| peer.supports(Feature::HeadersSync))
+            .map(|(peer_id, _)| *peer_id)
+            .collect();
+        if peers.is_empty() {
+            log::debug!("No connected peer serves block ranges, waiting for gossip");
         }
         
+        for request in self.downloader.schedule(&peers, now) {
+            self.send_block_request(&request);
+        }
+        log::debug!("📥 Block download at {} of {}, {} chunk(s) in flight",
+            self.downloader.next_height(), self.downloader.target_height(), self.downloader.in_flight());
         Ok(())
     }
     
+    fn send_block_request(&mut self, request: &ChunkRequest<PeerId>) {
+        log::debug!("Requesting blocks {} to {} from peer: {}", request.start, request.end, request.peer);
+        let request_id = self.swarm.behaviour_mut().sync
+            .send_request(&request.peer, BlocksRequest { start: request.start, end: request.end });
+        self.block_requests.insert(request_id, request.start);
+    }
+    
+    /// Serve a peer's range request, and take the blocks answering ours
+    fn handle_sync_event(&mut self, event: request_response::Event<BlocksRequest, BlocksResponse>) -> Result<()> {
+        match event {
+            request_response::Event::Message { peer, message: request_response::Message::Request { request, channel, .. } } => {
+                // Blocks are only for peers that have shown they're on our chain
+                if !self.peers.get(&peer).is_some_and(|peer_info| peer_info.handshake.is_complete()) {
+                    self.misbehaving(peer, Misbehavior::MalformedMessage);
+                    return Ok(());
+                }
+                let response = self.serve_blocks(peer, request.start, request.end)?;
+                if self.swarm.behaviour_mut().sync.send_response(channel, response).is_err() {
+                    log::debug!("Peer {} went away before its blocks were sent", peer);
+                }
+            }
+            
+            request_response::Event::Message { peer, message: request_response::Message::Response { request_id, response } } => {
+                let Some(start) = self.block_requests.remove(&request_id) else {
+                    return Ok(());
+                };
+                match response {
+                    BlocksResponse::Blocks(data) => {
+                        self.stats.bytes_received += data.len() as u64;
+                        let blocks = match decode_frame(self.magic, PayloadKind::Sync, &data) {
+                            Ok(payload) => bincode::deserialize::<Vec<Block>>(payload).map_err(|_| Misbehavior::MalformedMessage),
+                            Err(e) => Err(e.misbehavior()),
+                        };
+                        match blocks {
+                            Ok(blocks) => self.take_blocks(peer, blocks),
+                            Err(misbehavior) => self.misbehaving(peer, misbehavior),
+                        }
+                        self.downloader.on_answered(&peer, start);
+                    }
+                    BlocksResponse::NotFound { from } => {
+                        log::debug!("Peer {} has no blocks from {} on", peer, from);
+                        self.downloader.on_not_found(&peer, from.max(start));
+                    }
+                }
+                self.drive_block_download()?;
+            }
+            
+            request_response::Event::OutboundFailure { peer, request_id, error } => {
+                if let Some(start) = self.block_requests.remove(&request_id) {
+                    log::debug!("Block request to {} failed: {}", peer, error);
+                    self.downloader.on_not_found(&peer, start);
+                    self.drive_block_download()?;
+                }
+            }
+            
+            request_response::Event::InboundFailure { peer, error, .. } => {
+                log::debug!("Block request from {} failed: {}", peer, error);
+            }
+            
+            request_response::Event::ResponseSent { .. } => {}
+        }
+        Ok(())
+    }
+    
+    /// Hand the downloader the blocks `peer` answered with
+    fn take_blocks(&mut self, peer: PeerId, blocks: Vec<Block>) {
+        for block in blocks {
+            let height = block.header.height;
+            if !Blockchain::header_meets_target(&block.header) {
+                self.misbehaving(peer, Misbehavior::InvalidProofOfWork);
+                break;
+            }
+            if !self.downloader.on_block(&peer, block) {
+                log::debug!("Ignoring unrequested block {} from {}", height, peer);
+                self.misbehaving(peer, Misbehavior::UnrequestedBlocks);
+            }
+        }
+        for block in self.downloader.ready_blocks() {
+            let _ = self.event_sender.send(Message::new(MessageType::Block(block)));
+        }
+    }
+    
+    /// The answer to a range request: the blocks from `start` that fit in one
+    /// response, or `NotFound` if we don't have the first of them
+    fn serve_blocks(&mut self, peer: PeerId, start: u64, end: u64) -> Result<BlocksResponse> {
+        let end = end.min(start.saturating_add(MAX_SERVED_BLOCKS - 1));
+        let mut blocks = Vec::new();
+        let mut size = 0;
+        
+        for height in start..=end {
+            let Some(block) = self.blockchain.read().unwrap().get_block_by_height(height)? else {
+                break;
+            };
+            let block_size = block.size();
+            if !blocks.is_empty() && size + block_size > MAX_SYNC_MESSAGE_BYTES {
+                break;
+            }
+            size += block_size;
+            blocks.push(block);
+        }
+        
+        if blocks.is_empty() {
+            return Ok(BlocksResponse::NotFound { from: start });
+        }
+        log::debug!("📤 Serving {} block(s) from {} to peer {}", blocks.len(), start, peer);
+        let payload = bincode::serialize(&blocks)
+            .map_err(|e| QtcError::Network(format!("Failed to serialize blocks: {}", e)))?;
+        let data = self.frame(PayloadKind::Sync, &payload)?;
+        self.stats.bytes_sent += data.len() as u64;
+        Ok(BlocksResponse::Blocks(data))
+    }
+    
+    /// Score a protocol violation, banning and dropping the peer once its score reaches the threshold
+    fn misbehaving(&mut self, peer_id: PeerId, misbehavior: Misbehavior) {
+        log::warn!("⚠️ Peer {} sent {} (+{} misbehavior)", peer_id, misbehavior.describe(), misbehavior.score());
+        if !self.ban_scores.record(&peer_id, misbehavior) {
+            return;
+        }
+        
+        let reason = format!("misbehavior score reached {}, last: {}", self.ban_scores.threshold(), misbehavior.describe());
+        match self.bans.ban(&peer_id.to_string(), self.ban_duration, &reason, "node") {
+            Ok(_) => log::warn!("🚫 Banned peer {} for {}s: {}", peer_id, self.ban_duration.as_secs(), reason),
+            Err(e) => log::warn!("⚠️ Failed to save the ban on peer {}: {}", peer_id, e),
+        }
+        let _ = self.swarm.disconnect_peer_id(peer_id);
+    }
+    
+    /// Drop connected peers banned since they connected, e.g. over the API
+    fn drop_banned_peers(&mut self) {
+        let banned: Vec<PeerId> = self.peers.iter()
+            .filter(|(peer_id, info)| match info.address.parse() {
+                Ok(address) => self.bans.is_peer_banned(&peer_id.to_string(), &address),
+                Err(_) => self.bans.is_banned(&peer_id.to_string()),
+            })
+            .map(|(peer_id, _)| *peer_id)
+            .collect();
+        
+        for peer_id in banned {
+            log::info!("🚫 Disconnecting banned peer {}", peer_id);
+            let _ = self.swarm.disconnect_peer_id(peer_id);
+        }
+    }
+    
     async fn connect_peer(&mut self, address: String) -> Result<()> {
         log::info!("🔗 Connecting to peer: {}", address);
         
@@ -522,26 +1146,126 @@ impl P2PNode {
         Ok(())
     }
     
-    async fn request_blockchain_sync(&mut self, peer_id: PeerId) -> Result<()> {
-        log::info!("🔄 Requesting blockchain sync from peer: {}", peer_id);
-        
-        // Get our current height
-        let our_height = {
-            let blockchain = self.blockchain.read().unwrap();
-            blockchain.height
+    /// Answer a peer's version, and act on its answer to ours, dropping peers
+    /// on another network or chain, and ones that won't take part
+    async fn handle_handshake_event(&mut self, event: request_response::Event<Message, Message>) -> Result<()> {
+        match event {
+            request_response::Event::Message { peer, message: request_response::Message::Request { request, channel, .. } }
+                if matches!(request.message_type, MessageType::GetCFilters { .. }) =>
+            {
+                // Filters are only for peers that have shown they're on our chain
+                if !self.peers.get(&peer).is_some_and(|peer_info| peer_info.handshake.is_complete()) {
+                    self.misbehaving(peer, Misbehavior::MalformedMessage);
+                    return Ok(());
+                }
+                if let Some(response) = self.protocol_handler.handle_message(request, &peer.to_string()).await? {
+                    let _ = self.swarm.behaviour_mut().handshake.send_response(channel, response);
+                }
+            }
+            
+            request_response::Event::Message { peer, message: request_response::Message::Request { request, channel, .. } } => {
+                match check_version(&self.identity, &request.message_type) {
+                    Ok(version) => {
+                        let _ = self.swarm.behaviour_mut().handshake.send_response(channel, Message::new(MessageType::VerAck));
+                        self.accept_version(peer, version);
+                        self.complete_handshake(peer).await?;
+                    }
+                    Err(mismatch) => {
+                        // They check our version too, so a lost Reject still ends it
+                        log::warn!("🚫 Disconnecting {}: {}", peer, mismatch);
+                        let _ = self.swarm.behaviour_mut().handshake.send_response(channel, Message::new(mismatch.to_reject()));
+                        let _ = self.swarm.disconnect_peer_id(peer);
+                    }
+                }
+            }
+            
+            request_response::Event::Message { peer, message: request_response::Message::Response { response, .. } } => {
+                match response.message_type {
+                    MessageType::VerAck => {
+                        if let Some(peer_info) = self.peers.get_mut(&peer) {
+                            peer_info.handshake.verack_received = true;
+                        }
+                        self.complete_handshake(peer).await?;
+                    }
+                    MessageType::Reject { reason, .. } => {
+                        log::warn!("🚫 Disconnecting {}: it rejected our version: {}", peer, reason);
+                        let _ = self.swarm.disconnect_peer_id(peer);
+                    }
+                    _ => {
+                        self.misbehaving(peer, Misbehavior::MalformedMessage);
+                        let _ = self.swarm.disconnect_peer_id(peer);
+                    }
+                }
+            }
+            
+            request_response::Event::OutboundFailure { peer, error, .. } => {
+                log::warn!("🚫 Disconnecting {}: handshake failed: {}", peer, error);
+                let _ = self.swarm.disconnect_peer_id(peer);
+            }
+            
+            request_response::Event::InboundFailure { peer, error, .. } => {
+                log::debug!("Handshake from {} failed: {}", peer, error);
+            }
+            
+            request_response::Event::ResponseSent { .. } => {}
+        }
+        Ok(())
+    }
+    
+    /// Settle which features a peer whose version checked out and we both use
+    fn accept_version(&mut self, peer_id: PeerId, version: PeerVersion) {
+        let Some(peer_info) = self.peers.get_mut(&peer_id) else {
+            return;
         };
         
-        // In a full implementation, this would send a sync request message
-        // For now, just log the sync request
-        log::debug!("Our height: {}, requesting sync from peer", our_height);
+        peer_info.protocol_version = version.version;
+        peer_info.features = self.protocol_handler.local_features().negotiate(version.features);
+        peer_info.height = peer_info.height.max(version.start_height);
+        peer_info.handshake.version_received = true;
+        if version.version < PROTOCOL_VERSION {
+            log::info!("⬇️ Peer {} speaks protocol {}, using {:?} only",
+                peer_id, version.version, peer_info.features.iter().collect::<Vec<_>>());
+        }
+    }
+    
+    /// Start syncing and relaying with a peer once both halves of its handshake are in
+    async fn complete_handshake(&mut self, peer_id: PeerId) -> Result<()> {
+        let Some(peer_info) = self.peers.get(&peer_id).filter(|peer| peer.handshake.is_complete()) else {
+            return Ok(());
+        };
+        log::info!("✅ Handshake with {} complete: protocol {}, height {}", peer_id, peer_info.protocol_version, peer_info.height);
+        let headers_sync = peer_info.supports(Feature::HeadersSync);
+        let tx_relay = peer_info.supports(Feature::TxRelay);
         
+        if headers_sync {
+            self.request_blockchain_sync(peer_id).await?;
+        }
+        if tx_relay && !self.pending_transactions.is_empty() {
+            self.flush_pending_transactions()?;
+        }
         Ok(())
     }
     
+    async fn request_blockchain_sync(&mut self, peer_id: PeerId) -> Result<()> {
+        log::info!("🔄 Requesting blockchain sync from peer: {}", peer_id);
+        
+        // A new range-serving peer takes a share of any download in progress
+        self.drive_block_download()
+    }
+    
     fn update_stats(&mut self) {
         self.stats.uptime_seconds = self.start_time.elapsed().as_secs();
         self.stats.connected_peers = self.peers.values().cloned().collect();
         self.stats.peer_count = self.peers.len();
+        self.stats.diversity = self.diversity.stats();
+        
+        if self.stats.duplicate_blocks + self.stats.duplicate_transactions > 0 {
+            log::debug!("♻️ Suppressed {} duplicate blocks and {} duplicate transactions ({} / {} hashes cached)",
+                self.stats.duplicate_blocks,
+                self.stats.duplicate_transactions,
+                self.seen_blocks.len(),
+                self.seen_transactions.len());
+        }
     }
     
     async fn maintenance_tasks(&mut self) -> Result<()> {
@@ -560,8 +1284,27 @@ impl P2PNode {
         for peer_id in stale_peers {
             log::warn!("🗑️ Removing stale peer: {}", peer_id);
             self.peers.remove(&peer_id);
+            self.diversity.remove(&peer_id.to_string());
         }
         
+        // Peers that never finish the handshake only hold a slot
+        let unverified: Vec<PeerId> = self.peers
+            .iter()
+            .filter(|(_, info)| !info.handshake.is_complete() && now.saturating_sub(info.connected_at) > HANDSHAKE_TIMEOUT.as_secs())
+            .map(|(peer_id, _)| *peer_id)
+            .collect();
+        
+        for peer_id in unverified {
+            log::warn!("⌛ Disconnecting {}: no handshake within {}s", peer_id, HANDSHAKE_TIMEOUT.as_secs());
+            let _ = self.swarm.disconnect_peer_id(peer_id);
+        }
+        
+        if let Err(e) = self.bans.expire() {
+            log::warn!("⚠️ Failed to clear expired bans: {}", e);
+        }
+        self.drop_banned_peers();
+        self.check_partition();
+        
         // Bootstrap if we have too few peers
         if self.peers.len() < 3 {
             log::info!("🔄 Bootstrapping - too few peers connected");
@@ -572,6 +1315,73 @@ impl P2PNode {
         Ok(())
     }
     
+    fn check_partition(&mut self) {
+        let Some(partition) = &mut self.partition else {
+            return;
+        };
+        let now = Instant::now();
+        let (height, tip) = {
+            let blockchain = self.blockchain.read().unwrap();
+            (blockchain.height, blockchain.tip)
+        };
+        partition.observe_tip(height, now);
+        
+        match partition.check(now) {
+            PartitionCheck::Suspected { since_tip, since_peer_block } => {
+                let best_peer_height = partition.best_peer_height();
+                let (probed, rotated) = self.probe_for_network();
+                let alert = PartitionAlert {
+                    height,
+                    tip: tip.to_hex(),
+                    secs_since_tip_change: since_tip.as_secs(),
+                    secs_since_peer_block: since_peer_block.map(|since| since.as_secs()),
+                    best_peer_height,
+                    peer_count: self.peers.len(),
+                    outbound_peers: self.peers.values().filter(|peer| peer.is_outbound).count(),
+                    peer_groups: self.diversity.stats().distinct_groups,
+                    probed,
+                    rotated,
+                };
+                log::warn!("🏝️ Possible network partition: no new blocks for {}s, {} peer(s) in {} group(s); probed {} and rotated {} peer(s)",
+                    alert.secs_since_tip_change, alert.peer_count, alert.peer_groups, alert.probed.len(), alert.rotated.len());
+                let _ = self.partition_alerts.send(alert);
+            }
+            PartitionCheck::Recovered => log::info!("🌉 Blocks are arriving again, partition over"),
+            PartitionCheck::Connected | PartitionCheck::Probing => {}
+        }
+    }
+    
+    /// Dial seed and trusted peers and drop the oldest outbound peers, which
+    /// may all sit on the wrong side of the split
+    fn probe_for_network(&mut self) -> (Vec<String>, Vec<String>) {
+        let mut probed = Vec::new();
+        for address in &self.probe_peers {
+            match address.parse::<libp2p::Multiaddr>() {
+                Ok(multiaddr) => match self.swarm.dial(multiaddr) {
+                    Ok(()) => probed.push(address.clone()),
+                    Err(e) => log::debug!("Failed to probe {}: {}", address, e),
+                },
+                Err(e) => log::warn!("⚠️ Invalid probe peer address {}: {}", address, e),
+            }
+        }
+        
+        let mut outbound: Vec<(PeerId, u64)> = self.peers.iter()
+            .filter(|(_, peer)| peer.is_outbound)
+            .map(|(peer_id, peer)| (*peer_id, peer.connected_at))
+            .collect();
+        outbound.sort_by_key(|(_, connected_at)| *connected_at);
+        
+        let mut rotated = Vec::new();
+        for (peer_id, _) in outbound.into_iter().take(PARTITION_ROTATE_PEERS) {
+            let _ = self.swarm.disconnect_peer_id(peer_id);
+            rotated.push(peer_id.to_string());
+        }
+        
+        // Look for peers beyond the ones we know
+        let _ = self.swarm.behaviour_mut().kademlia.bootstrap();
+        (probed, rotated)
+    }
+    
     pub fn get_stats(&self) -> NetworkStats {
         self.stats.clone()
     }
@@ -583,6 +1393,27 @@ impl P2PNode {
     pub fn get_peers(&self) -> Vec<PeerInfo> {
         self.peers.values().cloned().collect()
     }
+    
+    /// Addresses of the peers we dialed, worth dialing again after a restart;
+    /// inbound peers connect from ephemeral ports, so they are left out
+    pub fn address_book(&self) -> Vec<String> {
+        self.peers.iter()
+            .filter(|(_, peer)| peer.is_outbound)
+            .map(|(peer_id, peer)| format!("{}/p2p/{}", peer.address, peer_id))
+            .collect()
+    }
+    
+    /// Dial the addresses a previous run saved from `address_book`
+    pub fn dial_saved_peers(&mut self, addresses: &[String]) {
+        for address in addresses {
+            match address.parse::<libp2p::Multiaddr>() {
+                Ok(multiaddr) => if let Err(e) = self.swarm.dial(multiaddr) {
+                    log::debug!("Failed to dial saved peer {}: {}", address, e);
+                },
+                Err(e) => log::warn!("⚠️ Invalid saved peer address {}: {}", address, e),
+            }
+        }
+    }
 }
 
 #[cfg(test)]
@@ -605,6 +1436,97 @@ mod tests {
         
         assert_eq!(node.get_peer_count(), 0);
         
+        Ok(())
+    }    
+    #[tokio::test]
+    async fn test_gossip_frames_and_legacy_peers() -> Result<()> {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Arc::new(Database::new(temp_dir.path().join("test.db"))?);
+        let blockchain = Arc::new(RwLock::new(Blockchain::new(db)?));
+        let (mut node, _receiver, _sender) = P2PNode::new(blockchain, 0, vec![]).await?;
+        
+        let mut connect = |protocol_version: u32| {
+            let peer_id = PeerId::random();
+            node.peers.insert(peer_id, PeerInfo {
+                peer_id: peer_id.to_string(),
+                address: "/ip4/10.0.0.1/tcp/8333".to_string(),
+                connected_at: 0,
+                last_seen: 0,
+                version: "test".to_string(),
+                height: 0,
+                ping_ms: None,
+                is_outbound: true,
+                protocol_version,
+                features: FeatureSet::empty(),
+                handshake: Handshake { version_received: true, verack_received: true },
+            });
+            peer_id
+        };
+        let (legacy, current) = (connect(2), connect(PROTOCOL_VERSION));
+        
+        let payload = bincode::serialize(&Transaction::new()).unwrap();
+        let frame = node.frame(PayloadKind::Transaction, &payload)?;
+        assert_eq!(node.unframe(current, PayloadKind::Transaction, &frame), Some(&payload[..]));
+        assert_eq!(node.unframe(legacy, PayloadKind::Transaction, &frame), Some(&payload[..]));
+        
+        // Bare payloads are only taken from peers that predate framing
+        assert_eq!(node.unframe(legacy, PayloadKind::Transaction, &payload), Some(&payload[..]));
+        assert_eq!(node.unframe(current, PayloadKind::Transaction, &payload), None);
+        assert_eq!(node.ban_scores.score(&current), Misbehavior::MalformedMessage.score());
+        
+        let mut corrupt = frame.clone();
+        *corrupt.last_mut().unwrap() ^= 1;
+        assert_eq!(node.unframe(legacy, PayloadKind::Transaction, &corrupt), None);
+        assert_eq!(node.ban_scores.score(&legacy), Misbehavior::BadChecksum.score());
+        
+        Ok(())
+    }
+    
+    #[tokio::test]
+    async fn test_gossip_waits_for_the_handshake() -> Result<()> {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Arc::new(Database::new(temp_dir.path().join("test.db"))?);
+        let blockchain = Arc::new(RwLock::new(Blockchain::new(db)?));
+        let (mut node, _receiver, _sender) = P2PNode::new(blockchain, 0, vec![]).await?;
+        
+        let peer_id = PeerId::random();
+        node.peers.insert(peer_id, PeerInfo {
+            peer_id: peer_id.to_string(),
+            address: "/ip4/10.0.0.1/tcp/8333".to_string(),
+            connected_at: 0,
+            last_seen: 0,
+            version: "test".to_string(),
+            height: 0,
+            ping_ms: None,
+            is_outbound: true,
+            protocol_version: 0,
+            features: FeatureSet::empty(),
+            handshake: Handshake::default(),
+        });
+        let topic = gossipsub::IdentTopic::new(node.topics.transactions.clone()).hash();
+        let gossip = || gossipsub::Message {
+            source: Some(peer_id),
+            data: b"not a frame".to_vec(),
+            sequence_number: None,
+            topic: topic.clone(),
+        };
+        
+        // Nothing from the peer counts until it has shaken hands, not even garbage
+        let acceptance = node.handle_gossip_message(peer_id, gossip()).await?;
+        assert!(matches!(acceptance, gossipsub::MessageAcceptance::Ignore));
+        assert_eq!(node.ban_scores.score(&peer_id), 0);
+        
+        let version = node.protocol_handler.create_version_message("/ip4/10.0.0.1/tcp/8333")?;
+        node.accept_version(peer_id, check_version(&node.identity, &version.message_type).unwrap());
+        assert!(!node.peers[&peer_id].supports(Feature::TxRelay));
+        node.peers.get_mut(&peer_id).unwrap().handshake.verack_received = true;
+        assert!(node.peers[&peer_id].supports(Feature::TxRelay));
+        assert_eq!(node.peers[&peer_id].protocol_version, PROTOCOL_VERSION);
+        
+        let acceptance = node.handle_gossip_message(peer_id, gossip()).await?;
+        assert!(matches!(acceptance, gossipsub::MessageAcceptance::Reject));
+        assert_eq!(node.ban_scores.score(&peer_id), Misbehavior::MalformedMessage.score());
+        
         Ok(())
     }
 }
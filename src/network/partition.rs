@@ -0,0 +1,128 @@
+//! Network partition detection
+//!
+//! A node cut off from the rest of the network keeps running happily on a
+//! stale tip. The monitor watches two signals, our own tip and the highest
+//! block peers have relayed, and reports a possible partition once neither
+//! has moved for a whole window. The P2P node then probes seed and trusted
+//! peers and rotates its outbound connections, at most once per window.
+
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+
+/// What a `possible_partition` alert carries, to tell a quiet network from a cut-off node
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PartitionAlert {
+    pub height: u64,
+    pub tip: String,
+    pub secs_since_tip_change: u64,
+    pub secs_since_peer_block: Option<u64>, // None if no peer has relayed a block since startup
+    pub best_peer_height: u64,
+    pub peer_count: usize,
+    pub outbound_peers: usize,
+    pub peer_groups: usize,
+    pub probed: Vec<String>,
+    pub rotated: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PartitionCheck {
+    Connected,
+    /// Neither signal moved for the window; probe for the rest of the network
+    Suspected { since_tip: Duration, since_peer_block: Option<Duration> },
+    /// Still quiet, but probed within the last window
+    Probing,
+    /// Blocks are flowing again after a suspected partition
+    Recovered,
+}
+
+#[derive(Debug)]
+pub struct PartitionMonitor {
+    window: Duration,
+    tip_height: u64,
+    tip_changed_at: Instant,
+    best_peer_height: u64,
+    peer_advanced_at: Option<Instant>,
+    last_probe: Option<Instant>,
+}
+
+impl PartitionMonitor {
+    pub fn new(window: Duration, now: Instant) -> Self {
+        Self {
+            window,
+            tip_height: 0,
+            tip_changed_at: now,
+            best_peer_height: 0,
+            peer_advanced_at: None,
+            last_probe: None,
+        }
+    }
+
+    pub fn best_peer_height(&self) -> u64 {
+        self.best_peer_height
+    }
+
+    pub fn observe_tip(&mut self, height: u64, now: Instant) {
+        if height != self.tip_height {
+            self.tip_height = height;
+            self.tip_changed_at = now;
+        }
+    }
+
+    /// A peer relayed a block; only a new best height counts as the network moving
+    pub fn observe_peer_block(&mut self, height: u64, now: Instant) {
+        if height > self.best_peer_height || self.peer_advanced_at.is_none() {
+            self.best_peer_height = self.best_peer_height.max(height);
+            self.peer_advanced_at = Some(now);
+        }
+    }
+
+    pub fn check(&mut self, now: Instant) -> PartitionCheck {
+        let since_tip = now.duration_since(self.tip_changed_at);
+        let since_peer_block = self.peer_advanced_at.map(|at| now.duration_since(at));
+        let quiet = since_tip >= self.window && since_peer_block.is_none_or(|since| since >= self.window);
+
+        if !quiet {
+            return match self.last_probe.take() {
+                Some(_) => PartitionCheck::Recovered,
+                None => PartitionCheck::Connected,
+            };
+        }
+        if self.last_probe.is_some_and(|at| now.duration_since(at) < self.window) {
+            return PartitionCheck::Probing;
+        }
+
+        self.last_probe = Some(now);
+        PartitionCheck::Suspected { since_tip, since_peer_block }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_partition_suspected_after_quiet_window() {
+        let start = Instant::now();
+        let window = Duration::from_secs(600);
+        let at = |secs| start + Duration::from_secs(secs);
+        let mut monitor = PartitionMonitor::new(window, start);
+
+        monitor.observe_tip(5, at(100));
+        monitor.observe_peer_block(5, at(200));
+        assert_eq!(monitor.check(at(650)), PartitionCheck::Connected);
+
+        // Peers relaying old blocks doesn't count as the network moving
+        monitor.observe_peer_block(4, at(700));
+        assert_eq!(
+            monitor.check(at(800)),
+            PartitionCheck::Suspected { since_tip: Duration::from_secs(700), since_peer_block: Some(Duration::from_secs(600)) },
+        );
+        assert_eq!(monitor.check(at(900)), PartitionCheck::Probing);
+        assert!(matches!(monitor.check(at(1_400)), PartitionCheck::Suspected { .. }));
+
+        monitor.observe_peer_block(6, at(1_500));
+        assert_eq!(monitor.check(at(1_510)), PartitionCheck::Recovered);
+        assert_eq!(monitor.check(at(1_520)), PartitionCheck::Connected);
+        assert_eq!(monitor.best_peer_height(), 6);
+    }
+}
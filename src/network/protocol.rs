@@ -1,10 +1,171 @@
-use crate::core::{Block, Transaction, Blockchain};
+use crate::consensus::params::MIN_PQC_WITNESS_PERCENT;
+use crate::consensus::validation::MAX_BLOCK_WEIGHT;
+use crate::core::{Block, BlockFilter, Transaction, Blockchain};
 use crate::crypto::hash::Hashable;
 use crate::crypto::hash::Hash256;
+use crate::network::bans::Misbehavior;
+use crate::network::features::{Feature, FeatureSet, PROTOCOL_VERSION};
+use crate::network::handshake::{check_version, ChainIdentity};
+use crate::network::messaging::MAX_ENVELOPE_SIZE;
 use crate::{QtcError, Result};
 use serde::{Deserialize, Serialize};
 use std::sync::{Arc, RwLock};
 
+/// Version of the gossip frame below
+pub const WIRE_VERSION: u8 = 1;
+
+/// Network magic, wire version, payload kind, payload length (little endian)
+/// and the first four bytes of the payload's SHA256d, ahead of the payload
+pub const FRAME_HEADER_SIZE: usize = 4 + 1 + 1 + 4 + 4;
+
+/// Largest block taken from a peer. The weight limit counts PQC signature bytes
+/// at a discount, so a valid block full of them serializes to up to this much
+pub const MAX_BLOCK_PAYLOAD: usize = MAX_BLOCK_WEIGHT * 100 / MIN_PQC_WITNESS_PERCENT as usize;
+/// Largest transaction relayed; a bigger one only reaches a block through its miner
+pub const MAX_TRANSACTION_PAYLOAD: usize = 400_000;
+/// A `Blocks` answer fills its budget, or holds one block that doesn't fit in it
+pub const MAX_SYNC_PAYLOAD: usize = MAX_BLOCK_PAYLOAD + 1024;
+
+/// Request/response protocol peers download block ranges on, once their handshake is done
+pub const BLOCKS_PROTOCOL: &str = "/qtc/blocks/1";
+/// Request/response protocol light clients ask for block filters on, once their handshake is done
+pub const FILTERS_PROTOCOL: &str = "/qtc/cfilters/1";
+/// Most filters one `GetCFilters` is answered with
+pub const MAX_CFILTERS_PER_REQUEST: u64 = 1_000;
+/// `Reject` code for a `GetCFilters` this node can't answer
+pub const REJECT_NO_FILTERS: u8 = 0x13;
+
+/// What a frame carries; each gossip topic takes one kind, and block range answers are sync frames
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PayloadKind {
+    Block = 1,
+    Transaction = 2,
+    Sync = 3,
+    Envelope = 4,
+}
+
+impl PayloadKind {
+    fn from_byte(byte: u8) -> Option<Self> {
+        match byte {
+            1 => Some(PayloadKind::Block),
+            2 => Some(PayloadKind::Transaction),
+            3 => Some(PayloadKind::Sync),
+            4 => Some(PayloadKind::Envelope),
+            _ => None,
+        }
+    }
+
+    pub fn max_size(self) -> usize {
+        match self {
+            PayloadKind::Block => MAX_BLOCK_PAYLOAD,
+            PayloadKind::Transaction => MAX_TRANSACTION_PAYLOAD,
+            PayloadKind::Sync => MAX_SYNC_PAYLOAD,
+            PayloadKind::Envelope => MAX_ENVELOPE_SIZE,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            PayloadKind::Block => "block",
+            PayloadKind::Transaction => "transaction",
+            PayloadKind::Sync => "sync",
+            PayloadKind::Envelope => "envelope",
+        }
+    }
+}
+
+/// Why a gossip frame was refused
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FrameError {
+    Malformed(String),
+    Oversized { kind: PayloadKind, size: usize },
+    BadChecksum,
+}
+
+impl FrameError {
+    /// What the peer that sent the frame is scored for
+    pub fn misbehavior(&self) -> Misbehavior {
+        match self {
+            FrameError::Malformed(_) => Misbehavior::MalformedMessage,
+            FrameError::Oversized { .. } => Misbehavior::OversizedPayload,
+            FrameError::BadChecksum => Misbehavior::BadChecksum,
+        }
+    }
+}
+
+impl std::fmt::Display for FrameError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            FrameError::Malformed(reason) => write!(f, "malformed frame: {}", reason),
+            FrameError::Oversized { kind, size } => {
+                write!(f, "{} of {} bytes, at most {} allowed", kind.name(), size, kind.max_size())
+            }
+            FrameError::BadChecksum => write!(f, "payload does not match its checksum"),
+        }
+    }
+}
+
+fn checksum(payload: &[u8]) -> [u8; 4] {
+    let hash = Hash256::double_hash(payload);
+    let mut checksum = [0; 4];
+    checksum.copy_from_slice(&hash.as_bytes()[..4]);
+    checksum
+}
+
+/// Wrap `payload` in a frame for the network with `magic`
+pub fn encode_frame(magic: [u8; 4], kind: PayloadKind, payload: &[u8]) -> Result<Vec<u8>> {
+    if payload.len() > kind.max_size() {
+        return Err(QtcError::Network(format!(
+            "Can't relay a {} of {} bytes, at most {} allowed", kind.name(), payload.len(), kind.max_size()
+        )));
+    }
+    let mut frame = Vec::with_capacity(FRAME_HEADER_SIZE + payload.len());
+    frame.extend_from_slice(&magic);
+    frame.push(WIRE_VERSION);
+    frame.push(kind as u8);
+    frame.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    frame.extend_from_slice(&checksum(payload));
+    frame.extend_from_slice(payload);
+    Ok(frame)
+}
+
+/// Whether `data` starts like a frame for the network with `magic`, rather
+/// than the bare bincode nodes before protocol 3 gossip
+pub fn is_framed(magic: [u8; 4], data: &[u8]) -> bool {
+    data.starts_with(&magic)
+}
+
+/// The payload of a `kind` frame, checked against the size limit for its kind
+/// before anything else is read and against its checksum after
+pub fn decode_frame(magic: [u8; 4], kind: PayloadKind, data: &[u8]) -> std::result::Result<&[u8], FrameError> {
+    if data.len() < FRAME_HEADER_SIZE {
+        return Err(FrameError::Malformed(format!("{} bytes is shorter than a frame header", data.len())));
+    }
+    if data[..4] != magic {
+        return Err(FrameError::Malformed(format!("magic {} is not this network's", hex::encode(&data[..4]))));
+    }
+    if data[4] != WIRE_VERSION {
+        return Err(FrameError::Malformed(format!("unknown wire version {}", data[4])));
+    }
+    match PayloadKind::from_byte(data[5]) {
+        Some(framed) if framed == kind => {}
+        _ => return Err(FrameError::Malformed(format!("payload kind {} on the {} topic", data[5], kind.name()))),
+    }
+
+    let length = u32::from_le_bytes([data[6], data[7], data[8], data[9]]) as usize;
+    if length > kind.max_size() {
+        return Err(FrameError::Oversized { kind, size: length });
+    }
+    let payload = &data[FRAME_HEADER_SIZE..];
+    if payload.len() != length {
+        return Err(FrameError::Malformed(format!("{} payload bytes where the header says {}", payload.len(), length)));
+    }
+    if checksum(payload) != data[10..FRAME_HEADER_SIZE] {
+        return Err(FrameError::BadChecksum);
+    }
+    Ok(payload)
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Message {
     pub message_type: MessageType,
@@ -26,6 +187,11 @@ pub enum MessageType {
         count: u32,
     },
     BlockHeaders(Vec<crate::core::BlockHeader>),
+    GetCFilters {
+        start_height: u64,
+        stop_hash: Hash256, // a main chain block at most MAX_CFILTERS_PER_REQUEST past start_height
+    },
+    CFilter(Vec<BlockFilter>), // answers GetCFilters, in height order
     
     // Transaction messages
     Transaction(Transaction),
@@ -42,6 +208,8 @@ pub enum MessageType {
         nonce: u64,
         user_agent: String,
         start_height: u64,
+        magic: [u8; 4],  // network the sender is on
+        genesis: Hash256, // and its chain's first block
     },
     VerAck,
     
@@ -89,6 +257,7 @@ pub struct ProtocolHandler {
     blockchain: Arc<RwLock<Blockchain>>,
     version: u32,
     user_agent: String,
+    proxied: bool, // connected through a proxy, so our own address must not be sent
 }
 
 impl Message {
@@ -116,6 +285,8 @@ impl Message {
             MessageType::Block(_) => "block",
             MessageType::GetBlockHeaders { .. } => "getheaders",
             MessageType::BlockHeaders(_) => "headers",
+            MessageType::GetCFilters { .. } => "getcfilters",
+            MessageType::CFilter(_) => "cfilter",
             MessageType::Transaction(_) => "tx",
             MessageType::GetMempool => "getmempool",
             MessageType::Mempool(_) => "mempool",
@@ -139,9 +310,15 @@ impl ProtocolHandler {
             blockchain,
             version: 1,
             user_agent: "QTC/1.0.0".to_string(),
+            proxied: false,
         }
     }
     
+    /// Leave our address out of `Version` messages, e.g. when connected through Tor
+    pub fn set_proxied(&mut self, proxied: bool) {
+        self.proxied = proxied;
+    }
+    
     pub async fn handle_message(&self, message: Message, peer_id: &str) -> Result<Option<Message>> {
         log::debug!("📨 Handling {} message from peer {}", message.message_type_name(), peer_id);
         
@@ -158,6 +335,10 @@ impl ProtocolHandler {
                 self.handle_get_block_headers(start_height, count).await
             }
             
+            MessageType::GetCFilters { start_height, stop_hash } => {
+                self.handle_get_cfilters(start_height, &stop_hash)
+            }
+            
             MessageType::Transaction(tx) => {
                 self.handle_transaction(tx).await
             }
@@ -166,8 +347,8 @@ impl ProtocolHandler {
                 self.handle_get_mempool().await
             }
             
-            MessageType::Version { version, start_height, .. } => {
-                self.handle_version(version, start_height, peer_id).await
+            MessageType::Version { .. } => {
+                self.handle_version(&message.message_type, peer_id)
             }
             
             MessageType::Ping(nonce) => {
@@ -267,8 +448,8 @@ impl ProtocolHandler {
         
         for i in 0..max_headers {
             let height = start_height + i as u64;
-            if let Ok(Some(block)) = blockchain.get_block_by_height(height) {
-                headers.push(block.header);
+            if let Ok(Some(header)) = blockchain.get_block_header_by_height(height) {
+                headers.push(header);
             } else {
                 break;
             }
@@ -281,6 +462,27 @@ impl ProtocolHandler {
         }
     }
     
+    /// Serve a light client the filters it asked for, or say why not
+    fn handle_get_cfilters(&self, start_height: u64, stop_hash: &Hash256) -> Result<Option<Message>> {
+        log::debug!("🔎 Handling getcfilters request: start={}, stop={}", start_height, stop_hash);
+        
+        let blockchain = self.blockchain.read().unwrap();
+        let reason = if !blockchain.has_block_filters() {
+            "block filters are not built on this node".to_string()
+        } else {
+            match blockchain.get_block_filters(start_height, stop_hash, MAX_CFILTERS_PER_REQUEST)? {
+                Some(filters) => return Ok(Some(Message::new(MessageType::CFilter(filters)))),
+                None => format!("no filters from height {} to block {}", start_height, stop_hash),
+            }
+        };
+        
+        Ok(Some(Message::new(MessageType::Reject {
+            message: "getcfilters".to_string(),
+            code: REJECT_NO_FILTERS,
+            reason,
+        })))
+    }
+    
     async fn handle_transaction(&self, tx: Transaction) -> Result<Option<Message>> {
         log::debug!("💰 Received transaction: {}", hex::encode(tx.hash().as_bytes()));
         
@@ -313,22 +515,35 @@ impl ProtocolHandler {
         Ok(Some(Message::new(MessageType::Mempool(vec![]))))
     }
     
-    async fn handle_version(
-        &self,
-        peer_version: u32,
-        peer_height: u64,
-        peer_id: &str,
-    ) -> Result<Option<Message>> {
-        log::info!("🤝 Received version from peer {}: version={}, height={}", 
-                  peer_id, peer_version, peer_height);
-        
-        // Check version compatibility
-        if peer_version < self.version {
-            log::warn!("⚠️ Peer {} has older version {}", peer_id, peer_version);
+    /// Acknowledge a peer's version, or reject it when it follows another chain
+    fn handle_version(&self, version: &MessageType, peer_id: &str) -> Result<Option<Message>> {
+        match check_version(&self.chain_identity()?, version) {
+            Ok(peer) => {
+                log::info!("🤝 Received version from peer {}: version={}, height={}",
+                          peer_id, peer.version, peer.start_height);
+                log::debug!("Features shared with {}: {:?}", peer_id, self.local_features().negotiate(peer.features).iter().collect::<Vec<_>>());
+                Ok(Some(Message::new(MessageType::VerAck)))
+            }
+            Err(mismatch) => {
+                log::warn!("🚫 Rejecting version from peer {}: {}", peer_id, mismatch);
+                Ok(Some(Message::new(mismatch.to_reject())))
+            }
         }
-        
-        // Send version acknowledgment
-        Ok(Some(Message::new(MessageType::VerAck)))
+    }
+    
+    /// What our `Version` offers: block filters only when the chain builds them
+    pub fn local_features(&self) -> FeatureSet {
+        let mut features = FeatureSet::local();
+        if self.blockchain.read().unwrap().has_block_filters() {
+            features.insert(Feature::BlockFilters);
+        }
+        features
+    }
+    
+    /// The network and genesis block our `Version` announces
+    pub fn chain_identity(&self) -> Result<ChainIdentity> {
+        let blockchain = self.blockchain.read().unwrap();
+        Ok(ChainIdentity { magic: blockchain.chain_params().magic, genesis: blockchain.genesis_hash()? })
     }
     
     async fn handle_get_addr(&self) -> Result<Option<Message>> {
@@ -380,7 +595,10 @@ impl ProtocolHandler {
         for item in items {
             match item.item_type {
                 InventoryType::Block => {
-                    if let Ok(Some(block)) = blockchain.get_block(&item.hash) {
+                    if blockchain.is_block_pruned(&item.hash).unwrap_or(false) {
+                        log::debug!("✂️ Not serving pruned block {}", item.hash);
+                        not_found.push(item);
+                    } else if let Ok(Some(block)) = blockchain.get_block(&item.hash) {
                         // Send the block (would normally send to requesting peer directly)
                         log::debug!("📦 Sending block {}", item.hash);
                         // Return the block message
@@ -406,19 +624,24 @@ impl ProtocolHandler {
         }
     }
     
-    pub fn create_version_message(&self, peer_addr: &str) -> Message {
+    pub fn create_version_message(&self, peer_addr: &str) -> Result<Message> {
+        let identity = self.chain_identity()?;
+        let services = self.local_features().bits();
         let blockchain = self.blockchain.read().unwrap();
         
-        Message::new(MessageType::Version {
-            version: self.version,
-            services: 0, // No special services
+        Ok(Message::new(MessageType::Version {
+            version: PROTOCOL_VERSION,
+            services,
             timestamp: chrono::Utc::now().timestamp() as u64,
             addr_recv: peer_addr.to_string(),
-            addr_from: "127.0.0.1:8333".to_string(), // Our address
+            // Our address; unroutable when proxied, as the real one would unmask us
+            addr_from: if self.proxied { "0.0.0.0:0" } else { "127.0.0.1:8333" }.to_string(),
             nonce: rand::random(),
             user_agent: self.user_agent.clone(),
             start_height: blockchain.height,
-        })
+            magic: identity.magic,
+            genesis: identity.genesis,
+        }))
     }
     
     pub fn create_ping_message() -> Message {
@@ -533,6 +756,70 @@ mod tests {
         Ok(())
     }
     
+    #[test]
+    fn test_frames_check_magic_size_and_checksum() -> Result<()> {
+        let magic = *b"QTCT";
+        let frame = encode_frame(magic, PayloadKind::Transaction, b"payload")?;
+        assert!(is_framed(magic, &frame));
+        assert_eq!(decode_frame(magic, PayloadKind::Transaction, &frame), Ok(&b"payload"[..]));
+
+        // Another network, another topic's kind, or a cut-off frame
+        assert!(matches!(decode_frame(*b"QTCR", PayloadKind::Transaction, &frame), Err(FrameError::Malformed(_))));
+        assert!(matches!(decode_frame(magic, PayloadKind::Block, &frame), Err(FrameError::Malformed(_))));
+        assert!(matches!(decode_frame(magic, PayloadKind::Transaction, &frame[..frame.len() - 1]), Err(FrameError::Malformed(_))));
+
+        let mut corrupt = frame.clone();
+        *corrupt.last_mut().unwrap() ^= 1;
+        let err = decode_frame(magic, PayloadKind::Transaction, &corrupt).unwrap_err();
+        assert_eq!(err.misbehavior(), Misbehavior::BadChecksum);
+
+        // A declared length over the limit is refused without reading the payload
+        let mut oversized = frame[..FRAME_HEADER_SIZE].to_vec();
+        oversized[6..10].copy_from_slice(&(MAX_ENVELOPE_SIZE as u32 + 1).to_le_bytes());
+        oversized[5] = PayloadKind::Envelope as u8;
+        let err = decode_frame(magic, PayloadKind::Envelope, &oversized).unwrap_err();
+        assert_eq!(err, FrameError::Oversized { kind: PayloadKind::Envelope, size: MAX_ENVELOPE_SIZE + 1 });
+        assert_eq!(err.misbehavior(), Misbehavior::OversizedPayload);
+        assert!(encode_frame(magic, PayloadKind::Envelope, &vec![0; MAX_ENVELOPE_SIZE + 1]).is_err());
+
+        // Bare bincode from an older node isn't mistaken for a frame
+        let legacy = bincode::serialize(&Transaction::new()).unwrap();
+        assert!(!is_framed(magic, &legacy));
+        Ok(())
+    }
+    
+    #[test]
+    fn test_heaviest_pqc_block_fits_a_frame() -> Result<()> {
+        use crate::core::transaction::{pqc_signature_script, OutPoint};
+        use crate::crypto::pqc::PqcSignature;
+
+        // Signature scripts the weight limit only counts a quarter of
+        let script = pqc_signature_script(
+            &PqcSignature { signature: vec![7; 60_000], public_key: vec![8; 1_952] },
+            &[9; 1_184],
+        );
+        let spend = |n: u32| {
+            let mut tx = Transaction::new();
+            tx.add_input(OutPoint::new(Hash256::hash(&n.to_le_bytes()), 0), script.clone());
+            tx.add_output(1_000, "qtc1frame");
+            tx
+        };
+        let mut block = Block::new(Hash256::zero(), vec![Transaction::new_coinbase("qtc1frame".to_string(), 1_000, "frame".to_string())], 0x207f_ffff, 1);
+        for n in 0.. {
+            block.transactions.push(spend(n));
+            if block.weight(MIN_PQC_WITNESS_PERCENT) > MAX_BLOCK_WEIGHT {
+                block.transactions.pop();
+                break;
+            }
+        }
+
+        let payload = bincode::serialize(&block).unwrap();
+        assert!(payload.len() > 3 * MAX_BLOCK_WEIGHT);
+        let frame = encode_frame(*b"QTCT", PayloadKind::Block, &payload)?;
+        assert_eq!(decode_frame(*b"QTCT", PayloadKind::Block, &frame), Ok(&payload[..]));
+        Ok(())
+    }
+    
     #[test]
     fn test_inventory_item_creation() {
         let hash = Hash256::hash(b"test");
@@ -0,0 +1,275 @@
+//! Outbound P2P connections through a SOCKS5 proxy, usually Tor
+//!
+//! With `network.proxy` set, the proxy makes every outbound connection and
+//! resolves hostnames and `.onion` addresses itself, so neither a DNS lookup
+//! nor a direct connection gives away where the node runs. Inbound connections
+//! are only accepted on loopback, where a Tor onion service forwards them, and
+//! listen addresses are never translated into the public one peers observe.
+//! `network.onion_only` also refuses every peer that isn't an onion service.
+
+use crate::{QtcError, Result};
+use libp2p::core::multiaddr::Protocol;
+use libp2p::core::transport::{ListenerId, TransportError, TransportEvent};
+use libp2p::futures::future::BoxFuture;
+use libp2p::{tcp, Multiaddr, Transport};
+use std::io;
+use std::net::{IpAddr, SocketAddr, ToSocketAddrs};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+const SOCKS_VERSION: u8 = 5;
+const NO_AUTHENTICATION: u8 = 0;
+const CONNECT: u8 = 1;
+const ATYP_IPV4: u8 = 1;
+const ATYP_DOMAIN: u8 = 3;
+const ATYP_IPV6: u8 = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProxyConfig {
+    pub address: SocketAddr,
+    pub onion_only: bool,
+}
+
+impl ProxyConfig {
+    /// `network.proxy` as `host:port`, e.g. Tor's `127.0.0.1:9050`
+    pub fn parse(address: &str, onion_only: bool) -> Result<Self> {
+        let address = address.to_socket_addrs().ok()
+            .and_then(|mut addresses| addresses.next())
+            .ok_or_else(|| QtcError::InvalidInput(format!(
+                "network.proxy must be a reachable host:port, e.g. 127.0.0.1:9050 (got {})", address
+            )))?;
+        Ok(Self { address, onion_only })
+    }
+}
+
+/// Where a multiaddr points, as a SOCKS5 destination
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProxyTarget {
+    Ip(SocketAddr),
+    Domain(String, u16), // resolved by the proxy
+}
+
+impl ProxyTarget {
+    /// `/ip4`, `/ip6`, `/dns*` with `/tcp`, or `/onion3`, optionally ending in `/p2p`
+    pub fn from_multiaddr(address: &Multiaddr) -> Option<Self> {
+        let mut protocols = address.iter();
+        let target = match (protocols.next()?, protocols.next()) {
+            (Protocol::Ip4(ip), Some(Protocol::Tcp(port))) => Self::Ip(SocketAddr::new(IpAddr::V4(ip), port)),
+            (Protocol::Ip6(ip), Some(Protocol::Tcp(port))) => Self::Ip(SocketAddr::new(IpAddr::V6(ip), port)),
+            (Protocol::Dns(host) | Protocol::Dns4(host) | Protocol::Dns6(host), Some(Protocol::Tcp(port))) => {
+                Self::Domain(host.to_string(), port)
+            }
+            (Protocol::Onion3(onion), next) => {
+                let target = Self::Domain(format!("{}.onion", base32_lower(onion.hash())), onion.port());
+                return match next {
+                    None | Some(Protocol::P2p(_)) if protocols.next().is_none() => Some(target),
+                    _ => None,
+                };
+            }
+            _ => return None,
+        };
+        match protocols.next() {
+            None | Some(Protocol::P2p(_)) if protocols.next().is_none() => Some(target),
+            _ => None,
+        }
+    }
+
+    pub fn is_onion(&self) -> bool {
+        matches!(self, Self::Domain(host, _) if host.ends_with(".onion"))
+    }
+}
+
+/// RFC 4648 base32 without padding, as used in onion addresses
+fn base32_lower(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 32] = b"abcdefghijklmnopqrstuvwxyz234567";
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(5) * 8);
+    let (mut buffer, mut bits) = (0u32, 0);
+    for byte in bytes {
+        buffer = (buffer << 8) | *byte as u32;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            encoded.push(ALPHABET[((buffer >> bits) & 31) as usize] as char);
+        }
+    }
+    if bits > 0 {
+        encoded.push(ALPHABET[((buffer << (5 - bits)) & 31) as usize] as char);
+    }
+    encoded
+}
+
+fn socks_error(message: impl Into<String>) -> io::Error {
+    io::Error::other(message.into())
+}
+
+/// Open a connection to `target` through the SOCKS5 proxy at `proxy`
+pub async fn socks5_connect(proxy: SocketAddr, target: &ProxyTarget) -> io::Result<TcpStream> {
+    let mut stream = TcpStream::connect(proxy).await?;
+    stream.set_nodelay(true)?;
+
+    stream.write_all(&[SOCKS_VERSION, 1, NO_AUTHENTICATION]).await?;
+    let mut choice = [0u8; 2];
+    stream.read_exact(&mut choice).await?;
+    if choice != [SOCKS_VERSION, NO_AUTHENTICATION] {
+        return Err(socks_error("SOCKS5 proxy requires an unsupported authentication method"));
+    }
+
+    let mut request = vec![SOCKS_VERSION, CONNECT, 0];
+    let port = match target {
+        ProxyTarget::Ip(SocketAddr::V4(address)) => {
+            request.push(ATYP_IPV4);
+            request.extend_from_slice(&address.ip().octets());
+            address.port()
+        }
+        ProxyTarget::Ip(SocketAddr::V6(address)) => {
+            request.push(ATYP_IPV6);
+            request.extend_from_slice(&address.ip().octets());
+            address.port()
+        }
+        ProxyTarget::Domain(host, port) => {
+            let length = u8::try_from(host.len()).map_err(|_| socks_error("Host name too long for SOCKS5"))?;
+            request.extend_from_slice(&[ATYP_DOMAIN, length]);
+            request.extend_from_slice(host.as_bytes());
+            *port
+        }
+    };
+    request.extend_from_slice(&port.to_be_bytes());
+    stream.write_all(&request).await?;
+
+    let mut reply = [0u8; 4];
+    stream.read_exact(&mut reply).await?;
+    if reply[0] != SOCKS_VERSION {
+        return Err(socks_error("Not a SOCKS5 proxy"));
+    }
+    if reply[1] != 0 {
+        return Err(socks_error(format!("SOCKS5 proxy refused the connection (code {})", reply[1])));
+    }
+    // The address the proxy bound for us, which we have no use for
+    let bound = match reply[3] {
+        ATYP_IPV4 => 4,
+        ATYP_IPV6 => 16,
+        ATYP_DOMAIN => stream.read_u8().await? as usize,
+        atyp => return Err(socks_error(format!("Unknown SOCKS5 address type {}", atyp))),
+    };
+    let mut skipped = vec![0u8; bound + 2];
+    stream.read_exact(&mut skipped).await?;
+    Ok(stream)
+}
+
+/// TCP transport that dials through a SOCKS5 proxy and only listens on loopback
+pub struct Socks5Transport {
+    config: ProxyConfig,
+    listener: tcp::tokio::Transport,
+}
+
+impl Socks5Transport {
+    pub fn new(config: ProxyConfig) -> Self {
+        Self {
+            config,
+            listener: tcp::tokio::Transport::new(tcp::Config::default().nodelay(true)),
+        }
+    }
+}
+
+impl Transport for Socks5Transport {
+    type Output = tcp::tokio::TcpStream;
+    type Error = io::Error;
+    type ListenerUpgrade = <tcp::tokio::Transport as Transport>::ListenerUpgrade;
+    type Dial = BoxFuture<'static, io::Result<Self::Output>>;
+
+    fn listen_on(&mut self, id: ListenerId, address: Multiaddr) -> std::result::Result<(), TransportError<Self::Error>> {
+        let loopback = match address.iter().next() {
+            Some(Protocol::Ip4(ip)) => ip.is_loopback(),
+            Some(Protocol::Ip6(ip)) => ip.is_loopback(),
+            _ => false,
+        };
+        if !loopback {
+            return Err(TransportError::MultiaddrNotSupported(address));
+        }
+        self.listener.listen_on(id, address)
+    }
+
+    fn remove_listener(&mut self, id: ListenerId) -> bool {
+        self.listener.remove_listener(id)
+    }
+
+    fn dial(&mut self, address: Multiaddr) -> std::result::Result<Self::Dial, TransportError<Self::Error>> {
+        let Some(target) = ProxyTarget::from_multiaddr(&address) else {
+            return Err(TransportError::MultiaddrNotSupported(address));
+        };
+        if self.config.onion_only && !target.is_onion() {
+            return Err(TransportError::MultiaddrNotSupported(address));
+        }
+        let proxy = self.config.address;
+        Ok(Box::pin(async move {
+            socks5_connect(proxy, &target).await.map(tcp::tokio::TcpStream)
+        }))
+    }
+
+    fn dial_as_listener(&mut self, address: Multiaddr) -> std::result::Result<Self::Dial, TransportError<Self::Error>> {
+        self.dial(address)
+    }
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<TransportEvent<Self::ListenerUpgrade, Self::Error>> {
+        Pin::new(&mut self.listener).poll(cx)
+    }
+
+    fn address_translation(&self, _listen: &Multiaddr, _observed: &Multiaddr) -> Option<Multiaddr> {
+        None // what peers see is the proxy's exit, not an address of ours
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    #[test]
+    fn test_proxy_targets() {
+        let parse = |address: &str| ProxyTarget::from_multiaddr(&address.parse().unwrap());
+        assert_eq!(parse("/ip4/10.0.0.1/tcp/8333"), Some(ProxyTarget::Ip("10.0.0.1:8333".parse().unwrap())));
+        assert_eq!(parse("/dns/seed.example/tcp/8333"), Some(ProxyTarget::Domain("seed.example".to_string(), 8333)));
+        assert_eq!(parse("/ip4/10.0.0.1/udp/8333"), None);
+
+        let onion = "vww6ybal4bd7szmgncyruucpgfkqahzddi37ktceo3ah7ngmcopnpyyd";
+        let target = parse(&format!("/onion3/{}:8333", onion)).unwrap();
+        assert_eq!(target, ProxyTarget::Domain(format!("{}.onion", onion), 8333));
+        assert!(target.is_onion());
+    }
+
+    #[tokio::test]
+    async fn test_socks5_connect_sends_domain_to_proxy() {
+        let proxy = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = proxy.local_addr().unwrap();
+        let server = tokio::spawn(async move {
+            let (mut client, _) = proxy.accept().await.unwrap();
+            let mut greeting = [0u8; 3];
+            client.read_exact(&mut greeting).await.unwrap();
+            client.write_all(&[SOCKS_VERSION, NO_AUTHENTICATION]).await.unwrap();
+
+            let mut header = [0u8; 5];
+            client.read_exact(&mut header).await.unwrap();
+            let mut host = vec![0u8; header[4] as usize + 2];
+            client.read_exact(&mut host).await.unwrap();
+            client.write_all(&[SOCKS_VERSION, 0, 0, ATYP_IPV4, 0, 0, 0, 0, 0, 0]).await.unwrap();
+            (header, host)
+        });
+
+        let target = ProxyTarget::Domain("peer.onion".to_string(), 8333);
+        socks5_connect(address, &target).await.unwrap();
+        let (header, host) = server.await.unwrap();
+        assert_eq!(header, [SOCKS_VERSION, CONNECT, 0, ATYP_DOMAIN, 10]);
+        assert_eq!(&host[..10], b"peer.onion");
+        assert_eq!(u16::from_be_bytes([host[10], host[11]]), 8333);
+    }
+
+    #[test]
+    fn test_onion_only_refuses_clearnet_peers() {
+        let config = ProxyConfig { address: "127.0.0.1:9050".parse().unwrap(), onion_only: true };
+        let mut transport = Socks5Transport::new(config);
+        assert!(transport.dial("/ip4/10.0.0.1/tcp/8333".parse().unwrap()).is_err());
+        assert!(transport.listen_on(ListenerId::next(), "/ip4/0.0.0.0/tcp/0".parse().unwrap()).is_err());
+    }
+}
@@ -0,0 +1,102 @@
+//! Transactions waiting for a peer that takes them
+//!
+//! A wallet send may reach the P2P node before any peer that relays
+//! transactions has connected, most of all on the short-lived node a CLI
+//! send brings up. Those transactions wait here until one joins, and
+//! `P2PCommand::WaitForRelay` callers are answered once the queue is empty.
+
+use crate::core::Transaction;
+use crate::crypto::hash::Hashable;
+use tokio::sync::oneshot;
+
+/// Queued transactions and the callers waiting for them to go out
+#[derive(Debug)]
+pub struct RelayQueue {
+    capacity: usize,
+    transactions: Vec<Transaction>,
+    waiters: Vec<oneshot::Sender<()>>,
+}
+
+impl RelayQueue {
+    pub fn new(capacity: usize) -> Self {
+        Self { capacity: capacity.max(1), transactions: Vec::new(), waiters: Vec::new() }
+    }
+
+    /// Queue `tx`, returning false if the queue is full and it was dropped
+    pub fn push(&mut self, tx: Transaction) -> bool {
+        if self.transactions.len() >= self.capacity {
+            log::warn!("⚠️ Relay queue full, dropping transaction {}", tx.hash());
+            return false;
+        }
+        log::info!("⏳ No peers for transaction {} yet, queued for relay", tx.hash());
+        self.transactions.push(tx);
+        true
+    }
+
+    /// Everything queued, to publish; hand back what still found no peer with `settle`
+    pub fn take(&mut self) -> Vec<Transaction> {
+        std::mem::take(&mut self.transactions)
+    }
+
+    /// Re-queue transactions a flush couldn't relay, answering the waiters if none are left
+    pub fn settle(&mut self, unsent: Vec<Transaction>) {
+        self.transactions.extend(unsent);
+        if self.transactions.is_empty() {
+            for waiter in self.waiters.drain(..) {
+                let _ = waiter.send(());
+            }
+        }
+    }
+
+    /// Answer `done` once nothing is queued, right away if that is now
+    pub fn wait(&mut self, done: oneshot::Sender<()>) {
+        if self.transactions.is_empty() {
+            let _ = done.send(());
+        } else {
+            self.waiters.push(done);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.transactions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.transactions.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tx(n: u8) -> Transaction {
+        Transaction::new_coinbase("qtc1relay".to_string(), 1_000, format!("tx {}", n))
+    }
+
+    #[test]
+    fn test_relay_queue_answers_waiters_once_drained() {
+        let mut queue = RelayQueue::new(2);
+        let (done, mut idle) = oneshot::channel();
+        queue.wait(done);
+        assert!(idle.try_recv().is_ok()); // nothing queued
+
+        assert!(queue.push(tx(1)) && queue.push(tx(2)));
+        assert!(!queue.push(tx(3)));
+        let (done, mut relayed) = oneshot::channel();
+        queue.wait(done);
+
+        // A flush that leaves one behind keeps the caller waiting
+        let mut queued = queue.take();
+        let unsent = queued.split_off(1);
+        queue.settle(unsent);
+        assert_eq!(queue.len(), 1);
+        assert!(relayed.try_recv().is_err());
+
+        let queued = queue.take();
+        assert_eq!(queued[0].hash(), tx(2).hash());
+        queue.settle(Vec::new());
+        assert!(queue.is_empty());
+        assert!(relayed.try_recv().is_ok());
+    }
+}
@@ -0,0 +1,72 @@
+//! Rolling caches of gossip payloads we have already seen
+
+use crate::crypto::hash::Hash256;
+use std::collections::{HashSet, VecDeque};
+
+/// Bounded set of recently seen inventory hashes; the oldest entries roll off first
+#[derive(Debug)]
+pub struct SeenCache {
+    capacity: usize,
+    entries: HashSet<Hash256>,
+    order: VecDeque<Hash256>,
+}
+
+impl SeenCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            entries: HashSet::with_capacity(capacity),
+            order: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    /// Record `hash`, returning false if it was already in the cache
+    pub fn insert(&mut self, hash: Hash256) -> bool {
+        if !self.entries.insert(hash) {
+            return false;
+        }
+
+        self.order.push_back(hash);
+        if self.order.len() > self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.entries.remove(&oldest);
+            }
+        }
+        true
+    }
+
+    pub fn contains(&self, hash: &Hash256) -> bool {
+        self.entries.contains(hash)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seen_cache_rolls_over() {
+        let mut cache = SeenCache::new(2);
+        let a = Hash256::hash(b"a");
+        let b = Hash256::hash(b"b");
+        let c = Hash256::hash(b"c");
+
+        assert!(cache.insert(a));
+        assert!(!cache.insert(a));
+        assert!(cache.insert(b));
+        assert!(cache.insert(c));
+
+        // `a` was the oldest entry and has been evicted
+        assert_eq!(cache.len(), 2);
+        assert!(!cache.contains(&a));
+        assert!(cache.contains(&b) && cache.contains(&c));
+    }
+}
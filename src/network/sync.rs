@@ -0,0 +1,446 @@
+//! Parallel block download
+//!
+//! Catching up one block at a time from a single peer is slow, and stops
+//! whenever that peer does. The downloader splits the missing heights into
+//! chunks and spreads them over every peer that serves block ranges. A chunk
+//! whose request times out, or that its peer can't serve, goes to another
+//! peer. Blocks are handed out strictly in height order, so the chain only
+//! ever sees blocks that extend its tip.
+//!
+//! Chunks are asked for over the `BLOCKS_PROTOCOL` request/response protocol,
+//! one request per chunk. An answer that would outgrow its budget carries the
+//! first blocks of the chunk only, and the rest is asked for again.
+
+use crate::core::Block;
+use crate::network::protocol::MAX_BLOCK_PAYLOAD;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::hash::Hash;
+use std::time::{Duration, Instant};
+
+pub const DEFAULT_CHUNK_SIZE: u64 = 16;
+pub const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(20);
+/// Chunks one peer may have outstanding at a time
+pub const DEFAULT_MAX_CHUNKS_PER_PEER: usize = 2;
+/// How far past the next block the chain needs chunks are requested, bounding
+/// what a stalled chunk leaves buffered
+pub const DEFAULT_WINDOW_CHUNKS: u64 = 32;
+/// Budget for the blocks of one answer, which holds one block at least
+pub const MAX_SYNC_MESSAGE_BYTES: usize = MAX_BLOCK_PAYLOAD;
+
+/// Ask a peer for blocks `start..=end`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlocksRequest {
+    pub start: u64,
+    pub end: u64,
+}
+
+/// A peer's answer to a `BlocksRequest`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum BlocksResponse {
+    /// A sync frame of the first blocks asked for, in height order. Sent as one
+    /// byte string, which CBOR would otherwise encode byte by byte.
+    Blocks(#[serde(with = "byte_string")] Vec<u8>),
+    /// The peer has no blocks from `from` on
+    NotFound { from: u64 },
+}
+
+mod byte_string {
+    use serde::de::{Deserializer, Error, SeqAccess, Visitor};
+    use serde::Serializer;
+    use std::fmt;
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_bytes(bytes)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        struct Bytes;
+
+        impl<'de> Visitor<'de> for Bytes {
+            type Value = Vec<u8>;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a byte string")
+            }
+
+            fn visit_bytes<E: Error>(self, bytes: &[u8]) -> Result<Vec<u8>, E> {
+                Ok(bytes.to_vec())
+            }
+
+            fn visit_byte_buf<E: Error>(self, bytes: Vec<u8>) -> Result<Vec<u8>, E> {
+                Ok(bytes)
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Vec<u8>, A::Error> {
+                let mut bytes = Vec::new();
+                while let Some(byte) = seq.next_element()? {
+                    bytes.push(byte);
+                }
+                Ok(bytes)
+            }
+        }
+
+        deserializer.deserialize_byte_buf(Bytes)
+    }
+}
+
+/// A chunk to ask `peer` for
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChunkRequest<P> {
+    pub peer: P,
+    pub start: u64,
+    pub end: u64, // inclusive
+}
+
+#[derive(Debug)]
+struct InFlight<P> {
+    peer: P,
+    end: u64,
+    sent_at: Instant,
+    failed: Vec<P>, // peers that timed out on or couldn't serve this chunk
+}
+
+#[derive(Debug)]
+struct Retry<P> {
+    start: u64,
+    end: u64,
+    failed: Vec<P>,
+}
+
+/// Work scheduler for catching up on blocks `next_height..=target_height`
+#[derive(Debug)]
+pub struct BlockDownloader<P> {
+    chunk_size: u64,
+    timeout: Duration,
+    max_per_peer: usize,
+    window: u64,
+    next_height: u64,   // next block to hand to the chain
+    target_height: u64, // highest block wanted
+    queued_until: u64,  // heights below this are in flight, received or waiting for a retry
+    retries: VecDeque<Retry<P>>,
+    in_flight: BTreeMap<u64, InFlight<P>>, // by first height
+    received: BTreeMap<u64, Block>,
+}
+
+impl<P: Clone + Eq + Hash> BlockDownloader<P> {
+    /// A downloader with nothing to do until `set_target` raises the target past `next_height`
+    pub fn new(next_height: u64) -> Self {
+        Self {
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            timeout: DEFAULT_REQUEST_TIMEOUT,
+            max_per_peer: DEFAULT_MAX_CHUNKS_PER_PEER,
+            window: DEFAULT_CHUNK_SIZE * DEFAULT_WINDOW_CHUNKS,
+            next_height,
+            target_height: next_height.saturating_sub(1),
+            queued_until: next_height,
+            retries: VecDeque::new(),
+            in_flight: BTreeMap::new(),
+            received: BTreeMap::new(),
+        }
+    }
+
+    pub fn with_chunk_size(mut self, chunk_size: u64) -> Self {
+        self.chunk_size = chunk_size.max(1);
+        self.window = self.chunk_size * DEFAULT_WINDOW_CHUNKS;
+        self
+    }
+
+    pub fn with_request_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn with_max_chunks_per_peer(mut self, max_per_peer: usize) -> Self {
+        self.max_per_peer = max_per_peer.max(1);
+        self
+    }
+
+    pub fn next_height(&self) -> u64 {
+        self.next_height
+    }
+
+    pub fn target_height(&self) -> u64 {
+        self.target_height
+    }
+
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.len()
+    }
+
+    pub fn is_idle(&self) -> bool {
+        self.next_height > self.target_height
+    }
+
+    /// Download up to `height`; a lower target than the current one is ignored
+    pub fn set_target(&mut self, height: u64) {
+        self.target_height = self.target_height.max(height);
+    }
+
+    /// Follow the chain's tip, which also moves with gossiped and mined blocks.
+    /// A tip behind what was handed out means the chain rejected those blocks,
+    /// so they are fetched again.
+    pub fn sync_tip(&mut self, tip_height: u64) {
+        let next = tip_height + 1;
+        if next > self.next_height {
+            self.next_height = next;
+            self.received = self.received.split_off(&next);
+            self.in_flight.retain(|_, request| request.end >= next);
+            self.retries.retain(|retry| retry.end >= next);
+            self.queued_until = self.queued_until.max(next);
+        } else if next < self.next_height {
+            self.retries.push_front(Retry { start: next, end: self.next_height - 1, failed: Vec::new() });
+            self.next_height = next;
+        }
+    }
+
+    /// Hand chunks to `peers`, least busy first, retries before new heights.
+    /// A retry avoids the peers that failed it while any other peer has room.
+    pub fn schedule(&mut self, peers: &[P], now: Instant) -> Vec<ChunkRequest<P>> {
+        let mut load: HashMap<&P, usize> = peers.iter().map(|peer| (peer, 0)).collect();
+        for request in self.in_flight.values() {
+            if let Some(count) = load.get_mut(&request.peer) {
+                *count += 1;
+            }
+        }
+
+        let mut requests = Vec::new();
+        loop {
+            let (start, end, failed) = if let Some(retry) = self.retries.front() {
+                (retry.start, retry.end, retry.failed.clone())
+            } else if self.queued_until <= self.target_height && self.queued_until < self.next_height + self.window {
+                let end = (self.queued_until + self.chunk_size - 1).min(self.target_height);
+                (self.queued_until, end, Vec::new())
+            } else {
+                break;
+            };
+
+            let available = |avoid: &[P]| load.iter()
+                .filter(|(peer, count)| **count < self.max_per_peer && !avoid.contains(peer))
+                .min_by_key(|(_, count)| **count)
+                .map(|(peer, _)| (*peer).clone());
+            let Some(peer) = available(&failed).or_else(|| available(&[])) else {
+                break;
+            };
+
+            if self.retries.front().is_some_and(|retry| retry.start == start) {
+                self.retries.pop_front();
+            } else {
+                self.queued_until = end + 1;
+            }
+            *load.get_mut(&peer).expect("chosen from the load table") += 1;
+            self.in_flight.insert(start, InFlight { peer: peer.clone(), end, sent_at: now, failed });
+            requests.push(ChunkRequest { peer, start, end });
+        }
+        requests
+    }
+
+    /// Take a block `peer` was asked for, returning false for anything unrequested
+    pub fn on_block(&mut self, peer: &P, block: Block) -> bool {
+        let height = block.header.height;
+        let Some((&start, request)) = self.in_flight.range(..=height).next_back() else {
+            return false;
+        };
+        if height > request.end || &request.peer != peer || height < self.next_height {
+            return false;
+        }
+
+        let end = request.end;
+        self.received.insert(height, block);
+        if (start..=end).all(|height| height < self.next_height || self.received.contains_key(&height)) {
+            self.in_flight.remove(&start);
+        }
+        true
+    }
+
+    /// `peer` answered its request for the chunk at `start`; whatever the answer
+    /// left out goes back in the queue, without holding it against the peer
+    pub fn on_answered(&mut self, peer: &P, start: u64) {
+        if self.in_flight.get(&start).is_some_and(|request| &request.peer == peer) {
+            self.requeue(start, false);
+        }
+    }
+
+    /// `peer` can't serve heights from `from` on; the rest of its chunk goes to another peer
+    pub fn on_not_found(&mut self, peer: &P, from: u64) {
+        let Some((&start, request)) = self.in_flight.range(..=from).next_back() else {
+            return;
+        };
+        if from <= request.end && &request.peer == peer {
+            self.requeue(start, true);
+        }
+    }
+
+    /// Requests older than the timeout, which go back in the queue for another peer
+    pub fn check_timeouts(&mut self, now: Instant) -> Vec<ChunkRequest<P>> {
+        let stalled: Vec<ChunkRequest<P>> = self.in_flight.iter()
+            .filter(|(_, request)| now.duration_since(request.sent_at) >= self.timeout)
+            .map(|(&start, request)| ChunkRequest { peer: request.peer.clone(), start, end: request.end })
+            .collect();
+        for request in &stalled {
+            self.requeue(request.start, true);
+        }
+        stalled
+    }
+
+    /// Give a disconnected peer's chunks to the others
+    pub fn remove_peer(&mut self, peer: &P) {
+        let starts: Vec<u64> = self.in_flight.iter()
+            .filter(|(_, request)| &request.peer == peer)
+            .map(|(&start, _)| start)
+            .collect();
+        for start in starts {
+            self.requeue(start, false);
+        }
+    }
+
+    /// Blocks that extend the chain from `next_height`, in order
+    pub fn ready_blocks(&mut self) -> Vec<Block> {
+        let mut ready = Vec::new();
+        while let Some(block) = self.received.remove(&self.next_height) {
+            ready.push(block);
+            self.next_height += 1;
+        }
+        ready
+    }
+
+    /// Put the heights of an in-flight chunk that haven't arrived back in the queue
+    fn requeue(&mut self, start: u64, blame_peer: bool) {
+        let Some(mut request) = self.in_flight.remove(&start) else {
+            return;
+        };
+        let first_missing = (start.max(self.next_height)..=request.end)
+            .find(|height| !self.received.contains_key(height));
+        if let Some(first_missing) = first_missing {
+            if blame_peer {
+                request.failed.push(request.peer);
+            }
+            self.retries.push_back(Retry { start: first_missing, end: request.end, failed: request.failed });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::hash::Hash256;
+
+    fn block(height: u64) -> Block {
+        Block::new(Hash256::zero(), vec![], 1, height)
+    }
+
+    fn deliver(downloader: &mut BlockDownloader<&'static str>, request: &ChunkRequest<&'static str>) {
+        for height in request.start..=request.end {
+            assert!(downloader.on_block(&request.peer, block(height)));
+        }
+    }
+
+    #[test]
+    fn test_chunks_spread_over_peers_and_arrive_in_order() {
+        let now = Instant::now();
+        let mut downloader = BlockDownloader::new(1).with_chunk_size(4).with_max_chunks_per_peer(1);
+        downloader.set_target(10);
+
+        let requests = downloader.schedule(&["a", "b", "c"], now);
+        let ranges: Vec<(u64, u64)> = requests.iter().map(|r| (r.start, r.end)).collect();
+        assert_eq!(ranges, vec![(1, 4), (5, 8), (9, 10)]);
+        let mut peers: Vec<&str> = requests.iter().map(|r| r.peer).collect();
+        peers.sort();
+        assert_eq!(peers, vec!["a", "b", "c"]);
+
+        // Later chunks wait for the first one
+        deliver(&mut downloader, &requests[2]);
+        deliver(&mut downloader, &requests[1]);
+        assert!(downloader.ready_blocks().is_empty());
+        assert!(!downloader.on_block(&"z", block(2)));
+
+        deliver(&mut downloader, &requests[0]);
+        let heights: Vec<u64> = downloader.ready_blocks().iter().map(|b| b.header.height).collect();
+        assert_eq!(heights, (1..=10).collect::<Vec<_>>());
+        assert!(downloader.is_idle());
+        assert_eq!(downloader.in_flight(), 0);
+    }
+
+    #[test]
+    fn test_stalled_and_unserved_chunks_move_to_other_peers() {
+        let now = Instant::now();
+        let mut downloader = BlockDownloader::new(1)
+            .with_chunk_size(4)
+            .with_max_chunks_per_peer(1)
+            .with_request_timeout(Duration::from_secs(5));
+        downloader.set_target(8);
+
+        let requests = downloader.schedule(&["a", "b"], now);
+        assert_eq!(requests.len(), 2);
+        let slow = requests.iter().find(|r| r.start == 1).unwrap().clone();
+        let other = if slow.peer == "a" { "b" } else { "a" };
+
+        // Half a chunk arrives, then the peer goes quiet; only the rest is asked for again
+        assert!(downloader.on_block(&slow.peer, block(1)));
+        assert!(downloader.on_block(&slow.peer, block(2)));
+        assert!(downloader.check_timeouts(now + Duration::from_secs(1)).is_empty());
+        let stalled = downloader.check_timeouts(now + Duration::from_secs(5));
+        assert_eq!(stalled.len(), 2);
+
+        let later = now + Duration::from_secs(6);
+        let retried = downloader.schedule(&["a", "b"], later);
+        let first = retried.iter().find(|r| r.start == 3).unwrap();
+        assert_eq!((first.peer, first.end), (other, 4));
+        assert!(!downloader.on_block(&slow.peer, block(3)));
+
+        // A peer without the blocks says so and the chunk moves on at once
+        let second = retried.iter().find(|r| r.start == 5).unwrap().clone();
+        downloader.on_not_found(&second.peer, 5);
+        downloader.remove_peer(&first.peer);
+        let reassigned = downloader.schedule(&["a", "b"], later);
+        assert_eq!(reassigned.len(), 2);
+        for request in &reassigned {
+            deliver(&mut downloader, request);
+        }
+        assert_eq!(downloader.ready_blocks().len(), 8);
+    }
+
+    #[test]
+    fn test_partial_answers_requeue_the_rest() {
+        let now = Instant::now();
+        let mut downloader = BlockDownloader::new(1).with_chunk_size(4).with_max_chunks_per_peer(1);
+        downloader.set_target(4);
+        let request = downloader.schedule(&["a", "b"], now).remove(0);
+
+        // The answer ran out of room after two blocks; the peer isn't blamed for it
+        assert!(downloader.on_block(&request.peer, block(1)));
+        assert!(downloader.on_block(&request.peer, block(2)));
+        downloader.on_answered(&"z", request.start);
+        assert_eq!(downloader.in_flight(), 1);
+        downloader.on_answered(&request.peer, request.start);
+        assert_eq!(downloader.in_flight(), 0);
+
+        let rest = downloader.schedule(&[request.peer], now);
+        assert_eq!((rest[0].peer, rest[0].start, rest[0].end), (request.peer, 3, 4));
+        deliver(&mut downloader, &rest[0]);
+        downloader.on_answered(&request.peer, rest[0].start);
+        assert_eq!(downloader.ready_blocks().len(), 4);
+        assert!(downloader.is_idle());
+    }
+
+    #[test]
+    fn test_rejected_blocks_are_fetched_again() {
+        let now = Instant::now();
+        let mut downloader = BlockDownloader::new(1).with_chunk_size(4);
+        downloader.set_target(4);
+        let request = downloader.schedule(&["a"], now).remove(0);
+        deliver(&mut downloader, &request);
+        assert_eq!(downloader.ready_blocks().len(), 4);
+
+        // The chain only took the first two
+        downloader.sync_tip(2);
+        assert_eq!(downloader.next_height(), 3);
+        let retry = downloader.schedule(&["a"], now);
+        assert_eq!((retry[0].start, retry[0].end), (3, 4));
+
+        // Gossip moving the tip past the target leaves nothing to do
+        downloader.sync_tip(9);
+        assert!(downloader.is_idle());
+        assert_eq!(downloader.in_flight(), 0);
+    }
+}
@@ -0,0 +1,122 @@
+//! Client diversity statistics built from libp2p identify data
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+
+/// Disconnected peers still count as "recently seen" for this long
+const RECENT_PEER_SECS: u64 = 24 * 60 * 60;
+
+#[derive(Debug, Clone)]
+struct PeerVersion {
+    agent_version: String,
+    protocol_version: String,
+    protocols: Vec<String>,
+    connected: bool,
+    last_seen: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VersionCount {
+    pub connected: usize,
+    pub recent: usize, // connected plus recently disconnected
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct VersionSummary {
+    pub connected_peers: usize,
+    pub recent_peers: usize,
+    pub agents: BTreeMap<String, VersionCount>,
+    pub protocol_versions: BTreeMap<String, VersionCount>,
+    pub services: BTreeMap<String, VersionCount>, // libp2p protocols each peer advertises
+}
+
+/// What connected and recently seen peers told us about themselves, shared with the API
+#[derive(Debug, Default)]
+pub struct PeerVersions {
+    peers: Mutex<HashMap<String, PeerVersion>>,
+}
+
+impl PeerVersions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record_identify(&self, peer_id: &str, agent_version: &str, protocol_version: &str, protocols: Vec<String>) {
+        let mut peers = self.lock();
+        peers.insert(peer_id.to_string(), PeerVersion {
+            agent_version: agent_version.to_string(),
+            protocol_version: protocol_version.to_string(),
+            protocols,
+            connected: true,
+            last_seen: chrono::Utc::now().timestamp() as u64,
+        });
+    }
+
+    pub fn mark_disconnected(&self, peer_id: &str) {
+        if let Some(peer) = self.lock().get_mut(peer_id) {
+            peer.connected = false;
+            peer.last_seen = chrono::Utc::now().timestamp() as u64;
+        }
+    }
+
+    pub fn summary(&self) -> VersionSummary {
+        let now = chrono::Utc::now().timestamp() as u64;
+        let mut peers = self.lock();
+        peers.retain(|_, peer| peer.connected || now.saturating_sub(peer.last_seen) < RECENT_PEER_SECS);
+
+        let mut summary = VersionSummary::default();
+        for peer in peers.values() {
+            summary.recent_peers += 1;
+            if peer.connected {
+                summary.connected_peers += 1;
+            }
+
+            Self::count(&mut summary.agents, &peer.agent_version, peer.connected);
+            Self::count(&mut summary.protocol_versions, &peer.protocol_version, peer.connected);
+            for protocol in &peer.protocols {
+                Self::count(&mut summary.services, protocol, peer.connected);
+            }
+        }
+
+        summary
+    }
+
+    fn count(counts: &mut BTreeMap<String, VersionCount>, key: &str, connected: bool) {
+        let entry = counts.entry(key.to_string()).or_default();
+        entry.recent += 1;
+        if connected {
+            entry.connected += 1;
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, PeerVersion>> {
+        match self.peers.lock() {
+            Ok(peers) => peers,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_version_summary() {
+        let versions = PeerVersions::new();
+        let gossip = vec!["/meshsub/1.1.0".to_string(), "/ipfs/ping/1.0.0".to_string()];
+        versions.record_identify("a", "qtcd/0.1.0", "/qtc/1.0.0", gossip.clone());
+        versions.record_identify("b", "qtcd/0.1.0", "/qtc/1.0.0", gossip);
+        versions.record_identify("c", "qtcd/0.2.0", "/qtc/1.0.0", vec!["/ipfs/ping/1.0.0".to_string()]);
+        versions.mark_disconnected("b");
+
+        let summary = versions.summary();
+        assert_eq!(summary.connected_peers, 2);
+        assert_eq!(summary.recent_peers, 3);
+        assert_eq!(summary.agents["qtcd/0.1.0"], VersionCount { connected: 1, recent: 2 });
+        assert_eq!(summary.protocol_versions["/qtc/1.0.0"].recent, 3);
+        assert_eq!(summary.services["/ipfs/ping/1.0.0"], VersionCount { connected: 2, recent: 3 });
+        assert_eq!(summary.services["/meshsub/1.1.0"].connected, 1);
+    }
+}
@@ -0,0 +1,285 @@
+//! Native desktop notifications for people running a node on their own machine
+//!
+//! Opt-in with `qtcd start --notify-desktop`. Shows payments to local wallets,
+//! blocks this node mined and the end of initial sync through the platform's
+//! own notifier: `notify-send` on Linux and the BSDs, `osascript` on macOS.
+//! Payments found while catching up are history, so they stay quiet until the
+//! node is synced.
+
+use crate::core::transaction::OutPoint;
+use crate::core::{Block, Blockchain, ChainEvent, Transaction, UtxoEntry, UtxoSet};
+use crate::crypto::hash::Hashable;
+use crate::mining::miner::BlockMinedEvent;
+use crate::node::ShutdownSignal;
+use crate::storage::Database;
+use crate::{QtcError, Result};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, RwLock};
+use tokio::process::Command;
+use tokio::sync::broadcast;
+
+/// A tip this recent means we have caught up with the network
+const SYNCED_TIP_AGE_SECS: u64 = 60 * 60;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DesktopEvent {
+    PaymentReceived { wallet: String, amount: u64, txid: String },
+    BlockMined { height: u64, hash: String, reward: u64 },
+    SyncComplete { height: u64 },
+}
+
+impl DesktopEvent {
+    pub fn title(&self) -> String {
+        match self {
+            Self::PaymentReceived { wallet, .. } => format!("Payment received in '{}'", wallet),
+            Self::BlockMined { height, .. } => format!("Block {} mined", height),
+            Self::SyncComplete { .. } => "QTC node synced".to_string(),
+        }
+    }
+
+    pub fn body(&self) -> String {
+        match self {
+            Self::PaymentReceived { amount, txid, .. } => {
+                format!("{:.8} QTC confirmed in {}", *amount as f64 / 100_000_000.0, &txid[..16])
+            }
+            Self::BlockMined { hash, reward, .. } => {
+                format!("Reward {:.8} QTC, block {}", *reward as f64 / 100_000_000.0, &hash[..16.min(hash.len())])
+            }
+            Self::SyncComplete { height } => format!("Caught up with the network at height {}", height),
+        }
+    }
+}
+
+pub struct DesktopNotifier {
+    db: Arc<Database>,
+    blockchain: Arc<RwLock<Blockchain>>,
+    owners: HashMap<String, String>, // address as recorded in UTXO entries -> wallet
+    syncing: bool,
+    unavailable: bool, // the notifier command is missing; keep logging only
+}
+
+impl DesktopNotifier {
+    pub fn new(db: Arc<Database>, blockchain: Arc<RwLock<Blockchain>>) -> Self {
+        Self { db, blockchain, owners: HashMap::new(), syncing: false, unavailable: false }
+    }
+
+    pub async fn run(
+        mut self,
+        mut events: broadcast::Receiver<ChainEvent>,
+        mut mined: Option<broadcast::Receiver<BlockMinedEvent>>,
+        mut shutdown: ShutdownSignal,
+    ) -> Result<()> {
+        self.syncing = self.is_behind()?;
+        if !self.syncing {
+            self.load_owners()?;
+        }
+        log::info!("🔔 Desktop notifications on{}", if self.syncing { ", waiting for sync" } else { "" });
+
+        loop {
+            tokio::select! {
+                event = events.recv() => match event {
+                    Ok(ChainEvent::BlockConnected { block, spent }) => {
+                        let low_work = self.blockchain.read().unwrap().is_low_work();
+                        if !self.syncing {
+                            self.load_owners()?;
+                        }
+                        for event in self.block_events(&block, &spent, low_work, now()) {
+                            self.show(&event).await;
+                        }
+                    }
+                    Ok(_) => {}
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        log::debug!("🔔 Skipped {} chain events", skipped);
+                    }
+                    Err(broadcast::error::RecvError::Closed) => {
+                        return Err(QtcError::Blockchain("Chain event stream closed".to_string()));
+                    }
+                },
+                event = recv_mined(&mut mined) => match event {
+                    Ok(event) => {
+                        self.show(&DesktopEvent::BlockMined { height: event.height, hash: event.hash, reward: event.reward }).await;
+                    }
+                    Err(broadcast::error::RecvError::Lagged(_)) => {}
+                    Err(broadcast::error::RecvError::Closed) => mined = None,
+                },
+                _ = shutdown.wait() => return Ok(()),
+            }
+        }
+    }
+
+    fn is_behind(&self) -> Result<bool> {
+        let blockchain = self.blockchain.read().unwrap();
+        let tip_time = blockchain.get_block_by_height(blockchain.height)?
+            .map_or(0, |block| block.header.timestamp);
+        Ok(blockchain.is_low_work() || tip_time + SYNCED_TIP_AGE_SECS < now())
+    }
+
+    /// Map every address of every local wallet to its wallet, picking up new ones
+    fn load_owners(&mut self) -> Result<()> {
+        self.owners.clear();
+        for name in self.db.list_wallets()? {
+            let wallet = self.db.load_wallet(&name, self.blockchain.clone())?;
+            for address in wallet.addresses.keys() {
+                self.owners.insert(recorded_address(address), name.clone());
+            }
+        }
+        Ok(())
+    }
+
+    /// What a newly connected block is worth telling the user about
+    fn block_events(&mut self, block: &Block, spent: &[(OutPoint, UtxoEntry)], low_work: bool, now: u64) -> Vec<DesktopEvent> {
+        if self.syncing {
+            if low_work || block.header.timestamp + SYNCED_TIP_AGE_SECS < now {
+                return Vec::new();
+            }
+            self.syncing = false;
+            if let Err(e) = self.load_owners() {
+                log::warn!("🔔 Failed to load wallets for payment notifications: {}", e);
+            }
+            return vec![DesktopEvent::SyncComplete { height: block.header.height }];
+        }
+
+        let spent: HashMap<&OutPoint, &UtxoEntry> = spent.iter().map(|(outpoint, entry)| (outpoint, entry)).collect();
+        let mut events = Vec::new();
+        // Coinbase rewards are reported as mined blocks
+        for tx in block.transactions.iter().filter(|tx| !tx.is_coinbase()) {
+            let senders: Vec<&String> = tx.inputs.iter()
+                .filter_map(|input| spent.get(&input.previous_output))
+                .filter_map(|entry| self.owners.get(&entry.address))
+                .collect();
+
+            let mut received: BTreeMap<&String, u64> = BTreeMap::new();
+            for output in &tx.outputs {
+                if let Some(wallet) = self.owners.get(&UtxoSet::output_address(&output.script_pubkey)) {
+                    // Change coming back to the paying wallet isn't a payment
+                    if !senders.contains(&wallet) {
+                        *received.entry(wallet).or_default() += output.value;
+                    }
+                }
+            }
+
+            let txid = tx.hash().to_hex();
+            events.extend(received.into_iter().map(|(wallet, amount)| DesktopEvent::PaymentReceived {
+                wallet: wallet.clone(),
+                amount,
+                txid: txid.clone(),
+            }));
+        }
+        events
+    }
+
+    async fn show(&mut self, event: &DesktopEvent) {
+        let (title, body) = (event.title(), event.body());
+        log::info!("🔔 {}: {}", title, body);
+        if self.unavailable {
+            return;
+        }
+
+        let Some(mut command) = notify_command(&title, &body) else {
+            log::warn!("🔔 Desktop notifications aren't supported on this platform");
+            self.unavailable = true;
+            return;
+        };
+        match command.status().await {
+            Ok(status) if status.success() => {}
+            Ok(status) => log::debug!("🔔 Notifier exited with {}", status),
+            Err(e) => {
+                log::warn!("🔔 Desktop notifications unavailable: {}", e);
+                self.unavailable = true;
+            }
+        }
+    }
+}
+
+async fn recv_mined(mined: &mut Option<broadcast::Receiver<BlockMinedEvent>>) -> std::result::Result<BlockMinedEvent, broadcast::error::RecvError> {
+    match mined {
+        Some(mined) => mined.recv().await,
+        None => std::future::pending().await,
+    }
+}
+
+/// The form an output paying `address` takes in `UtxoEntry::address`
+fn recorded_address(address: &str) -> String {
+    UtxoSet::output_address(&Transaction::address_to_script_pubkey(address))
+}
+
+fn notify_command(title: &str, body: &str) -> Option<Command> {
+    if cfg!(target_os = "macos") {
+        let mut command = Command::new("osascript");
+        command.arg("-e").arg(format!(
+            "display notification {} with title {}",
+            applescript_string(body),
+            applescript_string(title),
+        ));
+        Some(command)
+    } else if cfg!(unix) {
+        let mut command = Command::new("notify-send");
+        command.args(["--app-name", "qtcd", title, body]);
+        Some(command)
+    } else {
+        None
+    }
+}
+
+fn applescript_string(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn now() -> u64 {
+    chrono::Utc::now().timestamp() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::hash::Hash256;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_payments_notified_once_synced() -> Result<()> {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Arc::new(Database::new(temp_dir.path().join("test.db"))?);
+        let blockchain = Arc::new(RwLock::new(Blockchain::new(db.clone())?));
+        let mut notifier = DesktopNotifier::new(db, blockchain);
+        notifier.owners.insert(recorded_address("qtc1ours"), "hot".to_string());
+        notifier.syncing = true;
+
+        // Someone else pays us; we spend from our own address with change back
+        let funding = OutPoint::new(Hash256::hash(b"funding"), 0);
+        let mut payment = Transaction::new();
+        payment.add_input(OutPoint::new(Hash256::hash(b"theirs"), 0), vec![]);
+        payment.add_output(70_000, "qtc1ours");
+        let mut spend = Transaction::new();
+        spend.add_input(funding.clone(), vec![]);
+        spend.add_output(10_000, "qtc1merchant");
+        spend.add_output(5_000, "qtc1ours");
+        let coinbase = Transaction::new_coinbase("qtc1ours".to_string(), 1_000, "cb".to_string());
+        let block = Block::new(Hash256::zero(), vec![coinbase, payment.clone(), spend], 1, 5);
+        let spent = vec![(funding, UtxoEntry {
+            txid: Hash256::hash(b"funding"),
+            vout: 0,
+            value: 16_000,
+            script_pubkey: vec![],
+            address: recorded_address("qtc1ours"),
+            height: 1,
+            is_coinbase: false,
+        })];
+
+        // An old block while catching up says nothing
+        let now = block.header.timestamp;
+        assert!(notifier.block_events(&block, &spent, false, now + 2 * SYNCED_TIP_AGE_SECS).is_empty());
+        assert!(notifier.block_events(&block, &spent, true, now).is_empty());
+        assert_eq!(notifier.block_events(&block, &spent, false, now), vec![DesktopEvent::SyncComplete { height: 5 }]);
+
+        // Reloading owners found no wallets in the database, so put ours back
+        notifier.owners.insert(recorded_address("qtc1ours"), "hot".to_string());
+        let events = notifier.block_events(&block, &spent, false, now);
+        assert_eq!(events, vec![DesktopEvent::PaymentReceived {
+            wallet: "hot".to_string(),
+            amount: 70_000,
+            txid: payment.hash().to_hex(),
+        }]);
+        assert_eq!(applescript_string(r#"say "hi""#), r#""say \"hi\"""#);
+        Ok(())
+    }
+}
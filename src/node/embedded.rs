@@ -0,0 +1,728 @@
+//! In-process node for applications that embed QTC instead of running `qtcd`
+//!
+//! `NodeBuilder` wires up the same subsystems as `qtcd start` (P2P, wallet
+//! balances, the APIs enabled in the config, and a miner that idles until
+//! started) under one supervisor; `Node` is the handle to query the chain,
+//! submit transactions and follow chain events while it runs.
+//!
+//! ```no_run
+//! # async fn run() -> quantum_goldchain::Result<()> {
+//! let node = quantum_goldchain::Node::builder(quantum_goldchain::config::Config::regtest()).start().await?;
+//! let mut events = node.subscribe();
+//! println!("tip {} at height {}", node.tip(), node.height());
+//! while let Ok(event) = events.recv().await {
+//!     println!("{:?}", event);
+//! }
+//! node.stop().await
+//! # }
+//! ```
+
+use crate::api::auth::{ApiScope, ScopeGuard};
+use crate::api::faucet::Faucet;
+use crate::api::jsonrpc::JsonRpcServer;
+use crate::api::pubsub::PubSubPublisher;
+use crate::api::rest::RestApi;
+use crate::api::webhooks::WebhookNotifier;
+use crate::api::websocket::WebSocketServer;
+use crate::config::Config;
+use crate::consensus::ChainParams;
+use crate::core::blockchain::ChainState;
+use crate::core::{Block, Blockchain, ChainEvent, Transaction};
+use crate::crypto::hash::Hash256;
+use crate::crypto::keys::select_address_prefix;
+use crate::mining::{MiningController, PayoutRotation, PayoutSplit, ShareTracker};
+use crate::network::bans::BanList;
+use crate::network::diversity::{AsnMap, PeerDiversity};
+use crate::network::federation::{load_or_create_identity, FederationAllowlist};
+use crate::network::messaging::Mailbox;
+use crate::network::p2p::{P2PCommand, P2PNode};
+use crate::network::proxy::ProxyConfig;
+use crate::node::selftest::{self, CheckStatus};
+use crate::node::state;
+use crate::node::{DesktopNotifier, RestartPolicy, SubsystemHealth, Supervisor};
+use crate::storage::database::AuditAction;
+use crate::storage::lock::DataDirLock;
+use crate::storage::{Database, StorageEncryption, StorageSecret};
+use crate::wallet::BalanceTracker;
+use crate::{QtcError, Result};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tokio::sync::{broadcast, mpsc};
+
+/// How long `Node::stop` waits for subsystems before saving state regardless
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Options for starting a `Node`; everything not set here comes from the `Config`
+pub struct NodeBuilder {
+    config: Config,
+    db: Option<Arc<Database>>,
+    encryption: Option<StorageEncryption>,
+    mining_address: Option<String>,
+    mine: bool,
+    notify_desktop: bool,
+    self_test: bool,
+}
+
+impl NodeBuilder {
+    pub fn new(config: Config) -> Self {
+        Self {
+            config,
+            db: None,
+            encryption: None,
+            mining_address: None,
+            mine: false,
+            notify_desktop: false,
+            self_test: true,
+        }
+    }
+
+    /// Use an already opened database instead of `<data_dir>/qtc.db`
+    pub fn with_database(mut self, db: Arc<Database>) -> Self {
+        self.db = Some(db);
+        self
+    }
+
+    /// Unlock an encrypted data directory; a configured key file is used when this isn't set
+    pub fn with_storage_encryption(mut self, encryption: StorageEncryption) -> Self {
+        self.encryption = Some(encryption);
+        self
+    }
+
+    /// Mine to `address`, or along the configured `mining.payouts` or `mining.payout_wallet` when it is `None`
+    pub fn with_mining(mut self, address: Option<String>) -> Self {
+        self.mine = true;
+        self.mining_address = address;
+        self
+    }
+
+    pub fn with_desktop_notifications(mut self, enabled: bool) -> Self {
+        self.notify_desktop = enabled;
+        self
+    }
+
+    /// Whether to run the startup self-test (on by default) before starting any subsystem
+    pub fn with_self_test(mut self, enabled: bool) -> Self {
+        self.self_test = enabled;
+        self
+    }
+
+    /// Open the chain and start every subsystem; call `Node::stop` to shut it down again
+    pub async fn start(self) -> Result<Node> {
+        let config = self.config;
+        select_address_prefix(&ChainParams::for_network(config.network_type).address_prefix);
+        std::fs::create_dir_all(&config.storage.data_dir)?;
+        // A database handed in is the caller's to lock
+        let (db, lock) = match self.db {
+            Some(db) => (db, None),
+            None => {
+                let lock = match config.storage.lock_data_dir {
+                    true => Some(DataDirLock::acquire(&config.storage.data_dir, "embedded node", false)?),
+                    false => None,
+                };
+                let encryption = match self.encryption {
+                    Some(encryption) => Some(encryption),
+                    None => key_file_encryption(&config)?,
+                };
+                (Arc::new(Database::with_encryption(config.storage.data_dir.join("qtc.db"), encryption.as_ref())?), lock)
+            }
+        };
+
+        record_config_change(&config, &db)?;
+
+        let mut chain = open_blockchain(&config, db.clone())?;
+        chain.set_txindex(config.storage.txindex);
+        if self.self_test {
+            let tip_timestamp = db.get_block_header(&chain.tip)?.map(|header| header.timestamp);
+            let report = selftest::run_startup(&config, tip_timestamp);
+            for check in report.checks.iter().filter(|check| check.status != CheckStatus::Pass) {
+                log::warn!("🩺 {}", check);
+            }
+            report.into_result()?;
+        }
+        if let Err(e) = state::load_mempool(&config.storage.data_dir, &chain) {
+            log::warn!("🗂️ Could not restore the saved mempool: {}", e);
+        }
+        let blockchain = Arc::new(RwLock::new(chain));
+
+        let (mut p2p_node, p2p_events, p2p_commands) = P2PNode::with_identity(
+            node_identity(&config)?,
+            blockchain.clone(),
+            config.network.port,
+            config.network.bootstrap_nodes.clone(),
+            proxy(&config)?,
+        ).await?;
+        p2p_node.set_peer_diversity(Arc::new(peer_diversity(&config)?));
+        p2p_node.set_ban_list(Arc::new(BanList::load(db.clone())?));
+        p2p_node.set_ban_policy(
+            config.network.ban_threshold,
+            Duration::from_secs(config.network.ban_duration_secs),
+        );
+        if let Some(allowlist) = federation(&config)? {
+            p2p_node.set_federation(Arc::new(allowlist));
+        }
+        if config.network.messaging {
+            p2p_node.set_mailbox(Arc::new(Mailbox::new(db.clone())?))?;
+        }
+        if config.network.partition_window_secs > 0 {
+            p2p_node.set_partition_detection(
+                std::time::Duration::from_secs(config.network.partition_window_secs),
+                config.network.trusted_peers.clone(),
+            );
+        }
+        match state::load_peers(&config.storage.data_dir) {
+            Ok(addresses) => p2p_node.dial_saved_peers(&addresses),
+            Err(e) => log::warn!("🗂️ Could not read saved peer addresses: {}", e),
+        }
+        let partition_alerts = p2p_node.subscribe_partition_alerts();
+        let peer_versions = p2p_node.peer_versions();
+        let peer_diversity = p2p_node.peer_diversity();
+        let bans = p2p_node.ban_list();
+
+        // Mining can be started later over the control socket, so the event sinks
+        // below subscribe to the controller whether or not it starts out mining
+        let mut mining = MiningController::new(blockchain.clone(), config.mining.threads);
+        mining.set_fast_mode(config.mining.fast_mode);
+        if let Some(wallet) = &config.mining.payout_wallet {
+            let payout = PayoutRotation::new(db.clone(), blockchain.clone(), wallet, config.mining.payout_rotation_blocks);
+            match payout {
+                Ok(payout) => mining.set_payout_rotation(Arc::new(payout)),
+                // Only fatal when the node was told to mine to this wallet now
+                Err(e) if self.mine && self.mining_address.is_none() => return Err(e),
+                Err(e) => log::warn!("⛏️ Mining payout wallet {} unavailable: {}", wallet, e),
+            }
+        }
+        if !config.mining.payouts.is_empty() {
+            mining.set_payout_split(PayoutSplit::parse(&config.mining.payouts)?);
+        }
+        // An explicit mining address wins over configured payouts, and those over the payout wallet
+        if self.mine {
+            mining.start(self.mining_address.clone(), &[], None, false)?;
+        }
+        let mining = Arc::new(mining);
+
+        // Subsystems stop in reverse order, so spawn the ones others depend on first
+        let mut supervisor = Supervisor::new();
+
+        let p2p_node = Arc::new(tokio::sync::Mutex::new(p2p_node));
+        let supervised_p2p = p2p_node.clone();
+        supervisor.spawn("p2p", RestartPolicy::Always, move |mut shutdown| {
+            let p2p_node = supervised_p2p.clone();
+            async move {
+                let mut p2p_node = p2p_node.lock().await;
+                tokio::select! {
+                    result = p2p_node.run() => result,
+                    _ = shutdown.wait() => Ok(()),
+                }
+            }
+        });
+
+        let events_blockchain = blockchain.clone();
+        supervisor.spawn("p2p-events", RestartPolicy::Always, move |mut shutdown| {
+            let blockchain = events_blockchain.clone();
+            let mut p2p_events = p2p_events.resubscribe();
+            async move {
+                loop {
+                    let event = tokio::select! {
+                        event = p2p_events.recv() => event,
+                        _ = shutdown.wait() => return Ok(()),
+                    };
+                    match event {
+                        Ok(event) => {
+                            if let Err(e) = handle_p2p_event(blockchain.clone(), event).await {
+                                log::error!("P2P event handling error: {}", e);
+                            }
+                        }
+                        Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                            log::warn!("P2P event handler skipped {} events", skipped);
+                        }
+                        Err(broadcast::error::RecvError::Closed) => {
+                            return Err(QtcError::Network("P2P event channel closed".to_string()));
+                        }
+                    }
+                }
+            }
+        });
+
+        // Subscribed before the tracker loads balances, so no block slips in between
+        let (balances_blockchain, balances_db) = (blockchain.clone(), db.clone());
+        supervisor.spawn("wallet-balances", RestartPolicy::Always, move |shutdown| {
+            let events = balances_blockchain.read().unwrap().subscribe_events();
+            BalanceTracker::new(balances_db.clone(), balances_blockchain.clone()).run(events, shutdown)
+        });
+
+        if self.notify_desktop {
+            let (notify_blockchain, notify_db, mining) = (blockchain.clone(), db.clone(), mining.clone());
+            supervisor.spawn("desktop-notifications", RestartPolicy::OnFailure, move |shutdown| {
+                let events = notify_blockchain.read().unwrap().subscribe_events();
+                let mined = Some(mining.subscribe_blocks());
+                DesktopNotifier::new(notify_db.clone(), notify_blockchain.clone()).run(events, mined, shutdown)
+            });
+        }
+
+        if config.api.pubsub.is_enabled() {
+            let (pubsub_blockchain, pubsub_config) = (blockchain.clone(), config.api.pubsub.clone());
+            supervisor.spawn("pubsub", RestartPolicy::Always, move |shutdown| {
+                let events = pubsub_blockchain.read().unwrap().subscribe_events();
+                let config = pubsub_config.clone();
+                async move { PubSubPublisher::bind(&config).await?.run(events, shutdown).await }
+            });
+        }
+
+        // Mainnet coins are worth something, so the faucet only ever runs on testnet
+        let faucet = match &config.api.faucet {
+            Some(_) if !config.is_testnet() => {
+                log::warn!("🚰 Faucet disabled: it is only available on testnet");
+                None
+            }
+            Some(faucet_config) => {
+                db.load_wallet(&faucet_config.wallet, blockchain.clone())
+                    .map_err(|e| QtcError::InvalidInput(format!("Faucet wallet '{}': {}", faucet_config.wallet, e)))?;
+                let mut faucet = Faucet::new(faucet_config.clone(), db.clone(), blockchain.clone());
+                faucet.set_p2p_commands(p2p_commands.clone());
+                Some(Arc::new(faucet))
+            }
+            None => None,
+        };
+
+        if config.api.enable_rest {
+            let (blockchain, db, api_config) = (blockchain.clone(), db.clone(), config.api.clone());
+            let (addrindex, health, p2p_commands) = (config.storage.addrindex, supervisor.health(), p2p_commands.clone());
+            supervisor.spawn("rest", RestartPolicy::Always, move |mut shutdown| {
+                let mut rest_api = RestApi::with_db(blockchain.clone(), db.clone(), api_config.clone());
+                rest_api.set_address_index(addrindex);
+                if let Some(faucet) = &faucet {
+                    rest_api.set_faucet(faucet.clone());
+                }
+                rest_api.set_peer_versions(peer_versions.clone());
+                rest_api.set_peer_diversity(peer_diversity.clone());
+                rest_api.set_ban_list(bans.clone());
+                rest_api.set_subsystem_health(health.clone());
+                rest_api.set_p2p_commands(p2p_commands.clone());
+                async move {
+                    tokio::select! {
+                        result = rest_api.start() => result,
+                        _ = shutdown.wait() => Ok(()),
+                    }
+                }
+            });
+        }
+
+        let enable_rpc = config.api.enable_rpc && config.api.rpc_user.is_some() && config.api.rpc_password.is_some();
+        if config.api.enable_rpc && !enable_rpc {
+            log::warn!("🔐 JSON-RPC disabled: rpc_user and rpc_password must both be set");
+        }
+        if config.mining.pool.is_some() && !(enable_rpc && config.api.enable_mining_endpoints) {
+            log::warn!("⛏️ mining.pool needs JSON-RPC with mining endpoints enabled; no shares will be accepted");
+        }
+        if enable_rpc {
+            let pool = match &config.mining.pool {
+                Some(pool_config) => {
+                    let wallet = db.load_wallet(&pool_config.wallet, blockchain.clone())?;
+                    log::info!("⛏️ Accepting pool shares for wallet {} ({} payouts)", pool_config.wallet, pool_config.scheme);
+                    Some(Arc::new(ShareTracker::new(db.clone(), pool_config.clone(), &wallet)?))
+                }
+                None => None,
+            };
+            let (blockchain, db, api_config) = (blockchain.clone(), db.clone(), config.api.clone());
+            let (network, p2p_commands) = (config.network_type.chain_name(), p2p_commands.clone());
+            supervisor.spawn("jsonrpc", RestartPolicy::Always, move |mut shutdown| {
+                let mut rpc_server = JsonRpcServer::with_db(blockchain.clone(), db.clone(), api_config.clone());
+                rpc_server.set_network(network);
+                rpc_server.set_p2p_commands(p2p_commands.clone());
+                if let Some(pool) = &pool {
+                    rpc_server.set_pool(pool.clone());
+                }
+                async move {
+                    tokio::select! {
+                        result = rpc_server.start() => result,
+                        _ = shutdown.wait() => Ok(()),
+                    }
+                }
+            });
+        }
+
+        #[cfg(unix)]
+        {
+            let socket = config.control_socket_path();
+            let (blockchain, db, api_config) = (blockchain.clone(), db.clone(), config.api.clone());
+            let (addrindex, p2p_commands, mining) = (config.storage.addrindex, p2p_commands.clone(), mining.clone());
+            let data_dir = config.storage.data_dir.clone();
+            supervisor.spawn("control-socket", RestartPolicy::Always, move |mut shutdown| {
+                let mut rest_api = RestApi::with_db(blockchain.clone(), db.clone(), api_config.clone());
+                rest_api.set_address_index(addrindex);
+                let mut server = crate::api::cluster::ControlServer::new(blockchain.clone(), rest_api, socket.clone());
+                server.set_p2p_commands(p2p_commands.clone());
+                server.set_mining(mining.clone());
+                server.set_data_dir(data_dir.clone());
+                async move {
+                    tokio::select! {
+                        result = server.start() => result,
+                        _ = shutdown.wait() => Ok(()),
+                    }
+                }
+            });
+        }
+
+        if config.api.enable_websocket {
+            let (blockchain, db, api_config, mining) = (blockchain.clone(), db.clone(), config.api.clone(), mining.clone());
+            supervisor.spawn("websocket", RestartPolicy::Always, move |mut shutdown| {
+                let mut ws_server = WebSocketServer::new(blockchain.clone(), api_config.websocket_port);
+                ws_server.set_bind_address(api_config.bind_address);
+                ws_server.set_rate_limit(api_config.rate_limit_per_minute);
+                if api_config.require_api_keys {
+                    ws_server.set_auth(ScopeGuard::from_config(ApiScope::Read, db.clone(), &api_config));
+                }
+                // The relay feeds this server instance, so it lives and dies with it
+                let relay = ws_server.relay_block_mined(mining.subscribe_blocks());
+                async move {
+                    let result = tokio::select! {
+                        result = ws_server.start() => result,
+                        _ = shutdown.wait() => Ok(()),
+                    };
+                    relay.abort();
+                    result
+                }
+            });
+        }
+
+        if !config.api.webhook_urls.is_empty() && config.network.partition_window_secs > 0 {
+            let notifier = Arc::new(webhook_notifier(&config));
+            supervisor.spawn("partition-alerts", RestartPolicy::OnFailure, move |mut shutdown| {
+                let mut relay = notifier.clone().relay_partition_alerts(partition_alerts.resubscribe());
+                async move {
+                    tokio::select! {
+                        result = &mut relay => result
+                            .map_err(|e| QtcError::Network(format!("Partition alert relay stopped: {}", e))),
+                        _ = shutdown.wait() => {
+                            relay.abort();
+                            Ok(())
+                        }
+                    }
+                }
+            });
+        }
+
+        if !config.api.webhook_urls.is_empty() {
+            let notifier = Arc::new(webhook_notifier(&config));
+            let mining = mining.clone();
+            supervisor.spawn("webhooks", RestartPolicy::OnFailure, move |mut shutdown| {
+                let mut relay = notifier.clone().relay_block_mined(mining.subscribe_blocks());
+                async move {
+                    tokio::select! {
+                        result = &mut relay => result
+                            .map_err(|e| QtcError::Network(format!("Webhook relay stopped: {}", e))),
+                        _ = shutdown.wait() => {
+                            relay.abort();
+                            Ok(())
+                        }
+                    }
+                }
+            });
+        }
+
+        // A refused start (e.g. chain still below minimum work) is retried with backoff
+        let miner = mining.clone();
+        supervisor.spawn("miner", RestartPolicy::OnFailure, move |shutdown| miner.clone().run(shutdown));
+
+        Ok(Node { config, db, blockchain, p2p_node, p2p_commands, mining, supervisor, _lock: lock })
+    }
+}
+
+/// A running node; dropping it without `stop` leaves the subsystems running until the runtime ends
+pub struct Node {
+    config: Config,
+    db: Arc<Database>,
+    blockchain: Arc<RwLock<Blockchain>>,
+    p2p_node: Arc<tokio::sync::Mutex<P2PNode>>,
+    p2p_commands: mpsc::Sender<P2PCommand>,
+    mining: Arc<MiningController>,
+    supervisor: Supervisor,
+    _lock: Option<DataDirLock>,
+}
+
+impl Node {
+    pub fn builder(config: Config) -> NodeBuilder {
+        NodeBuilder::new(config)
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Shared chain state, for queries the methods below don't cover
+    pub fn blockchain(&self) -> Arc<RwLock<Blockchain>> {
+        self.blockchain.clone()
+    }
+
+    pub fn database(&self) -> Arc<Database> {
+        self.db.clone()
+    }
+
+    /// Start, stop and watch this node's miner
+    pub fn mining(&self) -> Arc<MiningController> {
+        self.mining.clone()
+    }
+
+    /// Blocks connected and disconnected, and mempool changes, as they happen
+    pub fn subscribe(&self) -> broadcast::Receiver<ChainEvent> {
+        self.blockchain.read().unwrap().subscribe_events()
+    }
+
+    pub fn height(&self) -> u64 {
+        self.blockchain.read().unwrap().height
+    }
+
+    pub fn tip(&self) -> Hash256 {
+        self.blockchain.read().unwrap().tip
+    }
+
+    pub fn chain_info(&self) -> Result<ChainState> {
+        self.blockchain.read().unwrap().get_chain_info()
+    }
+
+    pub fn get_block(&self, hash: &Hash256) -> Result<Option<Block>> {
+        self.blockchain.read().unwrap().get_block(hash)
+    }
+
+    pub fn get_block_by_height(&self, height: u64) -> Result<Option<Block>> {
+        self.blockchain.read().unwrap().get_block_by_height(height)
+    }
+
+    /// A confirmed transaction (needs `storage.txindex`) or one waiting in the mempool
+    pub fn get_transaction(&self, txid: &Hash256) -> Result<Option<Transaction>> {
+        if let Some(tx) = self.db.get_transaction(txid)? {
+            return Ok(Some(tx));
+        }
+        let blockchain = self.blockchain.read().unwrap();
+        let mempool = blockchain.mempool.read().unwrap();
+        Ok(mempool.get(txid).map(|entry| entry.tx.clone()))
+    }
+
+    pub fn get_balance(&self, address: &str) -> Result<u64> {
+        self.blockchain.read().unwrap().get_balance(address)
+    }
+
+    /// Add `tx` to the mempool and relay it to peers
+    pub async fn submit_transaction(&self, tx: Transaction) -> Result<Hash256> {
+        let txid = self.blockchain.read().unwrap().accept_to_mempool(tx.clone())?;
+        self.p2p_commands.send(P2PCommand::BroadcastTransaction(tx)).await
+            .map_err(|e| QtcError::Network(format!("Failed to relay transaction: {}", e)))?;
+        Ok(txid)
+    }
+
+    /// Names of the subsystems this node started, in start order
+    pub fn subsystems(&self) -> Vec<String> {
+        self.supervisor.subsystems()
+    }
+
+    pub fn health(&self) -> Vec<SubsystemHealth> {
+        self.supervisor.health().snapshot()
+    }
+
+    /// Stop every subsystem, save the mempool and peer addresses for the next start,
+    /// and flush the UTXO cache and database. State is saved even when a subsystem
+    /// overruns `SHUTDOWN_TIMEOUT`.
+    pub async fn stop(self) -> Result<()> {
+        if tokio::time::timeout(SHUTDOWN_TIMEOUT, self.supervisor.shutdown()).await.is_err() {
+            log::warn!("⏱️ Subsystems did not stop within {}s, saving state anyway", SHUTDOWN_TIMEOUT.as_secs());
+        }
+        
+        let data_dir = &self.config.storage.data_dir;
+        match self.p2p_node.try_lock().map(|p2p_node| p2p_node.address_book()) {
+            Ok(addresses) => match state::save_peers(data_dir, &addresses) {
+                Ok(()) => log::info!("🗂️ Saved the addresses of {} peer(s)", addresses.len()),
+                Err(e) => log::warn!("🗂️ Could not save peer addresses: {}", e),
+            },
+            Err(_) => log::warn!("🗂️ P2P is still running, peer addresses not saved"),
+        }
+        
+        let blockchain = self.blockchain.read().unwrap();
+        match state::save_mempool(data_dir, &blockchain) {
+            Ok(count) => log::info!("🗂️ Saved {} mempool transaction(s)", count),
+            Err(e) => log::warn!("🗂️ Could not save the mempool: {}", e),
+        }
+        blockchain.flush_utxos()?;
+        self.db.flush()
+    }
+}
+
+fn webhook_notifier(config: &Config) -> WebhookNotifier {
+    let mut notifier = WebhookNotifier::new(config.api.webhook_urls.clone());
+    if let Some(secret) = &config.api.webhook_secret {
+        notifier.set_secret(secret.clone());
+    }
+    notifier
+}
+
+/// Load the chain with this network's consensus parameters applied
+pub(crate) fn open_blockchain(config: &Config, db: Arc<Database>) -> Result<Blockchain> {
+    if config.storage.prune_target_mb.is_some() && (config.storage.txindex || config.storage.addrindex) {
+        return Err(QtcError::InvalidInput(
+            "storage.prune_target_mb can't be combined with txindex or addrindex, which need every block".to_string()
+        ));
+    }
+    if config.storage.addrindex && !db.is_address_history_complete()? {
+        if let Some(height) = db.get_pruned_height()? {
+            return Err(QtcError::InvalidInput(format!(
+                "storage.addrindex needs every block, but blocks up to height {} were pruned or came from a UTXO snapshot", height
+            )));
+        }
+    }
+    let genesis = config.genesis_params()?;
+    let mut blockchain = Blockchain::with_genesis(db, &genesis)?;
+    if config.consensus.genesis.is_some() && blockchain.genesis_hash()? != genesis.hash() {
+        return Err(QtcError::Consensus(format!(
+            "This data directory holds a chain with genesis {}, but consensus.genesis describes {}",
+            blockchain.genesis_hash()?, genesis.hash()
+        )));
+    }
+    blockchain.set_chain_params(config.chain_params()?);
+    blockchain.set_minimum_chain_work(config.consensus.minimum_chain_work);
+    blockchain.set_addrindex(config.storage.addrindex)?;
+    blockchain.set_blockfilterindex(config.storage.blockfilterindex)?;
+    blockchain.set_utxo_flush_interval(config.storage.utxo_flush_blocks);
+    blockchain.set_prune_target(config.storage.prune_target_mb)?;
+    Ok(blockchain)
+}
+
+/// Per-subnet peer caps, grouped by ASN when an asmap is configured
+pub(crate) fn peer_diversity(config: &Config) -> Result<PeerDiversity> {
+    let max_per_group = config.network.max_peers_per_subnet;
+    match &config.network.asmap_file {
+        Some(path) => {
+            let asmap = AsnMap::load(path)?;
+            log::info!("🗺️ Loaded {} ASN prefixes from {}", asmap.len(), path.display());
+            Ok(PeerDiversity::with_asmap(max_per_group, asmap))
+        }
+        None => Ok(PeerDiversity::new(max_per_group)),
+    }
+}
+
+pub(crate) fn node_identity(config: &Config) -> Result<libp2p::identity::Keypair> {
+    load_or_create_identity(&config.storage.data_dir.join("node_key"))
+}
+
+/// The federation allowlist, if this node runs on a private network
+pub(crate) fn federation(config: &Config) -> Result<Option<FederationAllowlist>> {
+    if config.network.federation_peers.is_empty() {
+        return Ok(None);
+    }
+    FederationAllowlist::parse(&config.network.federation_peers).map(Some)
+}
+
+/// The SOCKS5 proxy outbound connections go through, if one is configured
+pub(crate) fn proxy(config: &Config) -> Result<Option<ProxyConfig>> {
+    match &config.network.proxy {
+        Some(address) => ProxyConfig::parse(address, config.network.onion_only).map(Some),
+        None if config.network.onion_only => Err(QtcError::InvalidInput(
+            "network.onion_only needs network.proxy to reach onion services".to_string()
+        )),
+        None => Ok(None),
+    }
+}
+
+/// An encrypted data directory can only be opened here if its key lives in a file
+fn key_file_encryption(config: &Config) -> Result<Option<StorageEncryption>> {
+    let Some(encryption) = &config.storage.encryption else {
+        return Ok(None);
+    };
+    let Some(path) = &encryption.key_file else {
+        return Err(QtcError::InvalidInput(
+            "storage.encryption needs a key_file, or pass the passphrase with NodeBuilder::with_storage_encryption".to_string()
+        ));
+    };
+    Ok(Some(StorageEncryption {
+        secret: StorageSecret::KeyFile(path.clone()),
+        encrypt_blocks: encryption.encrypt_blocks,
+    }))
+}
+
+/// Log a config_changed audit entry when the node starts with a different configuration
+fn record_config_change(config: &Config, db: &Database) -> Result<()> {
+    let serialized = crate::crypto::canonical::to_canonical_vec(config)?;
+    let fingerprint = Hash256::hash(&serialized).to_hex();
+
+    let details = match db.swap_config_fingerprint(&fingerprint)? {
+        Some(previous) if previous == fingerprint => return Ok(()),
+        Some(previous) => format!("configuration {} replaced {}", &fingerprint[..16], &previous[..16.min(previous.len())]),
+        None => format!("initial configuration {}", &fingerprint[..16]),
+    };
+
+    db.record_audit_event(AuditAction::ConfigChanged, "node", details)?;
+    Ok(())
+}
+
+async fn handle_p2p_event(
+    blockchain: Arc<RwLock<Blockchain>>,
+    event: crate::network::protocol::Message,
+) -> Result<()> {
+    match event.message_type {
+        crate::network::protocol::MessageType::Block(block) => {
+            let mut bc = blockchain.write().unwrap();
+            if let Err(e) = bc.add_block(block) {
+                log::warn!("Failed to add received block: {}", e);
+            }
+        }
+
+        crate::network::protocol::MessageType::Transaction(tx) => {
+            let bc = blockchain.read().unwrap();
+            match bc.accept_to_mempool(tx) {
+                Ok(txid) => log::info!("Received valid transaction: {}", txid),
+                Err(e) => log::debug!("Rejected relayed transaction: {}", e),
+            }
+        }
+
+        _ => {
+            // Handle other message types
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::hash::Hashable;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_embedded_node_lifecycle() -> Result<()> {
+        let temp_dir = TempDir::new().unwrap();
+        let mut config = Config::regtest();
+        config.storage.data_dir = temp_dir.path().to_path_buf();
+        config.network.port = 0;
+        config.network.bootstrap_nodes.clear();
+        config.api.enable_rest = false;
+        config.api.enable_rpc = false;
+        config.api.enable_websocket = false;
+
+        let node = Node::builder(config).start().await?;
+        assert_eq!(node.subsystems()[..3], ["p2p", "p2p-events", "wallet-balances"]);
+        // The miner always runs, idle until a start arrives over the control socket
+        assert!(node.subsystems().iter().any(|name| name == "miner"));
+        assert!(!node.mining().status().mining);
+        assert_eq!(node.height(), 0);
+        let genesis = node.get_block_by_height(0)?.expect("genesis block");
+        assert_eq!(genesis.hash(), node.tip());
+        assert_eq!(node.chain_info()?.tip, node.tip());
+
+        // A transaction spending nothing we know of is turned away, and not relayed
+        let mut events = node.subscribe();
+        let mut tx = Transaction::new();
+        tx.add_input(crate::core::transaction::OutPoint::new(Hash256::hash(b"missing"), 0), vec![]);
+        tx.add_output(1_000, "qtc1payee");
+        assert!(node.submit_transaction(tx.clone()).await.is_err());
+        assert!(node.get_transaction(&tx.hash())?.is_none());
+        assert!(events.try_recv().is_err());
+
+        node.stop().await?;
+
+        // The chain survives a restart from the same data directory
+        let mut config = Config::regtest();
+        config.storage.data_dir = temp_dir.path().to_path_buf();
+        let db = Arc::new(Database::new(temp_dir.path().join("qtc.db"))?);
+        assert_eq!(open_blockchain(&config, db)?.tip, genesis.hash());
+        Ok(())
+    }
+}
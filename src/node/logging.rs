@@ -0,0 +1,194 @@
+//! Log output: levels per module, text or JSON, and rotated files for the node
+//!
+//! The code logs through the `log` macros, which `tracing` picks up. Every
+//! command logs to stderr; `qtcd start` also writes `<data_dir>/logs/qtcd.*.log`,
+//! rotated daily by default, so a daemon keeps its history without stdout
+//! being redirected anywhere. `logging.modules` raises or lowers single
+//! modules over `logging.level`, e.g. `network=debug,mining=info`, where a bare
+//! module name means this crate's. `RUST_LOG` still wins over both. The filter
+//! can be swapped while the node runs (`PUT /api/v1/admin/logging`).
+
+use crate::config::LoggingConfig;
+use crate::{QtcError, Result};
+use serde::{Deserialize, Serialize};
+use std::io::IsTerminal;
+use std::path::Path;
+use std::sync::OnceLock;
+use tracing::level_filters::LevelFilter;
+use tracing::Subscriber;
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{fmt, reload, EnvFilter, Layer, Registry};
+
+/// Rotated log files are named `qtcd.<date>.log`
+pub const LOG_FILE_PREFIX: &str = "qtcd";
+
+/// Top-level modules of this crate, which `logging.modules` may name bare
+const CRATE_MODULES: [&str; 12] = [
+    "api", "cli", "config", "consensus", "core", "crypto", "error", "mining", "network", "node", "storage", "wallet",
+];
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    #[default]
+    Text,
+    Json, // one object per line, for log shippers
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogRotation {
+    Hourly,
+    #[default]
+    Daily,
+    Never,
+}
+
+impl From<LogRotation> for Rotation {
+    fn from(rotation: LogRotation) -> Self {
+        match rotation {
+            LogRotation::Hourly => Rotation::HOURLY,
+            LogRotation::Daily => Rotation::DAILY,
+            LogRotation::Never => Rotation::NEVER,
+        }
+    }
+}
+
+static FILTER: OnceLock<reload::Handle<EnvFilter, Registry>> = OnceLock::new();
+
+/// Filter directives for `level`, with the `modules` overrides applied over it
+pub fn filter_directives(level: &str, modules: &str) -> Result<String> {
+    let mut directives = vec![parse_level(level)?.to_string()];
+    for directive in modules.split(',').map(str::trim).filter(|directive| !directive.is_empty()) {
+        let (target, level) = directive.split_once('=').ok_or_else(|| QtcError::InvalidInput(format!(
+            "Invalid log module override '{}': expected MODULE=LEVEL, e.g. network=debug", directive
+        )))?;
+        let target = target.trim();
+        let target = match target.split("::").next() {
+            Some(module) if CRATE_MODULES.contains(&module) => format!("{}::{}", env!("CARGO_CRATE_NAME"), target),
+            _ => target.to_string(),
+        };
+        directives.push(format!("{}={}", target, parse_level(level)?));
+    }
+    Ok(directives.join(","))
+}
+
+fn parse_level(level: &str) -> Result<LevelFilter> {
+    level.trim().parse().map_err(|_| QtcError::InvalidInput(format!(
+        "Unknown log level '{}' (expected trace, debug, info, warn, error or off)", level.trim()
+    )))
+}
+
+fn env_filter(directives: &str) -> Result<EnvFilter> {
+    EnvFilter::try_new(directives)
+        .map_err(|e| QtcError::InvalidInput(format!("Invalid log filter '{}': {}", directives, e)))
+}
+
+/// Start logging to stderr and, given `log_dir`, to rotated files there; `debug`
+/// lowers the configured level to debug. Files are written without a background
+/// thread, which wouldn't survive `start --daemon` forking.
+pub fn init(config: &LoggingConfig, debug: bool, log_dir: Option<&Path>) -> Result<()> {
+    let directives = match std::env::var("RUST_LOG") {
+        Ok(directives) if !directives.trim().is_empty() => directives,
+        _ => filter_directives(if debug { "debug" } else { &config.level }, &config.modules)?,
+    };
+    let (filter, handle) = reload::Layer::new(env_filter(&directives)?);
+
+    let file_layer = match log_dir {
+        Some(dir) => {
+            let appender = RollingFileAppender::builder()
+                .rotation(config.rotation.into())
+                .filename_prefix(LOG_FILE_PREFIX)
+                .filename_suffix("log")
+                .max_log_files(config.max_files.max(1))
+                .build(dir)
+                .map_err(|e| QtcError::Io(std::io::Error::other(format!("Failed to open log files in {}: {}", dir.display(), e))))?;
+            Some(output_layer(config.format, appender, false))
+        }
+        None => None,
+    };
+
+    let stderr_ansi = std::io::stderr().is_terminal();
+    if tracing_subscriber::registry()
+        .with(filter)
+        .with(output_layer(config.format, std::io::stderr, stderr_ansi))
+        .with(file_layer)
+        .try_init()
+        .is_err()
+    {
+        // Already set up, e.g. by an application embedding the node
+        return Ok(());
+    }
+    let _ = FILTER.set(handle);
+    sync_log_max_level();
+    Ok(())
+}
+
+fn output_layer<S, W>(format: LogFormat, writer: W, ansi: bool) -> Box<dyn Layer<S> + Send + Sync>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    W: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+{
+    let layer = fmt::layer().with_writer(writer).with_ansi(ansi);
+    match format {
+        LogFormat::Text => layer.boxed(),
+        LogFormat::Json => layer.json().boxed(),
+    }
+}
+
+/// The filter in effect, or None if `init` didn't set logging up
+pub fn current_filter() -> Option<String> {
+    FILTER.get()?.with_current(|filter| filter.to_string()).ok()
+}
+
+/// Swap the filter while the process runs; returns the directives now in effect
+pub fn set_filter(level: &str, modules: &str) -> Result<String> {
+    let handle = FILTER.get()
+        .ok_or_else(|| QtcError::InvalidInput("Logging was not set up by this process".to_string()))?;
+    let directives = filter_directives(level, modules)?;
+    handle.reload(env_filter(&directives)?)
+        .map_err(|e| QtcError::InvalidInput(format!("Failed to change the log filter: {}", e)))?;
+    sync_log_max_level();
+    log::info!("📝 Log filter changed to {}", directives);
+    Ok(directives)
+}
+
+/// The `log` macros drop anything above `log::max_level` before tracing sees it,
+/// so it has to follow the filter
+fn sync_log_max_level() {
+    let hint = FILTER.get()
+        .and_then(|handle| handle.with_current(|filter| filter.max_level_hint()).ok())
+        .flatten();
+    log::set_max_level(match hint {
+        Some(LevelFilter::OFF) => log::LevelFilter::Off,
+        Some(LevelFilter::ERROR) => log::LevelFilter::Error,
+        Some(LevelFilter::WARN) => log::LevelFilter::Warn,
+        Some(LevelFilter::INFO) => log::LevelFilter::Info,
+        Some(LevelFilter::DEBUG) => log::LevelFilter::Debug,
+        _ => log::LevelFilter::Trace,
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_filter_directives_scope_bare_modules_to_this_crate() {
+        let crate_name = env!("CARGO_CRATE_NAME");
+        assert_eq!(filter_directives("info", "").unwrap(), "info");
+        assert_eq!(
+            filter_directives("WARN", " network=debug, mining::miner=trace ,libp2p_gossipsub=error").unwrap(),
+            format!("warn,{0}::network=debug,{0}::mining::miner=trace,libp2p_gossipsub=error", crate_name)
+        );
+        assert!(env_filter(&filter_directives("info", "network=debug").unwrap()).is_ok());
+
+        assert!(filter_directives("verbose", "").is_err());
+        assert!(filter_directives("info", "network").is_err());
+        assert!(filter_directives("info", "network=loud").is_err());
+    }
+}
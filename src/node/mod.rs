@@ -0,0 +1,13 @@
+//! Node runtime: supervision of the subsystems a running node is made of
+
+pub mod desktop;
+pub mod embedded;
+pub mod logging;
+pub mod selftest;
+pub mod state;
+pub mod supervisor;
+
+pub use desktop::DesktopNotifier;
+pub use embedded::{Node, NodeBuilder};
+pub use selftest::{CheckStatus, SelfTestReport};
+pub use supervisor::{HealthRegistry, RestartPolicy, ShutdownSignal, SubsystemHealth, Supervisor};
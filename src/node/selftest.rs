@@ -0,0 +1,305 @@
+//! Self-tests that catch a broken host before it corrupts node state
+//!
+//! `qtcd doctor` runs every check; node startup runs the abbreviated set that
+//! needs no network and refuses to start when one fails. Each check says what
+//! to do about a failure rather than just that something is wrong.
+
+use crate::config::Config;
+use crate::crypto::hash::Hash256;
+use crate::crypto::keys::PrivateKey;
+use crate::mining::randomx::{RandomXCache, RandomXVM, RANDOMX_FLAG_DEFAULT};
+use crate::{QtcError, Result};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::net::UdpSocket;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// RandomX test vector: (key, input, expected hash)
+const RANDOMX_VECTOR: (&[u8], &[u8], &str) = (
+    b"qtc self-test key",
+    b"qtc self-test input",
+    "adabfba7962368da20f239e726ee21c3653f7ad9ed3f7a8e490e9f230db03cbb",
+);
+
+/// Free space below which the node refuses to start
+const MIN_FREE_DISK: u64 = 1024 * 1024 * 1024;
+/// Free space below which a warning is given
+const LOW_FREE_DISK: u64 = 10 * 1024 * 1024 * 1024;
+
+/// Peers reject blocks timestamped further ahead than this
+const MAX_CLOCK_DRIFT: u64 = 2 * 60 * 60;
+/// Clock offset worth a warning
+const CLOCK_WARN_DRIFT: u64 = 5 * 60;
+
+const NTP_SERVER: &str = "pool.ntp.org:123";
+const NTP_TIMEOUT: Duration = Duration::from_secs(3);
+const NTP_UNIX_OFFSET: u64 = 2_208_988_800; // seconds from 1900 to 1970
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CheckStatus {
+    Pass,
+    Warn,
+    Fail,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CheckResult {
+    pub name: String,
+    pub status: CheckStatus,
+    pub message: String,
+}
+
+impl CheckResult {
+    fn pass(name: &str, message: impl Into<String>) -> Self {
+        Self { name: name.to_string(), status: CheckStatus::Pass, message: message.into() }
+    }
+
+    fn warn(name: &str, message: impl Into<String>) -> Self {
+        Self { name: name.to_string(), status: CheckStatus::Warn, message: message.into() }
+    }
+
+    fn fail(name: &str, message: impl Into<String>) -> Self {
+        Self { name: name.to_string(), status: CheckStatus::Fail, message: message.into() }
+    }
+}
+
+impl fmt::Display for CheckResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let icon = match self.status {
+            CheckStatus::Pass => "✅",
+            CheckStatus::Warn => "⚠️ ",
+            CheckStatus::Fail => "❌",
+        };
+        write!(f, "{} {}: {}", icon, self.name, self.message)
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SelfTestReport {
+    pub checks: Vec<CheckResult>,
+}
+
+impl SelfTestReport {
+    pub fn failures(&self) -> impl Iterator<Item = &CheckResult> {
+        self.checks.iter().filter(|check| check.status == CheckStatus::Fail)
+    }
+
+    pub fn passed(&self) -> bool {
+        self.failures().next().is_none()
+    }
+
+    /// An error naming every failed check, if any failed
+    pub fn into_result(self) -> Result<Self> {
+        if self.passed() {
+            return Ok(self);
+        }
+        let failures: Vec<String> = self.failures().map(|check| format!("{}: {}", check.name, check.message)).collect();
+        Err(QtcError::SelfTest(failures.join("; ")))
+    }
+}
+
+/// Every check; `tip_timestamp` is the chain tip's, when the database could be read
+pub fn run_all(config: &Config, tip_timestamp: Option<u64>) -> SelfTestReport {
+    let mut report = run_startup(config, tip_timestamp);
+    report.checks.push(check_ntp(NTP_SERVER));
+    report
+}
+
+/// The checks that need no network, run before the node starts
+pub fn run_startup(config: &Config, tip_timestamp: Option<u64>) -> SelfTestReport {
+    let data_dir = &config.storage.data_dir;
+    SelfTestReport {
+        checks: vec![
+            check_randomx(),
+            check_signatures(),
+            check_storage(data_dir),
+            check_disk_space(data_dir),
+            check_clock(unix_now(), tip_timestamp),
+        ],
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|elapsed| elapsed.as_secs()).unwrap_or(0)
+}
+
+fn randomx_hash(key: &[u8], input: &[u8]) -> Result<String> {
+    let mut cache = RandomXCache::new(RANDOMX_FLAG_DEFAULT)?;
+    cache.init(key)?;
+    let vm = RandomXVM::new(RANDOMX_FLAG_DEFAULT, Arc::new(cache))?;
+    Ok(vm.calculate_hash(input)?.to_hex())
+}
+
+pub fn check_randomx() -> CheckResult {
+    const NAME: &str = "randomx";
+    let (key, input, expected) = RANDOMX_VECTOR;
+    match randomx_hash(key, input) {
+        Ok(hash) if hash == expected => CheckResult::pass(NAME, "hash matches the test vector"),
+        Ok(hash) => CheckResult::fail(NAME, format!(
+            "hash {} differs from the test vector {}; this build or CPU computes proof of work wrongly, rebuild without custom CPU flags",
+            hash, expected
+        )),
+        Err(e) => CheckResult::fail(NAME, format!("could not hash: {}", e)),
+    }
+}
+
+pub fn check_signatures() -> CheckResult {
+    const NAME: &str = "signatures";
+    let outcome = (|| -> Result<(bool, bool)> {
+        let key = PrivateKey::from_bytes(&[7u8; 32])?;
+        let public_key = key.public_key()?;
+        let message = Hash256::hash(b"qtc self-test message");
+        let signature = key.sign(&message)?;
+        let valid = public_key.verify(&message, &signature)?;
+        let forged = public_key.verify(&Hash256::hash(b"another message"), &signature)?;
+        Ok((valid, forged))
+    })();
+    match outcome {
+        Ok((true, false)) => CheckResult::pass(NAME, "secp256k1 signatures verify"),
+        Ok((valid, _)) => CheckResult::fail(NAME, format!(
+            "secp256k1 {}; the crypto library is broken, reinstall qtcd",
+            if valid { "accepted a signature for the wrong message" } else { "rejected a valid signature" }
+        )),
+        Err(e) => CheckResult::fail(NAME, format!("could not sign: {}", e)),
+    }
+}
+
+/// Write, flush and read back a scratch sled database next to the real one
+pub fn check_storage(data_dir: &Path) -> CheckResult {
+    const NAME: &str = "storage";
+    let scratch = data_dir.join("selftest.db");
+    let outcome = (|| -> Result<bool> {
+        let db = sled::open(&scratch)?;
+        db.insert(b"selftest", b"qtc".as_slice())?;
+        db.flush()?;
+        Ok(db.get(b"selftest")?.is_some_and(|value| value.as_ref() == b"qtc"))
+    })();
+    let _ = std::fs::remove_dir_all(&scratch);
+    match outcome {
+        Ok(true) => CheckResult::pass(NAME, format!("read and write work in {}", data_dir.display())),
+        Ok(false) => CheckResult::fail(NAME, format!(
+            "a value written in {} read back differently; check the disk and filesystem for errors", data_dir.display()
+        )),
+        Err(e) => CheckResult::fail(NAME, format!(
+            "cannot write a database in {}: {}; check its permissions or choose another --data-dir", data_dir.display(), e
+        )),
+    }
+}
+
+fn free_disk_space(path: &Path) -> std::io::Result<u64> {
+    use std::os::unix::ffi::OsStrExt;
+    let path = std::ffi::CString::new(path.as_os_str().as_bytes())
+        .map_err(|_| std::io::Error::new(std::io::ErrorKind::InvalidInput, "path contains a NUL byte"))?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(stat.f_bavail as u64 * stat.f_frsize as u64)
+}
+
+pub fn check_disk_space(data_dir: &Path) -> CheckResult {
+    const NAME: &str = "disk";
+    let gib = |bytes: u64| bytes as f64 / (1024.0 * 1024.0 * 1024.0);
+    match free_disk_space(data_dir) {
+        Ok(free) if free < MIN_FREE_DISK => CheckResult::fail(NAME, format!(
+            "only {:.2} GiB free in {}; free up space or move --data-dir, the database corrupts when writes fail",
+            gib(free), data_dir.display()
+        )),
+        Ok(free) if free < LOW_FREE_DISK => CheckResult::warn(NAME, format!(
+            "{:.1} GiB free in {}, which the chain will outgrow", gib(free), data_dir.display()
+        )),
+        Ok(free) => CheckResult::pass(NAME, format!("{:.1} GiB free", gib(free))),
+        Err(e) => CheckResult::warn(NAME, format!("cannot tell the free space in {}: {}", data_dir.display(), e)),
+    }
+}
+
+/// The clock must not be behind the chain tip by more than peers tolerate
+pub fn check_clock(now: u64, tip_timestamp: Option<u64>) -> CheckResult {
+    const NAME: &str = "clock";
+    match tip_timestamp {
+        Some(tip) if tip > now + MAX_CLOCK_DRIFT => CheckResult::fail(NAME, format!(
+            "the clock reads {} but the chain tip is from {}; set the system clock (enable NTP) before starting",
+            format_time(now), format_time(tip)
+        )),
+        Some(tip) if tip > now + CLOCK_WARN_DRIFT => CheckResult::warn(NAME, format!(
+            "the chain tip is {}s ahead of the clock; the clock may be slow", tip - now
+        )),
+        _ => CheckResult::pass(NAME, format!("system time {}", format_time(now))),
+    }
+}
+
+/// Compare the clock with an NTP server
+pub fn check_ntp(server: &str) -> CheckResult {
+    const NAME: &str = "ntp";
+    match ntp_offset(server) {
+        Ok(offset) if offset.unsigned_abs() > MAX_CLOCK_DRIFT => CheckResult::fail(NAME, format!(
+            "the clock is {}s off {}; blocks would be rejected or mined with bad timestamps, set the system clock",
+            offset, server
+        )),
+        Ok(offset) if offset.unsigned_abs() > CLOCK_WARN_DRIFT => CheckResult::warn(NAME, format!(
+            "the clock is {}s off {}; enable time synchronization", offset, server
+        )),
+        Ok(offset) => CheckResult::pass(NAME, format!("within {}s of {}", offset.unsigned_abs(), server)),
+        Err(e) => CheckResult::warn(NAME, format!("could not reach {}: {}", server, e)),
+    }
+}
+
+/// Seconds the server's clock is ahead of ours (SNTP, RFC 4330)
+fn ntp_offset(server: &str) -> std::io::Result<i64> {
+    let socket = UdpSocket::bind("0.0.0.0:0")?;
+    socket.set_read_timeout(Some(NTP_TIMEOUT))?;
+    socket.connect(server)?;
+
+    let mut request = [0u8; 48];
+    request[0] = 0x23; // no leap warning, version 4, client mode
+    let sent = unix_now();
+    socket.send(&request)?;
+    let mut reply = [0u8; 48];
+    if socket.recv(&mut reply)? < 48 {
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "short NTP reply"));
+    }
+    let received = unix_now();
+
+    let transmit = u32::from_be_bytes([reply[40], reply[41], reply[42], reply[43]]) as u64;
+    if transmit < NTP_UNIX_OFFSET {
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "NTP server sent no time"));
+    }
+    let server_time = (transmit - NTP_UNIX_OFFSET) as i64;
+    Ok(server_time - (sent + received) as i64 / 2)
+}
+
+fn format_time(timestamp: u64) -> String {
+    chrono::DateTime::from_timestamp(timestamp as i64, 0)
+        .map(|time| time.format("%Y-%m-%d %H:%M:%S UTC").to_string())
+        .unwrap_or_else(|| timestamp.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_startup_checks_pass_on_a_healthy_host() {
+        assert_eq!(check_randomx().status, CheckStatus::Pass);
+        assert_eq!(check_signatures().status, CheckStatus::Pass);
+
+        let temp_dir = TempDir::new().unwrap();
+        assert_eq!(check_storage(temp_dir.path()).status, CheckStatus::Pass);
+        assert!(!temp_dir.path().join("selftest.db").exists());
+        assert_ne!(check_disk_space(temp_dir.path()).status, CheckStatus::Fail);
+    }
+
+    #[test]
+    fn test_clock_behind_the_chain_fails() {
+        let now = 1_700_000_000;
+        assert_eq!(check_clock(now, Some(now - 600)).status, CheckStatus::Pass);
+        assert_eq!(check_clock(now, Some(now + 600)).status, CheckStatus::Warn);
+        assert_eq!(check_clock(now, Some(now + MAX_CLOCK_DRIFT + 1)).status, CheckStatus::Fail);
+
+        let report = SelfTestReport { checks: vec![check_clock(now, Some(now + MAX_CLOCK_DRIFT + 1))] };
+        assert!(matches!(report.into_result(), Err(QtcError::SelfTest(_))));
+    }
+}
@@ -0,0 +1,93 @@
+//! Node state kept across restarts: the mempool and the peers we dialed
+//!
+//! Both are written on a clean shutdown and read back on the next start, so a
+//! restart neither drops pending transactions nor starts peer discovery from
+//! scratch. The files are consumed when loaded; a crashed node simply starts
+//! without them.
+
+use crate::core::{Blockchain, MempoolDump};
+use crate::{QtcError, Result};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const MEMPOOL_FILE: &str = "mempool.json";
+pub const PEERS_FILE: &str = "peers.json";
+
+/// Write the mempool next to the database
+pub fn save_mempool(data_dir: &Path, blockchain: &Blockchain) -> Result<usize> {
+    let dump = blockchain.dump_mempool();
+    write_json(&data_dir.join(MEMPOOL_FILE), &dump)?;
+    Ok(dump.entries.len())
+}
+
+/// Return a saved mempool to the pool, revalidating every entry against the current tip
+pub fn load_mempool(data_dir: &Path, blockchain: &Blockchain) -> Result<usize> {
+    let Some(dump) = take_json::<MempoolDump>(&data_dir.join(MEMPOOL_FILE))? else {
+        return Ok(0);
+    };
+    let saved = dump.entries.len();
+    let restored = blockchain.load_mempool(dump)?;
+    log::info!("🗂️ Restored {} of {} saved mempool transaction(s)", restored.len(), saved);
+    Ok(restored.len())
+}
+
+pub fn save_peers(data_dir: &Path, addresses: &[String]) -> Result<()> {
+    write_json(&data_dir.join(PEERS_FILE), &addresses)
+}
+
+pub fn load_peers(data_dir: &Path) -> Result<Vec<String>> {
+    Ok(take_json(&data_dir.join(PEERS_FILE))?.unwrap_or_default())
+}
+
+/// Write through a temporary file, so a shutdown cut short leaves the old file or none
+fn write_json<T: Serialize>(path: &Path, value: &T) -> Result<()> {
+    let temp = temp_path(path);
+    fs::write(&temp, serde_json::to_vec(value)?)?;
+    fs::rename(&temp, path)?;
+    Ok(())
+}
+
+/// Read and remove `path`; an unreadable file is dropped with a warning rather than blocking startup
+fn take_json<T: DeserializeOwned>(path: &Path) -> Result<Option<T>> {
+    let bytes = match fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(QtcError::Io(e)),
+    };
+    fs::remove_file(path)?;
+    match serde_json::from_slice(&bytes) {
+        Ok(value) => Ok(Some(value)),
+        Err(e) => {
+            log::warn!("🗂️ Ignoring unreadable {}: {}", path.display(), e);
+            Ok(None)
+        }
+    }
+}
+
+fn temp_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".tmp");
+    path.with_file_name(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_saved_peers_are_consumed_on_load() {
+        let temp_dir = TempDir::new().unwrap();
+        let peers = vec!["/ip4/10.0.0.1/tcp/8333/p2p/12D3KooWExample".to_string()];
+
+        save_peers(temp_dir.path(), &peers).unwrap();
+        assert!(!temp_dir.path().join("peers.json.tmp").exists());
+        assert_eq!(load_peers(temp_dir.path()).unwrap(), peers);
+        assert!(load_peers(temp_dir.path()).unwrap().is_empty());
+
+        fs::write(temp_dir.path().join(PEERS_FILE), b"not json").unwrap();
+        assert!(load_peers(temp_dir.path()).unwrap().is_empty());
+    }
+}
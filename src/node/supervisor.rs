@@ -0,0 +1,291 @@
+//! Owns the node's long-running subsystems (P2P, APIs, miner, ...), restarts
+//! the ones that crash and stops them in reverse start order on shutdown
+
+use crate::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+
+/// First restart delay; doubles after every crash up to `MAX_BACKOFF`
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// A subsystem that stayed up this long gets its backoff reset
+const STABLE_RUN: Duration = Duration::from_secs(300);
+
+/// How long a subsystem may take to wind down before it is aborted
+const SHUTDOWN_GRACE: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RestartPolicy {
+    /// Restart whenever the task ends, e.g. servers that should never exit
+    Always,
+    /// Restart after errors and panics, but let a clean exit stand
+    OnFailure,
+    Never,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SubsystemHealth {
+    pub name: String,
+    pub state: String, // "running", "restarting", "stopped" or "failed"
+    pub restarts: u32,
+    pub last_error: Option<String>,
+    pub started_at: Option<u64>,
+}
+
+impl SubsystemHealth {
+    pub fn is_healthy(&self) -> bool {
+        self.state == "running"
+    }
+}
+
+/// Shared view of subsystem health, handed to the REST API
+#[derive(Debug, Default)]
+pub struct HealthRegistry {
+    subsystems: Mutex<BTreeMap<String, SubsystemHealth>>,
+}
+
+impl HealthRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn snapshot(&self) -> Vec<SubsystemHealth> {
+        self.lock().values().cloned().collect()
+    }
+
+    pub fn all_healthy(&self) -> bool {
+        self.lock().values().all(SubsystemHealth::is_healthy)
+    }
+
+    fn update(&self, name: &str, update: impl FnOnce(&mut SubsystemHealth)) {
+        let mut subsystems = self.lock();
+        let health = subsystems.entry(name.to_string()).or_insert_with(|| SubsystemHealth {
+            name: name.to_string(),
+            state: "stopped".to_string(),
+            restarts: 0,
+            last_error: None,
+            started_at: None,
+        });
+        update(health);
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, BTreeMap<String, SubsystemHealth>> {
+        match self.subsystems.lock() {
+            Ok(subsystems) => subsystems,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+}
+
+/// Tells a subsystem the node is shutting down, so it can stop cleanly
+#[derive(Debug, Clone)]
+pub struct ShutdownSignal(watch::Receiver<bool>);
+
+impl ShutdownSignal {
+    pub fn is_shutdown(&self) -> bool {
+        *self.0.borrow()
+    }
+
+    /// Resolves once shutdown has been requested
+    pub async fn wait(&mut self) {
+        while !*self.0.borrow_and_update() {
+            if self.0.changed().await.is_err() {
+                return;
+            }
+        }
+    }
+}
+
+struct Subsystem {
+    name: String,
+    stop: watch::Sender<bool>,
+    handle: JoinHandle<()>,
+}
+
+pub struct Supervisor {
+    health: Arc<HealthRegistry>,
+    subsystems: Vec<Subsystem>,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+}
+
+impl Supervisor {
+    pub fn new() -> Self {
+        Self::with_backoff(INITIAL_BACKOFF, MAX_BACKOFF)
+    }
+
+    pub fn with_backoff(initial_backoff: Duration, max_backoff: Duration) -> Self {
+        Self {
+            health: Arc::new(HealthRegistry::new()),
+            subsystems: Vec::new(),
+            initial_backoff,
+            max_backoff: max_backoff.max(initial_backoff),
+        }
+    }
+
+    pub fn health(&self) -> Arc<HealthRegistry> {
+        self.health.clone()
+    }
+
+    /// Names of the spawned subsystems, in start order
+    pub fn subsystems(&self) -> Vec<String> {
+        self.subsystems.iter().map(|subsystem| subsystem.name.clone()).collect()
+    }
+
+    /// Start a subsystem; `factory` builds a fresh run of it for every (re)start
+    pub fn spawn<F, Fut>(&mut self, name: &str, policy: RestartPolicy, mut factory: F)
+    where
+        F: FnMut(ShutdownSignal) -> Fut + Send + 'static,
+        Fut: Future<Output = Result<()>> + Send + 'static,
+    {
+        let (stop, stop_rx) = watch::channel(false);
+        let health = self.health.clone();
+        let task_name = name.to_string();
+        let (initial_backoff, max_backoff) = (self.initial_backoff, self.max_backoff);
+
+        let handle = tokio::spawn(async move {
+            let name = task_name;
+            let mut signal = ShutdownSignal(stop_rx);
+            let mut backoff = initial_backoff;
+
+            while !signal.is_shutdown() {
+                let started = Instant::now();
+                health.update(&name, |h| {
+                    h.state = "running".to_string();
+                    h.started_at = Some(chrono::Utc::now().timestamp() as u64);
+                });
+
+                // A separate task, so a panic is reported instead of taking the supervisor down
+                let mut run = tokio::spawn(factory(signal.clone()));
+                let outcome = tokio::select! {
+                    outcome = &mut run => outcome,
+                    _ = signal.wait() => {
+                        if tokio::time::timeout(SHUTDOWN_GRACE, &mut run).await.is_err() {
+                            log::warn!("⏱️ {} did not stop within {}s, aborting", name, SHUTDOWN_GRACE.as_secs());
+                            run.abort();
+                        }
+                        break;
+                    }
+                };
+                // The subsystem may notice the shutdown before the select above does
+                if signal.is_shutdown() {
+                    break;
+                }
+
+                let error = match outcome {
+                    Ok(Ok(())) => None,
+                    Ok(Err(e)) => Some(e.to_string()),
+                    Err(e) if e.is_panic() => Some("panicked".to_string()),
+                    Err(e) => Some(e.to_string()),
+                };
+                let restart = match policy {
+                    RestartPolicy::Always => true,
+                    RestartPolicy::OnFailure => error.is_some(),
+                    RestartPolicy::Never => false,
+                };
+
+                match &error {
+                    Some(e) => log::error!("💥 {} failed: {}", name, e),
+                    None => log::info!("{} exited", name),
+                }
+                if !restart {
+                    health.update(&name, |h| {
+                        h.state = if error.is_some() { "failed" } else { "stopped" }.to_string();
+                        h.last_error = error.or(h.last_error.take());
+                    });
+                    return;
+                }
+
+                if started.elapsed() >= STABLE_RUN {
+                    backoff = initial_backoff;
+                }
+                health.update(&name, |h| {
+                    h.state = "restarting".to_string();
+                    h.restarts += 1;
+                    if error.is_some() {
+                        h.last_error = error;
+                    }
+                });
+                log::warn!("🔁 Restarting {} in {:?}", name, backoff);
+
+                tokio::select! {
+                    _ = tokio::time::sleep(backoff) => {}
+                    _ = signal.wait() => break,
+                }
+                backoff = (backoff * 2).min(max_backoff);
+            }
+
+            health.update(&name, |h| h.state = "stopped".to_string());
+        });
+
+        self.subsystems.push(Subsystem { name: name.to_string(), stop, handle });
+    }
+
+    /// Stop subsystems newest first, so e.g. the miner and APIs go down before P2P
+    pub async fn shutdown(self) {
+        for subsystem in self.subsystems.into_iter().rev() {
+            log::info!("🛑 Stopping {}", subsystem.name);
+            let _ = subsystem.stop.send(true);
+            if let Err(e) = subsystem.handle.await {
+                log::warn!("Supervisor task for {} ended abnormally: {}", subsystem.name, e);
+            }
+        }
+    }
+}
+
+impl Default for Supervisor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::QtcError;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    #[tokio::test]
+    async fn test_restart_policies_and_ordered_shutdown() {
+        let mut supervisor = Supervisor::with_backoff(Duration::from_millis(10), Duration::from_millis(50));
+        let health = supervisor.health();
+
+        // Fails twice, then stays up until shutdown
+        let attempts = Arc::new(AtomicU32::new(0));
+        let counter = attempts.clone();
+        supervisor.spawn("flaky", RestartPolicy::OnFailure, move |mut shutdown| {
+            let attempt = counter.fetch_add(1, Ordering::SeqCst);
+            async move {
+                if attempt < 2 {
+                    return Err(QtcError::Network("boom".to_string()));
+                }
+                shutdown.wait().await;
+                Ok(())
+            }
+        });
+        supervisor.spawn("panics", RestartPolicy::Never, |_| async { panic!("bad state") });
+        supervisor.spawn("oneshot", RestartPolicy::OnFailure, |_| async { Ok(()) });
+
+        tokio::time::sleep(Duration::from_millis(300)).await;
+
+        let snapshot: BTreeMap<String, SubsystemHealth> = health.snapshot().into_iter()
+            .map(|h| (h.name.clone(), h))
+            .collect();
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+        assert_eq!(snapshot["flaky"].state, "running");
+        assert_eq!(snapshot["flaky"].restarts, 2);
+        assert!(snapshot["flaky"].last_error.as_deref().unwrap().contains("boom"));
+        assert_eq!(snapshot["panics"].state, "failed");
+        assert_eq!(snapshot["oneshot"].state, "stopped");
+        assert!(!health.all_healthy());
+
+        supervisor.shutdown().await;
+        assert!(health.snapshot().iter().all(|h| h.state != "running"));
+    }
+}
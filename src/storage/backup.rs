@@ -0,0 +1,129 @@
+//! Backups of a data directory taken while the node keeps running
+//!
+//! Archiving sled's files as the node writes to them can catch pages half
+//! written. Instead the database is copied tree by tree into a staging
+//! directory while the chain lock keeps blocks from being connected, and the
+//! copy is what gets archived. Before the archive is moved into place it is
+//! unpacked again and opened, and every tree and block is checked against
+//! what was copied, so a backup that reports success can be restored.
+
+use crate::core::Blockchain;
+use crate::storage::lock::LOCK_FILE;
+use crate::{QtcError, Result};
+use flate2::read::GzDecoder;
+use flate2::{Compression, GzBuilder};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+use tar::{Archive, Builder};
+
+/// Top-level directory inside every backup archive
+const ARCHIVE_ROOT: &str = "qtc-data";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackupReport {
+    pub path: PathBuf,
+    pub size: u64, // bytes, compressed
+    pub height: u64,
+    pub trees: usize,
+    pub entries: usize,
+    pub blocks: usize, // blocks read back from the archive
+}
+
+/// Archive `data_dir` to `dest` as a `.tar.gz` holding `qtc-data/`
+pub fn create_backup(blockchain: &RwLock<Blockchain>, data_dir: &Path, dest: &Path) -> Result<BackupReport> {
+    let file_name = dest.file_name()
+        .ok_or_else(|| QtcError::InvalidInput(format!("Invalid backup path {}", dest.display())))?;
+    // Next to the destination, so the finished archive can be renamed into place
+    let work = dest.with_file_name(format!(".{}.partial", file_name.to_string_lossy()));
+    if work.exists() {
+        fs::remove_dir_all(&work)?;
+    }
+    fs::create_dir_all(&work)?;
+
+    let result = build_and_verify(blockchain, data_dir, dest, &work);
+    let _ = fs::remove_dir_all(&work);
+    result
+}
+
+fn build_and_verify(blockchain: &RwLock<Blockchain>, data_dir: &Path, dest: &Path, work: &Path) -> Result<BackupReport> {
+    let staging = work.join(ARCHIVE_ROOT);
+    let (db, height, counts) = {
+        let chain = blockchain.read().unwrap();
+        let db = chain.database().clone();
+        let counts = db.snapshot_to(&staging.join("qtc.db"))?;
+        (db, chain.height, counts)
+    };
+
+    // The node key and other small files beside the database; the control socket and lock are skipped
+    for entry in fs::read_dir(data_dir)? {
+        let entry = entry?;
+        if entry.file_type()?.is_file() && entry.file_name() != LOCK_FILE {
+            fs::copy(entry.path(), staging.join(entry.file_name()))?;
+        }
+    }
+
+    let archive_path = work.join("backup.tar.gz");
+    let encoder = GzBuilder::new()
+        .filename(format!("qtc-backup-{}.tar", chrono::Utc::now().format("%Y%m%d-%H%M%S")))
+        .write(File::create(&archive_path)?, Compression::default());
+    let mut builder = Builder::new(encoder);
+    builder.append_dir_all(ARCHIVE_ROOT, &staging)
+        .map_err(|e| QtcError::Storage(format!("Failed to create backup archive: {}", e)))?;
+    builder.into_inner()
+        .and_then(|encoder| encoder.finish())
+        .and_then(|file| file.sync_all())
+        .map_err(|e| QtcError::Storage(format!("Failed to finalize backup: {}", e)))?;
+
+    let unpacked = work.join("verify");
+    Archive::new(GzDecoder::new(File::open(&archive_path)?)).unpack(&unpacked)
+        .map_err(|e| QtcError::Storage(format!("Backup archive is unreadable: {}", e)))?;
+    let blocks = db.verify_snapshot(&unpacked.join(ARCHIVE_ROOT).join("qtc.db"), &counts)
+        .map_err(|e| QtcError::Storage(format!("Backup failed verification: {}", e)))?;
+
+    fs::rename(&archive_path, dest)?;
+    Ok(BackupReport {
+        path: dest.to_path_buf(),
+        size: fs::metadata(dest)?.len(),
+        height,
+        trees: counts.len(),
+        entries: counts.values().sum(),
+        blocks,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::Database;
+    use std::sync::Arc;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_backup_restores() -> Result<()> {
+        let temp_dir = TempDir::new().unwrap();
+        let data_dir = temp_dir.path().join("data");
+        fs::create_dir_all(&data_dir)?;
+        fs::write(data_dir.join("node_key"), b"key")?;
+        let db = Arc::new(Database::new(data_dir.join("qtc.db"))?);
+        let blockchain = RwLock::new(Blockchain::new(db)?);
+        let genesis = blockchain.read().unwrap().tip;
+
+        let dest = temp_dir.path().join("backup.tar.gz");
+        let report = create_backup(&blockchain, &data_dir, &dest)?;
+        assert_eq!((report.height, report.blocks), (0, 1));
+        assert!(report.entries > 0 && report.size > 0);
+        assert!(!temp_dir.path().join(".backup.tar.gz.partial").exists());
+
+        // Restoring is unpacking the archive and pointing --data-dir at it
+        let restored = temp_dir.path().join("restored");
+        Archive::new(GzDecoder::new(File::open(&dest)?)).unpack(&restored)?;
+        let restored = restored.join(ARCHIVE_ROOT);
+        assert_eq!(fs::read(restored.join("node_key"))?, b"key");
+        let chain = Blockchain::new(Arc::new(Database::new(restored.join("qtc.db"))?))?;
+        assert_eq!(chain.tip, genesis);
+        assert!(chain.get_block_by_height(0)?.is_some());
+        Ok(())
+    }
+}
@@ -0,0 +1,248 @@
+//! Append-only flat file storage for block bodies
+//!
+//! Blocks are written sequentially into `blkNNNNN.dat` files. Each record is
+//! prefixed with a magic value and its length so the files can be scanned
+//! without the sled index. The index itself (hash -> position) lives in sled.
+
+use crate::{QtcError, Result};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Magic bytes prefixed to every block record
+pub const BLOCK_FILE_MAGIC: [u8; 4] = *b"QTCB";
+
+/// Roll over to a new file once the current one reaches this size
+pub const DEFAULT_MAX_FILE_SIZE: u64 = 128 * 1024 * 1024;
+
+const RECORD_HEADER_SIZE: u64 = 8; // magic + u32 length
+
+/// Location of a serialized block inside the flat files
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlockPosition {
+    pub file: u32,
+    pub offset: u64,
+    pub len: u32,
+}
+
+#[derive(Debug)]
+struct WriterState {
+    file: u32,
+    offset: u64,
+}
+
+#[derive(Debug)]
+pub struct BlockFileStore {
+    dir: PathBuf,
+    max_file_size: u64,
+    writer: Mutex<WriterState>,
+}
+
+impl BlockFileStore {
+    pub fn open<P: AsRef<Path>>(dir: P) -> Result<Self> {
+        Self::with_max_file_size(dir, DEFAULT_MAX_FILE_SIZE)
+    }
+
+    pub fn with_max_file_size<P: AsRef<Path>>(dir: P, max_file_size: u64) -> Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)
+            .map_err(|e| QtcError::Storage(format!("Failed to create block file directory: {}", e)))?;
+
+        // Resume appending to the highest numbered file
+        let file = Self::scan_file_numbers(&dir)?.into_iter().max().unwrap_or(0);
+        let offset = match fs::metadata(Self::path_for(&dir, file)) {
+            Ok(meta) => meta.len(),
+            Err(_) => 0,
+        };
+
+        Ok(Self {
+            dir,
+            max_file_size,
+            writer: Mutex::new(WriterState { file, offset }),
+        })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn file_path(&self, file: u32) -> PathBuf {
+        Self::path_for(&self.dir, file)
+    }
+
+    /// Append a serialized block and return where it was written
+    pub fn append(&self, data: &[u8]) -> Result<BlockPosition> {
+        let len = u32::try_from(data.len())
+            .map_err(|_| QtcError::Storage("Block too large for block file".to_string()))?;
+
+        let mut writer = self.writer.lock()
+            .map_err(|_| QtcError::Storage("Block file writer lock poisoned".to_string()))?;
+
+        let record_size = RECORD_HEADER_SIZE + len as u64;
+        if writer.offset > 0 && writer.offset + record_size > self.max_file_size {
+            writer.file += 1;
+            writer.offset = 0;
+        }
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.file_path(writer.file))
+            .map_err(|e| QtcError::Storage(format!("Failed to open block file: {}", e)))?;
+
+        let mut record = Vec::with_capacity(record_size as usize);
+        record.extend_from_slice(&BLOCK_FILE_MAGIC);
+        record.extend_from_slice(&len.to_le_bytes());
+        record.extend_from_slice(data);
+
+        file.write_all(&record)
+            .map_err(|e| QtcError::Storage(format!("Failed to write block file: {}", e)))?;
+        file.flush()
+            .map_err(|e| QtcError::Storage(format!("Failed to flush block file: {}", e)))?;
+
+        let position = BlockPosition {
+            file: writer.file,
+            offset: writer.offset + RECORD_HEADER_SIZE,
+            len,
+        };
+        writer.offset += record_size;
+
+        Ok(position)
+    }
+
+    /// Read the raw block bytes stored at `position`
+    pub fn read(&self, position: &BlockPosition) -> Result<Vec<u8>> {
+        let mut file = File::open(self.file_path(position.file))
+            .map_err(|e| QtcError::Storage(format!("Failed to open block file {}: {}", position.file, e)))?;
+
+        // Check the record header so a bad index entry doesn't hand back garbage
+        file.seek(SeekFrom::Start(position.offset.saturating_sub(RECORD_HEADER_SIZE)))
+            .map_err(|e| QtcError::Storage(format!("Failed to seek block file: {}", e)))?;
+        let mut header = [0u8; RECORD_HEADER_SIZE as usize];
+        file.read_exact(&mut header)
+            .map_err(|e| QtcError::Storage(format!("Failed to read block record header: {}", e)))?;
+
+        let record_len = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+        if header[0..4] != BLOCK_FILE_MAGIC || record_len != position.len {
+            return Err(QtcError::Storage(format!(
+                "Corrupt block record in file {} at offset {}", position.file, position.offset
+            )));
+        }
+
+        let mut data = vec![0u8; position.len as usize];
+        file.read_exact(&mut data)
+            .map_err(|e| QtcError::Storage(format!("Failed to read block data: {}", e)))?;
+
+        Ok(data)
+    }
+
+    /// The file new blocks are appended to
+    pub fn current_file(&self) -> Result<u32> {
+        let writer = self.writer.lock()
+            .map_err(|_| QtcError::Storage("Block file writer lock poisoned".to_string()))?;
+        Ok(writer.file)
+    }
+
+    /// Delete a finished block file, returning how many bytes it freed
+    pub fn remove_file(&self, file: u32) -> Result<u64> {
+        if file == self.current_file()? {
+            return Err(QtcError::Storage(format!("Block file {} is still being written", file)));
+        }
+        let path = self.file_path(file);
+        let len = fs::metadata(&path).map(|meta| meta.len()).unwrap_or(0);
+        fs::remove_file(&path)
+            .map_err(|e| QtcError::Storage(format!("Failed to remove block file {}: {}", file, e)))?;
+        Ok(len)
+    }
+
+    /// Numbers of all block files currently on disk, in ascending order
+    pub fn list_files(&self) -> Result<Vec<u32>> {
+        let mut files = Self::scan_file_numbers(&self.dir)?;
+        files.sort_unstable();
+        Ok(files)
+    }
+
+    /// Total size of all block files in bytes
+    pub fn total_size(&self) -> Result<u64> {
+        let mut total = 0;
+        for file in self.list_files()? {
+            if let Ok(meta) = fs::metadata(self.file_path(file)) {
+                total += meta.len();
+            }
+        }
+        Ok(total)
+    }
+
+    fn path_for(dir: &Path, file: u32) -> PathBuf {
+        dir.join(format!("blk{:05}.dat", file))
+    }
+
+    fn scan_file_numbers(dir: &Path) -> Result<Vec<u32>> {
+        let entries = fs::read_dir(dir)
+            .map_err(|e| QtcError::Storage(format!("Failed to read block file directory: {}", e)))?;
+
+        let mut files = Vec::new();
+        for entry in entries.flatten() {
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            if let Some(num) = name.strip_prefix("blk").and_then(|n| n.strip_suffix(".dat")) {
+                if let Ok(num) = num.parse::<u32>() {
+                    files.push(num);
+                }
+            }
+        }
+
+        Ok(files)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_append_and_read() -> Result<()> {
+        let temp_dir = TempDir::new().unwrap();
+        let store = BlockFileStore::open(temp_dir.path().join("blocks"))?;
+
+        let first = store.append(b"first block")?;
+        let second = store.append(b"second block")?;
+
+        assert_eq!(first.file, 0);
+        assert_eq!(second.file, 0);
+        assert!(second.offset > first.offset);
+        assert_eq!(store.read(&first)?, b"first block");
+        assert_eq!(store.read(&second)?, b"second block");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_rollover_and_reopen() -> Result<()> {
+        let temp_dir = TempDir::new().unwrap();
+        let dir = temp_dir.path().join("blocks");
+
+        let first = {
+            let store = BlockFileStore::with_max_file_size(&dir, 32)?;
+            let first = store.append(&[1u8; 20])?;
+            let second = store.append(&[2u8; 20])?;
+            assert_eq!(second.file, 1);
+            assert_eq!(store.list_files()?, vec![0, 1]);
+            first
+        };
+
+        // Reopening continues after the last record instead of overwriting it
+        let store = BlockFileStore::with_max_file_size(&dir, 32)?;
+        let third = store.append(&[3u8; 20])?;
+        assert_eq!(third.file, 2);
+        assert_eq!(store.read(&first)?, vec![1u8; 20]);
+
+        let bad = BlockPosition { file: 0, offset: first.offset + 1, len: first.len };
+        assert!(store.read(&bad).is_err());
+
+        Ok(())
+    }
+}
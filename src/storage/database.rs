@@ -1,36 +1,544 @@
-use crate::core::{Block, Transaction, UtxoEntry};
+use crate::api::auth::ApiKey;
+use crate::wallet::balance::WalletBalance;
+use crate::wallet::history::WalletHistory;
+use crate::wallet::labels::Contact;
+use crate::wallet::rescan::RescanCursor;
+use crate::core::{Block, BlockFilter, BlockHeader, FeeEstimator, GenesisParams, Transaction, UtxoEntry};
+use crate::mining::pool::{PoolRound, Share, WorkerStats};
+use crate::network::bans::Ban;
+use crate::network::messaging::{Envelope, MessagingIdentity};
+use crate::storage::blockfiles::{BlockFileStore, BlockPosition};
+use crate::storage::encryption::{is_legacy_sealed, KeyEnvelope, StorageCipher, StorageEncryption, StorageSecret};
+use crate::storage::upgrade::{UpgradeKind, UpgradePlan, UpgradeStep, SCHEMA_VERSION};
 use crate::core::blockchain::ChainState;
 use crate::core::transaction::OutPoint;
 use crate::crypto::hash::{Hash256, Hashable};
-use crate::wallet::{WalletInfo, wallet::WalletAddress};
+use crate::wallet::{MultisigWallet, UtxoLockTable, WalletInfo, wallet::{WalletAddress, WalletSettings}};
 use crate::{QtcError, Result};
+use sled::transaction::Transactional;
 use sled::{Db, Tree};
 use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
 use std::path::Path;
 use std::sync::Arc;
 
 // Database tree names (equivalent to column families)
+// Legacy tree that held full blocks before they moved to flat files
 const TREE_BLOCKS: &str = "blocks";
+const TREE_BLOCK_POSITIONS: &str = "block_positions";
 const TREE_BLOCK_INDEX: &str = "block_index";
+const TREE_STALE_BLOCKS: &str = "stale_blocks";
+const TREE_BLOCK_WORK: &str = "block_work";
+const TREE_BLOCKS_BY_WORK: &str = "blocks_by_work";
+const TREE_INVALID_BLOCKS: &str = "invalid_blocks"; // blocks an operator marked invalid
 const TREE_TRANSACTIONS: &str = "transactions";
 const TREE_UTXOS: &str = "utxos";
+const TREE_SPENT_INDEX: &str = "spent_index";
+const TREE_BLOCK_UNDO: &str = "block_undo";
+const TREE_BLOCK_FILTERS: &str = "block_filters"; // compact filters for light clients, by block hash
 const TREE_CHAIN_STATE: &str = "chain_state";
 const TREE_WALLETS: &str = "wallets";
+const TREE_WALLET_BALANCES: &str = "wallet_balances";
+const TREE_WALLET_HISTORY: &str = "wallet_history";
+const TREE_WALLET_SETTINGS: &str = "wallet_settings";
+const TREE_WALLET_RESCANS: &str = "wallet_rescans";
+
+/// Multisig definitions share the wallets tree with the `WalletInfo` records, under keys no wallet name starts with
+const MULTISIG_KEY_PREFIX: &[u8] = b"\0multisig:";
+const TREE_REPLACEABLE_SENDS: &str = "replaceable_sends"; // unconfirmed wallet sends that signal RBF
 const TREE_ADDRESSES: &str = "addresses";
+const TREE_ADDRESS_UTXOS: &str = "address_utxos";
+const TREE_ADDRESS_BALANCES: &str = "address_balances";
+const TREE_ADDRESS_HISTORY: &str = "address_history";
+const TREE_UTXO_LOCKS: &str = "utxo_locks";
+const TREE_RESERVED_ADDRESSES: &str = "reserved_addresses";
+const TREE_ADDRESS_LABELS: &str = "address_labels";
+const TREE_CONTACTS: &str = "contacts";
+const TREE_AUDIT_LOG: &str = "audit_log";
+const TREE_AUDIT_STATE: &str = "audit_state";
+const TREE_API_KEYS: &str = "api_keys";
+const TREE_ENCRYPTION: &str = "encryption";
+const TREE_FEE_ESTIMATES: &str = "fee_estimates";
+const TREE_BLOCK_HEADERS: &str = "block_headers"; // headers of pruned blocks
+const TREE_BLOCK_FILE_HEIGHTS: &str = "block_file_heights"; // highest block height in each block file
+const TREE_POOL_SHARES: &str = "pool_shares";
+const TREE_POOL_WORKERS: &str = "pool_workers";
+const TREE_POOL_ROUNDS: &str = "pool_rounds";
+const TREE_MESSAGING_KEYS: &str = "messaging_keys";
+const TREE_INBOX: &str = "inbox";
+const TREE_PEER_BANS: &str = "peer_bans";
+const TREE_META: &str = "meta"; // never encrypted, so the layout can be checked without a key
+
+const SCHEMA_VERSION_KEY: &[u8] = b"schema_version";
+
+const ENVELOPE_KEY: &[u8] = b"envelope";
+/// Set once every sealed value in the trees is bound to its key
+const KEYED_VALUES_KEY: &[u8] = b"keyed_values";
+
+/// Trees whose values are always sealed in an encrypted data directory
+const ENCRYPTED_TREES: [&str; 15] = [
+    TREE_UTXOS,
+    TREE_SPENT_INDEX,
+    TREE_BLOCK_UNDO,
+    TREE_CHAIN_STATE,
+    TREE_ADDRESS_BALANCES,
+    TREE_WALLETS,
+    TREE_WALLET_BALANCES,
+    TREE_WALLET_HISTORY,
+    TREE_REPLACEABLE_SENDS,
+    TREE_ADDRESSES,
+    TREE_RESERVED_ADDRESSES,
+    TREE_ADDRESS_LABELS,
+    TREE_CONTACTS,
+    TREE_API_KEYS,
+    TREE_MESSAGING_KEYS,
+];
 
 #[derive(Debug, Clone)]
 pub struct Database {
     db: Arc<Db>,
+    block_files: Arc<BlockFileStore>,
+    utxo_locks: Arc<UtxoLockTable>,
+    cipher: Option<Arc<StorageCipher>>,
 }
 
 impl Database {
     pub fn new<P: AsRef<Path>>(path: P) -> Result<Self> {
-        let db = sled::open(path)
+        Self::with_encryption(path, None)
+    }
+    
+    /// Open a data directory that is, or is about to be, encrypted at rest
+    pub fn with_encryption<P: AsRef<Path>>(path: P, encryption: Option<&StorageEncryption>) -> Result<Self> {
+        let database = Self::open_unmigrated(path, encryption)?;
+        database.migrate()?;
+        Ok(database)
+    }
+    
+    fn open_unmigrated<P: AsRef<Path>>(path: P, encryption: Option<&StorageEncryption>) -> Result<Self> {
+        let db = sled::open(path.as_ref())
             .map_err(|e| QtcError::Storage(format!("Failed to open database: {}", e)))?;
         
-        Ok(Self {
+        // Block bodies live in blkNNNNN.dat files next to the sled data
+        let block_files = BlockFileStore::open(path.as_ref().join("blocks"))?;
+        let cipher = Self::unlock(&db, &block_files, encryption)?;
+        
+        let utxo_locks = UtxoLockTable::with_tree(db.open_tree(TREE_UTXO_LOCKS)
+            .map_err(|e| QtcError::Storage(format!("Failed to open tree {}: {}", TREE_UTXO_LOCKS, e)))?)?;
+        
+        let database = Self {
             db: Arc::new(db),
-        })
+            block_files: Arc::new(block_files),
+            utxo_locks: Arc::new(utxo_locks),
+            cipher: cipher.map(Arc::new),
+        };
+        Ok(database)
+    }
+    
+    /// Bring a data directory written by an older version up to `SCHEMA_VERSION`
+    fn migrate(&self) -> Result<()> {
+        if let Some(version) = self.schema_version()?.filter(|version| *version > SCHEMA_VERSION) {
+            return Err(QtcError::Storage(format!(
+                "Data directory was written by a newer qtcd (schema v{}, this build supports v{})", version, SCHEMA_VERSION
+            )));
+        }
+        
+        self.migrate_legacy_blocks()?;
+        self.build_address_utxo_index()?;
+        self.build_address_balances()?;
+        self.set_schema_version(SCHEMA_VERSION)
+    }
+    
+    fn schema_version(&self) -> Result<Option<u32>> {
+        let data = self.get_tree(TREE_META)?.get(SCHEMA_VERSION_KEY)
+            .map_err(|e| QtcError::Storage(format!("Failed to read schema version: {}", e)))?;
+        Ok(data.and_then(|data| data.as_ref().try_into().ok()).map(u32::from_be_bytes))
+    }
+    
+    fn set_schema_version(&self, version: u32) -> Result<()> {
+        if self.schema_version()? == Some(version) {
+            return Ok(());
+        }
+        self.get_tree(TREE_META)?.insert(SCHEMA_VERSION_KEY, &version.to_be_bytes())
+            .map_err(|e| QtcError::Storage(format!("Failed to save schema version: {}", e)))?;
+        self.flush()
+    }
+    
+    /// Work opening the data directory at `path` will do, found without changing anything.
+    /// `addrindex` is whether the node will run with the address history index.
+    pub fn upgrade_plan<P: AsRef<Path>>(path: P, encryption: Option<&StorageEncryption>, addrindex: bool) -> Result<UpgradePlan> {
+        if !path.as_ref().exists() {
+            return Ok(UpgradePlan::default());
+        }
+        
+        let db = sled::open(path.as_ref())
+            .map_err(|e| QtcError::Storage(format!("Failed to open database: {}", e)))?;
+        if let Some(envelope) = Self::load_envelope(&db)?.filter(|envelope| !envelope.pending_trees.is_empty()) {
+            return Ok(UpgradePlan {
+                schema_version: None,
+                steps: vec![UpgradeStep::blocked(format!(
+                    "Encryption stopped with {} tree(s) left; run `qtcd db encrypt` to finish", envelope.pending_trees.len()
+                ))],
+            });
+        }
+        drop(db);
+        
+        Self::open_unmigrated(path, encryption)?.pending_upgrades(addrindex)
+    }
+    
+    fn pending_upgrades(&self, addrindex: bool) -> Result<UpgradePlan> {
+        let mut plan = UpgradePlan { schema_version: self.schema_version()?, steps: Vec::new() };
+        if let Some(version) = plan.schema_version.filter(|version| *version > SCHEMA_VERSION) {
+            plan.steps.push(UpgradeStep::blocked(format!(
+                "Data directory was written by a newer qtcd (schema v{}); run that version or sync a new data directory", version
+            )));
+            return Ok(plan);
+        }
+        
+        let legacy_blocks = self.get_tree(TREE_BLOCKS)?.len() as u64;
+        if legacy_blocks > 0 {
+            plan.steps.push(UpgradeStep::new(UpgradeKind::Migration,
+                format!("Move {} blocks from the database to flat block files", legacy_blocks), legacy_blocks, 2_000));
+        }
+        let utxos = self.get_tree(TREE_UTXOS)?.len() as u64;
+        if utxos > 0 && self.get_tree(TREE_ADDRESS_UTXOS)?.is_empty() {
+            plan.steps.push(UpgradeStep::new(UpgradeKind::Migration,
+                format!("Index {} UTXOs by address", utxos), utxos, 50_000));
+        }
+        if utxos > 0 && self.get_tree(TREE_ADDRESS_BALANCES)?.is_empty() {
+            // Zero-value outputs leave no balance behind, so they alone don't need totalling
+            let funded = self.get_all_utxos()?.iter().filter(|(_, utxo)| utxo.value > 0).count() as u64;
+            if funded > 0 {
+                plan.steps.push(UpgradeStep::new(UpgradeKind::Migration,
+                    format!("Total balances of {} UTXOs by address", funded), funded, 100_000));
+            }
+        }
+        
+        let Some(state) = self.get_chain_state()? else {
+            return Ok(plan);
+        };
+        let blocks = state.height + 1;
+        if state.total_work == 0 {
+            plan.steps.push(UpgradeStep::new(UpgradeKind::Migration,
+                format!("Recompute chain work over {} blocks", blocks), blocks, 2_000));
+        }
+        if self.is_utxo_flush_interrupted()? {
+            plan.steps.push(match self.get_pruned_height()? {
+                Some(_) => UpgradeStep::blocked(
+                    "UTXO set was left half-written and old blocks are pruned; resync into an empty data directory".to_string()
+                ),
+                None => UpgradeStep::new(UpgradeKind::Reindex,
+                    format!("Rebuild the UTXO set from {} blocks", blocks), blocks, 500),
+            });
+        }
+        if addrindex && !self.is_address_history_complete()? {
+            plan.steps.push(UpgradeStep::new(UpgradeKind::Reindex,
+                format!("Build the address history index from {} blocks", blocks), blocks, 1_000));
+        }
+        Ok(plan)
+    }
+    
+    /// The data key for `db`, creating one if encryption is wanted for an empty data directory
+    fn unlock(db: &Db, block_files: &BlockFileStore, encryption: Option<&StorageEncryption>) -> Result<Option<StorageCipher>> {
+        let envelope = Self::load_envelope(db)?;
+        
+        match (envelope, encryption) {
+            (None, None) => Ok(None),
+            (Some(_), None) => Err(QtcError::Storage(
+                "Data directory is encrypted; set storage.encryption in the config to unlock it".to_string()
+            )),
+            (Some(envelope), Some(_)) if !envelope.pending_trees.is_empty() => Err(QtcError::Storage(
+                "Encrypting the data directory was interrupted; run `qtcd db encrypt` again to finish".to_string()
+            )),
+            (Some(envelope), Some(encryption)) => {
+                if envelope.encrypt_blocks != encryption.encrypt_blocks {
+                    log::warn!("🔐 encrypt_blocks is fixed when a data directory is created; blocks here are {}",
+                        if envelope.encrypt_blocks { "encrypted" } else { "not encrypted" });
+                }
+                let cipher = envelope.unlock(&encryption.secret)?;
+                Self::reseal_legacy_values(db, &cipher)?;
+                Ok(Some(cipher))
+            }
+            (None, Some(encryption)) => {
+                if Self::holds_data(db, block_files)? {
+                    return Err(QtcError::Storage(
+                        "Data directory holds unencrypted data; run `qtcd db encrypt` to encrypt it".to_string()
+                    ));
+                }
+                let (envelope, cipher) = KeyEnvelope::create(&encryption.secret, encryption.encrypt_blocks)?;
+                Self::save_envelope(db, &envelope)?;
+                Self::mark_values_keyed(db)?;
+                log::info!("🔐 Created encrypted data directory{}", if envelope.encrypt_blocks { " (blocks included)" } else { "" });
+                Ok(Some(cipher))
+            }
+        }
+    }
+    
+    fn load_envelope(db: &Db) -> Result<Option<KeyEnvelope>> {
+        let tree = db.open_tree(TREE_ENCRYPTION)
+            .map_err(|e| QtcError::Storage(format!("Failed to open tree {}: {}", TREE_ENCRYPTION, e)))?;
+        match tree.get(ENVELOPE_KEY)
+            .map_err(|e| QtcError::Storage(format!("Failed to read encryption key: {}", e)))? {
+            Some(data) => Ok(Some(bincode::deserialize(&data)
+                .map_err(|e| QtcError::Storage(format!("Failed to deserialize encryption key: {}", e)))?)),
+            None => Ok(None),
+        }
+    }
+    
+    fn save_envelope(db: &Db, envelope: &KeyEnvelope) -> Result<()> {
+        let data = bincode::serialize(envelope)
+            .map_err(|e| QtcError::Storage(format!("Failed to serialize encryption key: {}", e)))?;
+        let tree = db.open_tree(TREE_ENCRYPTION)
+            .map_err(|e| QtcError::Storage(format!("Failed to open tree {}: {}", TREE_ENCRYPTION, e)))?;
+        tree.insert(ENVELOPE_KEY, data)
+            .map_err(|e| QtcError::Storage(format!("Failed to save encryption key: {}", e)))?;
+        tree.flush()
+            .map_err(|e| QtcError::Storage(format!("Failed to flush encryption key: {}", e)))?;
+        Ok(())
+    }
+    
+    /// Seal values written before they were bound to their keys again, so a value
+    /// copied under another key can't be opened there; a rerun skips what is done
+    fn reseal_legacy_values(db: &Db, cipher: &StorageCipher) -> Result<()> {
+        let meta_tree = db.open_tree(TREE_ENCRYPTION)
+            .map_err(|e| QtcError::Storage(format!("Failed to open tree {}: {}", TREE_ENCRYPTION, e)))?;
+        if meta_tree.contains_key(KEYED_VALUES_KEY)
+            .map_err(|e| QtcError::Storage(format!("Failed to read encryption state: {}", e)))? {
+            return Ok(());
+        }
+        
+        let mut trees = ENCRYPTED_TREES.to_vec();
+        if cipher.encrypt_blocks() {
+            trees.push(TREE_TRANSACTIONS);
+        }
+        let mut resealed = 0;
+        for name in trees {
+            let tree = db.open_tree(name)
+                .map_err(|e| QtcError::Storage(format!("Failed to open tree {}: {}", name, e)))?;
+            let mut batch = sled::Batch::default();
+            for item in tree.iter() {
+                let (key, value) = item
+                    .map_err(|e| QtcError::Storage(format!("Failed to read {}: {}", name, e)))?;
+                if is_legacy_sealed(&value) {
+                    batch.insert(key.clone(), cipher.seal(name, &key, &cipher.open_legacy(name, &value)?)?);
+                    resealed += 1;
+                }
+            }
+            tree.apply_batch(batch)
+                .map_err(|e| QtcError::Storage(format!("Failed to reseal {}: {}", name, e)))?;
+        }
+        
+        Self::mark_values_keyed(db)?;
+        if resealed > 0 {
+            log::info!("🔐 Bound {} stored value(s) to their keys", resealed);
+        }
+        Ok(())
+    }
+    
+    fn mark_values_keyed(db: &Db) -> Result<()> {
+        let meta_tree = db.open_tree(TREE_ENCRYPTION)
+            .map_err(|e| QtcError::Storage(format!("Failed to open tree {}: {}", TREE_ENCRYPTION, e)))?;
+        meta_tree.insert(KEYED_VALUES_KEY, &[1])
+            .map_err(|e| QtcError::Storage(format!("Failed to save encryption state: {}", e)))?;
+        db.flush()
+            .map_err(|e| QtcError::Storage(format!("Failed to flush database: {}", e)))?;
+        Ok(())
+    }
+    
+    fn holds_data(db: &Db, block_files: &BlockFileStore) -> Result<bool> {
+        if block_files.total_size()? > 0 {
+            return Ok(true);
+        }
+        for name in db.tree_names() {
+            if name.as_ref() == TREE_ENCRYPTION.as_bytes() || name.as_ref() == TREE_META.as_bytes() {
+                continue;
+            }
+            let tree = db.open_tree(&name)
+                .map_err(|e| QtcError::Storage(format!("Failed to open tree: {}", e)))?;
+            if !tree.is_empty() {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+    
+    /// Encrypt an existing data directory in place, one tree at a time; an interrupted
+    /// run picks up where it stopped. Returns the number of values sealed.
+    pub fn encrypt_existing<P: AsRef<Path>>(path: P, encryption: &StorageEncryption) -> Result<usize> {
+        let db = sled::open(path.as_ref())
+            .map_err(|e| QtcError::Storage(format!("Failed to open database: {}", e)))?;
+        let block_files = BlockFileStore::open(path.as_ref().join("blocks"))?;
+        
+        let (mut envelope, cipher) = match Self::load_envelope(&db)? {
+            Some(envelope) if envelope.pending_trees.is_empty() => {
+                return Err(QtcError::Storage("Data directory is already encrypted".to_string()));
+            }
+            Some(envelope) => {
+                log::info!("🔐 Resuming encryption of {} tree(s)", envelope.pending_trees.len());
+                let cipher = envelope.unlock(&encryption.secret)?;
+                (envelope, cipher)
+            }
+            None => {
+                // Block records are appended to flat files, so they can't be rewritten in place
+                if encryption.encrypt_blocks && block_files.total_size()? > 0 {
+                    return Err(QtcError::Storage(
+                        "Existing block files can't be encrypted in place; sync a new data directory with encrypt_blocks instead".to_string()
+                    ));
+                }
+                let (mut envelope, cipher) = KeyEnvelope::create(&encryption.secret, encryption.encrypt_blocks)?;
+                envelope.pending_trees = ENCRYPTED_TREES.iter().map(|tree| tree.to_string()).collect();
+                if encryption.encrypt_blocks {
+                    envelope.pending_trees.push(TREE_TRANSACTIONS.to_string());
+                }
+                Self::save_envelope(&db, &envelope)?;
+                Self::mark_values_keyed(&db)?;
+                (envelope, cipher)
+            }
+        };
+        
+        let meta_tree = db.open_tree(TREE_ENCRYPTION)
+            .map_err(|e| QtcError::Storage(format!("Failed to open tree {}: {}", TREE_ENCRYPTION, e)))?;
+        let mut sealed = 0;
+        while let Some(name) = envelope.pending_trees.first().cloned() {
+            let tree = db.open_tree(&name)
+                .map_err(|e| QtcError::Storage(format!("Failed to open tree {}: {}", name, e)))?;
+            let mut values = Vec::new();
+            for item in tree.iter() {
+                let (key, value) = item
+                    .map_err(|e| QtcError::Storage(format!("Failed to read {}: {}", name, e)))?;
+                values.push((key.clone(), cipher.seal(&name, &key, &value)?));
+            }
+            
+            // Seal the tree and mark it done together, so a crash never seals a value twice
+            envelope.pending_trees.remove(0);
+            let envelope_data = bincode::serialize(&envelope)
+                .map_err(|e| QtcError::Storage(format!("Failed to serialize encryption key: {}", e)))?;
+            (&tree, &meta_tree).transaction(|(tree, meta_tree)| {
+                for (key, value) in &values {
+                    tree.insert(key, value.as_slice())?;
+                }
+                meta_tree.insert(ENVELOPE_KEY, envelope_data.as_slice())?;
+                Ok::<_, sled::transaction::ConflictableTransactionError<()>>(())
+            }).map_err(|e| QtcError::Storage(format!("Failed to encrypt {}: {:?}", name, e)))?;
+            
+            log::info!("🔐 Encrypted {} value(s) in {}", values.len(), name);
+            sealed += values.len();
+        }
+        
+        db.flush()
+            .map_err(|e| QtcError::Storage(format!("Failed to flush database: {}", e)))?;
+        Ok(sealed)
+    }
+    
+    /// Wrap the data key under a new passphrase or key file; stored values are untouched
+    pub fn rotate_encryption_key(&self, secret: &StorageSecret) -> Result<()> {
+        let cipher = self.cipher.as_ref()
+            .ok_or_else(|| QtcError::Storage("Data directory is not encrypted".to_string()))?;
+        let envelope = Self::load_envelope(&self.db)?
+            .ok_or_else(|| QtcError::Storage("Data directory has no encryption key".to_string()))?;
+        
+        Self::save_envelope(&self.db, &envelope.rewrap(cipher, secret)?)
+    }
+    
+    /// Move blocks stored inside sled by older versions into the flat files
+    fn migrate_legacy_blocks(&self) -> Result<()> {
+        let blocks_tree = self.get_tree(TREE_BLOCKS)?;
+        if blocks_tree.is_empty() {
+            return Ok(());
+        }
+        
+        let positions_tree = self.get_tree(TREE_BLOCK_POSITIONS)?;
+        let mut migrated = 0;
+        
+        log::info!("📦 Migrating {} blocks to flat file storage...", blocks_tree.len());
+        
+        for item in blocks_tree.iter() {
+            let (hash, data) = item
+                .map_err(|e| QtcError::Storage(format!("Failed to read legacy block: {}", e)))?;
+            
+            // Skip blocks already copied by an interrupted migration
+            if positions_tree.contains_key(&hash)
+                .map_err(|e| QtcError::Storage(format!("Failed to check block position: {}", e)))? {
+                continue;
+            }
+            
+            let position = self.block_files.append(&self.seal_value(TREE_BLOCKS, &hash, data.to_vec())?)?;
+            self.save_block_position(&positions_tree, &hash, &position)?;
+            migrated += 1;
+        }
+        
+        self.flush()?;
+        blocks_tree.clear()
+            .map_err(|e| QtcError::Storage(format!("Failed to clear legacy block tree: {}", e)))?;
+        self.flush()?;
+        
+        log::info!("✅ Migrated {} blocks to {}", migrated, self.block_files.dir().display());
+        Ok(())
+    }
+    
+    /// Index the UTXO set by address for databases created before the index existed
+    fn build_address_utxo_index(&self) -> Result<()> {
+        let index_tree = self.get_tree(TREE_ADDRESS_UTXOS)?;
+        let utxo_tree = self.get_tree(TREE_UTXOS)?;
+        if !index_tree.is_empty() || utxo_tree.is_empty() {
+            return Ok(());
+        }
+        
+        log::info!("📇 Indexing {} UTXOs by address...", utxo_tree.len());
+        for item in utxo_tree.iter() {
+            let (key, value) = item
+                .map_err(|e| QtcError::Storage(format!("Failed to read UTXO: {}", e)))?;
+            let utxo: UtxoEntry = bincode::deserialize(&self.open_value(TREE_UTXOS, &key, &value)?)
+                .map_err(|e| QtcError::Storage(format!("Failed to deserialize UTXO: {}", e)))?;
+            index_tree.insert(Self::address_utxo_key(&utxo.address, &key), &[])
+                .map_err(|e| QtcError::Storage(format!("Failed to index UTXO by address: {}", e)))?;
+        }
+        
+        // Older versions kept a write-only copy of this index in the addresses tree
+        let address_tree = self.get_tree(TREE_ADDRESSES)?;
+        for item in address_tree.scan_prefix(b"utxo_") {
+            let (key, _) = item
+                .map_err(|e| QtcError::Storage(format!("Failed to read address entry: {}", e)))?;
+            address_tree.remove(key)
+                .map_err(|e| QtcError::Storage(format!("Failed to remove address entry: {}", e)))?;
+        }
+        
+        self.flush()
+    }
+    
+    /// Total the UTXO set per address for databases created before balances were kept
+    fn build_address_balances(&self) -> Result<()> {
+        let balance_tree = self.get_tree(TREE_ADDRESS_BALANCES)?;
+        if !balance_tree.is_empty() {
+            return Ok(());
+        }
+        
+        let mut totals: HashMap<String, u64> = HashMap::new();
+        for (_, utxo) in self.get_all_utxos()? {
+            *totals.entry(utxo.address).or_default() += utxo.value;
+        }
+        totals.retain(|_, total| *total > 0);
+        if totals.is_empty() {
+            return Ok(());
+        }
+        
+        log::info!("💰 Totalling balances for {} addresses...", totals.len());
+        let mut batch = sled::Batch::default();
+        for (address, total) in totals {
+            batch.insert(address.as_bytes(), self.seal_value(TREE_ADDRESS_BALANCES, address.as_bytes(), total.to_be_bytes().to_vec())?);
+        }
+        balance_tree.apply_batch(batch)
+            .map_err(|e| QtcError::Storage(format!("Failed to save address balances: {}", e)))?;
+        self.flush()
+    }
+    
+    pub fn block_files(&self) -> &BlockFileStore {
+        &self.block_files
+    }
+    
+    /// Wallet UTXO locks shared by every handle to this database
+    pub fn utxo_locks(&self) -> &UtxoLockTable {
+        &self.utxo_locks
     }
     
     fn get_tree(&self, tree_name: &str) -> Result<Tree> {
@@ -38,18 +546,40 @@ impl Database {
             .map_err(|e| QtcError::Storage(format!("Failed to open tree {}: {}", tree_name, e)))
     }
     
+    /// Whether values of `tree` (or block file records, for `TREE_BLOCKS`) are sealed
+    fn is_encrypted(&self, tree: &str) -> bool {
+        self.cipher.as_ref().is_some_and(|cipher| {
+            ENCRYPTED_TREES.contains(&tree)
+                || (cipher.encrypt_blocks() && (tree == TREE_BLOCKS || tree == TREE_TRANSACTIONS))
+        })
+    }
+    
+    /// Seal `data` for storage under `key` in `tree` (a block's hash, for block files)
+    fn seal_value(&self, tree: &str, key: &[u8], data: Vec<u8>) -> Result<Vec<u8>> {
+        match &self.cipher {
+            Some(cipher) if self.is_encrypted(tree) => cipher.seal(tree, key, &data),
+            _ => Ok(data),
+        }
+    }
+    
+    fn open_value<'a>(&self, tree: &str, key: &[u8], data: &'a [u8]) -> Result<Cow<'a, [u8]>> {
+        match &self.cipher {
+            // Block files are append-only, so records sealed before values were bound
+            // to their keys stay as they are; read_block_at checks their hash instead
+            Some(cipher) if tree == TREE_BLOCKS && is_legacy_sealed(data) => Ok(Cow::Owned(cipher.open_legacy(tree, data)?)),
+            Some(cipher) if self.is_encrypted(tree) => Ok(Cow::Owned(cipher.open(tree, key, data)?)),
+            _ => Ok(Cow::Borrowed(data)),
+        }
+    }
+    
+    pub fn is_encrypted_at_rest(&self) -> bool {
+        self.cipher.is_some()
+    }
+    
     // Block operations
     pub fn save_block(&self, block: &Block) -> Result<()> {
-        let blocks_tree = self.get_tree(TREE_BLOCKS)?;
         let index_tree = self.get_tree(TREE_BLOCK_INDEX)?;
-        
-        let block_hash = block.hash();
-        let block_data = bincode::serialize(block)
-            .map_err(|e| QtcError::Storage(format!("Failed to serialize block: {}", e)))?;
-        
-        // Save block by hash
-        blocks_tree.insert(block_hash.as_bytes(), block_data)
-            .map_err(|e| QtcError::Storage(format!("Failed to save block: {}", e)))?;
+        let block_hash = self.save_block_data(block)?;
         
         // Save block hash by height
         let height_key = format!("height_{}", block.header.height);
@@ -60,21 +590,85 @@ impl Database {
         Ok(())
     }
     
+    /// Drop the main chain entry at `height` (used when disconnecting the tip)
+    pub fn remove_block_height(&self, height: u64) -> Result<()> {
+        let index_tree = self.get_tree(TREE_BLOCK_INDEX)?;
+        let height_key = format!("height_{}", height);
+        
+        index_tree.remove(height_key.as_bytes())
+            .map_err(|e| QtcError::Storage(format!("Failed to remove block index: {}", e)))?;
+        Ok(())
+    }
+    
+    /// Store a block body without touching the main chain height index
+    pub fn save_block_data(&self, block: &Block) -> Result<Hash256> {
+        let positions_tree = self.get_tree(TREE_BLOCK_POSITIONS)?;
+        let block_hash = block.hash();
+        
+        // Block files are append-only, so don't write the same block twice
+        if !positions_tree.contains_key(block_hash.as_bytes())
+            .map_err(|e| QtcError::Storage(format!("Failed to check block position: {}", e)))? {
+            let block_data = bincode::serialize(block)
+                .map_err(|e| QtcError::Storage(format!("Failed to serialize block: {}", e)))?;
+            
+            let position = self.block_files.append(&self.seal_value(TREE_BLOCKS, block_hash.as_bytes(), block_data)?)?;
+            self.save_block_position(&positions_tree, block_hash.as_bytes(), &position)?;
+            self.raise_block_file_height(position.file, block.header.height)?;
+        }
+        
+        Ok(block_hash)
+    }
+    
     pub fn get_block(&self, hash: &Hash256) -> Result<Option<Block>> {
-        let blocks_tree = self.get_tree(TREE_BLOCKS)?;
+        match self.get_block_position(hash)? {
+            Some(position) => Ok(Some(self.read_block_at(hash.as_bytes(), &position)?)),
+            None => Ok(None),
+        }
+    }
+    
+    pub fn get_block_position(&self, hash: &Hash256) -> Result<Option<BlockPosition>> {
+        let positions_tree = self.get_tree(TREE_BLOCK_POSITIONS)?;
         
-        match blocks_tree.get(hash.as_bytes())
-            .map_err(|e| QtcError::Storage(format!("Failed to get block: {}", e)))? {
+        match positions_tree.get(hash.as_bytes())
+            .map_err(|e| QtcError::Storage(format!("Failed to get block position: {}", e)))? {
             Some(data) => {
-                let block: Block = bincode::deserialize(&data)
-                    .map_err(|e| QtcError::Storage(format!("Failed to deserialize block: {}", e)))?;
-                Ok(Some(block))
+                let position: BlockPosition = bincode::deserialize(&data)
+                    .map_err(|e| QtcError::Storage(format!("Failed to deserialize block position: {}", e)))?;
+                Ok(Some(position))
             }
             None => Ok(None),
         }
     }
     
+    fn save_block_position(&self, positions_tree: &Tree, hash: &[u8], position: &BlockPosition) -> Result<()> {
+        let data = bincode::serialize(position)
+            .map_err(|e| QtcError::Storage(format!("Failed to serialize block position: {}", e)))?;
+        
+        positions_tree.insert(hash, data)
+            .map_err(|e| QtcError::Storage(format!("Failed to save block position: {}", e)))?;
+        Ok(())
+    }
+    
+    /// The block stored at `position`, which must be the one with `hash`
+    fn read_block_at(&self, hash: &[u8], position: &BlockPosition) -> Result<Block> {
+        let data = self.block_files.read(position)?;
+        let block: Block = bincode::deserialize(&self.open_value(TREE_BLOCKS, hash, &data)?)
+            .map_err(|e| QtcError::Storage(format!("Failed to deserialize block: {}", e)))?;
+        if block.hash().as_bytes() != hash {
+            return Err(QtcError::Storage(format!("Block at {:?} is not {}", position, hex::encode(hash))));
+        }
+        Ok(block)
+    }
+    
     pub fn get_block_by_height(&self, height: u64) -> Result<Option<Block>> {
+        match self.get_block_hash_by_height(height)? {
+            Some(block_hash) => self.get_block(&block_hash),
+            None => Ok(None),
+        }
+    }
+    
+    /// Hash of the main chain block at `height`
+    pub fn get_block_hash_by_height(&self, height: u64) -> Result<Option<Hash256>> {
         let index_tree = self.get_tree(TREE_BLOCK_INDEX)?;
         let height_key = format!("height_{}", height);
         
@@ -87,9 +681,7 @@ impl Database {
                 
                 let mut hash_array = [0u8; 32];
                 hash_array.copy_from_slice(&hash_bytes);
-                let block_hash = Hash256::new(hash_array);
-                
-                self.get_block(&block_hash)
+                Ok(Some(Hash256::new(hash_array)))
             }
             None => Ok(None),
         }
@@ -125,6 +717,318 @@ impl Database {
         Ok(blocks)
     }
     
+    /// Header of a block whose body is stored or was pruned
+    pub fn get_block_header(&self, hash: &Hash256) -> Result<Option<BlockHeader>> {
+        if let Some(block) = self.get_block(hash)? {
+            return Ok(Some(block.header));
+        }
+        match self.get_tree(TREE_BLOCK_HEADERS)?.get(hash.as_bytes())
+            .map_err(|e| QtcError::Storage(format!("Failed to get block header: {}", e)))? {
+            Some(data) => Ok(Some(bincode::deserialize(&data)
+                .map_err(|e| QtcError::Storage(format!("Failed to deserialize block header: {}", e)))?)),
+            None => Ok(None),
+        }
+    }
+    
+    pub fn get_block_header_by_height(&self, height: u64) -> Result<Option<BlockHeader>> {
+        match self.get_block_hash_by_height(height)? {
+            Some(block_hash) => self.get_block_header(&block_hash),
+            None => Ok(None),
+        }
+    }
+    
+    /// Record a main chain block known only by its header, as if its body had been pruned
+    pub fn save_pruned_header(&self, header: &BlockHeader) -> Result<Hash256> {
+        let hash = header.hash();
+        let data = bincode::serialize(header)
+            .map_err(|e| QtcError::Storage(format!("Failed to serialize block header: {}", e)))?;
+        self.get_tree(TREE_BLOCK_HEADERS)?.insert(hash.as_bytes(), data)
+            .map_err(|e| QtcError::Storage(format!("Failed to save block header: {}", e)))?;
+        self.get_tree(TREE_BLOCK_INDEX)?.insert(format!("height_{}", header.height).as_bytes(), hash.as_bytes())
+            .map_err(|e| QtcError::Storage(format!("Failed to save block index: {}", e)))?;
+        Ok(hash)
+    }
+    
+    /// Whether the body of a block we know of has been deleted
+    pub fn is_block_pruned(&self, hash: &Hash256) -> Result<bool> {
+        self.get_tree(TREE_BLOCK_HEADERS)?.contains_key(hash.as_bytes())
+            .map_err(|e| QtcError::Storage(format!("Failed to check pruned block: {}", e)))
+    }
+    
+    /// Highest block whose body has been pruned, if any
+    pub fn get_pruned_height(&self) -> Result<Option<u64>> {
+        let height = self.get_tree(TREE_CHAIN_STATE)?.get(b"pruned_height")
+            .map_err(|e| QtcError::Storage(format!("Failed to read pruned height: {}", e)))?;
+        let Some(bytes) = height else {
+            return Ok(None);
+        };
+        let mut height = [0u8; 8];
+        height.copy_from_slice(&self.open_value(TREE_CHAIN_STATE, b"pruned_height", &bytes)?[..8]);
+        Ok(Some(u64::from_be_bytes(height)))
+    }
+    
+    pub(crate) fn set_pruned_height(&self, height: u64) -> Result<()> {
+        self.get_tree(TREE_CHAIN_STATE)?.insert(b"pruned_height", self.seal_value(TREE_CHAIN_STATE, b"pruned_height", height.to_be_bytes().to_vec())?)
+            .map_err(|e| QtcError::Storage(format!("Failed to save pruned height: {}", e)))?;
+        Ok(())
+    }
+    
+    /// Delete the oldest block files until they fit in `target_bytes`, stopping at the first
+    /// file that holds a block at `keep_from` or above. Headers and work entries of main chain
+    /// blocks are kept; their undo data and any stale blocks in the files go. Returns the
+    /// number of files deleted.
+    pub fn prune_block_files(&self, target_bytes: u64, keep_from: u64) -> Result<usize> {
+        let mut size = self.block_files.total_size()?;
+        let current = self.block_files.current_file()?;
+        let mut pruned = 0;
+        
+        for file in self.block_files.list_files()? {
+            if size <= target_bytes || file == current {
+                break;
+            }
+            let max_height = self.block_file_height(file)?;
+            if max_height >= keep_from {
+                break;
+            }
+            
+            self.forget_block_file(file)?;
+            size = size.saturating_sub(self.block_files.remove_file(file)?);
+            if self.get_pruned_height()?.is_none_or(|height| height < max_height) {
+                self.set_pruned_height(max_height)?;
+            }
+            pruned += 1;
+            log::info!("✂️ Pruned block file {} (blocks up to height {})", file, max_height);
+        }
+        
+        if pruned > 0 {
+            self.flush()?;
+        }
+        Ok(pruned)
+    }
+    
+    /// Move the index entries of every block in `file` over to the pruned state
+    fn forget_block_file(&self, file: u32) -> Result<()> {
+        let positions_tree = self.get_tree(TREE_BLOCK_POSITIONS)?;
+        let headers_tree = self.get_tree(TREE_BLOCK_HEADERS)?;
+        
+        for (hash, position) in self.block_positions_in(file)? {
+            let block = self.read_block_at(hash.as_bytes(), &position)?;
+            let main_chain = self.get_block_hash_by_height(block.header.height)? == Some(hash);
+            if main_chain {
+                let header = bincode::serialize(&block.header)
+                    .map_err(|e| QtcError::Storage(format!("Failed to serialize block header: {}", e)))?;
+                headers_tree.insert(hash.as_bytes(), header)
+                    .map_err(|e| QtcError::Storage(format!("Failed to save block header: {}", e)))?;
+            } else {
+                // A stale branch this old can never win, and without its body it couldn't be connected anyway
+                self.remove_stale_block(&hash)?;
+                self.remove_block_work(&hash)?;
+            }
+            positions_tree.remove(hash.as_bytes())
+                .map_err(|e| QtcError::Storage(format!("Failed to remove block position: {}", e)))?;
+            self.delete_block_undo(&hash)?;
+        }
+        
+        self.get_tree(TREE_BLOCK_FILE_HEIGHTS)?.remove(file.to_be_bytes())
+            .map_err(|e| QtcError::Storage(format!("Failed to remove block file height: {}", e)))?;
+        Ok(())
+    }
+    
+    fn block_positions_in(&self, file: u32) -> Result<Vec<(Hash256, BlockPosition)>> {
+        let mut positions = Vec::new();
+        for item in self.get_tree(TREE_BLOCK_POSITIONS)?.iter() {
+            let (hash, value) = item
+                .map_err(|e| QtcError::Storage(format!("Failed to iterate block positions: {}", e)))?;
+            let position: BlockPosition = bincode::deserialize(&value)
+                .map_err(|e| QtcError::Storage(format!("Failed to deserialize block position: {}", e)))?;
+            if position.file == file && hash.len() == 32 {
+                let mut hash_array = [0u8; 32];
+                hash_array.copy_from_slice(&hash);
+                positions.push((Hash256::new(hash_array), position));
+            }
+        }
+        Ok(positions)
+    }
+    
+    /// Highest block height in `file`, worked out from its blocks for files written before this was tracked
+    fn block_file_height(&self, file: u32) -> Result<u64> {
+        let heights_tree = self.get_tree(TREE_BLOCK_FILE_HEIGHTS)?;
+        if let Some(bytes) = heights_tree.get(file.to_be_bytes())
+            .map_err(|e| QtcError::Storage(format!("Failed to read block file height: {}", e)))? {
+            let mut height = [0u8; 8];
+            height.copy_from_slice(&bytes[..8]);
+            return Ok(u64::from_be_bytes(height));
+        }
+        
+        let mut max_height = 0;
+        for (hash, position) in self.block_positions_in(file)? {
+            max_height = max_height.max(self.read_block_at(hash.as_bytes(), &position)?.header.height);
+        }
+        self.raise_block_file_height(file, max_height)?;
+        Ok(max_height)
+    }
+    
+    fn raise_block_file_height(&self, file: u32, height: u64) -> Result<()> {
+        self.get_tree(TREE_BLOCK_FILE_HEIGHTS)?
+            .fetch_and_update(file.to_be_bytes(), |old| {
+                let old = old.map(|bytes| {
+                    let mut stored = [0u8; 8];
+                    stored.copy_from_slice(&bytes[..8]);
+                    u64::from_be_bytes(stored)
+                });
+                Some(old.unwrap_or(0).max(height).to_be_bytes().to_vec())
+            })
+            .map_err(|e| QtcError::Storage(format!("Failed to save block file height: {}", e)))?;
+        Ok(())
+    }
+    
+    // Stale block operations
+    pub fn save_stale_block(&self, block: &Block, fork_height: u64, fork_point: Hash256) -> Result<()> {
+        let stale_tree = self.get_tree(TREE_STALE_BLOCKS)?;
+        let block_hash = self.save_block_data(block)?;
+        
+        let info = StaleBlockInfo {
+            hash: block_hash,
+            height: block.header.height,
+            previous_hash: block.header.previous_hash,
+            fork_height,
+            fork_point,
+            recorded_at: chrono::Utc::now().timestamp() as u64,
+        };
+        let data = bincode::serialize(&info)
+            .map_err(|e| QtcError::Storage(format!("Failed to serialize stale block info: {}", e)))?;
+        
+        stale_tree.insert(block_hash.as_bytes(), data)
+            .map_err(|e| QtcError::Storage(format!("Failed to save stale block: {}", e)))?;
+        
+        log::debug!("💾 Saved stale block {} at height {} (fork at {})", block_hash, block.header.height, fork_height);
+        Ok(())
+    }
+    
+    pub fn get_stale_block_info(&self, hash: &Hash256) -> Result<Option<StaleBlockInfo>> {
+        let stale_tree = self.get_tree(TREE_STALE_BLOCKS)?;
+        
+        match stale_tree.get(hash.as_bytes())
+            .map_err(|e| QtcError::Storage(format!("Failed to get stale block: {}", e)))? {
+            Some(data) => {
+                let info: StaleBlockInfo = bincode::deserialize(&data)
+                    .map_err(|e| QtcError::Storage(format!("Failed to deserialize stale block info: {}", e)))?;
+                Ok(Some(info))
+            }
+            None => Ok(None),
+        }
+    }
+    
+    /// All stale blocks, highest first
+    pub fn get_stale_blocks(&self) -> Result<Vec<StaleBlockInfo>> {
+        let stale_tree = self.get_tree(TREE_STALE_BLOCKS)?;
+        let mut stale = Vec::new();
+        
+        for item in stale_tree.iter() {
+            match item {
+                Ok((_, value)) => {
+                    if let Ok(info) = bincode::deserialize::<StaleBlockInfo>(&value) {
+                        stale.push(info);
+                    }
+                }
+                Err(e) => {
+                    log::warn!("Error iterating stale blocks: {}", e);
+                    break;
+                }
+            }
+        }
+        
+        stale.sort_by(|a, b| b.height.cmp(&a.height).then(b.recorded_at.cmp(&a.recorded_at)));
+        Ok(stale)
+    }
+    
+    pub fn remove_stale_block(&self, hash: &Hash256) -> Result<()> {
+        let stale_tree = self.get_tree(TREE_STALE_BLOCKS)?;
+        
+        stale_tree.remove(hash.as_bytes())
+            .map_err(|e| QtcError::Storage(format!("Failed to remove stale block: {}", e)))?;
+        Ok(())
+    }
+    
+    // Blocks marked invalid by hand, with when they were marked
+    pub fn save_invalid_block(&self, hash: &Hash256) -> Result<()> {
+        let marked_at = chrono::Utc::now().timestamp() as u64;
+        self.get_tree(TREE_INVALID_BLOCKS)?.insert(hash.as_bytes(), &marked_at.to_be_bytes())
+            .map_err(|e| QtcError::Storage(format!("Failed to save invalid block: {}", e)))?;
+        Ok(())
+    }
+    
+    pub fn is_block_invalid(&self, hash: &Hash256) -> Result<bool> {
+        self.get_tree(TREE_INVALID_BLOCKS)?.contains_key(hash.as_bytes())
+            .map_err(|e| QtcError::Storage(format!("Failed to get invalid block: {}", e)))
+    }
+    
+    pub fn remove_invalid_block(&self, hash: &Hash256) -> Result<()> {
+        self.get_tree(TREE_INVALID_BLOCKS)?.remove(hash.as_bytes())
+            .map_err(|e| QtcError::Storage(format!("Failed to remove invalid block: {}", e)))?;
+        Ok(())
+    }
+    
+    // Fork tracking: cumulative work of every known block, main chain or not
+    pub fn save_block_work(&self, entry: &BlockWorkEntry) -> Result<()> {
+        let work_tree = self.get_tree(TREE_BLOCK_WORK)?;
+        let by_work_tree = self.get_tree(TREE_BLOCKS_BY_WORK)?;
+        let data = bincode::serialize(entry)
+            .map_err(|e| QtcError::Storage(format!("Failed to serialize block work: {}", e)))?;
+        
+        work_tree.insert(entry.hash.as_bytes(), data)
+            .map_err(|e| QtcError::Storage(format!("Failed to save block work: {}", e)))?;
+        by_work_tree.insert(Self::work_key(entry), entry.hash.as_bytes())
+            .map_err(|e| QtcError::Storage(format!("Failed to save block work index: {}", e)))?;
+        Ok(())
+    }
+    
+    pub fn get_block_work(&self, hash: &Hash256) -> Result<Option<BlockWorkEntry>> {
+        let work_tree = self.get_tree(TREE_BLOCK_WORK)?;
+        
+        match work_tree.get(hash.as_bytes())
+            .map_err(|e| QtcError::Storage(format!("Failed to get block work: {}", e)))? {
+            Some(data) => {
+                let entry: BlockWorkEntry = bincode::deserialize(&data)
+                    .map_err(|e| QtcError::Storage(format!("Failed to deserialize block work: {}", e)))?;
+                Ok(Some(entry))
+            }
+            None => Ok(None),
+        }
+    }
+    
+    /// Forget a block's work, e.g. after its branch failed to connect
+    pub fn remove_block_work(&self, hash: &Hash256) -> Result<()> {
+        if let Some(entry) = self.get_block_work(hash)? {
+            self.get_tree(TREE_BLOCKS_BY_WORK)?.remove(Self::work_key(&entry))
+                .map_err(|e| QtcError::Storage(format!("Failed to remove block work index: {}", e)))?;
+            self.get_tree(TREE_BLOCK_WORK)?.remove(hash.as_bytes())
+                .map_err(|e| QtcError::Storage(format!("Failed to remove block work: {}", e)))?;
+        }
+        Ok(())
+    }
+    
+    /// The known block with the most cumulative work
+    pub fn get_most_work_block(&self) -> Result<Option<BlockWorkEntry>> {
+        let by_work_tree = self.get_tree(TREE_BLOCKS_BY_WORK)?;
+        
+        match by_work_tree.last()
+            .map_err(|e| QtcError::Storage(format!("Failed to read block work index: {}", e)))? {
+            Some((_, hash_bytes)) => match Hash256::from_slice(&hash_bytes) {
+                Some(hash) => self.get_block_work(&hash),
+                None => Err(QtcError::Storage("Invalid block hash length".to_string())),
+            },
+            None => Ok(None),
+        }
+    }
+    
+    /// Big-endian work first so sled keeps the index sorted by work
+    fn work_key(entry: &BlockWorkEntry) -> Vec<u8> {
+        let mut key = entry.chain_work.to_be_bytes().to_vec();
+        key.extend_from_slice(entry.hash.as_bytes());
+        key
+    }
+    
     // Transaction operations
     pub fn save_transaction(&self, tx: &Transaction) -> Result<()> {
         let tx_tree = self.get_tree(TREE_TRANSACTIONS)?;
@@ -132,7 +1036,7 @@ impl Database {
         let tx_data = bincode::serialize(tx)
             .map_err(|e| QtcError::Storage(format!("Failed to serialize transaction: {}", e)))?;
         
-        tx_tree.insert(tx_hash.as_bytes(), tx_data)
+        tx_tree.insert(tx_hash.as_bytes(), self.seal_value(TREE_TRANSACTIONS, tx_hash.as_bytes(), tx_data)?)
             .map_err(|e| QtcError::Storage(format!("Failed to save transaction: {}", e)))?;
         
         log::debug!("💾 Saved transaction {}", tx_hash);
@@ -145,7 +1049,7 @@ impl Database {
         match tx_tree.get(hash.as_bytes())
             .map_err(|e| QtcError::Storage(format!("Failed to get transaction: {}", e)))? {
             Some(data) => {
-                let tx: Transaction = bincode::deserialize(&data)
+                let tx: Transaction = bincode::deserialize(&self.open_value(TREE_TRANSACTIONS, hash.as_bytes(), &data)?)
                     .map_err(|e| QtcError::Storage(format!("Failed to deserialize transaction: {}", e)))?;
                 Ok(Some(tx))
             }
@@ -160,18 +1064,23 @@ impl Database {
         let data = bincode::serialize(utxo)
             .map_err(|e| QtcError::Storage(format!("Failed to serialize UTXO: {}", e)))?;
         
-        utxo_tree.insert(&key, data)
+        let replaced = utxo_tree.insert(&key, self.seal_value(TREE_UTXOS, &key, data)?)
             .map_err(|e| QtcError::Storage(format!("Failed to save UTXO: {}", e)))?;
+        if let Some(old) = replaced {
+            let old: UtxoEntry = bincode::deserialize(&self.open_value(TREE_UTXOS, &key, &old)?)
+                .map_err(|e| QtcError::Storage(format!("Failed to deserialize UTXO: {}", e)))?;
+            self.adjust_address_balance(&old.address, -(old.value as i128))?;
+        }
+        self.adjust_address_balance(&utxo.address, utxo.value as i128)?;
         
-        // INDEX BY ADDRESS FOR BLOCKCHAIN EXPLORER
-        let address_tree = self.get_tree(TREE_ADDRESSES)?;
-        let address_key = format!("utxo_{}_{}", utxo.address, self.outpoint_to_string(outpoint));
-        address_tree.insert(address_key.as_bytes(), b"1")
+        let index_tree = self.get_tree(TREE_ADDRESS_UTXOS)?;
+        index_tree.insert(Self::address_utxo_key(&utxo.address, &key), &[])
             .map_err(|e| QtcError::Storage(format!("Failed to index UTXO by address: {}", e)))?;
         
         // TRACK ADDRESS IN GLOBAL ADDRESS LIST
+        let address_tree = self.get_tree(TREE_ADDRESSES)?;
         let addr_list_key = format!("address_{}", utxo.address);
-        address_tree.insert(addr_list_key.as_bytes(), b"1")
+        address_tree.insert(addr_list_key.as_bytes(), self.seal_value(TREE_ADDRESSES, addr_list_key.as_bytes(), b"1".to_vec())?)
             .map_err(|e| QtcError::Storage(format!("Failed to track address: {}", e)))?;
         
         log::debug!("💾 Saved UTXO {}:{}", hex::encode(outpoint.txid.as_bytes()), outpoint.vout);
@@ -185,7 +1094,7 @@ impl Database {
         match utxo_tree.get(&key)
             .map_err(|e| QtcError::Storage(format!("Failed to get UTXO: {}", e)))? {
             Some(data) => {
-                let utxo: UtxoEntry = bincode::deserialize(&data)
+                let utxo: UtxoEntry = bincode::deserialize(&self.open_value(TREE_UTXOS, &key, &data)?)
                     .map_err(|e| QtcError::Storage(format!("Failed to deserialize UTXO: {}", e)))?;
                 Ok(Some(utxo))
             }
@@ -197,63 +1106,532 @@ impl Database {
         let utxo_tree = self.get_tree(TREE_UTXOS)?;
         let key = self.outpoint_to_key(outpoint);
         
-        utxo_tree.remove(&key)
+        let removed = utxo_tree.remove(&key)
             .map_err(|e| QtcError::Storage(format!("Failed to delete UTXO: {}", e)))?;
         
+        if let Some(data) = removed {
+            let utxo: UtxoEntry = bincode::deserialize(&self.open_value(TREE_UTXOS, &key, &data)?)
+                .map_err(|e| QtcError::Storage(format!("Failed to deserialize UTXO: {}", e)))?;
+            self.get_tree(TREE_ADDRESS_UTXOS)?
+                .remove(Self::address_utxo_key(&utxo.address, &key))
+                .map_err(|e| QtcError::Storage(format!("Failed to unindex UTXO: {}", e)))?;
+            self.adjust_address_balance(&utxo.address, -(utxo.value as i128))?;
+        }
+        
         log::debug!("🗑️ Deleted UTXO {}:{}", hex::encode(outpoint.txid.as_bytes()), outpoint.vout);
         Ok(())
     }
     
+    /// Sum of the address's unspent outputs as of the last UTXO flush
+    pub fn get_address_balance(&self, address: &str) -> Result<u64> {
+        let balance = self.get_tree(TREE_ADDRESS_BALANCES)?.get(address.as_bytes())
+            .map_err(|e| QtcError::Storage(format!("Failed to get address balance: {}", e)))?;
+        let Some(bytes) = balance else {
+            return Ok(0);
+        };
+        let mut total = [0u8; 8];
+        total.copy_from_slice(&self.open_value(TREE_ADDRESS_BALANCES, address.as_bytes(), &bytes)?[..8]);
+        Ok(u64::from_be_bytes(total))
+    }
+    
+    fn adjust_address_balance(&self, address: &str, delta: i128) -> Result<()> {
+        let balance_tree = self.get_tree(TREE_ADDRESS_BALANCES)?;
+        let total = (self.get_address_balance(address)? as i128 + delta).clamp(0, u64::MAX as i128) as u64;
+        let result = if total == 0 {
+            balance_tree.remove(address.as_bytes()).map(|_| ())
+        } else {
+            balance_tree.insert(address.as_bytes(), self.seal_value(TREE_ADDRESS_BALANCES, address.as_bytes(), total.to_be_bytes().to_vec())?).map(|_| ())
+        };
+        result.map_err(|e| QtcError::Storage(format!("Failed to save address balance: {}", e)))
+    }
+    
+    /// Drop every unspent output and what is derived from them, ahead of replaying the chain
+    pub fn clear_utxo_set(&self) -> Result<()> {
+        for tree in [TREE_UTXOS, TREE_ADDRESS_UTXOS, TREE_ADDRESS_BALANCES] {
+            self.get_tree(tree)?.clear()
+                .map_err(|e| QtcError::Storage(format!("Failed to clear {}: {}", tree, e)))?;
+        }
+        Ok(())
+    }
+    
+    /// The block the stored UTXO set was last flushed at
+    pub fn get_utxo_tip(&self) -> Result<Option<Hash256>> {
+        let tip = self.get_tree(TREE_CHAIN_STATE)?.get(b"utxo_tip")
+            .map_err(|e| QtcError::Storage(format!("Failed to read UTXO tip: {}", e)))?;
+        let Some(bytes) = tip else {
+            return Ok(None);
+        };
+        let mut hash = [0u8; 32];
+        hash.copy_from_slice(&self.open_value(TREE_CHAIN_STATE, b"utxo_tip", &bytes)?[..32]);
+        Ok(Some(Hash256::new(hash)))
+    }
+    
+    pub fn set_utxo_tip(&self, tip: &Hash256) -> Result<()> {
+        self.get_tree(TREE_CHAIN_STATE)?.insert(b"utxo_tip", self.seal_value(TREE_CHAIN_STATE, b"utxo_tip", tip.as_bytes().to_vec())?)
+            .map_err(|e| QtcError::Storage(format!("Failed to save UTXO tip: {}", e)))?;
+        Ok(())
+    }
+    
+    /// Set while a UTXO flush is being written; still set on open means it was cut short
+    pub fn is_utxo_flush_interrupted(&self) -> Result<bool> {
+        self.get_tree(TREE_CHAIN_STATE)?.contains_key(b"utxo_flushing")
+            .map_err(|e| QtcError::Storage(format!("Failed to read UTXO flush state: {}", e)))
+    }
+    
+    pub fn set_utxo_flushing(&self, flushing: bool) -> Result<()> {
+        let state_tree = self.get_tree(TREE_CHAIN_STATE)?;
+        let result = if flushing {
+            state_tree.insert(b"utxo_flushing", self.seal_value(TREE_CHAIN_STATE, b"utxo_flushing", vec![1])?).map(|_| ())
+        } else {
+            state_tree.remove(b"utxo_flushing").map(|_| ())
+        };
+        result.map_err(|e| QtcError::Storage(format!("Failed to save UTXO flush state: {}", e)))
+    }
+    
+    // Undo data: outputs spent by each connected block
+    pub fn save_block_undo(&self, block_hash: &Hash256, spent: &[(OutPoint, UtxoEntry)]) -> Result<()> {
+        let undo_tree = self.get_tree(TREE_BLOCK_UNDO)?;
+        let data = bincode::serialize(spent)
+            .map_err(|e| QtcError::Storage(format!("Failed to serialize block undo: {}", e)))?;
+        
+        undo_tree.insert(block_hash.as_bytes(), self.seal_value(TREE_BLOCK_UNDO, block_hash.as_bytes(), data)?)
+            .map_err(|e| QtcError::Storage(format!("Failed to save block undo: {}", e)))?;
+        Ok(())
+    }
+    
+    pub fn get_block_undo(&self, block_hash: &Hash256) -> Result<Option<Vec<(OutPoint, UtxoEntry)>>> {
+        let undo_tree = self.get_tree(TREE_BLOCK_UNDO)?;
+        
+        match undo_tree.get(block_hash.as_bytes())
+            .map_err(|e| QtcError::Storage(format!("Failed to get block undo: {}", e)))? {
+            Some(data) => {
+                let spent = bincode::deserialize(&self.open_value(TREE_BLOCK_UNDO, block_hash.as_bytes(), &data)?)
+                    .map_err(|e| QtcError::Storage(format!("Failed to deserialize block undo: {}", e)))?;
+                Ok(Some(spent))
+            }
+            None => Ok(None),
+        }
+    }
+    
+    pub fn delete_block_undo(&self, block_hash: &Hash256) -> Result<()> {
+        let undo_tree = self.get_tree(TREE_BLOCK_UNDO)?;
+        
+        undo_tree.remove(block_hash.as_bytes())
+            .map_err(|e| QtcError::Storage(format!("Failed to delete block undo: {}", e)))?;
+        Ok(())
+    }
+    
+    // Block filter operations
+    pub fn save_block_filter(&self, filter: &BlockFilter) -> Result<()> {
+        let data = bincode::serialize(filter)
+            .map_err(|e| QtcError::Storage(format!("Failed to serialize block filter: {}", e)))?;
+        self.get_tree(TREE_BLOCK_FILTERS)?.insert(filter.block_hash.as_bytes(), data)
+            .map_err(|e| QtcError::Storage(format!("Failed to save block filter: {}", e)))?;
+        Ok(())
+    }
+    
+    pub fn get_block_filter(&self, block_hash: &Hash256) -> Result<Option<BlockFilter>> {
+        let data = self.get_tree(TREE_BLOCK_FILTERS)?.get(block_hash.as_bytes())
+            .map_err(|e| QtcError::Storage(format!("Failed to get block filter: {}", e)))?;
+        data.map(|data| bincode::deserialize(&data)
+            .map_err(|e| QtcError::Storage(format!("Failed to deserialize block filter: {}", e))))
+            .transpose()
+    }
+    
+    pub fn has_block_filter(&self, block_hash: &Hash256) -> Result<bool> {
+        self.get_tree(TREE_BLOCK_FILTERS)?.contains_key(block_hash.as_bytes())
+            .map_err(|e| QtcError::Storage(format!("Failed to read block filters: {}", e)))
+    }
+    
+    // Spent index operations
+    pub fn save_spent_output(&self, outpoint: &OutPoint, spent: &SpentOutput) -> Result<()> {
+        let spent_tree = self.get_tree(TREE_SPENT_INDEX)?;
+        let key = self.outpoint_to_key(outpoint);
+        let data = bincode::serialize(spent)
+            .map_err(|e| QtcError::Storage(format!("Failed to serialize spent output: {}", e)))?;
+        
+        spent_tree.insert(&key, self.seal_value(TREE_SPENT_INDEX, &key, data)?)
+            .map_err(|e| QtcError::Storage(format!("Failed to save spent output: {}", e)))?;
+        Ok(())
+    }
+    
+    pub fn get_spent_output(&self, outpoint: &OutPoint) -> Result<Option<SpentOutput>> {
+        let spent_tree = self.get_tree(TREE_SPENT_INDEX)?;
+        let key = self.outpoint_to_key(outpoint);
+        
+        match spent_tree.get(&key)
+            .map_err(|e| QtcError::Storage(format!("Failed to get spent output: {}", e)))? {
+            Some(data) => {
+                let spent: SpentOutput = bincode::deserialize(&self.open_value(TREE_SPENT_INDEX, &key, &data)?)
+                    .map_err(|e| QtcError::Storage(format!("Failed to deserialize spent output: {}", e)))?;
+                Ok(Some(spent))
+            }
+            None => Ok(None),
+        }
+    }
+    
+    pub fn delete_spent_output(&self, outpoint: &OutPoint) -> Result<()> {
+        let spent_tree = self.get_tree(TREE_SPENT_INDEX)?;
+        let key = self.outpoint_to_key(outpoint);
+        
+        spent_tree.remove(&key)
+            .map_err(|e| QtcError::Storage(format!("Failed to delete spent output: {}", e)))?;
+        Ok(())
+    }
+    
     pub fn get_utxos_for_address(&self, address: &str) -> Result<Vec<(OutPoint, UtxoEntry)>> {
-        let utxo_tree = self.get_tree(TREE_UTXOS)?;
+        let index_tree = self.get_tree(TREE_ADDRESS_UTXOS)?;
+        let prefix = Self::address_prefix(address);
         let mut utxos = Vec::new();
         
-        for item in utxo_tree.iter() {
-            match item {
-                Ok((key, value)) => {
-                    if let Ok(utxo) = bincode::deserialize::<UtxoEntry>(&value) {
-                        if utxo.address == address {
-                            if let Ok(outpoint) = self.key_to_outpoint(&key) {
-                                utxos.push((outpoint, utxo));
-                            }
-                        }
-                    }
-                }
-                Err(e) => {
-                    log::warn!("Error iterating UTXOs: {}", e);
-                    break;
-                }
+        for item in index_tree.scan_prefix(&prefix) {
+            let (key, _) = item
+                .map_err(|e| QtcError::Storage(format!("Failed to read address index: {}", e)))?;
+            let outpoint = self.key_to_outpoint(&key[prefix.len()..])?;
+            // The index and the UTXO tree are written separately, so trust the UTXO tree
+            if let Some(utxo) = self.get_utxo(&outpoint)? {
+                utxos.push((outpoint, utxo));
             }
         }
         
         Ok(utxos)
     }
     
+    // Address history: address -> (height, txid) for every transaction paying or spending from it
+    pub fn save_address_history(&self, height: u64, entries: &[(String, Hash256)]) -> Result<()> {
+        let history_tree = self.get_tree(TREE_ADDRESS_HISTORY)?;
+        for (address, txid) in entries {
+            history_tree.insert(Self::address_history_key(address, height, txid), &[])
+                .map_err(|e| QtcError::Storage(format!("Failed to save address history: {}", e)))?;
+        }
+        Ok(())
+    }
+    
+    pub fn delete_address_history(&self, height: u64, entries: &[(String, Hash256)]) -> Result<()> {
+        let history_tree = self.get_tree(TREE_ADDRESS_HISTORY)?;
+        for (address, txid) in entries {
+            history_tree.remove(Self::address_history_key(address, height, txid))
+                .map_err(|e| QtcError::Storage(format!("Failed to delete address history: {}", e)))?;
+        }
+        Ok(())
+    }
+    
+    /// Newest first
+    pub fn get_address_history(&self, address: &str, limit: usize) -> Result<Vec<(u64, Hash256)>> {
+        let history_tree = self.get_tree(TREE_ADDRESS_HISTORY)?;
+        let prefix = Self::address_prefix(address);
+        let mut history = Vec::new();
+        
+        for item in history_tree.scan_prefix(&prefix).rev().take(limit) {
+            let (key, _) = item
+                .map_err(|e| QtcError::Storage(format!("Failed to read address history: {}", e)))?;
+            let rest = &key[prefix.len()..];
+            if rest.len() != 40 {
+                return Err(QtcError::Storage("Invalid address history key length".to_string()));
+            }
+            let mut height_bytes = [0u8; 8];
+            height_bytes.copy_from_slice(&rest[..8]);
+            let mut txid_bytes = [0u8; 32];
+            txid_bytes.copy_from_slice(&rest[8..]);
+            history.push((u64::from_be_bytes(height_bytes), Hash256::new(txid_bytes)));
+        }
+        
+        Ok(history)
+    }
+    
+    pub fn clear_address_history(&self) -> Result<()> {
+        self.get_tree(TREE_ADDRESS_HISTORY)?.clear()
+            .map_err(|e| QtcError::Storage(format!("Failed to clear address history: {}", e)))
+    }
+    
+    /// Whether the history index covers every connected block
+    pub fn is_address_history_complete(&self) -> Result<bool> {
+        self.get_tree(TREE_CHAIN_STATE)?.contains_key(b"address_history")
+            .map_err(|e| QtcError::Storage(format!("Failed to read address history state: {}", e)))
+    }
+    
+    pub fn set_address_history_complete(&self, complete: bool) -> Result<()> {
+        let state_tree = self.get_tree(TREE_CHAIN_STATE)?;
+        let result = if complete {
+            state_tree.insert(b"address_history", self.seal_value(TREE_CHAIN_STATE, b"address_history", vec![1])?).map(|_| ())
+        } else {
+            state_tree.remove(b"address_history").map(|_| ())
+        };
+        result.map_err(|e| QtcError::Storage(format!("Failed to save address history state: {}", e)))
+    }
+    
     // Chain state operations
     pub fn save_chain_state(&self, state: &ChainState) -> Result<()> {
         let state_tree = self.get_tree(TREE_CHAIN_STATE)?;
         let data = bincode::serialize(state)
             .map_err(|e| QtcError::Storage(format!("Failed to serialize chain state: {}", e)))?;
         
-        state_tree.insert(b"current", data)
+        state_tree.insert(b"current", self.seal_value(TREE_CHAIN_STATE, b"current", data)?)
             .map_err(|e| QtcError::Storage(format!("Failed to save chain state: {}", e)))?;
         
         log::debug!("💾 Saved chain state at height {}", state.height);
         Ok(())
     }
     
-    pub fn get_chain_state(&self) -> Result<Option<ChainState>> {
-        let state_tree = self.get_tree(TREE_CHAIN_STATE)?;
-        
-        match state_tree.get(b"current")
-            .map_err(|e| QtcError::Storage(format!("Failed to get chain state: {}", e)))? {
-            Some(data) => {
-                let state: ChainState = bincode::deserialize(&data)
-                    .map_err(|e| QtcError::Storage(format!("Failed to deserialize chain state: {}", e)))?;
-                Ok(Some(state))
-            }
-            None => Ok(None),
-        }
+    pub fn get_chain_state(&self) -> Result<Option<ChainState>> {
+        let state_tree = self.get_tree(TREE_CHAIN_STATE)?;
+        
+        match state_tree.get(b"current")
+            .map_err(|e| QtcError::Storage(format!("Failed to get chain state: {}", e)))? {
+            Some(data) => {
+                let state: ChainState = bincode::deserialize(&self.open_value(TREE_CHAIN_STATE, b"current", &data)?)
+                    .map_err(|e| QtcError::Storage(format!("Failed to deserialize chain state: {}", e)))?;
+                Ok(Some(state))
+            }
+            None => Ok(None),
+        }
+    }
+    
+    /// Parameters the chain's genesis block was built from; None for chains created before they were recorded
+    pub fn save_genesis_params(&self, params: &GenesisParams) -> Result<()> {
+        let state_tree = self.get_tree(TREE_CHAIN_STATE)?;
+        let data = bincode::serialize(params)
+            .map_err(|e| QtcError::Storage(format!("Failed to serialize genesis params: {}", e)))?;
+        
+        state_tree.insert(b"genesis", self.seal_value(TREE_CHAIN_STATE, b"genesis", data)?)
+            .map_err(|e| QtcError::Storage(format!("Failed to save genesis params: {}", e)))?;
+        Ok(())
+    }
+    
+    pub fn get_genesis_params(&self) -> Result<Option<GenesisParams>> {
+        let state_tree = self.get_tree(TREE_CHAIN_STATE)?;
+        
+        match state_tree.get(b"genesis")
+            .map_err(|e| QtcError::Storage(format!("Failed to get genesis params: {}", e)))? {
+            Some(data) => {
+                let params = bincode::deserialize(&self.open_value(TREE_CHAIN_STATE, b"genesis", &data)?)
+                    .map_err(|e| QtcError::Storage(format!("Failed to deserialize genesis params: {}", e)))?;
+                Ok(Some(params))
+            }
+            None => Ok(None),
+        }
+    }
+    
+    pub fn save_fee_estimator(&self, estimator: &FeeEstimator) -> Result<()> {
+        let tree = self.get_tree(TREE_FEE_ESTIMATES)?;
+        let data = bincode::serialize(estimator)
+            .map_err(|e| QtcError::Storage(format!("Failed to serialize fee estimates: {}", e)))?;
+        
+        tree.insert(b"current", data)
+            .map_err(|e| QtcError::Storage(format!("Failed to save fee estimates: {}", e)))?;
+        Ok(())
+    }
+    
+    pub fn get_fee_estimator(&self) -> Result<Option<FeeEstimator>> {
+        let tree = self.get_tree(TREE_FEE_ESTIMATES)?;
+        
+        match tree.get(b"current")
+            .map_err(|e| QtcError::Storage(format!("Failed to get fee estimates: {}", e)))? {
+            Some(data) => {
+                let estimator = bincode::deserialize(&data)
+                    .map_err(|e| QtcError::Storage(format!("Failed to deserialize fee estimates: {}", e)))?;
+                Ok(Some(estimator))
+            }
+            None => Ok(None),
+        }
+    }
+    
+    // Pool share accounting
+    /// Store an accepted share under a new id, which comes back
+    pub fn save_pool_share(&self, mut share: Share) -> Result<u64> {
+        // Ids start at 1 so that a round's `last_share` of 0 means no shares
+        share.id = self.db.generate_id()
+            .map_err(|e| QtcError::Storage(format!("Failed to allocate share id: {}", e)))? + 1;
+        let data = bincode::serialize(&share)
+            .map_err(|e| QtcError::Storage(format!("Failed to serialize share: {}", e)))?;
+        self.get_tree(TREE_POOL_SHARES)?.insert(share.id.to_be_bytes(), data)
+            .map_err(|e| QtcError::Storage(format!("Failed to save share: {}", e)))?;
+        Ok(share.id)
+    }
+    
+    pub fn last_pool_share_id(&self) -> Result<Option<u64>> {
+        let last = self.get_tree(TREE_POOL_SHARES)?.last()
+            .map_err(|e| QtcError::Storage(format!("Failed to read shares: {}", e)))?;
+        Ok(last.map(|(key, _)| {
+            let mut id = [0u8; 8];
+            id.copy_from_slice(&key[..8]);
+            u64::from_be_bytes(id)
+        }))
+    }
+    
+    /// Shares with ids in `ids`, oldest first
+    pub fn get_pool_shares(&self, ids: std::ops::RangeInclusive<u64>) -> Result<Vec<Share>> {
+        let tree = self.get_tree(TREE_POOL_SHARES)?;
+        tree.range(ids.start().to_be_bytes()..=ids.end().to_be_bytes())
+            .map(Self::decode_pool_share)
+            .collect()
+    }
+    
+    /// The newest `count` shares up to and including id `up_to`
+    pub fn get_recent_pool_shares(&self, up_to: u64, count: usize) -> Result<Vec<Share>> {
+        let tree = self.get_tree(TREE_POOL_SHARES)?;
+        let mut shares = tree.range(..=up_to.to_be_bytes())
+            .rev()
+            .take(count)
+            .map(Self::decode_pool_share)
+            .collect::<Result<Vec<_>>>()?;
+        shares.reverse();
+        Ok(shares)
+    }
+    
+    fn decode_pool_share(item: sled::Result<(sled::IVec, sled::IVec)>) -> Result<Share> {
+        let (_, value) = item
+            .map_err(|e| QtcError::Storage(format!("Failed to read share: {}", e)))?;
+        bincode::deserialize(&value)
+            .map_err(|e| QtcError::Storage(format!("Failed to deserialize share: {}", e)))
+    }
+    
+    pub fn update_pool_worker(&self, worker: &str, update: impl FnOnce(&mut WorkerStats)) -> Result<()> {
+        let tree = self.get_tree(TREE_POOL_WORKERS)?;
+        let mut stats = match tree.get(worker.as_bytes())
+            .map_err(|e| QtcError::Storage(format!("Failed to read worker stats: {}", e)))? {
+            Some(data) => bincode::deserialize(&data)
+                .map_err(|e| QtcError::Storage(format!("Failed to deserialize worker stats: {}", e)))?,
+            None => WorkerStats { worker: worker.to_string(), ..WorkerStats::default() },
+        };
+        update(&mut stats);
+        
+        let data = bincode::serialize(&stats)
+            .map_err(|e| QtcError::Storage(format!("Failed to serialize worker stats: {}", e)))?;
+        tree.insert(worker.as_bytes(), data)
+            .map_err(|e| QtcError::Storage(format!("Failed to save worker stats: {}", e)))?;
+        Ok(())
+    }
+    
+    pub fn get_pool_workers(&self) -> Result<Vec<WorkerStats>> {
+        let tree = self.get_tree(TREE_POOL_WORKERS)?;
+        tree.iter()
+            .map(|item| {
+                let (_, value) = item
+                    .map_err(|e| QtcError::Storage(format!("Failed to read worker stats: {}", e)))?;
+                bincode::deserialize(&value)
+                    .map_err(|e| QtcError::Storage(format!("Failed to deserialize worker stats: {}", e)))
+            })
+            .collect()
+    }
+    
+    pub fn save_pool_round(&self, round: &PoolRound) -> Result<()> {
+        // Height first so rounds list in chain order
+        let mut key = round.height.to_be_bytes().to_vec();
+        key.extend_from_slice(round.block_hash.as_bytes());
+        let data = bincode::serialize(round)
+            .map_err(|e| QtcError::Storage(format!("Failed to serialize pool round: {}", e)))?;
+        self.get_tree(TREE_POOL_ROUNDS)?.insert(key, data)
+            .map_err(|e| QtcError::Storage(format!("Failed to save pool round: {}", e)))?;
+        Ok(())
+    }
+    
+    /// Every round the pool has closed, lowest height first
+    pub fn get_pool_rounds(&self) -> Result<Vec<PoolRound>> {
+        let tree = self.get_tree(TREE_POOL_ROUNDS)?;
+        tree.iter()
+            .map(|item| {
+                let (_, value) = item
+                    .map_err(|e| QtcError::Storage(format!("Failed to read pool round: {}", e)))?;
+                bincode::deserialize(&value)
+                    .map_err(|e| QtcError::Storage(format!("Failed to deserialize pool round: {}", e)))
+            })
+            .collect()
+    }
+    
+    // Cosigner messaging
+    pub fn save_messaging_identity(&self, identity: &MessagingIdentity) -> Result<()> {
+        let data = bincode::serialize(identity)
+            .map_err(|e| QtcError::Storage(format!("Failed to serialize messaging identity: {}", e)))?;
+        self.get_tree(TREE_MESSAGING_KEYS)?.insert(identity.wallet.as_bytes(), self.seal_value(TREE_MESSAGING_KEYS, identity.wallet.as_bytes(), data)?)
+            .map_err(|e| QtcError::Storage(format!("Failed to save messaging identity: {}", e)))?;
+        Ok(())
+    }
+    
+    pub fn get_messaging_identity(&self, wallet: &str) -> Result<Option<MessagingIdentity>> {
+        let data = self.get_tree(TREE_MESSAGING_KEYS)?.get(wallet.as_bytes())
+            .map_err(|e| QtcError::Storage(format!("Failed to read messaging identity: {}", e)))?;
+        data.map(|data| self.decode_messaging_identity(wallet.as_bytes(), &data)).transpose()
+    }
+    
+    pub fn get_messaging_identities(&self) -> Result<Vec<MessagingIdentity>> {
+        self.get_tree(TREE_MESSAGING_KEYS)?.iter()
+            .map(|item| {
+                let (key, value) = item
+                    .map_err(|e| QtcError::Storage(format!("Failed to read messaging identity: {}", e)))?;
+                self.decode_messaging_identity(&key, &value)
+            })
+            .collect()
+    }
+    
+    fn decode_messaging_identity(&self, key: &[u8], data: &[u8]) -> Result<MessagingIdentity> {
+        bincode::deserialize(&self.open_value(TREE_MESSAGING_KEYS, key, data)?)
+            .map_err(|e| QtcError::Storage(format!("Failed to deserialize messaging identity: {}", e)))
+    }
+    
+    /// Envelopes are keyed by recipient tag, then envelope id
+    fn inbox_key(recipient: &Hash256, id: &Hash256) -> Vec<u8> {
+        let mut key = recipient.as_bytes().to_vec();
+        key.extend_from_slice(id.as_bytes());
+        key
+    }
+    
+    pub fn save_inbox_envelope(&self, envelope: &Envelope) -> Result<()> {
+        let data = envelope.to_bytes()?;
+        let key = Self::inbox_key(&envelope.recipient, &Hash256::hash(&data));
+        self.get_tree(TREE_INBOX)?.insert(key, data)
+            .map_err(|e| QtcError::Storage(format!("Failed to save message: {}", e)))?;
+        Ok(())
+    }
+    
+    pub fn get_inbox_envelopes(&self, recipient: &Hash256) -> Result<Vec<Envelope>> {
+        self.get_tree(TREE_INBOX)?.scan_prefix(recipient.as_bytes())
+            .map(|item| {
+                let (_, value) = item
+                    .map_err(|e| QtcError::Storage(format!("Failed to read message: {}", e)))?;
+                Envelope::from_bytes(&value)
+            })
+            .collect()
+    }
+    
+    pub fn count_inbox_envelopes(&self, recipient: &Hash256) -> Result<usize> {
+        Ok(self.get_tree(TREE_INBOX)?.scan_prefix(recipient.as_bytes()).count())
+    }
+    
+    pub fn remove_inbox_envelope(&self, recipient: &Hash256, id: &Hash256) -> Result<bool> {
+        let removed = self.get_tree(TREE_INBOX)?.remove(Self::inbox_key(recipient, id))
+            .map_err(|e| QtcError::Storage(format!("Failed to remove message: {}", e)))?;
+        Ok(removed.is_some())
+    }
+    
+    // Peer bans
+    pub fn save_peer_ban(&self, ban: &Ban) -> Result<()> {
+        let bans_tree = self.get_tree(TREE_PEER_BANS)?;
+        let data = bincode::serialize(ban)
+            .map_err(|e| QtcError::Storage(format!("Failed to serialize peer ban: {}", e)))?;
+        bans_tree.insert(ban.target.as_bytes(), data)
+            .map_err(|e| QtcError::Storage(format!("Failed to save peer ban: {}", e)))?;
+        bans_tree.flush()
+            .map_err(|e| QtcError::Storage(format!("Failed to flush peer bans: {}", e)))?;
+        Ok(())
+    }
+    
+    pub fn remove_peer_ban(&self, target: &str) -> Result<bool> {
+        let removed = self.get_tree(TREE_PEER_BANS)?.remove(target.as_bytes())
+            .map_err(|e| QtcError::Storage(format!("Failed to remove peer ban: {}", e)))?;
+        Ok(removed.is_some())
+    }
+    
+    /// Every saved ban, expired ones included
+    pub fn list_peer_bans(&self) -> Result<Vec<Ban>> {
+        self.get_tree(TREE_PEER_BANS)?.iter()
+            .map(|item| {
+                let (_, value) = item
+                    .map_err(|e| QtcError::Storage(format!("Failed to read peer ban: {}", e)))?;
+                bincode::deserialize(&value)
+                    .map_err(|e| QtcError::Storage(format!("Failed to deserialize peer ban: {}", e)))
+            })
+            .collect()
     }
     
     // Wallet operations
@@ -262,7 +1640,7 @@ impl Database {
         let data = bincode::serialize(wallet)
             .map_err(|e| QtcError::Storage(format!("Failed to serialize wallet: {}", e)))?;
         
-        wallet_tree.insert(wallet_id.as_bytes(), data)
+        wallet_tree.insert(wallet_id.as_bytes(), self.seal_value(TREE_WALLETS, wallet_id.as_bytes(), data)?)
             .map_err(|e| QtcError::Storage(format!("Failed to save wallet: {}", e)))?;
         
         log::debug!("💾 Saved wallet {}", wallet_id);
@@ -286,7 +1664,7 @@ impl Database {
                 .map_err(|e| QtcError::Storage(format!("Failed to serialize wallet address: {}", e)))?;
             
             let key = format!("{}:{}", wallet.info.name, address);
-            addr_tree.insert(key.as_bytes(), data)
+            addr_tree.insert(key.as_bytes(), self.seal_value(TREE_ADDRESSES, key.as_bytes(), data)?)
                 .map_err(|e| QtcError::Storage(format!("Failed to save wallet address: {}", e)))?;
         }
         
@@ -294,13 +1672,219 @@ impl Database {
         Ok(())
     }
     
+    /// Mark addresses as handed out so they are not offered as "unused" again
+    pub fn save_address_reservations(&self, reservations: &[AddressReservation]) -> Result<()> {
+        let reserved_tree = self.get_tree(TREE_RESERVED_ADDRESSES)?;
+        
+        for reservation in reservations {
+            let data = bincode::serialize(reservation)
+                .map_err(|e| QtcError::Storage(format!("Failed to serialize address reservation: {}", e)))?;
+            let key = format!("{}:{}", reservation.wallet, reservation.address);
+            reserved_tree.insert(key.as_bytes(), self.seal_value(TREE_RESERVED_ADDRESSES, key.as_bytes(), data)?)
+                .map_err(|e| QtcError::Storage(format!("Failed to save address reservation: {}", e)))?;
+        }
+        
+        Ok(())
+    }
+    
+    pub fn is_address_reserved(&self, wallet_id: &str, address: &str) -> Result<bool> {
+        let reserved_tree = self.get_tree(TREE_RESERVED_ADDRESSES)?;
+        let key = format!("{}:{}", wallet_id, address);
+        
+        reserved_tree.contains_key(key.as_bytes())
+            .map_err(|e| QtcError::Storage(format!("Failed to check address reservation: {}", e)))
+    }
+    
+    /// Reserved addresses of a wallet, oldest first
+    pub fn get_address_reservations(&self, wallet_id: &str) -> Result<Vec<AddressReservation>> {
+        let reserved_tree = self.get_tree(TREE_RESERVED_ADDRESSES)?;
+        let mut reservations = Vec::new();
+        
+        for item in reserved_tree.scan_prefix(format!("{}:", wallet_id).as_bytes()) {
+            let (key, value) = item
+                .map_err(|e| QtcError::Storage(format!("Failed to read address reservation: {}", e)))?;
+            if let Ok(reservation) = bincode::deserialize::<AddressReservation>(&self.open_value(TREE_RESERVED_ADDRESSES, &key, &value)?) {
+                reservations.push(reservation);
+            }
+        }
+        
+        reservations.sort_by_key(|r| r.reserved_at);
+        Ok(reservations)
+    }
+    
+    pub fn save_address_label(&self, wallet_id: &str, address: &str, label: &str) -> Result<()> {
+        let labels_tree = self.get_tree(TREE_ADDRESS_LABELS)?;
+        let key = format!("{}:{}", wallet_id, address);
+        
+        labels_tree.insert(key.as_bytes(), self.seal_value(TREE_ADDRESS_LABELS, key.as_bytes(), label.as_bytes().to_vec())?)
+            .map_err(|e| QtcError::Storage(format!("Failed to save address label: {}", e)))?;
+        Ok(())
+    }
+    
+    /// Whether `address` had a label to remove
+    pub fn remove_address_label(&self, wallet_id: &str, address: &str) -> Result<bool> {
+        let key = format!("{}:{}", wallet_id, address);
+        let removed = self.get_tree(TREE_ADDRESS_LABELS)?.remove(key.as_bytes())
+            .map_err(|e| QtcError::Storage(format!("Failed to remove address label: {}", e)))?;
+        Ok(removed.is_some())
+    }
+    
+    /// A wallet's labels by address
+    pub fn get_address_labels(&self, wallet_id: &str) -> Result<BTreeMap<String, String>> {
+        let labels_tree = self.get_tree(TREE_ADDRESS_LABELS)?;
+        let prefix = format!("{}:", wallet_id);
+        let mut labels = BTreeMap::new();
+        
+        for item in labels_tree.scan_prefix(prefix.as_bytes()) {
+            let (key, value) = item
+                .map_err(|e| QtcError::Storage(format!("Failed to read address label: {}", e)))?;
+            let address = String::from_utf8_lossy(&key[prefix.len()..]).into_owned();
+            let label = String::from_utf8(self.open_value(TREE_ADDRESS_LABELS, &key, &value)?.into_owned())
+                .map_err(|e| QtcError::Storage(format!("Failed to decode address label: {}", e)))?;
+            labels.insert(address, label);
+        }
+        Ok(labels)
+    }
+    
+    pub fn save_contact(&self, contact: &Contact) -> Result<()> {
+        let contacts_tree = self.get_tree(TREE_CONTACTS)?;
+        let data = bincode::serialize(contact)
+            .map_err(|e| QtcError::Storage(format!("Failed to serialize contact: {}", e)))?;
+        
+        contacts_tree.insert(contact.name.as_bytes(), self.seal_value(TREE_CONTACTS, contact.name.as_bytes(), data)?)
+            .map_err(|e| QtcError::Storage(format!("Failed to save contact: {}", e)))?;
+        Ok(())
+    }
+    
+    pub fn get_contact(&self, name: &str) -> Result<Option<Contact>> {
+        let contacts_tree = self.get_tree(TREE_CONTACTS)?;
+        
+        match contacts_tree.get(name.as_bytes())
+            .map_err(|e| QtcError::Storage(format!("Failed to get contact: {}", e)))? {
+            Some(data) => Ok(Some(bincode::deserialize(&self.open_value(TREE_CONTACTS, name.as_bytes(), &data)?)
+                .map_err(|e| QtcError::Storage(format!("Failed to deserialize contact: {}", e)))?)),
+            None => Ok(None),
+        }
+    }
+    
+    /// Every contact, by name
+    pub fn list_contacts(&self) -> Result<Vec<Contact>> {
+        let contacts_tree = self.get_tree(TREE_CONTACTS)?;
+        let mut contacts = Vec::new();
+        
+        for item in contacts_tree.iter() {
+            let (key, value) = item
+                .map_err(|e| QtcError::Storage(format!("Failed to read contact: {}", e)))?;
+            contacts.push(bincode::deserialize(&self.open_value(TREE_CONTACTS, &key, &value)?)
+                .map_err(|e| QtcError::Storage(format!("Failed to deserialize contact: {}", e)))?);
+        }
+        Ok(contacts)
+    }
+    
+    /// Whether there was a contact called `name` to remove
+    pub fn remove_contact(&self, name: &str) -> Result<bool> {
+        let removed = self.get_tree(TREE_CONTACTS)?.remove(name.as_bytes())
+            .map_err(|e| QtcError::Storage(format!("Failed to remove contact: {}", e)))?;
+        Ok(removed.is_some())
+    }
+    
+    pub fn save_api_key(&self, key: &ApiKey) -> Result<()> {
+        let keys_tree = self.get_tree(TREE_API_KEYS)?;
+        let data = bincode::serialize(key)
+            .map_err(|e| QtcError::Storage(format!("Failed to serialize API key: {}", e)))?;
+        keys_tree.insert(key.id.as_bytes(), self.seal_value(TREE_API_KEYS, key.id.as_bytes(), data)?)
+            .map_err(|e| QtcError::Storage(format!("Failed to save API key: {}", e)))?;
+        keys_tree.flush()
+            .map_err(|e| QtcError::Storage(format!("Failed to flush API keys: {}", e)))?;
+        Ok(())
+    }
+    
+    pub fn get_api_key(&self, id: &str) -> Result<Option<ApiKey>> {
+        let keys_tree = self.get_tree(TREE_API_KEYS)?;
+        match keys_tree.get(id.as_bytes())
+            .map_err(|e| QtcError::Storage(format!("Failed to get API key: {}", e)))? {
+            Some(data) => Ok(Some(bincode::deserialize(&self.open_value(TREE_API_KEYS, id.as_bytes(), &data)?)
+                .map_err(|e| QtcError::Storage(format!("Failed to deserialize API key: {}", e)))?)),
+            None => Ok(None),
+        }
+    }
+    
+    /// All API keys, revoked ones included, oldest first
+    pub fn list_api_keys(&self) -> Result<Vec<ApiKey>> {
+        let keys_tree = self.get_tree(TREE_API_KEYS)?;
+        let mut keys = Vec::new();
+        
+        for item in keys_tree.iter() {
+            let (key, value) = item
+                .map_err(|e| QtcError::Storage(format!("Failed to read API key: {}", e)))?;
+            keys.push(bincode::deserialize::<ApiKey>(&self.open_value(TREE_API_KEYS, &key, &value)?)
+                .map_err(|e| QtcError::Storage(format!("Failed to deserialize API key: {}", e)))?);
+        }
+        
+        keys.sort_by_key(|key| key.created_at);
+        Ok(keys)
+    }
+    
+    /// Append an entry to the audit trail; entries are never rewritten or removed
+    pub fn record_audit_event(&self, action: AuditAction, origin: &str, details: String) -> Result<AuditEvent> {
+        let audit_tree = self.get_tree(TREE_AUDIT_LOG)?;
+        let id = self.db.generate_id()
+            .map_err(|e| QtcError::Storage(format!("Failed to allocate audit id: {}", e)))?;
+        
+        let event = AuditEvent {
+            id,
+            timestamp: chrono::Utc::now().timestamp() as u64,
+            action,
+            origin: origin.to_string(),
+            details,
+        };
+        
+        // Timestamp first so `--since` queries are a range scan
+        let mut key = event.timestamp.to_be_bytes().to_vec();
+        key.extend_from_slice(&id.to_be_bytes());
+        
+        let data = bincode::serialize(&event)
+            .map_err(|e| QtcError::Storage(format!("Failed to serialize audit event: {}", e)))?;
+        audit_tree.insert(key, data)
+            .map_err(|e| QtcError::Storage(format!("Failed to save audit event: {}", e)))?;
+        audit_tree.flush()
+            .map_err(|e| QtcError::Storage(format!("Failed to flush audit log: {}", e)))?;
+        
+        Ok(event)
+    }
+    
+    /// Audit entries recorded at or after `since` (unix seconds), oldest first
+    pub fn get_audit_events(&self, since: u64, limit: usize) -> Result<Vec<AuditEvent>> {
+        let audit_tree = self.get_tree(TREE_AUDIT_LOG)?;
+        let mut events = Vec::new();
+        
+        for item in audit_tree.range(since.to_be_bytes().to_vec()..).take(limit) {
+            let (_, value) = item
+                .map_err(|e| QtcError::Storage(format!("Failed to read audit event: {}", e)))?;
+            let event = bincode::deserialize::<AuditEvent>(&value)
+                .map_err(|e| QtcError::Storage(format!("Failed to deserialize audit event: {}", e)))?;
+            events.push(event);
+        }
+        
+        Ok(events)
+    }
+    
+    /// Store the fingerprint of the running configuration, returning the previous one
+    pub fn swap_config_fingerprint(&self, fingerprint: &str) -> Result<Option<String>> {
+        let state_tree = self.get_tree(TREE_AUDIT_STATE)?;
+        let previous = state_tree.insert("config_fingerprint", fingerprint.as_bytes())
+            .map_err(|e| QtcError::Storage(format!("Failed to save config fingerprint: {}", e)))?;
+        
+        Ok(previous.map(|bytes| String::from_utf8_lossy(&bytes).into_owned()))
+    }
+    
     pub fn get_wallet(&self, wallet_id: &str) -> Result<Option<WalletInfo>> {
         let wallet_tree = self.get_tree(TREE_WALLETS)?;
         
         match wallet_tree.get(wallet_id.as_bytes())
             .map_err(|e| QtcError::Storage(format!("Failed to get wallet: {}", e)))? {
             Some(data) => {
-                let wallet: WalletInfo = bincode::deserialize(&data)
+                let wallet: WalletInfo = bincode::deserialize(&self.open_value(TREE_WALLETS, wallet_id.as_bytes(), &data)?)
                     .map_err(|e| QtcError::Storage(format!("Failed to deserialize wallet: {}", e)))?;
                 Ok(Some(wallet))
             }
@@ -314,6 +1898,7 @@ impl Database {
         
         for item in wallet_tree.iter() {
             match item {
+                Ok((key, _)) if key.starts_with(MULTISIG_KEY_PREFIX) => {}
                 Ok((key, _)) => {
                     if let Ok(wallet_id) = String::from_utf8(key.to_vec()) {
                         wallets.push(wallet_id);
@@ -342,7 +1927,7 @@ impl Database {
                 Ok((key, value)) => {
                     if let Ok(key_str) = String::from_utf8(key.to_vec()) {
                         if key_str.starts_with(&format!("{}:", wallet_id)) {
-                            if let Ok(addr_data) = bincode::deserialize::<WalletAddressData>(&value) {
+                            if let Ok(addr_data) = bincode::deserialize::<WalletAddressData>(&self.open_value(TREE_ADDRESSES, &key, &value)?) {
                                 addresses.insert(
                                     addr_data.address_info.address.clone(),
                                     addr_data.address_info
@@ -375,11 +1960,185 @@ impl Database {
         
         wallet_tree.remove(wallet_id.as_bytes())
             .map_err(|e| QtcError::Storage(format!("Failed to delete wallet: {}", e)))?;
+        wallet_tree.remove(Self::multisig_key(wallet_id))
+            .map_err(|e| QtcError::Storage(format!("Failed to delete multisig wallet: {}", e)))?;
+        self.get_tree(TREE_WALLET_BALANCES)?.remove(wallet_id.as_bytes())
+            .map_err(|e| QtcError::Storage(format!("Failed to delete wallet balance: {}", e)))?;
+        self.get_tree(TREE_WALLET_HISTORY)?.remove(wallet_id.as_bytes())
+            .map_err(|e| QtcError::Storage(format!("Failed to delete wallet history: {}", e)))?;
+        self.get_tree(TREE_WALLET_SETTINGS)?.remove(wallet_id.as_bytes())
+            .map_err(|e| QtcError::Storage(format!("Failed to delete wallet settings: {}", e)))?;
+        self.get_tree(TREE_WALLET_RESCANS)?.remove(wallet_id.as_bytes())
+            .map_err(|e| QtcError::Storage(format!("Failed to delete wallet rescan cursor: {}", e)))?;
+        let sends_tree = self.get_tree(TREE_REPLACEABLE_SENDS)?;
+        for item in sends_tree.scan_prefix(format!("{}:", wallet_id).as_bytes()) {
+            let (key, _) = item
+                .map_err(|e| QtcError::Storage(format!("Failed to read replaceable send: {}", e)))?;
+            sends_tree.remove(key)
+                .map_err(|e| QtcError::Storage(format!("Failed to delete replaceable send: {}", e)))?;
+        }
+        let labels_tree = self.get_tree(TREE_ADDRESS_LABELS)?;
+        for item in labels_tree.scan_prefix(format!("{}:", wallet_id).as_bytes()) {
+            let (key, _) = item
+                .map_err(|e| QtcError::Storage(format!("Failed to read address label: {}", e)))?;
+            labels_tree.remove(key)
+                .map_err(|e| QtcError::Storage(format!("Failed to delete address label: {}", e)))?;
+        }
         
         log::debug!("🗑️ Deleted wallet {}", wallet_id);
         Ok(())
     }
     
+    pub fn save_wallet_balance(&self, wallet_id: &str, balance: &WalletBalance) -> Result<()> {
+        let balance_tree = self.get_tree(TREE_WALLET_BALANCES)?;
+        let data = bincode::serialize(balance)
+            .map_err(|e| QtcError::Storage(format!("Failed to serialize wallet balance: {}", e)))?;
+        
+        balance_tree.insert(wallet_id.as_bytes(), self.seal_value(TREE_WALLET_BALANCES, wallet_id.as_bytes(), data)?)
+            .map_err(|e| QtcError::Storage(format!("Failed to save wallet balance: {}", e)))?;
+        Ok(())
+    }
+    
+    pub fn get_wallet_balance(&self, wallet_id: &str) -> Result<Option<WalletBalance>> {
+        let balance_tree = self.get_tree(TREE_WALLET_BALANCES)?;
+        
+        match balance_tree.get(wallet_id.as_bytes())
+            .map_err(|e| QtcError::Storage(format!("Failed to get wallet balance: {}", e)))? {
+            Some(data) => Ok(Some(bincode::deserialize(&self.open_value(TREE_WALLET_BALANCES, wallet_id.as_bytes(), &data)?)
+                .map_err(|e| QtcError::Storage(format!("Failed to deserialize wallet balance: {}", e)))?)),
+            None => Ok(None),
+        }
+    }
+    
+    pub fn save_wallet_history(&self, wallet_id: &str, history: &WalletHistory) -> Result<()> {
+        let history_tree = self.get_tree(TREE_WALLET_HISTORY)?;
+        let data = bincode::serialize(history)
+            .map_err(|e| QtcError::Storage(format!("Failed to serialize wallet history: {}", e)))?;
+        
+        history_tree.insert(wallet_id.as_bytes(), self.seal_value(TREE_WALLET_HISTORY, wallet_id.as_bytes(), data)?)
+            .map_err(|e| QtcError::Storage(format!("Failed to save wallet history: {}", e)))?;
+        Ok(())
+    }
+    
+    pub fn get_wallet_history(&self, wallet_id: &str) -> Result<Option<WalletHistory>> {
+        let history_tree = self.get_tree(TREE_WALLET_HISTORY)?;
+        
+        let Some(data) = history_tree.get(wallet_id.as_bytes())
+            .map_err(|e| QtcError::Storage(format!("Failed to get wallet history: {}", e)))? else {
+            return Ok(None);
+        };
+        // Only a cache, so one written in an older layout is scanned again
+        match bincode::deserialize(&self.open_value(TREE_WALLET_HISTORY, wallet_id.as_bytes(), &data)?) {
+            Ok(history) => Ok(Some(history)),
+            Err(e) => {
+                log::debug!("📜 Rebuilding the history cache of wallet {}: {}", wallet_id, e);
+                Ok(None)
+            }
+        }
+    }
+    
+    pub fn save_wallet_settings(&self, wallet_id: &str, settings: &WalletSettings) -> Result<()> {
+        let settings_tree = self.get_tree(TREE_WALLET_SETTINGS)?;
+        let data = bincode::serialize(settings)
+            .map_err(|e| QtcError::Storage(format!("Failed to serialize wallet settings: {}", e)))?;
+        
+        settings_tree.insert(wallet_id.as_bytes(), self.seal_value(TREE_WALLET_SETTINGS, wallet_id.as_bytes(), data)?)
+            .map_err(|e| QtcError::Storage(format!("Failed to save wallet settings: {}", e)))?;
+        Ok(())
+    }
+    
+    /// Store the cosigners of a multisig wallet, saved as a wallet of its own with `save_wallet_complete`
+    pub fn save_multisig_wallet(&self, multisig: &MultisigWallet) -> Result<()> {
+        let wallet_tree = self.get_tree(TREE_WALLETS)?;
+        let data = bincode::serialize(multisig)
+            .map_err(|e| QtcError::Storage(format!("Failed to serialize multisig wallet: {}", e)))?;
+        
+        wallet_tree.insert(Self::multisig_key(&multisig.name), self.seal_value(TREE_WALLETS, &Self::multisig_key(&multisig.name), data)?)
+            .map_err(|e| QtcError::Storage(format!("Failed to save multisig wallet: {}", e)))?;
+        Ok(())
+    }
+    
+    pub fn get_multisig_wallet(&self, wallet_id: &str) -> Result<Option<MultisigWallet>> {
+        let wallet_tree = self.get_tree(TREE_WALLETS)?;
+        
+        match wallet_tree.get(Self::multisig_key(wallet_id))
+            .map_err(|e| QtcError::Storage(format!("Failed to get multisig wallet: {}", e)))? {
+            Some(data) => bincode::deserialize(&self.open_value(TREE_WALLETS, &Self::multisig_key(wallet_id), &data)?)
+                .map(Some)
+                .map_err(|e| QtcError::Storage(format!("Failed to deserialize multisig wallet: {}", e))),
+            None => Ok(None),
+        }
+    }
+    
+    fn multisig_key(wallet_id: &str) -> Vec<u8> {
+        [MULTISIG_KEY_PREFIX, wallet_id.as_bytes()].concat()
+    }
+    
+    /// A wallet's settings, the defaults if none were ever changed
+    pub fn get_wallet_settings(&self, wallet_id: &str) -> Result<WalletSettings> {
+        let settings_tree = self.get_tree(TREE_WALLET_SETTINGS)?;
+        
+        match settings_tree.get(wallet_id.as_bytes())
+            .map_err(|e| QtcError::Storage(format!("Failed to get wallet settings: {}", e)))? {
+            Some(data) => bincode::deserialize(&self.open_value(TREE_WALLET_SETTINGS, wallet_id.as_bytes(), &data)?)
+                .map_err(|e| QtcError::Storage(format!("Failed to deserialize wallet settings: {}", e))),
+            None => Ok(WalletSettings::default()),
+        }
+    }
+    
+    pub fn save_wallet_rescan_cursor(&self, wallet_id: &str, cursor: &RescanCursor) -> Result<()> {
+        let rescan_tree = self.get_tree(TREE_WALLET_RESCANS)?;
+        let data = bincode::serialize(cursor)
+            .map_err(|e| QtcError::Storage(format!("Failed to serialize wallet rescan cursor: {}", e)))?;
+        
+        rescan_tree.insert(wallet_id.as_bytes(), self.seal_value(TREE_WALLET_RESCANS, wallet_id.as_bytes(), data)?)
+            .map_err(|e| QtcError::Storage(format!("Failed to save wallet rescan cursor: {}", e)))?;
+        Ok(())
+    }
+    
+    /// Where the wallet's last rescan stopped, if it was ever rescanned
+    pub fn get_wallet_rescan_cursor(&self, wallet_id: &str) -> Result<Option<RescanCursor>> {
+        let rescan_tree = self.get_tree(TREE_WALLET_RESCANS)?;
+        
+        match rescan_tree.get(wallet_id.as_bytes())
+            .map_err(|e| QtcError::Storage(format!("Failed to get wallet rescan cursor: {}", e)))? {
+            Some(data) => Ok(Some(bincode::deserialize(&self.open_value(TREE_WALLET_RESCANS, wallet_id.as_bytes(), &data)?)
+                .map_err(|e| QtcError::Storage(format!("Failed to deserialize wallet rescan cursor: {}", e)))?)),
+            None => Ok(None),
+        }
+    }
+    
+    /// Remember a wallet send that signals RBF, so its fee can be bumped later
+    pub fn save_replaceable_send(&self, wallet_id: &str, tx: &Transaction) -> Result<()> {
+        let sends_tree = self.get_tree(TREE_REPLACEABLE_SENDS)?;
+        let data = bincode::serialize(tx)
+            .map_err(|e| QtcError::Storage(format!("Failed to serialize replaceable send: {}", e)))?;
+        let key = format!("{}:{}", wallet_id, tx.hash().to_hex());
+        
+        sends_tree.insert(key.as_bytes(), self.seal_value(TREE_REPLACEABLE_SENDS, key.as_bytes(), data)?)
+            .map_err(|e| QtcError::Storage(format!("Failed to save replaceable send: {}", e)))?;
+        Ok(())
+    }
+    
+    pub fn get_replaceable_send(&self, wallet_id: &str, txid: &Hash256) -> Result<Option<Transaction>> {
+        let sends_tree = self.get_tree(TREE_REPLACEABLE_SENDS)?;
+        let key = format!("{}:{}", wallet_id, txid.to_hex());
+        
+        match sends_tree.get(key.as_bytes())
+            .map_err(|e| QtcError::Storage(format!("Failed to get replaceable send: {}", e)))? {
+            Some(data) => Ok(Some(bincode::deserialize(&self.open_value(TREE_REPLACEABLE_SENDS, key.as_bytes(), &data)?)
+                .map_err(|e| QtcError::Storage(format!("Failed to deserialize replaceable send: {}", e)))?)),
+            None => Ok(None),
+        }
+    }
+    
+    pub fn remove_replaceable_send(&self, wallet_id: &str, txid: &Hash256) -> Result<()> {
+        let key = format!("{}:{}", wallet_id, txid.to_hex());
+        self.get_tree(TREE_REPLACEABLE_SENDS)?.remove(key.as_bytes())
+            .map_err(|e| QtcError::Storage(format!("Failed to remove replaceable send: {}", e)))?;
+        Ok(())
+    }
+    
     // Address operations
     pub fn save_address_info(&self, address: &str, wallet_id: &str, derivation_path: &str) -> Result<()> {
         let addr_tree = self.get_tree(TREE_ADDRESSES)?;
@@ -391,7 +2150,7 @@ impl Database {
         let data = bincode::serialize(&info)
             .map_err(|e| QtcError::Storage(format!("Failed to serialize address info: {}", e)))?;
         
-        addr_tree.insert(address.as_bytes(), data)
+        addr_tree.insert(address.as_bytes(), self.seal_value(TREE_ADDRESSES, address.as_bytes(), data)?)
             .map_err(|e| QtcError::Storage(format!("Failed to save address info: {}", e)))?;
         
         Ok(())
@@ -403,7 +2162,7 @@ impl Database {
         match addr_tree.get(address.as_bytes())
             .map_err(|e| QtcError::Storage(format!("Failed to get address info: {}", e)))? {
             Some(data) => {
-                let info: AddressInfo = bincode::deserialize(&data)
+                let info: AddressInfo = bincode::deserialize(&self.open_value(TREE_ADDRESSES, address.as_bytes(), &data)?)
                     .map_err(|e| QtcError::Storage(format!("Failed to deserialize address info: {}", e)))?;
                 Ok(Some(info))
             }
@@ -419,8 +2178,26 @@ impl Database {
         key
     }
     
-    fn outpoint_to_string(&self, outpoint: &OutPoint) -> String {
-        format!("{}:{}", hex::encode(outpoint.txid.as_bytes()), outpoint.vout)
+    /// Addresses never contain a NUL byte, so it keeps "qtc1a" from matching "qtc1ab..."
+    fn address_prefix(address: &str) -> Vec<u8> {
+        let mut prefix = Vec::with_capacity(address.len() + 1);
+        prefix.extend_from_slice(address.as_bytes());
+        prefix.push(0);
+        prefix
+    }
+    
+    fn address_utxo_key(address: &str, outpoint_key: &[u8]) -> Vec<u8> {
+        let mut key = Self::address_prefix(address);
+        key.extend_from_slice(outpoint_key);
+        key
+    }
+    
+    /// Big-endian height so a prefix scan returns an address's history in chain order
+    fn address_history_key(address: &str, height: u64, txid: &Hash256) -> Vec<u8> {
+        let mut key = Self::address_prefix(address);
+        key.extend_from_slice(&height.to_be_bytes());
+        key.extend_from_slice(txid.as_bytes());
+        key
     }
     
     fn key_to_outpoint(&self, key: &[u8]) -> Result<OutPoint> {
@@ -451,6 +2228,88 @@ impl Database {
         self.flush()
     }
     
+    /// Copy every tree and block file into a new data directory at `dir`. The copy is
+    /// consistent as long as nothing writes meanwhile, which the node ensures by holding
+    /// the chain lock. Returns the number of entries copied per tree.
+    pub fn snapshot_to(&self, dir: &Path) -> Result<BTreeMap<String, usize>> {
+        self.flush()?;
+        let copy = sled::open(dir)
+            .map_err(|e| QtcError::Storage(format!("Failed to create snapshot database: {}", e)))?;
+        
+        let mut counts = BTreeMap::new();
+        for name in self.db.tree_names() {
+            let tree_name = String::from_utf8_lossy(&name).into_owned();
+            let source = self.get_tree(&tree_name)?;
+            let target = copy.open_tree(&name)
+                .map_err(|e| QtcError::Storage(format!("Failed to open snapshot tree {}: {}", tree_name, e)))?;
+            let mut count = 0;
+            for item in source.iter() {
+                let (key, value) = item
+                    .map_err(|e| QtcError::Storage(format!("Failed to read tree {}: {}", tree_name, e)))?;
+                target.insert(key, value)
+                    .map_err(|e| QtcError::Storage(format!("Failed to write snapshot tree {}: {}", tree_name, e)))?;
+                count += 1;
+            }
+            counts.insert(tree_name, count);
+        }
+        copy.flush()
+            .map_err(|e| QtcError::Storage(format!("Failed to flush snapshot database: {}", e)))?;
+        
+        // Bytes appended after this point belong to blocks the copied index doesn't know about
+        let blocks_dir = dir.join("blocks");
+        std::fs::create_dir_all(&blocks_dir)?;
+        for file in self.block_files.list_files()? {
+            let source = self.block_files.file_path(file);
+            let len = std::fs::metadata(&source)?.len();
+            let mut reader = std::io::Read::take(std::fs::File::open(&source)?, len);
+            let mut writer = std::fs::File::create(blocks_dir.join(format!("blk{:05}.dat", file)))?;
+            std::io::copy(&mut reader, &mut writer)?;
+            writer.sync_all()?;
+        }
+        Ok(counts)
+    }
+    
+    /// Open a copy made by `snapshot_to` and check it holds what was copied: the same
+    /// number of entries in every tree, a readable chain state and every indexed block.
+    /// Returns the number of blocks read.
+    pub fn verify_snapshot(&self, dir: &Path, counts: &BTreeMap<String, usize>) -> Result<usize> {
+        let db = sled::open(dir)
+            .map_err(|e| QtcError::Storage(format!("Failed to open snapshot: {}", e)))?;
+        for (name, expected) in counts {
+            let found = db.open_tree(name)
+                .map_err(|e| QtcError::Storage(format!("Failed to open snapshot tree {}: {}", name, e)))?
+                .len();
+            if found != *expected {
+                return Err(QtcError::Storage(format!(
+                    "Snapshot tree {} holds {} entries, expected {}", name, found, expected
+                )));
+            }
+        }
+        
+        // The copy shares this directory's key, so sealed values open the same way
+        let utxo_locks = UtxoLockTable::with_tree(db.open_tree(TREE_UTXO_LOCKS)
+            .map_err(|e| QtcError::Storage(format!("Failed to open tree {}: {}", TREE_UTXO_LOCKS, e)))?)?;
+        let copy = Self {
+            block_files: Arc::new(BlockFileStore::open(dir.join("blocks"))?),
+            db: Arc::new(db),
+            utxo_locks: Arc::new(utxo_locks),
+            cipher: self.cipher.clone(),
+        };
+        copy.get_chain_state()?;
+        
+        let mut blocks = 0;
+        for item in copy.get_tree(TREE_BLOCK_POSITIONS)?.iter() {
+            let (hash, value) = item
+                .map_err(|e| QtcError::Storage(format!("Failed to iterate block positions: {}", e)))?;
+            let position: BlockPosition = bincode::deserialize(&value)
+                .map_err(|e| QtcError::Storage(format!("Failed to deserialize block position: {}", e)))?;
+            copy.read_block_at(&hash, &position)
+                .map_err(|e| QtcError::Storage(format!("Snapshot block does not match its index entry: {}", e)))?;
+            blocks += 1;
+        }
+        Ok(blocks)
+    }
+    
     pub fn get_all_utxos(&self) -> Result<Vec<(OutPoint, UtxoEntry)>> {
         let utxo_tree = self.get_tree(TREE_UTXOS)?;
         let mut utxos = Vec::new();
@@ -458,7 +2317,7 @@ impl Database {
         for item in utxo_tree.iter() {
             match item {
                 Ok((key, value)) => {
-                    if let Ok(utxo) = bincode::deserialize::<UtxoEntry>(&value) {
+                    if let Ok(utxo) = bincode::deserialize::<UtxoEntry>(&self.open_value(TREE_UTXOS, &key, &value)?) {
                         if let Ok(outpoint) = self.key_to_outpoint(&key) {
                             utxos.push((outpoint, utxo));
                         }
@@ -474,15 +2333,22 @@ impl Database {
         Ok(utxos)
     }
     
+    /// Bytes on disk for the database and block files, without walking any tree
+    pub fn size_on_disk(&self) -> Result<u64> {
+        Ok(self.db.size_on_disk()? + self.block_files.total_size()?)
+    }
+    
     pub fn get_database_stats(&self) -> Result<DatabaseStats> {
         let mut stats = DatabaseStats::default();
         
         // Count items in each tree
-        for tree_name in &[TREE_BLOCKS, TREE_TRANSACTIONS, TREE_UTXOS, TREE_WALLETS] {
+        for tree_name in &[TREE_BLOCK_POSITIONS, TREE_TRANSACTIONS, TREE_UTXOS, TREE_WALLETS] {
             if let Ok(tree) = self.get_tree(tree_name) {
-                let count = tree.iter().count();
+                let count = tree.iter()
+                    .filter(|item| !item.as_ref().is_ok_and(|(key, _)| key.starts_with(MULTISIG_KEY_PREFIX)))
+                    .count();
                 match *tree_name {
-                    TREE_BLOCKS => stats.block_count = count,
+                    TREE_BLOCK_POSITIONS => stats.block_count = count,
                     TREE_TRANSACTIONS => stats.transaction_count = count,
                     TREE_UTXOS => stats.utxo_count = count,
                     TREE_WALLETS => stats.wallet_count = count,
@@ -491,6 +2357,9 @@ impl Database {
             }
         }
         
+        stats.blocks_size = self.block_files.total_size()? as usize;
+        stats.total_size = self.db.size_on_disk().unwrap_or(0) + stats.blocks_size as u64;
+        
         Ok(stats)
     }
     
@@ -519,18 +2388,43 @@ impl Database {
         Ok(addresses)
     }
     
+    /// Transactions involving `address`, newest first. Served from the address history
+    /// index when it is complete, otherwise by scanning recent blocks.
     pub fn get_address_transactions(&self, address: &str, limit: usize) -> Result<Vec<(Hash256, Transaction, u64)>> {
+        if !self.is_address_history_complete()? {
+            return self.scan_address_transactions(address, limit);
+        }
+        
+        let mut transactions = Vec::new();
+        let mut block: Option<Block> = None;
+        
+        for (height, txid) in self.get_address_history(address, limit)? {
+            if block.as_ref().is_none_or(|b| b.header.height != height) {
+                block = self.get_block_by_height(height)?;
+            }
+            let tx = block.as_ref()
+                .and_then(|b| b.transactions.iter().find(|tx| tx.hash() == txid))
+                .ok_or_else(|| QtcError::Storage(format!("Indexed transaction {} not found at height {}", txid, height)))?;
+            transactions.push((txid, tx.clone(), height));
+        }
+        
+        Ok(transactions)
+    }
+    
+    fn scan_address_transactions(&self, address: &str, limit: usize) -> Result<Vec<(Hash256, Transaction, u64)>> {
         let mut transactions = Vec::new();
         
         // Get all blocks to find transactions involving this address
-        let blocks_tree = self.get_tree(TREE_BLOCKS)?;
+        let positions_tree = self.get_tree(TREE_BLOCK_POSITIONS)?;
         let mut block_data = Vec::new();
         
-        for item in blocks_tree.iter() {
+        for item in positions_tree.iter() {
             match item {
-                Ok((_, value)) => {
-                    if let Ok(block) = bincode::deserialize::<Block>(&value) {
-                        block_data.push(block);
+                Ok((hash, value)) => {
+                    if let Ok(position) = bincode::deserialize::<BlockPosition>(&value) {
+                        if let Ok(block) = self.read_block_at(&hash, &position) {
+                            block_data.push(block);
+                        }
                     }
                 }
                 Err(e) => {
@@ -597,6 +2491,86 @@ impl Database {
 
 }
 
+/// A receive address handed out in bulk (e.g. to a shop backend)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AddressReservation {
+    pub wallet: String,
+    pub address: String,
+    pub derivation_path: Option<String>,
+    pub label: Option<String>,
+    pub reserved_at: u64,
+}
+
+/// Node-level actions recorded in the audit trail
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AuditAction {
+    WalletCreated,
+    KeyExported,
+    TransactionSent,
+    PeerBanned,
+    PeerUnbanned,
+    ConfigChanged,
+    ApiWrite,
+    ApiKeyCreated,
+    ApiKeyRevoked,
+    MessageSent,
+}
+
+impl std::fmt::Display for AuditAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            AuditAction::WalletCreated => "wallet_created",
+            AuditAction::KeyExported => "key_exported",
+            AuditAction::TransactionSent => "transaction_sent",
+            AuditAction::PeerBanned => "peer_banned",
+            AuditAction::PeerUnbanned => "peer_unbanned",
+            AuditAction::ConfigChanged => "config_changed",
+            AuditAction::ApiWrite => "api_write",
+            AuditAction::ApiKeyCreated => "api_key_created",
+            AuditAction::ApiKeyRevoked => "api_key_revoked",
+            AuditAction::MessageSent => "message_sent",
+        };
+        f.write_str(name)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditEvent {
+    pub id: u64,
+    pub timestamp: u64,
+    pub action: AuditAction,
+    pub origin: String, // "cli", "node" or "api <client ip>"
+    pub details: String,
+}
+
+/// A block that is stored but not part of the main chain
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StaleBlockInfo {
+    pub hash: Hash256,
+    pub height: u64,
+    pub previous_hash: Hash256,
+    pub fork_height: u64, // last height shared with the main chain
+    pub fork_point: Hash256,
+    pub recorded_at: u64,
+}
+
+/// Cumulative work up to and including a block
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlockWorkEntry {
+    pub hash: Hash256,
+    pub previous_hash: Hash256,
+    pub height: u64,
+    pub chain_work: u128,
+}
+
+/// Where a previously created output was spent
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SpentOutput {
+    pub spending_txid: Hash256,
+    pub input_index: u32,
+    pub height: u64,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AddressInfo {
     pub wallet_id: String,
@@ -626,4 +2600,217 @@ impl DatabaseStats {
     }
 }
 
-// Error handling for sled database is handled by the thiserror derive macro
\ No newline at end of file
+// Error handling for sled database is handled by the thiserror derive macro
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn test_block(height: u64) -> Block {
+        let coinbase = Transaction::new_coinbase(
+            "qtc1qtest".to_string(),
+            2710000000,
+            format!("block {}", height),
+        );
+        Block::new(Hash256::zero(), vec![coinbase], 6, height)
+    }
+
+    #[test]
+    fn test_blocks_stored_in_flat_files() -> Result<()> {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Database::new(temp_dir.path().join("test.db"))?;
+
+        let block = test_block(1);
+        db.save_block(&block)?;
+        db.save_block(&block)?;
+
+        let position = db.get_block_position(&block.hash())?.unwrap();
+        assert_eq!(position.file, 0);
+        assert_eq!(db.block_files().total_size()?, position.offset + position.len as u64);
+
+        let loaded = db.get_block_by_height(1)?.unwrap();
+        assert_eq!(loaded.hash(), block.hash());
+        assert_eq!(db.get_database_stats()?.block_count, 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_prune_block_files() -> Result<()> {
+        let temp_dir = TempDir::new().unwrap();
+        let mut db = Database::new(temp_dir.path().join("test.db"))?;
+        let record_size = bincode::serialize(&test_block(0)).unwrap().len() as u64 + 8;
+        db.block_files = Arc::new(BlockFileStore::with_max_file_size(
+            temp_dir.path().join("test.db").join("blocks"),
+            record_size * 2,
+        )?);
+
+        let blocks: Vec<Block> = (0..10).map(test_block).collect();
+        let stale = test_block(2);
+        let stale = Block { header: BlockHeader { nonce: 7, ..stale.header }, ..stale };
+        for block in &blocks {
+            db.save_block(block)?;
+            if block.header.height == 2 {
+                db.save_stale_block(&stale, 1, blocks[1].hash())?;
+            }
+        }
+        db.save_block_undo(&blocks[1].hash(), &[])?;
+        assert_eq!(db.prune_block_files(u64::MAX, 0)?, 0);
+
+        // Files holding heights 0..=5 may go; the one with block 6 onwards stays
+        assert!(db.prune_block_files(0, 6)? > 0);
+        let pruned_height = db.get_pruned_height()?.unwrap();
+        assert!(pruned_height < 6);
+        assert!(db.get_block_by_height(0)?.is_none());
+        assert_eq!(db.get_block_header_by_height(0)?.unwrap().height, 0);
+        assert!(db.is_block_pruned(&blocks[0].hash())?);
+        assert!(db.get_block_undo(&blocks[1].hash())?.is_none());
+        assert!(db.get_stale_block_info(&stale.hash())?.is_none());
+        assert!(!db.is_block_pruned(&stale.hash())?);
+        for block in &blocks[6..] {
+            assert_eq!(db.get_block(&block.hash())?.unwrap().hash(), block.hash());
+            assert!(!db.is_block_pruned(&block.hash())?);
+        }
+
+        // The file being written to is never deleted, whatever the target
+        db.prune_block_files(0, u64::MAX)?;
+        assert_eq!(db.block_files().list_files()?, vec![db.block_files().current_file()?]);
+        assert!(db.get_block(&blocks[9].hash())?.is_some());
+        Ok(())
+    }
+
+    #[test]
+    fn test_audit_log_since() -> Result<()> {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Database::new(temp_dir.path().join("test.db"))?;
+
+        let first = db.record_audit_event(AuditAction::WalletCreated, "cli", "wallet 'cold'".to_string())?;
+        db.record_audit_event(AuditAction::ApiWrite, "api 127.0.0.1", "POST /api/v1/wallets/cold/addresses".to_string())?;
+
+        let events = db.get_audit_events(0, 100)?;
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].id, first.id);
+        assert_eq!(events[1].action, AuditAction::ApiWrite);
+
+        assert!(db.get_audit_events(first.timestamp + 3600, 100)?.is_empty());
+        assert_eq!(db.get_audit_events(0, 1)?.len(), 1);
+
+        assert_eq!(db.swap_config_fingerprint("a")?, None);
+        assert_eq!(db.swap_config_fingerprint("b")?, Some("a".to_string()));
+        Ok(())
+    }
+
+    #[test]
+    fn test_legacy_blocks_migrated() -> Result<()> {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("test.db");
+        let block = test_block(1);
+
+        // Write a block the way older versions did
+        {
+            let db = sled::open(&path).unwrap();
+            let data = bincode::serialize(&block).unwrap();
+            db.open_tree(TREE_BLOCKS)?.insert(block.hash().as_bytes(), data)?;
+            db.flush()?;
+        }
+
+        let db = Database::new(&path)?;
+        assert!(db.get_tree(TREE_BLOCKS)?.is_empty());
+        assert!(db.get_block_position(&block.hash())?.is_some());
+        assert_eq!(db.get_block(&block.hash())?.unwrap().hash(), block.hash());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_stale_blocks_kept_off_main_chain() -> Result<()> {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Database::new(temp_dir.path().join("test.db"))?;
+
+        let genesis = test_block(0);
+        let main = Block::new(genesis.hash(), test_block(1).transactions, 6, 1);
+        let mut side = Block::new(genesis.hash(), test_block(1).transactions, 6, 1);
+        side.header.nonce = 42;
+
+        db.save_block(&genesis)?;
+        db.save_block(&main)?;
+        db.save_stale_block(&side, 0, genesis.hash())?;
+
+        assert_eq!(db.get_block_hash_by_height(1)?, Some(main.hash()));
+        assert!(db.get_block(&side.hash())?.is_some());
+        assert!(db.get_stale_block_info(&main.hash())?.is_none());
+
+        let stale = db.get_stale_blocks()?;
+        assert_eq!(stale.len(), 1);
+        assert_eq!(stale[0].hash, side.hash());
+        assert_eq!(stale[0].fork_point, genesis.hash());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_encrypted_data_directory() -> Result<()> {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("test.db");
+        let passphrase = |p: &str| StorageEncryption { secret: StorageSecret::Passphrase(p.to_string()), encrypt_blocks: true };
+        let block = test_block(1);
+        let outpoint = OutPoint::new(block.transactions[0].hash(), 0);
+        let utxo = UtxoEntry {
+            txid: outpoint.txid,
+            vout: 0,
+            value: 2710000000,
+            script_pubkey: vec![],
+            address: "qtc1qtest".to_string(),
+            height: 1,
+            is_coinbase: true,
+        };
+
+        {
+            let db = Database::with_encryption(&path, Some(&passphrase("hunter2")))?;
+            db.save_block(&block)?;
+            db.save_utxo(&outpoint, &utxo)?;
+            let raw = db.get_tree(TREE_UTXOS)?.get(db.outpoint_to_key(&outpoint))?.unwrap();
+            assert_ne!(raw.to_vec(), bincode::serialize(&utxo).unwrap());
+            db.rotate_encryption_key(&StorageSecret::Passphrase("correct horse".to_string()))?;
+        }
+
+        assert!(Database::new(&path).is_err());
+        assert!(Database::with_encryption(&path, Some(&passphrase("hunter2"))).is_err());
+        {
+            let db = Database::with_encryption(&path, Some(&passphrase("correct horse")))?;
+            assert_eq!(db.get_utxo(&outpoint)?.unwrap().value, utxo.value);
+            assert_eq!(db.get_address_balance("qtc1qtest")?, utxo.value);
+            assert_eq!(db.get_block_by_height(1)?.unwrap().hash(), block.hash());
+            
+            // A sealed value copied under another key doesn't open there
+            let utxos = db.get_tree(TREE_UTXOS)?;
+            let sealed = utxos.get(db.outpoint_to_key(&outpoint))?.unwrap();
+            let elsewhere = OutPoint::new(outpoint.txid, 1);
+            utxos.insert(db.outpoint_to_key(&elsewhere), sealed)?;
+            assert!(db.get_utxo(&elsewhere).is_err());
+            utxos.remove(db.outpoint_to_key(&elsewhere))?;
+            
+            // Values sealed by older versions are bound to their keys on the next unlock
+            let legacy = db.cipher.as_ref().unwrap().seal_legacy(TREE_UTXOS, &bincode::serialize(&utxo).unwrap());
+            utxos.insert(db.outpoint_to_key(&outpoint), legacy)?;
+            db.get_tree(TREE_ENCRYPTION)?.remove(KEYED_VALUES_KEY)?;
+            assert!(db.get_utxo(&outpoint).is_err());
+        }
+        {
+            let db = Database::with_encryption(&path, Some(&passphrase("correct horse")))?;
+            assert_eq!(db.get_utxo(&outpoint)?.unwrap().value, utxo.value);
+            let raw = db.get_tree(TREE_UTXOS)?.get(db.outpoint_to_key(&outpoint))?.unwrap();
+            assert!(!is_legacy_sealed(&raw));
+        }
+
+        // An existing plain directory has to go through `db encrypt`, which can't redo block files
+        let plain_path = temp_dir.path().join("plain.db");
+        Database::new(&plain_path)?.save_utxo(&outpoint, &utxo)?;
+        assert!(Database::with_encryption(&plain_path, Some(&passphrase("pw"))).is_err());
+        assert!(Database::encrypt_existing(&plain_path, &passphrase("pw")).is_ok());
+        let db = Database::with_encryption(&plain_path, Some(&StorageEncryption { encrypt_blocks: false, ..passphrase("pw") }))?;
+        assert_eq!(db.get_utxo(&outpoint)?.unwrap().address, utxo.address);
+        Ok(())
+    }
+}
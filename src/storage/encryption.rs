@@ -0,0 +1,182 @@
+//! Encryption at rest for the data directory
+//!
+//! Values in the wallet and chainstate trees are sealed with AES-256-GCM under
+//! a random data key, and so are block files and the transaction index when a
+//! new data directory is created with `encrypt_blocks`. The data key is stored
+//! wrapped by a key derived from the node passphrase or key file, so rotating
+//! the passphrase only rewraps it. Keys stay in the clear so lookups and prefix
+//! scans keep working, and each value is bound to its tree and key so a sealed
+//! value copied under another key fails to open.
+
+use crate::crypto::encryption::{decrypt_with_password, encrypt_with_password, EncryptedPayload};
+use crate::{QtcError, Result};
+use aes_gcm::aead::{Aead, KeyInit, Payload};
+use aes_gcm::{Aes256Gcm, Nonce};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/// First byte of every sealed value, so the format can change later
+const SEALED_VERSION: u8 = 2;
+/// Values sealed by older versions, authenticated with the tree name alone
+const LEGACY_SEALED_VERSION: u8 = 1;
+const NONCE_SIZE: usize = 12;
+
+/// What unlocks the data key
+#[derive(Debug, Clone)]
+pub enum StorageSecret {
+    Passphrase(String),
+    KeyFile(PathBuf),
+}
+
+impl StorageSecret {
+    /// Password material for the key derivation; key files may hold raw bytes
+    fn material(&self) -> Result<String> {
+        match self {
+            StorageSecret::Passphrase(passphrase) if passphrase.is_empty() => {
+                Err(QtcError::InvalidInput("Storage passphrase must not be empty".to_string()))
+            }
+            StorageSecret::Passphrase(passphrase) => Ok(passphrase.clone()),
+            StorageSecret::KeyFile(path) => {
+                let contents = std::fs::read(path)
+                    .map_err(|e| QtcError::Storage(format!("Failed to read key file {}: {}", path.display(), e)))?;
+                let contents = contents.trim_ascii();
+                if contents.is_empty() {
+                    return Err(QtcError::Storage(format!("Key file {} is empty", path.display())));
+                }
+                Ok(hex::encode(contents))
+            }
+        }
+    }
+}
+
+/// How a data directory should be encrypted and unlocked
+#[derive(Debug, Clone)]
+pub struct StorageEncryption {
+    pub secret: StorageSecret,
+    pub encrypt_blocks: bool, // only honoured when the data directory is created
+}
+
+/// The wrapped data key, stored in the database it protects
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeyEnvelope {
+    pub wrapped_key: EncryptedPayload,
+    pub encrypt_blocks: bool,
+    pub created_at: u64,
+    pub rotated_at: Option<u64>,
+    pub pending_trees: Vec<String>, // trees `db encrypt` has yet to seal
+}
+
+impl KeyEnvelope {
+    /// A fresh data key, wrapped under `secret`
+    pub fn create(secret: &StorageSecret, encrypt_blocks: bool) -> Result<(Self, StorageCipher)> {
+        let mut key = [0u8; 32];
+        rand::thread_rng().fill_bytes(&mut key);
+
+        let envelope = Self {
+            wrapped_key: encrypt_with_password(&key, &secret.material()?)?,
+            encrypt_blocks,
+            created_at: chrono::Utc::now().timestamp() as u64,
+            rotated_at: None,
+            pending_trees: Vec::new(),
+        };
+        Ok((envelope, StorageCipher::new(key, encrypt_blocks)))
+    }
+
+    pub fn unlock(&self, secret: &StorageSecret) -> Result<StorageCipher> {
+        let key = decrypt_with_password(&self.wrapped_key, &secret.material()?)
+            .map_err(|_| QtcError::Storage("Failed to unlock the data directory (wrong passphrase or key file?)".to_string()))?;
+        let key: [u8; 32] = key.try_into()
+            .map_err(|_| QtcError::Storage("Stored data key has the wrong length".to_string()))?;
+        Ok(StorageCipher::new(key, self.encrypt_blocks))
+    }
+
+    /// The same data key wrapped under `secret` instead
+    pub fn rewrap(&self, cipher: &StorageCipher, secret: &StorageSecret) -> Result<Self> {
+        Ok(Self {
+            wrapped_key: encrypt_with_password(&cipher.key, &secret.material()?)?,
+            rotated_at: Some(chrono::Utc::now().timestamp() as u64),
+            ..self.clone()
+        })
+    }
+}
+
+/// Seals and opens stored values with the unlocked data key
+pub struct StorageCipher {
+    key: [u8; 32],
+    cipher: Aes256Gcm,
+    encrypt_blocks: bool,
+}
+
+impl std::fmt::Debug for StorageCipher {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("StorageCipher").field("encrypt_blocks", &self.encrypt_blocks).finish_non_exhaustive()
+    }
+}
+
+impl StorageCipher {
+    fn new(key: [u8; 32], encrypt_blocks: bool) -> Self {
+        let cipher = Aes256Gcm::new((&key).into());
+        Self { key, cipher, encrypt_blocks }
+    }
+
+    pub fn encrypt_blocks(&self) -> bool {
+        self.encrypt_blocks
+    }
+
+    /// Encrypt `plaintext` stored under `key`; `context` (the tree name) and `key` must
+    /// match when opening
+    pub fn seal(&self, context: &str, key: &[u8], plaintext: &[u8]) -> Result<Vec<u8>> {
+        let mut nonce = [0u8; NONCE_SIZE];
+        rand::thread_rng().fill_bytes(&mut nonce);
+        let aad = associated_data(context, key);
+        let ciphertext = self.cipher.encrypt(Nonce::from_slice(&nonce), Payload { msg: plaintext, aad: &aad })
+            .map_err(|_| QtcError::Storage("Failed to encrypt stored value".to_string()))?;
+
+        let mut sealed = Vec::with_capacity(1 + NONCE_SIZE + ciphertext.len());
+        sealed.push(SEALED_VERSION);
+        sealed.extend_from_slice(&nonce);
+        sealed.extend_from_slice(&ciphertext);
+        Ok(sealed)
+    }
+
+    pub fn open(&self, context: &str, key: &[u8], sealed: &[u8]) -> Result<Vec<u8>> {
+        self.decrypt(context, SEALED_VERSION, &associated_data(context, key), sealed)
+    }
+
+    /// Open a value sealed by an older version, which didn't bind it to its key
+    pub fn open_legacy(&self, context: &str, sealed: &[u8]) -> Result<Vec<u8>> {
+        self.decrypt(context, LEGACY_SEALED_VERSION, context.as_bytes(), sealed)
+    }
+
+    /// A value as older versions sealed it, to test the upgrade
+    #[cfg(test)]
+    pub(crate) fn seal_legacy(&self, context: &str, plaintext: &[u8]) -> Vec<u8> {
+        let nonce = [7u8; NONCE_SIZE];
+        let ciphertext = self.cipher.encrypt(Nonce::from_slice(&nonce), Payload { msg: plaintext, aad: context.as_bytes() }).unwrap();
+        [&[LEGACY_SEALED_VERSION][..], &nonce, &ciphertext].concat()
+    }
+
+    fn decrypt(&self, context: &str, version: u8, aad: &[u8], sealed: &[u8]) -> Result<Vec<u8>> {
+        if sealed.len() < 1 + NONCE_SIZE || sealed[0] != version {
+            return Err(QtcError::Storage(format!("Stored value in {} is not encrypted", context)));
+        }
+        let (nonce, ciphertext) = sealed[1..].split_at(NONCE_SIZE);
+        self.cipher.decrypt(Nonce::from_slice(nonce), Payload { msg: ciphertext, aad })
+            .map_err(|_| QtcError::Storage(format!("Failed to decrypt stored value in {}", context)))
+    }
+}
+
+/// Whether `sealed` was written by an older version and should be sealed again
+pub fn is_legacy_sealed(sealed: &[u8]) -> bool {
+    sealed.first() == Some(&LEGACY_SEALED_VERSION)
+}
+
+/// Tree name and record key, split by a NUL no tree name contains
+fn associated_data(context: &str, key: &[u8]) -> Vec<u8> {
+    let mut aad = Vec::with_capacity(context.len() + 1 + key.len());
+    aad.extend_from_slice(context.as_bytes());
+    aad.push(0);
+    aad.extend_from_slice(key);
+    aad
+}
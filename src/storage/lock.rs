@@ -0,0 +1,213 @@
+//! Exclusive use of a data directory by one process
+//!
+//! sled already locks its own files, but a second process only gets an opaque
+//! "could not acquire lock" error from it, and the lock doesn't cover the block
+//! files, control socket and logs beside the database. Every command that opens
+//! the database first creates `.lock` in the data directory, holding its PID,
+//! start time and command, and removes it on exit. Another process finding the file
+//! names whoever holds it. A process that dies without cleaning up leaves
+//! the file behind; `--force-unlock` removes it once its PID is confirmed
+//! to be gone.
+
+use crate::{QtcError, Result};
+use std::fs::{self, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+pub const LOCK_FILE: &str = ".lock";
+
+/// Who holds a data directory, as written in its lock file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LockHolder {
+    pub pid: u32,
+    pub started_at: u64,
+    pub command: String,
+}
+
+impl LockHolder {
+    fn current(command: &str) -> Self {
+        Self {
+            pid: std::process::id(),
+            started_at: chrono::Utc::now().timestamp() as u64,
+            command: command.to_string(),
+        }
+    }
+
+    fn parse(text: &str) -> Option<Self> {
+        let mut lines = text.lines();
+        Some(Self {
+            pid: lines.next()?.trim().parse().ok()?,
+            started_at: lines.next().and_then(|line| line.trim().parse().ok()).unwrap_or(0),
+            command: lines.next().unwrap_or_default().to_string(),
+        })
+    }
+
+    fn serialize(&self) -> String {
+        format!("{}\n{}\n{}\n", self.pid, self.started_at, self.command)
+    }
+
+    pub fn is_running(&self) -> bool {
+        process_exists(self.pid)
+    }
+}
+
+impl std::fmt::Display for LockHolder {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "process {}", self.pid)?;
+        if !self.command.is_empty() {
+            write!(f, " (`{}`)", self.command)?;
+        }
+        if let Some(time) = chrono::DateTime::from_timestamp(self.started_at as i64, 0).filter(|_| self.started_at > 0) {
+            write!(f, ", since {}", time.format("%Y-%m-%d %H:%M:%S UTC"))?;
+        }
+        Ok(())
+    }
+}
+
+/// The lock on a data directory, released when dropped
+#[derive(Debug)]
+pub struct DataDirLock {
+    path: PathBuf,
+}
+
+impl DataDirLock {
+    /// Lock `data_dir` for this process, recording `command` (never its
+    /// arguments, which may hold secrets) for whoever finds it locked. With
+    /// `force`, a lock left by a process that is no longer running is removed
+    /// first; a live holder always wins.
+    pub fn acquire(data_dir: &Path, command: &str, force: bool) -> Result<Self> {
+        // Absolute, so it is still found after a daemon changes directory
+        let path = std::path::absolute(data_dir.join(LOCK_FILE))?;
+        let holder = LockHolder::current(command);
+        loop {
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(mut file) => {
+                    file.write_all(holder.serialize().as_bytes())?;
+                    file.sync_all()?;
+                    return Ok(Self { path });
+                }
+                Err(e) if e.kind() == ErrorKind::AlreadyExists => {}
+                Err(e) => return Err(e.into()),
+            }
+
+            let existing = match fs::read_to_string(&path) {
+                Ok(text) => LockHolder::parse(&text),
+                Err(e) if e.kind() == ErrorKind::NotFound => continue, // released while we looked
+                Err(e) => return Err(e.into()),
+            };
+            match existing {
+                Some(existing) if existing.is_running() => {
+                    return Err(QtcError::Storage(format!(
+                        "Data directory {} is in use by {}; stop it before running this command",
+                        data_dir.display(), existing
+                    )));
+                }
+                Some(existing) if !force => {
+                    return Err(QtcError::Storage(format!(
+                        "Data directory {} has a stale lock from {}, which is no longer running. \
+                         If no other qtcd uses this directory, run again with --force-unlock",
+                        data_dir.display(), existing
+                    )));
+                }
+                None if !force => {
+                    return Err(QtcError::Storage(format!(
+                        "Data directory {} has an unreadable lock file {}. \
+                         If no other qtcd uses this directory, run again with --force-unlock",
+                        data_dir.display(), path.display()
+                    )));
+                }
+                stale => {
+                    match stale {
+                        Some(stale) => log::warn!("🔓 Removing the stale lock left by {}", stale),
+                        None => log::warn!("🔓 Removing the unreadable lock file {}", path.display()),
+                    }
+                    match fs::remove_file(&path) {
+                        Ok(()) => {}
+                        Err(e) if e.kind() == ErrorKind::NotFound => {}
+                        Err(e) => return Err(e.into()),
+                    }
+                }
+            }
+        }
+    }
+
+    /// Who holds `data_dir`, if anyone has it locked
+    pub fn holder(data_dir: &Path) -> Result<Option<LockHolder>> {
+        match fs::read_to_string(data_dir.join(LOCK_FILE)) {
+            Ok(text) => Ok(LockHolder::parse(&text)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Record this process as the holder of the lock its parent took, after daemonizing
+    pub fn take_over(data_dir: &Path) -> Result<()> {
+        let path = data_dir.join(LOCK_FILE);
+        let mut holder = fs::read_to_string(&path).ok()
+            .and_then(|text| LockHolder::parse(&text))
+            .ok_or_else(|| QtcError::Storage(format!("No lock to take over in {}", data_dir.display())))?;
+        holder.pid = std::process::id();
+        let temp = path.with_extension("partial");
+        fs::write(&temp, holder.serialize())?;
+        fs::rename(&temp, &path)?;
+        Ok(())
+    }
+}
+
+impl Drop for DataDirLock {
+    fn drop(&mut self) {
+        // Only our own lock; a forked parent exits without dropping it
+        let ours = fs::read_to_string(&self.path).ok()
+            .and_then(|text| LockHolder::parse(&text))
+            .is_some_and(|holder| holder.pid == std::process::id());
+        if ours {
+            if let Err(e) = fs::remove_file(&self.path) {
+                log::warn!("🔓 Failed to remove the lock file {}: {}", self.path.display(), e);
+            }
+        }
+    }
+}
+
+fn process_exists(pid: u32) -> bool {
+    let Ok(pid) = libc::pid_t::try_from(pid) else {
+        return false;
+    };
+    if pid <= 0 {
+        return false;
+    }
+    // Signal 0 checks the process exists without touching it; EPERM means it does, as another user
+    let signalled = unsafe { libc::kill(pid, 0) == 0 };
+    signalled || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_lock_excludes_and_recovers_from_dead_holders() -> Result<()> {
+        let temp_dir = TempDir::new().unwrap();
+        let data_dir = temp_dir.path();
+
+        let lock = DataDirLock::acquire(data_dir, "qtcd shell", false)?;
+        let holder = DataDirLock::holder(data_dir)?.unwrap();
+        assert_eq!((holder.pid, holder.command.as_str()), (std::process::id(), "qtcd shell"));
+        // A live holder can't be forced out
+        let err = DataDirLock::acquire(data_dir, "qtcd start", true).unwrap_err();
+        assert!(err.to_string().contains(&format!("process {}", holder.pid)), "{}", err);
+        drop(lock);
+        assert!(DataDirLock::holder(data_dir)?.is_none());
+
+        // A crashed holder leaves its file behind, which only --force-unlock clears
+        let dead = LockHolder { pid: i32::MAX as u32, started_at: 1, command: "qtcd start".to_string() };
+        assert!(!dead.is_running());
+        fs::write(data_dir.join(LOCK_FILE), dead.serialize())?;
+        let err = DataDirLock::acquire(data_dir, "qtcd start", false).unwrap_err();
+        assert!(err.to_string().contains("--force-unlock"), "{}", err);
+        let lock = DataDirLock::acquire(data_dir, "qtcd start", true)?;
+        assert_eq!(DataDirLock::holder(data_dir)?.unwrap().pid, std::process::id());
+        drop(lock);
+        Ok(())
+    }
+}
@@ -1,5 +1,16 @@
 //! Storage module for persistent data
 
+pub mod backup;
+pub mod blockfiles;
 pub mod database;
+pub mod encryption;
+pub mod lock;
+pub mod upgrade;
+pub mod utxo_snapshot;
 
+pub use backup::{create_backup, BackupReport};
+pub use blockfiles::{BlockFileStore, BlockPosition};
 pub use database::Database;
+pub use encryption::{StorageEncryption, StorageSecret};
+pub use upgrade::{UpgradePlan, SCHEMA_VERSION};
+pub use utxo_snapshot::{SnapshotInfo, UtxoSnapshot};
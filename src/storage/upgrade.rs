@@ -0,0 +1,171 @@
+//! What opening a data directory with this build will do to it
+//!
+//! Most upgrades happen on their own when the database opens: legacy tables
+//! are moved or indexed in place. Some need every block replayed, and a few
+//! can't be done automatically at all. `UpgradePlan` lists the work ahead of
+//! time with a rough duration, so operators can schedule it, and gives scripts
+//! an exit status to act on.
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Layout version this build writes; bump it whenever a migration is added
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Exit statuses for `qtcd start --check-upgrade`
+pub const EXIT_UP_TO_DATE: i32 = 0;
+pub const EXIT_MIGRATIONS: i32 = 3;
+pub const EXIT_REINDEX: i32 = 4;
+pub const EXIT_BLOCKED: i32 = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UpgradeKind {
+    Migration, // rewrites part of the database in place on startup
+    Reindex,   // replays blocks on startup
+    Blocked,   // needs the operator to act before the node will start
+}
+
+impl fmt::Display for UpgradeKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            UpgradeKind::Migration => write!(f, "migration"),
+            UpgradeKind::Reindex => write!(f, "reindex"),
+            UpgradeKind::Blocked => write!(f, "action required"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UpgradeStep {
+    pub kind: UpgradeKind,
+    pub description: String,
+    pub estimated_secs: u64,
+}
+
+impl UpgradeStep {
+    /// A step over `items` records processed at about `per_sec` a second
+    pub fn new(kind: UpgradeKind, description: String, items: u64, per_sec: u64) -> Self {
+        Self { kind, description, estimated_secs: items.div_ceil(per_sec.max(1)) }
+    }
+
+    pub fn blocked(description: String) -> Self {
+        Self { kind: UpgradeKind::Blocked, description, estimated_secs: 0 }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct UpgradePlan {
+    pub schema_version: Option<u32>, // None for a new or pre-versioning data directory
+    pub steps: Vec<UpgradeStep>,
+}
+
+impl UpgradePlan {
+    pub fn is_up_to_date(&self) -> bool {
+        self.steps.is_empty()
+    }
+
+    pub fn count(&self, kind: UpgradeKind) -> usize {
+        self.steps.iter().filter(|step| step.kind == kind).count()
+    }
+
+    pub fn estimated_secs(&self) -> u64 {
+        self.steps.iter().map(|step| step.estimated_secs).sum()
+    }
+
+    /// The status `--check-upgrade` exits with: the most serious kind of step pending
+    pub fn exit_code(&self) -> i32 {
+        match self.steps.iter().map(|step| step.kind).max() {
+            None => EXIT_UP_TO_DATE,
+            Some(UpgradeKind::Migration) => EXIT_MIGRATIONS,
+            Some(UpgradeKind::Reindex) => EXIT_REINDEX,
+            Some(UpgradeKind::Blocked) => EXIT_BLOCKED,
+        }
+    }
+
+    /// One line, e.g. `migrations: 2, reindex: 0, estimated time: 10m`
+    pub fn summary(&self) -> String {
+        let mut summary = format!(
+            "migrations: {}, reindex: {}, estimated time: {}",
+            self.count(UpgradeKind::Migration),
+            self.count(UpgradeKind::Reindex),
+            format_estimate(self.estimated_secs())
+        );
+        let blocked = self.count(UpgradeKind::Blocked);
+        if blocked > 0 {
+            summary.push_str(&format!(", blocked: {}", blocked));
+        }
+        summary
+    }
+}
+
+/// Round a duration up to the unit an operator plans with: `40s`, `10m`, `2h 5m`
+pub fn format_estimate(secs: u64) -> String {
+    match secs {
+        0 => "none".to_string(),
+        1..=59 => format!("{}s", secs),
+        60..=3599 => format!("{}m", secs.div_ceil(60)),
+        _ => {
+            let minutes = secs.div_ceil(60);
+            match minutes % 60 {
+                0 => format!("{}h", minutes / 60),
+                rest => format!("{}h {}m", minutes / 60, rest),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::Blockchain;
+    use crate::storage::Database;
+    use crate::Result;
+    use std::sync::Arc;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_upgrade_plan() -> Result<()> {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("qtc.db");
+        assert!(Database::upgrade_plan(&path, None, false)?.is_up_to_date());
+
+        {
+            let db = Arc::new(Database::new(&path)?);
+            let chain = Blockchain::new(db.clone())?;
+            chain.flush_utxos()?;
+        }
+        let plan = Database::upgrade_plan(&path, None, false)?;
+        assert_eq!(plan.schema_version, Some(SCHEMA_VERSION));
+        assert_eq!(plan.exit_code(), EXIT_UP_TO_DATE);
+
+        // Make it look like a pre-versioning directory without the address index
+        {
+            let db = sled::open(&path).unwrap();
+            db.drop_tree("meta").unwrap();
+            db.drop_tree("address_utxos").unwrap();
+        }
+        let plan = Database::upgrade_plan(&path, None, true)?;
+        assert_eq!(plan.schema_version, None);
+        assert_eq!(plan.count(UpgradeKind::Migration), 1);
+        assert_eq!(plan.count(UpgradeKind::Reindex), 1);
+        assert_eq!(plan.exit_code(), EXIT_REINDEX);
+        assert_eq!(plan.summary(), "migrations: 1, reindex: 1, estimated time: 2s");
+
+        // Opening migrates; the address history is built later, by the blockchain
+        drop(Database::new(&path)?);
+        let plan = Database::upgrade_plan(&path, None, true)?;
+        assert_eq!((plan.count(UpgradeKind::Migration), plan.exit_code()), (0, EXIT_REINDEX));
+
+        // A newer build's data directory is refused rather than half-upgraded
+        sled::open(&path).unwrap().open_tree("meta").unwrap()
+            .insert(b"schema_version", &(SCHEMA_VERSION + 1).to_be_bytes()).unwrap();
+        assert_eq!(Database::upgrade_plan(&path, None, false)?.exit_code(), EXIT_BLOCKED);
+        assert!(Database::new(&path).is_err());
+
+        assert_eq!(format_estimate(45), "45s");
+        assert_eq!(format_estimate(540), "9m");
+        assert_eq!(format_estimate(7_500), "2h 5m");
+        Ok(())
+    }
+}
@@ -0,0 +1,289 @@
+//! UTXO set snapshots, for bootstrapping a node without replaying the chain
+//!
+//! A snapshot holds the chain state at the tip, the headers of every main
+//! chain block up to it and the unspent outputs as of that block, behind a
+//! hash over all of it. Loading one into a fresh data directory leaves it
+//! looking like a node pruned up to the snapshot height: headers without
+//! bodies and a UTXO set it never built itself, after which sync carries on
+//! from the next block.
+//!
+//! The header chain is checked, but the outputs are taken on trust
+//! (assume-valid style), so compare the hash with one published by a source
+//! you trust before loading.
+
+use crate::core::blockchain::ChainState;
+use crate::core::transaction::OutPoint;
+use crate::core::{BlockHeader, Blockchain, UtxoEntry};
+use crate::crypto::hash::{Hash256, Hashable};
+use crate::storage::database::BlockWorkEntry;
+use crate::storage::Database;
+use crate::{QtcError, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+/// Leads every snapshot file, ahead of the hash and the bincode body
+const SNAPSHOT_MAGIC: &[u8; 8] = b"QTCUTXO\x01";
+
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UtxoSnapshot {
+    pub version: u32,
+    pub state: ChainState,
+    pub headers: Vec<BlockHeader>, // one per height, genesis first
+    pub utxos: Vec<(OutPoint, UtxoEntry)>,
+}
+
+/// What a snapshot holds, as shown after creating or loading one
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SnapshotInfo {
+    pub hash: Hash256, // over the whole body; publish it next to the file
+    pub height: u64,
+    pub tip: Hash256,
+    pub utxo_count: usize,
+    pub total_value: u64,
+    pub size: u64, // bytes
+}
+
+impl UtxoSnapshot {
+    /// Take a snapshot of the chain at its tip
+    pub fn from_chain(blockchain: &Blockchain) -> Result<Self> {
+        blockchain.flush_utxos()?;
+        let db = blockchain.database();
+        if db.get_utxo_tip()?.is_some_and(|tip| tip != blockchain.tip) {
+            return Err(QtcError::Storage("The stored UTXO set is behind the chain tip".to_string()));
+        }
+        let state = db.get_chain_state()?
+            .ok_or_else(|| QtcError::Blockchain("No chain state to snapshot".to_string()))?;
+
+        let headers = (0..=state.height)
+            .map(|height| blockchain.get_block_header_by_height(height)?
+                .ok_or_else(|| QtcError::Blockchain(format!("Missing header at height {}", height))))
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut utxos = db.get_all_utxos()?;
+        utxos.sort_by(|(a, _), (b, _)| (a.txid.as_bytes(), a.vout).cmp(&(b.txid.as_bytes(), b.vout)));
+
+        Ok(Self { version: SNAPSHOT_VERSION, state, headers, utxos })
+    }
+
+    /// Check the snapshot holds together: a linked header chain ending at the
+    /// tip, each header meeting its difficulty, and the stated chain work
+    pub fn verify(&self) -> Result<()> {
+        let invalid = |reason: String| Err(QtcError::InvalidInput(format!("Invalid snapshot: {}", reason)));
+
+        if self.version != SNAPSHOT_VERSION {
+            return invalid(format!("unsupported version {}", self.version));
+        }
+        if self.headers.len() as u64 != self.state.height + 1 {
+            return invalid(format!("{} headers for height {}", self.headers.len(), self.state.height));
+        }
+
+        let mut previous = Hash256::zero();
+        let mut total_work = 0u128;
+        for (height, header) in self.headers.iter().enumerate() {
+            if header.height != height as u64 || header.previous_hash != previous {
+                return invalid(format!("header chain breaks at height {}", height));
+            }
+            if height > 0 && !Blockchain::header_meets_target(header) {
+                return invalid(format!("header at height {} does not meet its difficulty", height));
+            }
+            total_work = total_work.saturating_add(Blockchain::block_work(header.bits));
+            previous = header.hash();
+        }
+        if previous != self.state.tip {
+            return invalid(format!("headers end at {}, not the tip {}", previous, self.state.tip));
+        }
+        if total_work != self.state.total_work {
+            return invalid(format!("chain work is {}, not the stated {}", total_work, self.state.total_work));
+        }
+
+        for window in self.utxos.windows(2) {
+            let (a, b) = (&window[0].0, &window[1].0);
+            if (a.txid.as_bytes(), a.vout) >= (b.txid.as_bytes(), b.vout) {
+                return invalid(format!("outputs out of order at {}:{}", b.txid, b.vout));
+            }
+        }
+        if let Some((outpoint, _)) = self.utxos.iter().find(|(_, utxo)| utxo.height > self.state.height) {
+            return invalid(format!("output {}:{} is from after the tip", outpoint.txid, outpoint.vout));
+        }
+        Ok(())
+    }
+
+    pub fn total_value(&self) -> u64 {
+        self.utxos.iter().map(|(_, utxo)| utxo.value).fold(0, u64::saturating_add)
+    }
+
+    /// Write the snapshot to `path` through a temporary file
+    pub fn write(&self, path: &Path) -> Result<SnapshotInfo> {
+        let body = bincode::serialize(self)
+            .map_err(|e| QtcError::Storage(format!("Failed to serialize snapshot: {}", e)))?;
+        let hash = Hash256::hash(&body);
+
+        let mut data = Vec::with_capacity(SNAPSHOT_MAGIC.len() + 32 + body.len());
+        data.extend_from_slice(SNAPSHOT_MAGIC);
+        data.extend_from_slice(hash.as_bytes());
+        data.extend_from_slice(&body);
+
+        let temp = path.with_extension("partial");
+        fs::write(&temp, &data)?;
+        fs::rename(&temp, path)?;
+        Ok(self.info(hash, data.len() as u64))
+    }
+
+    /// Read and verify a snapshot, rejecting it if its contents don't match its hash
+    pub fn read(path: &Path) -> Result<(Self, SnapshotInfo)> {
+        let data = fs::read(path)?;
+        let header_len = SNAPSHOT_MAGIC.len() + 32;
+        if data.len() < header_len || &data[..SNAPSHOT_MAGIC.len()] != SNAPSHOT_MAGIC {
+            return Err(QtcError::InvalidInput(format!("{} is not a UTXO snapshot", path.display())));
+        }
+
+        let hash = Hash256::from_slice(&data[SNAPSHOT_MAGIC.len()..header_len])
+            .ok_or_else(|| QtcError::InvalidInput("Invalid snapshot hash".to_string()))?;
+        let body = &data[header_len..];
+        if Hash256::hash(body) != hash {
+            return Err(QtcError::InvalidInput(format!("{} is corrupt: its contents don't match its hash", path.display())));
+        }
+
+        let snapshot: Self = bincode::deserialize(body)
+            .map_err(|e| QtcError::InvalidInput(format!("Invalid snapshot: {}", e)))?;
+        snapshot.verify()?;
+        let info = snapshot.info(hash, data.len() as u64);
+        Ok((snapshot, info))
+    }
+
+    /// Bootstrap `db` from the snapshot. The database must hold a new chain
+    /// with the snapshot's genesis block and nothing past it; open it as a
+    /// `Blockchain` again afterwards to continue from the snapshot height.
+    pub fn load_into(&self, db: &Database) -> Result<()> {
+        let state = db.get_chain_state()?.unwrap_or_default();
+        if state.height > 0 {
+            return Err(QtcError::InvalidInput(format!(
+                "A snapshot can only be loaded into a new data directory; this chain is at height {}", state.height
+            )));
+        }
+        let genesis = self.headers[0].hash();
+        if db.get_block_hash_by_height(0)? != Some(genesis) {
+            return Err(QtcError::InvalidInput(format!("The snapshot is of another chain (genesis {})", genesis)));
+        }
+
+        // Marked for the whole load, so a crash part way through is noticed on the next start
+        db.set_utxo_flushing(true)?;
+        db.clear_utxo_set()?;
+        for (outpoint, utxo) in &self.utxos {
+            db.save_utxo(outpoint, utxo)?;
+        }
+
+        let mut chain_work = Blockchain::block_work(self.headers[0].bits);
+        for header in &self.headers[1..] {
+            let hash = db.save_pruned_header(header)?;
+            chain_work = chain_work.saturating_add(Blockchain::block_work(header.bits));
+            db.save_block_work(&BlockWorkEntry {
+                hash,
+                previous_hash: header.previous_hash,
+                height: header.height,
+                chain_work,
+            })?;
+        }
+
+        db.set_utxo_tip(&self.state.tip)?;
+        if self.state.height > 0 {
+            db.set_pruned_height(self.state.height)?;
+        }
+        db.set_address_history_complete(false)?;
+        db.save_chain_state(&self.state)?;
+        db.set_utxo_flushing(false)?;
+        db.flush()
+    }
+
+    fn info(&self, hash: Hash256, size: u64) -> SnapshotInfo {
+        SnapshotInfo {
+            hash,
+            height: self.state.height,
+            tip: self.state.tip,
+            utxo_count: self.utxos.len(),
+            total_value: self.total_value(),
+            size,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::consensus::target::REGTEST_BITS;
+    use crate::consensus::ChainParams;
+    use crate::core::{Block, Transaction};
+    use std::sync::Arc;
+    use tempfile::TempDir;
+
+    /// A chain whose blocks need only a bit of work and whose difficulty never adjusts
+    fn easy_chain(db: Arc<Database>) -> Result<Blockchain> {
+        let mut chain = Blockchain::new(db)?;
+        chain.set_chain_params(ChainParams {
+            initial_bits: REGTEST_BITS,
+            difficulty_adjustment_interval: 1_000_000,
+            ..ChainParams::mainnet()
+        });
+        Ok(chain)
+    }
+
+    fn mine_next(chain: &Blockchain) -> Result<Block> {
+        let parent = chain.get_block_header_by_height(chain.height)?.unwrap();
+        let height = chain.height + 1;
+        let coinbase = Transaction::new_coinbase(
+            "qtc1snapshotminer".to_string(),
+            chain.monetary_policy().coinbase_reward(height),
+            format!("height {}", height),
+        );
+        let mut block = Block::new(chain.tip, vec![coinbase], REGTEST_BITS, height);
+        block.header.timestamp = parent.timestamp + 30;
+        while !chain.is_valid_proof_of_work(&block) {
+            block.increment_nonce();
+        }
+        Ok(block)
+    }
+
+    #[test]
+    fn test_snapshot_bootstraps_a_new_node() -> Result<()> {
+        let temp_dir = TempDir::new().unwrap();
+        let mut chain = easy_chain(Arc::new(Database::new(temp_dir.path().join("source.db"))?))?;
+        for _ in 0..3 {
+            let block = mine_next(&chain)?;
+            chain.add_block(block)?;
+        }
+
+        let path = temp_dir.path().join("utxo.snapshot");
+        let created = UtxoSnapshot::from_chain(&chain)?.write(&path)?;
+        assert_eq!(created.height, 3);
+
+        let (snapshot, info) = UtxoSnapshot::read(&path)?;
+        assert_eq!(info.hash, created.hash);
+        assert_eq!(info.utxo_count, chain.database().get_all_utxos()?.len());
+
+        let target = Arc::new(Database::new(temp_dir.path().join("target.db"))?);
+        drop(easy_chain(target.clone())?);
+        snapshot.load_into(&target)?;
+        assert!(snapshot.load_into(&target).is_err());
+
+        // Picks up from the snapshot like a pruned node and follows the chain from there
+        let mut restored = easy_chain(target.clone())?;
+        assert_eq!((restored.height, restored.tip, restored.total_work), (chain.height, chain.tip, chain.total_work));
+        assert_eq!(target.get_pruned_height()?, Some(3));
+        assert!(restored.get_block_by_height(2)?.is_none());
+
+        let next = mine_next(&chain)?;
+        chain.add_block(next.clone())?;
+        restored.add_block(next)?;
+        assert_eq!(restored.tip, chain.tip);
+
+        let mut data = std::fs::read(&path)?;
+        let last = data.len() - 1;
+        data[last] ^= 1;
+        std::fs::write(&path, data)?;
+        assert!(UtxoSnapshot::read(&path).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,342 @@
+//! Cached wallet balances
+//!
+//! Summing every address on each `get_balance` call gets slow for wallets with
+//! hundreds of addresses. Instead each wallet keeps a per-address total pinned to
+//! a chain tip, advanced block by block from `ChainEvent`s while the node runs and
+//! from undo data when a wallet is opened after the chain has moved.
+
+use crate::core::transaction::OutPoint;
+use crate::core::{Block, Blockchain, ChainEvent, Transaction, UtxoEntry, UtxoSet};
+use crate::crypto::hash::{Hash256, Hashable};
+use crate::node::ShutdownSignal;
+use crate::storage::Database;
+use crate::{QtcError, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, RwLock};
+use tokio::sync::broadcast;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct WalletBalance {
+    pub tip: Hash256,
+    pub height: u64,
+    pub confirmed: BTreeMap<String, u64>,
+    /// Net effect of each mempool transaction; only known to the process holding the mempool
+    #[serde(skip)]
+    pub pending: HashMap<Hash256, i64>,
+}
+
+impl WalletBalance {
+    /// Sum the UTXOs of every address at the given tip
+    pub fn compute<'a>(db: &Database, addresses: impl IntoIterator<Item = &'a String>, tip: Hash256, height: u64) -> Result<Self> {
+        let mut balance = Self { tip, height, ..Self::default() };
+        for address in addresses {
+            balance.confirmed.insert(address.clone(), address_total(db, address)?);
+        }
+        Ok(balance)
+    }
+
+    pub fn total(&self) -> u64 {
+        self.confirmed.values().sum()
+    }
+
+    pub fn pending_total(&self) -> i64 {
+        self.pending.values().sum()
+    }
+
+    pub fn address(&self, address: &str) -> Option<u64> {
+        self.confirmed.get(address).copied()
+    }
+
+    /// Pick up addresses added to the wallet since the balance was cached
+    pub fn sync_addresses<'a>(&mut self, db: &Database, addresses: impl IntoIterator<Item = &'a String>) -> Result<bool> {
+        let mut changed = false;
+        for address in addresses {
+            if !self.confirmed.contains_key(address) {
+                self.confirmed.insert(address.clone(), address_total(db, address)?);
+                changed = true;
+            }
+        }
+        Ok(changed)
+    }
+
+    pub fn block_connected(&mut self, block: &Block, spent: &[(OutPoint, UtxoEntry)]) {
+        for tx in &block.transactions {
+            self.pending.remove(&tx.hash());
+            for output in &tx.outputs {
+                if let Some(total) = self.confirmed.get_mut(&UtxoSet::output_address(&output.script_pubkey)) {
+                    *total = total.saturating_add(output.value);
+                }
+            }
+        }
+        for (_, entry) in spent {
+            if let Some(total) = self.confirmed.get_mut(&entry.address) {
+                *total = total.saturating_sub(entry.value);
+            }
+        }
+        self.tip = block.hash();
+        self.height = block.header.height;
+    }
+
+    pub fn block_disconnected(&mut self, block: &Block, spent: &[(OutPoint, UtxoEntry)]) {
+        for tx in &block.transactions {
+            for output in &tx.outputs {
+                if let Some(total) = self.confirmed.get_mut(&UtxoSet::output_address(&output.script_pubkey)) {
+                    *total = total.saturating_sub(output.value);
+                }
+            }
+        }
+        for (_, entry) in spent {
+            if let Some(total) = self.confirmed.get_mut(&entry.address) {
+                *total = total.saturating_add(entry.value);
+            }
+        }
+        self.tip = block.header.previous_hash;
+        self.height = block.header.height.saturating_sub(1);
+    }
+
+    /// Record what an unconfirmed transaction pays to or takes from the wallet
+    pub fn transaction_added(&mut self, db: &Database, tx: &Transaction) -> Result<()> {
+        let mut delta = 0i64;
+        for output in &tx.outputs {
+            if self.confirmed.contains_key(&UtxoSet::output_address(&output.script_pubkey)) {
+                delta += output.value as i64;
+            }
+        }
+        for input in &tx.inputs {
+            if let Some(entry) = db.get_utxo(&input.previous_output)? {
+                if self.confirmed.contains_key(&entry.address) {
+                    delta -= entry.value as i64;
+                }
+            }
+        }
+
+        if delta != 0 {
+            self.pending.insert(tx.hash(), delta);
+        }
+        Ok(())
+    }
+
+    pub fn transaction_removed(&mut self, txid: &Hash256) {
+        self.pending.remove(txid);
+    }
+}
+
+fn address_total(db: &Database, address: &str) -> Result<u64> {
+    Ok(db.get_utxos_for_address(address)?.iter().map(|(_, utxo)| utxo.value).sum())
+}
+
+/// The cached balance of `wallet`, brought up to the current tip and saved back
+pub fn current_balance<'a>(
+    db: &Database,
+    wallet: &str,
+    addresses: impl IntoIterator<Item = &'a String> + Clone,
+    blockchain: &Blockchain,
+) -> Result<WalletBalance> {
+    let cached = db.get_wallet_balance(wallet)?;
+    let mut changed = false;
+
+    let mut balance = match cached {
+        Some(balance) if balance.tip == blockchain.tip => balance,
+        Some(balance) => {
+            changed = true;
+            match catch_up(db, balance, blockchain)? {
+                Some(balance) => balance,
+                None => WalletBalance::compute(db, addresses.clone(), blockchain.tip, blockchain.height)?,
+            }
+        }
+        None => {
+            changed = true;
+            WalletBalance::compute(db, addresses.clone(), blockchain.tip, blockchain.height)?
+        }
+    };
+
+    changed |= balance.sync_addresses(db, addresses)?;
+    if changed {
+        db.save_wallet_balance(wallet, &balance)?;
+    }
+    Ok(balance)
+}
+
+/// Replay the blocks connected since the cached tip. `None` when the cached tip
+/// has been reorganized away, since its undo data no longer exists.
+fn catch_up(db: &Database, mut balance: WalletBalance, blockchain: &Blockchain) -> Result<Option<WalletBalance>> {
+    let on_main_chain = balance.height <= blockchain.height
+        && db.get_block_by_height(balance.height)?.is_some_and(|block| block.hash() == balance.tip);
+    if !on_main_chain {
+        return Ok(None);
+    }
+
+    for height in balance.height + 1..=blockchain.height {
+        let block = db.get_block_by_height(height)?
+            .ok_or_else(|| QtcError::Blockchain(format!("Block at height {} not found", height)))?;
+        let Some(spent) = db.get_block_undo(&block.hash())? else {
+            return Ok(None);
+        };
+        balance.block_connected(&block, &spent);
+    }
+    Ok(Some(balance))
+}
+
+/// Keeps every wallet's cached balance current while the node runs
+pub struct BalanceTracker {
+    db: Arc<Database>,
+    blockchain: Arc<RwLock<Blockchain>>,
+    balances: HashMap<String, WalletBalance>,
+}
+
+impl BalanceTracker {
+    pub fn new(db: Arc<Database>, blockchain: Arc<RwLock<Blockchain>>) -> Self {
+        Self { db, blockchain, balances: HashMap::new() }
+    }
+
+    pub async fn run(mut self, mut events: broadcast::Receiver<ChainEvent>, mut shutdown: ShutdownSignal) -> Result<()> {
+        self.load_wallets()?;
+
+        loop {
+            let event = tokio::select! {
+                event = events.recv() => event,
+                _ = shutdown.wait() => return Ok(()),
+            };
+            match event {
+                Ok(event) => self.apply(event)?,
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    // Missed events can't be replayed; catch up from the chain instead
+                    log::warn!("💰 Balance tracker missed {} chain events, reloading", skipped);
+                    self.balances.clear();
+                    self.load_wallets()?;
+                }
+                Err(broadcast::error::RecvError::Closed) => {
+                    return Err(QtcError::Blockchain("Chain event stream closed".to_string()));
+                }
+            }
+        }
+    }
+
+    /// Start tracking wallets created since the last look
+    fn load_wallets(&mut self) -> Result<()> {
+        for name in self.db.list_wallets()? {
+            if self.balances.contains_key(&name) {
+                continue;
+            }
+            let wallet = self.db.load_wallet(&name, self.blockchain.clone())?;
+            let balance = {
+                let blockchain = self.blockchain.read()
+                    .map_err(|_| QtcError::Blockchain("Blockchain lock poisoned".to_string()))?;
+                current_balance(&self.db, &name, wallet.addresses.keys(), &blockchain)?
+            };
+            self.balances.insert(name, balance);
+        }
+        Ok(())
+    }
+
+    fn apply(&mut self, event: ChainEvent) -> Result<()> {
+        match event {
+            ChainEvent::BlockConnected { block, spent } => {
+                self.load_wallets()?;
+                for (name, balance) in self.balances.iter_mut() {
+                    // Skip wallets loaded after this block was already connected
+                    if balance.tip == block.header.previous_hash {
+                        balance.block_connected(&block, &spent);
+                        self.db.save_wallet_balance(name, balance)?;
+                    }
+                }
+            }
+            ChainEvent::BlockDisconnected { block, spent } => {
+                for (name, balance) in self.balances.iter_mut() {
+                    if balance.tip == block.hash() {
+                        balance.block_disconnected(&block, &spent);
+                        self.db.save_wallet_balance(name, balance)?;
+                    }
+                }
+            }
+            ChainEvent::TransactionAdded(tx) => {
+                for balance in self.balances.values_mut() {
+                    balance.transaction_added(&self.db, &tx)?;
+                }
+            }
+            ChainEvent::TransactionRemoved(txid) => {
+                for balance in self.balances.values_mut() {
+                    balance.transaction_removed(&txid);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::consensus::target::REGTEST_BITS;
+    use crate::consensus::params::ChainParams;
+    use tempfile::TempDir;
+
+    fn mine_block(chain: &Blockchain, parent: &Block, address: &str) -> Block {
+        let height = parent.header.height + 1;
+        let coinbase = Transaction::new_coinbase(
+            address.to_string(),
+            chain.monetary_policy().coinbase_reward(height),
+            format!("height {}", height),
+        );
+        let mut block = Block::new(parent.hash(), vec![coinbase], REGTEST_BITS, height);
+        block.header.timestamp = parent.header.timestamp + 30;
+        while !chain.is_valid_proof_of_work(&block) {
+            block.increment_nonce();
+        }
+        block
+    }
+
+    #[test]
+    fn test_balance_cache_tracks_the_chain() -> Result<()> {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Arc::new(Database::new(temp_dir.path().join("test.db"))?);
+        let mut chain = Blockchain::new(db.clone())?;
+        chain.set_chain_params(ChainParams {
+            initial_bits: REGTEST_BITS,
+            difficulty_adjustment_interval: 1_000_000,
+            ..ChainParams::mainnet()
+        });
+        let addresses = vec![UtxoSet::output_address(&Transaction::address_to_script_pubkey("qtc1cached"))];
+        let reward = chain.monetary_policy().coinbase_reward(1);
+
+        assert_eq!(current_balance(&db, "w", &addresses, &chain)?.total(), 0);
+        let mut events = chain.subscribe_events();
+        let mut live = db.get_wallet_balance("w")?.unwrap();
+
+        let genesis = chain.get_block_by_height(0)?.unwrap();
+        let b1 = mine_block(&chain, &genesis, "qtc1cached");
+        chain.add_block(b1.clone())?;
+        let b2 = mine_block(&chain, &b1, "qtc1cached");
+        chain.add_block(b2)?;
+
+        // A stale cache catches up from undo data, the live copy from events
+        assert_eq!(current_balance(&db, "w", &addresses, &chain)?.total(), 2 * reward);
+        while let Ok(event) = events.try_recv() {
+            if let ChainEvent::BlockConnected { block, spent } = event {
+                live.block_connected(&block, &spent);
+            }
+        }
+        assert_eq!(live.total(), 2 * reward);
+
+        chain.disconnect_tip()?;
+        match events.try_recv() {
+            Ok(ChainEvent::BlockDisconnected { block, spent }) => live.block_disconnected(&block, &spent),
+            other => panic!("expected a disconnect event, got {:?}", other),
+        }
+        assert_eq!((live.tip, live.total()), (b1.hash(), reward));
+
+        // The cached tip was reorganized away, so the balance is recomputed
+        let cached = current_balance(&db, "w", &addresses, &chain)?;
+        assert_eq!((cached.tip, cached.total()), (b1.hash(), reward));
+
+        let mut payment = Transaction::new();
+        payment.add_input(OutPoint::new(b1.transactions[0].hash(), 0), vec![]);
+        payment.add_output(1_000, "qtc1cached");
+        live.transaction_added(&db, &payment)?;
+        assert_eq!(live.pending_total(), 1_000 - reward as i64);
+        live.transaction_removed(&payment.hash());
+        assert_eq!(live.pending_total(), 0);
+        Ok(())
+    }
+}
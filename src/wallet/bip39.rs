@@ -2,7 +2,7 @@ use crate::crypto::keys::PrivateKey;
 use crate::crypto::hash::Hash256;
 use crate::{QtcError, Result};
 use bip39::Mnemonic as Bip39Mnemonic;
-use bitcoin::bip32::{Xpriv, Xpub, DerivationPath, ChildNumber};
+use bitcoin::bip32::{Xpriv, Xpub, DerivationPath, ChildNumber, Fingerprint};
 use bitcoin::Network;
 use serde::{Deserialize, Serialize};
 use std::str::FromStr;
@@ -123,14 +123,25 @@ impl HdWallet {
         let master_key = self.get_master_key()?;
         let secp = secp256k1::Secp256k1::new();
         
+        master_key.derive_priv(&secp, &Self::account_path(account)?)
+            .map_err(|e| QtcError::Wallet(format!("Failed to derive account key: {}", e)))
+    }
+    
+    fn account_path(account: u32) -> Result<DerivationPath> {
         // BIP44 path: m/44'/coin_type'/account'
         // Using coin type 0 for now (Bitcoin's, should be registered)
         let path = format!("m/44'/0'/{}'", account);
-        let derivation_path = DerivationPath::from_str(&path)
-            .map_err(|e| QtcError::Wallet(format!("Invalid derivation path: {}", e)))?;
-        
-        master_key.derive_priv(&secp, &derivation_path)
-            .map_err(|e| QtcError::Wallet(format!("Failed to derive account key: {}", e)))
+        DerivationPath::from_str(&path)
+            .map_err(|e| QtcError::Wallet(format!("Invalid derivation path: {}", e)))
+    }
+    
+    /// The account xpub with its origin (master fingerprint and path), as descriptors carry it
+    pub fn account_xpub(&self) -> Result<(Fingerprint, DerivationPath, Xpub)> {
+        let master_key = self.get_master_key()?;
+        let secp = secp256k1::Secp256k1::new();
+        let path = Self::account_path(self.account_index)?;
+        let account_key = self.derive_account_key(self.account_index)?;
+        Ok((master_key.fingerprint(&secp), path, Xpub::from_priv(&secp, &account_key)))
     }
     
     pub fn derive_address_key(&self, account: u32, change: bool, index: u32) -> Result<Xpriv> {
@@ -0,0 +1,209 @@
+//! Coin selection strategies for the transaction builder
+//!
+//! Every strategy works on effective values: what an output is worth once the
+//! fee for spending it is paid. Outputs worth no more than that fee are never
+//! picked, since spending them would only burn money. A selection has to cover
+//! the payment plus the fee for the rest of the transaction; whatever is left
+//! becomes change, unless it is too small to be worth a change output.
+
+use crate::{QtcError, Result};
+use rand::seq::SliceRandom;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+/// Smallest output the builder creates; smaller leftovers go to the miner
+pub const DUST_THRESHOLD: u64 = 546;
+
+/// Search steps before branch-and-bound gives up on a changeless solution
+const MAX_BNB_TRIES: usize = 100_000;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CoinSelection {
+    /// Fewest inputs: spend the biggest outputs first
+    #[default]
+    LargestFirst,
+    /// Look for inputs matching the payment closely enough to need no change, else largest-first
+    BranchAndBound,
+    /// Random inputs, so spends don't reveal which outputs the wallet holds the most in
+    Random,
+}
+
+/// What a selection has to pay for, in satoshis
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SelectionTarget {
+    pub amount: u64,      // outputs plus the fee for a transaction without inputs
+    pub input_fee: u64,   // fee for each input added
+    pub change_cost: u64, // fee for a change output plus the smallest change worth making
+}
+
+impl std::fmt::Display for CoinSelection {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let name = match self {
+            Self::LargestFirst => "largest-first",
+            Self::BranchAndBound => "bnb",
+            Self::Random => "random",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl std::str::FromStr for CoinSelection {
+    type Err = QtcError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "largest-first" | "largest" => Ok(Self::LargestFirst),
+            "bnb" | "branch-and-bound" => Ok(Self::BranchAndBound),
+            "random" => Ok(Self::Random),
+            _ => Err(QtcError::InvalidInput(format!(
+                "Unknown coin selection '{}' (expected largest-first, bnb or random)", s
+            ))),
+        }
+    }
+}
+
+/// Pick outputs from `values` covering `target`; returns their indices, or None if the funds fall short
+pub fn select_coins<R: Rng + ?Sized>(
+    strategy: CoinSelection,
+    values: &[u64],
+    target: &SelectionTarget,
+    rng: &mut R,
+) -> Option<Vec<usize>> {
+    let candidates: Vec<(usize, u64)> = values.iter()
+        .enumerate()
+        .filter(|(_, value)| **value > target.input_fee)
+        .map(|(index, value)| (index, value - target.input_fee))
+        .collect();
+
+    match strategy {
+        CoinSelection::LargestFirst => largest_first(candidates, target.amount),
+        CoinSelection::BranchAndBound => {
+            branch_and_bound(candidates.clone(), target.amount, target.amount + target.change_cost)
+                .or_else(|| {
+                    log::debug!("🪙 No changeless input set found, falling back to largest-first");
+                    largest_first(candidates, target.amount)
+                })
+        }
+        CoinSelection::Random => random(candidates, target, rng),
+    }
+}
+
+fn largest_first(mut candidates: Vec<(usize, u64)>, amount: u64) -> Option<Vec<usize>> {
+    candidates.sort_by_key(|(_, value)| std::cmp::Reverse(*value));
+    accumulate(&candidates, amount)
+}
+
+/// Take `candidates` in order until they cover `amount`
+fn accumulate(candidates: &[(usize, u64)], amount: u64) -> Option<Vec<usize>> {
+    let mut selected = Vec::new();
+    let mut total = 0u64;
+    for (index, value) in candidates {
+        selected.push(*index);
+        total += value;
+        if total >= amount {
+            return Some(selected);
+        }
+    }
+    None
+}
+
+fn random<R: Rng + ?Sized>(mut candidates: Vec<(usize, u64)>, target: &SelectionTarget, rng: &mut R) -> Option<Vec<usize>> {
+    candidates.shuffle(rng);
+
+    // Aim for real change, so the payment amount isn't obvious from the inputs
+    accumulate(&candidates, target.amount + target.change_cost)
+        .or_else(|| accumulate(&candidates, target.amount))
+}
+
+/// Depth-first search for the input set whose total lands in `amount..=upper`
+/// with the least left over
+fn branch_and_bound(mut candidates: Vec<(usize, u64)>, amount: u64, upper: u64) -> Option<Vec<usize>> {
+    candidates.sort_by_key(|(_, value)| std::cmp::Reverse(*value));
+
+    // remaining[i] is what candidates[i..] could still add
+    let mut remaining = vec![0u64; candidates.len() + 1];
+    for i in (0..candidates.len()).rev() {
+        remaining[i] = remaining[i + 1] + candidates[i].1;
+    }
+
+    let mut search = BnbSearch { candidates: &candidates, remaining, amount, upper, tries: 0, best: None };
+    search.explore(0, 0, &mut Vec::new());
+    search.best.map(|(_, selected)| selected)
+}
+
+struct BnbSearch<'a> {
+    candidates: &'a [(usize, u64)],
+    remaining: Vec<u64>,
+    amount: u64,
+    upper: u64,
+    tries: usize,
+    best: Option<(u64, Vec<usize>)>, // (excess, indices)
+}
+
+impl BnbSearch<'_> {
+    fn explore(&mut self, depth: usize, total: u64, selected: &mut Vec<usize>) {
+        if self.tries >= MAX_BNB_TRIES || self.best.as_ref().is_some_and(|(excess, _)| *excess == 0) {
+            return;
+        }
+        self.tries += 1;
+
+        if total > self.upper {
+            return;
+        }
+        if total >= self.amount {
+            let excess = total - self.amount;
+            if self.best.as_ref().is_none_or(|(best, _)| excess < *best) {
+                self.best = Some((excess, selected.clone()));
+            }
+            return;
+        }
+        if depth == self.candidates.len() || total + self.remaining[depth] < self.amount {
+            return;
+        }
+
+        let (index, value) = self.candidates[depth];
+        selected.push(index);
+        self.explore(depth + 1, total + value, selected);
+        selected.pop();
+        self.explore(depth + 1, total, selected);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn test_strategies_cover_target_and_skip_dust() -> Result<()> {
+        let mut rng = StdRng::seed_from_u64(7);
+        let values = [100, 50_000, 30_000, 20_500, 10_000];
+        let target = SelectionTarget { amount: 30_000, input_fee: 250, change_cost: 1_000 };
+        let total = |selected: &[usize]| {
+            selected.iter().map(|i| values[*i] - target.input_fee).sum::<u64>()
+        };
+
+        let largest = select_coins(CoinSelection::LargestFirst, &values, &target, &mut rng).unwrap();
+        assert_eq!(largest, vec![1]);
+
+        // 20_500 + 10_000 less two input fees lands within the change window; 30_000 alone falls short
+        let changeless = select_coins(CoinSelection::BranchAndBound, &values, &target, &mut rng).unwrap();
+        let mut sorted = changeless.clone();
+        sorted.sort();
+        assert_eq!(sorted, vec![3, 4]);
+        assert!(total(&changeless) <= target.amount + target.change_cost);
+
+        for _ in 0..20 {
+            let selected = select_coins(CoinSelection::Random, &values, &target, &mut rng).unwrap();
+            assert!(total(&selected) >= target.amount);
+            assert!(!selected.contains(&0), "dust input was selected");
+        }
+
+        let short = SelectionTarget { amount: 200_000, ..target };
+        assert!(select_coins(CoinSelection::BranchAndBound, &values, &short, &mut rng).is_none());
+        assert_eq!("bnb".parse::<CoinSelection>()?, CoinSelection::BranchAndBound);
+        assert_eq!(CoinSelection::Random.to_string().parse::<CoinSelection>()?, CoinSelection::Random);
+        Ok(())
+    }
+}
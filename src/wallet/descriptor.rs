@@ -0,0 +1,509 @@
+//! Output descriptors for exporting wallets and rebuilding them elsewhere
+//!
+//! Three forms are understood, each optionally followed by a BIP 380 `#checksum`:
+//!
+//! - `pkh(KEY)`: P2PKH addresses. `KEY` is a hex public key, or an xpub with
+//!   an optional `[fingerprint/path]` origin, unhardened steps and a final `/*`
+//!   for a range of HD addresses, e.g. `pkh([d34db33f/44'/0'/0']xpub.../0/*)`.
+//! - `sh(multi(k,KEY,...))`: a P2SH multisig address over hex public keys. The
+//!   bare `multi(...)` earlier versions exported is still read.
+//! - `qpqc(SIGNING,ENCRYPTION)`: a QTC post-quantum address from its hex
+//!   Dilithium3 and Kyber768 public keys.
+//!
+//! Descriptors carry public keys only. A wallet rebuilt from them watches the
+//! same addresses; spending needs the private keys passed alongside as WIF.
+
+use crate::core::Blockchain;
+use crate::crypto::keys::{PrivateKey, PublicKey};
+use crate::crypto::pqc::pqc_address;
+use crate::storage::Database;
+use crate::wallet::multisig::MultisigScript;
+use crate::wallet::wallet::{AddressType, PqcAddressData, Wallet, WalletAddress, WalletInfo, WalletType};
+use crate::{QtcError, Result};
+use bitcoin::bip32::{ChildNumber, DerivationPath, Fingerprint, Xpub};
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+
+const INPUT_CHARSET: &str = "0123456789()[],'/*abcdefgh@:$%{}IJKLMNOPQRSTUVWXYZ&+-.;<=>?!^_|~ijklmnopqrstuvwxyzABCDEFGH`#\"\\ ";
+const CHECKSUM_CHARSET: &[u8] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+const GENERATOR: [u64; 5] = [0xf5dee51989, 0xa9fdca3312, 0x1bab10e32d, 0x3706b1677a, 0x644d626ffd];
+
+/// The 8 character BIP 380 checksum of `descriptor`, or None if it holds characters descriptors can't
+pub fn checksum(descriptor: &str) -> Option<String> {
+    let mut symbols = Vec::with_capacity(descriptor.len() * 4 / 3 + 8);
+    let mut groups = Vec::with_capacity(3);
+    for c in descriptor.chars() {
+        let value = INPUT_CHARSET.find(c)? as u64;
+        symbols.push(value & 31);
+        groups.push(value >> 5);
+        if groups.len() == 3 {
+            symbols.push(groups[0] * 9 + groups[1] * 3 + groups[2]);
+            groups.clear();
+        }
+    }
+    match groups.as_slice() {
+        [a] => symbols.push(*a),
+        [a, b] => symbols.push(a * 3 + b),
+        _ => {}
+    }
+    symbols.extend([0; 8]);
+
+    let mut chk = 1u64;
+    for value in symbols {
+        let top = chk >> 35;
+        chk = ((chk & 0x7_ffff_ffff) << 5) ^ value;
+        for (i, generator) in GENERATOR.iter().enumerate() {
+            if (top >> i) & 1 == 1 {
+                chk ^= generator;
+            }
+        }
+    }
+    chk ^= 1;
+
+    Some((0..8).map(|i| CHECKSUM_CHARSET[((chk >> (5 * (7 - i))) & 31) as usize] as char).collect())
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DescriptorKey {
+    Single(PublicKey),
+    Extended {
+        origin: Option<(Fingerprint, DerivationPath)>,
+        xpub: Xpub,
+        path: Vec<ChildNumber>, // unhardened steps below the xpub
+        ranged: bool,           // ends in `/*`
+    },
+}
+
+impl DescriptorKey {
+    /// The key at `index` of a ranged key; single keys ignore the index
+    fn derive(&self, index: u32) -> Result<(PublicKey, Option<String>)> {
+        match self {
+            DescriptorKey::Single(key) => Ok((key.clone(), None)),
+            DescriptorKey::Extended { origin, xpub, path, ranged } => {
+                let mut steps = path.clone();
+                if *ranged {
+                    steps.push(ChildNumber::from_normal_idx(index)
+                        .map_err(|e| QtcError::InvalidInput(format!("Invalid descriptor index: {}", e)))?);
+                }
+                let secp = secp256k1::Secp256k1::verification_only();
+                let child = xpub.derive_pub(&secp, &steps)
+                    .map_err(|e| QtcError::Wallet(format!("Failed to derive descriptor key: {}", e)))?;
+
+                // A full path is only known when the descriptor says where the xpub sits
+                let derivation_path = origin.as_ref().map(|(_, origin_path)| {
+                    let full: Vec<String> = origin_path.into_iter().chain(&steps).map(format_step).collect();
+                    format!("m/{}", full.join("/"))
+                });
+                Ok((PublicKey::from_bytes(&child.public_key.serialize())?, derivation_path))
+            }
+        }
+    }
+}
+
+impl fmt::Display for DescriptorKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DescriptorKey::Single(key) => write!(f, "{}", hex::encode(key.to_bytes())),
+            DescriptorKey::Extended { origin, xpub, path, ranged } => {
+                if let Some((fingerprint, origin_path)) = origin {
+                    write!(f, "[{}", fingerprint)?;
+                    for step in origin_path {
+                        write!(f, "/{}", format_step(step))?;
+                    }
+                    write!(f, "]")?;
+                }
+                write!(f, "{}", xpub)?;
+                for step in path {
+                    write!(f, "/{}", format_step(step))?;
+                }
+                if *ranged {
+                    write!(f, "/*")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl FromStr for DescriptorKey {
+    type Err = QtcError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (origin, rest) = match s.strip_prefix('[') {
+            Some(inner) => {
+                let (origin, rest) = inner.split_once(']')
+                    .ok_or_else(|| QtcError::InvalidInput("Unclosed key origin in descriptor".to_string()))?;
+                let (fingerprint, origin_path) = origin.split_once('/').unwrap_or((origin, ""));
+                let fingerprint = Fingerprint::from_str(fingerprint)
+                    .map_err(|_| QtcError::InvalidInput(format!("Invalid key origin fingerprint '{}'", fingerprint)))?;
+                let steps = origin_path.split('/').filter(|step| !step.is_empty())
+                    .map(parse_step)
+                    .collect::<Result<Vec<_>>>()?;
+                (Some((fingerprint, DerivationPath::from(steps))), rest)
+            }
+            None => (None, s),
+        };
+
+        let mut parts = rest.split('/');
+        let key = parts.next().unwrap_or_default();
+        if !key.starts_with("xpub") && !key.starts_with("tpub") {
+            if origin.is_some() {
+                return Err(QtcError::InvalidInput("Key origins are only supported on xpubs".to_string()));
+            }
+            let bytes = hex::decode(key)
+                .map_err(|_| QtcError::InvalidInput(format!("Invalid public key '{}' in descriptor", key)))?;
+            return Ok(DescriptorKey::Single(PublicKey::from_bytes(&bytes)?));
+        }
+
+        let xpub = Xpub::from_str(key)
+            .map_err(|e| QtcError::InvalidInput(format!("Invalid xpub in descriptor: {}", e)))?;
+        let mut path = Vec::new();
+        let mut ranged = false;
+        for step in parts {
+            if ranged {
+                return Err(QtcError::InvalidInput("`*` must be the last step of a descriptor key".to_string()));
+            }
+            if step == "*" {
+                ranged = true;
+                continue;
+            }
+            let step = parse_step(step)?;
+            if step.is_hardened() {
+                return Err(QtcError::InvalidInput("Hardened steps can't be derived from an xpub".to_string()));
+            }
+            path.push(step);
+        }
+        Ok(DescriptorKey::Extended { origin, xpub, path, ranged })
+    }
+}
+
+fn format_step(step: &ChildNumber) -> String {
+    match step {
+        ChildNumber::Normal { index } => index.to_string(),
+        ChildNumber::Hardened { index } => format!("{}'", index),
+    }
+}
+
+fn parse_step(step: &str) -> Result<ChildNumber> {
+    let invalid = || QtcError::InvalidInput(format!("Invalid derivation step '{}' in descriptor", step));
+    let (index, hardened) = match step.strip_suffix('\'').or_else(|| step.strip_suffix('h')) {
+        Some(index) => (index, true),
+        None => (step, false),
+    };
+    let index: u32 = index.parse().map_err(|_| invalid())?;
+    let child = if hardened { ChildNumber::from_hardened_idx(index) } else { ChildNumber::from_normal_idx(index) };
+    child.map_err(|_| invalid())
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Descriptor {
+    Pkh(DescriptorKey),
+    ShMulti { required: u32, keys: Vec<PublicKey> },
+    Pqc { signing_key: Vec<u8>, encryption_key: Vec<u8> },
+}
+
+/// One address a descriptor stands for, with the public data a watch-only wallet keeps
+#[derive(Debug, Clone)]
+pub struct DescriptorAddress {
+    pub address: String,
+    pub public_key: Vec<u8>,
+    pub derivation_path: Option<String>,
+    pub address_type: AddressType,
+    pub encryption_key: Option<Vec<u8>>, // PQC addresses only
+}
+
+impl Descriptor {
+    pub fn is_ranged(&self) -> bool {
+        matches!(self, Descriptor::Pkh(DescriptorKey::Extended { ranged: true, .. }))
+    }
+
+    /// The descriptor followed by its `#checksum`, as exported
+    pub fn to_string_with_checksum(&self) -> String {
+        let descriptor = self.to_string();
+        match checksum(&descriptor) {
+            Some(checksum) => format!("{}#{}", descriptor, checksum),
+            None => descriptor,
+        }
+    }
+
+    /// Addresses `0..count` of a ranged descriptor, or the single address of any other
+    pub fn addresses(&self, count: u32) -> Result<Vec<DescriptorAddress>> {
+        match self {
+            Descriptor::Pkh(key) => {
+                let count = if self.is_ranged() { count } else { 1 };
+                (0..count).map(|index| {
+                    let (public_key, derivation_path) = key.derive(index)?;
+                    Ok(DescriptorAddress {
+                        address: public_key.to_address(),
+                        public_key: public_key.to_bytes().to_vec(),
+                        derivation_path,
+                        address_type: AddressType::Classic,
+                        encryption_key: None,
+                    })
+                }).collect()
+            }
+            Descriptor::ShMulti { required, keys } => {
+                let script = MultisigScript::new(*required, keys.clone())?;
+                Ok(vec![DescriptorAddress {
+                    address: script.to_address(),
+                    public_key: script.script.clone(),
+                    derivation_path: None,
+                    address_type: AddressType::Classic,
+                    encryption_key: None,
+                }])
+            }
+            Descriptor::Pqc { signing_key, encryption_key } => Ok(vec![DescriptorAddress {
+                address: pqc_address(signing_key, encryption_key),
+                public_key: signing_key.clone(),
+                derivation_path: None,
+                address_type: AddressType::PostQuantum,
+                encryption_key: Some(encryption_key.clone()),
+            }]),
+        }
+    }
+}
+
+impl fmt::Display for Descriptor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Descriptor::Pkh(key) => write!(f, "pkh({})", key),
+            Descriptor::ShMulti { required, keys } => {
+                let keys: Vec<String> = keys.iter().map(|key| hex::encode(key.to_bytes())).collect();
+                write!(f, "sh(multi({},{}))", required, keys.join(","))
+            }
+            Descriptor::Pqc { signing_key, encryption_key } => {
+                write!(f, "qpqc({},{})", hex::encode(signing_key), hex::encode(encryption_key))
+            }
+        }
+    }
+}
+
+impl FromStr for Descriptor {
+    type Err = QtcError;
+
+    /// Parse a descriptor, verifying its checksum when it has one
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+        let body = match s.split_once('#') {
+            Some((body, given)) => {
+                let expected = checksum(body)
+                    .ok_or_else(|| QtcError::InvalidInput("Descriptor contains invalid characters".to_string()))?;
+                if given != expected {
+                    return Err(QtcError::InvalidInput(format!(
+                        "Descriptor checksum mismatch: got {}, expected {}", given, expected
+                    )));
+                }
+                body
+            }
+            None => s,
+        };
+
+        if let Some(key) = unwrap_call(body, "pkh") {
+            return Ok(Descriptor::Pkh(key.parse()?));
+        }
+        if let Some(multi) = unwrap_call(body, "sh").and_then(|inner| unwrap_call(inner, "multi")).or_else(|| unwrap_call(body, "multi")) {
+            let mut parts = multi.split(',');
+            let required: u32 = parts.next().unwrap_or_default().parse()
+                .map_err(|_| QtcError::Multisig("Invalid required signature count".to_string()))?;
+            let keys = parts.map(|key| {
+                let bytes = hex::decode(key)
+                    .map_err(|_| QtcError::Multisig("Invalid public key hex".to_string()))?;
+                PublicKey::from_bytes(&bytes)
+            }).collect::<Result<Vec<_>>>()?;
+            if keys.is_empty() {
+                return Err(QtcError::Multisig("Invalid descriptor format".to_string()));
+            }
+            return Ok(Descriptor::ShMulti { required, keys });
+        }
+        if let Some(keys) = unwrap_call(body, "qpqc") {
+            let (signing_key, encryption_key) = keys.split_once(',')
+                .ok_or_else(|| QtcError::InvalidInput("qpqc() takes a signing and an encryption key".to_string()))?;
+            let decode = |key: &str| hex::decode(key)
+                .map_err(|_| QtcError::InvalidInput("Invalid PQC public key hex in descriptor".to_string()));
+            return Ok(Descriptor::Pqc { signing_key: decode(signing_key)?, encryption_key: decode(encryption_key)? });
+        }
+
+        Err(QtcError::InvalidInput(format!(
+            "Unsupported descriptor '{}' (expected pkh(), sh(multi()) or qpqc())", body
+        )))
+    }
+}
+
+/// The argument text of `name(...)`
+fn unwrap_call<'a>(s: &'a str, name: &str) -> Option<&'a str> {
+    s.strip_prefix(name)?.strip_prefix('(')?.strip_suffix(')')
+}
+
+/// Descriptors covering every address of `wallet`
+///
+/// An HD wallet exports its receive and change branches as two ranged
+/// descriptors; keys it didn't derive (imports, PQC keys) are listed singly.
+pub fn wallet_descriptors(wallet: &Wallet) -> Result<Vec<Descriptor>> {
+    let mut descriptors = Vec::new();
+    if let Some(hd_wallet) = &wallet.hd_wallet {
+        let (fingerprint, origin_path, xpub) = hd_wallet.account_xpub()?;
+        for branch in [0, 1] {
+            descriptors.push(Descriptor::Pkh(DescriptorKey::Extended {
+                origin: Some((fingerprint, origin_path.clone())),
+                xpub,
+                path: vec![ChildNumber::Normal { index: branch }],
+                ranged: true,
+            }));
+        }
+    }
+
+    let mut singles: Vec<&WalletAddress> = wallet.addresses.values()
+        .filter(|addr| wallet.hd_wallet.is_none() || addr.derivation_path.is_none())
+        .collect();
+    singles.sort_by(|a, b| a.address.cmp(&b.address));
+
+    for addr in singles {
+        match (&addr.address_type, &addr.pqc_data) {
+            (AddressType::Classic, _) => {
+                descriptors.push(Descriptor::Pkh(DescriptorKey::Single(PublicKey::from_bytes(&addr.public_key)?)));
+            }
+            (_, Some(pqc)) if !pqc.encryption_public_key.is_empty() => descriptors.push(Descriptor::Pqc {
+                signing_key: pqc.signing_public_key.clone(),
+                encryption_key: pqc.encryption_public_key.clone(),
+            }),
+            _ => log::warn!("Address {} lacks the public keys a descriptor needs; skipped", addr.address),
+        }
+    }
+    Ok(descriptors)
+}
+
+/// A wallet over the addresses of `descriptors`, deriving `range` of each ranged one
+///
+/// `keys` are attached to the addresses they control; without any the wallet
+/// is watch-only. A key matching none of the addresses is an error, since it
+/// most likely belongs to a different wallet.
+pub fn wallet_from_descriptors(
+    name: String,
+    descriptors: &[Descriptor],
+    keys: &[PrivateKey],
+    range: u32,
+    db: Arc<Database>,
+    blockchain: Arc<RwLock<Blockchain>>,
+) -> Result<Wallet> {
+    let mut addresses = HashMap::new();
+    for descriptor in descriptors {
+        if let Descriptor::ShMulti { .. } = descriptor {
+            return Err(QtcError::InvalidInput(
+                "Multisig descriptors are imported with `wallet multisig import`".to_string()
+            ));
+        }
+        let is_change = matches!(
+            descriptor,
+            Descriptor::Pkh(DescriptorKey::Extended { path, ranged: true, .. }) if path.last() == Some(&ChildNumber::Normal { index: 1 })
+        );
+        for derived in descriptor.addresses(range)? {
+            let pqc_data = derived.encryption_key.map(|encryption_public_key| PqcAddressData {
+                signing_private_key: None,
+                encryption_private_key: None,
+                signing_public_key: derived.public_key.clone(),
+                encryption_public_key,
+            });
+            addresses.insert(derived.address.clone(), WalletAddress {
+                address: derived.address,
+                private_key: None,
+                public_key: derived.public_key,
+                derivation_path: derived.derivation_path,
+                is_change,
+                used: false,
+                address_type: derived.address_type,
+                pqc_data,
+            });
+        }
+    }
+
+    for key in keys {
+        let address = key.public_key()?.to_address();
+        let entry = addresses.get_mut(&address).ok_or_else(|| QtcError::InvalidInput(format!(
+            "Private key for {} matches no descriptor address (raise --range for HD keys)", address
+        )))?;
+        entry.private_key = Some(key.to_bytes().to_vec());
+    }
+
+    let info = WalletInfo {
+        name,
+        wallet_type: if keys.is_empty() { WalletType::WatchOnly } else { WalletType::Simple },
+        created_at: chrono::Utc::now().timestamp() as u64,
+        last_used: 0,
+        is_encrypted: false,
+        balance: 0,
+        address_count: addresses.len() as u32,
+    };
+
+    Ok(Wallet {
+        info,
+        addresses,
+        hd_wallet: None,
+        db,
+        blockchain,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::keys::KeyPair;
+    use crate::wallet::bip39::Mnemonic;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_checksum_matches_bip380() {
+        assert_eq!(checksum("raw(deadbeef)").unwrap(), "89f8spxm");
+        assert_eq!(checksum("addr(mkmZxiEcEd8ZqjQWVZuC6so5dFMKEFpN2j)").unwrap(), "02wpgw69");
+        assert!(checksum("pkh(é)").is_none());
+    }
+
+    #[test]
+    fn test_descriptor_roundtrip_and_checksum_validation() -> Result<()> {
+        let keys: Vec<PublicKey> = (0..3).map(|_| KeyPair::new().unwrap().public_key).collect();
+        let multi = Descriptor::ShMulti { required: 2, keys: keys.clone() };
+        let exported = multi.to_string_with_checksum();
+        assert_eq!(exported.parse::<Descriptor>()?, multi);
+
+        // A changed character no longer matches the checksum
+        let tampered = exported.replacen("multi(2", "multi(3", 1);
+        assert!(tampered.parse::<Descriptor>().is_err());
+
+        // Bare multi() from older exports still reads
+        let bare = format!("multi(2,{})", keys.iter().map(|k| hex::encode(k.to_bytes())).collect::<Vec<_>>().join(","));
+        assert_eq!(bare.parse::<Descriptor>()?, multi);
+        Ok(())
+    }
+
+    #[test]
+    fn test_hd_wallet_rebuilt_from_descriptors() -> Result<()> {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Arc::new(Database::new(temp_dir.path().join("test.db"))?);
+        let blockchain = Arc::new(RwLock::new(Blockchain::new(db.clone())?));
+
+        let mnemonic = Mnemonic::new(12)?;
+        let mut wallet = Wallet::new_hd("hd".to_string(), &mnemonic, "", db.clone(), blockchain.clone())?;
+        let generated = wallet.generate_addresses(3)?; // indexes 10 to 12, after the initial ten
+
+        let exported: Vec<String> = wallet_descriptors(&wallet)?.iter().map(Descriptor::to_string_with_checksum).collect();
+        assert_eq!(exported.len(), 2);
+        let descriptors = exported.iter().map(|d| d.parse()).collect::<Result<Vec<Descriptor>>>()?;
+
+        let watch_only = wallet_from_descriptors("hd-watch".to_string(), &descriptors, &[], 15, db.clone(), blockchain.clone())?;
+        assert!(matches!(watch_only.info.wallet_type, WalletType::WatchOnly));
+        for address in &generated {
+            let rebuilt = &watch_only.addresses[address];
+            assert_eq!(rebuilt.derivation_path, wallet.addresses[address].derivation_path);
+            assert!(rebuilt.private_key.is_none());
+        }
+
+        // With its key, an address becomes spendable again; a stranger's key is refused
+        let key = PrivateKey::from_wif(&wallet.export_private_key(&generated[1])?)?;
+        let restored = wallet_from_descriptors("hd-keys".to_string(), &descriptors, &[key], 15, db.clone(), blockchain.clone())?;
+        assert!(restored.addresses[&generated[1]].private_key.is_some());
+        let stranger = KeyPair::new()?.private_key;
+        assert!(wallet_from_descriptors("hd-bad".to_string(), &descriptors, &[stranger], 15, db, blockchain).is_err());
+        Ok(())
+    }
+}
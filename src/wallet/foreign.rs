@@ -0,0 +1,333 @@
+//! Importing keys from Bitcoin Core and Electrum wallets
+//!
+//! QTC uses the same secp256k1 keys as Bitcoin, so someone moving over from
+//! Bitcoin tooling can bring their keys along; each key simply gets a QTC
+//! address. Only unencrypted material can be read:
+//!
+//! - Electrum: the JSON wallet file, either imported keys or a BIP32 keystore
+//!   (`xprv`, `yprv` or `zprv`) whose receive and change chains are derived
+//!   up to Electrum's gap limits
+//! - Bitcoin Core: `bitcoin-cli dumpwallet` output, or the `wallet.dat` itself
+//!   (legacy or descriptor), which is scanned for the DER-encoded keys Core
+//!   stores
+
+use crate::core::Blockchain;
+use crate::crypto::hash::Hash256;
+use crate::crypto::keys::PrivateKey;
+use crate::storage::Database;
+use crate::wallet::wallet::{AddressType, Wallet, WalletAddress, WalletInfo, WalletType};
+use crate::{QtcError, Result};
+use bitcoin::bip32::{ChildNumber, Xpriv};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+
+/// Addresses Electrum watches on each chain before giving up
+const ELECTRUM_RECEIVE_GAP: u32 = 20;
+const ELECTRUM_CHANGE_GAP: u32 = 10;
+
+/// What Bitcoin Core writes in front of a 32-byte secret, for compressed and
+/// uncompressed keys
+const CORE_DER_PREFIXES: [&[u8]; 2] = [
+    &[0x30, 0x81, 0xd3, 0x02, 0x01, 0x01, 0x04, 0x20],
+    &[0x30, 0x82, 0x01, 0x13, 0x02, 0x01, 0x01, 0x04, 0x20],
+];
+
+const CORE_DUMP_HEADER: &[u8] = b"# Wallet dump created by Bitcoin";
+
+/// BIP32 and SLIP-132 private key versions: xprv/yprv/zprv and tprv/uprv/vprv
+const MAINNET_XPRV_VERSIONS: [[u8; 4]; 3] = [[0x04, 0x88, 0xad, 0xe4], [0x04, 0x9d, 0x78, 0x78], [0x04, 0xb2, 0x43, 0x0c]];
+const TESTNET_XPRV_VERSIONS: [[u8; 4]; 3] = [[0x04, 0x35, 0x83, 0x94], [0x04, 0x4a, 0x4e, 0x28], [0x04, 0x5f, 0x18, 0xbc]];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ForeignFormat {
+    Electrum,
+    Core,
+}
+
+impl FromStr for ForeignFormat {
+    type Err = QtcError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "electrum" => Ok(Self::Electrum),
+            "core" | "bitcoin-core" => Ok(Self::Core),
+            _ => Err(QtcError::InvalidInput(format!("Unknown wallet format '{}': use electrum or core", s))),
+        }
+    }
+}
+
+impl fmt::Display for ForeignFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Electrum => write!(f, "Electrum"),
+            Self::Core => write!(f, "Bitcoin Core"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ForeignKey {
+    pub private_key: PrivateKey,
+    pub derivation_path: Option<String>, // as the source wallet derived it
+    pub is_change: bool,
+}
+
+/// Keys read out of another wallet, ready to become a QTC wallet
+#[derive(Debug, Clone)]
+pub struct ForeignWallet {
+    pub format: ForeignFormat,
+    pub keys: Vec<ForeignKey>,
+}
+
+impl ForeignWallet {
+    pub fn read_from_file<P: AsRef<Path>>(format: ForeignFormat, path: P) -> Result<Self> {
+        let data = std::fs::read(path.as_ref())
+            .map_err(|e| QtcError::Wallet(format!("Failed to read {}: {}", path.as_ref().display(), e)))?;
+        Self::parse(format, &data)
+    }
+
+    pub fn parse(format: ForeignFormat, data: &[u8]) -> Result<Self> {
+        let mut keys = match format {
+            ForeignFormat::Electrum => parse_electrum(data)?,
+            ForeignFormat::Core if data.starts_with(CORE_DUMP_HEADER) => parse_core_dump(&String::from_utf8_lossy(data))?,
+            ForeignFormat::Core => scan_core_wallet(data)?,
+        };
+
+        // Wallet files often hold the same key in several records
+        let mut seen = HashSet::new();
+        keys.retain(|key| seen.insert(key.private_key.to_bytes()));
+
+        if keys.is_empty() {
+            return Err(QtcError::Wallet(format!("No private keys found in {} wallet", format)));
+        }
+        Ok(Self { format, keys })
+    }
+
+    /// A simple wallet holding every imported key
+    pub fn into_wallet(self, name: String, db: Arc<Database>, blockchain: Arc<RwLock<Blockchain>>) -> Result<Wallet> {
+        let mut addresses = HashMap::new();
+        for key in self.keys {
+            let public_key = key.private_key.public_key()?;
+            let address = public_key.to_address();
+            addresses.insert(address.clone(), WalletAddress {
+                address,
+                private_key: Some(key.private_key.to_bytes().to_vec()),
+                public_key: public_key.to_bytes().to_vec(),
+                derivation_path: key.derivation_path,
+                is_change: key.is_change,
+                used: false,
+                address_type: AddressType::Classic,
+                pqc_data: None,
+            });
+        }
+
+        let info = WalletInfo {
+            name,
+            wallet_type: WalletType::Simple,
+            created_at: chrono::Utc::now().timestamp() as u64,
+            last_used: 0,
+            is_encrypted: false,
+            balance: 0,
+            address_count: addresses.len() as u32,
+        };
+
+        Ok(Wallet {
+            info,
+            addresses,
+            hd_wallet: None,
+            db,
+            blockchain,
+        })
+    }
+}
+
+fn parse_electrum(data: &[u8]) -> Result<Vec<ForeignKey>> {
+    let wallet: serde_json::Value = serde_json::from_slice(data).map_err(|_| QtcError::Wallet(
+        "Not an Electrum wallet file; if the whole file is encrypted, remove its password in Electrum first".to_string()
+    ))?;
+    if wallet["use_encryption"].as_bool() == Some(true) {
+        return Err(QtcError::Wallet("Electrum wallet keys are encrypted; remove the password in Electrum and try again".to_string()));
+    }
+    let keystore = wallet.get("keystore")
+        .ok_or_else(|| QtcError::Wallet("Electrum wallet has no single keystore (multisig wallets are not supported)".to_string()))?;
+
+    match keystore["type"].as_str() {
+        Some("imported") => {
+            let keypairs = keystore["keypairs"].as_object()
+                .ok_or_else(|| QtcError::Wallet("Electrum keystore has no keypairs".to_string()))?;
+            keypairs.values()
+                .map(|secret| {
+                    let wif = secret.as_str()
+                        .ok_or_else(|| QtcError::Wallet("Electrum keypair is not a string".to_string()))?;
+                    Ok(ForeignKey { private_key: parse_bitcoin_wif(wif)?, derivation_path: None, is_change: false })
+                })
+                .collect()
+        }
+        Some("bip32") => {
+            let xprv = keystore["xprv"].as_str()
+                .ok_or_else(|| QtcError::Wallet("Electrum keystore is watch-only (no xprv)".to_string()))?;
+            let root = parse_xprv(xprv)?;
+            let secp = secp256k1::Secp256k1::new();
+
+            // Electrum derives m/0/i for receiving and m/1/i for change
+            let mut keys = Vec::new();
+            for (change, gap) in [(0, ELECTRUM_RECEIVE_GAP), (1, ELECTRUM_CHANGE_GAP)] {
+                for index in 0..gap {
+                    let path = [ChildNumber::from_normal_idx(change)?, ChildNumber::from_normal_idx(index)?];
+                    let child = root.derive_priv(&secp, &path)
+                        .map_err(|e| QtcError::Wallet(format!("Failed to derive Electrum key: {}", e)))?;
+                    keys.push(ForeignKey {
+                        private_key: PrivateKey::from_bytes(&child.private_key.secret_bytes())?,
+                        derivation_path: Some(format!("m/{}/{}", change, index)),
+                        is_change: change == 1,
+                    });
+                }
+            }
+            Ok(keys)
+        }
+        other => Err(QtcError::Wallet(format!("Unsupported Electrum keystore type '{}'", other.unwrap_or("none")))),
+    }
+}
+
+/// `dumpwallet` lines look like `<wif> <time> <flags> # addr=... hdkeypath=...`
+fn parse_core_dump(dump: &str) -> Result<Vec<ForeignKey>> {
+    let mut keys = Vec::new();
+    for line in dump.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#')) {
+        let (fields, comment) = line.split_once('#').unwrap_or((line, ""));
+        let fields: Vec<&str> = fields.split_whitespace().collect();
+        // Scripts are listed too, but carry no key
+        if fields.is_empty() || fields.contains(&"script=1") {
+            continue;
+        }
+
+        keys.push(ForeignKey {
+            private_key: parse_bitcoin_wif(fields[0])?,
+            derivation_path: comment.split_whitespace().find_map(|field| field.strip_prefix("hdkeypath=")).map(str::to_string),
+            is_change: fields.contains(&"change=1"),
+        });
+    }
+    Ok(keys)
+}
+
+fn scan_core_wallet(data: &[u8]) -> Result<Vec<ForeignKey>> {
+    let mut keys = Vec::new();
+    for prefix in CORE_DER_PREFIXES {
+        for start in find_all(data, prefix) {
+            let secret = &data[start + prefix.len()..];
+            if let Some(Ok(private_key)) = secret.get(..32).map(PrivateKey::from_bytes) {
+                keys.push(ForeignKey { private_key, derivation_path: None, is_change: false });
+            }
+        }
+    }
+
+    if keys.is_empty() && (find_all(data, b"mkey").next().is_some() || find_all(data, b"ckey").next().is_some()) {
+        return Err(QtcError::Wallet(
+            "wallet.dat is encrypted; unlock it with walletpassphrase, export it with `bitcoin-cli dumpwallet` and import the dump".to_string()
+        ));
+    }
+    Ok(keys)
+}
+
+fn find_all<'a>(data: &'a [u8], needle: &'a [u8]) -> impl Iterator<Item = usize> + 'a {
+    data.windows(needle.len())
+        .enumerate()
+        .filter(move |(_, window)| *window == needle)
+        .map(|(start, _)| start)
+}
+
+/// Bitcoin WIF, compressed or not, optionally with Electrum's `p2pkh:` style prefix
+fn parse_bitcoin_wif(wif: &str) -> Result<PrivateKey> {
+    let wif = wif.rsplit(':').next().unwrap_or(wif);
+    let key = bitcoin::PrivateKey::from_wif(wif)
+        .map_err(|e| QtcError::Wallet(format!("Invalid private key in wallet: {}", e)))?;
+    PrivateKey::from_bytes(&key.inner.secret_bytes())
+}
+
+/// An extended private key in any of the BIP32 or SLIP-132 encodings
+fn parse_xprv(encoded: &str) -> Result<Xpriv> {
+    let mut data = bs58::decode(encoded).into_vec()
+        .map_err(|e| QtcError::Wallet(format!("Invalid extended private key: {}", e)))?;
+    if data.len() != 82 || Hash256::double_hash(&data[..78]).as_bytes()[..4] != data[78..] {
+        return Err(QtcError::Wallet("Invalid extended private key".to_string()));
+    }
+
+    // The script type Electrum encodes in the version doesn't matter for the key itself
+    let version = [data[0], data[1], data[2], data[3]];
+    let standard = if MAINNET_XPRV_VERSIONS.contains(&version) {
+        MAINNET_XPRV_VERSIONS[0]
+    } else if TESTNET_XPRV_VERSIONS.contains(&version) {
+        TESTNET_XPRV_VERSIONS[0]
+    } else {
+        return Err(QtcError::Wallet("Unknown extended private key version".to_string()));
+    };
+    data[..4].copy_from_slice(&standard);
+
+    Xpriv::decode(&data[..78])
+        .map_err(|e| QtcError::Wallet(format!("Invalid extended private key: {}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::Network;
+    use tempfile::TempDir;
+
+    fn bitcoin_wif(seed: u8) -> String {
+        let secret = secp256k1::SecretKey::from_slice(&[seed; 32]).unwrap();
+        bitcoin::PrivateKey::new(secret, Network::Bitcoin).to_wif()
+    }
+
+    #[test]
+    fn test_parse_electrum_and_core_wallets() -> Result<()> {
+        let imported = serde_json::json!({
+            "keystore": { "type": "imported", "keypairs": { "02aa": format!("p2wpkh:{}", bitcoin_wif(1)) } },
+            "use_encryption": false,
+        });
+        let wallet = ForeignWallet::parse(ForeignFormat::Electrum, imported.to_string().as_bytes())?;
+        assert_eq!(wallet.keys.len(), 1);
+        assert_eq!(wallet.keys[0].private_key.to_bytes(), [1; 32]);
+
+        let xprv = Xpriv::new_master(Network::Bitcoin, &[7; 32]).unwrap().to_string();
+        let hd = serde_json::json!({ "keystore": { "type": "bip32", "xprv": xprv } });
+        let wallet = ForeignWallet::parse(ForeignFormat::Electrum, hd.to_string().as_bytes())?;
+        assert_eq!(wallet.keys.len(), (ELECTRUM_RECEIVE_GAP + ELECTRUM_CHANGE_GAP) as usize);
+        assert!(wallet.keys.iter().any(|key| key.is_change && key.derivation_path.as_deref() == Some("m/1/0")));
+
+        let encrypted = serde_json::json!({ "keystore": { "type": "bip32" }, "use_encryption": true });
+        assert!(ForeignWallet::parse(ForeignFormat::Electrum, encrypted.to_string().as_bytes()).is_err());
+
+        let dump = format!(
+            "# Wallet dump created by Bitcoin v25.0.0\n\n{} 2023-01-01T00:00:00Z label= # addr=1abc hdkeypath=m/0'/0'/0'\n\
+             {} 2023-01-01T00:00:00Z change=1 # addr=1def hdkeypath=m/0'/1'/0'\n\
+             76a914 0 script=1 # addr=3xyz\n",
+            bitcoin_wif(2), bitcoin_wif(3),
+        );
+        let wallet = ForeignWallet::parse(ForeignFormat::Core, dump.as_bytes())?;
+        assert_eq!(wallet.keys.len(), 2);
+        assert_eq!(wallet.keys[0].derivation_path.as_deref(), Some("m/0'/0'/0'"));
+        assert!(wallet.keys[1].is_change);
+
+        // Raw wallet.dat: the same DER record twice amid unrelated bytes
+        let mut dat = vec![0u8; 64];
+        for _ in 0..2 {
+            dat.extend_from_slice(b"\x03key");
+            dat.extend_from_slice(CORE_DER_PREFIXES[0]);
+            dat.extend_from_slice(&[4; 32]);
+            dat.extend_from_slice(&[0xa1; 16]);
+        }
+        let wallet = ForeignWallet::parse(ForeignFormat::Core, &dat)?;
+        assert_eq!(wallet.keys.len(), 1);
+        assert!(ForeignWallet::parse(ForeignFormat::Core, b"\x04mkey\x04ckey").is_err());
+
+        let temp_dir = TempDir::new().unwrap();
+        let db = Arc::new(Database::new(temp_dir.path().join("test.db"))?);
+        let blockchain = Arc::new(RwLock::new(Blockchain::new(db.clone())?));
+        let imported = wallet.into_wallet("migrated".to_string(), db, blockchain)?;
+        assert_eq!(imported.info.address_count, 1);
+        assert!(imported.addresses.values().all(|address| address.private_key.is_some()));
+        Ok(())
+    }
+}
@@ -0,0 +1,84 @@
+//! Receive-address gap monitoring
+//!
+//! Restoring an HD wallet from its seed derives receive addresses until it
+//! sees `ADDRESS_GAP_LIMIT` unused ones in a row, so anything paid to an
+//! address further past the last used one is never found. Integrations that
+//! hand out a fresh address per invoice and rarely get paid drift past that
+//! limit; the gap is reported in `wallet info` and the wallet REST status,
+//! and a warning is logged when new addresses widen it.
+
+use serde::{Deserialize, Serialize};
+
+/// Unused receive addresses in a row that a restore scans before giving up
+pub const ADDRESS_GAP_LIMIT: u32 = 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AddressGap {
+    pub unused: u32, // generated receive addresses never paid to
+    pub gap: u32,    // unused receive addresses past the last used one
+    pub limit: u32,
+}
+
+impl AddressGap {
+    /// From each receive address's derivation index (None for imported or
+    /// random keys) and whether it has been used. Without indexes the order
+    /// is unknown, so every unused address counts towards the gap.
+    pub fn compute(receive: impl IntoIterator<Item = (Option<u32>, bool)>) -> Self {
+        let mut unused = 0;
+        let mut unindexed_unused = 0;
+        let mut highest: Option<u32> = None;
+        let mut highest_used: Option<u32> = None;
+
+        for (index, used) in receive {
+            if !used {
+                unused += 1;
+            }
+            match index {
+                Some(index) => {
+                    highest = highest.max(Some(index));
+                    if used {
+                        highest_used = highest_used.max(Some(index));
+                    }
+                }
+                None if !used => unindexed_unused += 1,
+                None => {}
+            }
+        }
+
+        let derived_gap = match (highest, highest_used) {
+            (Some(highest), Some(used)) => highest - used,
+            (Some(highest), None) => highest + 1,
+            (None, _) => 0,
+        };
+
+        Self {
+            unused,
+            gap: derived_gap + unindexed_unused,
+            limit: ADDRESS_GAP_LIMIT,
+        }
+    }
+
+    /// Whether a restore from seed could miss payments to the newest addresses
+    pub fn exceeded(&self) -> bool {
+        self.gap > self.limit
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gap_counts_from_last_used_index() {
+        let gap = AddressGap::compute([(Some(0), true), (Some(1), false), (Some(4), true), (Some(5), false), (Some(6), false)]);
+        assert_eq!((gap.unused, gap.gap), (3, 2));
+        assert!(!gap.exceeded());
+
+        let fresh = AddressGap::compute((0..25).map(|index| (Some(index), false)));
+        assert_eq!(fresh.gap, 25);
+        assert!(fresh.exceeded());
+
+        let random_keys = AddressGap::compute([(None, true), (None, false), (None, false)]);
+        assert_eq!((random_keys.unused, random_keys.gap), (2, 2));
+    }
+}
@@ -0,0 +1,342 @@
+//! Wallet transaction history
+//!
+//! Every transaction paying to or spending from a wallet address, found through
+//! the address history index (or by scanning blocks when `addrindex` is off) and
+//! priced with the block undo data that says what each input spent. The result
+//! is cached per wallet with the tip it was scanned to, so later calls only look
+//! at newer blocks. A cached tip that was reorganized away, or addresses added
+//! since, mean a full rescan. The scan also counts the transactions touching
+//! each wallet address, which is how reused addresses are found.
+
+use crate::core::transaction::OutPoint;
+use crate::core::{Block, Blockchain, Transaction, UtxoEntry, UtxoSet};
+use crate::crypto::hash::{Hash256, Hashable};
+use crate::storage::Database;
+use crate::{QtcError, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum HistoryKind {
+    Receive,
+    Send,
+    SelfTransfer, // every output pays the wallet back
+    Coinbase,
+}
+
+impl fmt::Display for HistoryKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            HistoryKind::Receive => "receive",
+            HistoryKind::Send => "send",
+            HistoryKind::SelfTransfer => "self",
+            HistoryKind::Coinbase => "coinbase",
+        };
+        f.write_str(name)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub txid: Hash256,
+    pub kind: HistoryKind,
+    pub amount: u64, // received, or paid to others for sends
+    pub net: i64,    // change in the wallet's balance, fee included
+    pub fee: Option<u64>, // None for coinbases
+    pub height: u64,
+    pub timestamp: u64, // of the block
+    pub addresses: Vec<String>, // paid to others for sends, else the wallet's own paid
+    /// Filled in against the tip when the history is read
+    #[serde(skip)]
+    pub confirmations: u64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct WalletHistory {
+    pub tip: Hash256,
+    pub height: u64,
+    pub addresses: usize, // how many wallet addresses the scan covered
+    pub entries: Vec<HistoryEntry>, // oldest first
+    pub address_use: BTreeMap<String, u32>, // transactions paying to or spending from each address
+}
+
+/// What `tx` did to the wallet owning `ours`; None if it didn't touch it.
+/// `spent` holds the entries its inputs spent, in input order.
+pub fn classify(
+    tx: &Transaction,
+    spent: &[(OutPoint, UtxoEntry)],
+    ours: &HashSet<String>,
+    height: u64,
+    timestamp: u64,
+) -> Option<HistoryEntry> {
+    let is_ours = |script: &[u8]| ours.contains(&UtxoSet::output_address(script));
+    let received: u64 = tx.outputs.iter()
+        .filter(|output| is_ours(&output.script_pubkey))
+        .map(|output| output.value)
+        .sum();
+    let sent: u64 = spent.iter()
+        .filter(|(_, entry)| ours.contains(&entry.address))
+        .map(|(_, entry)| entry.value)
+        .sum();
+    let pays_us = tx.outputs.iter().any(|output| is_ours(&output.script_pubkey));
+    let spends_ours = spent.iter().any(|(_, entry)| ours.contains(&entry.address));
+    if !pays_us && !spends_ours {
+        return None;
+    }
+
+    let total_out = tx.total_output_value();
+    let (kind, amount, fee) = if tx.is_coinbase() {
+        (HistoryKind::Coinbase, received, None)
+    } else {
+        let total_in: u64 = spent.iter().map(|(_, entry)| entry.value).sum();
+        let fee = Some(total_in.saturating_sub(total_out));
+        if !spends_ours {
+            (HistoryKind::Receive, received, fee)
+        } else if received == total_out {
+            (HistoryKind::SelfTransfer, received, fee)
+        } else {
+            (HistoryKind::Send, total_out - received, fee)
+        }
+    };
+
+    let mut addresses: Vec<String> = tx.outputs.iter()
+        .map(|output| UtxoSet::output_address(&output.script_pubkey))
+        .filter(|address| (kind == HistoryKind::Send) != ours.contains(address))
+        .collect();
+    addresses.dedup();
+
+    Some(HistoryEntry {
+        txid: tx.hash(),
+        kind,
+        amount,
+        net: received as i64 - sent as i64,
+        fee,
+        height,
+        timestamp,
+        addresses,
+        confirmations: 0,
+    })
+}
+
+/// The wallet's history, newest first, brought up to the current tip and cached
+pub fn wallet_history<'a>(
+    db: &Database,
+    wallet: &str,
+    addresses: impl IntoIterator<Item = &'a String>,
+    blockchain: &Blockchain,
+) -> Result<Vec<HistoryEntry>> {
+    let mut history = cached_history(db, wallet, addresses, blockchain)?;
+    for entry in history.entries.iter_mut() {
+        entry.confirmations = blockchain.height.saturating_sub(entry.height) + 1;
+    }
+    history.entries.reverse();
+    Ok(history.entries)
+}
+
+/// How many confirmed transactions touched each wallet address; those never touched are left out
+pub fn address_use<'a>(
+    db: &Database,
+    wallet: &str,
+    addresses: impl IntoIterator<Item = &'a String>,
+    blockchain: &Blockchain,
+) -> Result<BTreeMap<String, u32>> {
+    Ok(cached_history(db, wallet, addresses, blockchain)?.address_use)
+}
+
+fn cached_history<'a>(
+    db: &Database,
+    wallet: &str,
+    addresses: impl IntoIterator<Item = &'a String>,
+    blockchain: &Blockchain,
+) -> Result<WalletHistory> {
+    let ours: HashSet<String> = addresses.into_iter().cloned().collect();
+
+    let cached = db.get_wallet_history(wallet)?.filter(|history| {
+        history.addresses == ours.len() && is_on_main_chain(db, history, blockchain)
+    });
+    let history = match cached {
+        Some(history) if history.tip == blockchain.tip => history,
+        Some(history) => {
+            let from = history.height + 1;
+            let history = scan_from(db, history, &ours, blockchain, from)?;
+            db.save_wallet_history(wallet, &history)?;
+            history
+        }
+        None => {
+            let fresh = WalletHistory { addresses: ours.len(), ..WalletHistory::default() };
+            let history = scan_from(db, fresh, &ours, blockchain, 0)?;
+            db.save_wallet_history(wallet, &history)?;
+            history
+        }
+    };
+    Ok(history)
+}
+
+fn is_on_main_chain(db: &Database, history: &WalletHistory, blockchain: &Blockchain) -> bool {
+    history.height <= blockchain.height
+        && db.get_block_header_by_height(history.height).ok().flatten()
+            .is_some_and(|header| header.hash() == history.tip)
+}
+
+/// Add the wallet's transactions in blocks `from..=tip`
+fn scan_from(
+    db: &Database,
+    mut history: WalletHistory,
+    ours: &HashSet<String>,
+    blockchain: &Blockchain,
+    from: u64,
+) -> Result<WalletHistory> {
+    let heights: BTreeSet<u64> = if db.is_address_history_complete()? {
+        let mut heights = BTreeSet::new();
+        for address in ours {
+            heights.extend(db.get_address_history(address, usize::MAX)?.into_iter()
+                .map(|(height, _)| height)
+                .take_while(|height| *height >= from));
+        }
+        heights
+    } else {
+        (from..=blockchain.height).collect()
+    };
+
+    for height in heights {
+        let block = db.get_block_by_height(height)?
+            .ok_or_else(|| QtcError::Blockchain(format!("Block at height {} not found", height)))?;
+        scan_block(db, &mut history, &block, ours)?;
+    }
+
+    history.tip = blockchain.tip;
+    history.height = blockchain.height;
+    Ok(history)
+}
+
+/// Add the wallet's transactions in `block` to `history`
+pub(crate) fn scan_block(db: &Database, history: &mut WalletHistory, block: &Block, ours: &HashSet<String>) -> Result<()> {
+    let spent = db.get_block_undo(&block.hash())?.unwrap_or_default();
+    let inputs: usize = block.transactions.iter()
+        .filter(|tx| !tx.is_coinbase())
+        .map(|tx| tx.inputs.len())
+        .sum();
+    if spent.len() != inputs {
+        log::warn!("📜 No undo data for block {}, its spends are missing from wallet history", block.header.height);
+    }
+
+    let mut spent = spent.as_slice();
+    for tx in &block.transactions {
+        let tx_spent = if tx.is_coinbase() {
+            &[][..]
+        } else {
+            let (tx_spent, rest) = spent.split_at(tx.inputs.len().min(spent.len()));
+            spent = rest;
+            tx_spent
+        };
+        if let Some(entry) = classify(tx, tx_spent, ours, block.header.height, block.header.timestamp) {
+            history.entries.push(entry);
+            let touched: BTreeSet<String> = tx.outputs.iter()
+                .map(|output| UtxoSet::output_address(&output.script_pubkey))
+                .chain(tx_spent.iter().map(|(_, entry)| entry.address.clone()))
+                .filter(|address| ours.contains(address))
+                .collect();
+            for address in touched {
+                *history.address_use.entry(address).or_insert(0) += 1;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::consensus::target::REGTEST_BITS;
+    use crate::consensus::params::ChainParams;
+    use tempfile::TempDir;
+
+    fn mine_block(chain: &Blockchain, parent: &Block, address: &str) -> Block {
+        let height = parent.header.height + 1;
+        let coinbase = Transaction::new_coinbase(
+            address.to_string(),
+            chain.monetary_policy().coinbase_reward(height),
+            format!("height {}", height),
+        );
+        let mut block = Block::new(parent.hash(), vec![coinbase], REGTEST_BITS, height);
+        block.header.timestamp = parent.header.timestamp + 30;
+        while !chain.is_valid_proof_of_work(&block) {
+            block.increment_nonce();
+        }
+        block
+    }
+
+    fn spent(address: &str, value: u64) -> (OutPoint, UtxoEntry) {
+        let script_pubkey = Transaction::address_to_script_pubkey(address);
+        let entry = UtxoEntry {
+            txid: Hash256::zero(),
+            vout: 0,
+            value,
+            address: UtxoSet::output_address(&script_pubkey),
+            script_pubkey,
+            height: 1,
+            is_coinbase: false,
+        };
+        (OutPoint::new(Hash256::zero(), 0), entry)
+    }
+
+    #[test]
+    fn test_classify() {
+        let ours: HashSet<String> = [UtxoSet::output_address(&Transaction::address_to_script_pubkey("qtc1ours"))].into();
+        let mut tx = Transaction::new();
+        tx.add_input(OutPoint::new(Hash256::zero(), 0), vec![]);
+        tx.add_output(700, "qtc1theirs");
+        tx.add_output(250, "qtc1ours");
+
+        let send = classify(&tx, &[spent("qtc1ours", 1_000)], &ours, 5, 0).unwrap();
+        assert_eq!((send.kind, send.amount, send.net, send.fee), (HistoryKind::Send, 700, -750, Some(50)));
+
+        let receive = classify(&tx, &[spent("qtc1theirs", 1_000)], &ours, 5, 0).unwrap();
+        assert_eq!((receive.kind, receive.amount, receive.net), (HistoryKind::Receive, 250, 250));
+
+        let mut consolidation = Transaction::new();
+        consolidation.add_input(OutPoint::new(Hash256::zero(), 0), vec![]);
+        consolidation.add_output(990, "qtc1ours");
+        let own = classify(&consolidation, &[spent("qtc1ours", 1_000)], &ours, 5, 0).unwrap();
+        assert_eq!((own.kind, own.net, own.fee), (HistoryKind::SelfTransfer, -10, Some(10)));
+
+        assert!(classify(&tx, &[spent("qtc1theirs", 1_000)], &HashSet::new(), 5, 0).is_none());
+    }
+
+    #[test]
+    fn test_history_cache_follows_the_chain() -> Result<()> {
+        let temp_dir = TempDir::new().unwrap();
+        let db = std::sync::Arc::new(Database::new(temp_dir.path().join("test.db"))?);
+        let mut chain = Blockchain::new(db.clone())?;
+        chain.set_chain_params(ChainParams {
+            initial_bits: REGTEST_BITS,
+            difficulty_adjustment_interval: 1_000_000,
+            ..ChainParams::mainnet()
+        });
+        let addresses = vec![UtxoSet::output_address(&Transaction::address_to_script_pubkey("qtc1history"))];
+
+        let genesis = chain.get_block_by_height(0)?.unwrap();
+        let b1 = mine_block(&chain, &genesis, "qtc1history");
+        chain.add_block(b1.clone())?;
+        let history = wallet_history(&db, "w", &addresses, &chain)?;
+        assert_eq!(history.len(), 1);
+        assert_eq!((history[0].kind, history[0].confirmations), (HistoryKind::Coinbase, 1));
+
+        // Only the new block is scanned, and older entries gain confirmations
+        let b2 = mine_block(&chain, &b1, "qtc1history");
+        chain.add_block(b2.clone())?;
+        let history = wallet_history(&db, "w", &addresses, &chain)?;
+        assert_eq!(history.iter().map(|entry| entry.height).collect::<Vec<_>>(), vec![2, 1]);
+        assert_eq!(history[1].confirmations, 2);
+        assert_eq!(db.get_wallet_history("w")?.unwrap().tip, b2.hash());
+        assert_eq!(address_use(&db, "w", &addresses, &chain)?.get(&addresses[0]), Some(&2));
+
+        // The cached tip was reorganized away, so the history is rebuilt
+        chain.disconnect_tip()?;
+        let history = wallet_history(&db, "w", &addresses, &chain)?;
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].txid, b1.transactions[0].hash());
+        Ok(())
+    }
+}
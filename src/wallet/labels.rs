@@ -0,0 +1,123 @@
+//! Address labels and the contact book
+//!
+//! A label names an address for one wallet, whether the wallet's own or one
+//! it pays, and is shown wherever that wallet lists addresses or
+//! transactions. Contacts are shared by every wallet on the node: names for
+//! addresses to pay, which `wallet send --to-contact` takes in place of the
+//! address. Outputs only carry a hash of the address they pay, so history is
+//! matched against the address each output stands for (`UtxoSet::output_address`).
+
+use crate::core::{Transaction, UtxoSet};
+use crate::crypto::keys::is_valid_address;
+use crate::storage::Database;
+use crate::{QtcError, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Longest label or contact name accepted
+pub const MAX_LABEL_LEN: usize = 100;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Contact {
+    pub name: String,
+    pub address: String,
+    pub added_at: u64,
+}
+
+impl Contact {
+    pub fn new(name: &str, address: &str) -> Result<Self> {
+        let name = check_label(name, "Contact name")?;
+        if name.is_empty() {
+            return Err(QtcError::InvalidInput("Contact name can't be empty".to_string()));
+        }
+        if !is_valid_address(address) {
+            return Err(QtcError::InvalidInput(format!("Invalid address: {}", address)));
+        }
+        Ok(Self {
+            name,
+            address: address.to_string(),
+            added_at: chrono::Utc::now().timestamp() as u64,
+        })
+    }
+}
+
+/// Trimmed `label`, refused if too long or multi-line
+pub fn check_label(label: &str, what: &str) -> Result<String> {
+    let label = label.trim();
+    if label.chars().count() > MAX_LABEL_LEN {
+        return Err(QtcError::InvalidInput(format!("{} is longer than {} characters", what, MAX_LABEL_LEN)));
+    }
+    if label.chars().any(char::is_control) {
+        return Err(QtcError::InvalidInput(format!("{} can't contain control characters", what)));
+    }
+    Ok(label.to_string())
+}
+
+/// Names for addresses as one wallet sees them: its labels, then the contacts
+#[derive(Debug, Clone, Default)]
+pub struct AddressBook {
+    names: HashMap<String, String>,
+}
+
+impl AddressBook {
+    pub fn load(db: &Database, wallet: &str) -> Result<Self> {
+        let mut book = Self::default();
+        for contact in db.list_contacts()? {
+            book.insert(&contact.address, contact.name);
+        }
+        // A wallet's own label wins over a contact name for the same address
+        for (address, label) in db.get_address_labels(wallet)? {
+            book.insert(&address, label);
+        }
+        Ok(book)
+    }
+
+    fn insert(&mut self, address: &str, name: String) {
+        let paid_as = UtxoSet::output_address(&Transaction::address_to_script_pubkey(address));
+        self.names.insert(paid_as, name.clone());
+        self.names.insert(address.to_string(), name);
+    }
+
+    /// The name for `address`, given as the wallet shows it or as an output pays it
+    pub fn name(&self, address: &str) -> Option<&str> {
+        self.names.get(address).map(String::as_str)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::keys::KeyPair;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_labels_and_contacts_name_addresses() -> Result<()> {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Database::new(temp_dir.path().join("test.db"))?;
+        let shop = KeyPair::new()?.address();
+        let friend = KeyPair::new()?.address();
+
+        db.save_contact(&Contact::new(" alice ", &friend)?)?;
+        db.save_contact(&Contact::new("shop", &shop)?)?;
+        db.save_address_label("main", &shop, "coffee shop")?;
+        assert!(Contact::new("bob", "not-an-address").is_err());
+        assert!(Contact::new("  ", &friend).is_err());
+        assert!(check_label("two\nlines", "Label").is_err());
+
+        let book = AddressBook::load(&db, "main")?;
+        assert_eq!(book.name(&shop), Some("coffee shop"));
+        let paid_as = UtxoSet::output_address(&Transaction::address_to_script_pubkey(&friend));
+        assert_eq!(book.name(&paid_as), Some("alice"));
+        // Labels are per wallet, contacts are not
+        assert_eq!(AddressBook::load(&db, "other")?.name(&shop), Some("shop"));
+
+        assert_eq!(db.get_contact("alice")?.unwrap().address, friend);
+        assert!(db.remove_contact("alice")?);
+        assert!(!db.remove_contact("alice")?);
+        assert_eq!(db.list_contacts()?.len(), 1);
+
+        db.delete_wallet("main")?;
+        assert!(db.get_address_labels("main")?.is_empty());
+        Ok(())
+    }
+}
@@ -0,0 +1,230 @@
+//! UTXO locks that keep concurrent sends from selecting the same outputs
+//!
+//! Locks taken while building a transaction are in-memory and expire on
+//! their own; locks created with `lockunspent` can be persisted so they
+//! survive a restart.
+
+use crate::core::transaction::OutPoint;
+use crate::{QtcError, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UtxoLock {
+    pub wallet: String,
+    pub locked_at: u64,
+    pub expires_at: Option<u64>, // None = until explicitly unlocked
+    pub persistent: bool,
+}
+
+impl UtxoLock {
+    pub fn is_expired(&self, now: u64) -> bool {
+        self.expires_at.is_some_and(|expires_at| now >= expires_at)
+    }
+}
+
+#[derive(Debug)]
+pub struct UtxoLockTable {
+    entries: Mutex<HashMap<OutPoint, UtxoLock>>,
+    tree: Option<sled::Tree>, // backing store for persistent locks
+}
+
+impl Default for UtxoLockTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl UtxoLockTable {
+    /// Purely in-memory table; persistent locks are kept only for this process
+    pub fn new() -> Self {
+        Self {
+            entries: Mutex::new(HashMap::new()),
+            tree: None,
+        }
+    }
+
+    /// Table backed by `tree`, loading any persistent locks that have not expired
+    pub fn with_tree(tree: sled::Tree) -> Result<Self> {
+        let now = now();
+        let mut entries = HashMap::new();
+
+        for item in tree.iter() {
+            let (key, value) = item
+                .map_err(|e| QtcError::Storage(format!("Failed to read UTXO lock: {}", e)))?;
+            let outpoint: OutPoint = bincode::deserialize(&key)
+                .map_err(|e| QtcError::Storage(format!("Failed to decode UTXO lock key: {}", e)))?;
+            let lock: UtxoLock = bincode::deserialize(&value)
+                .map_err(|e| QtcError::Storage(format!("Failed to decode UTXO lock: {}", e)))?;
+
+            if lock.is_expired(now) {
+                tree.remove(&key)
+                    .map_err(|e| QtcError::Storage(format!("Failed to remove UTXO lock: {}", e)))?;
+            } else {
+                entries.insert(outpoint, lock);
+            }
+        }
+
+        Ok(Self {
+            entries: Mutex::new(entries),
+            tree: Some(tree),
+        })
+    }
+
+    /// Lock every outpoint for `wallet`, or none of them if any is already locked
+    pub fn lock(&self, wallet: &str, outpoints: &[OutPoint], ttl_secs: Option<u64>, persistent: bool) -> Result<()> {
+        let now = now();
+        let mut entries = self.entries.lock().unwrap();
+        self.prune_expired(&mut entries, now)?;
+
+        if let Some(taken) = outpoints.iter().find(|outpoint| entries.contains_key(outpoint)) {
+            return Err(QtcError::Wallet(format!("Output {}:{} is already locked", taken.txid, taken.vout)));
+        }
+
+        let lock = UtxoLock {
+            wallet: wallet.to_string(),
+            locked_at: now,
+            expires_at: ttl_secs.map(|ttl| now + ttl),
+            persistent,
+        };
+
+        for outpoint in outpoints {
+            if persistent {
+                self.persist(outpoint, &lock)?;
+            }
+            entries.insert(outpoint.clone(), lock.clone());
+        }
+
+        Ok(())
+    }
+
+    /// Release the given outpoints, returning how many were locked
+    pub fn unlock(&self, outpoints: &[OutPoint]) -> Result<usize> {
+        let mut entries = self.entries.lock().unwrap();
+        let mut released = 0;
+
+        for outpoint in outpoints {
+            if let Some(lock) = entries.remove(outpoint) {
+                if lock.persistent {
+                    self.unpersist(outpoint)?;
+                }
+                released += 1;
+            }
+        }
+
+        Ok(released)
+    }
+
+    /// Release every lock held by `wallet`
+    pub fn unlock_wallet(&self, wallet: &str) -> Result<usize> {
+        let outpoints: Vec<OutPoint> = self.list(wallet)?.into_iter().map(|(outpoint, _)| outpoint).collect();
+        self.unlock(&outpoints)
+    }
+
+    pub fn is_locked(&self, outpoint: &OutPoint) -> bool {
+        let entries = self.entries.lock().unwrap();
+        entries.get(outpoint).is_some_and(|lock| !lock.is_expired(now()))
+    }
+
+    /// Live locks held by `wallet`
+    pub fn list(&self, wallet: &str) -> Result<Vec<(OutPoint, UtxoLock)>> {
+        let now = now();
+        let mut entries = self.entries.lock().unwrap();
+        self.prune_expired(&mut entries, now)?;
+
+        Ok(entries.iter()
+            .filter(|(_, lock)| lock.wallet == wallet)
+            .map(|(outpoint, lock)| (outpoint.clone(), lock.clone()))
+            .collect())
+    }
+
+    fn prune_expired(&self, entries: &mut HashMap<OutPoint, UtxoLock>, now: u64) -> Result<()> {
+        let expired: Vec<(OutPoint, bool)> = entries.iter()
+            .filter(|(_, lock)| lock.is_expired(now))
+            .map(|(outpoint, lock)| (outpoint.clone(), lock.persistent))
+            .collect();
+
+        for (outpoint, persistent) in expired {
+            entries.remove(&outpoint);
+            if persistent {
+                self.unpersist(&outpoint)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn persist(&self, outpoint: &OutPoint, lock: &UtxoLock) -> Result<()> {
+        if let Some(tree) = &self.tree {
+            let key = bincode::serialize(outpoint)
+                .map_err(|e| QtcError::Storage(format!("Failed to encode UTXO lock key: {}", e)))?;
+            let value = bincode::serialize(lock)
+                .map_err(|e| QtcError::Storage(format!("Failed to encode UTXO lock: {}", e)))?;
+            tree.insert(key, value)
+                .map_err(|e| QtcError::Storage(format!("Failed to save UTXO lock: {}", e)))?;
+        }
+        Ok(())
+    }
+
+    fn unpersist(&self, outpoint: &OutPoint) -> Result<()> {
+        if let Some(tree) = &self.tree {
+            let key = bincode::serialize(outpoint)
+                .map_err(|e| QtcError::Storage(format!("Failed to encode UTXO lock key: {}", e)))?;
+            tree.remove(key)
+                .map_err(|e| QtcError::Storage(format!("Failed to remove UTXO lock: {}", e)))?;
+        }
+        Ok(())
+    }
+}
+
+fn now() -> u64 {
+    chrono::Utc::now().timestamp() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::hash::Hash256;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_locks_are_exclusive_and_persisted() -> Result<()> {
+        let temp_dir = TempDir::new().unwrap();
+        let db = sled::open(temp_dir.path().join("locks.db")).unwrap();
+        let a = OutPoint::new(Hash256::new([1u8; 32]), 0);
+        let b = OutPoint::new(Hash256::new([2u8; 32]), 1);
+
+        let table = UtxoLockTable::with_tree(db.open_tree("utxo_locks").unwrap())?;
+        table.lock("alice", std::slice::from_ref(&a), Some(600), false)?;
+
+        // A selection overlapping an existing lock takes nothing
+        assert!(table.lock("alice", &[b.clone(), a.clone()], None, false).is_err());
+        assert!(!table.is_locked(&b));
+
+        table.lock("alice", std::slice::from_ref(&b), None, true)?;
+        assert_eq!(table.list("alice")?.len(), 2);
+        assert!(table.list("bob")?.is_empty());
+
+        // Only the persistent lock survives a reload
+        let reloaded = UtxoLockTable::with_tree(db.open_tree("utxo_locks").unwrap())?;
+        assert!(!reloaded.is_locked(&a));
+        assert!(reloaded.is_locked(&b));
+
+        assert_eq!(reloaded.unlock_wallet("alice")?, 1);
+        assert!(UtxoLockTable::with_tree(db.open_tree("utxo_locks").unwrap())?.list("alice")?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_expired_locks_are_released() -> Result<()> {
+        let table = UtxoLockTable::new();
+        let outpoint = OutPoint::new(Hash256::new([3u8; 32]), 0);
+
+        table.lock("alice", std::slice::from_ref(&outpoint), Some(0), false)?;
+        assert!(!table.is_locked(&outpoint));
+        table.lock("bob", std::slice::from_ref(&outpoint), None, false)?;
+        assert!(table.is_locked(&outpoint));
+        Ok(())
+    }
+}
@@ -1,9 +1,32 @@
 //! Wallet functionality for QTC
 
 pub mod wallet;
+pub mod balance;
 pub mod bip39;
+pub mod coin_selection;
+pub mod descriptor;
+pub mod foreign;
+pub mod gap;
+pub mod history;
+pub mod labels;
+pub mod locks;
 pub mod multisig;
+pub mod psbt;
+pub mod rescan;
+pub mod signer;
+pub mod viewonly;
 
 pub use wallet::{Wallet, WalletInfo};
+pub use balance::{BalanceTracker, WalletBalance};
 pub use bip39::{Mnemonic, Seed};
+pub use coin_selection::CoinSelection;
+pub use descriptor::Descriptor;
+pub use foreign::{ForeignFormat, ForeignWallet};
+pub use gap::{AddressGap, ADDRESS_GAP_LIMIT};
+pub use history::{HistoryEntry, HistoryKind};
+pub use labels::{AddressBook, Contact};
+pub use locks::{UtxoLock, UtxoLockTable};
 pub use multisig::{MultisigWallet, MultisigScript, SignatureCollector};
+pub use psbt::{FrameDecoder, Psbt, PsbtInput};
+pub use rescan::{RescanCursor, RescanReport};
+pub use signer::{ExternalSigner, LocalSigner, SigningRequest, SigningResponse, TransactionSigner};
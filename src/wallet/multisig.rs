@@ -1,11 +1,27 @@
-use crate::core::Transaction;
-use crate::crypto::keys::{PrivateKey, PublicKey};
+//! m-of-n multisig wallets paying to a P2SH address
+//!
+//! A multisig wallet is stored like any other wallet, holding only its P2SH
+//! address, next to a `MultisigWallet` record with the cosigners' keys. Its
+//! coins are spent by passing an unsigned PSBT from cosigner to cosigner: each
+//! adds its signatures to the input's signature script (`OP_0 <sig>... <redeem
+//! script>`), and the transaction can be broadcast once `required` are there.
+
+use crate::core::{SigHashType, Transaction};
+use crate::crypto::keys::{address_prefix, PrivateKey, PublicKey};
 use crate::crypto::signatures::Signature;
-use crate::crypto::hash::Hash256;
+use crate::crypto::hash::{Hash256, Hashable};
+use crate::wallet::descriptor::Descriptor;
+use crate::wallet::psbt::Psbt;
 use crate::{QtcError, Result};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
+/// Pushes of up to this many bytes are a single length byte
+const MAX_DIRECT_PUSH: usize = 75;
+const OP_PUSHDATA1: u8 = 0x4c;
+const OP_PUSHDATA2: u8 = 0x4d;
+const OP_CHECKMULTISIG: u8 = 0xae;
+
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MultisigScript {
@@ -38,6 +54,8 @@ pub struct SignatureCollector {
     pub required_signatures: u32,
     pub signatures: HashMap<usize, PartialSignature>,
     pub script: MultisigScript,
+    #[serde(default)]
+    pub sighash: SigHashType, // shared by every cosigner
 }
 
 impl MultisigScript {
@@ -77,11 +95,38 @@ impl MultisigScript {
         script.push(0x50 + public_keys.len() as u8);
         
         // OP_CHECKMULTISIG
-        script.push(0xae);
+        script.push(OP_CHECKMULTISIG);
         
         Ok(script)
     }
     
+    /// Parse a redeem script made by `new`
+    pub fn from_redeem_script(script: &[u8]) -> Result<Self> {
+        let invalid = || QtcError::Multisig("Invalid multisig redeem script".to_string());
+        let (&first, rest) = script.split_first().ok_or_else(invalid)?;
+        let (&last, rest) = rest.split_last().ok_or_else(invalid)?;
+        let (&total, mut keys) = rest.split_last().ok_or_else(invalid)?;
+        if last != OP_CHECKMULTISIG || first <= 0x50 || total <= 0x50 {
+            return Err(invalid());
+        }
+        
+        let mut public_keys = Vec::new();
+        while let Some((&len, rest)) = keys.split_first() {
+            let key = rest.get(..len as usize).ok_or_else(invalid)?;
+            public_keys.push(PublicKey::from_bytes(key)?);
+            keys = &rest[len as usize..];
+        }
+        if public_keys.len() != (total - 0x50) as usize {
+            return Err(invalid());
+        }
+        
+        let parsed = Self::new((first - 0x50) as u32, public_keys)?;
+        if parsed.script != script {
+            return Err(invalid());
+        }
+        Ok(parsed)
+    }
+    
     pub fn to_address(&self) -> String {
         // Create P2SH address from script hash
         let script_hash = Hash256::hash(&self.script);
@@ -95,13 +140,18 @@ impl MultisigScript {
         data.extend_from_slice(&hash.as_bytes()[0..4]);
         
         let address = bs58::encode(data).into_string();
-        format!("qtc{}", address)
+        format!("{}{}", address_prefix(), address)
     }
     
     pub fn get_redeem_script(&self) -> &[u8] {
         &self.script
     }
     
+    /// Index of `public_key` among the cosigners
+    pub fn key_index(&self, public_key: &PublicKey) -> Option<usize> {
+        self.public_keys.iter().position(|key| key.to_bytes() == public_key.to_bytes())
+    }
+    
     pub fn verify_signature_count(&self, signatures: &[Signature]) -> bool {
         signatures.len() >= self.required_signatures as usize
     }
@@ -172,49 +222,172 @@ impl MultisigWallet {
         &self.script.public_keys
     }
     
+    /// `sh(multi(...))#checksum` over the cosigners' public keys
     pub fn export_descriptor(&self) -> String {
-        let pubkey_strings: Vec<String> = self.script.public_keys
-            .iter()
-            .map(|pk| hex::encode(pk.to_bytes()))
-            .collect();
-        
-        format!(
-            "multi({},{})",
-            self.script.required_signatures,
-            pubkey_strings.join(",")
-        )
+        Descriptor::ShMulti {
+            required: self.script.required_signatures,
+            keys: self.script.public_keys.clone(),
+        }.to_string_with_checksum()
     }
     
     pub fn from_descriptor(name: String, descriptor: &str, our_indices: Vec<usize>) -> Result<Self> {
-        // Parse miniscript descriptor
-        // Simplified implementation - would use proper miniscript parsing in production
-        
-        if !descriptor.starts_with("multi(") || !descriptor.ends_with(")") {
-            return Err(QtcError::Multisig("Invalid multisig descriptor".to_string()));
+        match descriptor.parse::<Descriptor>()? {
+            Descriptor::ShMulti { required, keys } => Self::new(name, required, keys, our_indices),
+            _ => Err(QtcError::Multisig("Invalid multisig descriptor".to_string())),
         }
-        
-        let inner = &descriptor[6..descriptor.len()-1];
-        let parts: Vec<&str> = inner.split(',').collect();
-        
-        if parts.len() < 2 {
-            return Err(QtcError::Multisig("Invalid descriptor format".to_string()));
+    }
+    
+    /// The keys among ours that `keys` holds the private half of, by cosigner index
+    pub fn our_keys<'a>(&self, keys: impl IntoIterator<Item = &'a PrivateKey>) -> Result<Vec<(usize, &'a PrivateKey)>> {
+        let mut ours = Vec::new();
+        for key in keys {
+            if let Some(index) = self.script.key_index(&key.public_key()?).filter(|index| self.our_key_indices.contains(index)) {
+                ours.push((index, key));
+            }
+        }
+        Ok(ours)
+    }
+    
+    /// Add signatures with `keys` to every input of `psbt` spending from this wallet,
+    /// or only to `input_index`; returns how many signatures were added
+    pub fn sign_psbt(&self, psbt: &mut Psbt, keys: &[(usize, &PrivateKey)], input_index: Option<usize>) -> Result<usize> {
+        psbt.check()?;
+        if let Some(index) = input_index.filter(|&index| psbt.inputs.get(index).is_none_or(|input| input.address != self.address)) {
+            return Err(QtcError::Multisig(format!("Input {} doesn't spend from {}", index, self.address)));
         }
         
-        let required: u32 = parts[0].parse()
-            .map_err(|_| QtcError::Multisig("Invalid required signature count".to_string()))?;
-        
-        let mut public_keys = Vec::new();
-        for pubkey_hex in &parts[1..] {
-            let pubkey_bytes = hex::decode(pubkey_hex)
-                .map_err(|_| QtcError::Multisig("Invalid public key hex".to_string()))?;
-            let pubkey = PublicKey::from_bytes(&pubkey_bytes)?;
-            public_keys.push(pubkey);
+        let mut added = 0;
+        for index in self.own_inputs(psbt).into_iter().filter(|&index| input_index.is_none_or(|only| only == index)) {
+            let mut collector = SignatureCollector::from_transaction(&psbt.tx, index, self.script.clone())?;
+            for &(signer, key) in keys {
+                if collector.is_complete() || collector.signatures.contains_key(&signer) {
+                    continue;
+                }
+                collector.sign_with_key(signer, key)?;
+                added += 1;
+            }
+            psbt.tx.inputs[index].signature_script = collector.signature_script();
+        }
+        Ok(added)
+    }
+    
+    /// Merge the signatures another cosigner added to a copy of `psbt`; returns how many were new
+    pub fn combine_psbt(&self, psbt: &mut Psbt, other: &Psbt) -> Result<usize> {
+        if unsigned_txid(&psbt.tx) != unsigned_txid(&other.tx) {
+            return Err(QtcError::Multisig("The PSBTs are for different transactions".to_string()));
         }
         
-        Self::new(name, required, public_keys, our_indices)
+        let mut added = 0;
+        for index in self.own_inputs(psbt) {
+            let mut collector = SignatureCollector::from_transaction(&psbt.tx, index, self.script.clone())?;
+            let theirs = SignatureCollector::from_transaction(&other.tx, index, self.script.clone())?;
+            if !theirs.signatures.is_empty() {
+                collector.set_sighash(theirs.sighash)?;
+            }
+            added += collector.import_partial_signatures(theirs.export_partial_signatures())?;
+            psbt.tx.inputs[index].signature_script = collector.signature_script();
+        }
+        Ok(added)
+    }
+    
+    /// Signatures still needed before `psbt` can be broadcast, for the input missing the most
+    pub fn missing_signatures(&self, psbt: &Psbt) -> Result<u32> {
+        let mut missing = 0;
+        for index in self.own_inputs(psbt) {
+            let collector = SignatureCollector::from_transaction(&psbt.tx, index, self.script.clone())?;
+            missing = missing.max(collector.get_missing_signatures());
+        }
+        Ok(missing)
+    }
+    
+    fn own_inputs(&self, psbt: &Psbt) -> Vec<usize> {
+        psbt.inputs.iter()
+            .enumerate()
+            .filter(|(_, input)| input.address == self.address)
+            .map(|(index, _)| index)
+            .collect()
     }
 }
 
+/// The txid with every signature script left out, the same for each cosigner's copy
+fn unsigned_txid(tx: &Transaction) -> Hash256 {
+    let mut tx = tx.clone();
+    for input in &mut tx.inputs {
+        input.signature_script.clear();
+    }
+    tx.hash()
+}
+
+/// The (signature, sighash byte) pairs of a multisig signature script, and its redeem script
+pub type MultisigSpend<'a> = (Vec<(&'a [u8], u8)>, &'a [u8]);
+
+/// Split a multisig signature script into its signatures and redeem script
+pub fn split_multisig_script(script: &[u8]) -> Option<MultisigSpend<'_>> {
+    let (&op_0, mut rest) = script.split_first()?;
+    if op_0 != 0x00 {
+        return None;
+    }
+    
+    let mut signatures = Vec::new();
+    while let Some((&len, after)) = rest.split_first().filter(|(&len, _)| len == 64 || len == 65) {
+        let signature = after.get(..len as usize)?;
+        let &sighash = after.get(len as usize)?;
+        signatures.push((signature, sighash));
+        rest = &after[len as usize + 1..];
+    }
+    
+    let (&opcode, after) = rest.split_first()?;
+    let (len, redeem) = match opcode {
+        OP_PUSHDATA1 => (*after.first()? as usize, &after[1..]),
+        OP_PUSHDATA2 => (u16::from_le_bytes([*after.first()?, *after.get(1)?]) as usize, &after[2..]),
+        len if len as usize <= MAX_DIRECT_PUSH => (len as usize, after),
+        _ => return None,
+    };
+    (redeem.len() == len && len > 0).then_some((signatures, redeem))
+}
+
+/// Check a multisig spend: its redeem script must hash to the output's script and
+/// carry at least `required` valid signatures, in the order of the keys they're from
+pub fn verify_multisig_input(tx: &Transaction, input_index: usize, script_pubkey: &[u8]) -> Result<()> {
+    let invalid = |reason: String| QtcError::Transaction(format!("Input {} {}", input_index, reason));
+    let input = tx.inputs.get(input_index)
+        .ok_or_else(|| QtcError::Transaction("Invalid input index".to_string()))?;
+    let (signatures, redeem) = split_multisig_script(&input.signature_script)
+        .ok_or_else(|| invalid("has a malformed multisig signature script".to_string()))?;
+    let script = MultisigScript::from_redeem_script(redeem)
+        .map_err(|_| invalid("has an invalid redeem script".to_string()))?;
+    if Transaction::address_to_script_pubkey(&script.to_address()) != script_pubkey {
+        return Err(invalid("has a redeem script that doesn't match the output it spends".to_string()));
+    }
+    if signatures.len() < script.required_signatures as usize {
+        return Err(invalid(format!(
+            "has {} of the {} signatures it needs", signatures.len(), script.required_signatures
+        )));
+    }
+    
+    let mut keys = script.public_keys.iter();
+    for (signature, sighash) in signatures {
+        let signature = Signature::from_bytes(signature)?;
+        let signature_hash = tx.get_signature_hash_with(input_index, SigHashType::from_u8(sighash)?)?;
+        if !keys.by_ref().any(|key| key.verify(&signature_hash, &signature).unwrap_or(false)) {
+            return Err(invalid("has an invalid multisig signature".to_string()));
+        }
+    }
+    Ok(())
+}
+
+fn push_data(script: &mut Vec<u8>, data: &[u8]) {
+    match data.len() {
+        len if len <= MAX_DIRECT_PUSH => script.push(len as u8),
+        len if len <= u8::MAX as usize => script.extend_from_slice(&[OP_PUSHDATA1, len as u8]),
+        len => {
+            script.push(OP_PUSHDATA2);
+            script.extend_from_slice(&(len as u16).to_le_bytes());
+        }
+    }
+    script.extend_from_slice(data);
+}
+
 impl SignatureCollector {
     pub fn new(
         transaction: Transaction,
@@ -227,9 +400,48 @@ impl SignatureCollector {
             required_signatures: script.required_signatures,
             signatures: HashMap::new(),
             script,
+            sighash: SigHashType::All,
         }
     }
     
+    /// Pick up the signatures already in the input's signature script, so the
+    /// transaction itself can travel from cosigner to cosigner
+    pub fn from_transaction(transaction: &Transaction, input_index: usize, script: MultisigScript) -> Result<Self> {
+        let existing = transaction.inputs.get(input_index)
+            .ok_or_else(|| QtcError::Multisig("Invalid input index".to_string()))?
+            .signature_script.clone();
+        let mut collector = Self::new(transaction.clone(), input_index, script);
+        if existing.is_empty() {
+            return Ok(collector);
+        }
+        
+        let (signatures, redeem) = split_multisig_script(&existing)
+            .ok_or_else(|| QtcError::Multisig(format!("Input {} is not a multisig spend", input_index)))?;
+        if redeem != collector.script.script.as_slice() {
+            return Err(QtcError::Multisig(format!("Input {} spends from another multisig address", input_index)));
+        }
+        for (signature, sighash) in signatures {
+            let signature = Signature::from_bytes(signature)?;
+            let sighash = SigHashType::from_u8(sighash)?;
+            collector.set_sighash(sighash)?;
+            let signature_hash = collector.transaction.get_signature_hash_with(input_index, sighash)?;
+            let signer = collector.script.public_keys.iter()
+                .position(|key| key.verify(&signature_hash, &signature).unwrap_or(false))
+                .ok_or_else(|| QtcError::Multisig(format!("Input {} has a signature from none of the keys", input_index)))?;
+            collector.add_signature(signer, signature)?;
+        }
+        Ok(collector)
+    }
+    
+    /// Only takes effect before the first signature, since all signatures must commit to the same data
+    pub fn set_sighash(&mut self, sighash: SigHashType) -> Result<()> {
+        if !self.signatures.is_empty() && sighash != self.sighash {
+            return Err(QtcError::Multisig("Cannot change sighash type after signing".to_string()));
+        }
+        self.sighash = sighash;
+        Ok(())
+    }
+    
     pub fn add_signature(&mut self, signer_index: usize, signature: Signature) -> Result<()> {
         if signer_index >= self.script.total_keys as usize {
             return Err(QtcError::Multisig("Invalid signer index".to_string()));
@@ -238,7 +450,7 @@ impl SignatureCollector {
         let public_key = self.script.public_keys[signer_index].clone();
         
         // Verify the signature
-        let signature_hash = self.transaction.get_signature_hash(self.input_index);
+        let signature_hash = self.transaction.get_signature_hash_with(self.input_index, self.sighash)?;
         if !public_key.verify(&signature_hash, &signature)? {
             return Err(QtcError::Multisig("Invalid signature".to_string()));
         }
@@ -267,7 +479,7 @@ impl SignatureCollector {
             return Err(QtcError::Multisig("Private key doesn't match public key".to_string()));
         }
         
-        let signature_hash = self.transaction.get_signature_hash(self.input_index);
+        let signature_hash = self.transaction.get_signature_hash_with(self.input_index, self.sighash)?;
         let signature = private_key.sign(&signature_hash)?;
         
         self.add_signature(signer_index, signature)
@@ -296,34 +508,31 @@ impl SignatureCollector {
         
         let mut tx = self.transaction.clone();
         
-        // Create the signature script for multisig
-        let mut signature_script = Vec::new();
+        // Update the input's signature script
+        if self.input_index < tx.inputs.len() {
+            tx.inputs[self.input_index].signature_script = self.signature_script();
+        }
         
+        Ok(tx)
+    }
+    
+    /// `OP_0`, then the signatures collected so far (at most `required`) in key order, then the redeem script
+    pub fn signature_script(&self) -> Vec<u8> {
         // OP_0 (due to off-by-one bug in OP_CHECKMULTISIG)
-        signature_script.push(0x00);
+        let mut signature_script = vec![0x00];
         
-        // Add signatures in order
         let mut signature_indices: Vec<_> = self.signatures.keys().cloned().collect();
         signature_indices.sort();
         
-        for &index in &signature_indices[..self.required_signatures as usize] {
-            let partial_sig = &self.signatures[&index];
-            let sig_bytes = partial_sig.signature.to_bytes();
+        for index in signature_indices.into_iter().take(self.required_signatures as usize) {
+            let sig_bytes = self.signatures[&index].signature.to_bytes();
             signature_script.push(sig_bytes.len() as u8);
             signature_script.extend_from_slice(&sig_bytes);
+            signature_script.push(self.sighash.to_u8());
         }
         
-        // Add the redeem script
-        let redeem_script = self.script.get_redeem_script();
-        signature_script.push(redeem_script.len() as u8);
-        signature_script.extend_from_slice(redeem_script);
-        
-        // Update the input's signature script
-        if self.input_index < tx.inputs.len() {
-            tx.inputs[self.input_index].signature_script = signature_script;
-        }
-        
-        Ok(tx)
+        push_data(&mut signature_script, self.script.get_redeem_script());
+        signature_script
     }
     
     pub fn export_partial_signatures(&self) -> Vec<(usize, Signature)> {
@@ -425,6 +634,13 @@ impl MultisigUtils {
         base_size + input_size + script_size + (2 * output_size)
     }
     
+    /// Bytes in a fully signed input's signature script
+    pub fn signature_script_size(required: u32, total: u32) -> usize {
+        let redeem_script = 1 + total as usize * 34 + 2;
+        let push = if redeem_script <= MAX_DIRECT_PUSH { 1 } else if redeem_script <= u8::MAX as usize { 2 } else { 3 };
+        1 + required as usize * (1 + 65 + 1) + push + redeem_script
+    }
+    
     pub fn calculate_multisig_fee(required: u32, total: u32, fee_rate: u64) -> u64 {
         let size = Self::estimate_multisig_size(required, total) as u64;
         size * fee_rate
@@ -502,6 +718,52 @@ mod tests {
         Ok(())
     }
     
+    #[test]
+    fn test_cosigners_sign_a_psbt_in_turn() -> Result<()> {
+        use crate::core::transaction::{OutPoint, PreviewOutput};
+        use crate::storage::Database;
+        use crate::wallet::psbt::PsbtInput;
+        use tempfile::TempDir;
+        
+        let keys: Vec<KeyPair> = (0..3).map(|_| KeyPair::new()).collect::<Result<_>>()?;
+        let public_keys = keys.iter().map(|key| key.public_key.clone()).collect();
+        let multisig = MultisigWallet::new("vault".to_string(), 2, public_keys, vec![0, 2])?;
+        assert_eq!(MultisigScript::from_redeem_script(&multisig.script.script)?.to_address(), multisig.address);
+        
+        // Stored beside the wallet records without showing up as a wallet of its own
+        let temp_dir = TempDir::new().unwrap();
+        let db = Database::new(temp_dir.path().join("test.db"))?;
+        db.save_multisig_wallet(&multisig)?;
+        assert_eq!(db.get_multisig_wallet("vault")?.unwrap().address, multisig.address);
+        assert!(db.list_wallets()?.is_empty());
+        
+        let mut tx = Transaction::new();
+        tx.add_input(OutPoint::new(Hash256::hash(b"funding"), 0), Vec::new());
+        tx.add_output(90_000, "qtc1payee");
+        let input = PsbtInput { outpoint: tx.inputs[0].previous_output.clone(), value: 100_000, address: multisig.address.clone() };
+        let output = PreviewOutput { address: "qtc1payee".to_string(), value: 90_000 };
+        let unsigned = Psbt::new(tx, vec![input], vec![output])?;
+        
+        // Key 1 isn't ours, so only key 0 signs here; key 2 signs a copy elsewhere
+        let mut first = unsigned.clone();
+        let ours = multisig.our_keys([&keys[0].private_key, &keys[1].private_key])?;
+        assert_eq!(multisig.sign_psbt(&mut first, &ours, None)?, 1);
+        assert_eq!(multisig.missing_signatures(&first)?, 1);
+        let script_pubkey = Transaction::address_to_script_pubkey(&multisig.address);
+        assert!(verify_multisig_input(&first.tx, 0, &script_pubkey).is_err());
+        
+        let mut second = unsigned;
+        multisig.sign_psbt(&mut second, &multisig.our_keys([&keys[2].private_key])?, Some(0))?;
+        assert_eq!(multisig.combine_psbt(&mut first, &second)?, 1);
+        assert_eq!(multisig.missing_signatures(&first)?, 0);
+        verify_multisig_input(&first.tx, 0, &script_pubkey)?;
+        
+        // The same signatures don't unlock an output paid to another address
+        let elsewhere = Transaction::address_to_script_pubkey(&keys[0].address());
+        assert!(verify_multisig_input(&first.tx, 0, &elsewhere).is_err());
+        Ok(())
+    }
+    
     #[test]
     fn test_multisig_validation() {
         assert!(MultisigUtils::validate_multisig_params(2, 3).is_ok());
@@ -0,0 +1,404 @@
+//! Partially signed transactions for air-gapped signing
+//!
+//! A `Psbt` carries an unsigned transaction together with what an offline
+//! signer needs to check it: the value and owner of every input and the
+//! address behind every output. It travels between devices as a sequence of
+//! UR-style text frames small enough to show one per QR code, e.g.
+//! `UR:QTC-PSBT/2-5/1A2B3C4D/<hex fragment>`. Frames use only characters from
+//! the QR alphanumeric set so each code stays as small as possible. Where a
+//! USB stick or copy and paste is easier, the same bytes go as one hex string.
+
+use crate::core::transaction::{OutPoint, PreviewOutput, SigHashType};
+use crate::core::Transaction;
+use crate::crypto::hash::{Hash256, Hashable};
+use crate::wallet::signer::{sign_transaction, InputToSign, LocalSigner, TransactionSigner};
+use crate::wallet::Wallet;
+use crate::{QtcError, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+const PSBT_MAGIC: &[u8; 5] = b"qpsbt";
+const PSBT_VERSION: u8 = 1;
+
+/// Inputs of an exported PSBT stay locked this long while the offline signer has it
+pub const PSBT_LOCK_TTL_SECS: u64 = 24 * 60 * 60;
+
+pub const UR_PREFIX: &str = "UR:QTC-PSBT/";
+
+/// Payload bytes per frame; 200 bytes fit a version 13 QR code at medium error correction
+pub const DEFAULT_FRAGMENT_LEN: usize = 200;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PsbtInput {
+    pub outpoint: OutPoint,
+    pub value: u64,
+    pub address: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Psbt {
+    pub tx: Transaction,
+    pub inputs: Vec<PsbtInput>,
+    pub outputs: Vec<PreviewOutput>,
+}
+
+impl Psbt {
+    pub fn new(tx: Transaction, inputs: Vec<PsbtInput>, outputs: Vec<PreviewOutput>) -> Result<Self> {
+        let psbt = Self { tx, inputs, outputs };
+        psbt.check()?;
+        Ok(psbt)
+    }
+
+    /// Make sure the metadata describes the transaction, so a signer never approves
+    /// amounts or addresses other than the ones it is shown
+    pub fn check(&self) -> Result<()> {
+        if self.inputs.len() != self.tx.inputs.len() || self.outputs.len() != self.tx.outputs.len() {
+            return Err(QtcError::Transaction("PSBT metadata does not match its transaction".to_string()));
+        }
+
+        for (input, tx_input) in self.inputs.iter().zip(&self.tx.inputs) {
+            if input.outpoint != tx_input.previous_output {
+                return Err(QtcError::Transaction(format!(
+                    "PSBT input {}:{} does not match its transaction", input.outpoint.txid, input.outpoint.vout
+                )));
+            }
+        }
+        for (output, tx_output) in self.outputs.iter().zip(&self.tx.outputs) {
+            if output.value != tx_output.value
+                || Transaction::address_to_script_pubkey(&output.address) != tx_output.script_pubkey
+            {
+                return Err(QtcError::Transaction(format!(
+                    "PSBT output to {} does not match its transaction", output.address
+                )));
+            }
+        }
+
+        if self.total_input()? < self.tx.total_output_value() {
+            return Err(QtcError::Transaction("PSBT spends more than its inputs".to_string()));
+        }
+        Ok(())
+    }
+
+    /// Sum of the input values, an error if a crafted PSBT makes it overflow
+    pub fn total_input(&self) -> Result<u64> {
+        self.inputs.iter().try_fold(0u64, |total, input| total.checked_add(input.value))
+            .ok_or_else(|| QtcError::Transaction("PSBT input values overflow".to_string()))
+    }
+
+    pub fn fee(&self) -> Result<u64> {
+        Ok(self.total_input()?.saturating_sub(self.tx.total_output_value()))
+    }
+
+    pub fn txid(&self) -> Hash256 {
+        self.tx.hash()
+    }
+
+    /// Sign every input `wallet` holds the key for; returns how many were signed
+    pub fn sign(&mut self, wallet: &Wallet) -> Result<usize> {
+        self.sign_with(wallet, SigHashType::All)
+    }
+    
+    /// `sign`, committing each signature to only the parts of the transaction `sighash` covers
+    pub fn sign_with(&mut self, wallet: &Wallet, sighash: SigHashType) -> Result<usize> {
+        self.sign_with_signer(&LocalSigner::new(wallet), sighash)
+    }
+    
+    /// Sign with keys held outside the wallet, such as on a hardware device
+    pub fn sign_with_signer(&mut self, signer: &dyn TransactionSigner, sighash: SigHashType) -> Result<usize> {
+        self.check()?;
+        let inputs = self.inputs_to_sign();
+        sign_transaction(signer, &mut self.tx, &inputs, sighash)
+    }
+    
+    /// The inputs as a signer sees them
+    pub fn inputs_to_sign(&self) -> Vec<InputToSign> {
+        self.inputs.iter()
+            .enumerate()
+            .map(|(index, input)| InputToSign {
+                index,
+                outpoint: input.outpoint.clone(),
+                value: input.value,
+                address: input.address.clone(),
+            })
+            .collect()
+    }
+
+    pub fn is_signed(&self) -> bool {
+        self.tx.inputs.iter().all(|input| !input.signature_script.is_empty())
+    }
+
+    /// The transaction ready to broadcast, once every input carries a signature
+    pub fn finalize(self) -> Result<Transaction> {
+        self.check()?;
+        let unsigned = self.tx.inputs.iter().filter(|input| input.signature_script.is_empty()).count();
+        if unsigned > 0 {
+            return Err(QtcError::Transaction(format!("PSBT still has {} unsigned input(s)", unsigned)));
+        }
+        Ok(self.tx)
+    }
+
+    pub fn serialize(&self) -> Result<Vec<u8>> {
+        let mut data = PSBT_MAGIC.to_vec();
+        data.push(PSBT_VERSION);
+        data.extend(bincode::serialize(self)
+            .map_err(|e| QtcError::Transaction(format!("Failed to serialize PSBT: {}", e)))?);
+        Ok(data)
+    }
+
+    pub fn deserialize(data: &[u8]) -> Result<Self> {
+        let body = data.strip_prefix(PSBT_MAGIC.as_slice())
+            .ok_or_else(|| QtcError::Transaction("Not a QTC PSBT".to_string()))?;
+        match body.split_first() {
+            Some((&PSBT_VERSION, body)) => {
+                let psbt: Self = bincode::deserialize(body)
+                    .map_err(|e| QtcError::Transaction(format!("Corrupt PSBT: {}", e)))?;
+                psbt.check()?;
+                Ok(psbt)
+            }
+            Some((version, _)) => Err(QtcError::Transaction(format!("Unsupported PSBT version {}", version))),
+            None => Err(QtcError::Transaction("Empty PSBT".to_string())),
+        }
+    }
+
+    pub fn to_hex(&self) -> Result<String> {
+        Ok(hex::encode(self.serialize()?))
+    }
+
+    pub fn from_hex(data: &str) -> Result<Self> {
+        let bytes = hex::decode(data.trim())
+            .map_err(|_| QtcError::InvalidInput("PSBT is not valid hex".to_string()))?;
+        Self::deserialize(&bytes)
+    }
+
+    pub fn to_frames(&self, fragment_len: usize) -> Result<Vec<String>> {
+        Ok(encode_frames(&self.serialize()?, fragment_len))
+    }
+}
+
+/// First four bytes of the payload hash, tying frames of one PSBT together
+fn checksum(data: &[u8]) -> String {
+    hex::encode_upper(&Hash256::hash(data).as_bytes()[..4])
+}
+
+/// Split `data` into numbered frames of at most `fragment_len` payload bytes each
+pub fn encode_frames(data: &[u8], fragment_len: usize) -> Vec<String> {
+    let checksum = checksum(data);
+    let chunks: Vec<&[u8]> = data.chunks(fragment_len.max(1)).collect();
+    let total = chunks.len();
+
+    chunks.iter()
+        .enumerate()
+        .map(|(index, chunk)| format!("{}{}-{}/{}/{}", UR_PREFIX, index + 1, total, checksum, hex::encode_upper(chunk)))
+        .collect()
+}
+
+/// Reassembles frames scanned in any order, with repeats, as an animated QR loop produces them
+#[derive(Debug, Default)]
+pub struct FrameDecoder {
+    total: usize,
+    checksum: String,
+    fragments: BTreeMap<usize, Vec<u8>>,
+}
+
+impl FrameDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add one frame; returns false for a frame that was already received
+    pub fn receive(&mut self, frame: &str) -> Result<bool> {
+        let frame = frame.trim();
+        let invalid = || QtcError::InvalidInput(format!("Invalid PSBT frame: {}", frame));
+
+        // Scanners in keyboard mode sometimes lower-case everything
+        let body = frame.get(..UR_PREFIX.len())
+            .filter(|prefix| prefix.eq_ignore_ascii_case(UR_PREFIX))
+            .map(|_| &frame[UR_PREFIX.len()..])
+            .ok_or_else(invalid)?;
+
+        let mut parts = body.splitn(3, '/');
+        let (Some(sequence), Some(checksum), Some(fragment)) = (parts.next(), parts.next(), parts.next()) else {
+            return Err(invalid());
+        };
+        let (seq, total) = sequence.split_once('-').ok_or_else(invalid)?;
+        let seq: usize = seq.parse().map_err(|_| invalid())?;
+        let total: usize = total.parse().map_err(|_| invalid())?;
+        let checksum = checksum.to_ascii_uppercase();
+        let fragment = hex::decode(fragment).map_err(|_| invalid())?;
+
+        if total == 0 || seq == 0 || seq > total || checksum.len() != 8 {
+            return Err(invalid());
+        }
+
+        if self.fragments.is_empty() {
+            self.total = total;
+            self.checksum = checksum;
+        } else if total != self.total || checksum != self.checksum {
+            return Err(QtcError::InvalidInput(format!(
+                "Frame belongs to a different PSBT ({}, expected {})", checksum, self.checksum
+            )));
+        }
+
+        if self.fragments.contains_key(&seq) {
+            return Ok(false);
+        }
+        self.fragments.insert(seq, fragment);
+        Ok(true)
+    }
+
+    /// (frames received, frames expected); expected is 0 until the first frame arrives
+    pub fn progress(&self) -> (usize, usize) {
+        (self.fragments.len(), self.total)
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.total > 0 && self.fragments.len() == self.total
+    }
+
+    /// Frame numbers still missing, for telling the user which ones to rescan
+    pub fn missing(&self) -> Vec<usize> {
+        (1..=self.total).filter(|seq| !self.fragments.contains_key(seq)).collect()
+    }
+
+    pub fn result(&self) -> Result<Vec<u8>> {
+        if !self.is_complete() {
+            return Err(QtcError::InvalidInput(format!(
+                "Incomplete PSBT: received {} of {} frames", self.fragments.len(), self.total
+            )));
+        }
+
+        let data: Vec<u8> = self.fragments.values().flatten().copied().collect();
+        if checksum(&data) != self.checksum {
+            return Err(QtcError::InvalidInput("PSBT checksum mismatch, rescan the frames".to_string()));
+        }
+        Ok(data)
+    }
+
+    pub fn psbt(&self) -> Result<Psbt> {
+        Psbt::deserialize(&self.result()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::Blockchain;
+    use crate::storage::Database;
+    use std::sync::{Arc, RwLock};
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_psbt_frames_roundtrip_and_sign() -> Result<()> {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Arc::new(Database::new(temp_dir.path().join("test.db"))?);
+        let blockchain = Arc::new(RwLock::new(Blockchain::new(db.clone())?));
+        let wallet = Wallet::new_simple("cold".to_string(), db, blockchain)?;
+        let owned = wallet.get_addresses()[0].clone();
+
+        let outpoint = OutPoint::new(Hash256::hash(b"funding"), 0);
+        let mut tx = Transaction::new();
+        tx.add_input(outpoint.clone(), Vec::new());
+        tx.add_output(40_000, "qtc1recipient");
+        let outputs = vec![PreviewOutput { address: "qtc1recipient".to_string(), value: 40_000 }];
+        let inputs = vec![PsbtInput { outpoint, value: 50_000, address: owned }];
+        let psbt = Psbt::new(tx, inputs, outputs)?;
+        assert_eq!(psbt.fee()?, 10_000);
+        assert!(psbt.clone().finalize().is_err());
+
+        // Scan the frames out of order, with a repeat, as a camera would
+        let frames = psbt.to_frames(32)?;
+        assert!(frames.len() > 3);
+        let mut decoder = FrameDecoder::new();
+        for frame in frames.iter().rev() {
+            assert!(decoder.receive(frame)?);
+        }
+        assert!(!decoder.receive(&frames[0].to_lowercase())?);
+        assert!(decoder.is_complete());
+
+        let mut received = decoder.psbt()?;
+        assert_eq!(received.sign(&wallet)?, 1);
+        assert!(received.is_signed());
+        let signed = received.finalize()?;
+        assert!(!signed.inputs[0].signature_script.is_empty());
+
+        // Frames from another PSBT or with a tampered payload are refused
+        let other = encode_frames(b"something else", 32);
+        assert!(decoder.receive(&other[0]).is_err());
+        let mut tampered = FrameDecoder::new();
+        let mut flipped = frames[0].clone();
+        let last = if flipped.pop() == Some('0') { '1' } else { '0' };
+        flipped.push(last);
+        tampered.receive(&flipped)?;
+        for frame in &frames[1..] {
+            tampered.receive(frame)?;
+        }
+        assert!(tampered.result().is_err());
+
+        let mut partial = FrameDecoder::new();
+        partial.receive(&frames[1])?;
+        assert_eq!(partial.missing().len(), frames.len() - 1);
+        assert!(partial.result().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_psbt_rejects_overflowing_input_values() {
+        let mut tx = Transaction::new();
+        let mut inputs = Vec::new();
+        for n in 0..2u32 {
+            let outpoint = OutPoint::new(Hash256::hash(b"funding"), n);
+            tx.add_input(outpoint.clone(), Vec::new());
+            inputs.push(PsbtInput { outpoint, value: u64::MAX / 2 + 1, address: "qtc1payer".to_string() });
+        }
+        tx.add_output(1_000, "qtc1recipient");
+        let outputs = vec![PreviewOutput { address: "qtc1recipient".to_string(), value: 1_000 }];
+
+        let err = Psbt::new(tx, inputs, outputs).unwrap_err();
+        assert!(err.to_string().contains("overflow"));
+    }
+
+    #[test]
+    fn test_watch_only_wallet_prepares_for_offline_signer() -> Result<()> {
+        use crate::core::UtxoEntry;
+        use crate::crypto::keys::KeyPair;
+        use crate::wallet::viewonly::ViewOnlyBundle;
+
+        // The cold machine has its own empty chain; only the hot one knows about the funds
+        let temp_dir = TempDir::new().unwrap();
+        let cold_db = Arc::new(Database::new(temp_dir.path().join("cold.db"))?);
+        let cold = Wallet::new_simple("cold".to_string(), cold_db.clone(), Arc::new(RwLock::new(Blockchain::new(cold_db)?)))?;
+        let hot_db = Arc::new(Database::new(temp_dir.path().join("hot.db"))?);
+        let hot_chain = Arc::new(RwLock::new(Blockchain::new(hot_db.clone())?));
+        let hot = ViewOnlyBundle::from_wallet(&cold)?.into_watch_only_wallet("hot".to_string(), hot_db.clone(), hot_chain.clone())?;
+
+        let address = cold.get_addresses()[0].clone();
+        let funding = Transaction::new_coinbase(address.clone(), 10_000_000, "funding".to_string());
+        let outpoint = OutPoint::new(funding.hash(), 0);
+        hot_db.save_utxo(&outpoint, &UtxoEntry {
+            txid: funding.hash(),
+            vout: 0,
+            value: 10_000_000,
+            script_pubkey: funding.outputs[0].script_pubkey.clone(),
+            address: address.clone(),
+            height: 1,
+            is_coinbase: false,
+        })?;
+
+        let recipient = KeyPair::new()?.address();
+        let mut unsigned = hot.create_psbt(&recipient, 4_000_000, 10_000)?;
+        assert_eq!(hot.list_locked_unspent()?.len(), 1);
+        assert_eq!(unsigned.sign(&hot)?, 0);
+
+        let mut received = Psbt::from_hex(&format!("{}\n", unsigned.to_hex()?))?;
+        assert_eq!(received.inputs[0].value, 10_000_000);
+        assert_eq!(received.sign(&cold)?, 1);
+
+        let signed = Psbt::from_hex(&received.to_hex()?)?;
+        assert!(signed.is_signed() && !unsigned.is_signed());
+        hot_chain.read().unwrap().accept_to_mempool(signed.finalize()?)?;
+
+        assert!(Psbt::from_hex("not hex").is_err());
+        assert!(Psbt::from_hex("00ff").is_err());
+        Ok(())
+    }
+}
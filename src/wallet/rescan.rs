@@ -0,0 +1,212 @@
+//! Wallet rescans
+//!
+//! Keys imported into a wallet, or a wallet restored from its seed, may have
+//! been paid long before the wallet knew them. A rescan walks the main chain's
+//! blocks, matching their outputs, and the outputs they spent (from block undo
+//! data), against the wallet's addresses. HD wallets derive more receive
+//! addresses as payments turn up, keeping `ADDRESS_GAP_LIMIT` unused ones past
+//! the last one paid. Addresses found touched are marked used and the balance
+//! is recomputed; a rescan from genesis also replaces the history cache.
+//!
+//! Where a rescan stopped is saved per wallet, so the next one only looks at
+//! newer blocks, unless the wallet has gained addresses since or that block
+//! was reorganized away, which start it over from genesis.
+
+use crate::crypto::hash::{Hash256, Hashable};
+use crate::wallet::gap::ADDRESS_GAP_LIMIT;
+use crate::wallet::history::{self, HistoryEntry, WalletHistory};
+use crate::wallet::Wallet;
+use crate::{QtcError, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+/// How far a wallet has been rescanned
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RescanCursor {
+    pub tip: Hash256,
+    pub height: u64,
+    pub addresses: usize, // how many addresses the wallet had
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct RescanReport {
+    pub from_height: u64,
+    pub to_height: u64,
+    pub blocks_scanned: u64,
+    pub transactions: Vec<HistoryEntry>, // oldest first
+    pub newly_used: usize, // addresses found touched that weren't marked used
+    pub derived: u32,      // receive addresses an HD wallet derived along the way
+    pub balance: u64,
+}
+
+/// Rescan `wallet` from `from_height`, or from where the last rescan stopped.
+/// `progress` is called with each height scanned and the height being scanned to.
+pub fn rescan(wallet: &mut Wallet, from_height: Option<u64>, mut progress: impl FnMut(u64, u64)) -> Result<RescanReport> {
+    let db = wallet.db.clone();
+    let name = wallet.info.name.clone();
+    let (tip, tip_height) = {
+        let blockchain = wallet.blockchain.read().unwrap();
+        (blockchain.tip, blockchain.height)
+    };
+
+    let start = match from_height {
+        Some(height) if height > tip_height => {
+            return Err(QtcError::InvalidInput(format!("Height {} is past the tip at {}", height, tip_height)));
+        }
+        Some(height) => height,
+        None => match db.get_wallet_rescan_cursor(&name)? {
+            Some(cursor) if cursor.addresses == wallet.addresses.len()
+                && db.get_block_header_by_height(cursor.height)?.is_some_and(|header| header.hash() == cursor.tip) => {
+                cursor.height + 1
+            }
+            _ => 0,
+        },
+    };
+    if let Some(pruned) = db.get_pruned_height()? {
+        if start <= pruned && start <= tip_height {
+            return Err(QtcError::Wallet(format!(
+                "Blocks up to {} are pruned; rescan from height {} or later", pruned, pruned + 1
+            )));
+        }
+    }
+
+    let mut report = RescanReport { from_height: start, to_height: tip_height, ..RescanReport::default() };
+    report.derived += top_up_lookahead(wallet)?;
+    let mut ours: HashSet<String> = wallet.addresses.keys().cloned().collect();
+    let mut found = WalletHistory::default();
+
+    for height in start..=tip_height {
+        let block = db.get_block_by_height(height)?
+            .ok_or_else(|| QtcError::Blockchain(format!("Block at height {} not found", height)))?;
+        let before = found.entries.len();
+        history::scan_block(&db, &mut found, &block, &ours)?;
+        report.blocks_scanned += 1;
+
+        if found.entries.len() > before {
+            let mut marked = false;
+            for address in found.address_use.keys() {
+                if let Some(addr) = wallet.addresses.get_mut(address).filter(|addr| !addr.used) {
+                    addr.used = true;
+                    report.newly_used += 1;
+                    marked = true;
+                }
+            }
+            if marked {
+                report.derived += top_up_lookahead(wallet)?;
+                ours.extend(wallet.addresses.keys().cloned());
+            }
+        }
+        progress(height, tip_height);
+    }
+
+    wallet.save()?;
+    if start == 0 {
+        found.tip = tip;
+        found.height = tip_height;
+        found.addresses = ours.len();
+        db.save_wallet_history(&name, &found)?;
+    }
+    report.transactions = found.entries;
+    report.balance = wallet.refresh_balance()?;
+    db.save_wallet_rescan_cursor(&name, &RescanCursor { tip, height: tip_height, addresses: wallet.addresses.len() })?;
+    Ok(report)
+}
+
+/// Derive receive addresses until `ADDRESS_GAP_LIMIT` unused ones follow the
+/// last used one; returns how many were derived. Only HD wallets can.
+fn top_up_lookahead(wallet: &mut Wallet) -> Result<u32> {
+    if wallet.hd_wallet.is_none() {
+        return Ok(0);
+    }
+    let receive = || wallet.addresses.values().filter(|addr| !addr.is_change);
+    let next = receive().filter_map(|addr| addr.derivation_index()).max().map_or(0, |index| index + 1);
+    let unused_after = match receive().filter(|addr| addr.used).filter_map(|addr| addr.derivation_index()).max() {
+        Some(last_used) => next - last_used - 1,
+        None => next,
+    };
+
+    let missing = ADDRESS_GAP_LIMIT.saturating_sub(unused_after);
+    if missing > 0 {
+        wallet.generate_addresses(missing)?;
+    }
+    Ok(missing)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::consensus::target::REGTEST_BITS;
+    use crate::consensus::params::ChainParams;
+    use crate::core::{Block, Blockchain, Transaction, UtxoSet};
+    use crate::storage::Database;
+    use crate::wallet::bip39::Mnemonic;
+    use std::sync::{Arc, RwLock};
+    use tempfile::TempDir;
+
+    fn mine_block(chain: &Blockchain, address: &str) -> Result<Block> {
+        let parent = chain.get_block_header_by_height(chain.height)?.unwrap();
+        let height = chain.height + 1;
+        let coinbase = Transaction::new_coinbase(
+            address.to_string(),
+            chain.monetary_policy().coinbase_reward(height),
+            format!("height {}", height),
+        );
+        let mut block = Block::new(chain.tip, vec![coinbase], REGTEST_BITS, height);
+        block.header.timestamp = parent.timestamp + 30;
+        while !chain.is_valid_proof_of_work(&block) {
+            block.increment_nonce();
+        }
+        Ok(block)
+    }
+
+    #[test]
+    fn test_rescan_finds_payments_and_derives_past_them() -> Result<()> {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Arc::new(Database::new(temp_dir.path().join("test.db"))?);
+        let mut chain = Blockchain::new(db.clone())?;
+        chain.set_chain_params(ChainParams {
+            initial_bits: REGTEST_BITS,
+            difficulty_adjustment_interval: 1_000_000,
+            ..ChainParams::mainnet()
+        });
+        let blockchain = Arc::new(RwLock::new(chain));
+
+        let mnemonic = Mnemonic::new(12)?;
+        let mut wallet = Wallet::new_hd("restored".to_string(), &mnemonic, "", db.clone(), blockchain.clone())?;
+        let report = rescan(&mut wallet, None, |_, _| {})?;
+        assert_eq!((report.blocks_scanned, report.newly_used), (1, 0));
+        assert_eq!(wallet.addresses.len(), ADDRESS_GAP_LIMIT as usize);
+
+        // Outputs are matched by their `UtxoSet::output_address`, so receive index 15 goes by that
+        let paid = UtxoSet::output_address(&Transaction::address_to_script_pubkey("qtc1rescan"));
+        let key = wallet.addresses.values().find(|addr| addr.derivation_index() == Some(15)).unwrap().address.clone();
+        let mut addr = wallet.addresses.remove(&key).unwrap();
+        addr.address = paid.clone();
+        wallet.addresses.insert(paid.clone(), addr);
+        {
+            let mut chain = blockchain.write().unwrap();
+            for _ in 0..2 {
+                let block = mine_block(&chain, "qtc1rescan")?;
+                chain.add_block(block)?;
+            }
+        }
+
+        // Picks up after the last rescan, and keeps the gap past index 15
+        let mut heights = Vec::new();
+        let report = rescan(&mut wallet, None, |height, _| heights.push(height))?;
+        assert_eq!(heights, vec![1, 2]);
+        assert_eq!(report.transactions.iter().map(|entry| entry.height).collect::<Vec<_>>(), vec![1, 2]);
+        assert!(wallet.addresses[&paid].used);
+        assert_eq!((report.newly_used, report.derived), (1, 16));
+        assert_eq!(wallet.addresses.len(), 16 + ADDRESS_GAP_LIMIT as usize);
+        let policy = blockchain.read().unwrap().monetary_policy().clone();
+        assert_eq!(report.balance, policy.coinbase_reward(1) + policy.coinbase_reward(2));
+
+        // From genesis again, replacing the history cache
+        let report = rescan(&mut wallet, Some(0), |_, _| {})?;
+        assert_eq!((report.blocks_scanned, report.transactions.len(), report.newly_used), (3, 2, 0));
+        assert_eq!(wallet.get_transaction_history()?.len(), 2);
+        assert!(rescan(&mut wallet, Some(9), |_, _| {}).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,249 @@
+//! Signers that produce input signatures without the wallet touching the keys
+//!
+//! Building a transaction only needs to know which outputs it spends; whoever
+//! holds the keys is behind `TransactionSigner`. `LocalSigner` uses the keys
+//! stored in a wallet, while `ExternalSigner` hands a `SigningRequest` to
+//! something else (a helper program talking to a hardware wallet, or a file
+//! carried to an air-gapped machine) and takes back a `SigningResponse`.
+//! Either way the returned signatures are checked against the input's address
+//! and signature hash before they go into the transaction.
+
+use crate::core::transaction::{p2pkh_signature_script, OutPoint, SigHashType};
+use crate::core::Transaction;
+use crate::crypto::hash::Hashable;
+use crate::crypto::keys::{PrivateKey, PublicKey};
+use crate::crypto::signatures::Signature;
+use crate::wallet::Wallet;
+use crate::{QtcError, Result};
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// A P2PKH input the signer is asked to sign
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InputToSign {
+    pub index: usize,
+    pub outpoint: OutPoint,
+    pub value: u64,
+    pub address: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InputSignature {
+    pub index: usize,
+    pub signature: String,  // hex
+    pub public_key: String, // hex
+}
+
+pub trait TransactionSigner {
+    /// Signatures for the inputs this signer holds keys for; others are left out
+    fn sign_inputs(&self, tx: &Transaction, inputs: &[InputToSign], sighash: SigHashType) -> Result<Vec<InputSignature>>;
+}
+
+/// Ask `signer` for signatures and write the valid ones into `tx`; returns how many inputs were signed
+pub fn sign_transaction(
+    signer: &dyn TransactionSigner,
+    tx: &mut Transaction,
+    inputs: &[InputToSign],
+    sighash: SigHashType,
+) -> Result<usize> {
+    let signatures = signer.sign_inputs(tx, inputs, sighash)?;
+
+    // Check everything before changing anything, so a bad response leaves `tx` as it was
+    let mut scripts = Vec::with_capacity(signatures.len());
+    for signed in &signatures {
+        let input = inputs.iter()
+            .find(|input| input.index == signed.index)
+            .ok_or_else(|| QtcError::Transaction(format!("Signer returned a signature for unknown input {}", signed.index)))?;
+        let invalid = |reason: &str| QtcError::Transaction(format!("Signature for input {} {}", input.index, reason));
+
+        let public_key = hex::decode(&signed.public_key).ok()
+            .and_then(|bytes| PublicKey::from_bytes(&bytes).ok())
+            .ok_or_else(|| invalid("has a malformed public key"))?;
+        let signature = hex::decode(&signed.signature).ok()
+            .and_then(|bytes| Signature::from_bytes(&bytes).ok())
+            .ok_or_else(|| invalid("is malformed"))?;
+        if public_key.to_address() != input.address {
+            return Err(invalid(&format!("is from a key that does not own {}", input.address)));
+        }
+        let signature_hash = tx.get_signature_hash_with(input.index, sighash)?;
+        if !public_key.verify(&signature_hash, &signature)? {
+            return Err(invalid("does not match the transaction"));
+        }
+        scripts.push((input.index, p2pkh_signature_script(&signature, sighash, &public_key)));
+    }
+
+    for (index, script) in &scripts {
+        tx.inputs[*index].signature_script = script.clone();
+    }
+    Ok(scripts.len())
+}
+
+/// Signs with the private keys a wallet stores
+pub struct LocalSigner<'a> {
+    wallet: &'a Wallet,
+}
+
+impl<'a> LocalSigner<'a> {
+    pub fn new(wallet: &'a Wallet) -> Self {
+        Self { wallet }
+    }
+}
+
+impl TransactionSigner for LocalSigner<'_> {
+    fn sign_inputs(&self, tx: &Transaction, inputs: &[InputToSign], sighash: SigHashType) -> Result<Vec<InputSignature>> {
+        let mut signatures = Vec::new();
+        for input in inputs {
+            // Watch-only addresses and addresses of other wallets are not ours to sign
+            let Ok(private_key_wif) = self.wallet.export_private_key(&input.address) else {
+                continue;
+            };
+            let private_key = PrivateKey::from_wif(&private_key_wif)?;
+            let signature = private_key.sign(&tx.get_signature_hash_with(input.index, sighash)?)?;
+            signatures.push(InputSignature {
+                index: input.index,
+                signature: hex::encode(signature.to_bytes()),
+                public_key: hex::encode(private_key.public_key()?.to_bytes()),
+            });
+        }
+        Ok(signatures)
+    }
+}
+
+/// What an external signer is given: the transaction and, per input, the hash to sign
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SigningRequest {
+    pub txid: String,
+    pub tx: Transaction,
+    pub sighash: SigHashType,
+    pub inputs: Vec<SigningRequestInput>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SigningRequestInput {
+    #[serde(flatten)]
+    pub input: InputToSign,
+    pub signature_hash: String, // hex
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SigningResponse {
+    pub signatures: Vec<InputSignature>,
+}
+
+impl SigningRequest {
+    pub fn new(tx: &Transaction, inputs: &[InputToSign], sighash: SigHashType) -> Result<Self> {
+        let inputs = inputs.iter()
+            .map(|input| Ok(SigningRequestInput {
+                input: input.clone(),
+                signature_hash: tx.get_signature_hash_with(input.index, sighash)?.to_hex(),
+            }))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self { txid: tx.hash().to_hex(), tx: tx.clone(), sighash, inputs })
+    }
+}
+
+type Exchange = Box<dyn Fn(&SigningRequest) -> Result<SigningResponse> + Send + Sync>;
+
+/// Signs by exchanging a `SigningRequest` for a `SigningResponse` with something outside the wallet
+pub struct ExternalSigner {
+    exchange: Exchange,
+}
+
+impl ExternalSigner {
+    pub fn new(exchange: impl Fn(&SigningRequest) -> Result<SigningResponse> + Send + Sync + 'static) -> Self {
+        Self { exchange: Box::new(exchange) }
+    }
+
+    /// Run `program` for every request, writing the request as JSON to its stdin and
+    /// reading the response as JSON from its stdout
+    pub fn command(program: impl Into<String>, args: Vec<String>) -> Self {
+        let program = program.into();
+        Self::new(move |request| {
+            let failed = |e: String| QtcError::Wallet(format!("External signer '{}' failed: {}", program, e));
+            let body = serde_json::to_vec(request).map_err(|e| failed(e.to_string()))?;
+
+            let mut child = Command::new(&program)
+                .args(&args)
+                .stdin(Stdio::piped())
+                .stdout(Stdio::piped())
+                .spawn()
+                .map_err(|e| failed(e.to_string()))?;
+            if let Some(mut stdin) = child.stdin.take() {
+                stdin.write_all(&body).map_err(|e| failed(e.to_string()))?;
+            }
+            let output = child.wait_with_output().map_err(|e| failed(e.to_string()))?;
+            if !output.status.success() {
+                return Err(failed(format!("exited with {}", output.status)));
+            }
+            serde_json::from_slice(&output.stdout).map_err(|e| failed(format!("invalid response: {}", e)))
+        })
+    }
+
+    /// Signatures that were made elsewhere, such as on an air-gapped machine, and imported
+    pub fn from_response(response: SigningResponse) -> Self {
+        Self::new(move |_| Ok(response.clone()))
+    }
+}
+
+impl TransactionSigner for ExternalSigner {
+    fn sign_inputs(&self, tx: &Transaction, inputs: &[InputToSign], sighash: SigHashType) -> Result<Vec<InputSignature>> {
+        let request = SigningRequest::new(tx, inputs, sighash)?;
+        Ok((self.exchange)(&request)?.signatures)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::Blockchain;
+    use crate::crypto::hash::Hash256;
+    use crate::storage::Database;
+    use std::sync::{Arc, RwLock};
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_local_and_external_signers_agree() -> Result<()> {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Arc::new(Database::new(temp_dir.path().join("test.db"))?);
+        let blockchain = Arc::new(RwLock::new(Blockchain::new(db.clone())?));
+        let wallet = Wallet::new_simple("signer".to_string(), db, blockchain)?;
+        let address = wallet.get_addresses()[0].clone();
+
+        let mut tx = Transaction::new();
+        tx.add_input(OutPoint::new(Hash256::hash(b"funding"), 0), vec![]);
+        tx.add_input(OutPoint::new(Hash256::hash(b"elsewhere"), 1), vec![]);
+        tx.add_output(40_000, &address);
+        let inputs = vec![
+            InputToSign { index: 0, outpoint: tx.inputs[0].previous_output.clone(), value: 50_000, address: address.clone() },
+            InputToSign { index: 1, outpoint: tx.inputs[1].previous_output.clone(), value: 1_000, address: "qtc1notours".to_string() },
+        ];
+
+        // The local signer skips the input it has no key for
+        let mut local = tx.clone();
+        assert_eq!(sign_transaction(&LocalSigner::new(&wallet), &mut local, &inputs, SigHashType::All)?, 1);
+        assert!(local.inputs[1].signature_script.is_empty());
+
+        // Air-gapped flow: export a request, sign it elsewhere, import the response
+        let request = SigningRequest::new(&tx, &inputs, SigHashType::All)?;
+        let exported = serde_json::to_string(&request).unwrap();
+        let offline: SigningRequest = serde_json::from_str(&exported).unwrap();
+        let response = SigningResponse {
+            signatures: LocalSigner::new(&wallet).sign_inputs(&offline.tx, &[offline.inputs[0].input.clone()], offline.sighash)?,
+        };
+        let mut external = tx.clone();
+        let signer = ExternalSigner::from_response(response.clone());
+        assert_eq!(sign_transaction(&signer, &mut external, &inputs, SigHashType::All)?, 1);
+        assert_eq!(external.inputs[0].signature_script, local.inputs[0].signature_script);
+
+        // Signatures for a different transaction or from the wrong key are refused, leaving tx untouched
+        let mut other = tx.clone();
+        other.outputs[0].value = 39_000;
+        assert!(sign_transaction(&signer, &mut other, &inputs, SigHashType::All).is_err());
+        assert!(other.inputs[0].signature_script.is_empty());
+        let mut wrong_owner = inputs.clone();
+        wrong_owner[0].address = "qtc1notours".to_string();
+        assert!(sign_transaction(&signer, &mut tx, &wrong_owner, SigHashType::All).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,212 @@
+//! Password protected view-only wallet exports
+//!
+//! A view-only bundle carries the public half of a wallet (xpub, addresses,
+//! public keys) plus its known history. It never contains private keys, so it
+//! can be handed to auditors and imported as a watch-only wallet elsewhere.
+
+use crate::core::Blockchain;
+use crate::crypto::encryption::{self, EncryptedPayload};
+use crate::storage::Database;
+use crate::wallet::wallet::{AddressType, PqcAddressData, Wallet, WalletAddress, WalletInfo, WalletType};
+use crate::{QtcError, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+
+pub const VIEW_ONLY_FORMAT: &str = "qtc-view-only";
+pub const VIEW_ONLY_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ViewOnlyAddress {
+    pub address: String,
+    pub public_key: String,
+    pub derivation_path: Option<String>,
+    pub is_change: bool,
+    pub used: bool,
+    pub address_type: AddressType,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ViewOnlyHistoryEntry {
+    pub txid: String,
+    pub block_height: u64,
+    pub is_coinbase: bool,
+    pub total_output: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ViewOnlyBundle {
+    pub wallet_name: String,
+    pub source_type: WalletType,
+    pub created_at: u64,
+    pub exported_at: u64,
+    pub xpub: Option<String>,
+    pub addresses: Vec<ViewOnlyAddress>,
+    pub history: Vec<ViewOnlyHistoryEntry>,
+}
+
+/// On-disk file format: a small plaintext header around the encrypted bundle
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ViewOnlyFile {
+    pub format: String,
+    pub version: u32,
+    pub payload: EncryptedPayload,
+}
+
+impl ViewOnlyBundle {
+    pub fn from_wallet(wallet: &Wallet) -> Result<Self> {
+        let xpub = match &wallet.hd_wallet {
+            Some(hd_wallet) => Some(hd_wallet.export_xpub()?),
+            None => None,
+        };
+
+        let mut addresses: Vec<ViewOnlyAddress> = wallet.addresses.values()
+            .map(|addr| ViewOnlyAddress {
+                address: addr.address.clone(),
+                public_key: hex::encode(&addr.public_key),
+                derivation_path: addr.derivation_path.clone(),
+                is_change: addr.is_change,
+                used: addr.used,
+                address_type: addr.address_type.clone(),
+            })
+            .collect();
+        addresses.sort_by(|a, b| a.address.cmp(&b.address));
+
+        let mut history = Vec::new();
+        for addr in &addresses {
+            for (txid, tx, height) in wallet.db.get_address_transactions(&addr.address, 1000)? {
+                if history.iter().any(|h: &ViewOnlyHistoryEntry| h.txid == txid.to_hex()) {
+                    continue;
+                }
+                history.push(ViewOnlyHistoryEntry {
+                    txid: txid.to_hex(),
+                    block_height: height,
+                    is_coinbase: tx.is_coinbase(),
+                    total_output: tx.total_output_value(),
+                });
+            }
+        }
+        history.sort_by_key(|h| std::cmp::Reverse(h.block_height));
+
+        Ok(Self {
+            wallet_name: wallet.info.name.clone(),
+            source_type: wallet.info.wallet_type.clone(),
+            created_at: wallet.info.created_at,
+            exported_at: chrono::Utc::now().timestamp() as u64,
+            xpub,
+            addresses,
+            history,
+        })
+    }
+
+    pub fn encrypt(&self, password: &str) -> Result<ViewOnlyFile> {
+        let plaintext = serde_json::to_vec(self)?;
+        Ok(ViewOnlyFile {
+            format: VIEW_ONLY_FORMAT.to_string(),
+            version: VIEW_ONLY_VERSION,
+            payload: encryption::encrypt_with_password(&plaintext, password)?,
+        })
+    }
+
+    pub fn decrypt(file: &ViewOnlyFile, password: &str) -> Result<Self> {
+        if file.format != VIEW_ONLY_FORMAT {
+            return Err(QtcError::Wallet(format!("Not a view-only export: {}", file.format)));
+        }
+        if file.version > VIEW_ONLY_VERSION {
+            return Err(QtcError::Wallet(format!("Unsupported view-only export version {}", file.version)));
+        }
+
+        let plaintext = encryption::decrypt_with_password(&file.payload, password)?;
+        Ok(serde_json::from_slice(&plaintext)?)
+    }
+
+    pub fn write_to_file<P: AsRef<Path>>(&self, path: P, password: &str) -> Result<()> {
+        let file = self.encrypt(password)?;
+        std::fs::write(path, serde_json::to_string_pretty(&file)?)?;
+        Ok(())
+    }
+
+    pub fn read_from_file<P: AsRef<Path>>(path: P, password: &str) -> Result<Self> {
+        let data = std::fs::read_to_string(path)?;
+        let file: ViewOnlyFile = serde_json::from_str(&data)?;
+        Self::decrypt(&file, password)
+    }
+
+    /// Build a watch-only wallet holding only the exported public data
+    pub fn into_watch_only_wallet(self, name: String, db: Arc<Database>, blockchain: Arc<RwLock<Blockchain>>) -> Result<Wallet> {
+        let mut addresses = HashMap::new();
+        for addr in self.addresses {
+            let public_key = hex::decode(&addr.public_key)
+                .map_err(|_| QtcError::Wallet(format!("Invalid public key for {}", addr.address)))?;
+
+            // PQC addresses keep their public key so the type is still recognisable
+            let pqc_data = match addr.address_type {
+                AddressType::Classic => None,
+                _ => Some(PqcAddressData {
+                    signing_private_key: None,
+                    encryption_private_key: None,
+                    signing_public_key: public_key.clone(),
+                    encryption_public_key: Vec::new(),
+                }),
+            };
+
+            addresses.insert(addr.address.clone(), WalletAddress {
+                address: addr.address,
+                private_key: None,
+                public_key,
+                derivation_path: addr.derivation_path,
+                is_change: addr.is_change,
+                used: addr.used,
+                address_type: addr.address_type,
+                pqc_data,
+            });
+        }
+
+        let info = WalletInfo {
+            name,
+            wallet_type: WalletType::WatchOnly,
+            created_at: chrono::Utc::now().timestamp() as u64,
+            last_used: 0,
+            is_encrypted: false,
+            balance: 0,
+            address_count: addresses.len() as u32,
+        };
+
+        Ok(Wallet {
+            info,
+            addresses,
+            hd_wallet: None,
+            db,
+            blockchain,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_view_only_roundtrip_has_no_keys() -> Result<()> {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Arc::new(Database::new(temp_dir.path().join("test.db"))?);
+        let blockchain = Arc::new(RwLock::new(Blockchain::new(db.clone())?));
+
+        let wallet = Wallet::new_simple("books".to_string(), db.clone(), blockchain.clone())?;
+        let export_path = temp_dir.path().join("books.view");
+
+        ViewOnlyBundle::from_wallet(&wallet)?.write_to_file(&export_path, "audit")?;
+        assert!(ViewOnlyBundle::read_from_file(&export_path, "nope").is_err());
+
+        let bundle = ViewOnlyBundle::read_from_file(&export_path, "audit")?;
+        let watch_only = bundle.into_watch_only_wallet("books-audit".to_string(), db, blockchain)?;
+
+        assert!(matches!(watch_only.info.wallet_type, WalletType::WatchOnly));
+        assert_eq!(watch_only.get_addresses(), wallet.get_addresses());
+        assert!(watch_only.addresses.values().all(|a| a.private_key.is_none()));
+
+        Ok(())
+    }
+}
@@ -1,15 +1,25 @@
-use crate::core::{Transaction, TxInput};
-// use crate::core::transaction::OutPoint;
-// use crate::crypto::hash::Hashable;
+use crate::core::{SigHashType, Transaction, UtxoSet};
+use crate::core::transaction::{OutPoint, TransactionPreview, SEQUENCE_REPLACEABLE};
+use crate::crypto::hash::{Hash256, Hashable};
 use crate::core::Blockchain;
 use crate::crypto::keys::{PrivateKey, KeyPair};
-use crate::crypto::hash::Hash256;
 use crate::crypto::pqc::{PqcKeyPair};
 use crate::storage::Database;
+use crate::storage::database::AddressReservation;
+use crate::wallet::balance::{current_balance, WalletBalance};
 use crate::wallet::bip39::{HdWallet, Mnemonic};
+use crate::wallet::coin_selection::{CoinSelection, DUST_THRESHOLD};
+use crate::wallet::gap::AddressGap;
+use crate::wallet::history::{address_use, wallet_history, HistoryEntry};
+use crate::wallet::labels::{check_label, AddressBook};
+use crate::wallet::locks::UtxoLock;
+use crate::wallet::multisig::MultisigWallet;
+use crate::wallet::psbt::{Psbt, PsbtInput, PSBT_LOCK_TTL_SECS};
+use crate::wallet::rescan::{self, RescanReport};
+use crate::wallet::signer::{sign_transaction, InputToSign, LocalSigner};
 use crate::{QtcError, Result};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::sync::Arc;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -45,6 +55,15 @@ pub struct WalletAddress {
     pub pqc_data: Option<PqcAddressData>,
 }
 
+impl WalletAddress {
+    /// The last step of the derivation path, for addresses an HD wallet derived
+    pub fn derivation_index(&self) -> Option<u32> {
+        self.derivation_path.as_deref()
+            .and_then(|path| path.rsplit('/').next())
+            .and_then(|index| index.parse().ok())
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub enum AddressType {
     Classic,
@@ -60,6 +79,16 @@ pub struct PqcAddressData {
     pub encryption_public_key: Vec<u8>,
 }
 
+/// Per-wallet options, stored apart from the wallet itself
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WalletSettings {
+    /// Leave outputs on reused addresses out of automatic coin selection
+    pub avoid_reuse: bool,
+}
+
+/// Upper bound for a single batch address request
+pub const MAX_ADDRESS_BATCH: u32 = 10_000;
+
 #[derive(Debug)]
 pub struct Wallet {
     pub info: WalletInfo,
@@ -132,6 +161,50 @@ impl Wallet {
         Ok(wallet)
     }
     
+    /// A wallet holding the P2SH address of `multisig`; its keys stay with the cosigners
+    pub fn new_multisig(multisig: &MultisigWallet, db: Arc<Database>, blockchain: Arc<std::sync::RwLock<Blockchain>>) -> Result<Self> {
+        let mut addresses = HashMap::new();
+        addresses.insert(multisig.address.clone(), WalletAddress {
+            address: multisig.address.clone(),
+            private_key: None,
+            public_key: Vec::new(),
+            derivation_path: None,
+            is_change: false,
+            used: false,
+            address_type: AddressType::Classic,
+            pqc_data: None,
+        });
+        
+        let info = WalletInfo {
+            name: multisig.name.clone(),
+            wallet_type: WalletType::Multisig {
+                required: multisig.required_signatures(),
+                total: multisig.total_keys(),
+            },
+            created_at: multisig.created_at,
+            last_used: 0,
+            is_encrypted: false,
+            balance: 0,
+            address_count: 1,
+        };
+        
+        Ok(Self {
+            info,
+            addresses,
+            hd_wallet: None,
+            db,
+            blockchain,
+        })
+    }
+    
+    /// The cosigners and threshold of a multisig wallet; None for other wallets
+    pub fn multisig(&self) -> Result<Option<MultisigWallet>> {
+        match self.info.wallet_type {
+            WalletType::Multisig { .. } => self.db.get_multisig_wallet(&self.info.name),
+            _ => Ok(None),
+        }
+    }
+    
     pub fn from_mnemonic_phrase(name: String, phrase: &str, passphrase: &str, db: Arc<Database>, blockchain: Arc<std::sync::RwLock<Blockchain>>) -> Result<Self> {
         let mnemonic = Mnemonic::from_phrase(phrase)?;
         Self::new_hd(name, &mnemonic, passphrase, db, blockchain)
@@ -169,18 +242,17 @@ impl Wallet {
         Ok(new_addresses)
     }
     
+    /// Confirmed balance, served from the wallet's balance cache
     pub fn get_balance(&self) -> Result<u64> {
-        let mut total_balance = 0u64;
-        
-        for address in self.addresses.keys() {
-            let balance = {
-                let blockchain = self.blockchain.read().unwrap();
-                blockchain.get_balance(address)?
-            };
-            total_balance += balance;
-        }
-        
-        Ok(total_balance)
+        Ok(self.cached_balance()?.total())
+    }
+    
+    /// Recompute the balance from the UTXO set, replacing whatever was cached
+    pub fn refresh_balance(&self) -> Result<u64> {
+        let blockchain = self.blockchain.read().unwrap();
+        let balance = WalletBalance::compute(&self.db, self.addresses.keys(), blockchain.tip, blockchain.height)?;
+        self.db.save_wallet_balance(&self.info.name, &balance)?;
+        Ok(balance.total())
     }
     
     pub fn get_address_balance(&self, address: &str) -> Result<u64> {
@@ -188,8 +260,13 @@ impl Wallet {
             return Err(QtcError::Wallet("Address not found in wallet".to_string()));
         }
         
+        Ok(self.cached_balance()?.address(address).unwrap_or(0))
+    }
+    
+    fn cached_balance(&self) -> Result<WalletBalance> {
         let blockchain = self.blockchain.read().unwrap();
-        blockchain.get_balance(address)
+        blockchain.check_synced()?;
+        current_balance(&self.db, &self.info.name, self.addresses.keys(), &blockchain)
     }
     
     pub fn get_addresses(&self) -> Vec<String> {
@@ -198,10 +275,178 @@ impl Wallet {
     
     pub fn get_unused_address(&self) -> Option<String> {
         self.addresses.values()
-            .find(|addr| !addr.used && !addr.is_change)
+            .filter(|addr| !addr.used && !addr.is_change)
+            .find(|addr| !self.db.is_address_reserved(&self.info.name, &addr.address).unwrap_or(false))
             .map(|addr| addr.address.clone())
     }
     
+    pub fn settings(&self) -> Result<WalletSettings> {
+        self.db.get_wallet_settings(&self.info.name)
+    }
+    
+    pub fn set_avoid_reuse(&self, avoid_reuse: bool) -> Result<()> {
+        let mut settings = self.settings()?;
+        settings.avoid_reuse = avoid_reuse;
+        self.db.save_wallet_settings(&self.info.name, &settings)
+    }
+    
+    /// Label `address`, the wallet's own or one it pays; an empty label removes it
+    pub fn set_label(&self, address: &str, label: &str) -> Result<()> {
+        if !crate::crypto::keys::is_valid_address(address) && !self.addresses.contains_key(address) {
+            return Err(QtcError::InvalidInput(format!("Invalid address: {}", address)));
+        }
+        let label = check_label(label, "Label")?;
+        if label.is_empty() {
+            self.db.remove_address_label(&self.info.name, address)?;
+        } else {
+            self.db.save_address_label(&self.info.name, address, &label)?;
+        }
+        Ok(())
+    }
+    
+    /// The wallet's labels and the node's contacts, to name addresses by
+    pub fn address_book(&self) -> Result<AddressBook> {
+        AddressBook::load(&self.db, &self.info.name)
+    }
+    
+    /// How many confirmed transactions paid to or spent from each address; untouched ones are left out
+    pub fn address_use(&self) -> Result<BTreeMap<String, u32>> {
+        let blockchain = self.blockchain.read().unwrap();
+        address_use(&self.db, &self.info.name, self.addresses.keys(), &blockchain)
+    }
+    
+    /// Addresses more than one transaction has touched, so their coins link those payments together
+    pub fn reused_addresses(&self) -> Result<HashSet<String>> {
+        Ok(self.address_use()?.into_iter()
+            .filter(|(_, count)| *count > 1)
+            .map(|(address, _)| address)
+            .collect())
+    }
+    
+    /// Mark every address a transaction has touched as used, confirmed or in the
+    /// mempool, so it isn't handed out again; returns how many were newly marked
+    pub fn sync_used_addresses(&mut self) -> Result<usize> {
+        let mut touched: HashSet<String> = self.address_use()?.into_keys().collect();
+        {
+            let blockchain = self.blockchain.read().unwrap();
+            let mempool = blockchain.mempool.read().unwrap();
+            for entry in mempool.entries() {
+                touched.extend(entry.tx.outputs.iter()
+                    .map(|output| UtxoSet::output_address(&output.script_pubkey))
+                    .filter(|address| self.addresses.contains_key(address)));
+            }
+        }
+        
+        let mut marked = 0;
+        for address in touched {
+            if let Some(addr) = self.addresses.get_mut(&address).filter(|addr| !addr.used) {
+                addr.used = true;
+                marked += 1;
+            }
+        }
+        if marked > 0 {
+            self.save()?;
+        }
+        Ok(marked)
+    }
+    
+    /// Look for the wallet's transactions in blocks from `from_height`, or from
+    /// where the last rescan stopped; see `wallet::rescan`
+    pub fn rescan(&mut self, from_height: Option<u64>, progress: impl FnMut(u64, u64)) -> Result<RescanReport> {
+        rescan::rescan(self, from_height, progress)
+    }
+    
+    /// How far generated receive addresses run past the last one that was paid to
+    pub fn address_gap(&self) -> Result<AddressGap> {
+        let balance = self.cached_balance()?;
+        let mut receive = Vec::new();
+        for addr in self.addresses.values().filter(|addr| !addr.is_change) {
+            let used = addr.used
+                || balance.address(&addr.address).is_some_and(|value| value > 0)
+                || !self.db.get_address_history(&addr.address, 1)?.is_empty();
+            receive.push((addr.derivation_index(), used));
+        }
+        Ok(AddressGap::compute(receive))
+    }
+    
+    /// Generate `count` new receive addresses and reserve them for external use
+    pub fn reserve_receive_addresses(&mut self, count: u32, label: Option<String>) -> Result<Vec<AddressReservation>> {
+        if count == 0 || count > MAX_ADDRESS_BATCH {
+            return Err(QtcError::InvalidInput(format!("Address count must be between 1 and {}", MAX_ADDRESS_BATCH)));
+        }
+        
+        let addresses = match (&self.info.wallet_type, &self.hd_wallet) {
+            (_, Some(_)) => self.generate_addresses(count)?,
+            (WalletType::Simple, None) => {
+                let mut addresses = Vec::with_capacity(count as usize);
+                for _ in 0..count {
+                    let keypair = KeyPair::new()?;
+                    let address = keypair.address();
+                    self.addresses.insert(address.clone(), WalletAddress {
+                        address: address.clone(),
+                        private_key: Some(keypair.private_key.to_bytes().to_vec()),
+                        public_key: keypair.public_key.to_bytes().to_vec(),
+                        derivation_path: None,
+                        is_change: false,
+                        used: false,
+                        address_type: AddressType::Classic,
+                        pqc_data: None,
+                    });
+                    addresses.push(address);
+                }
+                self.info.address_count += count;
+                self.save()?;
+                addresses
+            }
+            (WalletType::HD, None) => {
+                return Err(QtcError::Wallet("HD seed is not loaded for this wallet".to_string()));
+            }
+            (wallet_type, None) => {
+                return Err(QtcError::Wallet(format!("Batch address generation is not supported for {:?} wallets", wallet_type)));
+            }
+        };
+        
+        let reservations = self.save_reservations(addresses, label)?;
+        log::info!("📇 Reserved {} receive addresses for wallet {}", reservations.len(), self.info.name);
+        
+        let gap = self.address_gap()?;
+        if gap.exceeded() {
+            log::warn!(
+                "📇 Wallet {} has {} unused receive addresses past its last used one (gap limit {}); \
+                 payments to the newest may not be found when restoring from seed",
+                self.info.name, gap.gap, gap.limit
+            );
+        }
+        
+        Ok(reservations)
+    }
+    
+    /// One address to hand to a payer: an unused one if the wallet has it, otherwise a new one
+    pub fn reserve_receive_address(&mut self, label: Option<String>) -> Result<AddressReservation> {
+        self.sync_used_addresses()?;
+        let mut reservations = match self.get_unused_address() {
+            Some(address) => self.save_reservations(vec![address], label)?,
+            None => self.reserve_receive_addresses(1, label)?,
+        };
+        Ok(reservations.remove(0))
+    }
+    
+    fn save_reservations(&self, addresses: Vec<String>, label: Option<String>) -> Result<Vec<AddressReservation>> {
+        let reserved_at = chrono::Utc::now().timestamp() as u64;
+        let reservations: Vec<AddressReservation> = addresses.into_iter()
+            .map(|address| AddressReservation {
+                wallet: self.info.name.clone(),
+                derivation_path: self.addresses.get(&address).and_then(|a| a.derivation_path.clone()),
+                address,
+                label: label.clone(),
+                reserved_at,
+            })
+            .collect();
+        
+        self.db.save_address_reservations(&reservations)?;
+        Ok(reservations)
+    }
+    
     pub fn get_change_address(&self) -> Result<String> {
         // For simple wallets, reuse existing address
         if self.hd_wallet.is_none() {
@@ -241,49 +486,202 @@ impl Wallet {
     }
     
     pub fn create_transaction(&self, to_address: &str, amount: u64, fee_rate: u64) -> Result<Transaction> {
+        self.create_transaction_with(to_address, amount, fee_rate, CoinSelection::default(), false, 0, None, false)
+    }
+    
+    /// A replaceable transaction is remembered so `bump_fee` can replace it later.
+    /// A non-zero `lock_time` keeps it out of blocks until that height or time.
+    /// `data` goes on chain in an OP_RETURN output next to the payment.
+    /// `allow_reuse` lets coin selection spend from reused addresses despite `avoid_reuse`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn create_transaction_with(
+        &self,
+        to_address: &str,
+        amount: u64,
+        fee_rate: u64,
+        coin_selection: CoinSelection,
+        replaceable: bool,
+        lock_time: u64,
+        data: Option<&[u8]>,
+        allow_reuse: bool,
+    ) -> Result<Transaction> {
+        if let WalletType::Multisig { required, total } = self.info.wallet_type {
+            return Err(QtcError::Wallet(format!(
+                "A {}-of-{} multisig wallet needs its cosigners' signatures; build the payment with \
+                 `wallet create-unsigned`, then have each cosigner run `wallet multisig sign`",
+                required, total
+            )));
+        }
+        
         // Use the TransactionBuilder from core::transaction module
         let mut builder = crate::core::transaction::TransactionBuilder::new(self);
         builder.add_output(to_address, amount)?;
+        if let Some(data) = data {
+            builder.add_data_output(data)?;
+        }
         builder.set_fee_rate(fee_rate);
-        builder.build()
+        builder.set_coin_selection(coin_selection);
+        builder.set_replaceable(replaceable);
+        builder.set_lock_time(lock_time);
+        builder.set_allow_reuse(allow_reuse);
+        let tx = builder.build()?;
+        
+        if replaceable {
+            self.db.save_replaceable_send(&self.info.name, &tx)?;
+        }
+        Ok(tx)
     }
     
-    pub fn sign_transaction(&self, tx: &mut Transaction) -> Result<()> {
-        // Pre-calculate signature hashes to avoid borrowing issues
-        let signature_hashes: Vec<_> = (0..tx.inputs.len())
-            .map(|index| tx.get_signature_hash(index))
-            .collect();
+    /// Replacement for an unconfirmed, replaceable send of this wallet paying at least
+    /// `fee_rate` (satoshis per 1000 bytes) and more than the original plus the relay
+    /// minimum. The extra fee comes out of the change output, which must stay above dust.
+    pub fn bump_fee(&self, txid: &Hash256, fee_rate: u64) -> Result<Transaction> {
+        let original = match self.db.get_replaceable_send(&self.info.name, txid)? {
+            Some(tx) => tx,
+            None => self.blockchain.read().unwrap().mempool.read().unwrap().get(txid)
+                .map(|entry| entry.tx.clone())
+                .ok_or_else(|| QtcError::Wallet(format!("No unconfirmed send {} in wallet '{}'", txid, self.info.name)))?,
+        };
+        if !original.signals_rbf() {
+            return Err(QtcError::Wallet(format!("Transaction {} does not signal replace-by-fee", txid)));
+        }
         
-        for (index, input) in tx.inputs.iter_mut().enumerate() {
-            // Find the private key for this input
-            if let Some(private_key) = self.find_private_key_for_input(input)? {
-                let signature_hash = &signature_hashes[index];
-                let signature = private_key.sign(signature_hash)?;
-                let public_key = private_key.public_key()?;
-                
-                // Create signature script (simplified)
-                let mut script = Vec::new();
-                script.extend_from_slice(&signature.to_bytes());
-                script.extend_from_slice(public_key.to_bytes());
-                
-                input.signature_script = script;
+        let mut total_input = 0u64;
+        for input in &original.inputs {
+            let Some(utxo) = self.db.get_utxo(&input.previous_output)? else {
+                self.db.remove_replaceable_send(&self.info.name, txid)?;
+                return Err(QtcError::Wallet(format!("Transaction {} is already confirmed or replaced", txid)));
+            };
+            if !self.addresses.contains_key(&utxo.address) {
+                return Err(QtcError::Wallet(format!("Transaction {} spends outputs this wallet does not own", txid)));
             }
+            total_input += utxo.value;
         }
+        let old_fee = total_input.saturating_sub(original.total_output_value());
         
-        Ok(())
+        let min_fee = self.blockchain.read().unwrap().mempool.read().unwrap().min_fee();
+        let new_fee = (fee_rate * original.size() as u64 / 1000).max(old_fee + min_fee);
+        let extra = new_fee - old_fee;
+        
+        // Change goes last, so take the fee from the last output paying this wallet
+        let change_index = original.outputs.iter()
+            .rposition(|output| self.addresses.contains_key(&UtxoSet::output_address(&output.script_pubkey)))
+            .ok_or_else(|| QtcError::Wallet(format!("Transaction {} has no change output to take a higher fee from", txid)))?;
+        let change = original.outputs[change_index].value;
+        if change < extra + DUST_THRESHOLD {
+            return Err(QtcError::InsufficientFunds { required: extra + DUST_THRESHOLD, available: change });
+        }
+        
+        let mut replacement = original.clone();
+        replacement.outputs[change_index].value = change - extra;
+        for input in replacement.inputs.iter_mut() {
+            input.sequence = SEQUENCE_REPLACEABLE;
+        }
+        self.sign_transaction(&mut replacement)?;
+        
+        self.db.save_replaceable_send(&self.info.name, &replacement)?;
+        self.db.remove_replaceable_send(&self.info.name, txid)?;
+        log::info!("💸 Bumped fee of {} from {} to {} as {}", txid, old_fee, new_fee, replacement.hash());
+        Ok(replacement)
+    }
+    
+    /// Build a payment for an offline signer; its inputs stay locked until it is broadcast or unlocked
+    pub fn create_psbt(&self, to_address: &str, amount: u64, fee_rate: u64) -> Result<Psbt> {
+        self.create_batch_psbt(&[(to_address.to_string(), amount)], fee_rate)
+    }
+    
+    /// `create_psbt` paying several addresses in one transaction
+    pub fn create_batch_psbt(&self, payments: &[(String, u64)], fee_rate: u64) -> Result<Psbt> {
+        let mut builder = crate::core::transaction::TransactionBuilder::new(self);
+        for (address, amount) in payments {
+            builder.add_output(address, *amount)?;
+        }
+        builder.set_fee_rate(fee_rate);
+        builder.set_lock_ttl(PSBT_LOCK_TTL_SECS);
+        let (tx, selected, outputs) = builder.build_unsigned()?;
+        
+        let inputs = tx.inputs.iter()
+            .zip(selected)
+            .map(|(input, selected)| PsbtInput {
+                outpoint: input.previous_output.clone(),
+                value: selected.value,
+                address: selected.address,
+            })
+            .collect();
+        
+        Psbt::new(tx, inputs, outputs)
+    }
+    
+    /// Work out inputs, fee and change for a payment without signing or locking anything
+    pub fn preview_transaction(&self, to_address: &str, amount: u64, fee_rate: u64) -> Result<TransactionPreview> {
+        self.preview_transaction_with(to_address, amount, fee_rate, CoinSelection::default(), None, false)
+    }
+    
+    pub fn preview_transaction_with(
+        &self,
+        to_address: &str,
+        amount: u64,
+        fee_rate: u64,
+        coin_selection: CoinSelection,
+        data: Option<&[u8]>,
+        allow_reuse: bool,
+    ) -> Result<TransactionPreview> {
+        let mut builder = crate::core::transaction::TransactionBuilder::new(self);
+        builder.add_output(to_address, amount)?;
+        if let Some(data) = data {
+            builder.add_data_output(data)?;
+        }
+        builder.set_fee_rate(fee_rate);
+        builder.set_coin_selection(coin_selection);
+        builder.set_allow_reuse(allow_reuse);
+        builder.preview()
+    }
+    
+    /// Lock outputs so coin selection skips them
+    pub fn lock_unspent(&self, outpoints: &[OutPoint], ttl_secs: Option<u64>, persistent: bool) -> Result<()> {
+        self.db.utxo_locks().lock(&self.info.name, outpoints, ttl_secs, persistent)
     }
     
-    fn find_private_key_for_input(&self, _input: &TxInput) -> Result<Option<PrivateKey>> {
-        // This would need to look up the output being spent to determine the address
-        // For now, simplified implementation
-        for addr_info in self.addresses.values() {
-            if let Some(private_key_bytes) = &addr_info.private_key {
-                let private_key = PrivateKey::from_bytes(private_key_bytes)?;
-                return Ok(Some(private_key));
+    pub fn unlock_unspent(&self, outpoints: &[OutPoint]) -> Result<usize> {
+        self.db.utxo_locks().unlock(outpoints)
+    }
+    
+    pub fn unlock_all_unspent(&self) -> Result<usize> {
+        self.db.utxo_locks().unlock_wallet(&self.info.name)
+    }
+    
+    pub fn list_locked_unspent(&self) -> Result<Vec<(OutPoint, UtxoLock)>> {
+        self.db.utxo_locks().list(&self.info.name)
+    }
+    
+    /// Release the inputs of `tx` once it has been broadcast or abandoned
+    pub fn release_transaction_locks(&self, tx: &Transaction) -> Result<usize> {
+        let outpoints: Vec<OutPoint> = tx.inputs.iter()
+            .map(|input| input.previous_output.clone())
+            .collect();
+        self.unlock_unspent(&outpoints)
+    }
+    
+    pub fn sign_transaction(&self, tx: &mut Transaction) -> Result<()> {
+        self.sign_transaction_with(tx, SigHashType::All)
+    }
+    
+    pub fn sign_transaction_with(&self, tx: &mut Transaction, sighash: SigHashType) -> Result<()> {
+        // Look up who owns each spent output so the signer can tell which inputs are ours
+        let mut inputs = Vec::new();
+        for (index, input) in tx.inputs.iter().enumerate() {
+            if let Some(utxo) = self.db.get_utxo(&input.previous_output)? {
+                inputs.push(InputToSign {
+                    index,
+                    outpoint: input.previous_output.clone(),
+                    value: utxo.value,
+                    address: utxo.address,
+                });
             }
         }
         
-        Ok(None)
+        sign_transaction(&LocalSigner::new(self), tx, &inputs, sighash)?;
+        Ok(())
     }
     
     pub fn mark_address_used(&mut self, address: &str) -> Result<()> {
@@ -337,10 +735,10 @@ impl Wallet {
         Ok(address)
     }
     
-    pub fn get_transaction_history(&self) -> Result<Vec<(Hash256, Transaction, u64)>> {
-        // This would need to scan the blockchain for transactions involving our addresses
-        // Simplified implementation for now
-        Ok(Vec::new())
+    /// Every confirmed transaction touching the wallet, newest first
+    pub fn get_transaction_history(&self) -> Result<Vec<HistoryEntry>> {
+        let blockchain = self.blockchain.read().unwrap();
+        wallet_history(&self.db, &self.info.name, self.addresses.keys(), &blockchain)
     }
 
     /// Create a new Post-Quantum Cryptography wallet
@@ -492,6 +890,55 @@ impl Wallet {
     }
 }
 
+/// Render reserved addresses as CSV (address,derivation_path,label,reserved_at)
+pub fn reservations_to_csv(reservations: &[AddressReservation]) -> String {
+    let mut csv = String::from("address,derivation_path,label,reserved_at\n");
+    for reservation in reservations {
+        csv.push_str(&format!("{},{},{},{}\n",
+            csv_field(&reservation.address),
+            csv_field(reservation.derivation_path.as_deref().unwrap_or("")),
+            csv_field(reservation.label.as_deref().unwrap_or("")),
+            reservation.reserved_at,
+        ));
+    }
+    csv
+}
+
+/// `qtc:` payment URI for `address`, with an optional amount (in satoshis) and label
+pub fn payment_uri(address: &str, amount: Option<u64>, label: Option<&str>) -> String {
+    let mut params = Vec::new();
+    if let Some(amount) = amount {
+        let qtc = format!("{}.{:08}", amount / 100_000_000, amount % 100_000_000);
+        params.push(format!("amount={}", qtc.trim_end_matches('0').trim_end_matches('.')));
+    }
+    if let Some(label) = label {
+        params.push(format!("label={}", uri_encode(label)));
+    }
+    
+    if params.is_empty() {
+        format!("qtc:{}", address)
+    } else {
+        format!("qtc:{}?{}", address, params.join("&"))
+    }
+}
+
+fn uri_encode(value: &str) -> String {
+    value.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => (b as char).to_string(),
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -528,4 +975,132 @@ mod tests {
         
         Ok(())
     }
-}
\ No newline at end of file
+    
+    #[test]
+    fn test_batch_address_reservation() -> Result<()> {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Arc::new(Database::new(temp_dir.path().join("test.db"))?);
+        let blockchain = Arc::new(std::sync::RwLock::new(Blockchain::new(db.clone())?));
+        
+        let mnemonic = Mnemonic::new(12)?;
+        let mut wallet = Wallet::new_hd("shop".to_string(), &mnemonic, "", db.clone(), blockchain)?;
+        let reservations = wallet.reserve_receive_addresses(5, Some("orders, eu".to_string()))?;
+        
+        assert_eq!(reservations.len(), 5);
+        assert!(reservations.iter().all(|r| r.derivation_path.is_some()));
+        assert_eq!(db.get_address_reservations("shop")?.len(), 5);
+        
+        let unused = wallet.get_unused_address().unwrap();
+        assert!(reservations.iter().all(|r| r.address != unused));
+        
+        let csv = reservations_to_csv(&reservations);
+        assert_eq!(csv.lines().count(), 6);
+        assert!(csv.contains("\"orders, eu\""));
+        
+        assert!(wallet.reserve_receive_addresses(0, None).is_err());
+        Ok(())
+    }
+    
+    #[test]
+    fn test_receive_address_and_uri() -> Result<()> {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Arc::new(Database::new(temp_dir.path().join("test.db"))?);
+        let blockchain = Arc::new(std::sync::RwLock::new(Blockchain::new(db.clone())?));
+        
+        let mut wallet = Wallet::new_simple("till".to_string(), db.clone(), blockchain)?;
+        let original = wallet.get_addresses()[0].clone();
+        let first = wallet.reserve_receive_address(None)?;
+        let second = wallet.reserve_receive_address(Some("table 4".to_string()))?;
+        
+        // The existing unused address is handed out once, then a new one is made
+        assert_eq!(first.address, original);
+        assert_ne!(first.address, second.address);
+        assert!(db.is_address_reserved("till", &second.address)?);
+        
+        assert_eq!(payment_uri("qtc1qabc", None, None), "qtc:qtc1qabc");
+        assert_eq!(
+            payment_uri("qtc1qabc", Some(150_000_000), Some("table 4")),
+            "qtc:qtc1qabc?amount=1.5&label=table%204"
+        );
+        assert_eq!(payment_uri("qtc1qabc", Some(200_000_000), None), "qtc:qtc1qabc?amount=2");
+        Ok(())
+    }
+    
+    #[test]
+    fn test_transaction_preview() -> Result<()> {
+        use crate::crypto::hash::Hashable;
+        
+        let temp_dir = TempDir::new().unwrap();
+        let db = Arc::new(Database::new(temp_dir.path().join("test.db"))?);
+        let blockchain = Arc::new(std::sync::RwLock::new(Blockchain::new(db.clone())?));
+        
+        let wallet = Wallet::new_simple("payer".to_string(), db.clone(), blockchain)?;
+        let address = wallet.get_addresses()[0].clone();
+        let funding = Transaction::new_coinbase(address.clone(), 10_000_000, "funding".to_string());
+        db.save_utxo(&OutPoint::new(funding.hash(), 0), &crate::core::UtxoEntry {
+            txid: funding.hash(),
+            vout: 0,
+            value: 10_000_000,
+            script_pubkey: funding.outputs[0].script_pubkey.clone(),
+            address,
+            height: 1,
+            is_coinbase: false,
+        })?;
+        
+        let recipient = KeyPair::new()?.address();
+        let preview = wallet.preview_transaction(&recipient, 4_000_000, 1000)?;
+        
+        assert_eq!(preview.inputs.len(), 1);
+        assert_eq!(preview.outputs[0].address, recipient);
+        let change = preview.change.as_ref().map_or(0, |c| c.value);
+        assert_eq!(preview.total_input, 4_000_000 + change + preview.fee);
+        assert!(preview.fee > 0 && preview.estimated_vsize > 0);
+        
+        // A preview leaves the inputs free for a real send
+        assert!(wallet.list_locked_unspent()?.is_empty());
+        Ok(())
+    }
+    
+    #[test]
+    fn test_avoid_reuse_leaves_reused_coins_out() -> Result<()> {
+        use crate::crypto::hash::Hashable;
+        use crate::wallet::history::WalletHistory;
+        
+        let temp_dir = TempDir::new().unwrap();
+        let db = Arc::new(Database::new(temp_dir.path().join("test.db"))?);
+        let blockchain = Arc::new(std::sync::RwLock::new(Blockchain::new(db.clone())?));
+        
+        let wallet = Wallet::new_simple("reuser".to_string(), db.clone(), blockchain.clone())?;
+        let address = wallet.get_addresses()[0].clone();
+        let funding = Transaction::new_coinbase(address.clone(), 10_000_000, "funding".to_string());
+        db.save_utxo(&OutPoint::new(funding.hash(), 0), &crate::core::UtxoEntry {
+            txid: funding.hash(),
+            vout: 0,
+            value: 10_000_000,
+            script_pubkey: funding.outputs[0].script_pubkey.clone(),
+            address: address.clone(),
+            height: 1,
+            is_coinbase: false,
+        })?;
+        // As if two confirmed transactions had paid the address
+        let tip = blockchain.read().unwrap().tip;
+        db.save_wallet_history("reuser", &WalletHistory {
+            tip,
+            addresses: 1,
+            address_use: [(address.clone(), 2)].into(),
+            ..WalletHistory::default()
+        })?;
+        assert!(wallet.reused_addresses()?.contains(&address));
+        
+        let recipient = KeyPair::new()?.address();
+        assert!(wallet.preview_transaction(&recipient, 4_000_000, 1000).is_ok());
+        
+        wallet.set_avoid_reuse(true)?;
+        assert!(wallet.settings()?.avoid_reuse);
+        let err = wallet.preview_transaction(&recipient, 4_000_000, 1000).unwrap_err();
+        assert!(err.to_string().contains("--allow-reuse"), "{}", err);
+        let preview = wallet.preview_transaction_with(&recipient, 4_000_000, 1000, CoinSelection::default(), None, true)?;
+        assert_eq!(preview.inputs.len(), 1);
+        Ok(())
+    }
+}